@@ -0,0 +1,63 @@
+// Culls the pooled chunk meshes against the frustum of a view, writing the indexed indirect draw of each chunk:
+// all of its indices when its bounds intersect the frustum, none otherwise.
+
+struct CulledChunk {
+    // center and half size of the bounds of the chunk, in world space.
+    center: vec3<f32>,
+    index_count: u32,
+    extents: vec3<f32>,
+};
+
+struct CulledChunks {
+    chunks: array<CulledChunk>,
+};
+
+struct CullingView {
+    // left, right, bottom, top and near planes of the view frustum, their normals pointing inside the frustum.
+    planes: array<vec4<f32>, 5>,
+    chunk_count: u32,
+};
+
+// arguments of the indexed indirect draw of a chunk.
+struct DrawIndexedIndirect {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+};
+
+@group(0) @binding(0)
+var<storage, read> culled_chunks: CulledChunks;
+
+@group(0) @binding(1)
+var<storage, read> culling_view: CullingView;
+
+@group(0) @binding(2)
+var<storage, read_write> draws: array<DrawIndexedIndirect>;
+
+@compute @workgroup_size(64)
+fn cull_chunks(@builtin(global_invocation_id) id: vec3<u32>) {
+    let slot = id.x;
+    if (slot >= culling_view.chunk_count) {
+        return;
+    }
+
+    let chunk = culled_chunks.chunks[slot];
+    var visible = true;
+    for (var i = 0u; i < 5u; i = i + 1u) {
+        let plane = culling_view.planes[i];
+        // the chunk is outside when the corner of its bounds furthest along the plane normal is behind the plane.
+        if (dot(plane.xyz, chunk.center) + dot(abs(plane.xyz), chunk.extents) + plane.w < 0.0) {
+            visible = false;
+        }
+    }
+
+    var draw: DrawIndexedIndirect;
+    draw.index_count = select(0u, chunk.index_count, visible);
+    draw.instance_count = 1u;
+    draw.first_index = 0u;
+    draw.base_vertex = 0;
+    draw.first_instance = 0u;
+    draws[slot] = draw;
+}
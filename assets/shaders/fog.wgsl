@@ -1,16 +1,40 @@
 
 
-let FOG_MIN_DISTANCE: f32 = 384.0;
-let FOG_COLOR: vec4<f32> = vec4<f32>(0.4, 0.4, 0.4, 1.0);
+// Returns how much fog covers a fragment at distance `d`, from 0 before `fog_start` to 1 past `fog_end`.
+fn ffog_calc_factor(d: f32, fog_start: f32, fog_end: f32) -> f32 {
+    return clamp((d - fog_start) / max(fog_end - fog_start, 0.0001), 0.0, 1.0);
+}
+
+// Fades the color into the fog color (the sky horizon color by default) toward the edge of the render distance.
+fn ffog_apply_fog(d: f32, fog_start: f32, fog_end: f32, color: vec4<f32>, fog_color: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(mix(color.rgb, fog_color.rgb, ffog_calc_factor(d, fog_start, fog_end)), color.a);
+}
 
-// 
-fn ffog_calc_factor(clamped_d: f32, fog_distance: f32, chunk_size: f32) -> f32 {
-    let fog_max: f32 = fog_distance;
-    let fog_min: f32 = fog_max - 4.5 * chunk_size;
-    let clamped_d = clamp(fog_min, clamped_d, fog_max);
-    return 1.0 - (fog_max - clamped_d) / (fog_max - fog_min);
+// Returns the length of the segment from the camera to the fragment lying below the liquid surface.
+fn ffog_submerged_distance(camera: vec3<f32>, fragment: vec3<f32>, surface: f32) -> f32 {
+    let d = distance(camera, fragment);
+    if (fragment.y <= surface) {
+        return d;
+    }
+    // the view ray leaves the liquid where it crosses the surface.
+    return d * clamp((surface - camera.y) / max(fragment.y - camera.y, 0.0001), 0.0, 1.0);
 }
 
-fn ffog_apply_fog(d: f32, fog_min: f32, chunk_size: f32, color: vec4<f32>) -> vec4<f32> {
-    return mix(color, FOG_COLOR, ffog_calc_factor(d, fog_min, 32.0));
-}
\ No newline at end of file
+// Applies the fog of the material the camera is submerged in, its alpha being the maximum opacity of the fog.
+// The light reaching the camera crossed the liquid from the surface down to the fragment, then to the camera: the
+// colors are absorbed along that path and the fog thickens with the distance traveled under the surface.
+// `absorption` holds the per voxel absorption of each color channel and the fog density in its w component.
+fn ffog_apply_submerged_fog(camera: vec3<f32>, fragment: vec3<f32>, surface: f32, fog: vec4<f32>, absorption: vec4<f32>, color: vec4<f32>) -> vec4<f32> {
+    if (fog.a <= 0.0) {
+        return color;
+    }
+
+    let water_distance = ffog_submerged_distance(camera, fragment, surface);
+    let fragment_depth = max(surface - fragment.y, 0.0);
+    let camera_depth = max(surface - camera.y, 0.0);
+    let absorbed = color.rgb * exp(-absorption.rgb * (water_distance + fragment_depth));
+    // deeper in the liquid, less light is scattered toward the camera.
+    let fog_color = fog.rgb * exp(-absorption.rgb * camera_depth);
+    let factor = fog.a * (1.0 - exp(-water_distance * absorption.w));
+    return vec4<f32>(mix(absorbed, fog_color, factor), color.a);
+}
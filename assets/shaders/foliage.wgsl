@@ -0,0 +1,56 @@
+// Crossed quads of the foliage voxels, cut into blades and swaying with the wind.
+
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+#import bevy_pbr::mesh_functions
+
+struct FoliageWind {
+    time: f32,
+    strength: f32,
+    direction: vec2<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> wind: FoliageWind;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+
+    // the sway is out of phase from one voxel to another, and only moves the top of the quads.
+    let phase = dot(floor(world_position.xz), vec2<f32>(0.37, 0.23)) * 6.2831853;
+    let sway = 0.7 * sin(1.9 * wind.time + phase) + 0.3 * sin(4.3 * wind.time + 1.7 * phase);
+    let offset = wind.direction * (0.6 + 0.4 * sway) * wind.strength * vertex.uv.y;
+    world_position = world_position + vec4<f32>(offset.x, 0.0, offset.y, 0.0);
+
+    var out: VertexOutput;
+    out.clip_position = mesh_position_world_to_clip(world_position);
+    out.uv = vertex.uv;
+    out.color = vertex.color;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    // three blades per quad, narrowing to a point at their top.
+    let blade = abs(fract(in.uv.x * 3.0) * 2.0 - 1.0);
+    if (blade > 1.0 - in.uv.y) {
+        discard;
+    }
+
+    // the bottom of the blades is shaded by the surrounding foliage.
+    return vec4<f32>(in.color.rgb * (0.55 + 0.45 * in.uv.y), 1.0);
+}
@@ -0,0 +1,115 @@
+// Emits the vertices of the visible faces of a chunk, 6 vertices (2 triangles) per voxel face.
+// The vertices are encoded like the vertices of the CPU meshed chunks (see voxel_data.wgsl), faces aren't merged.
+
+struct ChunkVoxels {
+    // dimensions of the chunk padded with a 1 voxel wide border of its neighbors.
+    padded_size: vec3<u32>,
+    // material id (x) and light (y) of each voxel of the padded chunk.
+    voxels: array<vec2<u32>>,
+};
+
+struct Vertex {
+    voxel_data: u32,
+    light: u32,
+};
+
+// arguments of the indirect draw of the chunk vertices.
+struct DrawIndirect {
+    vertex_count: atomic<u32>,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+};
+
+@group(0) @binding(0)
+var<storage, read> chunk: ChunkVoxels;
+
+@group(0) @binding(1)
+var<storage, read_write> vertices: array<Vertex>;
+
+@group(0) @binding(2)
+var<storage, read_write> draw: DrawIndirect;
+
+// voxel face normals, in the same order as VOXEL_NORMALS.
+var<private> FACE_NORMALS: array<vec3<i32>, 6> = array<vec3<i32>, 6>(
+    vec3<i32>(-1, 0, 0),
+    vec3<i32>(0, -1, 0),
+    vec3<i32>(0, 0, -1),
+    vec3<i32>(1, 0, 0),
+    vec3<i32>(0, 1, 0),
+    vec3<i32>(0, 0, 1),
+);
+
+// edges of the faces, the cross product of the first and second edges is the face normal (counter clockwise winding).
+var<private> FACE_FIRST_EDGES: array<vec3<i32>, 6> = array<vec3<i32>, 6>(
+    vec3<i32>(0, 0, 1),
+    vec3<i32>(1, 0, 0),
+    vec3<i32>(0, 1, 0),
+    vec3<i32>(0, 1, 0),
+    vec3<i32>(0, 0, 1),
+    vec3<i32>(1, 0, 0),
+);
+
+var<private> FACE_SECOND_EDGES: array<vec3<i32>, 6> = array<vec3<i32>, 6>(
+    vec3<i32>(0, 1, 0),
+    vec3<i32>(0, 0, 1),
+    vec3<i32>(1, 0, 0),
+    vec3<i32>(0, 0, 1),
+    vec3<i32>(1, 0, 0),
+    vec3<i32>(0, 1, 0),
+);
+
+fn padded_voxel_at(position: vec3<i32>) -> vec2<u32> {
+    let size = vec3<i32>(chunk.padded_size);
+    return chunk.voxels[position.x + size.x * (position.y + size.y * position.z)];
+}
+
+// Packs a chunk local vertex position in the high bits of the vertex data.
+fn pack_vertex_position(position: vec3<i32>) -> u32 {
+    let packed = vec3<u32>(position) & vec3<u32>(127u);
+    return packed.x << 11u | packed.y << 18u | packed.z << 25u;
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn mesh_chunk(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let local_position = vec3<i32>(invocation_id);
+    let position = local_position + vec3<i32>(1);
+    if (any(position >= vec3<i32>(chunk.padded_size) - vec3<i32>(1))) {
+        return;
+    }
+
+    let material = padded_voxel_at(position).x;
+    if (material == 0u) {
+        return;
+    }
+
+    for (var face = 0u; face < 6u; face = face + 1u) {
+        let neighbor = padded_voxel_at(position + FACE_NORMALS[face]);
+        if (neighbor.x != 0u) {
+            continue;
+        }
+
+        let first_vertex = atomicAdd(&draw.vertex_count, 6u);
+        if (first_vertex + 6u > arrayLength(&vertices)) {
+            return;
+        }
+
+        let data = face << 8u | material;
+        let light = neighbor.y;
+        let first_edge = FACE_FIRST_EDGES[face];
+        let second_edge = FACE_SECOND_EDGES[face];
+        let corner = local_position + max(FACE_NORMALS[face], vec3<i32>(0));
+
+        let corner0 = pack_vertex_position(corner) | data;
+        let corner1 = pack_vertex_position(corner + first_edge) | data;
+        let corner2 = pack_vertex_position(corner + first_edge + second_edge) | data;
+        let corner3 = pack_vertex_position(corner + second_edge) | data;
+
+        vertices[first_vertex] = Vertex(corner0, light);
+        vertices[first_vertex + 1u] = Vertex(corner1, light);
+        vertices[first_vertex + 2u] = Vertex(corner2, light);
+        vertices[first_vertex + 3u] = Vertex(corner0, light);
+        vertices[first_vertex + 4u] = Vertex(corner2, light);
+        vertices[first_vertex + 5u] = Vertex(corner3, light);
+    }
+}
@@ -0,0 +1,53 @@
+// Sky box drawn at the far plane: a zenith to horizon gradient with a sun disk and the light it scatters near the horizon.
+
+#import bevy_pbr::mesh_view_bindings
+
+struct Sky {
+    zenith_color: vec4<f32>,
+    horizon_color: vec4<f32>,
+    ground_color: vec4<f32>,
+    sun_color: vec4<f32>,
+    sun_direction: vec3<f32>,
+    gradient_exponent: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> sky: Sky;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) direction: vec3<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    // the box follows the camera rendering it, and is flattened on the far plane (0 with the reversed depth).
+    let clip = view.view_proj * vec4<f32>(view.world_position + vertex.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(clip.xy, 0.0, clip.w);
+    out.direction = vertex.position;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = normalize(in.direction);
+    let height = pow(clamp(direction.y, 0.0, 1.0), sky.gradient_exponent);
+
+    var color = mix(sky.horizon_color.rgb, sky.zenith_color.rgb, height);
+    // below the horizon the sky quickly fades into the ground color.
+    color = mix(color, sky.ground_color.rgb, clamp(-direction.y * 4.0, 0.0, 1.0));
+
+    // the sunlight scatters more through the thicker atmosphere close to the horizon.
+    let sun_cos = max(dot(direction, sky.sun_direction), 0.0);
+    let glow = pow(sun_cos, 8.0) * 0.35 * (1.0 - height) + pow(sun_cos, 64.0) * 0.25;
+    let disk = smoothstep(0.9994, 0.9997, sun_cos);
+    color = color + sky.sun_color.rgb * (glow + disk);
+
+    return vec4<f32>(color, 1.0);
+}
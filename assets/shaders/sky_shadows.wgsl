@@ -0,0 +1,43 @@
+
+// Returns the height of the top of a heightfield column, columns outside of the heightfield don't cast shadows.
+fn sky_shadow_column_height(column: vec2<i32>) -> f32 {
+    let local = column - sky_shadow_heightfield.origin;
+    let size = i32(sky_shadow_heightfield.size);
+    if (local.x < 0 || local.y < 0 || local.x >= size || local.y >= size) {
+        return -1.0e9;
+    }
+    return sky_shadow_heightfield.heights[local.y * size + local.x];
+}
+
+// Returns how much of the sunlight reaches a voxel face (1 being fully lit), by ray marching the heightfield toward the sun.
+// The penumbra gets wider as the occluder gets farther, like with soft shadows from signed distance fields.
+fn sky_shadow(world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if (sky_shadow_heightfield.strength <= 0.0) {
+        return 1.0;
+    }
+
+    let sun = sky_shadow_heightfield.sun_direction;
+    // faces turned away from the sun don't receive any of its light.
+    if (dot(normal, sun) <= 0.0) {
+        return 0.0;
+    }
+
+    let origin = world_position + normal * 0.05;
+    var shadow = 1.0;
+    var t = 0.5;
+    for (var i = 0; i < 48; i = i + 1) {
+        if (t > sky_shadow_heightfield.max_distance) {
+            break;
+        }
+
+        let p = origin + sun * t;
+        let height = sky_shadow_column_height(vec2<i32>(floor(p.xz)));
+        shadow = min(shadow, sky_shadow_heightfield.softness * (p.y - height) / t);
+        if (shadow <= 0.0) {
+            break;
+        }
+        t = t + max(0.5, 0.08 * t);
+    }
+
+    return clamp(shadow, 0.0, 1.0);
+}
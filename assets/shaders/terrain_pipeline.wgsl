@@ -5,6 +5,9 @@
 #import "shaders/terrain_uniforms.wgsl"
 #import "shaders/noise.wgsl"
 #import "shaders/fog.wgsl"
+#import "shaders/sky_shadows.wgsl"
+#import "shaders/terrain_shadows.wgsl"
+#import "shaders/triplanar.wgsl"
 
 @group(1) @binding(0)
 var<uniform> mesh: Mesh;
@@ -19,8 +22,8 @@ var<uniform> mesh: Mesh;
 #import bevy_pbr::pbr_functions
 
 struct Vertex {
-    @location(0) position: vec3<f32>,
-    @location(1) voxel_data: u32,
+    @location(0) voxel_data: u32,
+    @location(1) light: u32,
 };
 
 struct VertexOutput {
@@ -28,17 +31,23 @@ struct VertexOutput {
     @location(0) voxel_normal: vec3<f32>,
     @location(1) voxel_data: u32,
     @location(2) world_position: vec3<f32>,
+    @location(3) light: vec2<f32>,
+    @location(4) block_light_color: vec3<f32>,
+    @location(5) block_light_emitter: u32,
 };
 
 @vertex
 fn vertex(vertex: Vertex) -> VertexOutput {
-    let world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    let world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(voxel_data_extract_position(vertex.voxel_data), 1.0));
 
     var out: VertexOutput;
     out.clip_position = mesh_position_world_to_clip(world_position);
     out.voxel_normal = voxel_data_extract_normal(vertex.voxel_data);
     out.voxel_data = vertex.voxel_data;
     out.world_position = world_position.xyz;
+    out.light = voxel_light_extract_levels(vertex.light);
+    out.block_light_color = voxel_light_extract_block_color(vertex.light);
+    out.block_light_emitter = voxel_light_extract_block_emitter(vertex.light);
 
     return out;
 }
@@ -52,12 +61,45 @@ struct Fragment {
     @location(1) voxel_color: u32,
     /// The world position of the voxel vertex.
     @location(2) world_position: vec3<f32>,
+    /// The sunlight and block light levels of the voxel face.
+    @location(3) light: vec2<f32>,
+    /// The color of the block light, the emissive color of the voxel it comes from.
+    @location(4) block_light_color: vec3<f32>,
+    /// The id of the material emitting the block light, animating it.
+    @location(5) block_light_emitter: u32,
 };
 
-fn prepare_pbr_input_from_voxel_mat(voxel_mat: VoxelMat, frag: Fragment) -> PbrInput {
+// Returns the color of the light received by a voxel face, each light level dims the light by 20%.
+// The sunlight is further dimmed by the terrain shadows approximated from the sky shadow heightfield, and by the ones
+// of the shadow map cascades close to the camera when the surface details are drawn.
+fn voxel_light_color(light: vec2<f32>, block_light_color: vec3<f32>, block_light_emitter: u32, world_position: vec3<f32>, normal: vec3<f32>, details: bool) -> vec3<f32> {
+    let sky_shadow = 1.0 - sky_shadow_heightfield.strength * (1.0 - sky_shadow(world_position, normal));
+    var cascade_shadow = 1.0;
+    if (details) {
+        cascade_shadow = 1.0 - terrain_shadows.strength * (1.0 - terrain_cascade_shadow(world_position, normal));
+    }
+    let shadow = min(sky_shadow, cascade_shadow);
+    let sun = pow(0.8, 15.0 * (1.0 - light.x)) * shadow;
+    let block = pow(0.8, 15.0 * (1.0 - light.y)) * select(0.0, 1.0, light.y > 0.0);
+
+    // block light is animated like its emitter, flickering out of phase from one area to another.
+    let animation = terrain_block_light(block_light_emitter);
+    let phase = hash(vec4<f32>(floor(terrain_world_voxel(world_position) / 8.0), 2.0)) * 3.1415926;
+    let t = 6.2831853 * animation.flicker.y * terrain_settings.block_light_time + phase;
+    let flicker = 1.0 + animation.flicker.x * (0.6 * sin(t) + 0.4 * sin(2.3 * t + 1.7));
+
+    return max(vec3<f32>(sun), block * flicker * block_light_color * animation.color.rgb);
+}
+
+fn prepare_pbr_input_from_voxel_mat(voxel_mat: VoxelMat, frag: Fragment, details: bool) -> PbrInput {
 
     var base_color: vec4<f32> = voxel_mat.base_color;
-    base_color = base_color + hash(vec4<f32>(floor(frag.world_position - frag.voxel_normal * 0.5), 1.0)) * 0.0226;
+    base_color = base_color + hash(vec4<f32>(terrain_world_voxel(frag.world_position - frag.voxel_normal * 0.5), 1.0)) * 0.0226;
+    // triplanar noise breaks the uniformity of the large flat areas of the material.
+    if (details && (voxel_mat.flags & VOXEL_MAT_FLAG_TRIPLANAR) != 0u) {
+        let noise = triplanar_noise(frag.world_position, frag.voxel_normal, voxel_mat.triplanar_scale);
+        base_color = vec4<f32>(base_color.rgb * (1.0 + noise * voxel_mat.triplanar_strength), base_color.a);
+    }
 
     var pbr_input: PbrInput = pbr_input_new();
     pbr_input.material.metallic = voxel_mat.metallic;
@@ -77,13 +119,24 @@ fn prepare_pbr_input_from_voxel_mat(voxel_mat: VoxelMat, frag: Fragment) -> PbrI
 
 @fragment
 fn fragment(frag: Fragment) -> @location(0) vec4<f32> {
-    let material = VOXEL_MATERIALS.materials[voxel_data_extract_material_index(frag.voxel_color)];
+    let material = terrain_voxel_material(voxel_data_extract_material_index(frag.voxel_color));
+    let horizontal_distance = distance(frag.world_position.xz, view.world_position.xz);
+    // the secondary views skip the costly details of the distant fragments.
+    let details = horizontal_distance < terrain_view.detail_distance;
 
     /// PBR lighting input data preparation
-    var pbr_input = prepare_pbr_input_from_voxel_mat(material, frag);
-    let pbr_colour = pbr(pbr_input);
-
-    //fragment distance from camera, used to determine amount of fog to apply.
-    let fog_distance = distance(frag.world_position, view.world_position);
-    return ffog_apply_fog(fog_distance, f32(terrain_settings.render_distance) * f32(TERRAIN_CHUNK_LENGTH), f32(TERRAIN_CHUNK_LENGTH), pbr_colour);
+    var pbr_input = prepare_pbr_input_from_voxel_mat(material, frag, details);
+    var pbr_colour = pbr(pbr_input);
+
+    // light emitting voxels aren't dimmed by the voxel light.
+    let emission = max(material.emissive.r, max(material.emissive.g, material.emissive.b));
+    let light = max(voxel_light_color(frag.light, frag.block_light_color, frag.block_light_emitter, frag.world_position, frag.voxel_normal, details), vec3<f32>(max(emission, 0.03)));
+    pbr_colour = vec4<f32>(pbr_colour.rgb * light, pbr_colour.a);
+
+    let horizontal_fog_max = f32(terrain_view.render_distance) * f32(TERRAIN_CHUNK_LENGTH);
+    let vertical_fog_max = f32(terrain_view.vertical_render_distance) * f32(TERRAIN_CHUNK_HEIGHT);
+    // the loaded area is a cylinder, the vertical distance is rescaled so the fog reaches its top and bottom along with its sides.
+    let vertical_distance = abs(frag.world_position.y - view.world_position.y) * horizontal_fog_max / vertical_fog_max;
+    let fogged_colour = ffog_apply_fog(max(horizontal_distance, vertical_distance), terrain_view.fog_start, terrain_view.fog_end, pbr_colour, terrain_settings.fog_color);
+    return ffog_apply_submerged_fog(view.world_position, frag.world_position, terrain_settings.submerged_surface, terrain_settings.submerged_fog, terrain_settings.submerged_absorption, fogged_colour);
 }
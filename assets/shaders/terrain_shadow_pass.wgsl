@@ -0,0 +1,24 @@
+#import "shaders/voxel_data.wgsl"
+
+// Renders the depth of the terrain meshes, as seen from the sun, into the shadow map of a cascade.
+
+struct ShadowCascadeView {
+    // transform from the rendered positions to the clip space of the cascade
+    view_projection: mat4x4<f32>,
+};
+
+struct ShadowCaster {
+    model: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> cascade: ShadowCascadeView;
+
+@group(1) @binding(0)
+var<uniform> caster: ShadowCaster;
+
+@vertex
+fn vertex(@location(0) voxel_data: u32) -> @builtin(position) vec4<f32> {
+    let world_position = caster.model * vec4<f32>(voxel_data_extract_position(voxel_data), 1.0);
+    return cascade.view_projection * world_position;
+}
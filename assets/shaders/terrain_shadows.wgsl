@@ -0,0 +1,39 @@
+
+// Returns how much of the sunlight reaches a voxel face according to the shadow map cascades (1 being fully lit).
+// The faces beyond the last cascade are fully lit, the sky shadows still shading them.
+fn terrain_cascade_shadow(world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let count = terrain_shadows.cascade_count;
+    if (count == 0u) {
+        return 1.0;
+    }
+
+    let sun = sky_shadow_heightfield.sun_direction;
+    // faces turned away from the sun don't receive any of its light.
+    if (dot(normal, sun) <= 0.0) {
+        return 0.0;
+    }
+
+    // the cascades split the view along the camera direction.
+    let depth = -(view.inverse_view * vec4<f32>(world_position, 1.0)).z;
+    var cascade = 0u;
+    loop {
+        if (cascade >= count || depth <= terrain_shadows.splits[cascade]) {
+            break;
+        }
+        cascade = cascade + 1u;
+    }
+    if (cascade >= count) {
+        return 1.0;
+    }
+
+    let biased = world_position + normal * terrain_shadows.normal_bias;
+    let clip = terrain_shadows.view_projections[cascade] * vec4<f32>(biased, 1.0);
+    let ndc = clip.xyz / clip.w;
+    // the Y axis points down in texture coordinates.
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5);
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    return textureSampleCompareLevel(terrain_shadow_maps, terrain_shadow_sampler, uv, i32(cascade), ndc.z);
+}
@@ -1,6 +1,28 @@
 
 let VOXEL_MAT_FLAG_LIQUID: u32 = 2u; // 1 << 1
+let VOXEL_MAT_FLAG_TRIPLANAR: u32 = 16u; // 1 << 4
+// horizontal and vertical lengths of the chunks, must match `CHUNK_LENGTH` and `CHUNK_HEIGHT`.
+// the CHUNK_16, CHUNK_64 and CHUNK_TALL shader defs follow the chunk_16, chunk_64 and chunk_tall features.
+#ifdef CHUNK_16
+let TERRAIN_CHUNK_LENGTH: u32 = 16u;
+#ifdef CHUNK_TALL
+let TERRAIN_CHUNK_HEIGHT: u32 = 32u;
+#else
+let TERRAIN_CHUNK_HEIGHT: u32 = 16u;
+#endif
+#else
+#ifdef CHUNK_64
+let TERRAIN_CHUNK_LENGTH: u32 = 64u;
+let TERRAIN_CHUNK_HEIGHT: u32 = 64u;
+#else
 let TERRAIN_CHUNK_LENGTH: u32 = 32u;
+#ifdef CHUNK_TALL
+let TERRAIN_CHUNK_HEIGHT: u32 = 64u;
+#else
+let TERRAIN_CHUNK_HEIGHT: u32 = 32u;
+#endif
+#endif
+#endif
 
 struct VoxelMat {
     base_color: vec4<f32>,
@@ -9,6 +31,10 @@ struct VoxelMat {
     perceptual_roughness: f32,
     metallic: f32,
     reflectance: f32,
+    // size (in voxels) of the triplanar noise features
+    triplanar_scale: f32,
+    // how much the triplanar noise darkens and lightens the base color
+    triplanar_strength: f32,
 };
 
 // A GPU-suited representation of voxel materials.
@@ -16,8 +42,28 @@ struct VoxelMaterials {
     materials: array<VoxelMat>
 };
 
+// Animation of the block light emitted by a material.
+struct BlockLight {
+    // current drift of the light color, multiplying the color of the emitter
+    color: vec4<f32>,
+    // x: flicker amplitude, y: flicker frequency (Hz)
+    flicker: vec2<f32>,
+};
+
 struct TerrainRenderSettings {
-    render_distance: u32,
+    submerged_fog: vec4<f32>,
+    // color of the distance fog, matching the sky horizon
+    fog_color: vec4<f32>,
+    // world position (X and Z) of the origin of the rendered positions, see `WorldOrigin`
+    world_origin: vec2<i32>,
+    // xyz: per voxel absorption of the colors through the liquid the camera is submerged in, w: its fog density
+    submerged_absorption: vec4<f32>,
+    // height of the surface of the liquid the camera is submerged in
+    submerged_surface: f32,
+    // time the block light animations are evaluated at, in seconds
+    block_light_time: f32,
+    // animation of the block light emitted by each material, see `LightAnimation`
+    block_lights: array<BlockLight>,
 };
 
 @group(2) @binding(0)
@@ -26,6 +72,109 @@ var<storage> VOXEL_MATERIALS: VoxelMaterials;
 @group(2)  @binding(1)
 var<storage> terrain_settings: TerrainRenderSettings;
 
+// Returns the material of the specified index, the indices past the registered materials (e.g. of a mesh built before
+// the materials buffer got updated) falling back to the default material ending the buffer.
+fn terrain_voxel_material(index: u32) -> VoxelMat {
+    return VOXEL_MATERIALS.materials[min(index, arrayLength(&VOXEL_MATERIALS.materials) - 1u)];
+}
+
+// Returns the animation of the block light emitted by the specified material, the materials past the registered ones
+// emitting a steady light.
+fn terrain_block_light(emitter: u32) -> BlockLight {
+    return terrain_settings.block_lights[min(emitter, arrayLength(&terrain_settings.block_lights) - 1u)];
+}
+
+// Returns the world position of the voxel containing the specified rendered position, so that the per-voxel noise
+// doesn't change when the origin of the rendered positions moves.
+fn terrain_world_voxel(position: vec3<f32>) -> vec3<f32> {
+    let origin = terrain_settings.world_origin;
+    return floor(position) + vec3<f32>(f32(origin.x), 0.0, f32(origin.y));
+}
+
+// Height of the highest solid voxel of each column around the player.
+struct SkyShadowHeightfield {
+    // relative to the world origin of the rendered positions
+    origin: vec2<i32>,
+    size: u32,
+    // how much the shadows darken the sunlight, 0 when disabled
+    strength: f32,
+    sun_direction: vec3<f32>,
+    softness: f32,
+    max_distance: f32,
+    heights: array<f32>,
+};
+
+@group(2) @binding(2)
+var<storage> sky_shadow_heightfield: SkyShadowHeightfield;
+
+// Coarse occupancy and material volume of the voxels around the camera, see `VoxelVolume`.
+struct VoxelVolumeParams {
+    // position of the first texel, relative to the world origin of the rendered positions
+    origin: vec3<i32>,
+    // number of voxels along each side of a texel
+    scale: i32,
+    // number of texels along each axis, 0 when disabled
+    size: vec3<u32>,
+};
+
+@group(2) @binding(3)
+var voxel_volume: texture_3d<u32>;
+
+@group(2) @binding(4)
+var<storage> voxel_volume_params: VoxelVolumeParams;
+
+// Returns the material id of the coarse voxel volume texel containing the rendered position, 0 when empty or outside
+// of the volume.
+fn voxel_volume_material(position: vec3<f32>) -> u32 {
+    let local = (position - vec3<f32>(voxel_volume_params.origin)) / f32(voxel_volume_params.scale);
+    let texel = vec3<i32>(floor(local));
+    if (any(texel < vec3<i32>(0)) || any(vec3<u32>(texel) >= voxel_volume_params.size)) {
+        return 0u;
+    }
+    return textureLoad(voxel_volume, texel, 0).r;
+}
+
+// Returns whether the coarse voxel volume texel containing the rendered position holds any voxel.
+fn voxel_volume_occupied(position: vec3<f32>) -> bool {
+    return voxel_volume_material(position) != 0u;
+}
+
+// Sun shadow maps of the terrain, split into cascades of increasing size away from the camera, see `TerrainShadowSettings`.
+struct TerrainShadows {
+    // transforms from the rendered positions to the clip space of each cascade
+    view_projections: array<mat4x4<f32>, 4>,
+    // distance from the camera along its view direction up to which each cascade is used
+    splits: vec4<f32>,
+    // 0 when the shadows are disabled
+    cascade_count: u32,
+    normal_bias: f32,
+    strength: f32,
+};
+
+@group(2) @binding(5)
+var terrain_shadow_maps: texture_depth_2d_array;
+
+@group(2) @binding(6)
+var terrain_shadow_sampler: sampler_comparison;
+
+@group(2) @binding(7)
+var<storage> terrain_shadows: TerrainShadows;
+
+// Render settings of the view drawing the terrain, see `ViewRenderDistance`.
+struct TerrainViewSettings {
+    // horizontal and vertical render distance radii, in chunks
+    render_distance: u32,
+    vertical_render_distance: u32,
+    // horizontal distances at which the distance fog starts and ends, in voxels
+    fog_start: f32,
+    fog_end: f32,
+    // horizontal distance past which the surface details (triplanar noise, cascade shadows) aren't drawn, in voxels
+    detail_distance: f32,
+};
+
+@group(2) @binding(8)
+var<uniform> terrain_view: TerrainViewSettings;
+
 // Returns computed fragment color from the current ambient light + diffuse per face lighting
 fn calc_voxel_lighting(col: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
     let per_face_light = vec3<f32>(0.8, 1.0, 0.6);
@@ -0,0 +1,29 @@
+// Smooth value noise in [-1; 1], interpolating the hashes of the corners of the cell containing a point.
+fn triplanar_value_noise(p: vec2<f32>) -> f32 {
+    let cell = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+
+    let a = hash(vec4<f32>(cell, 3.0, 1.0));
+    let b = hash(vec4<f32>(cell + vec2<f32>(1.0, 0.0), 3.0, 1.0));
+    let c = hash(vec4<f32>(cell + vec2<f32>(0.0, 1.0), 3.0, 1.0));
+    let d = hash(vec4<f32>(cell + vec2<f32>(1.0, 1.0), 3.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+// Two octaves of value noise, in [-1; 1].
+fn triplanar_fbm(p: vec2<f32>) -> f32 {
+    return (triplanar_value_noise(p) * 2.0 + triplanar_value_noise(p * 2.7 + 17.0)) / 3.0;
+}
+
+// Returns noise in [-1; 1] projected along the three axes of the world and blended by the normal of the face, so that it
+// doesn't stretch on any side of the voxels. `scale` is the size (in voxels) of the noise features.
+fn triplanar_noise(world_position: vec3<f32>, normal: vec3<f32>, scale: f32) -> f32 {
+    // the noise doesn't move along with the origin of the rendered positions.
+    let origin = terrain_settings.world_origin;
+    let p = (world_position + vec3<f32>(f32(origin.x), 0.0, f32(origin.y))) / max(scale, 1.0);
+
+    var weights = pow(abs(normal), vec3<f32>(4.0));
+    weights = weights / (weights.x + weights.y + weights.z);
+    return triplanar_fbm(p.zy) * weights.x + triplanar_fbm(p.xz) * weights.y + triplanar_fbm(p.xy) * weights.z;
+}
@@ -1,18 +1,17 @@
 
-
 //
 // Layout of voxel information encoded into a single u32
 //
 //  00000000    00000000    00000000    00000000    
-//  XXXXXYYY    YYZZZZZ          NNN    MATERIAL
+//  ZZZZZZZY    YYYYYYXX    XXXXXNNN    MATERIAL
 //
-// X: X position
-// Y: Y position
-// Z: Z position
+// X: X position, local to the chunk
+// Y: Y position, local to the chunk
+// Z: Z position, local to the chunk
 // N: normal index in the VOXEL_NORMALS array
 // MATERIAL: material index in the palette
 // 
-// The remaining 5 free bits could be used to store UV data or additional info or even extend voxel material id size.
+// The positions are 7 bits wide so that the vertices of chunks up to 127 voxels long on each axis can be encoded.
 
 // An array of voxel face normals 
 var<private> VOXEL_NORMALS: array<vec3<f32>, 6> = array<vec3<f32>, 6>(
@@ -29,15 +28,31 @@ fn voxel_data_extract_normal(voxel_data: u32) -> vec3<f32> {
     return VOXEL_NORMALS[voxel_data >> 8u & 7u];
 }
 
-// fn voxel_data_extract_position(voxel_data: u32) -> vec3<f32> {
-//     return vec3<f32>(
-//         f32(voxel_data >> 27u),
-//         f32(voxel_data >> 22u & 31u),
-//         f32(voxel_data >> 17u & 31u)
-//     );
-// }
+// Extracts the chunk local vertex position from the encoded voxel data
+fn voxel_data_extract_position(voxel_data: u32) -> vec3<f32> {
+    return vec3<f32>(
+        f32(voxel_data >> 11u & 127u),
+        f32(voxel_data >> 18u & 127u),
+        f32(voxel_data >> 25u)
+    );
+}
 
 // Extracts the material index from the encoded voxel data
 fn voxel_data_extract_material_index(voxel_data: u32) -> u32 {
     return voxel_data & 255u;
 }
+
+// Extracts the sunlight (x) and block light (y) levels from the packed voxel light, normalized in the [0, 1] range.
+fn voxel_light_extract_levels(light: u32) -> vec2<f32> {
+    return vec2<f32>(f32(light >> 4u & 15u), f32(light & 15u)) / 15.0;
+}
+
+// Extracts the color of the block light from the packed voxel light, 5 bits per channel.
+fn voxel_light_extract_block_color(light: u32) -> vec3<f32> {
+    return vec3<f32>(f32(light >> 8u & 31u), f32(light >> 13u & 31u), f32(light >> 18u & 31u)) / 31.0;
+}
+
+// Extracts the id of the material emitting the block light from the packed voxel light.
+fn voxel_light_extract_block_emitter(light: u32) -> u32 {
+    return light >> 23u & 255u;
+}
@@ -0,0 +1,175 @@
+use bevy::{
+    input::{keyboard::KeyboardInput, ButtonState},
+    math::{IVec3, Vec3},
+    pbr::{NotShadowCaster, PbrBundle},
+    prelude::{
+        Assets, Color, Commands, Component, CoreStage, EventReader, Handle, KeyCode, Mesh, Plugin,
+        Query, Res, ResMut, StandardMaterial, Transform, Visibility, With,
+    },
+    render::{mesh::Indices, render_resource::PrimitiveTopology, view::NoFrustumCulling},
+    time::Time,
+    utils::HashMap,
+};
+
+use crate::voxel::{
+    render::push_box_lines, ChunkEntities, CurrentLocalPlayerChunk, DirtyChunks, WorldOrigin,
+    CHUNK_SIZE,
+};
+
+/// Color of the borders of the loaded chunks.
+const BORDER_COLOR: Color = Color::rgb(0.3, 0.3, 0.35);
+/// Color of the chunks which were recently marked dirty.
+const DIRTY_COLOR: Color = Color::rgb(1.0, 0.5, 0.1);
+/// Color of the chunk the player is in.
+const PLAYER_CHUNK_COLOR: Color = Color::rgb(1.0, 0.9, 0.1);
+
+/// Settings of the chunk border visualizer, toggled with the F8 key or from the chunk inspector.
+pub struct ChunkBorderDebug {
+    pub enabled: bool,
+    /// Highlights the chunks marked dirty during the last `dirty_highlight_duration` seconds.
+    pub show_dirty: bool,
+    pub dirty_highlight_duration: f32,
+    /// Only the borders of the chunks within this radius of the player chunk (in chunks) are drawn.
+    pub radius: i32,
+    /// When each chunk got last marked dirty, in seconds since startup.
+    last_dirty: HashMap<IVec3, f32>,
+}
+
+impl Default for ChunkBorderDebug {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            show_dirty: true,
+            dirty_highlight_duration: 1.0,
+            radius: 3,
+            last_dirty: Default::default(),
+        }
+    }
+}
+
+/// Tags the line mesh drawing the chunk borders.
+#[derive(Component)]
+struct ChunkBorderLines;
+
+fn toggle_chunk_borders(
+    mut inputs: EventReader<KeyboardInput>,
+    mut settings: ResMut<ChunkBorderDebug>,
+) {
+    for input in inputs.iter() {
+        match input.key_code {
+            Some(KeyCode::F8) if input.state == ButtonState::Pressed => {
+                settings.enabled = !settings.enabled;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn spawn_chunk_border_lines(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    // the mesh gets rebuilt every frame, its bounds never get updated.
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::new(PrimitiveTopology::LineList)),
+            material: materials.add(StandardMaterial {
+                base_color: Color::WHITE,
+                unlit: true,
+                ..Default::default()
+            }),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(ChunkBorderLines)
+        .insert(NotShadowCaster)
+        .insert(NoFrustumCulling);
+}
+
+/// Rebuilds the lines of the borders of the loaded chunks around the player.
+/// The lines are positioned relative to the chunk of the player, keeping them precise far away from the world origin.
+#[allow(clippy::too_many_arguments)]
+fn update_chunk_border_lines(
+    mut settings: ResMut<ChunkBorderDebug>,
+    time: Res<Time>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    loaded_chunks: Res<ChunkEntities>,
+    dirty_chunks: Res<DirtyChunks>,
+    origin: Res<WorldOrigin>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut lines: Query<(&Handle<Mesh>, &mut Transform, &mut Visibility), With<ChunkBorderLines>>,
+) {
+    let (mesh, mut transform, mut visibility) = match lines.get_single_mut() {
+        Ok(lines) => lines,
+        Err(_) => return,
+    };
+
+    visibility.is_visible = settings.enabled;
+    if !settings.enabled {
+        settings.last_dirty.clear();
+        return;
+    }
+
+    let now = time.seconds_since_startup() as f32;
+    let duration = settings.dirty_highlight_duration;
+    settings.last_dirty.retain(|_, time| now - *time < duration);
+    dirty_chunks.iter_dirty().for_each(|key| {
+        settings.last_dirty.insert(*key, now);
+    });
+
+    let (mut positions, mut colors, mut indices) = (Vec::new(), Vec::new(), Vec::new());
+    let size = CHUNK_SIZE.as_vec3();
+    let player_key = player_chunk.chunk_min;
+    transform.translation = origin.to_translation(player_key);
+
+    for key in loaded_chunks.iter_keys() {
+        let distance = (*key - player_key) / CHUNK_SIZE;
+        if distance.abs().max_element() > settings.radius {
+            continue;
+        }
+
+        let color = if settings.show_dirty && settings.last_dirty.contains_key(key) {
+            DIRTY_COLOR
+        } else {
+            BORDER_COLOR
+        };
+        let min = (*key - player_key).as_vec3();
+        push_box_lines(min, min + size, &mut positions, &mut indices);
+        colors.resize(positions.len(), color.as_linear_rgba_f32());
+    }
+
+    // inset so that the player chunk lines aren't hidden by the borders drawn at the same place.
+    let min = Vec3::splat(0.05);
+    push_box_lines(
+        min,
+        min + size - Vec3::splat(0.1),
+        &mut positions,
+        &mut indices,
+    );
+    colors.resize(positions.len(), PLAYER_CHUNK_COLOR.as_linear_rgba_f32());
+
+    if let Some(mesh) = meshes.get_mut(mesh) {
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            vec![[0.0, 1.0, 0.0]; positions.len()],
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; positions.len()]);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh.set_indices(Some(Indices::U32(indices)));
+    }
+}
+
+/// Draws the borders of the loaded chunks, the recently dirtied chunks and the chunk the player is in.
+pub struct ChunkBorderDebugPlugin;
+
+impl Plugin for ChunkBorderDebugPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkBorderDebug>()
+            .add_startup_system(spawn_chunk_border_lines)
+            .add_system(toggle_chunk_borders)
+            // the dirty chunks are only known once all the chunk stages ran, and get cleared on the last stage.
+            .add_system_to_stage(CoreStage::PostUpdate, update_chunk_border_lines);
+    }
+}
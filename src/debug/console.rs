@@ -0,0 +1,898 @@
+use bevy::{
+    input::{keyboard::KeyboardInput, ButtonState},
+    math::IVec3,
+    prelude::{
+        EventReader, EventWriter, GlobalTransform, KeyCode, Local,
+        ParallelSystemDescriptorCoercion, Plugin, Query, Res, ResMut, SystemLabel, With,
+    },
+    utils::HashSet,
+};
+use bevy_egui::{egui, EguiContext};
+
+use crate::voxel::{
+    chunk_key_at,
+    interaction::{PlacementMaterial, TargetedVoxel},
+    material::VoxelMaterialRegistry,
+    player::PlayerController,
+    schematic::{ExportSchematic, ImportSchematic},
+    storage::WorldSave,
+    terraingen::{
+        register_default_biomes, BaseTerrain, TerrainGenerator, TerrainGeneratorSwap,
+        TerrainGeneratorSwapped, TERRAIN_GENERATOR,
+    },
+    BrushShape, CancelChunkPregen, ChunkDecision, ChunkIntegrity, ChunkPipelineLog, ChunkPregen,
+    ChunkPregenFinished, ChunkPregenProgress, Explosion, ExplosionRemeshLatency, ExportChunkText,
+    ImportChunkText, ReplayPlayer, ReplayRecorder, StartChunkPregen, StopReplayRecording,
+    TerraformBrush, ValidateChunks, VoxelInventory, VoxelSimulationPause, WorldOrigin,
+    MAX_BRUSH_RADIUS, MAX_EXPLOSION_RADIUS,
+};
+
+/// Maximum number of lines kept in the console log.
+const MAX_LOG_LINES: usize = 256;
+
+/// A command typed in the debug console.
+pub struct ConsoleCommand {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// State of the debug console.
+#[derive(Default)]
+pub struct Console {
+    open: bool,
+    input: String,
+    log: Vec<String>,
+    commands: Vec<(&'static str, &'static str)>,
+}
+
+#[allow(dead_code)]
+impl Console {
+    /// Registers a command so that it is listed by `help` and not reported as unknown.
+    pub fn register_command(&mut self, name: &'static str, description: &'static str) -> &mut Self {
+        self.commands.push((name, description));
+        self
+    }
+
+    /// Appends a line to the console log.
+    pub fn print(&mut self, line: impl Into<String>) {
+        self.log.push(line.into());
+        if self.log.len() > MAX_LOG_LINES {
+            self.log.remove(0);
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+}
+
+fn toggle_console(mut inputs: EventReader<KeyboardInput>, mut console: ResMut<Console>) {
+    for input in inputs.iter() {
+        match input.key_code {
+            Some(KeyCode::Grave) if input.state == ButtonState::Pressed => {
+                console.open = !console.open;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn display_console(
+    mut egui: ResMut<EguiContext>,
+    mut console: ResMut<Console>,
+    mut commands: EventWriter<ConsoleCommand>,
+) {
+    if !console.open {
+        return;
+    }
+
+    egui::Window::new("console").show(egui.ctx_mut(), |ui| {
+        egui::ScrollArea::vertical()
+            .max_height(240.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                console.log.iter().for_each(|line| {
+                    ui.monospace(line);
+                });
+            });
+
+        ui.separator();
+        let response = ui.text_edit_singleline(&mut console.input);
+
+        if response.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+            let line = std::mem::take(&mut console.input);
+            let mut words = line.split_whitespace().map(String::from);
+
+            if let Some(name) = words.next() {
+                console.print(format!("> {}", line));
+
+                if name == "help" {
+                    let help: Vec<String> = console
+                        .commands
+                        .iter()
+                        .map(|(name, description)| format!("{} - {}", name, description))
+                        .collect();
+                    help.into_iter().for_each(|line| console.print(line));
+                } else if console.commands.iter().any(|(command, _)| *command == name) {
+                    commands.send(ConsoleCommand {
+                        name,
+                        args: words.collect(),
+                    });
+                } else {
+                    console.print(format!(
+                        "Unknown command {:?}, type help for a list of commands",
+                        name
+                    ));
+                }
+            }
+
+            response.request_focus();
+        }
+    });
+}
+
+/// Handles the chunk related console commands.
+fn handle_chunk_commands(
+    mut commands: EventReader<ConsoleCommand>,
+    mut validate_events: EventWriter<ValidateChunks>,
+) {
+    for command in commands.iter() {
+        if command.name == "validate_chunks" {
+            validate_events.send(ValidateChunks {
+                repair: command.args.iter().any(|arg| arg == "repair"),
+            });
+        }
+    }
+}
+
+/// Prints the chunk validation reports to the console.
+fn print_chunk_integrity_reports(integrity: Res<ChunkIntegrity>, mut console: ResMut<Console>) {
+    if !integrity.is_changed() {
+        return;
+    }
+
+    if let Some(report) = integrity.last_report.as_ref() {
+        console.print(format!(
+            "Chunk validation: {} inconsistencies{}",
+            report.issue_count(),
+            if report.repaired { " (repaired)" } else { "" }
+        ));
+        report
+            .summary()
+            .into_iter()
+            .for_each(|line| console.print(line));
+    }
+}
+
+/// Formats the progress of a chunk pregeneration for the console.
+fn format_pregen_progress(progress: &ChunkPregenProgress) -> String {
+    format!(
+        "{}/{} chunks ({:.0}%): {} generated, {} skipped, {} failed in {:.1}s",
+        progress.done(),
+        progress.total,
+        progress.fraction() * 100.0,
+        progress.generated,
+        progress.skipped,
+        progress.failed,
+        progress.elapsed.as_secs_f32()
+    )
+}
+
+/// Handles the `pregen <radius> [x z]`, `pregen status` and `pregen cancel` commands.
+/// The pregenerated area is centered on the player unless a position is specified.
+#[allow(clippy::too_many_arguments)]
+fn handle_pregen_commands(
+    mut commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut start_events: EventWriter<StartChunkPregen>,
+    mut cancel_events: EventWriter<CancelChunkPregen>,
+    pregen: Res<ChunkPregen>,
+    world_save: Option<Res<WorldSave>>,
+    player: Query<&GlobalTransform, With<PlayerController>>,
+    origin: Res<WorldOrigin>,
+) {
+    for command in commands.iter().filter(|command| command.name == "pregen") {
+        let args: Vec<&str> = command.args.iter().map(|arg| arg.as_str()).collect();
+
+        match args.as_slice() {
+            ["status"] if pregen.is_running() => {
+                console.print(format!(
+                    "Pregenerating: {}",
+                    format_pregen_progress(&pregen.progress())
+                ));
+            }
+            ["status"] => console.print("No pregeneration in progress"),
+            ["cancel"] => cancel_events.send(CancelChunkPregen),
+            [radius, position @ ..] => {
+                let radius = match radius.parse::<i32>() {
+                    Ok(radius) if radius >= 0 => radius,
+                    _ => {
+                        console.print(format!("Invalid pregeneration radius {:?}", radius));
+                        continue;
+                    }
+                };
+
+                // a position without height is pregenerated around the height of the player.
+                let player_position = player
+                    .get_single()
+                    .ok()
+                    .map(|transform| origin.voxel_at(transform.translation()));
+                let center = match position {
+                    [x, z] => match (x.parse::<i32>(), z.parse::<i32>()) {
+                        (Ok(x), Ok(z)) => Some(IVec3::new(
+                            x,
+                            player_position.map_or(0, |position| position.y),
+                            z,
+                        )),
+                        _ => None,
+                    },
+                    [] => player_position,
+                    _ => None,
+                };
+
+                match center {
+                    Some(_) if world_save.is_none() => {
+                        console.print("Pregeneration requires a world save, start with --world")
+                    }
+                    Some(center) => {
+                        console.print(format!(
+                            "Pregenerating the chunks within {} chunks of {:?}",
+                            radius, center
+                        ));
+                        start_events.send(StartChunkPregen { center, radius });
+                    }
+                    None => console.print("Usage: pregen <radius> [x z] | status | cancel"),
+                }
+            }
+            [] => console.print("Usage: pregen <radius> [x z] | status | cancel"),
+        }
+    }
+}
+
+/// Parses the coordinates of a voxel position.
+fn parse_position(args: &[&str]) -> Option<IVec3> {
+    match args {
+        [x, y, z] => Some(IVec3::new(
+            x.parse().ok()?,
+            y.parse().ok()?,
+            z.parse().ok()?,
+        )),
+        _ => None,
+    }
+}
+
+/// Handles the `schem_import <path> [x y z]` and `schem_export <path> <x1 y1 z1> <x2 y2 z2>` commands.
+/// Schematics are imported at the position of the player unless a position is specified.
+fn handle_schematic_commands(
+    mut commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut import_events: EventWriter<ImportSchematic>,
+    mut export_events: EventWriter<ExportSchematic>,
+    player: Query<&GlobalTransform, With<PlayerController>>,
+    origin: Res<WorldOrigin>,
+) {
+    for command in commands.iter() {
+        let args: Vec<&str> = command.args.iter().map(|arg| arg.as_str()).collect();
+
+        match (command.name.as_str(), args.as_slice()) {
+            ("schem_import", [path, position @ ..]) => {
+                let position = match position {
+                    [] => player
+                        .get_single()
+                        .ok()
+                        .map(|transform| origin.voxel_at(transform.translation())),
+                    position => parse_position(position),
+                };
+
+                match position {
+                    Some(position) => {
+                        console.print(format!("Importing schematic {} at {:?}", path, position));
+                        import_events.send(ImportSchematic {
+                            path: path.into(),
+                            position,
+                        });
+                    }
+                    None => console.print("Usage: schem_import <path> [x y z]"),
+                }
+            }
+            ("schem_import", []) => console.print("Usage: schem_import <path> [x y z]"),
+            ("schem_export", [path, x1, y1, z1, x2, y2, z2]) => {
+                match (
+                    parse_position(&[*x1, *y1, *z1]),
+                    parse_position(&[*x2, *y2, *z2]),
+                ) {
+                    (Some(min), Some(max)) => {
+                        console.print(format!(
+                            "Exporting the voxels from {:?} to {:?} to schematic {}",
+                            min, max, path
+                        ));
+                        export_events.send(ExportSchematic {
+                            path: path.into(),
+                            min,
+                            max,
+                        });
+                    }
+                    _ => console.print("Usage: schem_export <path> <x1 y1 z1> <x2 y2 z2>"),
+                }
+            }
+            ("schem_export", _) => {
+                console.print("Usage: schem_export <path> <x1 y1 z1> <x2 y2 z2>")
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Handles the `chunk_export` and `chunk_import` commands, the chunk being picked by a position inside of it.
+fn handle_chunk_text_commands(
+    mut commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut export_events: EventWriter<ExportChunkText>,
+    mut import_events: EventWriter<ImportChunkText>,
+    player: Query<&GlobalTransform, With<PlayerController>>,
+    origin: Res<WorldOrigin>,
+) {
+    let player_position = || {
+        player
+            .get_single()
+            .ok()
+            .map(|transform| origin.voxel_at(transform.translation()))
+    };
+
+    for command in commands.iter() {
+        let args: Vec<&str> = command.args.iter().map(|arg| arg.as_str()).collect();
+
+        match (command.name.as_str(), args.as_slice()) {
+            ("chunk_export", [path, position @ ..]) => {
+                let position = match position {
+                    [] => player_position(),
+                    position => parse_position(position),
+                };
+
+                match position.map(chunk_key_at) {
+                    Some(key) => {
+                        console.print(format!("Exporting chunk {:?} to {}", key, path));
+                        export_events.send(ExportChunkText {
+                            path: path.into(),
+                            key,
+                        });
+                    }
+                    None => console.print("Usage: chunk_export <path> [x y z]"),
+                }
+            }
+            ("chunk_import", [path, position @ ..]) => {
+                let key = match position {
+                    [] => None,
+                    position => match parse_position(position) {
+                        Some(position) => Some(chunk_key_at(position)),
+                        None => {
+                            console.print("Usage: chunk_import <path> [x y z]");
+                            continue;
+                        }
+                    },
+                };
+
+                console.print(format!("Importing chunk {}", path));
+                import_events.send(ImportChunkText {
+                    path: path.into(),
+                    key,
+                });
+            }
+            ("chunk_export", []) => console.print("Usage: chunk_export <path> [x y z]"),
+            ("chunk_import", []) => console.print("Usage: chunk_import <path> [x y z]"),
+            _ => {}
+        }
+    }
+}
+
+/// Handles the `replay status` and `replay stop` commands, the recording and playback being started from the command line.
+fn handle_replay_commands(
+    mut commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut stop_events: EventWriter<StopReplayRecording>,
+    recorder: Option<Res<ReplayRecorder>>,
+    replay_player: Option<Res<ReplayPlayer>>,
+) {
+    for command in commands.iter().filter(|command| command.name == "replay") {
+        let args: Vec<&str> = command.args.iter().map(|arg| arg.as_str()).collect();
+
+        match args.as_slice() {
+            ["status"] => {
+                match recorder.as_ref() {
+                    Some(recorder) => console.print(format!(
+                        "Recording to {}: {} frames",
+                        recorder.path().display(),
+                        recorder.frame_count()
+                    )),
+                    None => console.print("Not recording, start with --record <path>"),
+                }
+                if let Some(replay_player) = replay_player.as_ref() {
+                    let (frame, frames) = replay_player.progress();
+                    console.print(format!("Playing back frame {}/{}", frame, frames));
+                }
+            }
+            ["stop"] if recorder.is_some() => stop_events.send(StopReplayRecording),
+            ["stop"] => console.print("Not recording"),
+            _ => console.print("Usage: replay status | stop"),
+        }
+    }
+}
+
+/// Handles the `brush <sphere|cube|smooth> [radius]` and `brush off` commands, the fill brushes using the placement material.
+fn handle_brush_commands(
+    mut commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut brush: ResMut<TerraformBrush>,
+    placement_material: Res<PlacementMaterial>,
+) {
+    for command in commands.iter().filter(|command| command.name == "brush") {
+        let args: Vec<&str> = command.args.iter().map(|arg| arg.as_str()).collect();
+
+        let (shape, radius) = match args.as_slice() {
+            ["off"] => {
+                brush.enabled = false;
+                console.print("Brush disabled");
+                continue;
+            }
+            [shape, radius @ ..] => (
+                match *shape {
+                    "sphere" => Some(BrushShape::Sphere),
+                    "cube" => Some(BrushShape::Cube),
+                    "smooth" => Some(BrushShape::Smooth),
+                    _ => None,
+                },
+                match radius {
+                    [] => Some(brush.radius),
+                    [radius] => radius
+                        .parse::<i32>()
+                        .ok()
+                        .filter(|radius| (0..=MAX_BRUSH_RADIUS).contains(radius)),
+                    _ => None,
+                },
+            ),
+            [] => (None, None),
+        };
+
+        match (shape, radius) {
+            (Some(shape), Some(radius)) => {
+                brush.enabled = true;
+                brush.shape = shape;
+                brush.radius = radius;
+                brush.material = placement_material.0;
+                console.print(format!(
+                    "{:?} brush of radius {} enabled, left click applies it and right click erases",
+                    shape, radius
+                ));
+            }
+            _ => console.print(format!(
+                "Usage: brush <sphere|cube|smooth> [radius up to {}] | off",
+                MAX_BRUSH_RADIUS
+            )),
+        }
+    }
+}
+
+/// Handles the `explode [radius]` command, blowing up the targeted voxel or the voxel at the player if none is targeted.
+fn handle_explode_commands(
+    mut commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    targeted: Res<TargetedVoxel>,
+    player: Query<&GlobalTransform, With<PlayerController>>,
+    origin: Res<WorldOrigin>,
+    latency: Res<ExplosionRemeshLatency>,
+    mut explosions: EventWriter<Explosion>,
+) {
+    for command in commands.iter().filter(|command| command.name == "explode") {
+        let radius = match command.args.as_slice() {
+            [] => 6.0,
+            [radius] => match radius.parse::<f32>() {
+                Ok(radius) if radius > 0.0 && radius <= MAX_EXPLOSION_RADIUS => radius,
+                _ => {
+                    console.print(format!(
+                        "Usage: explode [radius up to {}]",
+                        MAX_EXPLOSION_RADIUS
+                    ));
+                    continue;
+                }
+            },
+            _ => {
+                console.print("Usage: explode [radius]");
+                continue;
+            }
+        };
+
+        let center = match (targeted.0, player.get_single()) {
+            (Some(hit), _) => hit.position,
+            (None, Ok(transform)) => origin.voxel_at(transform.translation()),
+            (None, Err(_)) => {
+                console.print("Nothing to blow up");
+                continue;
+            }
+        };
+
+        explosions.send(Explosion { center, radius });
+        console.print(format!(
+            "Explosion of radius {} at {}{}",
+            radius,
+            center,
+            latency.last.map_or(String::new(), |last| format!(
+                ", the last one got remeshed in {:.1}ms",
+                last.as_secs_f64() * 1000.0
+            ))
+        ));
+    }
+}
+
+/// Handles the `inventory` command, listing the voxels picked up by the player.
+fn handle_inventory_commands(
+    mut commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    inventory: Res<VoxelInventory>,
+    materials: Res<VoxelMaterialRegistry>,
+) {
+    for _ in commands
+        .iter()
+        .filter(|command| command.name == "inventory")
+    {
+        if inventory.is_empty() {
+            console.print("The inventory is empty");
+            continue;
+        }
+
+        for (voxel, count) in inventory.iter() {
+            console.print(format!(
+                "{} x{}",
+                materials
+                    .get_by_id(voxel.0)
+                    .map_or("unknown material", |material| material.name.as_ref()),
+                count
+            ));
+        }
+    }
+}
+
+/// Handles the `pause`, `resume` and `step [updates]` commands controlling the [`VoxelSimulationPause`].
+fn handle_pause_commands(
+    mut commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut pause: ResMut<VoxelSimulationPause>,
+) {
+    for command in commands.iter() {
+        match (command.name.as_str(), command.args.as_slice()) {
+            ("pause", []) => {
+                pause.paused = true;
+                console.print("Voxel simulation paused, use step to advance it");
+            }
+            ("resume", []) => {
+                pause.paused = false;
+                console.print("Voxel simulation resumed");
+            }
+            ("step", args) if args.len() <= 1 => {
+                let updates = match args.first().map(|updates| updates.parse::<u32>()) {
+                    None => 1,
+                    Some(Ok(updates)) if updates > 0 => updates,
+                    Some(_) => {
+                        console.print("Usage: step [updates]");
+                        continue;
+                    }
+                };
+                if !pause.paused {
+                    console.print("The voxel simulation isn't paused");
+                    continue;
+                }
+
+                (0..updates).for_each(|_| pause.step());
+                console.print(format!(
+                    "Stepping {} update(s) of the voxel simulation",
+                    updates
+                ));
+            }
+            ("pause" | "resume" | "step", _) => {
+                console.print("Usage: pause | resume | step [updates]")
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Handles the `terrain` command, swapping the terrain generator for the chunks generated afterwards.
+fn handle_terrain_commands(
+    mut commands: EventReader<ConsoleCommand>,
+    mut swapped_events: EventReader<TerrainGeneratorSwapped>,
+    mut console: ResMut<Console>,
+    mut swap: ResMut<TerrainGeneratorSwap>,
+) {
+    for command in commands.iter() {
+        let base = match (command.name.as_str(), command.args.as_slice()) {
+            ("terrain", []) => {
+                console.print(format!(
+                    "Generating {} terrain",
+                    TERRAIN_GENERATOR.read().unwrap().base_terrain().name()
+                ));
+                continue;
+            }
+            ("terrain", [base]) if base == "noise" => BaseTerrain::Noise,
+            ("terrain", [base]) if base == "superflat" => BaseTerrain::default_superflat(),
+            ("terrain", _) => {
+                console.print("Usage: terrain [noise|superflat]");
+                continue;
+            }
+            _ => continue,
+        };
+
+        let mut generator = TerrainGenerator::default();
+        register_default_biomes(&mut generator);
+        generator.set_base_terrain(base);
+        swap.request(generator);
+    }
+
+    for swapped in swapped_events.iter() {
+        console.print(format!(
+            "Switched to {} terrain, the loaded chunks are kept as is",
+            swapped.base_terrain.name()
+        ));
+    }
+}
+
+/// Parses the decisions listed in a command, `all` standing for every decision.
+fn parse_decisions(args: &[&str]) -> Option<Vec<ChunkDecision>> {
+    if args == ["all"] {
+        return Some(ChunkDecision::ALL.to_vec());
+    }
+    args.iter()
+        .map(|name| ChunkDecision::from_name(name))
+        .collect()
+}
+
+/// Handles the `chunklog on|off|clear|filter <decisions...|all>` commands controlling the [`ChunkPipelineLog`],
+/// and the `why chunk <x y z> [decisions...]` command printing the logged decisions of the chunk containing a position.
+fn handle_chunk_log_commands(
+    mut commands: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut log: ResMut<ChunkPipelineLog>,
+) {
+    let decision_names = || {
+        ChunkDecision::ALL
+            .iter()
+            .map(|decision| decision.name())
+            .collect::<Vec<_>>()
+            .join("|")
+    };
+
+    for command in commands.iter() {
+        let args: Vec<&str> = command.args.iter().map(|arg| arg.as_str()).collect();
+
+        match (command.name.as_str(), args.as_slice()) {
+            ("chunklog", ["on"]) => {
+                log.enabled = true;
+                console.print("Chunk pipeline logging enabled");
+            }
+            ("chunklog", ["off"]) => {
+                log.enabled = false;
+                console.print("Chunk pipeline logging disabled");
+            }
+            ("chunklog", ["clear"]) => {
+                log.clear();
+                console.print("Chunk pipeline log cleared");
+            }
+            ("chunklog", ["filter", decisions @ ..]) if !decisions.is_empty() => {
+                match parse_decisions(decisions) {
+                    Some(decisions) => {
+                        log.recorded = decisions.into_iter().collect();
+                        console.print(format!("Recording {}", decisions_list(&log.recorded)));
+                    }
+                    None => console.print(format!(
+                        "Unknown decision, expected all or {}",
+                        decision_names()
+                    )),
+                }
+            }
+            ("chunklog", []) => console.print(format!(
+                "Chunk pipeline logging {}, {} chunks logged, recording {}",
+                if log.enabled { "enabled" } else { "disabled" },
+                log.chunk_count(),
+                decisions_list(&log.recorded)
+            )),
+            ("chunklog", _) => console.print(format!(
+                "Usage: chunklog [on|off|clear|filter <{}|all>...]",
+                decision_names()
+            )),
+            ("why", ["chunk", x, y, z, decisions @ ..]) => {
+                let (pos, decisions) =
+                    match (parse_position(&[*x, *y, *z]), parse_decisions(decisions)) {
+                        (Some(pos), Some(decisions)) => (pos, decisions),
+                        _ => {
+                            console.print(format!(
+                                "Usage: why chunk <x y z> [{}|all]...",
+                                decision_names()
+                            ));
+                            continue;
+                        }
+                    };
+
+                let key = chunk_key_at(pos);
+                let lines: Vec<String> = log
+                    .entries(key)
+                    .filter(|entry| decisions.is_empty() || decisions.contains(&entry.decision))
+                    .map(|entry| {
+                        format!(
+                            "[{:>9.3}s frame {}] {}: {}",
+                            entry.time.as_secs_f64(),
+                            entry.frame,
+                            entry.decision.name(),
+                            entry.reason
+                        )
+                    })
+                    .collect();
+
+                if lines.is_empty() {
+                    console.print(format!(
+                        "Nothing logged for chunk {}{}",
+                        key,
+                        if log.enabled {
+                            ""
+                        } else {
+                            ", enable the log with chunklog on"
+                        }
+                    ));
+                } else {
+                    console.print(format!("Chunk {}:", key));
+                    lines.into_iter().for_each(|line| console.print(line));
+                }
+            }
+            ("why", _) => console.print(format!(
+                "Usage: why chunk <x y z> [{}|all]...",
+                decision_names()
+            )),
+            _ => {}
+        }
+    }
+}
+
+/// Formats a set of decisions for the console.
+fn decisions_list(decisions: &HashSet<ChunkDecision>) -> String {
+    let names: Vec<&str> = ChunkDecision::ALL
+        .into_iter()
+        .filter(|decision| decisions.contains(decision))
+        .map(|decision| decision.name())
+        .collect();
+    if names.is_empty() {
+        "nothing".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+/// Prints the progress of the chunk pregeneration to the console every 10%, and its outcome.
+fn print_pregen_progress(
+    pregen: Res<ChunkPregen>,
+    mut finished_events: EventReader<ChunkPregenFinished>,
+    mut console: ResMut<Console>,
+    mut last_decile: Local<Option<usize>>,
+) {
+    for finished in finished_events.iter() {
+        console.print(format!(
+            "Pregeneration {}: {}",
+            if finished.cancelled {
+                "cancelled"
+            } else {
+                "done"
+            },
+            format_pregen_progress(&finished.progress)
+        ));
+        *last_decile = None;
+    }
+
+    if !pregen.is_running() {
+        return;
+    }
+
+    let progress = pregen.progress();
+    let decile = (progress.fraction() * 10.0) as usize;
+    if *last_decile != Some(decile) {
+        if last_decile.is_some() {
+            console.print(format_pregen_progress(&progress));
+        }
+        *last_decile = Some(decile);
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`DebugConsolePlugin`]
+pub enum ConsoleSystem {
+    /// Displays the console window and sends the typed commands.
+    DisplayConsole,
+}
+
+/// A debug console for running commands, toggled with the grave (`) key.
+pub struct DebugConsolePlugin;
+
+impl Plugin for DebugConsolePlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let mut console = Console::default();
+        console.register_command(
+            "validate_chunks",
+            "cross-checks the chunk bookkeeping, pass repair to fix the inconsistencies found",
+        );
+        console.register_command(
+            "pregen",
+            "pregen <radius> [x z] generates the chunks within radius chunks to the world save, pregen status | cancel",
+        );
+        console.register_command(
+            "schem_import",
+            "schem_import <path> [x y z] loads a .vox or .schem file into the world at the player or the specified position",
+        );
+        console.register_command(
+            "schem_export",
+            "schem_export <path> <x1 y1 z1> <x2 y2 z2> saves the voxels of a region to a .vox or .schem file",
+        );
+        console.register_command(
+            "chunk_export",
+            "chunk_export <path> [x y z] writes the voxels of the chunk at the player or the specified position as text",
+        );
+        console.register_command(
+            "chunk_import",
+            "chunk_import <path> [x y z] replaces the voxels of a loaded chunk, the one written in the file by default",
+        );
+
+        console.register_command(
+            "replay",
+            "replay status | stop shows the state of the replay recording or playback, stops and saves the recording",
+        );
+
+        console.register_command(
+            "brush",
+            "brush <sphere|cube|smooth> [radius] | off enables the terraforming brush with the placement material",
+        );
+
+        console.register_command(
+            "explode",
+            "explode [radius] blows up a ragged crater at the targeted voxel, logging how long its chunks take to get remeshed",
+        );
+
+        console.register_command(
+            "chunklog",
+            "chunklog [on|off|clear|filter <decisions...>] controls the log of the chunk pipeline decisions",
+        );
+        console.register_command(
+            "why",
+            "why chunk <x y z> [decisions...] prints why the chunk containing a position got loaded, unloaded or remeshed",
+        );
+
+        console.register_command(
+            "inventory",
+            "inventory lists the voxels picked up from the drops of the broken voxels",
+        );
+        console.register_command(
+            "pause",
+            "pause freezes the chunk loading, generation, fluids, lighting and meshing, the camera staying free",
+        );
+        console.register_command("resume", "resume unfreezes the voxel simulation");
+        console.register_command(
+            "terrain",
+            "terrain [noise|superflat] switches the terrain generated for the chunks not generated yet",
+        );
+        console.register_command(
+            "step",
+            "step [updates] advances the paused voxel simulation by the specified number of updates",
+        );
+
+        app.insert_resource(console)
+            .add_event::<ConsoleCommand>()
+            .add_system(toggle_console)
+            .add_system(display_console.label(ConsoleSystem::DisplayConsole))
+            .add_system(handle_chunk_commands.after(ConsoleSystem::DisplayConsole))
+            .add_system(handle_pregen_commands.after(ConsoleSystem::DisplayConsole))
+            .add_system(handle_schematic_commands.after(ConsoleSystem::DisplayConsole))
+            .add_system(handle_chunk_text_commands.after(ConsoleSystem::DisplayConsole))
+            .add_system(handle_replay_commands.after(ConsoleSystem::DisplayConsole))
+            .add_system(handle_brush_commands.after(ConsoleSystem::DisplayConsole))
+            .add_system(handle_explode_commands.after(ConsoleSystem::DisplayConsole))
+            .add_system(handle_chunk_log_commands.after(ConsoleSystem::DisplayConsole))
+            .add_system(handle_pause_commands.after(ConsoleSystem::DisplayConsole))
+            .add_system(handle_terrain_commands.after(ConsoleSystem::DisplayConsole))
+            .add_system(handle_inventory_commands.after(ConsoleSystem::DisplayConsole))
+            .add_system(print_chunk_integrity_reports)
+            .add_system(print_pregen_progress);
+    }
+}
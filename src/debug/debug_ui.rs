@@ -2,23 +2,71 @@ use bevy::{
     diagnostic::{Diagnostics, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin},
     ecs::schedule::ShouldRun,
     input::{keyboard::KeyboardInput, ButtonState},
+    math::{IVec2, Vec2, Vec3Swizzles},
     prelude::{
-        Color, CoreStage, EventReader, KeyCode, ParallelSystemDescriptorCoercion, Plugin, Res,
-        ResMut, SystemSet, SystemStage,
+        error, info, Color, CoreStage, EventReader, EventWriter, KeyCode, Local,
+        ParallelSystemDescriptorCoercion, Plugin, Res, ResMut, StageLabel, SystemSet, SystemStage,
     },
+    utils::Duration,
 };
 use bevy_egui::{
     egui::{self, Rgba, Slider},
-    EguiContext, EguiPlugin,
+    EguiContext,
 };
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::screenshot::TakeScreenshot;
 use crate::voxel::{
-    material::VoxelMaterialRegistry, ChunkCommandQueue, ChunkEntities, ChunkLoadRadius,
-    CurrentLocalPlayerChunk, DirtyChunks,
+    interaction::{PlacementMaterial, TargetedVoxel},
+    material::{MaterialRegistryInfo, VoxelMaterialFlags, VoxelMaterialRegistry},
+    player::TeleportPlayer,
+    render::{
+        DistanceFogSettings, SkySettings, SubmergedFogSettings, TerrainShadowCascades,
+        TerrainShadowSettings, MAX_SHADOW_CASCADES,
+    },
+    storage::ChunkMap,
+    terraingen::{TerrainGenConfig, WorldSeed, TERRAIN_GENERATOR},
+    AnchoredChunks, ChunkCommandQueue, ChunkCompressionSettings, ChunkEntities, ChunkGenErrors,
+    ChunkIntegrity, ChunkLoadRadius, ChunkLoadShape, ChunkMemoryUsage, ChunkMeshApplyStats,
+    ChunkOcclusionCulling, ChunkPipelineStats, ChunkShape, ChunkTaskBudget,
+    CurrentLocalPlayerChunk, DirtyChunks, FloatingOriginSettings, Light, MaterialLodSettings,
+    MeshBufferPoolStats, RetryChunkGen, SkyShadowSettings, ValidateChunks, Voxel,
+    VoxelSimulationPause, WorldOrigin, CHUNK_GENERATION_QUEUE, CHUNK_GENERATION_TASKS,
+    CHUNK_GENERATION_TIME, CHUNK_LENGTH, CHUNK_MESHES_PENDING, CHUNK_MESHING_QUEUE,
+    CHUNK_MESHING_TASKS, CHUNK_MESHING_TIME,
+};
+
+use super::{
+    ChunkBorderDebug, ChunkBorderDebugPlugin, DebugConsolePlugin, ExportTerrainMeshes,
+    GamepadCursorPlugin, MeshExportPlugin, RegenerateWorld, SeedBrowserPlugin,
 };
 
-fn display_debug_stats(mut egui: ResMut<EguiContext>, diagnostics: Res<Diagnostics>) {
+#[allow(clippy::too_many_arguments)]
+fn display_debug_stats(
+    mut egui: ResMut<EguiContext>,
+    diagnostics: Res<Diagnostics>,
+    mesh_buffer_pool: Res<MeshBufferPoolStats>,
+    mesh_apply: Res<ChunkMeshApplyStats>,
+    memory_usage: Res<ChunkMemoryUsage>,
+    mut compression: ResMut<ChunkCompressionSettings>,
+    mut pause: ResMut<VoxelSimulationPause>,
+    mut material_lod: ResMut<MaterialLodSettings>,
+    mut screenshot_events: EventWriter<TakeScreenshot>,
+    mut export_events: EventWriter<ExportTerrainMeshes>,
+) {
     egui::Window::new("performance stuff").show(egui.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            let mut paused = pause.paused;
+            if ui.checkbox(&mut paused, "Pause voxel simulation").changed() {
+                pause.paused = paused;
+            }
+            if ui
+                .add_enabled(pause.paused, egui::Button::new("Step"))
+                .clicked()
+            {
+                pause.step();
+            }
+        });
         ui.label(format!(
             "Avg. FPS: {:.02}",
             diagnostics
@@ -35,9 +83,103 @@ fn display_debug_stats(mut egui: ResMut<EguiContext>, diagnostics: Res<Diagnosti
                 .average()
                 .unwrap_or_default()
         ));
+        ui.separator();
+
+        let average = |id| {
+            diagnostics
+                .get(id)
+                .and_then(|diagnostic| diagnostic.average())
+                .unwrap_or_default()
+        };
+        ui.label(format!(
+            "Avg. chunk generation time: {:.2} ms",
+            average(CHUNK_GENERATION_TIME)
+        ));
+        ui.label(format!(
+            "Avg. chunk meshing time: {:.2} ms",
+            average(CHUNK_MESHING_TIME)
+        ));
+        ui.label(format!(
+            "Generation: {:.0} queued, {:.0} in flight",
+            average(CHUNK_GENERATION_QUEUE),
+            average(CHUNK_GENERATION_TASKS)
+        ));
+        ui.label(format!(
+            "Meshing: {:.0} queued, {:.0} in flight, {:.0} waiting to be applied",
+            average(CHUNK_MESHING_QUEUE),
+            average(CHUNK_MESHING_TASKS),
+            average(CHUNK_MESHES_PENDING)
+        ));
+        let mib = |bytes: usize| bytes as f32 / (1024.0 * 1024.0);
+        for (name, stats) in [
+            ("voxels", memory_usage.voxels),
+            ("light", memory_usage.light),
+            ("total", memory_usage.total()),
+        ] {
+            ui.label(format!(
+                "Chunk {}: {:.2} MiB, {}/{} buffers compressed ({:.1}% saved)",
+                name,
+                mib(stats.bytes),
+                stats.compressed,
+                stats.buffers,
+                stats.savings() * 100.0
+            ));
+        }
+        ui.checkbox(&mut compression.enabled, "Chunk compression");
+        ui.label("Uncompressed chunk distance");
+        ui.add(Slider::new(&mut compression.min_distance, 0..=16));
+        ui.label(format!(
+            "Mesh buffer pool: {} buffers, {:.2} MiB ({} trims)",
+            mesh_buffer_pool.buffers,
+            mesh_buffer_pool.memory_bytes as f32 / (1024.0 * 1024.0),
+            mesh_buffer_pool.trimmed
+        ));
+        ui.label(format!(
+            "Chunk meshes applied: {} in {:.2} ms, {} deferred ({} total), {} outdated dropped",
+            mesh_apply.applied,
+            mesh_apply.apply_time.as_secs_f32() * 1000.0,
+            mesh_apply.deferred,
+            mesh_apply.total_deferred,
+            mesh_apply.dropped
+        ));
+        ui.separator();
+
+        // changing the material LOD remeshes all the chunks, so the settings are only written when edited.
+        let (mut enabled, mut distance, mut max_color_distance) = (
+            material_lod.enabled,
+            material_lod.distance,
+            material_lod.max_color_distance,
+        );
+        let mut changed = ui
+            .checkbox(&mut enabled, "Merge similar materials of the far chunks")
+            .changed();
+        ui.label("Material LOD distance");
+        changed |= ui.add(Slider::new(&mut distance, 1..=32)).changed();
+        ui.label("Merged materials color distance");
+        changed |= ui
+            .add(Slider::new(&mut max_color_distance, 0.0..=0.5))
+            .changed();
+        if changed {
+            *material_lod = MaterialLodSettings {
+                enabled,
+                distance,
+                max_color_distance,
+            };
+        }
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            if ui.button("Screenshot").clicked() {
+                screenshot_events.send(TakeScreenshot);
+            }
+            if ui.button("Export terrain meshes").clicked() {
+                export_events.send(ExportTerrainMeshes);
+            }
+        });
     });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn display_chunk_stats(
     mut egui: ResMut<EguiContext>,
     dirty_chunks: Res<DirtyChunks>,
@@ -45,7 +187,33 @@ fn display_chunk_stats(
     mut chunk_loading_radius: ResMut<ChunkLoadRadius>,
     mut chunk_command_queue: ResMut<ChunkCommandQueue>,
     loaded_chunks: Res<ChunkEntities>,
+    anchored_chunks: Res<AnchoredChunks>,
+    targeted_voxel: Res<TargetedVoxel>,
+    placement_material: Res<PlacementMaterial>,
+    materials: Res<VoxelMaterialRegistry>,
+    integrity: Res<ChunkIntegrity>,
+    mut validate_events: EventWriter<ValidateChunks>,
+    mut occlusion_culling: ResMut<ChunkOcclusionCulling>,
+    mut task_budget: ResMut<ChunkTaskBudget>,
+    mut chunk_map: ResMut<ChunkMap<Voxel, ChunkShape>>,
+    mut shape_chunk_counts: Local<Option<((i32, i32), Vec<usize>)>>,
 ) {
+    // counting the chunks of each shape walks the whole loaded area, so only when the radii change.
+    let radii = (
+        chunk_loading_radius.horizontal,
+        chunk_loading_radius.vertical,
+    );
+    if shape_chunk_counts
+        .as_ref()
+        .map_or(true, |(counted_radii, _)| *counted_radii != radii)
+    {
+        let counts = ChunkLoadShape::ALL
+            .iter()
+            .map(|shape| chunk_loading_radius.chunk_count(*shape))
+            .collect();
+        *shape_chunk_counts = Some((radii, counts));
+    }
+
     egui::Window::new("voxel world stuff").show(egui.ctx_mut(), |ui| {
         ui.heading("Chunks");
         ui.label(format!(
@@ -53,19 +221,486 @@ fn display_chunk_stats(
             dirty_chunks.num_dirty()
         ));
         ui.label(format!("Loaded chunk count: {}", loaded_chunks.len()));
+        ui.label(format!(
+            "Anchored (data only) chunk count: {}",
+            anchored_chunks.len()
+        ));
+        let cache_stats = chunk_map.cache_stats();
+        ui.label(format!(
+            "Unloaded chunk cache: {}/{} ({:.1}% hit rate)",
+            cache_stats.len,
+            cache_stats.capacity,
+            cache_stats.hit_rate() * 100.0
+        ));
+        ui.label("Unloaded chunk cache capacity");
+        let mut cache_capacity = cache_stats.capacity;
+        if ui.add(Slider::new(&mut cache_capacity, 0..=4096)).changed() {
+            chunk_map.set_cache_capacity(cache_capacity);
+        }
         ui.separator();
         ui.label("Horizontal chunk loading radius");
         ui.add(Slider::new(&mut chunk_loading_radius.horizontal, 8..=32));
+        ui.label("Vertical chunk loading radius");
+        ui.add(Slider::new(&mut chunk_loading_radius.vertical, 1..=16));
+        ui.label("Chunk loading shape");
+        ui.horizontal(|ui| {
+            for shape in ChunkLoadShape::ALL {
+                if ui
+                    .selectable_label(chunk_loading_radius.shape == shape, shape.name())
+                    .clicked()
+                    && chunk_loading_radius.shape != shape
+                {
+                    chunk_loading_radius.shape = shape;
+                }
+            }
+        });
+        if let Some((_, counts)) = shape_chunk_counts.as_ref() {
+            for (shape, count) in ChunkLoadShape::ALL.iter().zip(counts) {
+                ui.label(format!("Chunks in a {} area: {}", shape.name(), count));
+            }
+        }
+        ui.separator();
+        ui.label("Generation tasks spawned per frame");
+        ui.add(Slider::new(&mut task_budget.generation, 1..=256));
+        ui.label("Meshing tasks spawned per frame");
+        ui.add(Slider::new(&mut task_budget.meshing, 1..=256));
+        ui.label("Mesh application time per frame (ms)");
+        let mut apply_time = task_budget.mesh_apply_time.as_secs_f32() * 1000.0;
+        if ui.add(Slider::new(&mut apply_time, 0.5..=16.0)).changed() {
+            task_budget.mesh_apply_time = Duration::from_secs_f32(apply_time / 1000.0);
+        }
+        ui.separator();
+        ui.checkbox(&mut occlusion_culling.enabled, "Occlusion culling");
+        ui.label(format!(
+            "Chunks hidden behind terrain: {}",
+            occlusion_culling.culled
+        ));
         ui.separator();
 
         if ui.button("Clear loaded chunks").clicked() {
+            // don't bring back the cached chunks, clearing is used to regenerate the terrain.
+            chunk_map.clear_cache();
             chunk_command_queue.queue_unload(loaded_chunks.iter_keys());
         }
         ui.separator();
 
+        ui.horizontal(|ui| {
+            if ui.button("Validate chunks").clicked() {
+                validate_events.send(ValidateChunks { repair: false });
+            }
+            if ui.button("Validate and repair").clicked() {
+                validate_events.send(ValidateChunks { repair: true });
+            }
+        });
+        if let Some(report) = integrity.last_report.as_ref() {
+            ui.label(format!(
+                "Last validation: {} inconsistencies{}",
+                report.issue_count(),
+                if report.repaired { " (repaired)" } else { "" }
+            ));
+            report.summary().into_iter().for_each(|line| {
+                ui.label(line);
+            });
+        }
+        ui.separator();
+
         ui.heading("Current player position");
         ui.label(format!("Current position : {}", player_pos.world_pos));
         ui.label(format!("Current chunk : {:?}", player_pos.chunk_min));
+        ui.separator();
+
+        ui.heading("Interaction");
+        match targeted_voxel.0 {
+            Some(hit) => ui.label(format!(
+                "Targeted voxel : {} ({}) at {:.1} voxels, face {}",
+                hit.position,
+                materials
+                    .get_by_id(hit.voxel.0)
+                    .map_or("Unknown", |mat| mat.name.as_ref()),
+                hit.distance,
+                hit.normal
+            )),
+            None => ui.label("Targeted voxel : none"),
+        };
+        ui.label(format!(
+            "Placement material : {}",
+            materials
+                .get_by_id(placement_material.0 .0)
+                .map_or("Unknown", |mat| mat.name.as_ref())
+        ));
+    });
+}
+
+fn display_terrain_gen_config(
+    mut egui: ResMut<EguiContext>,
+    mut config: ResMut<TerrainGenConfig>,
+    world_seed: Res<WorldSeed>,
+    mut new_seed: Local<Option<i32>>,
+    mut regenerate_events: EventWriter<RegenerateWorld>,
+) {
+    egui::Window::new("terrain generation").show(egui.ctx_mut(), |ui| {
+        ui.heading("Seed");
+        ui.label(format!("World seed: {}", world_seed.0));
+        let seed = new_seed.get_or_insert(world_seed.0);
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(seed));
+            if ui.button("Random").clicked() {
+                // no need for a proper random crate, the clock is random enough for picking a seed.
+                let nanos = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |time| time.subsec_nanos());
+                *seed = nanos.wrapping_mul(0x9E37_79B9) as i32;
+            }
+        });
+        if ui.button("Regenerate the world").clicked() {
+            regenerate_events.send(RegenerateWorld { seed: *seed });
+        }
+        ui.separator();
+
+        // edit a copy so that the config only gets flagged as changed when a value actually changes.
+        let mut edited = *config;
+
+        ui.heading("Caves");
+        ui.checkbox(&mut edited.caves_enabled, "Enable caves");
+        ui.label("Cave frequency");
+        ui.add(Slider::new(&mut edited.cave_frequency, 0.005..=0.1f32));
+        ui.label("Cave threshold");
+        ui.add(Slider::new(&mut edited.cave_threshold, 0.0..=1.0f32));
+        ui.label("Cave surface margin");
+        ui.add(Slider::new(&mut edited.cave_surface_margin, 0..=32));
+        ui.label("Cave lava level");
+        ui.add(Slider::new(&mut edited.cave_lava_level, 0..=64));
+        ui.separator();
+
+        ui.heading("Beaches");
+        ui.checkbox(&mut edited.beaches_enabled, "Enable beaches");
+        ui.label("Beach width");
+        ui.add(Slider::new(&mut edited.beach_width, 0..=16));
+        ui.label("Beach height above sea level");
+        ui.add(Slider::new(&mut edited.beach_height, 0..=8));
+        ui.separator();
+        ui.label("Changes apply to newly generated chunks.");
+
+        if edited != *config {
+            *config = edited;
+        }
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn display_chunk_inspector(
+    mut egui: ResMut<EguiContext>,
+    player_pos: Res<CurrentLocalPlayerChunk>,
+    chunk_map: Res<ChunkMap<Voxel, ChunkShape>>,
+    gen_errors: Res<ChunkGenErrors>,
+    mut retry_events: EventWriter<RetryChunkGen>,
+    mut borders: ResMut<ChunkBorderDebug>,
+    mut teleport_events: EventWriter<TeleportPlayer>,
+    mut teleport_target: Local<Vec2>,
+    mut floating_origin: ResMut<FloatingOriginSettings>,
+    origin: Res<WorldOrigin>,
+) {
+    egui::Window::new("chunk inspector").show(egui.ctx_mut(), |ui| {
+        ui.checkbox(&mut borders.enabled, "Show chunk borders (F8)");
+        ui.checkbox(&mut borders.show_dirty, "Highlight dirty chunks");
+        ui.label("Chunk border radius");
+        ui.add(Slider::new(&mut borders.radius, 0..=16));
+        ui.separator();
+
+        let key = player_pos.chunk_min;
+        ui.label(format!("Current chunk : {:?}", key));
+        ui.label(format!(
+            "Data : {}",
+            if chunk_map.exists(key) {
+                "loaded"
+            } else {
+                "missing"
+            }
+        ));
+        match gen_errors.get(key) {
+            Some(err) => ui.label(format!("Generation failed : {}", err)),
+            None => ui.label("Generation : ok"),
+        };
+        if ui
+            .add_enabled(chunk_map.exists(key), egui::Button::new("Regenerate"))
+            .clicked()
+        {
+            retry_events.send(RetryChunkGen(key));
+        }
+        ui.separator();
+
+        // far away positions stress the chunk streaming and expose the float precision issues.
+        ui.heading("Teleport");
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut teleport_target.x).prefix("X: "));
+            ui.add(egui::DragValue::new(&mut teleport_target.y).prefix("Z: "));
+            if ui.button("Teleport").clicked() {
+                teleport_events.send(TeleportPlayer {
+                    position: *teleport_target,
+                });
+            }
+        });
+        ui.horizontal(|ui| {
+            for (label, position) in [
+                ("Origin", Vec2::ZERO),
+                ("Far lands +1e6", Vec2::splat(1.0e6)),
+                ("Far lands -1e6", Vec2::splat(-1.0e6)),
+            ] {
+                if ui.button(label).clicked() {
+                    *teleport_target = position;
+                    teleport_events.send(TeleportPlayer { position });
+                }
+            }
+        });
+        ui.checkbox(&mut floating_origin.enabled, "Floating origin");
+        ui.label(format!("World origin : {}", origin.get()));
+        ui.separator();
+
+        ui.heading("Generation errors");
+        ui.label(format!(
+            "Chunks replaced by a fallback chunk: {}",
+            gen_errors.len()
+        ));
+        let mut failed: Vec<_> = gen_errors.iter().collect();
+        failed.sort_by_key(|(key, _)| key.to_array());
+        for (key, err) in failed {
+            ui.horizontal(|ui| {
+                if ui.button("Retry").clicked() {
+                    retry_events.send(RetryChunkGen(*key));
+                }
+                ui.label(format!("{:?}: {}", key, err));
+            });
+        }
+    });
+}
+
+fn display_fog_settings(
+    mut egui: ResMut<EguiContext>,
+    mut fog: ResMut<DistanceFogSettings>,
+    mut submerged_fog: ResMut<SubmergedFogSettings>,
+    sky: Res<SkySettings>,
+) {
+    egui::Window::new("fog").show(egui.ctx_mut(), |ui| {
+        ui.label("Fog start (fraction of the render distance)");
+        let mut start = fog.start;
+        if ui.add(Slider::new(&mut start, 0.0..=1.0f32)).changed() {
+            fog.start = start.min(fog.end);
+        }
+        ui.label("Fog end (fraction of the render distance)");
+        let mut end = fog.end;
+        if ui.add(Slider::new(&mut end, 0.0..=1.0f32)).changed() {
+            fog.end = end.max(fog.start);
+        }
+
+        let mut match_sky = fog.color.is_none();
+        if ui
+            .checkbox(&mut match_sky, "Match the sky horizon")
+            .changed()
+        {
+            fog.color = if match_sky {
+                None
+            } else {
+                Some(sky.fog_color())
+            };
+        }
+        if let Some(color) = fog.color {
+            let mut editable_color =
+                Rgba::from_rgba_unmultiplied(color.r(), color.g(), color.b(), color.a());
+            if egui::widgets::color_picker::color_edit_button_rgba(
+                ui,
+                &mut editable_color,
+                egui::color_picker::Alpha::Opaque,
+            )
+            .changed()
+            {
+                fog.color = Some(Color::from(editable_color.to_array()));
+            }
+        }
+
+        ui.separator();
+        ui.label("Submerged fog density (per voxel)");
+        ui.add(Slider::new(&mut submerged_fog.density, 0.0..=1.0f32));
+        ui.label("Submerged color absorption (per voxel)");
+        ui.add(Slider::new(&mut submerged_fog.absorption.x, 0.0..=0.2f32).text("red"));
+        ui.add(Slider::new(&mut submerged_fog.absorption.y, 0.0..=0.2f32).text("green"));
+        ui.add(Slider::new(&mut submerged_fog.absorption.z, 0.0..=0.2f32).text("blue"));
+    });
+}
+
+fn display_shadow_settings(
+    mut egui: ResMut<EguiContext>,
+    mut terrain_shadows: ResMut<TerrainShadowSettings>,
+    mut sky_shadows: ResMut<SkyShadowSettings>,
+    cascades: Res<TerrainShadowCascades>,
+) {
+    egui::Window::new("shadows").show(egui.ctx_mut(), |ui| {
+        ui.checkbox(&mut terrain_shadows.enabled, "Shadow map cascades");
+        ui.label("Cascade count");
+        ui.add(Slider::new(
+            &mut terrain_shadows.cascade_count,
+            1..=MAX_SHADOW_CASCADES,
+        ));
+        ui.label("Cascade resolution");
+        ui.horizontal(|ui| {
+            for resolution in [512, 1024, 2048, 4096] {
+                ui.selectable_value(
+                    &mut terrain_shadows.resolution,
+                    resolution,
+                    resolution.to_string(),
+                );
+            }
+        });
+        ui.label("Shadowed distance (voxels)");
+        ui.add(Slider::new(&mut terrain_shadows.max_distance, 32.0..=512.0));
+        ui.label("Logarithmic cascade splits");
+        ui.add(Slider::new(&mut terrain_shadows.split_lambda, 0.0..=1.0));
+        ui.label("Normal bias (voxels)");
+        ui.add(Slider::new(&mut terrain_shadows.normal_bias, 0.0..=0.5));
+        ui.label("Strength");
+        ui.add(Slider::new(&mut terrain_shadows.strength, 0.0..=1.0));
+        for (index, cascade) in cascades.0.iter().enumerate() {
+            ui.label(format!(
+                "Cascade {}: up to {:.0} voxels, {:.0} voxels wide",
+                index,
+                cascade.far,
+                cascade.half_extent * 2.0
+            ));
+        }
+
+        ui.separator();
+        ui.checkbox(&mut sky_shadows.enabled, "Sky shadows");
+        ui.label("Sky shadows strength");
+        ui.add(Slider::new(&mut sky_shadows.strength, 0.0..=1.0));
+    });
+}
+
+fn display_biome_overlay(mut egui: ResMut<EguiContext>, player_pos: Res<CurrentLocalPlayerChunk>) {
+    // number of chunk columns displayed around the player on each axis.
+    const OVERLAY_RADIUS: i32 = 16;
+    const CELL_SIZE: f32 = 6.0;
+
+    let generator = TERRAIN_GENERATOR.read().unwrap();
+    let biomes = generator.biomes();
+    let player_column = player_pos.world_pos.xz();
+
+    egui::Window::new("biome map").show(egui.ctx_mut(), |ui| {
+        let climate = biomes.climate(player_column);
+        // e.g. superflat worlds or custom generators without any biome.
+        let current_biome = match biomes.get_by_id(biomes.biome_at(player_column)) {
+            Some(biome) => biome,
+            None => {
+                ui.label("No biomes registered");
+                return;
+            }
+        };
+
+        ui.label(format!("Current biome: {}", current_biome.name));
+        ui.label(format!(
+            "Temperature: {:.02} / Humidity: {:.02}",
+            climate.temperature, climate.humidity
+        ));
+        ui.separator();
+
+        let (response, painter) = ui.allocate_painter(
+            egui::Vec2::splat(CELL_SIZE * (2 * OVERLAY_RADIUS + 1) as f32),
+            egui::Sense::hover(),
+        );
+
+        for x in -OVERLAY_RADIUS..=OVERLAY_RADIUS {
+            for z in -OVERLAY_RADIUS..=OVERLAY_RADIUS {
+                let column = player_pos.chunk_min.xz()
+                    + IVec2::new(x, z) * CHUNK_LENGTH as i32
+                    + IVec2::splat(CHUNK_LENGTH as i32 / 2);
+                let color = match biomes.get_by_id(biomes.biome_at(column)) {
+                    Some(biome) => biome.debug_color,
+                    None => continue,
+                };
+
+                let min = response.rect.min
+                    + egui::vec2(
+                        (x + OVERLAY_RADIUS) as f32 * CELL_SIZE,
+                        (z + OVERLAY_RADIUS) as f32 * CELL_SIZE,
+                    );
+                painter.rect_filled(
+                    egui::Rect::from_min_size(min, egui::Vec2::splat(CELL_SIZE)),
+                    0.0,
+                    Rgba::from_rgba_unmultiplied(color.r(), color.g(), color.b(), 1.0),
+                );
+            }
+        }
+
+        painter.circle_filled(response.rect.center(), CELL_SIZE / 2.0, egui::Color32::RED);
+
+        ui.separator();
+        biomes.iter_biomes().for_each(|biome| {
+            ui.colored_label(
+                Rgba::from_rgba_unmultiplied(
+                    biome.debug_color.r(),
+                    biome.debug_color.g(),
+                    biome.debug_color.b(),
+                    1.0,
+                ),
+                biome.name,
+            );
+        });
+    });
+}
+
+fn display_world_stats(
+    mut egui: ResMut<EguiContext>,
+    diagnostics: Res<Diagnostics>,
+    mut stats: ResMut<ChunkPipelineStats>,
+) {
+    egui::Window::new("world statistics").show(egui.ctx_mut(), |ui| {
+        ui.label(format!(
+            "Chunks generated: {}/s, meshed: {}/s",
+            stats.generated_per_second(),
+            stats.meshed_per_second()
+        ));
+        ui.separator();
+
+        ui.label(format!(
+            "Latency from request to visible ({} chunks):",
+            stats.latency_samples()
+        ));
+        for percentile in [50.0, 95.0, 99.0] {
+            ui.label(match stats.latency_percentile(percentile) {
+                Some(latency) => format!(
+                    "P{:.0}: {:.1} ms",
+                    percentile,
+                    latency.as_secs_f32() * 1000.0
+                ),
+                None => format!("P{:.0}: -", percentile),
+            });
+        }
+        ui.separator();
+
+        // the current backlogs rather than their averages, so that they can be watched draining.
+        let current = |id| {
+            diagnostics
+                .get(id)
+                .and_then(|diagnostic| diagnostic.value())
+                .unwrap_or_default()
+        };
+        ui.label(format!(
+            "Chunks not visible yet: {}",
+            stats.pending_chunks()
+        ));
+        ui.label(format!(
+            "Generation backlog: {:.0} queued, {:.0} in flight",
+            current(CHUNK_GENERATION_QUEUE),
+            current(CHUNK_GENERATION_TASKS)
+        ));
+        ui.label(format!(
+            "Meshing backlog: {:.0} queued, {:.0} in flight, {:.0} waiting to be applied",
+            current(CHUNK_MESHING_QUEUE),
+            current(CHUNK_MESHING_TASKS),
+            current(CHUNK_MESHES_PENDING)
+        ));
+
+        if ui.button("Reset").clicked() {
+            stats.reset();
+        }
     });
 }
 
@@ -77,6 +712,22 @@ fn display_debug_ui_criteria(ui_state: Res<DebugUIState>) -> ShouldRun {
     }
 }
 
+fn display_biome_overlay_criteria(ui_state: Res<DebugUIState>) -> ShouldRun {
+    if ui_state.display_biome_overlay {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+fn display_world_stats_criteria(ui_state: Res<DebugUIState>) -> ShouldRun {
+    if ui_state.display_world_stats {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
 fn display_mat_debug_ui_criteria(ui_state: Res<DebugUIState>) -> ShouldRun {
     if ui_state.display_mat_debug {
         ShouldRun::Yes
@@ -94,6 +745,12 @@ fn toggle_debug_ui_displays(
             Some(key_code) if key_code == KeyCode::F3 && input.state == ButtonState::Pressed => {
                 ui_state.display_debug_info = !ui_state.display_debug_info;
             }
+            Some(key_code) if key_code == KeyCode::F4 && input.state == ButtonState::Pressed => {
+                ui_state.display_biome_overlay = !ui_state.display_biome_overlay;
+            }
+            Some(key_code) if key_code == KeyCode::F5 && input.state == ButtonState::Pressed => {
+                ui_state.display_world_stats = !ui_state.display_world_stats;
+            }
             Some(key_code) if key_code == KeyCode::F7 && input.state == ButtonState::Pressed => {
                 ui_state.display_mat_debug = !ui_state.display_mat_debug;
             }
@@ -102,10 +759,21 @@ fn toggle_debug_ui_displays(
     }
 }
 
+/// The material flags toggled by the material editor, along their labels.
+const EDITABLE_MATERIAL_FLAGS: [(VoxelMaterialFlags, &str); 4] = [
+    (VoxelMaterialFlags::LIQUID, "Liquid"),
+    (VoxelMaterialFlags::UNBREAKABLE, "Unbreakable"),
+    (VoxelMaterialFlags::FOLIAGE, "Foliage"),
+    (VoxelMaterialFlags::TRIPLANAR, "Triplanar noise"),
+];
+
 fn display_material_editor(
     mut egui: ResMut<EguiContext>,
     mut ui_state: ResMut<DebugUIState>,
     mut materials: ResMut<VoxelMaterialRegistry>,
+    chunk_entities: Res<ChunkEntities>,
+    chunk_map: Res<ChunkMap<Voxel, ChunkShape>>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
 ) {
     egui::Window::new("material editor").show(egui.ctx_mut(), |ui| {
         ui.heading("Select material");
@@ -122,11 +790,32 @@ fn display_material_editor(
                         content.selectable_value(
                             &mut ui_state.selected_mat,
                             mat_index as u8,
-                            mat.name,
+                            mat.name.as_ref(),
                         );
                     })
             });
 
+        // the new material starts as a copy of the selected one.
+        if ui.button("Add material").clicked() {
+            // registering a name already taken would override that material instead.
+            let name = (materials.iter_mats().count()..)
+                .map(|index| format!("Material {}", index))
+                .find(|name| materials.iter_mats().all(|mat| mat.name != *name))
+                .unwrap();
+            let material = MaterialRegistryInfo {
+                name: name.clone().into(),
+                ..materials.get_by_id(ui_state.selected_mat).unwrap().clone()
+            };
+
+            match materials.register_at_runtime(material) {
+                Some(id) => {
+                    info!("Added material {} (ID: {})", name, id);
+                    ui_state.selected_mat = id;
+                }
+                None => error!("Failed to add a material: the material registry is full"),
+            }
+        }
+
         ui.heading("Material properties");
 
         /// base_color
@@ -169,29 +858,130 @@ fn display_material_editor(
             egui::color_picker::Alpha::Opaque,
         );
         selected_mat.emissive = Color::from(editable_emissive.to_array());
+
+        let mut custom_reach = selected_mat.light_reach.is_some();
+        ui.checkbox(&mut custom_reach, "Light reach");
+        if custom_reach {
+            let reach = selected_mat.light_reach.get_or_insert(Light::MAX_LEVEL);
+            ui.add(Slider::new(reach, 0..=Light::MAX_LEVEL));
+        } else {
+            selected_mat.light_reach = None;
+        }
+
+        let mut flows = selected_mat.flow_interval.is_some();
+        ui.checkbox(&mut flows, "Flow interval (s)");
+        if flows {
+            let interval = selected_mat.flow_interval.get_or_insert(0.25);
+            ui.add(Slider::new(interval, 0.05..=5.0));
+        } else {
+            selected_mat.flow_interval = None;
+        }
+
+        let mut animated = selected_mat.light_animation.is_some();
+        ui.checkbox(&mut animated, "Light animation");
+        if animated {
+            let animation = selected_mat
+                .light_animation
+                .get_or_insert_with(Default::default);
+            ui.label("Temperature (K)");
+            ui.add(Slider::new(&mut animation.temperature, 1000.0..=12000.0f32));
+            ui.label("Temperature drift (K)");
+            ui.add(Slider::new(
+                &mut animation.temperature_drift,
+                0.0..=2000.0f32,
+            ));
+            ui.label("Drift frequency (Hz)");
+            ui.add(Slider::new(&mut animation.drift_frequency, 0.0..=2.0f32));
+            ui.label("Flicker amplitude");
+            ui.add(Slider::new(&mut animation.flicker_amplitude, 0.0..=0.5f32));
+            ui.label("Flicker frequency (Hz)");
+            ui.add(Slider::new(&mut animation.flicker_frequency, 0.0..=20.0f32));
+        } else {
+            selected_mat.light_animation = None;
+        }
+
+        ui.label("Flags");
+        let previous_flags = selected_mat.flags;
+        for (flag, label) in EDITABLE_MATERIAL_FLAGS {
+            let mut enabled = selected_mat.flags.contains(flag);
+            ui.checkbox(&mut enabled, label);
+            selected_mat.flags.set(flag, enabled);
+        }
+
+        if selected_mat.flags.contains(VoxelMaterialFlags::TRIPLANAR) {
+            ui.label("Noise scale");
+            ui.add(Slider::new(
+                &mut selected_mat.triplanar_scale,
+                1.0..=64.0f32,
+            ));
+            ui.label("Noise strength");
+            ui.add(Slider::new(
+                &mut selected_mat.triplanar_strength,
+                0.0..=1.0f32,
+            ));
+        }
+
+        // the flags change how the voxels get meshed (e.g. foliage), the chunks holding the material get remeshed.
+        if selected_mat.flags != previous_flags {
+            let voxel = Voxel(ui_state.selected_mat);
+            chunk_entities
+                .iter_keys()
+                .filter(|key| {
+                    chunk_map
+                        .buffer_at(**key)
+                        .map_or(false, |buffer| buffer.slice().contains(&voxel))
+                })
+                .for_each(|key| dirty_chunks.mark_dirty(*key));
+        }
+
+        ui.separator();
+        if ui.button("Save materials").clicked() {
+            match materials.save() {
+                Ok(path) => info!("Saved materials to {}", path.display()),
+                Err(err) => error!("Failed to save materials: {}", err),
+            }
+        }
     });
 }
 
+/// Label for the stage housing the debug UI systems, runs after [`CoreStage::PostUpdate`].
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, StageLabel)]
+pub struct DebugUiStage;
+
 pub struct DebugUIPlugins;
 
 impl Plugin for DebugUIPlugins {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_plugin(EguiPlugin)
+        app.add_plugin(DebugConsolePlugin)
+            .add_plugin(SeedBrowserPlugin)
+            .add_plugin(ChunkBorderDebugPlugin)
+            .add_plugin(GamepadCursorPlugin)
+            .add_plugin(MeshExportPlugin)
             .add_plugin(FrameTimeDiagnosticsPlugin)
             .add_plugin(EntityCountDiagnosticsPlugin)
             .add_stage_after(
                 CoreStage::PostUpdate,
-                "debug_ui_stage",
+                DebugUiStage,
                 SystemStage::parallel()
                     .with_system(toggle_debug_ui_displays)
                     .with_system_set(
                         SystemSet::new()
                             .with_system(display_debug_stats)
                             .with_system(display_chunk_stats)
+                            .with_system(display_terrain_gen_config)
+                            .with_system(display_fog_settings)
+                            .with_system(display_shadow_settings)
+                            .with_system(display_chunk_inspector)
                             .with_run_criteria(display_debug_ui_criteria),
                     )
                     .with_system(
                         display_material_editor.with_run_criteria(display_mat_debug_ui_criteria),
+                    )
+                    .with_system(
+                        display_biome_overlay.with_run_criteria(display_biome_overlay_criteria),
+                    )
+                    .with_system(
+                        display_world_stats.with_run_criteria(display_world_stats_criteria),
                     ),
             )
             .init_resource::<DebugUIState>();
@@ -202,6 +992,8 @@ impl Plugin for DebugUIPlugins {
 struct DebugUIState {
     display_debug_info: bool,
     display_mat_debug: bool,
+    display_biome_overlay: bool,
+    display_world_stats: bool,
 
     // DD
     pub selected_mat: u8,
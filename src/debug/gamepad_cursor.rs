@@ -0,0 +1,91 @@
+use bevy::{
+    input::{
+        mouse::{MouseButtonInput, MouseScrollUnit, MouseWheel},
+        ButtonState,
+    },
+    math::Vec2,
+    prelude::{
+        CoreStage, EventWriter, Input, Local, MouseButton, ParallelSystemDescriptorCoercion,
+        Plugin, Res, ResMut,
+    },
+    time::Time,
+    window::{CursorMoved, Windows},
+};
+use bevy_egui::EguiSystem;
+
+use crate::voxel::input::{GamepadSticks, InputAction, InputMap, InputMapSystem};
+
+/// Speed of the scrolling emulated with the look stick at full deflection, in pixels per second.
+const SCROLL_SPEED: f32 = 600.0;
+
+/// Moves the cursor with the gamepad move stick while the cursor is unlocked, and clicks with the cursor click action,
+/// so that the debug windows can be used without a mouse.
+/// The events are sent before egui processes the inputs, which sees them as coming from a mouse.
+fn emulate_gamepad_cursor(
+    sticks: Res<GamepadSticks>,
+    actions: Res<Input<InputAction>>,
+    input_map: Res<InputMap>,
+    time: Res<Time>,
+    mut windows: ResMut<Windows>,
+    mut position: Local<Option<Vec2>>,
+    mut cursor_moved: EventWriter<CursorMoved>,
+    mut mouse_buttons: EventWriter<MouseButtonInput>,
+    mut mouse_wheel: EventWriter<MouseWheel>,
+) {
+    let window = match windows.get_primary_mut() {
+        Some(window) => window,
+        None => return,
+    };
+
+    // follows the real cursor while the stick is idle, so that the mouse and gamepad can be used together.
+    if sticks.cursor == Vec2::ZERO {
+        *position = window.cursor_position();
+    } else {
+        let size = Vec2::new(window.width(), window.height());
+        let current = position.unwrap_or(size / 2.0);
+        let moved = (current + sticks.cursor * input_map.cursor_speed * time.delta_seconds())
+            .clamp(Vec2::ZERO, size);
+
+        *position = Some(moved);
+        window.set_cursor_position(moved);
+        cursor_moved.send(CursorMoved {
+            id: window.id(),
+            position: moved,
+        });
+    }
+
+    if sticks.scroll != Vec2::ZERO {
+        let scroll = sticks.scroll * SCROLL_SPEED * time.delta_seconds();
+        mouse_wheel.send(MouseWheel {
+            unit: MouseScrollUnit::Pixel,
+            x: scroll.x,
+            y: scroll.y,
+        });
+    }
+
+    let state = if actions.just_pressed(InputAction::CursorClick) {
+        ButtonState::Pressed
+    } else if actions.just_released(InputAction::CursorClick) {
+        ButtonState::Released
+    } else {
+        return;
+    };
+    mouse_buttons.send(MouseButtonInput {
+        button: MouseButton::Left,
+        state,
+    });
+}
+
+/// Emulates the mouse cursor with a gamepad for navigating the debug windows.
+pub struct GamepadCursorPlugin;
+
+impl Plugin for GamepadCursorPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            emulate_gamepad_cursor
+                .after(InputMapSystem::UpdateActions)
+                .before(EguiSystem::ProcessInput),
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    fmt::Write as _,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    math::IVec3,
+    prelude::{
+        error, info, Assets, EventReader, EventWriter, Handle, Input, KeyCode, Local, Mesh, Plugin,
+        Query, Res, Visibility,
+    },
+    tasks::{IoTaskPool, Task},
+};
+use futures_lite::future;
+
+use crate::voxel::{
+    material::VoxelMaterialRegistry,
+    render::{PooledChunkMesh, TerrainVertex},
+    Chunk,
+};
+
+/// Event requesting the meshes of the visible chunks to be exported to an OBJ file, along its MTL material library.
+/// The meshes of the fluids, the foliage and the chunks meshed on the GPU aren't exported.
+pub struct ExportTerrainMeshes;
+
+/// Settings of the terrain mesh exports.
+pub struct MeshExportSettings {
+    /// Key exporting the terrain meshes.
+    pub key: KeyCode,
+    /// Folder where the meshes are written.
+    pub directory: PathBuf,
+}
+
+impl Default for MeshExportSettings {
+    fn default() -> Self {
+        Self {
+            key: KeyCode::F9,
+            directory: PathBuf::from("exports"),
+        }
+    }
+}
+
+/// The packed vertex data and indices of a chunk mesh.
+struct ChunkMeshData {
+    key: IVec3,
+    vertices: Vec<u32>,
+    indices: Vec<u32>,
+}
+
+/// Writes the chunk meshes as a single OBJ object per chunk, in world coordinates, its faces grouped by material.
+fn write_obj(chunks: &[ChunkMeshData], mtl_name: &str) -> String {
+    let mut obj = String::new();
+    let _ = writeln!(obj, "mtllib {}", mtl_name);
+
+    let normals: Vec<IVec3> = (0..6)
+        .map(|face| TerrainVertex::unpack(face << 8).normal)
+        .collect();
+    normals.iter().for_each(|normal| {
+        let _ = writeln!(obj, "vn {} {} {}", normal.x, normal.y, normal.z);
+    });
+
+    // OBJ indices are 1-based and global to the file.
+    let mut first_vertex = 1;
+    for chunk in chunks {
+        let vertices: Vec<TerrainVertex> = chunk
+            .vertices
+            .iter()
+            .copied()
+            .map(TerrainVertex::unpack)
+            .collect();
+
+        let _ = writeln!(
+            obj,
+            "o chunk_{}_{}_{}",
+            chunk.key.x, chunk.key.y, chunk.key.z
+        );
+        for vertex in &vertices {
+            let position = chunk.key + vertex.position;
+            let _ = writeln!(obj, "v {} {} {}", position.x, position.y, position.z);
+        }
+
+        let mut material = None;
+        for triangle in chunk.indices.chunks_exact(3) {
+            let first = &vertices[triangle[0] as usize];
+            if material != Some(first.material) {
+                material = Some(first.material);
+                let _ = writeln!(obj, "usemtl material_{}", first.material);
+            }
+
+            let normal = normals
+                .iter()
+                .position(|normal| *normal == first.normal)
+                .unwrap()
+                + 1;
+            let _ = writeln!(
+                obj,
+                "f {}//{} {}//{} {}//{}",
+                first_vertex + triangle[0] as usize,
+                normal,
+                first_vertex + triangle[1] as usize,
+                normal,
+                first_vertex + triangle[2] as usize,
+                normal
+            );
+        }
+        first_vertex += vertices.len();
+    }
+
+    obj
+}
+
+/// Writes the base colors of the materials as an MTL material library.
+fn write_mtl(registry: &VoxelMaterialRegistry) -> String {
+    let mut mtl = String::new();
+    for (id, material) in registry.iter_mats().enumerate() {
+        let [r, g, b, a] = material.base_color.as_rgba_f32();
+        let _ = writeln!(mtl, "# {}", material.name);
+        let _ = writeln!(mtl, "newmtl material_{}", id);
+        let _ = writeln!(mtl, "Kd {} {} {}", r, g, b);
+        let _ = writeln!(mtl, "d {}\n", a);
+    }
+    mtl
+}
+
+fn request_mesh_export(
+    keys: Res<Input<KeyCode>>,
+    settings: Res<MeshExportSettings>,
+    mut export_events: EventWriter<ExportTerrainMeshes>,
+) {
+    if keys.just_pressed(settings.key) {
+        export_events.send(ExportTerrainMeshes);
+    }
+}
+
+/// Collects the meshes of the visible chunks and writes them in the background.
+fn export_terrain_meshes(
+    mut export_events: EventReader<ExportTerrainMeshes>,
+    settings: Res<MeshExportSettings>,
+    chunks: Query<(&Chunk, &Handle<Mesh>, Option<&PooledChunkMesh>, &Visibility)>,
+    meshes: Res<Assets<Mesh>>,
+    registry: Res<VoxelMaterialRegistry>,
+    mut tasks: Local<Vec<Task<Result<PathBuf, std::io::Error>>>>,
+) {
+    if export_events.iter().count() > 0 {
+        let mut chunk_meshes: Vec<ChunkMeshData> = chunks
+            .iter()
+            .filter(|(_, _, _, visibility)| visibility.is_visible)
+            .filter_map(|(chunk, handle, pooled_mesh, _)| {
+                let (vertices, indices) = match pooled_mesh {
+                    Some(pooled_mesh) => {
+                        (pooled_mesh.vertex_data(), pooled_mesh.indices().to_vec())
+                    }
+                    None => {
+                        let (vertices, indices) = TerrainVertex::mesh_data(meshes.get(handle)?)?;
+                        (vertices.to_vec(), indices.to_vec())
+                    }
+                };
+                (!indices.is_empty()).then(|| ChunkMeshData {
+                    key: chunk.0,
+                    vertices,
+                    indices,
+                })
+            })
+            .collect();
+        // keeps the exports of the same terrain identical.
+        chunk_meshes.sort_unstable_by_key(|chunk| chunk.key.to_array());
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let name = format!("terrain-{}-{:03}", now.as_secs(), now.subsec_millis());
+        let obj_path = settings.directory.join(format!("{}.obj", name));
+        let mtl_path = settings.directory.join(format!("{}.mtl", name));
+        let mtl = write_mtl(&registry);
+
+        info!("Exporting the meshes of {} chunks", chunk_meshes.len());
+        tasks.push(IoTaskPool::get().spawn(async move {
+            let obj = write_obj(&chunk_meshes, &format!("{}.mtl", name));
+            std::fs::create_dir_all(obj_path.parent().unwrap())?;
+            std::fs::write(&mtl_path, mtl)?;
+            std::fs::write(&obj_path, obj)?;
+            Ok(obj_path)
+        }));
+    }
+
+    tasks.retain_mut(|task| match future::block_on(future::poll_once(task)) {
+        Some(Ok(path)) => {
+            info!("Exported the terrain meshes to {}", path.display());
+            false
+        }
+        Some(Err(err)) => {
+            error!("Failed to export the terrain meshes: {}", err);
+            false
+        }
+        None => true,
+    });
+}
+
+/// Exports the meshes of the visible chunks to an OBJ file when pressing [`MeshExportSettings::key`] or on
+/// [`ExportTerrainMeshes`] events, for sharing renders and debugging meshing artifacts offline.
+pub struct MeshExportPlugin;
+
+impl Plugin for MeshExportPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<MeshExportSettings>()
+            .add_event::<ExportTerrainMeshes>()
+            .add_system(request_mesh_export)
+            .add_system(export_terrain_meshes);
+    }
+}
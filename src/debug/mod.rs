@@ -1,2 +1,20 @@
+mod console;
+pub use console::*;
+
 mod debug_ui;
 pub use debug_ui::*;
+
+/// Wireframes of the chunk borders, for spotting chunk streaming issues.
+mod chunk_borders;
+pub use chunk_borders::*;
+
+/// Gamepad emulation of the mouse cursor for navigating the debug windows.
+mod gamepad_cursor;
+pub use gamepad_cursor::*;
+
+/// Export of the terrain meshes, for inspecting them in other tools.
+mod mesh_export;
+pub use mesh_export::*;
+
+mod seed_browser;
+pub use seed_browser::*;
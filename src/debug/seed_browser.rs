@@ -0,0 +1,229 @@
+use bevy::{
+    ecs::change_detection::DetectChanges,
+    input::{keyboard::KeyboardInput, ButtonState},
+    prelude::{
+        Assets, EventReader, EventWriter, Handle, Image, KeyCode, Local, Plugin, Res, ResMut,
+    },
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+use bevy_egui::{egui, EguiContext};
+use futures_lite::future;
+
+use crate::voxel::{
+    storage::{ChunkMap, WorldSave},
+    terraingen::{structures::PendingVoxelEdits, WorldSeed, TERRAIN_GENERATOR},
+    ChunkCommandQueue, ChunkEntities, ChunkLoadRadius, ChunkShape, Voxel,
+};
+
+/// Number of seed previews displayed per row and per column of the browser grid.
+const SEED_GRID_SIZE: i32 = 4;
+/// Width of the square area around the world origin covered by a preview, in voxels.
+const PREVIEW_AREA: usize = 1024;
+/// Width of a preview, in pixels.
+const PREVIEW_RESOLUTION: usize = 64;
+/// Maximum number of previews kept in memory, the ones of the seeds not displayed get dropped past this count.
+const MAX_CACHED_PREVIEWS: usize = 64;
+
+/// The preview of the terrain generated with a seed.
+enum SeedPreview {
+    Generating(Task<Vec<u8>>),
+    Ready(Handle<Image>, egui::TextureId),
+}
+
+/// State of the seed browser, toggled with the F6 key.
+#[derive(Default)]
+struct SeedBrowser {
+    open: bool,
+    /// First seed of the displayed page of seeds.
+    first_seed: i32,
+    previews: HashMap<i32, SeedPreview>,
+}
+
+impl SeedBrowser {
+    fn displayed_seeds(&self) -> impl Iterator<Item = i32> {
+        let first_seed = self.first_seed;
+        (0..SEED_GRID_SIZE * SEED_GRID_SIZE).map(move |index| first_seed.wrapping_add(index))
+    }
+}
+
+/// Event requesting the world to be regenerated with a new seed.
+pub struct RegenerateWorld {
+    pub seed: i32,
+}
+
+fn toggle_seed_browser(mut inputs: EventReader<KeyboardInput>, mut browser: ResMut<SeedBrowser>) {
+    for input in inputs.iter() {
+        match input.key_code {
+            Some(KeyCode::F6) if input.state == ButtonState::Pressed => {
+                browser.open = !browser.open;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Spawns the generation of the missing previews of the displayed seeds and uploads the generated ones.
+fn update_seed_previews(
+    mut browser: ResMut<SeedBrowser>,
+    mut egui: ResMut<EguiContext>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !browser.open {
+        return;
+    }
+
+    let task_pool = AsyncComputeTaskPool::get();
+    let displayed: Vec<i32> = browser.displayed_seeds().collect();
+
+    for seed in displayed.iter().copied() {
+        browser.previews.entry(seed).or_insert_with(|| {
+            SeedPreview::Generating(task_pool.spawn(async move {
+                TERRAIN_GENERATOR
+                    .read()
+                    .unwrap()
+                    .preview(seed, PREVIEW_AREA, PREVIEW_RESOLUTION)
+            }))
+        });
+    }
+
+    for preview in browser.previews.values_mut() {
+        if let SeedPreview::Generating(task) = preview {
+            if let Some(pixels) = future::block_on(future::poll_once(task)) {
+                let handle = images.add(Image::new(
+                    Extent3d {
+                        width: PREVIEW_RESOLUTION as u32,
+                        height: PREVIEW_RESOLUTION as u32,
+                        depth_or_array_layers: 1,
+                    },
+                    TextureDimension::D2,
+                    pixels,
+                    TextureFormat::Rgba8UnormSrgb,
+                ));
+                let texture = egui.add_image(handle.clone());
+                *preview = SeedPreview::Ready(handle, texture);
+            }
+        }
+    }
+
+    // drop the previews of the seeds which aren't displayed anymore, dropping their task cancels it.
+    if browser.previews.len() > MAX_CACHED_PREVIEWS {
+        let evicted: Vec<i32> = browser
+            .previews
+            .keys()
+            .copied()
+            .filter(|seed| !displayed.contains(seed))
+            .collect();
+
+        for seed in evicted {
+            if let Some(SeedPreview::Ready(handle, _)) = browser.previews.remove(&seed) {
+                egui.remove_image(&handle);
+                images.remove(&handle);
+            }
+        }
+    }
+}
+
+fn display_seed_browser(
+    mut egui: ResMut<EguiContext>,
+    mut browser: ResMut<SeedBrowser>,
+    world_seed: Res<WorldSeed>,
+    world_save: Option<Res<WorldSave>>,
+    mut regenerate_events: EventWriter<RegenerateWorld>,
+) {
+    if !browser.open {
+        return;
+    }
+
+    let page_len = SEED_GRID_SIZE * SEED_GRID_SIZE;
+    egui::Window::new("seed browser").show(egui.ctx_mut(), |ui| {
+        ui.label(format!("Current seed: {}", world_seed.0));
+        ui.horizontal(|ui| {
+            if ui.button("<").clicked() {
+                browser.first_seed = browser.first_seed.wrapping_sub(page_len);
+            }
+            ui.add(egui::DragValue::new(&mut browser.first_seed).prefix("First seed: "));
+            if ui.button(">").clicked() {
+                browser.first_seed = browser.first_seed.wrapping_add(page_len);
+            }
+        });
+
+        // chunks stored in a world save are loaded from it rather than regenerated.
+        if world_save.is_some() {
+            ui.label("Regenerating is disabled while a world save is in use.");
+        }
+        ui.separator();
+
+        let size = egui::Vec2::splat(PREVIEW_RESOLUTION as f32 * 1.5);
+        let seeds: Vec<i32> = browser.displayed_seeds().collect();
+        egui::Grid::new("seed_grid").show(ui, |ui| {
+            for row in seeds.chunks(SEED_GRID_SIZE as usize) {
+                for seed in row.iter().copied() {
+                    ui.vertical(|ui| {
+                        match browser.previews.get(&seed) {
+                            Some(SeedPreview::Ready(_, texture)) => {
+                                let button = egui::ImageButton::new(*texture, size)
+                                    .selected(seed == world_seed.0);
+                                if ui.add_enabled(world_save.is_none(), button).clicked() {
+                                    regenerate_events.send(RegenerateWorld { seed });
+                                }
+                            }
+                            _ => {
+                                ui.add_sized(size, egui::Label::new("generating..."));
+                            }
+                        }
+                        ui.label(format!("{}", seed));
+                    });
+                }
+                ui.end_row();
+            }
+        });
+    });
+}
+
+/// Unloads the whole world and regenerates it with the requested seed.
+/// The chunks get unloaded at the end of the frame, the next frame drops them from the cache and loads the chunks
+/// around the player again.
+#[allow(clippy::too_many_arguments)]
+fn regenerate_world(
+    mut events: EventReader<RegenerateWorld>,
+    mut world_seed: ResMut<WorldSeed>,
+    mut chunk_map: ResMut<ChunkMap<Voxel, ChunkShape>>,
+    mut chunk_command_queue: ResMut<ChunkCommandQueue>,
+    mut pending_edits: ResMut<PendingVoxelEdits>,
+    mut load_radius: ResMut<ChunkLoadRadius>,
+    loaded_chunks: Res<ChunkEntities>,
+    mut unloading: Local<bool>,
+) {
+    if std::mem::take(&mut *unloading) {
+        // the cached chunks and the structures spilling from the previous terrain don't belong to the new one.
+        chunk_map.clear_cache();
+        *pending_edits = PendingVoxelEdits::default();
+        // the chunks around the player are only queued for loading when the load area changes.
+        load_radius.set_changed();
+    }
+
+    let seed = match events.iter().last() {
+        Some(event) => event.seed,
+        None => return,
+    };
+
+    world_seed.0 = seed;
+    chunk_command_queue.queue_unload(loaded_chunks.iter_keys());
+    *unloading = true;
+}
+
+/// A debug tool previewing the terrain of a grid of seeds, clicking a preview regenerates the world with its seed.
+pub struct SeedBrowserPlugin;
+
+impl Plugin for SeedBrowserPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<SeedBrowser>()
+            .add_event::<RegenerateWorld>()
+            .add_system(toggle_seed_browser)
+            .add_system(update_seed_previews)
+            .add_system(display_seed_browser)
+            .add_system(regenerate_world);
+    }
+}
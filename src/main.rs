@@ -1,19 +1,171 @@
+#[cfg(not(feature = "headless"))]
 use std::f32::consts::PI;
 
 use bevy::prelude::*;
 
+#[cfg(not(feature = "headless"))]
 mod debug;
+#[cfg(not(feature = "headless"))]
+mod screenshot;
+mod settings;
+#[cfg(not(feature = "headless"))]
+mod ui;
 mod voxel;
 
 fn main() {
-    App::default()
-        .add_plugins(DefaultPlugins)
+    let args: Vec<String> = std::env::args().collect();
+    let seed = world_seed(&args);
+
+    // offline world upgrade tool, applies newly added generation stages to an existing save and exits.
+    if let Some(path) = arg_value(&args, "--upgrade-world") {
+        let world_save = voxel::storage::WorldSave::open(path).expect("Failed to open world save");
+        let mut generator = voxel::terraingen::TerrainGenerator::default();
+        voxel::terraingen::register_default_biomes(&mut generator);
+        generator.set_config(voxel::terraingen::TerrainGenConfig {
+            seed: seed.0,
+            ..Default::default()
+        });
+
+        match voxel::persistence::upgrade_world_save(&world_save, &generator) {
+            Ok(report) => println!(
+                "World upgrade done: {} chunks upgraded, {} up to date, {} skipped (modified)",
+                report.upgraded, report.up_to_date, report.skipped_modified
+            ),
+            Err(err) => {
+                eprintln!("World upgrade failed: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // plays a recorded session back, on a world generated with the seed of the recording.
+    #[cfg(not(feature = "headless"))]
+    let replay_player = arg_value(&args, "--replay")
+        .map(|path| voxel::ReplayPlayer::load(path).expect("Failed to load replay"));
+    #[cfg(not(feature = "headless"))]
+    let seed = replay_player
+        .as_ref()
+        .map_or(seed, |replay_player| replay_player.seed());
+
+    let mut app = App::default();
+    app.insert_resource(seed);
+
+    #[cfg(not(feature = "headless"))]
+    if let Some(replay_player) = replay_player {
+        app.insert_resource(replay_player);
+    }
+
+    // records the session to the specified replay file, saved on exit.
+    #[cfg(not(feature = "headless"))]
+    if let Some(path) = arg_value(&args, "--record") {
+        app.insert_resource(voxel::ReplayRecorder::new(path));
+    }
+
+    if let Some(path) = arg_value(&args, "--world") {
+        app.insert_resource(
+            voxel::storage::WorldSave::open(path).expect("Failed to open world save"),
+        );
+    }
+
+    // authored level, streamed from its save and surrounded by barrier voxels (bedrock unless specified).
+    if let Some(path) = arg_value(&args, "--level") {
+        let level_save = voxel::storage::WorldSave::open(path).expect("Failed to open level");
+        let barrier = arg_value(&args, "--barrier")
+            .map(|id| id.parse().expect("Invalid barrier material id"))
+            .unwrap_or(<voxel::materials::Bedrock as voxel::material::VoxelMaterial>::ID);
+
+        app.insert_resource(
+            voxel::AuthoredLevel::from_save(&level_save, voxel::Voxel(barrier))
+                .expect("Failed to load level"),
+        )
+        .insert_resource(level_save);
+    }
+
+    // user settings, overridden by the settings stored in the world save.
+    let world_settings_path = app
+        .world
+        .get_resource::<voxel::storage::WorldSave>()
+        .map(|save| save.settings_path());
+    app.insert_resource(settings::LayeredSettings::load(
+        arg_value(&args, "--settings").unwrap_or(settings::USER_SETTINGS_PATH),
+        world_settings_path,
+    ));
+
+    // shares the world with the clients connecting to the specified address.
+    if let Some(addr) = arg_value(&args, "--serve") {
+        app.insert_resource(voxel::net::ChunkServer::bind(addr).expect("Failed to start server"));
+    }
+
+    // streams the world from the server at the specified address instead of generating it.
+    if let Some(addr) = arg_value(&args, "--connect") {
+        app.insert_resource(
+            voxel::net::ChunkClient::connect(addr).expect("Failed to connect to server"),
+        );
+    }
+
+    // registers a material per color of a palette file or image.
+    if let Some(path) = arg_value(&args, "--import-palette") {
+        app.insert_resource(voxel::palette::PaletteImport(path.into()));
+    }
+
+    // lets the terrain shader get hot reloaded when edited.
+    app.insert_resource(bevy::asset::AssetServerSettings {
+        watch_for_changes: true,
+        ..Default::default()
+    });
+
+    #[cfg(not(feature = "headless"))]
+    app.add_plugins(DefaultPlugins)
+        .add_plugin(bevy_egui::EguiPlugin)
         .add_plugin(voxel::VoxelWorldPlugin)
+        .add_plugin(settings::SettingsPlugin)
+        .add_plugin(ui::GameplayUIPlugins)
+        .add_plugin(screenshot::ScreenshotPlugin)
         .add_plugin(debug::DebugUIPlugins)
-        .add_startup_system(setup)
-        .run();
+        .add_startup_system(setup);
+
+    // only simulates the world, without any window nor rendering, at a fixed tick rate.
+    #[cfg(feature = "headless")]
+    app.insert_resource(bevy::app::ScheduleRunnerSettings::run_loop(
+        bevy::utils::Duration::from_secs_f64(1.0 / 60.0),
+    ))
+    .add_plugins(MinimalPlugins)
+    .add_plugin(bevy::log::LogPlugin)
+    .add_plugin(bevy::transform::TransformPlugin)
+    .add_plugin(bevy::hierarchy::HierarchyPlugin)
+    .add_plugin(bevy::diagnostic::DiagnosticsPlugin)
+    .add_plugin(bevy::asset::AssetPlugin)
+    .add_plugin(voxel::VoxelWorldSimulationPlugin)
+    .add_plugin(settings::SettingsPlugin);
+
+    app.run();
+}
+
+/// Returns the world seed passed with `--seed`, or read from the `--world-config` file, 0 otherwise.
+fn world_seed(args: &[String]) -> voxel::terraingen::WorldSeed {
+    let from_config = arg_value(args, "--world-config").and_then(|path| {
+        voxel::terraingen::WorldConfigFile::load(path)
+            .expect("Failed to read world config")
+            .seed
+    });
+
+    let seed = arg_value(args, "--seed")
+        .map(|seed| seed.parse().expect("Invalid world seed"))
+        .or(from_config)
+        .unwrap_or_default();
+    voxel::terraingen::WorldSeed(seed)
+}
+
+/// Returns the value following the specified flag in the command line arguments.
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|index| args.get(index + 1))
+        .map(|value| value.as_str())
 }
 
+#[cfg(not(feature = "headless"))]
 fn setup(mut cmds: Commands) {
     cmds.spawn_bundle(Camera3dBundle {
         projection: bevy::render::camera::Projection::Perspective(PerspectiveProjection {
@@ -24,8 +176,7 @@ fn setup(mut cmds: Commands) {
         transform: Transform::from_xyz(2.0, 160.0, 2.0).looking_at(Vec3::ZERO, Vec3::Y),
         ..Default::default()
     })
-    .insert(voxel::player::PlayerController::default())
-    .insert(bevy_atmosphere::plugin::AtmosphereCamera(None));
+    .insert(voxel::player::PlayerController::default());
 
     cmds.insert_resource(AmbientLight {
         color: Color::WHITE,
@@ -0,0 +1,447 @@
+use std::{
+    num::NonZeroU32,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    math::UVec2,
+    prelude::{
+        error, info, Assets, Camera, Camera3dBundle, Commands, Component, DespawnRecursiveExt,
+        Entity, EventReader, EventWriter, Handle, Image, Input, KeyCode, Local, Plugin, Query, Res,
+        ResMut, Transform, With, World,
+    },
+    render::{
+        camera::{Projection, RenderTarget},
+        render_asset::RenderAssets,
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext},
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, Extent3d, ImageCopyBuffer, ImageDataLayout,
+            MapMode, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+        },
+        renderer::{RenderContext, RenderDevice},
+        texture::BevyDefault,
+        Extract, RenderApp, RenderStage,
+    },
+    tasks::{IoTaskPool, Task},
+    window::Windows,
+};
+use futures_lite::future;
+
+use crate::voxel::player::PlayerController;
+
+/// Screenshots are captured once the offscreen camera rendered for this many frames, so that the render target got prepared.
+const SCREENSHOT_CAPTURE_FRAME: u32 = 2;
+
+/// Largest width or height of a screenshot, supersampling is reduced to stay under it.
+const MAX_SCREENSHOT_SIZE: u32 = 8192;
+
+/// Rows of a texture copied into a buffer must be aligned to this many bytes.
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+/// Settings of the in-engine screenshots.
+pub struct ScreenshotSettings {
+    /// Key taking a screenshot.
+    pub key: KeyCode,
+    /// Resolution multiplier of the screenshots relative to the window size (1 to 4).
+    pub supersampling: u32,
+    /// Folder where the screenshots are written.
+    pub directory: PathBuf,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        Self {
+            key: KeyCode::F12,
+            supersampling: 1,
+            directory: PathBuf::from("screenshots"),
+        }
+    }
+}
+
+/// Event requesting a screenshot, like pressing [`ScreenshotSettings::key`].
+pub struct TakeScreenshot;
+
+/// Event sent once a screenshot got written to disk.
+#[allow(dead_code)]
+pub struct ScreenshotTaken {
+    pub path: PathBuf,
+}
+
+/// A camera rendering the world to an offscreen image for a screenshot.
+/// Rendering offscreen keeps the egui overlays, which are only drawn on the window, out of the screenshot.
+#[derive(Component, Clone)]
+struct ScreenshotCamera {
+    image: Handle<Image>,
+    path: PathBuf,
+    size: UVec2,
+    format: TextureFormat,
+    frames: u32,
+}
+
+/// A screenshot read back from the GPU, with tightly packed rows.
+struct CapturedScreenshot {
+    path: PathBuf,
+    size: UVec2,
+    format: TextureFormat,
+    data: Vec<u8>,
+}
+
+struct ScreenshotSender(Mutex<Sender<CapturedScreenshot>>);
+
+struct ScreenshotReceiver(Mutex<Receiver<CapturedScreenshot>>);
+
+/// Returns a path in the screenshot folder named after the current time.
+fn screenshot_path(settings: &ScreenshotSettings) -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    settings.directory.join(format!(
+        "screenshot-{}-{:03}.png",
+        now.as_secs(),
+        now.subsec_millis()
+    ))
+}
+
+/// Spawns an offscreen camera matching the player camera when the screenshot key is pressed or a screenshot is
+/// requested.
+#[allow(clippy::too_many_arguments)]
+fn request_screenshot(
+    keys: Res<Input<KeyCode>>,
+    mut requests: EventReader<TakeScreenshot>,
+    settings: Res<ScreenshotSettings>,
+    windows: Res<Windows>,
+    player: Query<(&Transform, &Projection), With<PlayerController>>,
+    pending: Query<(), With<ScreenshotCamera>>,
+    mut images: ResMut<Assets<Image>>,
+    mut commands: Commands,
+) {
+    let requested = requests.iter().count() > 0 || keys.just_pressed(settings.key);
+    if !requested || !pending.is_empty() {
+        return;
+    }
+
+    let (window, (transform, projection)) = match (windows.get_primary(), player.get_single()) {
+        (Some(window), Ok(player)) => (window, player),
+        _ => return,
+    };
+
+    let window_size = UVec2::new(window.physical_width(), window.physical_height());
+    let supersampling = settings
+        .supersampling
+        .clamp(1, 4)
+        .min(MAX_SCREENSHOT_SIZE / window_size.max_element().max(1))
+        .max(1);
+    let size = window_size * supersampling;
+
+    let format = TextureFormat::bevy_default();
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("screenshot_target"),
+            size: Extent3d::default(),
+            dimension: TextureDimension::D2,
+            format,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..Default::default()
+    };
+    image.resize(Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 1,
+    });
+    let image = images.add(image);
+
+    commands
+        .spawn_bundle(Camera3dBundle {
+            camera: Camera {
+                target: RenderTarget::Image(image.clone()),
+                priority: -1,
+                ..Default::default()
+            },
+            projection: projection.clone(),
+            transform: *transform,
+            ..Default::default()
+        })
+        .insert(ScreenshotCamera {
+            image,
+            path: screenshot_path(&settings),
+            size,
+            format,
+            frames: 0,
+        });
+}
+
+/// Despawns the screenshot cameras once their image got captured.
+fn advance_screenshot_cameras(
+    mut cameras: Query<(Entity, &mut ScreenshotCamera)>,
+    mut commands: Commands,
+) {
+    for (entity, mut camera) in cameras.iter_mut() {
+        camera.frames += 1;
+        if camera.frames > SCREENSHOT_CAPTURE_FRAME {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Encodes the captured screenshots to PNG in the background and notifies once written.
+fn save_screenshots(
+    receiver: Res<ScreenshotReceiver>,
+    mut tasks: Local<Vec<Task<Option<PathBuf>>>>,
+    mut taken_events: EventWriter<ScreenshotTaken>,
+) {
+    let task_pool = IoTaskPool::get();
+
+    for mut screenshot in receiver.0.lock().unwrap().try_iter() {
+        tasks.push(task_pool.spawn(async move {
+            if matches!(
+                screenshot.format,
+                TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+            ) {
+                screenshot
+                    .data
+                    .chunks_exact_mut(4)
+                    .for_each(|pixel| pixel.swap(0, 2));
+            }
+
+            let result = screenshot
+                .path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .map_err(image::ImageError::IoError)
+                .and_then(|_| {
+                    image::save_buffer(
+                        &screenshot.path,
+                        &screenshot.data,
+                        screenshot.size.x,
+                        screenshot.size.y,
+                        image::ColorType::Rgba8,
+                    )
+                });
+
+            match result {
+                Ok(()) => Some(screenshot.path),
+                Err(err) => {
+                    error!(
+                        "Failed to save screenshot {}: {}",
+                        screenshot.path.display(),
+                        err
+                    );
+                    None
+                }
+            }
+        }));
+    }
+
+    tasks.retain_mut(|task| match future::block_on(future::poll_once(task)) {
+        Some(path) => {
+            if let Some(path) = path {
+                info!("Saved screenshot to {}", path.display());
+                taken_events.send(ScreenshotTaken { path });
+            }
+            false
+        }
+        None => true,
+    });
+}
+
+const MAPPING: u8 = 0;
+const MAPPED: u8 = 1;
+const MAPPING_FAILED: u8 = 2;
+
+/// A screenshot being copied from its render target to a buffer readable by the CPU.
+struct ScreenshotCapture {
+    camera: ScreenshotCamera,
+    buffer: Buffer,
+    padded_bytes_per_row: u32,
+    state: Arc<AtomicU8>,
+}
+
+/// The screenshots being captured, in the render world.
+#[derive(Default)]
+struct ScreenshotCaptures {
+    requested: Vec<ScreenshotCamera>,
+    copying: Vec<ScreenshotCapture>,
+    mapping: Vec<ScreenshotCapture>,
+}
+
+fn extract_screenshot_cameras(
+    cameras: Extract<Query<&ScreenshotCamera>>,
+    mut captures: ResMut<ScreenshotCaptures>,
+) {
+    captures.requested.extend(
+        cameras
+            .iter()
+            .filter(|camera| camera.frames == SCREENSHOT_CAPTURE_FRAME)
+            .cloned(),
+    );
+}
+
+/// Creates the buffers the requested screenshots get copied to.
+fn prepare_screenshot_buffers(
+    render_device: Res<RenderDevice>,
+    mut captures: ResMut<ScreenshotCaptures>,
+) {
+    let requested: Vec<_> = captures.requested.drain(..).collect();
+
+    captures.copying.extend(requested.into_iter().map(|camera| {
+        let unpadded_bytes_per_row = camera.size.x * 4;
+        let padded_bytes_per_row = (unpadded_bytes_per_row + COPY_BYTES_PER_ROW_ALIGNMENT - 1)
+            / COPY_BYTES_PER_ROW_ALIGNMENT
+            * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        ScreenshotCapture {
+            buffer: render_device.create_buffer(&BufferDescriptor {
+                label: Some("screenshot_buffer"),
+                size: (padded_bytes_per_row * camera.size.y) as u64,
+                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            camera,
+            padded_bytes_per_row,
+            state: Arc::new(AtomicU8::new(MAPPING)),
+        }
+    }));
+}
+
+/// Copies the render targets of the screenshot cameras to their readback buffers, once the cameras rendered.
+struct ScreenshotCopyNode;
+
+impl Node for ScreenshotCopyNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let images = world.resource::<RenderAssets<Image>>();
+
+        for capture in world.resource::<ScreenshotCaptures>().copying.iter() {
+            let image = match images.get(&capture.camera.image) {
+                Some(image) => image,
+                None => continue,
+            };
+
+            render_context.command_encoder.copy_texture_to_buffer(
+                image.texture.as_image_copy(),
+                ImageCopyBuffer {
+                    buffer: &capture.buffer,
+                    layout: ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(capture.padded_bytes_per_row),
+                        rows_per_image: None,
+                    },
+                },
+                Extent3d {
+                    width: capture.camera.size.x,
+                    height: capture.camera.size.y,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Maps the screenshot buffers copied this frame, and sends the ones mapped since to the main world.
+/// Mapping completes during a later submission of the render queue, so the buffers are read back at least one frame after the copy.
+fn read_screenshot_buffers(
+    sender: Res<ScreenshotSender>,
+    mut captures: ResMut<ScreenshotCaptures>,
+) {
+    let sender = sender.0.lock().unwrap();
+
+    captures
+        .mapping
+        .retain(|capture| match capture.state.load(Ordering::Acquire) {
+            MAPPED => {
+                let size = capture.camera.size;
+                let unpadded_bytes_per_row = (size.x * 4) as usize;
+                let mut data = Vec::with_capacity(unpadded_bytes_per_row * size.y as usize);
+
+                {
+                    let mapped = capture.buffer.slice(..).get_mapped_range();
+                    for row in mapped.chunks_exact(capture.padded_bytes_per_row as usize) {
+                        data.extend_from_slice(&row[..unpadded_bytes_per_row]);
+                    }
+                }
+                capture.buffer.unmap();
+
+                let _ = sender.send(CapturedScreenshot {
+                    path: capture.camera.path.clone(),
+                    size,
+                    format: capture.camera.format,
+                    data,
+                });
+                false
+            }
+            MAPPING_FAILED => false,
+            _ => true,
+        });
+
+    let copied: Vec<_> = captures.copying.drain(..).collect();
+    for capture in copied {
+        let state = capture.state.clone();
+        capture
+            .buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| match result {
+                Ok(()) => state.store(MAPPED, Ordering::Release),
+                Err(err) => {
+                    error!("Failed to read back screenshot: {}", err);
+                    state.store(MAPPING_FAILED, Ordering::Release);
+                }
+            });
+        captures.mapping.push(capture);
+    }
+}
+
+/// Label of the render graph node copying the screenshots.
+const SCREENSHOT_COPY_NODE: &str = "screenshot_copy";
+
+/// Takes screenshots of the world without the UI, optionally supersampled, when pressing [`ScreenshotSettings::key`]
+/// or on [`TakeScreenshot`] events.
+pub struct ScreenshotPlugin;
+
+impl Plugin for ScreenshotPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let (sender, receiver) = channel();
+
+        app.init_resource::<ScreenshotSettings>()
+            .insert_resource(ScreenshotReceiver(Mutex::new(receiver)))
+            .add_event::<TakeScreenshot>()
+            .add_event::<ScreenshotTaken>()
+            .add_system(request_screenshot)
+            .add_system(advance_screenshot_cameras)
+            .add_system(save_screenshots);
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .insert_resource(ScreenshotSender(Mutex::new(sender)))
+            .init_resource::<ScreenshotCaptures>()
+            .add_system_to_stage(RenderStage::Extract, extract_screenshot_cameras)
+            .add_system_to_stage(RenderStage::Prepare, prepare_screenshot_buffers)
+            .add_system_to_stage(RenderStage::Cleanup, read_screenshot_buffers);
+
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(SCREENSHOT_COPY_NODE, ScreenshotCopyNode);
+        graph
+            .add_node_edge(
+                bevy::render::main_graph::node::CAMERA_DRIVER,
+                SCREENSHOT_COPY_NODE,
+            )
+            .unwrap();
+    }
+}
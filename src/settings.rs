@@ -0,0 +1,334 @@
+#[cfg(not(feature = "headless"))]
+use std::collections::HashMap;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(not(feature = "headless"))]
+use bevy::prelude::Msaa;
+use bevy::{
+    prelude::{error, info, Plugin, Res, ResMut},
+    utils::Duration,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "headless"))]
+use crate::voxel::{
+    input::{InputAction, InputMap, InputSource},
+    render::TerrainShadowSettings,
+    SkyShadowSettings,
+};
+use crate::voxel::{persistence::AutosaveSettings, ChunkLoadRadius, ChunkTaskBudget};
+
+/// Path of the user settings file, unless another one is passed with `--settings`.
+pub const USER_SETTINGS_PATH: &str = "settings.ron";
+
+/// Presets trading the rendering quality for performance.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum QualityPreset {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+impl QualityPreset {
+    pub const ALL: [QualityPreset; 4] = [
+        QualityPreset::Low,
+        QualityPreset::Medium,
+        QualityPreset::High,
+        QualityPreset::Ultra,
+    ];
+
+    /// Applies the preset to the number of chunk tasks spawned per frame.
+    fn apply(self, budget: &mut ChunkTaskBudget) {
+        let tasks = match self {
+            QualityPreset::Low => 8,
+            QualityPreset::Medium => 16,
+            QualityPreset::High => 32,
+            QualityPreset::Ultra => 64,
+        };
+        budget.generation = tasks;
+        #[cfg(not(feature = "headless"))]
+        budget.meshing = tasks;
+    }
+
+    /// Applies the preset to the anti-aliasing, the sky shadows and the terrain shadow cascades.
+    #[cfg(not(feature = "headless"))]
+    fn apply_rendering(
+        self,
+        msaa: Option<&mut Msaa>,
+        sky_shadows: Option<&mut SkyShadowSettings>,
+        terrain_shadows: Option<&mut TerrainShadowSettings>,
+    ) {
+        let (samples, shadow_distance) = match self {
+            QualityPreset::Low => (1, None),
+            QualityPreset::Medium => (1, Some(48.0)),
+            QualityPreset::High => (4, Some(96.0)),
+            QualityPreset::Ultra => (4, Some(160.0)),
+        };
+        // cascade count and resolution of the terrain shadow maps.
+        let cascades = match self {
+            QualityPreset::Low => None,
+            QualityPreset::Medium => Some((2, 1024)),
+            QualityPreset::High => Some((3, 2048)),
+            QualityPreset::Ultra => Some((4, 2048)),
+        };
+
+        if let Some(msaa) = msaa {
+            msaa.samples = samples;
+        }
+        if let Some(sky_shadows) = sky_shadows {
+            sky_shadows.enabled = shadow_distance.is_some();
+            sky_shadows.max_distance = shadow_distance.unwrap_or(sky_shadows.max_distance);
+        }
+        if let Some(terrain_shadows) = terrain_shadows {
+            terrain_shadows.enabled = cascades.is_some();
+            if let Some((cascade_count, resolution)) = cascades {
+                terrain_shadows.cascade_count = cascade_count;
+                terrain_shadows.resolution = resolution;
+            }
+        }
+    }
+}
+
+/// A layer of settings, overriding the settings of the layers below it with the fields it specifies.
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SettingsLayer {
+    /// Horizontal radius of the loaded area, in chunks.
+    pub render_distance: Option<i32>,
+    /// Half height of the loaded area, in chunks.
+    pub vertical_render_distance: Option<i32>,
+    pub quality: Option<QualityPreset>,
+    /// Bindings replacing all the bindings of the listed actions.
+    #[cfg(not(feature = "headless"))]
+    pub keybinds: HashMap<InputAction, Vec<InputSource>>,
+    /// Time between two autosaves in seconds, 0 disabling the autosave.
+    pub autosave_interval: Option<f32>,
+}
+
+impl SettingsLayer {
+    /// Reads a settings layer from a RON file, a missing file being an empty layer.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(ron::de::from_bytes(&bytes)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Writes the settings layer to a RON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let serialized = ron::ser::to_string_pretty(self, Default::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        fs::write(path, serialized)
+    }
+}
+
+/// The settings resulting from stacking the layers on top of the engine defaults.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedSettings {
+    pub render_distance: i32,
+    pub vertical_render_distance: i32,
+    pub quality: QualityPreset,
+    #[cfg(not(feature = "headless"))]
+    pub keybinds: HashMap<InputAction, Vec<InputSource>>,
+    pub autosave_interval: f32,
+}
+
+impl ResolvedSettings {
+    /// Returns the engine defaults, the bottom layer of the settings.
+    pub fn engine_defaults() -> Self {
+        let load_radius = ChunkLoadRadius::default();
+        #[cfg(not(feature = "headless"))]
+        let input_map = InputMap::default();
+        Self {
+            render_distance: load_radius.horizontal,
+            vertical_render_distance: load_radius.vertical,
+            quality: QualityPreset::High,
+            #[cfg(not(feature = "headless"))]
+            keybinds: InputAction::ALL
+                .iter()
+                .map(|action| (*action, input_map.bindings(*action).to_vec()))
+                .collect(),
+            autosave_interval: AutosaveSettings::default()
+                .interval
+                .map_or(0.0, |interval| interval.as_secs_f32()),
+        }
+    }
+
+    /// Overrides the settings specified by a layer.
+    fn apply(&mut self, layer: &SettingsLayer) {
+        self.render_distance = layer.render_distance.unwrap_or(self.render_distance);
+        self.vertical_render_distance = layer
+            .vertical_render_distance
+            .unwrap_or(self.vertical_render_distance);
+        self.quality = layer.quality.unwrap_or(self.quality);
+        #[cfg(not(feature = "headless"))]
+        self.keybinds.extend(layer.keybinds.clone());
+        self.autosave_interval = layer.autosave_interval.unwrap_or(self.autosave_interval);
+    }
+}
+
+/// The editable settings layers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SettingsLayerKind {
+    /// Settings of the user, applying to all the worlds.
+    User,
+    /// Settings of the current world, stored in its save.
+    World,
+}
+
+/// The engine settings: the user settings layer and the settings layer of the world stacked on top of the engine defaults.
+/// Changes made to the layers are applied to the engine resources they cover.
+pub struct LayeredSettings {
+    pub user: SettingsLayer,
+    pub user_path: PathBuf,
+    pub world: SettingsLayer,
+    /// Path of the world settings, `None` when the world isn't saved.
+    pub world_path: Option<PathBuf>,
+}
+
+#[allow(dead_code)]
+impl LayeredSettings {
+    /// Reads the user and world settings layers, the unreadable layers being left empty.
+    pub fn load(user_path: impl Into<PathBuf>, world_path: Option<PathBuf>) -> Self {
+        let load_layer = |path: &Path| {
+            SettingsLayer::load(path).unwrap_or_else(|err| {
+                error!("Failed to read settings {}: {}", path.display(), err);
+                SettingsLayer::default()
+            })
+        };
+
+        let user_path = user_path.into();
+        Self {
+            user: load_layer(&user_path),
+            world: world_path.as_deref().map(load_layer).unwrap_or_default(),
+            user_path,
+            world_path,
+        }
+    }
+
+    /// Returns the settings resulting from the layers.
+    pub fn resolve(&self) -> ResolvedSettings {
+        let mut settings = ResolvedSettings::engine_defaults();
+        settings.apply(&self.user);
+        settings.apply(&self.world);
+        settings
+    }
+
+    /// Returns the settings resulting from the layers below the specified one.
+    pub fn resolve_below(&self, kind: SettingsLayerKind) -> ResolvedSettings {
+        let mut settings = ResolvedSettings::engine_defaults();
+        if kind == SettingsLayerKind::World {
+            settings.apply(&self.user);
+        }
+        settings
+    }
+
+    pub fn layer(&self, kind: SettingsLayerKind) -> &SettingsLayer {
+        match kind {
+            SettingsLayerKind::User => &self.user,
+            SettingsLayerKind::World => &self.world,
+        }
+    }
+
+    pub fn layer_mut(&mut self, kind: SettingsLayerKind) -> &mut SettingsLayer {
+        match kind {
+            SettingsLayerKind::User => &mut self.user,
+            SettingsLayerKind::World => &mut self.world,
+        }
+    }
+
+    /// Writes a settings layer to its file, returns the path of the file.
+    pub fn save(&self, kind: SettingsLayerKind) -> io::Result<PathBuf> {
+        let path = match kind {
+            SettingsLayerKind::User => self.user_path.clone(),
+            SettingsLayerKind::World => self
+                .world_path
+                .clone()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "the world isn't saved"))?,
+        };
+        self.layer(kind).save(&path)?;
+        Ok(path)
+    }
+}
+
+/// Applies the resolved settings to the engine resources whenever the settings layers change.
+fn apply_settings(
+    settings: Res<LayeredSettings>,
+    mut load_radius: ResMut<ChunkLoadRadius>,
+    mut budget: ResMut<ChunkTaskBudget>,
+    mut autosave: ResMut<AutosaveSettings>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let resolved = settings.resolve();
+
+    // the chunks get reloaded whenever the load radius changes.
+    if load_radius.horizontal != resolved.render_distance
+        || load_radius.vertical != resolved.vertical_render_distance
+    {
+        load_radius.horizontal = resolved.render_distance;
+        load_radius.vertical = resolved.vertical_render_distance;
+    }
+
+    resolved.quality.apply(&mut budget);
+
+    autosave.interval = Some(resolved.autosave_interval)
+        .filter(|interval| *interval > 0.0)
+        .map(Duration::from_secs_f32);
+
+    info!(
+        "Applied settings: {:?} quality, render distance {}x{}",
+        resolved.quality, resolved.render_distance, resolved.vertical_render_distance
+    );
+}
+
+/// Applies the resolved quality and key bindings to the rendering and input resources whenever the settings layers
+/// change.
+#[cfg(not(feature = "headless"))]
+fn apply_render_settings(
+    settings: Res<LayeredSettings>,
+    mut msaa: Option<ResMut<Msaa>>,
+    mut sky_shadows: Option<ResMut<SkyShadowSettings>>,
+    mut terrain_shadows: Option<ResMut<TerrainShadowSettings>>,
+    input_map: Option<ResMut<InputMap>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let resolved = settings.resolve();
+
+    resolved.quality.apply_rendering(
+        msaa.as_deref_mut(),
+        sky_shadows.as_deref_mut(),
+        terrain_shadows.as_deref_mut(),
+    );
+
+    if let Some(mut input_map) = input_map {
+        for (action, sources) in resolved.keybinds.iter() {
+            input_map.clear(*action);
+            sources
+                .iter()
+                .for_each(|source| input_map.bind(*action, *source));
+        }
+    }
+}
+
+/// Applies the [`LayeredSettings`] resource, read from the user settings file when it isn't inserted beforehand.
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        if !app.world.contains_resource::<LayeredSettings>() {
+            app.insert_resource(LayeredSettings::load(USER_SETTINGS_PATH, None));
+        }
+        app.add_system(apply_settings);
+        #[cfg(not(feature = "headless"))]
+        app.add_system(apply_render_settings);
+    }
+}
@@ -0,0 +1,205 @@
+use bevy::{
+    input::Input,
+    prelude::{
+        EventWriter, KeyCode, ParallelSystemDescriptorCoercion, Plugin, Res, ResMut, SystemLabel,
+    },
+};
+use bevy_egui::{
+    egui::{self, Rgba},
+    EguiContext,
+};
+
+use crate::voxel::{
+    input::InputAction,
+    interaction::{PlacementMaterial, PlacementMaterialChanged, VoxelInteractionSystem},
+    material::VoxelMaterialRegistry,
+    Voxel,
+};
+
+/// Maximum number of hotbar slots, one per number key.
+pub const HOTBAR_SLOTS: usize = 9;
+
+const SLOT_KEYS: [KeyCode; HOTBAR_SLOTS] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+/// The materials the player can quickly pick as the placement material.
+#[derive(Default)]
+pub struct Hotbar {
+    /// The materials of the slots, filled from the [`VoxelMaterialRegistry`].
+    pub slots: Vec<Voxel>,
+    /// Index of the selected slot, `None` when the placement material isn't in the hotbar (e.g. picked with the eyedropper).
+    pub selected: Option<usize>,
+}
+
+impl Hotbar {
+    /// Returns the slot holding the specified material.
+    pub fn slot_of(&self, voxel: Voxel) -> Option<usize> {
+        self.slots.iter().position(|slot| *slot == voxel)
+    }
+}
+
+/// Fills the hotbar slots with the registered materials whenever the registry changes.
+fn fill_hotbar(
+    registry: Res<VoxelMaterialRegistry>,
+    placement_material: Res<PlacementMaterial>,
+    mut hotbar: ResMut<Hotbar>,
+) {
+    if !registry.is_changed() {
+        return;
+    }
+
+    // skip the void material (id 0), which can't be placed.
+    hotbar.slots = (1..registry.iter_mats().count())
+        .take(HOTBAR_SLOTS)
+        .map(|id| Voxel(id as u8))
+        .collect();
+    hotbar.selected = hotbar.slot_of(placement_material.0);
+}
+
+/// Selects the hotbar slot matching the pressed number key, or the next / previous slot when cycling through the hotbar,
+/// and makes its material the placement material.
+fn select_hotbar_slot(
+    keys: Res<Input<KeyCode>>,
+    actions: Res<Input<InputAction>>,
+    mut egui: ResMut<EguiContext>,
+    mut hotbar: ResMut<Hotbar>,
+    mut placement_material: ResMut<PlacementMaterial>,
+    mut changed_events: EventWriter<PlacementMaterialChanged>,
+) {
+    // don't steal the digits typed into text fields (e.g. the debug console).
+    if egui.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
+    let len = hotbar.slots.len();
+    let cycled = if actions.just_pressed(InputAction::HotbarNext) {
+        Some(hotbar.selected.map_or(0, |slot| (slot + 1) % len.max(1)))
+    } else if actions.just_pressed(InputAction::HotbarPrevious) {
+        Some(
+            hotbar
+                .selected
+                .map_or(len.saturating_sub(1), |slot| (slot + len - 1) % len.max(1)),
+        )
+    } else {
+        None
+    };
+
+    let slot = match SLOT_KEYS
+        .iter()
+        .position(|key| keys.just_pressed(*key))
+        .or(cycled)
+        .filter(|slot| *slot < len)
+    {
+        Some(slot) => slot,
+        None => return,
+    };
+
+    let voxel = hotbar.slots[slot];
+    hotbar.selected = Some(slot);
+    if voxel != placement_material.0 {
+        changed_events.send(PlacementMaterialChanged {
+            previous: placement_material.0,
+            current: voxel,
+        });
+        placement_material.0 = voxel;
+    }
+}
+
+/// Keeps the selected slot in sync with placement material changes made elsewhere.
+fn sync_hotbar_selection(placement_material: Res<PlacementMaterial>, mut hotbar: ResMut<Hotbar>) {
+    if placement_material.is_changed() {
+        let selected = hotbar.slot_of(placement_material.0);
+        if hotbar.selected != selected {
+            hotbar.selected = selected;
+        }
+    }
+}
+
+/// Draws the hotbar at the bottom of the screen.
+fn display_hotbar(
+    mut egui: ResMut<EguiContext>,
+    hotbar: Res<Hotbar>,
+    registry: Res<VoxelMaterialRegistry>,
+) {
+    const SLOT_SIZE: f32 = 40.0;
+
+    egui::Area::new("hotbar")
+        .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -12.0])
+        .show(egui.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    for (slot, voxel) in hotbar.slots.iter().enumerate() {
+                        let material = match registry.get_by_id(voxel.0) {
+                            Some(material) => material,
+                            None => continue,
+                        };
+
+                        let (rect, response) = ui.allocate_exact_size(
+                            egui::Vec2::splat(SLOT_SIZE),
+                            egui::Sense::hover(),
+                        );
+                        let color = material.base_color;
+                        ui.painter().rect_filled(
+                            rect,
+                            4.0,
+                            Rgba::from_rgba_unmultiplied(color.r(), color.g(), color.b(), 1.0),
+                        );
+                        if hotbar.selected == Some(slot) {
+                            ui.painter().rect_stroke(
+                                rect.expand(2.0),
+                                4.0,
+                                egui::Stroke::new(2.0, egui::Color32::WHITE),
+                            );
+                        }
+                        ui.painter().text(
+                            rect.left_top() + egui::vec2(4.0, 2.0),
+                            egui::Align2::LEFT_TOP,
+                            slot + 1,
+                            egui::FontId::monospace(12.0),
+                            egui::Color32::WHITE,
+                        );
+                        response.on_hover_text(material.name.as_ref());
+                    }
+                });
+            });
+        });
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`HotbarPlugin`]
+pub enum HotbarSystem {
+    /// Fills the hotbar slots from the material registry.
+    FillHotbar,
+    /// Selects a slot with the number keys or the hotbar cycling actions.
+    SelectSlot,
+}
+
+/// A hotbar bound to the number keys and the hotbar cycling actions for picking the placement material.
+pub struct HotbarPlugin;
+
+impl Plugin for HotbarPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<Hotbar>()
+            .add_system(fill_hotbar.label(HotbarSystem::FillHotbar))
+            .add_system(
+                select_hotbar_slot
+                    .label(HotbarSystem::SelectSlot)
+                    .after(HotbarSystem::FillHotbar),
+            )
+            .add_system(
+                sync_hotbar_selection
+                    .after(HotbarSystem::SelectSlot)
+                    .after(VoxelInteractionSystem::Eyedropper),
+            )
+            .add_system(display_hotbar.after(HotbarSystem::FillHotbar));
+    }
+}
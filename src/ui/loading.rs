@@ -0,0 +1,66 @@
+use bevy::prelude::{Plugin, Res, ResMut};
+use bevy_egui::{
+    egui::{self, Color32, LayerId, ProgressBar},
+    EguiContext,
+};
+
+use crate::voxel::SpawnLoading;
+
+/// Color the world is hidden behind while loading.
+const BACKGROUND_COLOR: Color32 = Color32::from_rgb(18, 20, 24);
+
+/// Covers the world with the progress of the loading of the spawn area until it's loaded, instead of showing the chunks
+/// popping in.
+fn display_loading_screen(mut egui: ResMut<EguiContext>, loading: Res<SpawnLoading>) {
+    if loading.is_loaded() {
+        return;
+    }
+
+    let progress = loading.progress();
+    let ctx = egui.ctx_mut();
+    // the background layer is drawn over the world, behind the other windows.
+    let screen = ctx.input().screen_rect();
+    ctx.layer_painter(LayerId::background())
+        .rect_filled(screen, 0.0, BACKGROUND_COLOR);
+
+    let fraction = |count: usize| {
+        if progress.total == 0 {
+            0.0
+        } else {
+            count as f32 / progress.total as f32
+        }
+    };
+
+    egui::Area::new("loading_screen")
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            ui.set_width(320.0);
+            ui.vertical_centered(|ui| {
+                ui.heading("Loading the world");
+            });
+            ui.add_space(8.0);
+
+            ui.label("Generating chunks");
+            ui.add(
+                ProgressBar::new(fraction(progress.generated))
+                    .text(format!("{} / {}", progress.generated, progress.total)),
+            );
+            ui.label("Meshing chunks");
+            ui.add(
+                ProgressBar::new(fraction(progress.meshed))
+                    .text(format!("{} / {}", progress.meshed, progress.total)),
+            );
+
+            ui.add_space(4.0);
+            ui.label(format!("{:.1}s", progress.elapsed.as_secs_f32()));
+        });
+}
+
+/// Loading screen shown while the chunks around the spawn get generated and meshed.
+pub struct LoadingScreenPlugin;
+
+impl Plugin for LoadingScreenPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_system(display_loading_screen);
+    }
+}
@@ -0,0 +1,318 @@
+use bevy::{
+    input::Input,
+    math::{IVec2, IVec3, UVec2, Vec3Swizzles},
+    prelude::{
+        Assets, Changed, Entity, Handle, Image, ParallelSystemDescriptorCoercion, Plugin, Query,
+        Res, ResMut, SystemLabel, Transform, With,
+    },
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    utils::{HashMap, HashSet},
+};
+use bevy_egui::{
+    egui::{self, Color32, Pos2, Stroke},
+    EguiContext,
+};
+
+use crate::voxel::{
+    input::InputAction, player::PlayerController, ChunkEntities, ChunkLoadRadius, ChunkMapColors,
+    CurrentLocalPlayerChunk, CHUNK_HEIGHT, CHUNK_LENGTH,
+};
+
+/// Color of the loaded columns without any voxel.
+const EMPTY_COLUMN_COLOR: [u8; 4] = [24, 26, 30, 255];
+/// How much the height difference with the column to the north brightens or darkens a column, per voxel.
+const RELIEF_SHADING: f32 = 0.06;
+
+/// Settings of the minimap, toggled with [`InputAction::ToggleMinimap`].
+pub struct MinimapSettings {
+    pub open: bool,
+    /// Radius (in chunks) of the area around the player shown by the minimap.
+    pub radius: i32,
+    /// Size of the minimap on screen, in logical pixels.
+    pub size: f32,
+}
+
+impl Default for MinimapSettings {
+    fn default() -> Self {
+        Self {
+            open: false,
+            radius: 6,
+            size: 256.0,
+        }
+    }
+}
+
+/// Top-down view of the loaded chunk columns around the player, one pixel per voxel column.
+#[derive(Default)]
+pub struct Minimap {
+    texture: Option<(Handle<Image>, egui::TextureId)>,
+    /// World position (on the X and Z axes) of the first pixel of the texture.
+    origin: IVec2,
+    /// Number of pixels along each side of the texture.
+    size: u32,
+    /// The rasterized pixels of the chunk columns, keyed by the X and Z of their chunk minimums.
+    columns: HashMap<IVec2, Box<[[u8; 4]]>>,
+    /// Chunk columns to rasterize again.
+    queued: HashSet<IVec2>,
+    /// Chunk columns rasterized since the texture got last updated.
+    changed: HashSet<IVec2>,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`MinimapPlugin`]
+pub enum MinimapSystem {
+    /// Rasterizes the chunk columns whose map colors changed.
+    Rasterize,
+    /// Uploads the rasterized chunk columns to the minimap texture.
+    UpdateTexture,
+}
+
+fn toggle_minimap(actions: Res<Input<InputAction>>, mut settings: ResMut<MinimapSettings>) {
+    if actions.just_pressed(InputAction::ToggleMinimap) {
+        settings.open = !settings.open;
+    }
+}
+
+/// Rasterizes the chunk columns whose map colors changed, taking the color of the highest loaded chunk of each voxel
+/// column.
+fn rasterize_minimap(
+    settings: Res<MinimapSettings>,
+    mut minimap: ResMut<Minimap>,
+    changed_chunks: Query<Entity, Changed<ChunkMapColors>>,
+    map_colors: Query<&ChunkMapColors>,
+    chunk_entities: Res<ChunkEntities>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    load_radius: Res<ChunkLoadRadius>,
+) {
+    let minimap = &mut *minimap;
+    // the chunks aren't tracked while closed, they all get rasterized again on opening.
+    if settings.is_changed() {
+        minimap.columns.clear();
+        minimap.changed.clear();
+        minimap.queued = if settings.open {
+            chunk_entities.iter_keys().map(|key| key.xz()).collect()
+        } else {
+            Default::default()
+        };
+    }
+    if !settings.open {
+        return;
+    }
+
+    minimap.queued.extend(
+        changed_chunks
+            .iter()
+            .filter_map(|entity| chunk_entities.chunk_key(entity))
+            .map(|key| key.xz()),
+    );
+
+    // drop the chunk columns without any loaded chunk left.
+    let chunk_keys_y: Vec<i32> = (-load_radius.vertical..=load_radius.vertical)
+        .rev()
+        .map(|y| player_chunk.chunk_min.y + y * CHUNK_HEIGHT as i32)
+        .collect();
+    if chunk_entities.is_changed() {
+        let Minimap {
+            columns, changed, ..
+        } = &mut *minimap;
+        columns.retain(|stack, _| {
+            let loaded = chunk_keys_y.iter().any(|y| {
+                chunk_entities
+                    .entity(IVec3::new(stack.x, *y, stack.y))
+                    .is_some()
+            });
+            if !loaded {
+                changed.insert(*stack);
+            }
+            loaded
+        });
+    }
+
+    for stack in std::mem::take(&mut minimap.queued) {
+        let chunks: Vec<(i32, &ChunkMapColors)> = chunk_keys_y
+            .iter()
+            .filter_map(|y| {
+                let entity = chunk_entities.entity(IVec3::new(stack.x, *y, stack.y))?;
+                Some((*y, map_colors.get(entity).ok()?))
+            })
+            .collect();
+        if chunks.is_empty() {
+            continue;
+        }
+
+        let mut heights = vec![None; (CHUNK_LENGTH * CHUNK_LENGTH) as usize];
+        let mut pixels = vec![EMPTY_COLUMN_COLOR; (CHUNK_LENGTH * CHUNK_LENGTH) as usize];
+        for z in 0..CHUNK_LENGTH {
+            for x in 0..CHUNK_LENGTH {
+                let local = UVec2::new(x, z);
+                let index = (z * CHUNK_LENGTH + x) as usize;
+                if let Some((color, height)) = chunks.iter().find_map(|(y, colors)| {
+                    Some((colors.color(local)?, *y + colors.height(local)? as i32))
+                }) {
+                    pixels[index] = color.as_rgba_u32().to_le_bytes();
+                    heights[index] = Some(height);
+                }
+            }
+        }
+
+        // the slopes facing north get brighter and the ones facing south darker, for showing the relief.
+        for index in CHUNK_LENGTH as usize..pixels.len() {
+            if let (Some(height), Some(north)) =
+                (heights[index], heights[index - CHUNK_LENGTH as usize])
+            {
+                let shade = 1.0 + ((north - height) as f32 * RELIEF_SHADING).clamp(-0.3, 0.3);
+                let shade = |channel: u8| (channel as f32 * shade).min(255.0) as u8;
+                let [r, g, b, a] = pixels[index];
+                pixels[index] = [shade(r), shade(g), shade(b), a];
+            }
+        }
+
+        minimap.columns.insert(stack, pixels.into_boxed_slice());
+        minimap.changed.insert(stack);
+    }
+}
+
+/// Keeps the minimap texture centered on the player chunk, redrawing it when the player changes chunk and only
+/// uploading the rasterized chunk columns otherwise.
+fn update_minimap_texture(
+    settings: Res<MinimapSettings>,
+    mut minimap: ResMut<Minimap>,
+    mut egui: ResMut<EguiContext>,
+    mut images: ResMut<Assets<Image>>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+) {
+    if !settings.open {
+        return;
+    }
+
+    let chunk_length = CHUNK_LENGTH as i32;
+    let radius = settings.radius.max(0);
+    let origin = player_chunk.chunk_min.xz() - IVec2::splat(radius * chunk_length);
+    let size = ((2 * radius + 1) * chunk_length) as u32;
+
+    let minimap = &mut *minimap;
+    let redraw = minimap.texture.is_none() || minimap.origin != origin || minimap.size != size;
+    if redraw && minimap.size != size {
+        if let Some((handle, _)) = minimap.texture.take() {
+            egui.remove_image(&handle);
+            images.remove(&handle);
+        }
+    }
+    let (handle, _) = minimap.texture.get_or_insert_with(|| {
+        let handle = images.add(Image::new(
+            Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            vec![0; (size * size * 4) as usize],
+            TextureFormat::Rgba8UnormSrgb,
+        ));
+        let texture = egui.add_image(handle.clone());
+        (handle, texture)
+    });
+
+    if !redraw && minimap.changed.is_empty() {
+        return;
+    }
+    let image = match images.get_mut(handle) {
+        Some(image) => image,
+        None => return,
+    };
+
+    let stacks: Vec<IVec2> = if redraw {
+        image.data.fill(0);
+        minimap.changed.clear();
+        minimap.columns.keys().copied().collect()
+    } else {
+        minimap.changed.drain().collect()
+    };
+    minimap.origin = origin;
+    minimap.size = size;
+
+    for stack in stacks {
+        let local = stack - origin;
+        if local.cmplt(IVec2::ZERO).any() || local.cmpge(IVec2::splat(size as i32)).any() {
+            continue;
+        }
+
+        let pixels = minimap.columns.get(&stack);
+        for z in 0..chunk_length {
+            let start = (((local.y + z) * size as i32 + local.x) * 4) as usize;
+            let row = &mut image.data[start..start + (chunk_length * 4) as usize];
+            match pixels {
+                Some(pixels) => row
+                    .chunks_exact_mut(4)
+                    .zip(&pixels[(z * chunk_length) as usize..])
+                    .for_each(|(pixel, color)| pixel.copy_from_slice(color)),
+                None => row.fill(0),
+            }
+        }
+    }
+}
+
+/// Shows the minimap in the top right corner, with a marker at the position of the player pointing where they look.
+fn display_minimap(
+    settings: Res<MinimapSettings>,
+    minimap: Res<Minimap>,
+    mut egui: ResMut<EguiContext>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    player: Query<&Transform, With<PlayerController>>,
+) {
+    if !settings.open || minimap.size == 0 {
+        return;
+    }
+    let texture = match &minimap.texture {
+        Some((_, texture)) => *texture,
+        None => return,
+    };
+
+    egui::Window::new("minimap")
+        .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+        .title_bar(false)
+        .resizable(false)
+        .show(egui.ctx_mut(), |ui| {
+            let response = ui.image(texture, egui::Vec2::splat(settings.size));
+            let rect = response.rect;
+            let scale = settings.size / minimap.size as f32;
+
+            let local = (player_chunk.world_pos.xz() - minimap.origin).as_vec2() + 0.5;
+            let center = rect.min + egui::Vec2::new(local.x, local.y) * scale;
+            let forward = player
+                .get_single()
+                .map(|transform| transform.forward().xz().normalize_or_zero())
+                .unwrap_or_default();
+            let forward = egui::Vec2::new(forward.x, forward.y);
+            let side = egui::Vec2::new(-forward.y, forward.x);
+
+            let marker: Vec<Pos2> = vec![
+                center + forward * 7.0,
+                center - forward * 4.0 + side * 4.0,
+                center - forward * 4.0 - side * 4.0,
+            ];
+            ui.painter_at(rect).add(egui::Shape::convex_polygon(
+                marker,
+                Color32::WHITE,
+                Stroke::new(1.0, Color32::BLACK),
+            ));
+        });
+}
+
+/// Minimap rasterizing the map colors of the loaded chunk columns, see [`Minimap`].
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<MinimapSettings>()
+            .init_resource::<Minimap>()
+            .add_system(toggle_minimap.before(MinimapSystem::Rasterize))
+            .add_system(rasterize_minimap.label(MinimapSystem::Rasterize))
+            .add_system(
+                update_minimap_texture
+                    .label(MinimapSystem::UpdateTexture)
+                    .after(MinimapSystem::Rasterize),
+            )
+            .add_system(display_minimap.after(MinimapSystem::UpdateTexture));
+    }
+}
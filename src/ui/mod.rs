@@ -0,0 +1,29 @@
+use bevy::prelude::Plugin;
+
+/// Hotbar for picking the material of the voxels placed by the player.
+mod hotbar;
+pub use hotbar::*;
+
+/// Window editing the user settings and the settings of the current world.
+mod settings;
+pub use settings::*;
+
+/// Progress of the loading of the world at startup.
+mod loading;
+pub use loading::*;
+
+/// Top-down map of the chunks loaded around the player.
+mod minimap;
+pub use minimap::*;
+
+/// Registers the in-game (non debug) user interface.
+pub struct GameplayUIPlugins;
+
+impl Plugin for GameplayUIPlugins {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_plugin(HotbarPlugin)
+            .add_plugin(SettingsWindowPlugin)
+            .add_plugin(LoadingScreenPlugin)
+            .add_plugin(MinimapPlugin);
+    }
+}
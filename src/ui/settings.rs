@@ -0,0 +1,254 @@
+use bevy::{
+    input::Input,
+    prelude::{error, info, KeyCode, MouseButton, Plugin, Res, ResMut},
+};
+use bevy_egui::{
+    egui::{self, Slider},
+    EguiContext,
+};
+
+use crate::{
+    settings::{LayeredSettings, QualityPreset, SettingsLayer, SettingsLayerKind},
+    voxel::input::{InputAction, InputSource},
+};
+
+/// Key toggling the settings window.
+const SETTINGS_WINDOW_KEY: KeyCode = KeyCode::F10;
+
+/// State of the settings window.
+pub struct SettingsWindow {
+    pub open: bool,
+    /// The settings layer being edited.
+    pub layer: SettingsLayerKind,
+    /// Action whose new binding is the next pressed key or mouse button.
+    pub rebinding: Option<InputAction>,
+}
+
+impl Default for SettingsWindow {
+    fn default() -> Self {
+        Self {
+            open: false,
+            layer: SettingsLayerKind::User,
+            rebinding: None,
+        }
+    }
+}
+
+fn toggle_settings_window(keys: Res<Input<KeyCode>>, mut window: ResMut<SettingsWindow>) {
+    if keys.just_pressed(SETTINGS_WINDOW_KEY) {
+        window.open = !window.open;
+        window.rebinding = None;
+    }
+}
+
+/// Draws a setting of a layer with a checkbox overriding it, the inherited value being shown when it isn't overridden.
+fn override_field<T: Clone>(
+    ui: &mut egui::Ui,
+    label: &str,
+    value: &mut Option<T>,
+    inherited: T,
+    edit: impl FnOnce(&mut egui::Ui, &mut T),
+) {
+    ui.horizontal(|ui| {
+        let mut overridden = value.is_some();
+        if ui.checkbox(&mut overridden, label).changed() {
+            *value = overridden.then(|| inherited.clone());
+        }
+
+        match value {
+            Some(value) => edit(ui, value),
+            None => {
+                let mut inherited = inherited;
+                ui.add_enabled_ui(false, |ui| edit(ui, &mut inherited));
+            }
+        }
+    });
+}
+
+/// Formats the inputs bound to an action.
+fn format_bindings(sources: &[InputSource]) -> String {
+    if sources.is_empty() {
+        return "unbound".to_string();
+    }
+
+    sources
+        .iter()
+        .map(|source| match source {
+            InputSource::Key(key) => format!("{:?}", key),
+            InputSource::Mouse(button) => format!("Mouse {:?}", button),
+            InputSource::Gamepad(button) => format!("Gamepad {:?}", button),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Binds the next pressed key or mouse button to the action being rebound, escape cancelling the rebinding.
+fn capture_binding(
+    keys: &Input<KeyCode>,
+    mouse_buttons: &Input<MouseButton>,
+    window: &mut SettingsWindow,
+    layer: &mut SettingsLayer,
+) {
+    let action = match window.rebinding {
+        Some(action) => action,
+        None => return,
+    };
+
+    if keys.just_pressed(KeyCode::Escape) {
+        window.rebinding = None;
+        return;
+    }
+
+    let source = keys
+        .get_just_pressed()
+        .next()
+        .map(|key| InputSource::Key(*key))
+        .or_else(|| {
+            mouse_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| InputSource::Mouse(*button))
+        });
+    if let Some(source) = source {
+        layer.keybinds.insert(action, vec![source]);
+        window.rebinding = None;
+    }
+}
+
+/// Draws the settings window, editing the user settings or the settings of the current world.
+fn display_settings_window(
+    mut egui: ResMut<EguiContext>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut window: ResMut<SettingsWindow>,
+    mut settings: ResMut<LayeredSettings>,
+) {
+    if !window.open {
+        return;
+    }
+
+    if settings.world_path.is_none() {
+        window.layer = SettingsLayerKind::User;
+    }
+
+    // the layer is edited on a copy, so that the settings only get applied again when actually changed.
+    let kind = window.layer;
+    let inherited = settings.resolve_below(kind);
+    let mut layer = settings.layer(kind).clone();
+    let mut open = window.open;
+    let mut save = false;
+
+    capture_binding(&keys, &mouse_buttons, &mut window, &mut layer);
+
+    egui::Window::new("settings")
+        .open(&mut open)
+        .show(egui.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut window.layer, SettingsLayerKind::User, "User");
+                ui.add_enabled_ui(settings.world_path.is_some(), |ui| {
+                    ui.selectable_value(&mut window.layer, SettingsLayerKind::World, "World")
+                        .on_disabled_hover_text("The world isn't saved");
+                });
+            });
+            ui.separator();
+
+            override_field(
+                ui,
+                "Render distance",
+                &mut layer.render_distance,
+                inherited.render_distance,
+                |ui, value| {
+                    ui.add(Slider::new(value, 8..=32));
+                },
+            );
+            override_field(
+                ui,
+                "Vertical render distance",
+                &mut layer.vertical_render_distance,
+                inherited.vertical_render_distance,
+                |ui, value| {
+                    ui.add(Slider::new(value, 1..=16));
+                },
+            );
+            override_field(
+                ui,
+                "Quality",
+                &mut layer.quality,
+                inherited.quality,
+                |ui, value| {
+                    egui::ComboBox::from_id_source("quality")
+                        .selected_text(format!("{:?}", value))
+                        .show_ui(ui, |ui| {
+                            for preset in QualityPreset::ALL {
+                                ui.selectable_value(value, preset, format!("{:?}", preset));
+                            }
+                        });
+                },
+            );
+            override_field(
+                ui,
+                "Autosave interval (s)",
+                &mut layer.autosave_interval,
+                inherited.autosave_interval,
+                |ui, value| {
+                    ui.add(Slider::new(value, 0.0..=1800.0).step_by(30.0));
+                },
+            );
+            ui.separator();
+
+            ui.heading("Key bindings");
+            egui::Grid::new("keybinds").striped(true).show(ui, |ui| {
+                for action in InputAction::ALL {
+                    let overridden = layer.keybinds.get(&action);
+                    ui.label(format!("{:?}", action));
+                    if window.rebinding == Some(action) {
+                        ui.label("press a key...");
+                    } else {
+                        let bindings = overridden
+                            .or_else(|| inherited.keybinds.get(&action))
+                            .map_or(&[][..], |sources| sources);
+                        ui.add_enabled(
+                            overridden.is_some(),
+                            egui::Label::new(format_bindings(bindings)),
+                        );
+                    }
+                    if ui.button("rebind").clicked() {
+                        window.rebinding = Some(action);
+                    }
+                    if ui
+                        .add_enabled(overridden.is_some(), egui::Button::new("reset"))
+                        .clicked()
+                    {
+                        layer.keybinds.remove(&action);
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+
+            save = ui.button("Save").clicked();
+        });
+    window.open = open;
+
+    if &layer != settings.layer(kind) {
+        *settings.layer_mut(kind) = layer;
+    }
+
+    if save {
+        match settings.save(kind) {
+            Ok(path) => info!("Saved settings to {}", path.display()),
+            Err(err) => error!("Failed to save settings: {}", err),
+        }
+    }
+}
+
+/// A settings window toggled with F10, editing the user settings and the settings of the current world.
+pub struct SettingsWindowPlugin;
+
+impl Plugin for SettingsWindowPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<SettingsWindow>()
+            .add_system(toggle_settings_window)
+            .add_system(display_settings_window);
+    }
+}
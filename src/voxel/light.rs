@@ -0,0 +1,92 @@
+use bevy::prelude::Color;
+
+/// Light levels of a voxel, the sunlight level is packed in bits 4 to 7 and the block light level in bits 0 to 3.
+/// The color of the block light is packed as 5 bits per channel in bits 8 to 22, so that light emitting voxels light their
+/// surroundings with their own color, and the id of the material emitting it in bits 23 to 30, so that the terrain shader
+/// animates the block light like its emitter.
+#[derive(Copy, Clone, Hash, Debug, Default, PartialEq, Eq)]
+pub struct Light(pub u32);
+
+#[allow(dead_code)]
+impl Light {
+    pub const MAX_LEVEL: u8 = 15;
+
+    const LEVELS_MASK: u32 = 0xFF;
+    const TINT_SHIFT: u32 = 8;
+    const TINT_CHANNEL_MAX: u32 = 31;
+    const TINT_MASK: u32 = 0x7FFF;
+    const EMITTER_SHIFT: u32 = 15;
+
+    #[inline]
+    pub const fn new(sun: u8, block: u8) -> Self {
+        Self(((sun & 0xF) as u32) << 4 | (block & 0xF) as u32)
+    }
+
+    /// The level of light received from the sky.
+    #[inline]
+    pub const fn sun(&self) -> u8 {
+        (self.0 >> 4 & 0xF) as u8
+    }
+
+    /// The level of light received from light emitting voxels.
+    #[inline]
+    pub const fn block(&self) -> u8 {
+        (self.0 & 0xF) as u8
+    }
+
+    #[inline]
+    pub const fn with_sun(&self, level: u8) -> Self {
+        Self(self.0 & !Self::LEVELS_MASK | Self::new(level, self.block()).0)
+    }
+
+    #[inline]
+    pub const fn with_block(&self, level: u8) -> Self {
+        Self(self.0 & !Self::LEVELS_MASK | Self::new(self.sun(), level).0)
+    }
+
+    /// The packed color and emitter of the block light, as returned by [`Light::pack_source`].
+    #[inline]
+    pub const fn block_source(&self) -> u32 {
+        self.0 >> Self::TINT_SHIFT
+    }
+
+    #[inline]
+    pub const fn with_block_source(&self, source: u32) -> Self {
+        Self(self.0 & Self::LEVELS_MASK | source << Self::TINT_SHIFT)
+    }
+
+    /// The id of the material emitting the block light.
+    #[inline]
+    pub const fn block_emitter(&self) -> u8 {
+        (self.block_source() >> Self::EMITTER_SHIFT) as u8
+    }
+
+    /// Packs the color of the light emitted by a material (see [`Light::pack_tint`]) along the id of the material.
+    pub fn pack_source(emitter: u8, color: Color) -> u32 {
+        Self::pack_tint(color) | (emitter as u32) << Self::EMITTER_SHIFT
+    }
+
+    /// Packs a light color, rescaled so that its brightest channel is at full intensity since the brightness of the light
+    /// is given by its level. Black gives white light.
+    pub fn pack_tint(color: Color) -> u32 {
+        let [r, g, b, _] = color.as_rgba_f32();
+        let max = r.max(g).max(b);
+        if max <= 0.0 {
+            return Self::pack_tint(Color::WHITE);
+        }
+
+        let channel = |value: f32| {
+            ((value / max).clamp(0.0, 1.0) * Self::TINT_CHANNEL_MAX as f32).round() as u32
+        };
+        channel(r) | channel(g) << 5 | channel(b) << 10
+    }
+
+    /// The color of the block light, in the [0, 1] range.
+    #[cfg(not(feature = "headless"))]
+    pub fn block_color(&self) -> [f32; 3] {
+        let tint = self.block_source() & Self::TINT_MASK;
+        [tint, tint >> 5, tint >> 10].map(|channel| {
+            (channel & Self::TINT_CHANNEL_MAX) as f32 / Self::TINT_CHANNEL_MAX as f32
+        })
+    }
+}
@@ -1,24 +1,174 @@
 use bevy::{
-    prelude::{info, Color, Plugin},
-    utils::HashMap,
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::{
+        error, info, warn, AddAsset, AssetEvent, AssetServer, Assets, Color, Commands, EventReader,
+        Handle, Local, Plugin, Res, ResMut, StartupStage,
+    },
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
 };
 use bitflags::bitflags;
-use std::{any::type_name, any::TypeId};
+use serde::{Deserialize, Serialize};
+use std::{any::type_name, any::TypeId, borrow::Cow, io, path::PathBuf};
 
-use super::Voxel;
+use super::{storage::WorldSave, Voxel};
 
 //todo: rewrite this in a way which allows constifying stuff.
 
 // Registry info about a voxel material
-#[derive(Default)]
+#[derive(Clone)]
 pub struct MaterialRegistryInfo {
-    pub name: &'static str,
+    /// Name of the material, unique within the registry.
+    pub name: Cow<'static, str>,
     pub base_color: Color,
     pub flags: VoxelMaterialFlags,
     pub emissive: Color,
     pub perceptual_roughness: f32,
     pub metallic: f32,
     pub reflectance: f32,
+    /// Damage per second dealt to the player while in contact with the material.
+    pub contact_damage: f32,
+    /// Color and opacity of the fog seen while the camera is submerged in the material.
+    pub submerged_fog: Option<Color>,
+    /// Distance (in chunks) beyond which the material isn't meshed, for decorative details invisible from afar anyway.
+    pub max_render_distance: Option<u32>,
+    /// Number of voxels reached by the light emitted by the material, tinted by its emissive color (white if black).
+    /// Defaults to the brightness of the emissive color scaled to [`crate::voxel::Light::MAX_LEVEL`], `Some(0)` disabling the emitted light.
+    pub light_reach: Option<u8>,
+    /// Size (in voxels) of the noise features of the [`VoxelMaterialFlags::TRIPLANAR`] materials.
+    pub triplanar_scale: f32,
+    /// How much the noise of the [`VoxelMaterialFlags::TRIPLANAR`] materials darkens and lightens their base color.
+    pub triplanar_strength: f32,
+    /// Interval (in seconds) between two spreads of the flowing voxels of the material, `None` for the materials which
+    /// don't flow.
+    pub flow_interval: Option<f32>,
+    /// Animation of the light emitted by the material, `None` for a steady light.
+    pub light_animation: Option<LightAnimation>,
+}
+
+impl Default for MaterialRegistryInfo {
+    fn default() -> Self {
+        Self {
+            name: Default::default(),
+            base_color: Default::default(),
+            flags: Default::default(),
+            emissive: Default::default(),
+            perceptual_roughness: Default::default(),
+            metallic: Default::default(),
+            reflectance: Default::default(),
+            contact_damage: Default::default(),
+            submerged_fog: Default::default(),
+            max_render_distance: Default::default(),
+            light_reach: Default::default(),
+            triplanar_scale: default_triplanar_scale(),
+            triplanar_strength: default_triplanar_strength(),
+            flow_interval: Default::default(),
+            light_animation: Default::default(),
+        }
+    }
+}
+
+fn default_triplanar_scale() -> f32 {
+    12.0
+}
+
+fn default_triplanar_strength() -> f32 {
+    0.2
+}
+
+/// Animation of the light emitted by a material (torches, lava...), evaluated by the terrain shader every frame for the
+/// block light the material emits, and applied to the point lights cast by its voxels.
+/// The color of the light itself is the emissive color of the material, the animation only makes it drift.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LightAnimation {
+    /// Color temperature around which the light drifts, in kelvins.
+    pub temperature: f32,
+    /// Maximum drift of the color temperature around [`LightAnimation::temperature`], in kelvins.
+    pub temperature_drift: f32,
+    /// Frequency of the color temperature drift, in Hz.
+    pub drift_frequency: f32,
+    /// Maximum relative variation of the light intensity.
+    pub flicker_amplitude: f32,
+    /// Frequency of the light flicker, in Hz. Neighboring lights flicker out of phase.
+    pub flicker_frequency: f32,
+}
+
+impl Default for LightAnimation {
+    fn default() -> Self {
+        Self {
+            temperature: 4000.0,
+            temperature_drift: 300.0,
+            drift_frequency: 0.2,
+            flicker_amplitude: 0.08,
+            flicker_frequency: 6.0,
+        }
+    }
+}
+
+#[cfg(not(feature = "headless"))]
+impl LightAnimation {
+    /// Returns the time the animations are evaluated at, wrapped to keep enough float precision in the shader.
+    pub fn seconds(time: &bevy::time::Time) -> f32 {
+        (time.seconds_since_startup() % 3600.0) as f32
+    }
+
+    /// Returns the color the light is multiplied by after `seconds` of animation, white when not drifting.
+    pub fn color_at(&self, seconds: f32) -> Color {
+        let phase = seconds * self.drift_frequency * std::f32::consts::TAU;
+        // two incommensurate waves so that the drift doesn't look periodic.
+        let drift = 0.7 * phase.sin() + 0.3 * (2.3 * phase + 1.7).sin();
+        let base = color_temperature(self.temperature);
+        let drifted = color_temperature(self.temperature + drift * self.temperature_drift);
+
+        // relative to the base temperature so that the emitters keep their own color.
+        let ratio = |drifted: f32, base: f32| {
+            if base > 0.0 {
+                (drifted / base).min(2.0)
+            } else {
+                1.0
+            }
+        };
+        Color::rgb(
+            ratio(drifted.r(), base.r()),
+            ratio(drifted.g(), base.g()),
+            ratio(drifted.b(), base.b()),
+        )
+    }
+
+    /// Returns the factor the light intensity is multiplied by after `seconds` of animation, `phase` (in radians)
+    /// offsetting the flicker of the light. Matches the flicker of the block light in the terrain shader.
+    pub fn flicker_at(&self, seconds: f32, phase: f32) -> f32 {
+        let t = std::f32::consts::TAU * self.flicker_frequency * seconds + phase;
+        1.0 + self.flicker_amplitude * (0.6 * t.sin() + 0.4 * (2.3 * t + 1.7).sin())
+    }
+}
+
+/// Approximates the color of a black body at the specified temperature (in kelvins), valid from 1000K to 40000K.
+#[cfg(not(feature = "headless"))]
+fn color_temperature(kelvin: f32) -> Color {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let (r, g) = if t <= 66.0 {
+        (255.0, 99.470_8 * t.ln() - 161.119_57)
+    } else {
+        (
+            329.698_73 * (t - 60.0).powf(-0.133_204_76),
+            288.122_17 * (t - 60.0).powf(-0.075_514_85),
+        )
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+
+    Color::rgb(
+        (r / 255.0).clamp(0.0, 1.0),
+        (g / 255.0).clamp(0.0, 1.0),
+        (b / 255.0).clamp(0.0, 1.0),
+    )
 }
 
 /// Helper / marker trait for voxel materials.
@@ -48,6 +198,11 @@ bitflags! {
         const SOLID = 0 << 0;
         const LIQUID = 1 << 1;
         const UNBREAKABLE = 1 << 2;
+        /// Meshed as crossed quads (grass tufts, flowers), letting the light and the player through.
+        const FOLIAGE = 1 << 3;
+        /// Shaded with noise projected along the world axes, so that the large flat areas of the material don't look
+        /// uniform.
+        const TRIPLANAR = 1 << 4;
     }
 }
 
@@ -57,6 +212,12 @@ impl Default for VoxelMaterialFlags {
     }
 }
 
+/// Maximum number of registered materials, including the void material.
+/// The material ids are 8 bits wide everywhere they are stored: in the voxels (and so in the world saves and the network
+/// messages), in the vertex data of the terrain meshes and in the emitter of the block light. Only the GPU materials
+/// buffer is sized by the registry, allowing more materials would need all of them widened.
+pub const MAX_MATERIALS: usize = u8::MAX as usize + 1;
+
 /// A registry for voxel material types.
 /// This stores the voxel materials along their material id used to refer them in voxel data
 pub struct VoxelMaterialRegistry {
@@ -95,9 +256,132 @@ impl VoxelMaterialRegistry {
         self.mat_ids.insert(TypeId::of::<M>(), self.materials.len());
     }
 
+    /// Registers a material created at runtime (e.g. imported, or added from the material editor), which isn't attached
+    /// to a material type. The GPU materials buffer picks it up along with the other changes made to the registry.
+    /// Returns the id of the material, e.g. for making it the placement material, or `None` if the registry is full.
+    /// A material named like a registered one replaces its properties and keeps its id, e.g. when importing a palette
+    /// again or registering a material restored from the world save.
+    pub fn register_at_runtime(&mut self, mat: MaterialRegistryInfo) -> Option<u8> {
+        if let Some(id) = self
+            .materials
+            .iter()
+            .position(|material| material.name == mat.name)
+        {
+            self.materials[id] = mat;
+            return Some(id as u8);
+        }
+
+        if self.materials.len() >= MAX_MATERIALS {
+            return None;
+        }
+
+        self.materials.push(mat);
+        info!(
+            "Registered material {:?} (ID: {})",
+            self.materials.last().unwrap().name,
+            self.materials.len() - 1
+        );
+        Some((self.materials.len() - 1) as u8)
+    }
+
     pub fn iter_mats(&self) -> impl Iterator<Item = &MaterialRegistryInfo> {
         self.materials.iter()
     }
+
+    /// Restores the ids the materials had when the names by id were saved (see [`WorldSave::save_material_names`]),
+    /// so that the saved voxels keep referring to the same materials whatever order the runtime materials are
+    /// registered in. Missing materials are registered with the default properties, until registered again (e.g. by
+    /// the [`MATERIALS_FILE`] or a palette import).
+    pub fn restore_ids(&mut self, names: &[String]) {
+        for (id, name) in names.iter().enumerate() {
+            match self.materials.get(id) {
+                Some(material) if material.name != *name => warn!(
+                    "Material {} was saved as {:?} but is now {:?}, its saved voxels changed material",
+                    id, name, material.name
+                ),
+                Some(_) => {}
+                None if self.materials.len() >= MAX_MATERIALS => break,
+                None => self.materials.push(MaterialRegistryInfo {
+                    name: name.clone().into(),
+                    ..Default::default()
+                }),
+            }
+        }
+    }
+
+    /// Returns the editable properties of the registered materials, for saving them to a file.
+    pub fn to_asset(&self) -> VoxelMaterialsAsset {
+        VoxelMaterialsAsset {
+            materials: self
+                .materials
+                .iter()
+                .skip(1)
+                .map(|material| SerializedMaterial {
+                    name: material.name.to_string(),
+                    base_color: material.base_color,
+                    flags: material.flags.bits(),
+                    emissive: material.emissive,
+                    perceptual_roughness: material.perceptual_roughness,
+                    metallic: material.metallic,
+                    reflectance: material.reflectance,
+                    contact_damage: material.contact_damage,
+                    submerged_fog: material.submerged_fog,
+                    max_render_distance: material.max_render_distance,
+                    light_reach: material.light_reach,
+                    triplanar_scale: material.triplanar_scale,
+                    triplanar_strength: material.triplanar_strength,
+                    flow_interval: material.flow_interval,
+                    light_animation: material.light_animation,
+                })
+                .collect(),
+        }
+    }
+
+    /// Overrides the properties of the registered materials with the ones of the asset, matching them by name.
+    /// Entries naming unregistered materials (e.g. created from the material editor) are registered at runtime, unless
+    /// the registry is full. Returns the number of updated or registered materials.
+    pub fn apply_asset(&mut self, asset: &VoxelMaterialsAsset) -> usize {
+        asset
+            .materials
+            .iter()
+            .filter(|serialized| {
+                match self
+                    .materials
+                    .iter_mut()
+                    .find(|material| material.name == serialized.name)
+                {
+                    Some(material) => {
+                        *material = serialized.to_registry_info(material.name.clone());
+                        true
+                    }
+                    None => {
+                        let registered = self
+                            .register_at_runtime(
+                                serialized.to_registry_info(serialized.name.clone().into()),
+                            )
+                            .is_some();
+                        if !registered {
+                            error!(
+                                "Failed to register material {:?}, the registry is full",
+                                serialized.name
+                            );
+                        }
+                        registered
+                    }
+                }
+            })
+            .count()
+    }
+
+    /// Saves the properties of the registered materials to the [`MATERIALS_FILE`] of the assets folder.
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let path = materials_file_path();
+        let serialized = ron::ser::to_string_pretty(&self.to_asset(), Default::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+        std::fs::write(&path, serialized)?;
+        Ok(path)
+    }
 }
 
 impl Default for VoxelMaterialRegistry {
@@ -109,7 +393,7 @@ impl Default for VoxelMaterialRegistry {
 
         registry.register_material::<Void>(MaterialRegistryInfo {
             base_color: Color::BLACK,
-            name: "Void",
+            name: "Void".into(),
             flags: VoxelMaterialFlags::SOLID,
             ..Default::default()
         });
@@ -121,9 +405,231 @@ impl Default for VoxelMaterialRegistry {
 // The material with ID #0;
 pub struct Void;
 
+/// Path (relative to the assets folder) of the file storing the tweaked material properties.
+pub const MATERIALS_FILE: &str = "materials.ron";
+
+/// Returns the path of the [`MATERIALS_FILE`], resolved the same way as the asset server resolves the assets folder.
+fn materials_file_path() -> PathBuf {
+    std::env::var("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .or_else(|_| {
+            std::env::current_exe().map(|exe| exe.parent().map(PathBuf::from).unwrap_or_default())
+        })
+        .unwrap_or_default()
+        .join("assets")
+        .join(MATERIALS_FILE)
+}
+
+/// The editable properties of a material, matched by name against the registered materials when loaded (registered
+/// when none matches).
+#[derive(Serialize, Deserialize)]
+pub struct SerializedMaterial {
+    pub name: String,
+    pub base_color: Color,
+    pub flags: u32,
+    pub emissive: Color,
+    pub perceptual_roughness: f32,
+    pub metallic: f32,
+    pub reflectance: f32,
+    #[serde(default)]
+    pub contact_damage: f32,
+    #[serde(default)]
+    pub submerged_fog: Option<Color>,
+    #[serde(default)]
+    pub max_render_distance: Option<u32>,
+    #[serde(default)]
+    pub light_reach: Option<u8>,
+    #[serde(default = "default_triplanar_scale")]
+    pub triplanar_scale: f32,
+    #[serde(default = "default_triplanar_strength")]
+    pub triplanar_strength: f32,
+    #[serde(default)]
+    pub flow_interval: Option<f32>,
+    #[serde(default)]
+    pub light_animation: Option<LightAnimation>,
+}
+
+impl SerializedMaterial {
+    /// Returns the registry info of the material, named `name`.
+    fn to_registry_info(&self, name: Cow<'static, str>) -> MaterialRegistryInfo {
+        MaterialRegistryInfo {
+            name,
+            base_color: self.base_color,
+            flags: VoxelMaterialFlags::from_bits_truncate(self.flags),
+            emissive: self.emissive,
+            perceptual_roughness: self.perceptual_roughness,
+            metallic: self.metallic,
+            reflectance: self.reflectance,
+            contact_damage: self.contact_damage,
+            submerged_fog: self.submerged_fog,
+            max_render_distance: self.max_render_distance,
+            light_reach: self.light_reach,
+            triplanar_scale: self.triplanar_scale,
+            triplanar_strength: self.triplanar_strength,
+            flow_interval: self.flow_interval,
+            light_animation: self.light_animation,
+        }
+    }
+}
+
+/// Material properties stored in a RON file.
+#[derive(Serialize, Deserialize, TypeUuid)]
+#[uuid = "5c9f6b7e-1f0d-4a53-9a8e-3b2d7c4e6f10"]
+pub struct VoxelMaterialsAsset {
+    pub materials: Vec<SerializedMaterial>,
+}
+
+#[derive(Default)]
+struct VoxelMaterialsAssetLoader;
+
+impl AssetLoader for VoxelMaterialsAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let asset: VoxelMaterialsAsset = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ron"]
+    }
+}
+
+/// Handle keeping the loaded [`MATERIALS_FILE`] alive, so that its changes get picked up when hot reloading.
+struct VoxelMaterialsHandle(#[allow(dead_code)] Handle<VoxelMaterialsAsset>);
+
+/// Loads the [`MATERIALS_FILE`] if one was saved.
+fn load_materials_file(asset_server: Res<AssetServer>, mut commands: Commands) {
+    if materials_file_path().exists() {
+        commands.insert_resource(VoxelMaterialsHandle(asset_server.load(MATERIALS_FILE)));
+    }
+}
+
+/// Applies the material properties of the [`MATERIALS_FILE`] once (re)loaded.
+fn apply_loaded_materials(
+    mut events: EventReader<AssetEvent<VoxelMaterialsAsset>>,
+    assets: Res<Assets<VoxelMaterialsAsset>>,
+    mut registry: ResMut<VoxelMaterialRegistry>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+            match assets.get(handle) {
+                Some(asset) => {
+                    let applied = registry.apply_asset(asset);
+                    info!("Loaded {} materials from {}", applied, MATERIALS_FILE);
+                }
+                None => error!("Failed to load materials from {}", MATERIALS_FILE),
+            }
+        }
+    }
+}
+
+/// Restores the material ids of the [`WorldSave`], before the startup systems register any runtime material.
+fn restore_material_ids(
+    world_save: Option<Res<WorldSave>>,
+    mut registry: ResMut<VoxelMaterialRegistry>,
+) {
+    let world_save = match world_save {
+        Some(world_save) => world_save,
+        None => return,
+    };
+
+    match world_save.load_material_names() {
+        Ok(names) => registry.restore_ids(&names),
+        Err(err) => error!(
+            "Failed to restore the material ids of the world save: {}",
+            err
+        ),
+    }
+}
+
+/// Saves the material names by id to the [`WorldSave`] whenever materials get registered.
+fn save_material_ids(
+    world_save: Option<Res<WorldSave>>,
+    registry: Res<VoxelMaterialRegistry>,
+    mut saved_count: Local<usize>,
+) {
+    let world_save = match world_save {
+        Some(world_save) => world_save,
+        None => return,
+    };
+
+    let count = registry.iter_mats().count();
+    if !registry.is_changed() || count == *saved_count {
+        return;
+    }
+
+    let names = registry.iter_mats().map(|material| material.name.as_ref());
+    match world_save.save_material_names(names) {
+        Ok(()) => *saved_count = count,
+        Err(err) => error!("Failed to save the material ids of the world save: {}", err),
+    }
+}
+
 pub struct VoxelMaterialPlugin;
 impl Plugin for VoxelMaterialPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.init_resource::<VoxelMaterialRegistry>();
+        app.init_resource::<VoxelMaterialRegistry>()
+            .add_asset::<VoxelMaterialsAsset>()
+            .init_asset_loader::<VoxelMaterialsAssetLoader>()
+            .add_startup_system_to_stage(StartupStage::PreStartup, restore_material_ids)
+            .add_startup_system(load_materials_file)
+            .add_system(apply_loaded_materials)
+            .add_system(save_material_ids);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runtime_registration_stops_when_full() {
+        let mut registry = VoxelMaterialRegistry::default();
+
+        // the void material takes the id 0.
+        for id in 1..=u8::MAX {
+            assert_eq!(
+                registry.register_at_runtime(MaterialRegistryInfo {
+                    name: format!("Material {}", id).into(),
+                    ..Default::default()
+                }),
+                Some(id)
+            );
+        }
+        assert_eq!(
+            registry.register_at_runtime(MaterialRegistryInfo {
+                name: "Overflow".into(),
+                ..Default::default()
+            }),
+            None
+        );
+        assert_eq!(registry.iter_mats().count(), MAX_MATERIALS);
+    }
+
+    #[test]
+    fn restored_ids_survive_the_registration_order() {
+        let mut registry = VoxelMaterialRegistry::default();
+        registry.restore_ids(&["Void".to_string(), "Ash".to_string(), "Moss".to_string()]);
+
+        let moss = registry.register_at_runtime(MaterialRegistryInfo {
+            name: "Moss".into(),
+            base_color: Color::GREEN,
+            ..Default::default()
+        });
+        let ash = registry.register_at_runtime(MaterialRegistryInfo {
+            name: "Ash".into(),
+            base_color: Color::GRAY,
+            ..Default::default()
+        });
+
+        assert_eq!((ash, moss), (Some(1), Some(2)));
+        assert_eq!(registry.get_by_id(2).unwrap().base_color, Color::GREEN);
+        assert_eq!(registry.iter_mats().count(), 3);
     }
 }
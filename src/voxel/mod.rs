@@ -9,13 +9,26 @@ pub use world::*;
 pub mod terraingen;
 
 ///! Systems and utilities for rendering voxels.
+#[cfg(not(feature = "headless"))]
 pub mod render;
 
 ///! Systems for defining voxel materials with physical properties.
 pub mod material;
 
+///! Import of voxel materials from color palettes.
+pub mod palette;
+
+///! Import and export of voxel schematics.
+pub mod schematic;
+
+///! Replication of the voxel world over the network.
+pub mod net;
+
 /// rust ports of signed distance field functions for use in world generation.
 pub mod sdf;
 
 mod voxel;
 pub use voxel::*;
+
+mod light;
+pub use light::*;
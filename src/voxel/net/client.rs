@@ -0,0 +1,237 @@
+use std::{
+    io,
+    net::{TcpStream, ToSocketAddrs},
+};
+
+use bevy::{
+    math::IVec3,
+    prelude::{error, info, warn, EventWriter, Res, ResMut},
+    utils::HashSet,
+};
+
+use super::{
+    apply_voxel_edit, connection::Connection, NetMessage, VoxelEditRejected, PROTOCOL_VERSION,
+};
+#[cfg(not(feature = "headless"))]
+use crate::voxel::ImmediateChunkRemesh;
+use crate::voxel::{
+    storage::{ChunkMap, VoxelMetadataMap},
+    ChunkCommandQueue, ChunkLoadRadius, ChunkShape, CurrentLocalPlayerChunk, DirtyChunks,
+    LightUpdates, Voxel, CHUNK_SIZE,
+};
+
+/// A connection to a [`super::ChunkServer`], whose chunks replace the locally generated terrain.
+/// Inserting this resource before adding the [`crate::voxel::VoxelWorldPlugin`] makes the app a client.
+pub struct ChunkClient {
+    connection: Connection,
+    /// Whether the server accepted the client.
+    welcomed: bool,
+    /// Whether the connection was lost, the world stops being streamed.
+    disconnected: bool,
+    /// Load radius announced to the server.
+    radius: Option<i32>,
+    /// Player position last sent to the server.
+    position: Option<IVec3>,
+    /// Chunks waiting to be requested to the server.
+    to_request: Vec<IVec3>,
+    /// Chunks requested to the server and not received yet.
+    requested: HashSet<IVec3>,
+}
+
+#[allow(dead_code)]
+impl ChunkClient {
+    /// Connects to the server at the specified address.
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            connection: Connection::new(TcpStream::connect(addr)?)?,
+            welcomed: false,
+            disconnected: false,
+            radius: None,
+            position: None,
+            to_request: Vec::new(),
+            requested: HashSet::default(),
+        })
+    }
+
+    /// Returns whether the client is connected and got accepted by the server.
+    pub fn is_connected(&self) -> bool {
+        self.welcomed && !self.disconnected
+    }
+
+    /// Queues the request of a chunk, replacing its generation.
+    pub fn request_chunk(&mut self, key: IVec3) {
+        if self.requested.insert(key) {
+            self.to_request.push(key);
+        }
+    }
+
+    /// Returns the number of chunks requested and not received yet.
+    pub fn pending_chunks(&self) -> usize {
+        self.requested.len()
+    }
+
+    /// Forwards a voxel edit to the server.
+    pub(super) fn send_edit(&mut self, pos: IVec3, voxel: Voxel) {
+        self.connection.send(&NetMessage::VoxelEdit { pos, voxel });
+    }
+}
+
+/// Marks a chunk and its loaded face neighbors as dirty, so that the faces bordering the chunk get culled.
+fn mark_chunk_dirty(
+    key: IVec3,
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    dirty_chunks: &mut DirtyChunks,
+) {
+    dirty_chunks.mark_dirty(key);
+    [
+        IVec3::X,
+        IVec3::NEG_X,
+        IVec3::Y,
+        IVec3::NEG_Y,
+        IVec3::Z,
+        IVec3::NEG_Z,
+    ]
+    .into_iter()
+    .map(|direction| key + direction * CHUNK_SIZE)
+    .filter(|neighbor| chunks.exists(*neighbor))
+    .for_each(|neighbor| dirty_chunks.mark_dirty(neighbor));
+}
+
+/// Inserts the chunks received from the server and applies its voxel edits, restoring the voxels of the rejected ones.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn receive_server_messages(
+    client: Option<ResMut<ChunkClient>>,
+    mut rejected_events: EventWriter<VoxelEditRejected>,
+    mut chunks: ResMut<ChunkMap<Voxel, ChunkShape>>,
+    mut metadata: ResMut<VoxelMetadataMap>,
+    mut light_updates: ResMut<LightUpdates>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    #[cfg(not(feature = "headless"))] mut immediate_remesh: Option<ResMut<ImmediateChunkRemesh>>,
+) {
+    let mut client = match client {
+        Some(client) if !client.disconnected => client,
+        _ => return,
+    };
+
+    let messages = match client.connection.receive() {
+        Ok(messages) => messages,
+        Err(err) => {
+            error!("Disconnected from the server: {}", err);
+            client.disconnected = true;
+            return;
+        }
+    };
+
+    for message in messages {
+        match message {
+            NetMessage::Welcome { version } => {
+                info!("Connected to the server (protocol version {})", version);
+                client.welcomed = true;
+            }
+            NetMessage::Disconnect { reason } => {
+                error!("Disconnected by the server: {}", reason);
+                client.disconnected = true;
+                return;
+            }
+            NetMessage::ChunkData { key, voxels } => {
+                // chunks unloaded since they were requested are dropped.
+                if !client.requested.remove(&key) {
+                    continue;
+                }
+
+                match NetMessage::decode_chunk_voxels(&voxels) {
+                    Ok(buffer) => {
+                        chunks.insert(key, buffer);
+                        mark_chunk_dirty(key, &chunks, &mut dirty_chunks);
+                    }
+                    Err(err) => error!("Received invalid chunk {:?}: {}", key, err),
+                }
+            }
+            NetMessage::VoxelEdit { pos, voxel } => {
+                if apply_voxel_edit(
+                    &mut chunks,
+                    &mut metadata,
+                    &mut light_updates,
+                    &mut dirty_chunks,
+                    pos,
+                    voxel,
+                ) {
+                    #[cfg(not(feature = "headless"))]
+                    if let Some(immediate_remesh) = immediate_remesh.as_mut() {
+                        immediate_remesh.queue_edit(pos);
+                    }
+                }
+            }
+            NetMessage::EditRejected { pos, voxel, reason } => {
+                warn!("Voxel edit at {:?} rejected by the server: {}", pos, reason);
+                if let Some(voxel) = voxel {
+                    if apply_voxel_edit(
+                        &mut chunks,
+                        &mut metadata,
+                        &mut light_updates,
+                        &mut dirty_chunks,
+                        pos,
+                        voxel,
+                    ) {
+                        #[cfg(not(feature = "headless"))]
+                        if let Some(immediate_remesh) = immediate_remesh.as_mut() {
+                            immediate_remesh.queue_edit(pos);
+                        }
+                    }
+                }
+                rejected_events.send(VoxelEditRejected { pos, voxel, reason });
+            }
+            message => error!("Unexpected message from the server: {:?}", message),
+        }
+    }
+}
+
+/// Sends the load radius and position of the player, requests the chunks to load and forgets the unloaded ones.
+pub(super) fn send_client_messages(
+    client: Option<ResMut<ChunkClient>>,
+    chunk_command_queue: Res<ChunkCommandQueue>,
+    load_radius: Res<ChunkLoadRadius>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+) {
+    let mut client = match client {
+        Some(client) if !client.disconnected => client,
+        _ => return,
+    };
+
+    if client.radius != Some(load_radius.horizontal) {
+        client.radius = Some(load_radius.horizontal);
+        client.connection.send(&NetMessage::Hello {
+            version: PROTOCOL_VERSION,
+            radius: load_radius.horizontal,
+        });
+    }
+
+    if client.position != Some(player_chunk.world_pos) {
+        client.position = Some(player_chunk.world_pos);
+        client
+            .connection
+            .send(&NetMessage::PlayerPosition(player_chunk.world_pos));
+    }
+
+    let forgotten: Vec<IVec3> = chunk_command_queue
+        .pending_data_unloads()
+        .copied()
+        .collect();
+    if !forgotten.is_empty() {
+        forgotten.iter().for_each(|key| {
+            client.requested.remove(key);
+        });
+        client.to_request.retain(|key| !forgotten.contains(key));
+        client.connection.send(&NetMessage::ForgetChunks(forgotten));
+    }
+
+    if !client.to_request.is_empty() {
+        let keys = std::mem::take(&mut client.to_request);
+        client.connection.send(&NetMessage::RequestChunks(keys));
+    }
+
+    if let Err(err) = client.connection.flush() {
+        error!("Disconnected from the server: {}", err);
+        client.disconnected = true;
+    }
+}
@@ -0,0 +1,82 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream},
+};
+
+use super::NetMessage;
+
+/// A TCP connection exchanging [`NetMessage`]s without ever blocking, polled every frame.
+pub struct Connection {
+    stream: TcpStream,
+    /// Received bytes not forming a whole message yet.
+    incoming: Vec<u8>,
+    /// Encoded messages not written to the socket yet.
+    outgoing: Vec<u8>,
+}
+
+#[allow(dead_code)]
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        })
+    }
+
+    /// Returns the address of the remote end of the connection.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    /// Queues a message, written to the socket by the next calls to [`Connection::flush`].
+    pub fn send(&mut self, message: &NetMessage) {
+        message.encode(&mut self.outgoing);
+    }
+
+    /// Returns the number of queued bytes not written to the socket yet.
+    pub fn pending_bytes(&self) -> usize {
+        self.outgoing.len()
+    }
+
+    /// Writes as much of the queued messages as the socket accepts without blocking.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the messages received since the last call, fails once the connection is closed or a malformed message is received.
+    pub fn receive(&mut self) -> anyhow::Result<Vec<NetMessage>> {
+        let mut chunk = [0; 16 * 1024];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(anyhow::anyhow!("connection closed")),
+                Ok(read) => self.incoming.extend_from_slice(&chunk[..read]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let mut messages = Vec::new();
+        let mut consumed = 0;
+        while let Some((message, len)) = NetMessage::decode(&self.incoming[consumed..])? {
+            messages.push(message);
+            consumed += len;
+        }
+        self.incoming.drain(..consumed);
+        Ok(messages)
+    }
+}
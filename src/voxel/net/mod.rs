@@ -0,0 +1,205 @@
+use bevy::{
+    math::IVec3,
+    prelude::{
+        CoreStage, EventReader, EventWriter, ParallelSystemDescriptorCoercion, Plugin, ResMut,
+        SystemLabel, SystemSet,
+    },
+};
+
+#[cfg(not(feature = "headless"))]
+use super::ImmediateChunkRemesh;
+use super::{
+    storage::{ChunkMap, VoxelMetadataMap},
+    ChunkLoadingSystem, ChunkShape, DirtyChunks, LightUpdates, Voxel, VoxelEditJournal,
+};
+
+/// Binary encoding of the messages exchanged by the servers and their clients.
+mod protocol;
+pub use protocol::{NetMessage, MAX_MESSAGE_LEN, PROTOCOL_VERSION};
+
+/// Message framing over non-blocking TCP streams.
+mod connection;
+
+/// Streaming of the chunks of the world to the connected clients.
+mod server;
+pub use server::ChunkServer;
+
+/// Chunks received from a server instead of being generated locally.
+mod client;
+pub use client::ChunkClient;
+
+/// Checks run by a server on the voxel edits of its clients (reach, rate limit, materials, claims).
+mod validation;
+pub use validation::{
+    Claim, ClaimValidator, ClientId, EditRequest, EditValidator, MaterialValidator,
+    RateLimitValidator, ReachValidator,
+};
+
+/// Event editing a voxel of the world, replicated when the world is shared over the network:
+/// a server applies the edit and broadcasts it to the clients having the chunk of the voxel, a client forwards it to the
+/// server and applies it once the server broadcasts it back. Without either, the edit is simply applied.
+pub struct NetworkVoxelEdit {
+    pub pos: IVec3,
+    pub voxel: Voxel,
+    /// Whether the edit gets recorded in the [`VoxelEditJournal`], which isn't the case of the edits of the clients
+    /// applied by a server nor of the edits undoing / redoing the journaled ones.
+    pub journaled: bool,
+    /// On a server, the client which made the edit, notified when the edit doesn't get applied.
+    pub client: Option<ClientId>,
+}
+
+/// Event sent when a journaled [`NetworkVoxelEdit`] (i.e. an edit of the player) empties a voxel, e.g. for spawning its
+/// drop. Sent by clients as well when forwarding the edit to the server.
+pub struct VoxelBroken {
+    pub pos: IVec3,
+    /// The voxel before being broken.
+    pub voxel: Voxel,
+}
+
+/// Event sent on a client when the server rejects one of its voxel edits, e.g. for rolling back what was predicted
+/// when forwarding the edit (the [`VoxelBroken`] drops). The voxel was already restored to the one of the server.
+pub struct VoxelEditRejected {
+    pub pos: IVec3,
+    /// The voxel of the server, `None` if its chunk isn't loaded on the server.
+    pub voxel: Option<Voxel>,
+    pub reason: String,
+}
+
+/// Replaces a voxel of the world, dropping the metadata of the replaced voxel, and queues the update of its light and
+/// mesh, returns whether it changed.
+fn apply_voxel_edit(
+    chunks: &mut ChunkMap<Voxel, ChunkShape>,
+    metadata: &mut VoxelMetadataMap,
+    light_updates: &mut LightUpdates,
+    dirty_chunks: &mut DirtyChunks,
+    pos: IVec3,
+    voxel: Voxel,
+) -> bool {
+    if chunks
+        .voxel_at(pos)
+        .map_or(true, |current| current == voxel)
+        || !dirty_chunks.set_voxel(chunks, pos, voxel)
+    {
+        return false;
+    }
+
+    metadata.remove(pos);
+    light_updates.queue(pos);
+    true
+}
+
+/// Applies the [`NetworkVoxelEdit`]s, or forwards them to the server when connected to one, and journals them as a
+/// single batch.
+#[allow(clippy::too_many_arguments)]
+fn apply_voxel_edits(
+    mut edits: EventReader<NetworkVoxelEdit>,
+    mut journal: ResMut<VoxelEditJournal>,
+    mut server: Option<ResMut<ChunkServer>>,
+    mut client: Option<ResMut<ChunkClient>>,
+    mut chunks: ResMut<ChunkMap<Voxel, ChunkShape>>,
+    mut metadata: ResMut<VoxelMetadataMap>,
+    mut light_updates: ResMut<LightUpdates>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    #[cfg(not(feature = "headless"))] mut immediate_remesh: Option<ResMut<ImmediateChunkRemesh>>,
+    mut broken_events: EventWriter<VoxelBroken>,
+) {
+    for edit in edits.iter() {
+        let previous = chunks.voxel_at(edit.pos);
+        let broken = previous
+            .filter(|previous| {
+                edit.journaled
+                    && *previous != Voxel::EMPTY_VOXEL
+                    && edit.voxel == Voxel::EMPTY_VOXEL
+            })
+            .map(|voxel| VoxelBroken {
+                pos: edit.pos,
+                voxel,
+            });
+
+        if let Some(client) = client.as_mut() {
+            // predicted, the server rejects the edit back if it doesn't apply it.
+            client.send_edit(edit.pos, edit.voxel);
+        } else if apply_voxel_edit(
+            &mut chunks,
+            &mut metadata,
+            &mut light_updates,
+            &mut dirty_chunks,
+            edit.pos,
+            edit.voxel,
+        ) {
+            #[cfg(not(feature = "headless"))]
+            if let Some(immediate_remesh) = immediate_remesh.as_mut() {
+                immediate_remesh.queue_edit(edit.pos);
+            }
+            if let Some(server) = server.as_mut() {
+                server.broadcast_edit(edit.pos, edit.voxel);
+            }
+        } else {
+            // the edits of the clients got validated against the voxels of the server, which may have changed since.
+            if let (Some(server), Some(edit_client)) = (server.as_mut(), edit.client) {
+                let reason = match previous {
+                    Some(_) => format!("voxel {:?} already edited", edit.pos),
+                    None => format!("chunk of voxel {:?} not loaded", edit.pos),
+                };
+                server.reject_edit(edit_client, edit.pos, previous, reason);
+            }
+            continue;
+        }
+
+        if let (true, Some(previous)) = (edit.journaled, previous) {
+            journal.record(edit.pos, previous, edit.voxel);
+        }
+        broken_events.send_batch(broken);
+    }
+    journal.end_batch();
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`VoxelReplicationPlugin`]
+pub enum ReplicationSystem {
+    /// Accepts the new clients and handles the messages received from the clients or the server.
+    ReceiveMessages,
+    /// Applies or forwards the [`NetworkVoxelEdit`]s.
+    ApplyVoxelEdits,
+    /// Streams the chunks to the clients or the chunk requests to the server, and flushes the connections.
+    SendMessages,
+}
+
+/// Shares the voxel world over the network, when a [`ChunkServer`] or [`ChunkClient`] resource is inserted.
+///
+/// A server streams the chunks requested by its clients and keeps the area around each of them loaded, while a client
+/// requests the chunks it loads from the server instead of generating them. The voxel edits made through
+/// [`NetworkVoxelEdit`] events are replicated, the voxels changed by the local simulations (e.g. flowing fluids) aren't.
+pub struct VoxelReplicationPlugin;
+
+impl Plugin for VoxelReplicationPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_event::<NetworkVoxelEdit>()
+            .add_event::<VoxelBroken>()
+            .add_event::<VoxelEditRejected>()
+            .add_system_set(
+                SystemSet::new()
+                    .with_system(server::accept_clients.before(ReplicationSystem::ReceiveMessages))
+                    .with_system(
+                        server::receive_client_messages.label(ReplicationSystem::ReceiveMessages),
+                    )
+                    .with_system(
+                        client::receive_server_messages.label(ReplicationSystem::ReceiveMessages),
+                    )
+                    .with_system(
+                        apply_voxel_edits
+                            .label(ReplicationSystem::ApplyVoxelEdits)
+                            .after(ReplicationSystem::ReceiveMessages),
+                    ),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Last,
+                SystemSet::new()
+                    .with_system(server::send_server_messages)
+                    .with_system(client::send_client_messages)
+                    .label(ReplicationSystem::SendMessages)
+                    .after(ChunkLoadingSystem::DestroyChunks)
+                    .before(ChunkLoadingSystem::UnloadChunkData),
+            );
+    }
+}
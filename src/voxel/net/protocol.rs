@@ -0,0 +1,265 @@
+use std::io::{Read, Write};
+
+use anyhow::anyhow;
+use bevy::math::IVec3;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+
+use crate::voxel::{storage::VoxelBuffer, ChunkShape, Voxel};
+
+/// Version of the protocol, the server drops the clients speaking another version.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Maximum length of a message, in bytes. Longer messages are considered malformed.
+pub const MAX_MESSAGE_LEN: usize = 1 << 20;
+
+/// A message exchanged between a chunk server and its clients.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetMessage {
+    /// First message of a client, `radius` being the radius (in chunks) of the area the server should keep loaded around it.
+    Hello { version: u32, radius: i32 },
+    /// Answer of the server to a [`NetMessage::Hello`] of a compatible client.
+    Welcome { version: u32 },
+    /// Sent by the server before dropping a client.
+    Disconnect { reason: String },
+    /// World position (in voxels) of the player of a client.
+    PlayerPosition(IVec3),
+    /// Chunks a client wants to receive, sent as soon as they're loaded by the server.
+    RequestChunks(Vec<IVec3>),
+    /// Chunks a client unloaded, whose edits it doesn't want to receive anymore.
+    ForgetChunks(Vec<IVec3>),
+    /// The voxels of a chunk, as compressed by [`NetMessage::chunk_data`].
+    ChunkData { key: IVec3, voxels: Vec<u8> },
+    /// A voxel edit, requested by a client or broadcast by the server.
+    VoxelEdit { pos: IVec3, voxel: Voxel },
+    /// Sent by the server when rejecting a voxel edit of a client, along the voxel the server has at the edited position
+    /// (`None` if its chunk isn't loaded) so that the client can roll its prediction back.
+    EditRejected {
+        pos: IVec3,
+        voxel: Option<Voxel>,
+        reason: String,
+    },
+}
+
+const TAG_HELLO: u8 = 0;
+const TAG_WELCOME: u8 = 1;
+const TAG_DISCONNECT: u8 = 2;
+const TAG_PLAYER_POSITION: u8 = 3;
+const TAG_REQUEST_CHUNKS: u8 = 4;
+const TAG_FORGET_CHUNKS: u8 = 5;
+const TAG_CHUNK_DATA: u8 = 6;
+const TAG_VOXEL_EDIT: u8 = 7;
+const TAG_EDIT_REJECTED: u8 = 8;
+
+fn write_ivec3(bytes: &mut Vec<u8>, value: IVec3) {
+    value
+        .to_array()
+        .iter()
+        .for_each(|axis| bytes.extend_from_slice(&axis.to_le_bytes()));
+}
+
+fn write_keys(bytes: &mut Vec<u8>, keys: &[IVec3]) {
+    bytes.extend_from_slice(&(keys.len() as u32).to_le_bytes());
+    keys.iter().for_each(|key| write_ivec3(bytes, *key));
+}
+
+/// Reads the fields of a message payload, failing on truncated payloads.
+struct PayloadReader<'a>(&'a [u8]);
+
+impl<'a> PayloadReader<'a> {
+    fn bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if len > self.0.len() {
+            return Err(anyhow!("truncated message"));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn ivec3(&mut self) -> anyhow::Result<IVec3> {
+        Ok(IVec3::new(self.i32()?, self.i32()?, self.i32()?))
+    }
+
+    fn len(&mut self) -> anyhow::Result<usize> {
+        let len = self.u32()? as usize;
+        if len > self.0.len() {
+            return Err(anyhow!("truncated message"));
+        }
+        Ok(len)
+    }
+
+    fn keys(&mut self) -> anyhow::Result<Vec<IVec3>> {
+        let count = self.u32()? as usize;
+        if count * 12 > self.0.len() {
+            return Err(anyhow!("truncated message"));
+        }
+        (0..count).map(|_| self.ivec3()).collect()
+    }
+}
+
+impl NetMessage {
+    /// Creates a [`NetMessage::ChunkData`] message holding the deflate compressed voxels of a chunk.
+    pub fn chunk_data(key: IVec3, buffer: &VoxelBuffer<Voxel, ChunkShape>) -> Self {
+        let raw: Vec<u8> = buffer.slice().iter().map(|voxel| voxel.0).collect();
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+        // writing to a vector can't fail.
+        encoder.write_all(&raw).unwrap();
+        Self::ChunkData {
+            key,
+            voxels: encoder.finish().unwrap(),
+        }
+    }
+
+    /// Decompresses the voxels of a [`NetMessage::ChunkData`] message.
+    pub fn decode_chunk_voxels(voxels: &[u8]) -> anyhow::Result<VoxelBuffer<Voxel, ChunkShape>> {
+        let mut buffer = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+        let len = buffer.slice().len();
+
+        // the decompressed size is bounded, so that a malicious message can't exhaust the memory.
+        let mut raw = Vec::with_capacity(len);
+        DeflateDecoder::new(voxels)
+            .take(len as u64 + 1)
+            .read_to_end(&mut raw)?;
+        if raw.len() != len {
+            return Err(anyhow!("invalid chunk size"));
+        }
+
+        buffer
+            .slice_mut()
+            .iter_mut()
+            .zip(raw)
+            .for_each(|(voxel, raw)| *voxel = Voxel(raw));
+        Ok(buffer)
+    }
+
+    /// Appends the message to a byte stream, prefixed by its length.
+    pub fn encode(&self, bytes: &mut Vec<u8>) {
+        let start = bytes.len();
+        bytes.extend_from_slice(&[0; 4]);
+
+        match self {
+            Self::Hello { version, radius } => {
+                bytes.push(TAG_HELLO);
+                bytes.extend_from_slice(&version.to_le_bytes());
+                bytes.extend_from_slice(&radius.to_le_bytes());
+            }
+            Self::Welcome { version } => {
+                bytes.push(TAG_WELCOME);
+                bytes.extend_from_slice(&version.to_le_bytes());
+            }
+            Self::Disconnect { reason } => {
+                bytes.push(TAG_DISCONNECT);
+                bytes.extend_from_slice(&(reason.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(reason.as_bytes());
+            }
+            Self::PlayerPosition(pos) => {
+                bytes.push(TAG_PLAYER_POSITION);
+                write_ivec3(bytes, *pos);
+            }
+            Self::RequestChunks(keys) => {
+                bytes.push(TAG_REQUEST_CHUNKS);
+                write_keys(bytes, keys);
+            }
+            Self::ForgetChunks(keys) => {
+                bytes.push(TAG_FORGET_CHUNKS);
+                write_keys(bytes, keys);
+            }
+            Self::ChunkData { key, voxels } => {
+                bytes.push(TAG_CHUNK_DATA);
+                write_ivec3(bytes, *key);
+                bytes.extend_from_slice(&(voxels.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(voxels);
+            }
+            Self::VoxelEdit { pos, voxel } => {
+                bytes.push(TAG_VOXEL_EDIT);
+                write_ivec3(bytes, *pos);
+                bytes.push(voxel.0);
+            }
+            Self::EditRejected { pos, voxel, reason } => {
+                bytes.push(TAG_EDIT_REJECTED);
+                write_ivec3(bytes, *pos);
+                bytes.push(voxel.is_some() as u8);
+                bytes.push(voxel.map_or(0, |voxel| voxel.0));
+                bytes.extend_from_slice(&(reason.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(reason.as_bytes());
+            }
+        }
+
+        let len = (bytes.len() - start - 4) as u32;
+        bytes[start..start + 4].copy_from_slice(&len.to_le_bytes());
+    }
+
+    /// Decodes the first message of a byte stream, returns the message and its encoded length (prefix included),
+    /// or `None` if the stream doesn't hold a whole message yet.
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Option<(Self, usize)>> {
+        if bytes.len() < 4 {
+            return Ok(None);
+        }
+
+        let len = u32::from_le_bytes(bytes[..4].try_into().unwrap()) as usize;
+        if len == 0 || len > MAX_MESSAGE_LEN {
+            return Err(anyhow!("invalid message length {}", len));
+        }
+        let payload = match bytes.get(4..4 + len) {
+            Some(payload) => payload,
+            None => return Ok(None),
+        };
+
+        let mut reader = PayloadReader(payload);
+        let message = match reader.u8()? {
+            TAG_HELLO => Self::Hello {
+                version: reader.u32()?,
+                radius: reader.i32()?,
+            },
+            TAG_WELCOME => Self::Welcome {
+                version: reader.u32()?,
+            },
+            TAG_DISCONNECT => {
+                let len = reader.len()?;
+                Self::Disconnect {
+                    reason: String::from_utf8_lossy(reader.bytes(len)?).into_owned(),
+                }
+            }
+            TAG_PLAYER_POSITION => Self::PlayerPosition(reader.ivec3()?),
+            TAG_REQUEST_CHUNKS => Self::RequestChunks(reader.keys()?),
+            TAG_FORGET_CHUNKS => Self::ForgetChunks(reader.keys()?),
+            TAG_CHUNK_DATA => {
+                let key = reader.ivec3()?;
+                let len = reader.len()?;
+                Self::ChunkData {
+                    key,
+                    voxels: reader.bytes(len)?.to_vec(),
+                }
+            }
+            TAG_VOXEL_EDIT => Self::VoxelEdit {
+                pos: reader.ivec3()?,
+                voxel: Voxel(reader.u8()?),
+            },
+            TAG_EDIT_REJECTED => {
+                let pos = reader.ivec3()?;
+                let known = reader.u8()? != 0;
+                let voxel = Voxel(reader.u8()?);
+                let len = reader.len()?;
+                Self::EditRejected {
+                    pos,
+                    voxel: known.then_some(voxel),
+                    reason: String::from_utf8_lossy(reader.bytes(len)?).into_owned(),
+                }
+            }
+            tag => return Err(anyhow!("unknown message type {}", tag)),
+        };
+
+        Ok(Some((message, 4 + len)))
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
+};
+
+use bevy::{
+    math::IVec3,
+    prelude::{
+        info, warn, Commands, DespawnRecursiveExt, Entity, EventWriter, Query, Res, ResMut,
+        Transform, TransformBundle,
+    },
+    time::Time,
+    utils::HashSet,
+};
+
+use super::{
+    connection::Connection,
+    validation::{
+        ClientId, EditRequest, EditValidator, MaterialValidator, RateLimitValidator, ReachValidator,
+    },
+    NetMessage, NetworkVoxelEdit, PROTOCOL_VERSION,
+};
+use crate::voxel::{
+    chunk_key_at, material::VoxelMaterialRegistry, storage::ChunkMap, AuthoredLevel,
+    ChunkLoadAnchor, ChunkShape, Voxel, WorldOrigin, CHUNK_SIZE, MAX_GENERATED_HEIGHT,
+};
+
+/// Maximum number of bytes queued for a client, no chunk gets sent to a client lagging behind past it.
+const MAX_PENDING_BYTES: usize = 4 * 1024 * 1024;
+
+/// Maximum number of chunks a client can have requested and not received yet.
+const MAX_REQUESTED_CHUNKS: usize = 64 * 1024;
+
+/// A client connected to a [`ChunkServer`].
+struct ServerClient {
+    id: ClientId,
+    connection: Connection,
+    /// Entity keeping the chunks around the client loaded, spawned once the client said hello.
+    anchor: Option<Entity>,
+    /// World position of the player of the client.
+    position: IVec3,
+    /// Chunks requested by the client and not sent yet.
+    requested: HashSet<IVec3>,
+    /// Chunks sent to the client, whose edits get broadcast to it.
+    sent: HashSet<IVec3>,
+}
+
+/// Streams the chunks of the world to the clients connected to its TCP listener.
+/// Inserting this resource before adding the [`crate::voxel::VoxelWorldPlugin`] makes the app a server.
+pub struct ChunkServer {
+    listener: TcpListener,
+    clients: Vec<ServerClient>,
+    next_client_id: u64,
+    /// Checks run on the voxel edits of the clients, in order, before applying them.
+    validators: Vec<Box<dyn EditValidator>>,
+    /// Maximum radius (in chunks) of the area kept loaded around each client.
+    pub max_radius: i32,
+    /// Maximum number of chunks sent to each client per frame.
+    pub chunks_per_frame: usize,
+}
+
+#[allow(dead_code)]
+impl ChunkServer {
+    /// Starts listening for clients on the specified address.
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            clients: Vec::new(),
+            next_client_id: 0,
+            validators: vec![
+                Box::new(ReachValidator::default()),
+                Box::new(RateLimitValidator::default()),
+                Box::new(MaterialValidator),
+            ],
+            max_radius: 16,
+            chunks_per_frame: 16,
+        })
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Returns the number of connected clients.
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Adds a check run on the voxel edits of the clients, after the ones already added.
+    pub fn add_validator(&mut self, validator: impl EditValidator) -> &mut Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    /// Removes the checks run on the voxel edits of the clients, including the default reach, rate and material checks.
+    pub fn clear_validators(&mut self) {
+        self.validators.clear();
+    }
+
+    /// Sends a voxel edit to the clients having the chunk of the voxel.
+    pub(super) fn broadcast_edit(&mut self, pos: IVec3, voxel: Voxel) {
+        let key = chunk_key_at(pos);
+        self.clients
+            .iter_mut()
+            .filter(|client| client.sent.contains(&key))
+            .for_each(|client| {
+                client
+                    .connection
+                    .send(&NetMessage::VoxelEdit { pos, voxel })
+            });
+    }
+
+    /// Notifies a client that one of its voxel edits didn't get applied, along with the voxel of the server.
+    pub(super) fn reject_edit(
+        &mut self,
+        client: ClientId,
+        pos: IVec3,
+        voxel: Option<Voxel>,
+        reason: String,
+    ) {
+        // the client may have disconnected since.
+        if let Some(client) = self.clients.iter_mut().find(|other| other.id == client) {
+            client
+                .connection
+                .send(&NetMessage::EditRejected { pos, voxel, reason });
+        }
+    }
+}
+
+/// Accepts the clients connecting to the server.
+pub(super) fn accept_clients(server: Option<ResMut<ChunkServer>>) {
+    let mut server = match server {
+        Some(server) => server,
+        None => return,
+    };
+
+    loop {
+        match server.listener.accept() {
+            Ok((stream, addr)) => match Connection::new(stream) {
+                Ok(connection) => {
+                    info!("Client {} connected", addr);
+                    let id = ClientId(server.next_client_id);
+                    server.next_client_id += 1;
+                    server.clients.push(ServerClient {
+                        id,
+                        connection,
+                        anchor: None,
+                        position: IVec3::ZERO,
+                        requested: HashSet::default(),
+                        sent: HashSet::default(),
+                    });
+                }
+                Err(err) => warn!(
+                    "Failed to set up the connection of client {}: {}",
+                    addr, err
+                ),
+            },
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+            Err(err) => {
+                warn!("Failed to accept a client: {}", err);
+                break;
+            }
+        }
+    }
+}
+
+/// Handles the messages of the clients, dropping the disconnected ones along with their anchor.
+/// The voxel edits of the clients are only applied once accepted by all the [`EditValidator`]s of the server.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn receive_client_messages(
+    server: Option<ResMut<ChunkServer>>,
+    origin: Res<WorldOrigin>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    materials: Res<VoxelMaterialRegistry>,
+    time: Res<Time>,
+    mut anchors: Query<(&mut Transform, &mut ChunkLoadAnchor)>,
+    mut edits: EventWriter<NetworkVoxelEdit>,
+    mut commands: Commands,
+) {
+    let mut server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    let max_radius = server.max_radius;
+    let ChunkServer {
+        clients,
+        validators,
+        ..
+    } = &mut *server;
+
+    clients.retain_mut(|client| {
+        let messages = match client.connection.receive() {
+            Ok(messages) => messages,
+            Err(err) => {
+                info!("Client disconnected: {}", err);
+                if let Some(anchor) = client.anchor {
+                    commands.entity(anchor).despawn_recursive();
+                }
+                validators
+                    .iter_mut()
+                    .for_each(|validator| validator.forget_client(client.id));
+                return false;
+            }
+        };
+
+        for message in messages {
+            match message {
+                NetMessage::Hello { version, radius } => {
+                    if version != PROTOCOL_VERSION {
+                        client.connection.send(&NetMessage::Disconnect {
+                            reason: format!(
+                                "protocol version {} expected, got {}",
+                                PROTOCOL_VERSION, version
+                            ),
+                        });
+                        // the message still gets flushed before the connection is dropped.
+                        let _ = client.connection.flush();
+                        validators
+                            .iter_mut()
+                            .for_each(|validator| validator.forget_client(client.id));
+                        return false;
+                    }
+
+                    // the chunks at the edge of the requested area need their neighbors to be sent, see `is_chunk_complete`.
+                    let radius = radius.clamp(1, max_radius) + 1;
+                    match client
+                        .anchor
+                        .and_then(|anchor| anchors.get_mut(anchor).ok())
+                    {
+                        Some((_, mut anchor)) => anchor.radius = radius,
+                        None => {
+                            let translation = origin.to_translation(client.position);
+                            client.anchor = Some(
+                                commands
+                                    .spawn_bundle(TransformBundle::from_transform(
+                                        Transform::from_translation(translation),
+                                    ))
+                                    .insert(ChunkLoadAnchor { radius })
+                                    .id(),
+                            );
+                            client.connection.send(&NetMessage::Welcome {
+                                version: PROTOCOL_VERSION,
+                            });
+                        }
+                    }
+                }
+                NetMessage::PlayerPosition(pos) => {
+                    client.position = pos;
+                    if let Some((mut transform, _)) = client
+                        .anchor
+                        .and_then(|anchor| anchors.get_mut(anchor).ok())
+                    {
+                        transform.translation = origin.to_translation(pos);
+                    }
+                }
+                NetMessage::RequestChunks(keys) => {
+                    let room = MAX_REQUESTED_CHUNKS.saturating_sub(client.requested.len());
+                    client.requested.extend(
+                        keys.into_iter()
+                            .filter(|key| chunk_key_at(*key) == *key)
+                            .take(room),
+                    );
+                }
+                NetMessage::ForgetChunks(keys) => keys.iter().for_each(|key| {
+                    client.requested.remove(key);
+                    client.sent.remove(key);
+                }),
+                NetMessage::VoxelEdit { pos, voxel } if client.anchor.is_some() => {
+                    let request = EditRequest {
+                        client: client.id,
+                        player_position: client.position,
+                        pos,
+                        voxel,
+                        current: chunks.voxel_at(pos),
+                        materials: &materials,
+                        time: time.time_since_startup(),
+                    };
+                    match validators
+                        .iter_mut()
+                        .try_for_each(|validator| validator.validate(&request))
+                    {
+                        Ok(()) => edits.send(NetworkVoxelEdit {
+                            pos,
+                            voxel,
+                            journaled: false,
+                            client: Some(client.id),
+                        }),
+                        Err(reason) => client.connection.send(&NetMessage::EditRejected {
+                            pos,
+                            voxel: request.current,
+                            reason,
+                        }),
+                    }
+                }
+                message => warn!("Unexpected message from a client: {:?}", message),
+            }
+        }
+
+        true
+    });
+}
+
+/// Returns whether a chunk and all of its neighbors got their data, so that no structure spilling over from a neighbor
+/// still has to be written to the chunk. Neighbors which never get any data don't need to be loaded.
+fn is_chunk_complete(
+    key: IVec3,
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    level: Option<&AuthoredLevel>,
+) -> bool {
+    if !chunks.exists(key) {
+        return false;
+    }
+
+    (-1..=1).all(|x| {
+        (-1..=1).all(|y| {
+            (-1..=1).all(|z| {
+                let neighbor = key + IVec3::new(x, y, z) * CHUNK_SIZE;
+                neighbor.y < 0
+                    || neighbor.y >= MAX_GENERATED_HEIGHT
+                    || level.map_or(false, |level| !level.contains_chunk(neighbor))
+                    || chunks.exists(neighbor)
+            })
+        })
+    })
+}
+
+/// Sends the requested chunks once loaded, closest to each client first, and flushes the connections of the clients.
+pub(super) fn send_server_messages(
+    server: Option<ResMut<ChunkServer>>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    level: Option<Res<AuthoredLevel>>,
+    mut commands: Commands,
+) {
+    let mut server = match server {
+        Some(server) => server,
+        None => return,
+    };
+    let chunks_per_frame = server.chunks_per_frame;
+    let ChunkServer {
+        clients,
+        validators,
+        ..
+    } = &mut *server;
+
+    clients.retain_mut(|client| {
+        if client.connection.pending_bytes() < MAX_PENDING_BYTES {
+            let mut ready: Vec<IVec3> = client
+                .requested
+                .iter()
+                .copied()
+                .filter(|key| is_chunk_complete(*key, &chunks, level.as_deref()))
+                .collect();
+            let position = client.position;
+            // in floating point, the squared distance to a position sent by the client overflowing an i32.
+            ready.sort_unstable_by(|a, b| {
+                let distance = |key: &IVec3| key.as_vec3().distance_squared(position.as_vec3());
+                distance(a).total_cmp(&distance(b))
+            });
+
+            for key in ready.into_iter().take(chunks_per_frame) {
+                let buffer = chunks.buffer_at(key).unwrap();
+                client.connection.send(&NetMessage::chunk_data(key, buffer));
+                client.requested.remove(&key);
+                client.sent.insert(key);
+            }
+        }
+
+        match client.connection.flush() {
+            Ok(()) => true,
+            Err(err) => {
+                info!("Client disconnected: {}", err);
+                if let Some(anchor) = client.anchor {
+                    commands.entity(anchor).despawn_recursive();
+                }
+                validators
+                    .iter_mut()
+                    .for_each(|validator| validator.forget_client(client.id));
+                false
+            }
+        }
+    });
+}
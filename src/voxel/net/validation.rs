@@ -0,0 +1,169 @@
+use bevy::{
+    math::IVec3,
+    utils::{Duration, HashMap},
+};
+
+use crate::voxel::{
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    Voxel, MAX_BRUSH_RADIUS, PLAYER_REACH,
+};
+
+/// Identifier of a client of a [`super::ChunkServer`], unique for the lifetime of the server.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ClientId(pub u64);
+
+/// A voxel edit requested by a client, checked by the [`EditValidator`]s of the server before being applied.
+pub struct EditRequest<'a> {
+    pub client: ClientId,
+    /// World position of the player of the client, as last sent by the client.
+    pub player_position: IVec3,
+    pub pos: IVec3,
+    pub voxel: Voxel,
+    /// The voxel replaced by the edit, `None` if its chunk isn't loaded on the server.
+    pub current: Option<Voxel>,
+    pub materials: &'a VoxelMaterialRegistry,
+    /// Time elapsed since the startup of the server.
+    pub time: Duration,
+}
+
+/// A check of the voxel edits of the clients, run by the server before accepting them.
+/// Rejected edits aren't applied, and the client gets notified so that it can roll back its prediction.
+pub trait EditValidator: Send + Sync + 'static {
+    /// Returns the reason the edit is rejected, `Ok(())` to accept it.
+    fn validate(&mut self, edit: &EditRequest) -> Result<(), String>;
+
+    /// Drops the state kept about a disconnected client.
+    fn forget_client(&mut self, _client: ClientId) {}
+}
+
+/// Rejects the edits too far away from the player of the client.
+pub struct ReachValidator {
+    /// Maximum distance (in voxels) between the player and the edited voxels.
+    pub max_distance: f32,
+}
+
+impl Default for ReachValidator {
+    fn default() -> Self {
+        // the brushes edit the voxels around the targeted one, and the position of the player is rounded to a voxel.
+        Self {
+            max_distance: PLAYER_REACH + MAX_BRUSH_RADIUS as f32 + 2.0,
+        }
+    }
+}
+
+impl EditValidator for ReachValidator {
+    fn validate(&mut self, edit: &EditRequest) -> Result<(), String> {
+        // computed in floating point, the client being free to send any position.
+        let distance = edit.pos.as_vec3().distance(edit.player_position.as_vec3());
+        if distance > self.max_distance {
+            return Err(format!(
+                "voxel {:?} out of reach ({:.1} voxels away)",
+                edit.pos, distance
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Limits the rate of the edits of each client with a token bucket, refilling over time up to a burst of edits.
+pub struct RateLimitValidator {
+    /// Number of edits a client can make at once.
+    pub burst: f32,
+    /// Number of edits regained per second.
+    pub edits_per_second: f32,
+    /// Remaining edits of each client, along the time they were last updated.
+    buckets: HashMap<ClientId, (f32, Duration)>,
+}
+
+impl Default for RateLimitValidator {
+    fn default() -> Self {
+        // a stroke of the largest brush edits up to ~17k voxels.
+        Self {
+            burst: 20_000.0,
+            edits_per_second: 5_000.0,
+            buckets: HashMap::default(),
+        }
+    }
+}
+
+impl EditValidator for RateLimitValidator {
+    fn validate(&mut self, edit: &EditRequest) -> Result<(), String> {
+        let (tokens, updated) = self
+            .buckets
+            .entry(edit.client)
+            .or_insert((self.burst, edit.time));
+        let elapsed = edit.time.saturating_sub(*updated).as_secs_f32();
+        *tokens = (*tokens + elapsed * self.edits_per_second).min(self.burst);
+        *updated = edit.time;
+
+        if *tokens < 1.0 {
+            return Err("too many edits".to_string());
+        }
+        *tokens -= 1.0;
+        Ok(())
+    }
+
+    fn forget_client(&mut self, client: ClientId) {
+        self.buckets.remove(&client);
+    }
+}
+
+/// Rejects the edits placing unregistered materials or replacing unbreakable voxels.
+#[derive(Default)]
+pub struct MaterialValidator;
+
+impl EditValidator for MaterialValidator {
+    fn validate(&mut self, edit: &EditRequest) -> Result<(), String> {
+        if edit.voxel != Voxel::EMPTY_VOXEL && edit.materials.get_by_id(edit.voxel.0).is_none() {
+            return Err(format!("unregistered material {}", edit.voxel.0));
+        }
+
+        let unbreakable = edit
+            .current
+            .and_then(|current| edit.materials.get_by_id(current.0))
+            .map_or(false, |material| {
+                material.flags.contains(VoxelMaterialFlags::UNBREAKABLE)
+            });
+        if unbreakable && edit.current != Some(edit.voxel) {
+            return Err(format!("voxel {:?} is unbreakable", edit.pos));
+        }
+        Ok(())
+    }
+}
+
+/// An area of the world claimed by a client, or protected from all the clients when it has no owner.
+pub struct Claim {
+    pub min: IVec3,
+    pub max: IVec3,
+    pub owner: Option<ClientId>,
+}
+
+impl Claim {
+    pub fn contains(&self, pos: IVec3) -> bool {
+        pos.cmpge(self.min).all() && pos.cmple(self.max).all()
+    }
+}
+
+/// Rejects the edits of the voxels claimed by other clients.
+#[derive(Default)]
+pub struct ClaimValidator {
+    pub claims: Vec<Claim>,
+}
+
+impl EditValidator for ClaimValidator {
+    fn validate(&mut self, edit: &EditRequest) -> Result<(), String> {
+        match self
+            .claims
+            .iter()
+            .find(|claim| claim.contains(edit.pos) && claim.owner != Some(edit.client))
+        {
+            Some(_) => Err(format!("voxel {:?} is claimed", edit.pos)),
+            None => Ok(()),
+        }
+    }
+
+    fn forget_client(&mut self, client: ClientId) {
+        // the ids aren't reused, the claims of the disconnected clients would never be editable again.
+        self.claims.retain(|claim| claim.owner != Some(client));
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use bevy::prelude::{error, info, Color, Plugin, Res, ResMut};
+
+use super::material::{MaterialRegistryInfo, VoxelMaterialFlags, VoxelMaterialRegistry};
+
+/// Reads the colors of a palette, either from a Lospec palette file (`.hex`, `.gpl` or Paint.NET `.txt`) or from an image.
+/// Each distinct opaque pixel of a palette image is a color of the palette, in reading order.
+pub fn read_palette(path: impl AsRef<Path>) -> anyhow::Result<Vec<Color>> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    let colors = match extension.as_deref() {
+        Some("hex") => parse_hex_palette(&std::fs::read_to_string(path)?)?,
+        Some("gpl") => parse_gpl_palette(&std::fs::read_to_string(path)?)?,
+        Some("txt") => parse_paint_net_palette(&std::fs::read_to_string(path)?)?,
+        _ => read_image_palette(path)?,
+    };
+
+    if colors.is_empty() {
+        return Err(anyhow!("the palette doesn't have any color"));
+    }
+    Ok(colors)
+}
+
+/// Parses a palette listing a `RRGGBB` hex color per line.
+fn parse_hex_palette(content: &str) -> anyhow::Result<Vec<Color>> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            Color::hex(line.trim_start_matches('#'))
+                .with_context(|| format!("invalid color '{}'", line))
+        })
+        .collect()
+}
+
+/// Parses a GIMP palette, listing the `R G B` components of a color (and its optional name) per line after its header.
+fn parse_gpl_palette(content: &str) -> anyhow::Result<Vec<Color>> {
+    content
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| *line != "GIMP Palette")
+        .skip(1)
+        // the header is followed by optional `Name:` and `Columns:` lines, comments start with `#`.
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.contains(':'))
+        .map(|line| {
+            let components: Vec<u8> = line
+                .split_whitespace()
+                .take(3)
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .with_context(|| format!("invalid color '{}'", line))?;
+
+            match components[..] {
+                [r, g, b] => Ok(Color::rgb_u8(r, g, b)),
+                _ => Err(anyhow!("invalid color '{}'", line)),
+            }
+        })
+        .collect()
+}
+
+/// Parses a Paint.NET palette, listing a `AARRGGBB` hex color per line, comments start with `;`.
+fn parse_paint_net_palette(content: &str) -> anyhow::Result<Vec<Color>> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .map(|line| {
+            let argb = u32::from_str_radix(line, 16)
+                .ok()
+                .filter(|_| line.len() == 8)
+                .with_context(|| format!("invalid color '{}'", line))?;
+            let [a, r, g, b] = argb.to_be_bytes();
+            Ok(Color::rgba_u8(r, g, b, a))
+        })
+        .collect()
+}
+
+/// Reads the distinct opaque colors of an image, in reading order.
+fn read_image_palette(path: &Path) -> anyhow::Result<Vec<Color>> {
+    let image = image::open(path)?.into_rgba8();
+
+    let mut colors: Vec<[u8; 4]> = Vec::new();
+    for pixel in image.pixels() {
+        if pixel.0[3] > 0 && !colors.contains(&pixel.0) {
+            colors.push(pixel.0);
+        }
+    }
+
+    Ok(colors
+        .into_iter()
+        .map(|[r, g, b, a]| Color::rgba_u8(r, g, b, a))
+        .collect())
+}
+
+/// Registers a solid material per color of a palette, named after the palette and the color index.
+/// The materials get matte PBR defaults, which can then be tweaked in the material editor and saved.
+/// Returns the number of registered materials, colors not fitting in the registry are skipped.
+pub fn import_palette_materials(
+    registry: &mut VoxelMaterialRegistry,
+    palette_name: &str,
+    colors: &[Color],
+) -> usize {
+    colors
+        .iter()
+        .enumerate()
+        .take_while(|(index, color)| {
+            registry
+                .register_at_runtime(MaterialRegistryInfo {
+                    name: format!("{} {}", palette_name, index).into(),
+                    base_color: **color,
+                    flags: VoxelMaterialFlags::SOLID,
+                    perceptual_roughness: 0.8,
+                    metallic: 0.0,
+                    reflectance: 0.5,
+                    ..Default::default()
+                })
+                .is_some()
+        })
+        .count()
+}
+
+/// Palette to import materials from at startup.
+pub struct PaletteImport(pub PathBuf);
+
+fn import_palette(import: Option<Res<PaletteImport>>, mut registry: ResMut<VoxelMaterialRegistry>) {
+    let path = match import {
+        Some(import) => import.0.clone(),
+        None => return,
+    };
+
+    let palette_name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Palette".to_string());
+
+    match read_palette(&path) {
+        Ok(colors) => {
+            let imported = import_palette_materials(&mut registry, &palette_name, &colors);
+            info!(
+                "Imported {} of the {} colors of palette {:?} as materials",
+                imported,
+                colors.len(),
+                path
+            );
+        }
+        Err(err) => error!("Failed to import palette {:?}: {}", path, err),
+    }
+}
+
+/// Imports the materials of the [`PaletteImport`] palette, if any.
+pub struct PaletteImportPlugin;
+
+impl Plugin for PaletteImportPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_startup_system(import_palette);
+    }
+}
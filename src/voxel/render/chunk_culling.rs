@@ -0,0 +1,406 @@
+use bevy::{
+    core_pipeline::core_3d::AlphaMask3d,
+    math::{Mat4, Vec3, Vec4},
+    pbr::MeshUniform,
+    prelude::{
+        info, AssetServer, Commands, Entity, FromWorld, Plugin, Query, Res, ResMut, With, Without,
+        World,
+    },
+    render::{
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext},
+        render_phase::RenderPhase,
+        render_resource::{
+            BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
+            BufferBindingType, BufferDescriptor, BufferUsages, CachedComputePipelineId,
+            ComputePassDescriptor, ComputePipelineDescriptor, PipelineCache, ShaderStages,
+            ShaderType, StorageBuffer,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        settings::WgpuLimits,
+        view::{ExtractedView, NoFrustumCulling},
+        Extract, RenderApp, RenderStage,
+    },
+    utils::{HashMap, HashSet},
+};
+
+use super::{chunk_mesh_buffers::ChunkMeshBuffers, PooledChunkMesh, VoxelTerrainMesh};
+use crate::voxel::CHUNK_SIZE;
+
+/// Path of the chunk culling compute shader, relative to the assets folder.
+const CHUNK_CULLING_SHADER_PATH: &str = "shaders/chunk_culling.wgsl";
+
+/// Number of invocations of the workgroups of the culling compute shader, one invocation per chunk.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Size of the indexed indirect draw arguments of a chunk.
+const DRAW_INDEXED_INDIRECT_SIZE: u64 = 20;
+
+/// Selects the GPU frustum culling of the chunks with a [`PooledChunkMesh`]: the chunk bounds are tested against the
+/// frustum of each view by a compute shader writing the indirect draws of the chunks, instead of the CPU frustum
+/// culling of the chunk entities.
+pub struct GpuChunkCulling {
+    pub enabled: bool,
+    supported: bool,
+}
+
+#[allow(dead_code)]
+impl GpuChunkCulling {
+    /// Returns whether the graphics adapter supports culling the chunks with compute shaders.
+    pub fn is_supported(&self) -> bool {
+        self.supported
+    }
+
+    /// Returns whether the chunks are culled on the GPU.
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.supported
+    }
+}
+
+/// Returns whether the limits of the device allow running the culling compute shader.
+fn chunk_culling_supported(limits: &WgpuLimits) -> bool {
+    limits.max_compute_workgroups_per_dimension > 0
+        && limits.max_compute_invocations_per_workgroup >= WORKGROUP_SIZE
+        && limits.max_storage_buffers_per_shader_stage >= 3
+}
+
+/// Opts the chunks culled on the GPU out of the CPU frustum culling, so that they all get their mesh uniform extracted.
+fn update_chunk_frustum_culling(
+    mut commands: Commands,
+    settings: Res<GpuChunkCulling>,
+    cpu_culled: Query<Entity, (With<PooledChunkMesh>, Without<NoFrustumCulling>)>,
+    gpu_culled: Query<
+        (Entity, Option<&PooledChunkMesh>),
+        (With<VoxelTerrainMesh>, With<NoFrustumCulling>),
+    >,
+) {
+    let active = settings.is_active();
+    if active {
+        cpu_culled.for_each(|entity| {
+            commands.entity(entity).insert(NoFrustumCulling);
+        });
+    }
+
+    // chunks meshed on the GPU or through mesh assets are culled on the CPU again.
+    gpu_culled.for_each(|(entity, pooled_mesh)| {
+        if !active || pooled_mesh.is_none() {
+            commands.entity(entity).remove::<NoFrustumCulling>();
+        }
+    });
+}
+
+/// Bounds and index count of a chunk, as read by the culling compute shader.
+#[derive(ShaderType, Clone, Copy)]
+struct GpuCulledChunk {
+    center: Vec3,
+    index_count: u32,
+    extents: Vec3,
+}
+
+#[derive(ShaderType, Default)]
+struct GpuCulledChunks {
+    #[size(runtime)]
+    chunks: Vec<GpuCulledChunk>,
+}
+
+/// Frustum of a view, as read by the culling compute shader.
+#[derive(ShaderType, Default)]
+struct GpuCullingView {
+    planes: [Vec4; 5],
+    chunk_count: u32,
+}
+
+/// Returns the left, right, bottom, top and near planes of the frustum of a reversed Z view projection, the far plane
+/// being at infinity.
+fn frustum_planes(view_projection: Mat4) -> [Vec4; 5] {
+    let row3 = view_projection.row(3);
+    [
+        row3 + view_projection.row(0),
+        row3 - view_projection.row(0),
+        row3 + view_projection.row(1),
+        row3 - view_projection.row(1),
+        row3 - view_projection.row(2),
+    ]
+}
+
+struct ChunkCullingPipeline {
+    bind_group_layout: BindGroupLayout,
+    pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for ChunkCullingPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let storage_entry = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout =
+            world
+                .resource::<RenderDevice>()
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: Some("chunk_culling_layout"),
+                    entries: &[
+                        storage_entry(0, true),
+                        storage_entry(1, true),
+                        storage_entry(2, false),
+                    ],
+                });
+
+        let shader = world
+            .resource::<AssetServer>()
+            .load(CHUNK_CULLING_SHADER_PATH);
+        let pipeline = world
+            .resource_mut::<PipelineCache>()
+            .queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("chunk_culling_pipeline".into()),
+                layout: Some(vec![bind_group_layout.clone()]),
+                shader,
+                shader_defs: Vec::new(),
+                entry_point: "cull_chunks".into(),
+            });
+
+        Self {
+            bind_group_layout,
+            pipeline,
+        }
+    }
+}
+
+/// The culling buffers of a view.
+#[derive(Default)]
+struct ViewChunkCulling {
+    frustum: StorageBuffer<GpuCullingView>,
+    /// Indexed indirect draw arguments of the culled chunks, by slot, along with their capacity in chunks.
+    indirect: Option<(Buffer, u32)>,
+    bind_group: Option<BindGroup>,
+}
+
+/// The chunks culled on the GPU this frame and the culling buffers of the views.
+#[derive(Default)]
+pub(super) struct ChunkCulling {
+    active: bool,
+    chunks: StorageBuffer<GpuCulledChunks>,
+    /// Slot of the culled chunks in the chunks buffer and in the indirect draw buffers of the views.
+    slots: HashMap<Entity, u32>,
+    views: HashMap<Entity, ViewChunkCulling>,
+}
+
+impl ChunkCulling {
+    /// Returns the indirect draw buffer of a view along with the offset of the draw arguments of a chunk, `None` if the
+    /// chunk isn't culled on the GPU for this view.
+    pub fn indirect_draw(&self, view: Entity, chunk: Entity) -> Option<(&Buffer, u64)> {
+        let slot = self.slots.get(&chunk)?;
+        let (indirect, _) = self.views.get(&view)?.indirect.as_ref()?;
+        Some((indirect, *slot as u64 * DRAW_INDEXED_INDIRECT_SIZE))
+    }
+}
+
+fn extract_chunk_culling(
+    settings: Extract<Res<GpuChunkCulling>>,
+    mut culling: ResMut<ChunkCulling>,
+) {
+    culling.active = settings.is_active();
+}
+
+/// Assigns the chunk slots and writes the chunk bounds and the view frustums the culling compute shader reads.
+#[allow(clippy::too_many_arguments)]
+fn queue_chunk_culling(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    pipeline: Res<ChunkCullingPipeline>,
+    pipeline_cache: Res<PipelineCache>,
+    buffers: Res<ChunkMeshBuffers>,
+    mesh_uniforms: Query<&MeshUniform>,
+    views: Query<(Entity, &ExtractedView), With<RenderPhase<AlphaMask3d>>>,
+    mut culling: ResMut<ChunkCulling>,
+) {
+    let culling = &mut *culling;
+    culling.slots.clear();
+
+    // the chunks are drawn directly until the compute pipeline is ready.
+    if !culling.active
+        || pipeline_cache
+            .get_compute_pipeline(pipeline.pipeline)
+            .is_none()
+    {
+        culling.views.clear();
+        return;
+    }
+
+    let half_size = CHUNK_SIZE.as_vec3() * 0.5;
+    let mut chunks = Vec::with_capacity(buffers.0.len());
+    for (entity, chunk_buffers) in buffers.0.iter() {
+        if chunk_buffers.index_count == 0 {
+            continue;
+        }
+        if let Ok(mesh_uniform) = mesh_uniforms.get(*entity) {
+            // bounds of the transformed chunk, e.g. while its appearance is animated.
+            let transform = mesh_uniform.transform;
+            culling.slots.insert(*entity, chunks.len() as u32);
+            chunks.push(GpuCulledChunk {
+                center: transform.transform_point3(half_size),
+                index_count: chunk_buffers.index_count,
+                extents: transform.x_axis.truncate().abs() * half_size.x
+                    + transform.y_axis.truncate().abs() * half_size.y
+                    + transform.z_axis.truncate().abs() * half_size.z,
+            });
+        }
+    }
+
+    if chunks.is_empty() {
+        culling.views.clear();
+        return;
+    }
+
+    let chunk_count = chunks.len() as u32;
+    culling.chunks.set(GpuCulledChunks { chunks });
+    culling.chunks.write_buffer(&render_device, &render_queue);
+
+    let alive: HashSet<Entity> = views.iter().map(|(entity, _)| entity).collect();
+    culling.views.retain(|entity, _| alive.contains(entity));
+
+    for (entity, view) in views.iter() {
+        let view_culling = culling.views.entry(entity).or_default();
+        view_culling.frustum.set(GpuCullingView {
+            planes: frustum_planes(view.projection * view.transform.compute_matrix().inverse()),
+            chunk_count,
+        });
+        view_culling
+            .frustum
+            .write_buffer(&render_device, &render_queue);
+
+        if !matches!(view_culling.indirect, Some((_, capacity)) if capacity >= chunk_count) {
+            // rounded up so that the buffer doesn't get reallocated for every loaded chunk.
+            let capacity = chunk_count.next_power_of_two();
+            view_culling.indirect = Some((
+                render_device.create_buffer(&BufferDescriptor {
+                    label: Some("chunk_culling_indirect_buffer"),
+                    size: capacity as u64 * DRAW_INDEXED_INDIRECT_SIZE,
+                    usage: BufferUsages::INDIRECT | BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                }),
+                capacity,
+            ));
+        }
+
+        view_culling.bind_group = Some(
+            render_device.create_bind_group(&BindGroupDescriptor {
+                label: Some("chunk_culling_bind_group"),
+                layout: &pipeline.bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: culling.chunks.binding().unwrap(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: view_culling.frustum.binding().unwrap(),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: view_culling
+                            .indirect
+                            .as_ref()
+                            .unwrap()
+                            .0
+                            .as_entire_binding(),
+                    },
+                ],
+            }),
+        );
+    }
+}
+
+/// Dispatches the culling compute shader over the chunks for each view, before the cameras render.
+struct ChunkCullingNode;
+
+impl Node for ChunkCullingNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let culling = world.resource::<ChunkCulling>();
+        if culling.slots.is_empty() {
+            return Ok(());
+        }
+
+        let pipeline = match world
+            .resource::<PipelineCache>()
+            .get_compute_pipeline(world.resource::<ChunkCullingPipeline>().pipeline)
+        {
+            Some(pipeline) => pipeline,
+            None => return Ok(()),
+        };
+
+        let mut pass = render_context
+            .command_encoder
+            .begin_compute_pass(&ComputePassDescriptor {
+                label: Some("chunk_culling_pass"),
+            });
+        pass.set_pipeline(pipeline);
+
+        let workgroups = (culling.slots.len() as u32 + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+        for view_culling in culling.views.values() {
+            if let Some(bind_group) = &view_culling.bind_group {
+                pass.set_bind_group(0, bind_group, &[]);
+                pass.dispatch_workgroups(workgroups, 1, 1);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Label of the render graph node dispatching the culling compute shader.
+const CHUNK_CULLING_NODE: &str = "chunk_culling";
+
+/// Optional GPU frustum culling of the pooled chunk meshes, see [`GpuChunkCulling`].
+/// The culling is only enabled on graphics adapters supporting compute shaders.
+pub struct ChunkCullingPlugin;
+
+impl Plugin for ChunkCullingPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let supported = app
+            .world
+            .get_resource::<RenderDevice>()
+            .map_or(false, |device| chunk_culling_supported(&device.limits()));
+
+        app.insert_resource(GpuChunkCulling {
+            enabled: true,
+            supported,
+        })
+        .add_system(update_chunk_frustum_culling);
+
+        // the pooled chunk meshes read the culling results when drawn, even without GPU culling.
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app.init_resource::<ChunkCulling>();
+
+        if !supported {
+            info!("Compute shaders aren't supported, chunks are frustum culled on the CPU");
+            return;
+        }
+
+        render_app
+            .init_resource::<ChunkCullingPipeline>()
+            .add_system_to_stage(RenderStage::Extract, extract_chunk_culling)
+            .add_system_to_stage(RenderStage::Queue, queue_chunk_culling);
+
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(CHUNK_CULLING_NODE, ChunkCullingNode);
+        graph
+            .add_node_edge(
+                CHUNK_CULLING_NODE,
+                bevy::render::main_graph::node::CAMERA_DRIVER,
+            )
+            .unwrap();
+    }
+}
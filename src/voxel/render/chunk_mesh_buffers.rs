@@ -0,0 +1,342 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use bevy::{
+    core_pipeline::core_3d::AlphaMask3d,
+    ecs::system::{lifetimeless::SRes, SystemParamItem},
+    pbr::{MeshPipelineKey, MeshUniform, SetMeshBindGroup, SetMeshViewBindGroup},
+    prelude::{Component, Entity, Mesh, Msaa, Plugin, Query, Res, ResMut},
+    render::{
+        mesh::{Indices, MeshVertexBufferLayout},
+        render_phase::{
+            AddRenderCommand, DrawFunctions, EntityRenderCommand, RenderCommandResult, RenderPhase,
+            SetItemPipeline, TrackedRenderPass,
+        },
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, IndexFormat, PipelineCache, PrimitiveTopology,
+            SpecializedMeshPipelines,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        view::ExtractedView,
+        Extract, RenderApp, RenderStage,
+    },
+    utils::{HashMap, HashSet},
+};
+
+use super::{
+    chunk_culling::ChunkCulling, SetTerrainUniformsBindGroup, ViewRenderDistance, VoxelTerrainMesh,
+    VoxelTerrainRenderPipeline,
+};
+
+static NEXT_POOLED_CHUNK_MESH_REVISION: AtomicU64 = AtomicU64::new(0);
+
+/// Settings of the reuse of the GPU buffers of the chunk meshes. When enabled, the CPU meshes of the chunks are
+/// written in place into the vertex and index buffers of the chunk instead of replacing its mesh asset, which would
+/// reallocate the buffers on every remesh.
+pub struct ChunkMeshBufferReuse {
+    pub enabled: bool,
+    /// Extra capacity (as a fraction of the mesh size) the buffers get when (re)allocated, so that the meshes growing
+    /// a bit after an edit still fit.
+    pub headroom: f32,
+}
+
+impl Default for ChunkMeshBufferReuse {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            headroom: 0.25,
+        }
+    }
+}
+
+/// The vertices and indices of a chunk meshed on the CPU, written into the GPU buffers of the chunk in the render world.
+/// The mesh asset of the chunk is a placeholder while the chunk has this component.
+#[derive(Component, Clone)]
+pub struct PooledChunkMesh {
+    /// The interleaved vertex attributes, laid out like the mesh asset they got taken from.
+    vertices: Arc<Vec<u8>>,
+    indices: Arc<Vec<u32>>,
+    /// Identifies this version of the mesh, so that it only gets uploaded once.
+    revision: u64,
+}
+
+impl PooledChunkMesh {
+    /// Takes the vertices and indices of a chunk mesh, `None` if the mesh isn't laid out like the terrain meshes.
+    pub fn new(mesh: &Mesh) -> Option<Self> {
+        if mesh.get_mesh_vertex_buffer_layout()
+            != VoxelTerrainMesh::placeholder_mesh().get_mesh_vertex_buffer_layout()
+        {
+            return None;
+        }
+        let indices = match mesh.indices() {
+            Some(Indices::U32(indices)) => indices.clone(),
+            _ => return None,
+        };
+
+        Some(Self {
+            vertices: Arc::new(mesh.get_vertex_buffer_data()),
+            indices: Arc::new(indices),
+            revision: NEXT_POOLED_CHUNK_MESH_REVISION.fetch_add(1, Ordering::Relaxed),
+        })
+    }
+
+    /// Returns the packed [`VoxelTerrainMesh::ATTRIBUTE_DATA`] of the vertices.
+    pub fn vertex_data(&self) -> Vec<u32> {
+        // the attributes are interleaved in the order of their ids, the data attribute coming first.
+        let stride = VoxelTerrainMesh::ATTRIBUTE_DATA.format.size()
+            + VoxelTerrainMesh::ATTRIBUTE_LIGHT.format.size();
+        self.vertices
+            .chunks_exact(stride as usize)
+            .map(|vertex| u32::from_le_bytes([vertex[0], vertex[1], vertex[2], vertex[3]]))
+            .collect()
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+}
+
+/// The vertex layout of the terrain meshes, shared by all the pooled chunk meshes.
+struct PooledChunkMeshLayout(MeshVertexBufferLayout);
+
+impl Default for PooledChunkMeshLayout {
+    fn default() -> Self {
+        Self(VoxelTerrainMesh::placeholder_mesh().get_mesh_vertex_buffer_layout())
+    }
+}
+
+/// A GPU buffer written in place as long as the data fits in its capacity.
+pub(super) struct GrowableBuffer {
+    pub buffer: Buffer,
+    capacity: u64,
+}
+
+impl GrowableBuffer {
+    /// Writes the data into the buffer, reallocating it with some headroom when the data doesn't fit.
+    fn write(
+        buffer: &mut Option<Self>,
+        data: &[u8],
+        usage: BufferUsages,
+        headroom: f32,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+    ) {
+        let size = data.len() as u64;
+        if !matches!(buffer, Some(buffer) if buffer.capacity >= size) {
+            // buffer sizes are kept multiples of 4 bytes, as required by the buffer copies.
+            let capacity = ((size as f32 * (1.0 + headroom.max(0.0))).ceil() as u64)
+                .max(size)
+                .max(4);
+            let capacity = (capacity + 3) & !3;
+            *buffer = Some(Self {
+                buffer: render_device.create_buffer(&BufferDescriptor {
+                    label: Some("pooled_chunk_mesh_buffer"),
+                    size: capacity,
+                    usage: usage | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                capacity,
+            });
+        }
+
+        if !data.is_empty() {
+            render_queue.write_buffer(&buffer.as_ref().unwrap().buffer, 0, data);
+        }
+    }
+}
+
+/// The vertex and index buffers of the chunks with a [`PooledChunkMesh`], indexed by chunk entity.
+#[derive(Default)]
+pub(super) struct ChunkMeshBuffers(pub HashMap<Entity, PooledChunkMeshBuffers>);
+
+pub(super) struct PooledChunkMeshBuffers {
+    revision: u64,
+    pub vertices: GrowableBuffer,
+    pub indices: GrowableBuffer,
+    pub index_count: u32,
+}
+
+/// The chunks with a pooled mesh this frame, along with the ones whose mesh needs an upload.
+#[derive(Default)]
+struct ExtractedPooledChunkMeshes {
+    alive: HashSet<Entity>,
+    uploads: Vec<(Entity, PooledChunkMesh)>,
+    headroom: f32,
+}
+
+fn extract_pooled_chunk_meshes(
+    settings: Extract<Res<ChunkMeshBufferReuse>>,
+    chunks: Extract<Query<(Entity, &PooledChunkMesh)>>,
+    buffers: Res<ChunkMeshBuffers>,
+    mut extracted: ResMut<ExtractedPooledChunkMeshes>,
+) {
+    extracted.alive.clear();
+    extracted.uploads.clear();
+    extracted.headroom = settings.headroom;
+
+    for (entity, mesh) in chunks.iter() {
+        extracted.alive.insert(entity);
+        if buffers
+            .0
+            .get(&entity)
+            .map_or(true, |buffers| buffers.revision != mesh.revision)
+        {
+            extracted.uploads.push((entity, mesh.clone()));
+        }
+    }
+}
+
+/// Writes the meshes of the remeshed chunks into their buffers, only reallocating the buffers they outgrew.
+fn prepare_pooled_chunk_meshes(
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    extracted: Res<ExtractedPooledChunkMeshes>,
+    mut buffers: ResMut<ChunkMeshBuffers>,
+) {
+    buffers
+        .0
+        .retain(|entity, _| extracted.alive.contains(entity));
+
+    for (entity, mesh) in extracted.uploads.iter() {
+        let (mut vertices, mut indices) = match buffers.0.remove(entity) {
+            Some(buffers) => (Some(buffers.vertices), Some(buffers.indices)),
+            None => (None, None),
+        };
+
+        GrowableBuffer::write(
+            &mut vertices,
+            &mesh.vertices,
+            BufferUsages::VERTEX,
+            extracted.headroom,
+            &render_device,
+            &render_queue,
+        );
+        GrowableBuffer::write(
+            &mut indices,
+            &mesh
+                .indices
+                .iter()
+                .flat_map(|index| index.to_le_bytes())
+                .collect::<Vec<u8>>(),
+            BufferUsages::INDEX,
+            extracted.headroom,
+            &render_device,
+            &render_queue,
+        );
+
+        buffers.0.insert(
+            *entity,
+            PooledChunkMeshBuffers {
+                revision: mesh.revision,
+                vertices: vertices.unwrap(),
+                indices: indices.unwrap(),
+                index_count: mesh.indices.len() as u32,
+            },
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_pooled_chunk_meshes(
+    draw_functions: Res<DrawFunctions<AlphaMask3d>>,
+    voxel_pipeline: Res<VoxelTerrainRenderPipeline>,
+    layout: Res<PooledChunkMeshLayout>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    mut specialized_pipelines: ResMut<SpecializedMeshPipelines<VoxelTerrainRenderPipeline>>,
+    msaa: Res<Msaa>,
+    buffers: Res<ChunkMeshBuffers>,
+    mesh_uniforms: Query<&MeshUniform>,
+    mut views: Query<(
+        &ExtractedView,
+        &mut RenderPhase<AlphaMask3d>,
+        Option<&ViewRenderDistance>,
+    )>,
+) {
+    let draw_function = draw_functions.read().get_id::<DrawPooledVoxel>().unwrap();
+    let key = MeshPipelineKey::from_msaa_samples(msaa.samples)
+        | MeshPipelineKey::from_primitive_topology(PrimitiveTopology::TriangleList);
+    let pipeline = specialized_pipelines
+        .specialize(&mut pipeline_cache, &voxel_pipeline, key, &layout.0)
+        .unwrap();
+
+    for (view, mut phase, render_distance) in views.iter_mut() {
+        let view_row_2 = view.transform.compute_matrix().row(2);
+
+        // only the visible chunks got their mesh uniform extracted.
+        for (entity, chunk_buffers) in buffers.0.iter() {
+            if chunk_buffers.index_count == 0 {
+                continue;
+            }
+            if let Ok(mesh_uniform) = mesh_uniforms.get(*entity) {
+                let chunk_min = mesh_uniform.transform.col(3).truncate();
+                if !render_distance.map_or(true, |distance| {
+                    distance.contains_chunk(view.transform.translation(), chunk_min)
+                }) {
+                    continue;
+                }
+                phase.add(AlphaMask3d {
+                    entity: *entity,
+                    pipeline,
+                    draw_function,
+                    distance: view_row_2.dot(mesh_uniform.transform.col(3)),
+                });
+            }
+        }
+    }
+}
+
+/// Draws the vertices written into the buffers of a chunk.
+struct DrawPooledChunkMesh;
+
+impl EntityRenderCommand for DrawPooledChunkMesh {
+    type Param = (SRes<ChunkMeshBuffers>, SRes<ChunkCulling>);
+
+    fn render<'w>(
+        view: Entity,
+        item: Entity,
+        (buffers, culling): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        match buffers.into_inner().0.get(&item) {
+            Some(buffers) => {
+                pass.set_vertex_buffer(0, buffers.vertices.buffer.slice(..));
+                pass.set_index_buffer(buffers.indices.buffer.slice(..), 0, IndexFormat::Uint32);
+                // the chunks culled on the GPU draw the index count written by the culling pass of the view.
+                match culling.into_inner().indirect_draw(view, item) {
+                    Some((indirect, offset)) => pass.draw_indexed_indirect(indirect, offset),
+                    None => pass.draw_indexed(0..buffers.index_count, 0, 0..1),
+                }
+                RenderCommandResult::Success
+            }
+            None => RenderCommandResult::Failure,
+        }
+    }
+}
+
+type DrawPooledVoxel = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    SetTerrainUniformsBindGroup<2>,
+    DrawPooledChunkMesh,
+);
+
+/// Reuse of the GPU buffers of the chunk meshes across remeshes, see [`ChunkMeshBufferReuse`].
+pub struct ChunkMeshBuffersPlugin;
+
+impl Plugin for ChunkMeshBuffersPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkMeshBufferReuse>();
+
+        app.sub_app_mut(RenderApp)
+            .init_resource::<PooledChunkMeshLayout>()
+            .init_resource::<ChunkMeshBuffers>()
+            .init_resource::<ExtractedPooledChunkMeshes>()
+            .add_render_command::<AlphaMask3d, DrawPooledVoxel>()
+            .add_system_to_stage(RenderStage::Extract, extract_pooled_chunk_meshes)
+            .add_system_to_stage(RenderStage::Prepare, prepare_pooled_chunk_meshes)
+            .add_system_to_stage(RenderStage::Queue, queue_pooled_chunk_meshes);
+    }
+}
@@ -0,0 +1,68 @@
+use bevy::{
+    math::{IVec3, Vec3},
+    prelude::Mesh,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+
+/// Builds the surface mesh of partially filled fluid voxels, which can't be represented by the voxel terrain meshes.
+/// `voxels` lists the chunk local position of each fluid voxel along its fluid height (from 0 to 1).
+/// `neighbor_height` returns the fluid height of the voxel at a chunk local position, 0 for empty voxels,
+/// or `None` for voxels hiding the fluid faces touching them (solid voxels, full fluid voxels).
+pub fn mesh_fluid_voxels(
+    voxels: &[(IVec3, f32)],
+    neighbor_height: impl Fn(IVec3) -> Option<f32>,
+) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+
+    // corners are `origin`, `origin + u`, `origin + u + v` and `origin + v`, counter clockwise seen from the normal side.
+    let mut push_quad = |origin: Vec3, u: Vec3, v: Vec3, normal: Vec3| {
+        let first = positions.len() as u32;
+        positions.extend(
+            [origin, origin + u, origin + u + v, origin + v].map(|corner| corner.to_array()),
+        );
+        normals.extend([normal.to_array(); 4]);
+        indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    };
+
+    for (pos, height) in voxels.iter().copied() {
+        let base = pos.as_vec3();
+
+        if height < 1.0 || neighbor_height(pos + IVec3::Y) == Some(0.0) {
+            push_quad(base + Vec3::Y * height, Vec3::Z, Vec3::X, Vec3::Y);
+        }
+
+        if neighbor_height(pos - IVec3::Y) == Some(0.0) {
+            push_quad(base, Vec3::X, Vec3::Z, Vec3::NEG_Y);
+        }
+
+        // the sides are drawn down to the fluid surface of the neighbors.
+        for (normal, offset, side_first, edge) in [
+            (IVec3::X, Vec3::X, true, Vec3::Z),
+            (IVec3::NEG_X, Vec3::ZERO, false, Vec3::Z),
+            (IVec3::Z, Vec3::Z, false, Vec3::X),
+            (IVec3::NEG_Z, Vec3::ZERO, true, Vec3::X),
+        ] {
+            let bottom = match neighbor_height(pos + normal) {
+                Some(neighbor) if neighbor < height => neighbor,
+                _ => continue,
+            };
+
+            let origin = base + offset + Vec3::Y * bottom;
+            let side = Vec3::Y * (height - bottom);
+            if side_first {
+                push_quad(origin, side, edge, normal.as_vec3());
+            } else {
+                push_quad(origin, edge, side, normal.as_vec3());
+            }
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
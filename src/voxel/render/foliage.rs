@@ -0,0 +1,176 @@
+use bevy::{
+    math::{IVec3, Vec2, Vec3},
+    pbr::{MaterialPipeline, MaterialPipelineKey},
+    prelude::{
+        AlphaMode, Assets, Color, Component, Handle, Material, MaterialPlugin, Mesh, Plugin, Res,
+        ResMut, Time,
+    },
+    reflect::TypeUuid,
+    render::{
+        mesh::{Indices, MeshVertexBufferLayout},
+        render_resource::{
+            AsBindGroup, PrimitiveTopology, RenderPipelineDescriptor, ShaderRef, ShaderType,
+            SpecializedMeshPipelineError,
+        },
+    },
+};
+
+use crate::voxel::Light;
+
+/// Path of the shader drawing the foliage, swaying its top with the wind.
+const FOLIAGE_SHADER_PATH: &str = "shaders/foliage.wgsl";
+
+/// Parameters of the wind swaying the foliage.
+#[derive(Clone, Copy, ShaderType)]
+pub struct FoliageWind {
+    /// Seconds since startup, animating the sway.
+    pub time: f32,
+    /// Maximum horizontal displacement of the top of the foliage, in voxels.
+    pub strength: f32,
+    /// Horizontal (XZ) direction of the wind.
+    pub direction: Vec2,
+}
+
+impl Default for FoliageWind {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            strength: 0.12,
+            direction: Vec2::new(0.8, 0.6),
+        }
+    }
+}
+
+/// Material of the crossed quads of the foliage voxels, alpha tested into blades and swaying with the wind in the vertex shader.
+#[derive(AsBindGroup, TypeUuid, Clone, Default)]
+#[uuid = "8d3b5a4e-2c71-4f9a-b6e0-5f1c9d7a3e24"]
+pub struct FoliageMaterial {
+    #[uniform(0)]
+    pub wind: FoliageWind,
+}
+
+impl Material for FoliageMaterial {
+    fn vertex_shader() -> ShaderRef {
+        FOLIAGE_SHADER_PATH.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        FOLIAGE_SHADER_PATH.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Mask(0.5)
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.vertex.buffers = vec![layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(3),
+        ])?];
+        // the quads are seen from both sides.
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+/// The material shared by all the foliage meshes.
+pub struct FoliageMaterialHandle(pub Handle<FoliageMaterial>);
+
+/// Tags the entities holding the foliage mesh of a chunk.
+#[derive(Component)]
+pub struct FoliageMesh;
+
+/// Returns the color of the light received by a voxel, matching the light falloff of the terrain shader.
+fn light_color(light: Light) -> [f32; 3] {
+    let sun = 0.8f32.powi((Light::MAX_LEVEL - light.sun()) as i32);
+    let block = match light.block() {
+        0 => 0.0,
+        level => 0.8f32.powi((Light::MAX_LEVEL - level) as i32),
+    };
+    light.block_color().map(|channel| sun.max(block * channel))
+}
+
+/// Builds the mesh of foliage voxels (grass tufts, flowers), two crossed quads per voxel.
+/// `voxels` lists the chunk local position of each foliage voxel along its color and the light it receives.
+pub fn mesh_foliage(voxels: &[(IVec3, Color, Light)]) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = Vec::with_capacity(voxels.len() * 8);
+    let mut normals: Vec<[f32; 3]> = Vec::with_capacity(voxels.len() * 8);
+    let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(voxels.len() * 8);
+    let mut colors: Vec<[f32; 4]> = Vec::with_capacity(voxels.len() * 8);
+    let mut indices: Vec<u32> = Vec::with_capacity(voxels.len() * 12);
+
+    // the quads span the diagonals of the voxel, slightly inset to not poke through the neighbors.
+    const INSET: f32 = 0.15;
+    let diagonals = [
+        (
+            Vec3::new(INSET, 0.0, INSET),
+            Vec3::new(1.0 - INSET, 0.0, 1.0 - INSET),
+        ),
+        (
+            Vec3::new(INSET, 0.0, 1.0 - INSET),
+            Vec3::new(1.0 - INSET, 0.0, INSET),
+        ),
+    ];
+
+    for (pos, color, light) in voxels.iter().copied() {
+        let base = pos.as_vec3();
+        let [r, g, b] = light_color(light);
+        let color = [color.r() * r, color.g() * g, color.b() * b, 1.0];
+
+        for (start, end) in diagonals {
+            let first = positions.len() as u32;
+            positions.extend(
+                [start, end, end + Vec3::Y, start + Vec3::Y]
+                    .map(|corner| (base + corner).to_array()),
+            );
+            // the foliage is lit as if facing the sky from both sides.
+            normals.extend([[0.0, 1.0, 0.0]; 4]);
+            // the vertex shader sways the vertices along their height, read from the V coordinate.
+            uvs.extend([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+            colors.extend([color; 4]);
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+/// Advances the wind animation of the foliage material.
+fn animate_foliage_wind(
+    time: Res<Time>,
+    handle: Res<FoliageMaterialHandle>,
+    mut materials: ResMut<Assets<FoliageMaterial>>,
+) {
+    if let Some(material) = materials.get_mut(&handle.0) {
+        material.wind.time = time.seconds_since_startup() as f32;
+    }
+}
+
+/// Registers the foliage material and animates its wind.
+pub struct FoliageRenderPlugin;
+
+impl Plugin for FoliageRenderPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_plugin(MaterialPlugin::<FoliageMaterial>::default());
+
+        let handle = app
+            .world
+            .resource_mut::<Assets<FoliageMaterial>>()
+            .add(FoliageMaterial::default());
+        app.insert_resource(FoliageMaterialHandle(handle))
+            .add_system(animate_foliage_wind);
+    }
+}
@@ -0,0 +1,524 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+
+use bevy::{
+    core_pipeline::core_3d::AlphaMask3d,
+    ecs::system::{lifetimeless::SRes, SystemParamItem},
+    math::IVec3,
+    pbr::{MeshPipelineKey, MeshUniform, SetMeshBindGroup, SetMeshViewBindGroup},
+    prelude::{
+        info, AssetServer, Component, Entity, FromWorld, Mesh, Msaa, Plugin, Query, Res, ResMut,
+        World,
+    },
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext},
+        render_phase::{
+            AddRenderCommand, DrawFunctions, EntityRenderCommand, RenderCommandResult, RenderPhase,
+            SetItemPipeline, TrackedRenderPass,
+        },
+        render_resource::{
+            BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, Buffer,
+            BufferBindingType, BufferDescriptor, BufferInitDescriptor, BufferUsages,
+            CachedComputePipelineId, ComputePassDescriptor, ComputePipelineDescriptor,
+            PipelineCache, PrimitiveTopology, ShaderStages, SpecializedMeshPipelines,
+        },
+        renderer::{RenderContext, RenderDevice},
+        settings::WgpuLimits,
+        view::ExtractedView,
+        Extract, RenderApp, RenderStage,
+    },
+    utils::{HashMap, HashSet},
+};
+use ndshape::Shape;
+
+use super::{
+    SetTerrainUniformsBindGroup, ViewRenderDistance, VoxelTerrainMesh, VoxelTerrainRenderPipeline,
+};
+use crate::voxel::{
+    storage::VoxelBuffer, Light, PaddedChunkShape, Voxel, CHUNK_HEIGHT, CHUNK_LENGTH,
+};
+
+/// Path of the chunk meshing compute shader, relative to the assets folder.
+const GPU_MESHING_SHADER_PATH: &str = "shaders/gpu_meshing.wgsl";
+
+/// Number of invocations along each axis of the workgroups of the meshing compute shader, one invocation per voxel.
+const WORKGROUP_SIZE: u32 = 4;
+
+/// Voxel faces are emitted as two triangles without index buffer.
+const VERTICES_PER_FACE: u64 = 6;
+
+/// Size of an emitted vertex: packed voxel data followed by the packed light.
+const VERTEX_SIZE: u64 = 8;
+
+/// Normals of the voxel faces, in the same order as the normal indices of the vertex data.
+const FACE_NORMALS: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::NEG_Y,
+    IVec3::NEG_Z,
+    IVec3::X,
+    IVec3::Y,
+    IVec3::Z,
+];
+
+static NEXT_GPU_CHUNK_MESH_REVISION: AtomicU64 = AtomicU64::new(0);
+
+/// Selects the GPU meshing backend, where chunk vertices are emitted by a compute shader instead of being meshed on the CPU.
+/// Only the freshly generated chunks (pristine ones) are meshed on the GPU, the other chunks keep getting greedy meshed on the CPU.
+pub struct GpuMeshing {
+    pub enabled: bool,
+    supported: bool,
+}
+
+#[allow(dead_code)]
+impl GpuMeshing {
+    /// Returns whether the graphics adapter supports meshing chunks with compute shaders.
+    pub fn is_supported(&self) -> bool {
+        self.supported
+    }
+
+    /// Returns whether chunks get meshed on the GPU.
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.supported
+    }
+}
+
+/// Returns whether the limits of the device allow running the meshing compute shader.
+fn gpu_meshing_supported(limits: &WgpuLimits) -> bool {
+    limits.max_compute_workgroups_per_dimension > 0
+        && limits.max_compute_invocations_per_workgroup >= WORKGROUP_SIZE.pow(3)
+        && limits.max_storage_buffers_per_shader_stage >= 3
+}
+
+/// The voxel data of a chunk meshed on the GPU, uploaded to the render world so that a compute shader emits its vertices.
+#[derive(Component, Clone)]
+pub struct GpuChunkMesh {
+    /// The padded chunk dimensions followed by each padded chunk voxel and its light.
+    voxels: Arc<Vec<u32>>,
+    /// Number of visible faces of the chunk, the compute shader emits the same number of faces.
+    faces: u32,
+    /// Identifies this version of the chunk data, so that it only gets uploaded and meshed once.
+    revision: u64,
+}
+
+impl GpuChunkMesh {
+    /// Packs the voxels and light of a chunk padded with the bordering voxels of its neighbors for meshing on the GPU.
+    pub fn new(
+        padded_buffer: &VoxelBuffer<Voxel, PaddedChunkShape>,
+        padded_light: &VoxelBuffer<Light, PaddedChunkShape>,
+    ) -> Self {
+        let shape = padded_buffer.shape();
+        let extent = IVec3::from(shape.as_array().map(|axis| axis as i32));
+
+        let mut voxels = Vec::with_capacity(4 + 2 * shape.size() as usize);
+        voxels.extend_from_slice(&shape.as_array());
+        // the voxels start at the 8 bytes alignment of the `vec2<u32>` array of the shader.
+        voxels.push(0);
+        voxels.extend(
+            padded_buffer
+                .slice()
+                .iter()
+                .zip(padded_light.slice())
+                .flat_map(|(voxel, light)| [voxel.0 as u32, light.0]),
+        );
+
+        // faces of the solid voxels of the chunk facing an empty voxel, as done by the compute shader.
+        let mut faces = 0;
+        for z in 1..extent.z - 1 {
+            for y in 1..extent.y - 1 {
+                for x in 1..extent.x - 1 {
+                    let pos = IVec3::new(x, y, z);
+                    if padded_buffer.voxel_at(pos.as_uvec3()) == Voxel::EMPTY_VOXEL {
+                        continue;
+                    }
+
+                    faces += FACE_NORMALS
+                        .iter()
+                        .filter(|normal| {
+                            padded_buffer.voxel_at((pos + **normal).as_uvec3())
+                                == Voxel::EMPTY_VOXEL
+                        })
+                        .count() as u32;
+                }
+            }
+        }
+
+        Self {
+            voxels: Arc::new(voxels),
+            faces,
+            revision: NEXT_GPU_CHUNK_MESH_REVISION.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+/// The meshing compute pipeline, along with the vertex layout of the emitted vertices.
+struct GpuMeshingPipeline {
+    bind_group_layout: BindGroupLayout,
+    pipeline: CachedComputePipelineId,
+    vertex_layout: MeshVertexBufferLayout,
+}
+
+impl FromWorld for GpuMeshingPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let storage_entry = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout =
+            world
+                .resource::<RenderDevice>()
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: Some("gpu_meshing_layout"),
+                    entries: &[
+                        storage_entry(0, true),
+                        storage_entry(1, false),
+                        storage_entry(2, false),
+                    ],
+                });
+
+        let shader = world
+            .resource::<AssetServer>()
+            .load(GPU_MESHING_SHADER_PATH);
+        let pipeline = world
+            .resource_mut::<PipelineCache>()
+            .queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some("gpu_meshing_pipeline".into()),
+                layout: Some(vec![bind_group_layout.clone()]),
+                shader,
+                shader_defs: Vec::new(),
+                entry_point: "mesh_chunk".into(),
+            });
+
+        Self {
+            bind_group_layout,
+            pipeline,
+            vertex_layout: VoxelTerrainMesh::placeholder_mesh().get_mesh_vertex_buffer_layout(),
+        }
+    }
+}
+
+/// The vertices of the chunks meshed on the GPU, indexed by chunk entity.
+#[derive(Default)]
+struct GpuChunkMeshes(HashMap<Entity, GpuChunkMeshBuffers>);
+
+struct GpuChunkMeshBuffers {
+    revision: u64,
+    vertices: Buffer,
+    /// Indirect draw arguments, whose vertex count is written by the compute shader.
+    indirect: Buffer,
+}
+
+/// The chunks meshed on the GPU this frame, along with the ones whose voxel data needs an upload.
+#[derive(Default)]
+struct ExtractedGpuChunkMeshes {
+    alive: HashSet<Entity>,
+    uploads: Vec<(Entity, GpuChunkMesh)>,
+}
+
+/// A dispatch of the meshing compute shader over a chunk.
+struct GpuMeshingJob {
+    entity: Entity,
+    revision: u64,
+    bind_group: BindGroup,
+}
+
+/// The pending meshing compute shader dispatches, kept until the compute pipeline is ready.
+#[derive(Default)]
+struct GpuMeshingJobs {
+    pending: Vec<GpuMeshingJob>,
+    /// Set by the meshing node once it dispatched the pending jobs.
+    dispatched: AtomicBool,
+}
+
+fn extract_gpu_chunk_meshes(
+    chunks: Extract<Query<(Entity, &GpuChunkMesh)>>,
+    meshes: Res<GpuChunkMeshes>,
+    mut extracted: ResMut<ExtractedGpuChunkMeshes>,
+) {
+    extracted.alive.clear();
+    extracted.uploads.clear();
+
+    for (entity, mesh) in chunks.iter() {
+        extracted.alive.insert(entity);
+        if meshes
+            .0
+            .get(&entity)
+            .map_or(true, |buffers| buffers.revision != mesh.revision)
+        {
+            extracted.uploads.push((entity, mesh.clone()));
+        }
+    }
+}
+
+/// Uploads the voxel data of the newly GPU meshed chunks and creates the buffers receiving their vertices.
+fn prepare_gpu_chunk_meshes(
+    render_device: Res<RenderDevice>,
+    pipeline: Res<GpuMeshingPipeline>,
+    extracted: Res<ExtractedGpuChunkMeshes>,
+    mut meshes: ResMut<GpuChunkMeshes>,
+    mut jobs: ResMut<GpuMeshingJobs>,
+) {
+    if *jobs.dispatched.get_mut() {
+        *jobs.dispatched.get_mut() = false;
+        jobs.pending.clear();
+    }
+
+    meshes
+        .0
+        .retain(|entity, _| extracted.alive.contains(entity));
+
+    for (entity, mesh) in extracted.uploads.iter() {
+        let voxels = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("gpu_meshing_voxels"),
+            contents: &mesh
+                .voxels
+                .iter()
+                .flat_map(|voxel| voxel.to_le_bytes())
+                .collect::<Vec<u8>>(),
+            usage: BufferUsages::STORAGE,
+        });
+
+        let vertices = render_device.create_buffer(&BufferDescriptor {
+            label: Some("gpu_meshing_vertices"),
+            size: mesh.faces.max(1) as u64 * VERTICES_PER_FACE * VERTEX_SIZE,
+            usage: BufferUsages::VERTEX | BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        // vertex count, instance count, first vertex and first instance.
+        let indirect = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("gpu_meshing_indirect"),
+            contents: &[0u32, 1, 0, 0]
+                .iter()
+                .flat_map(|arg| arg.to_le_bytes())
+                .collect::<Vec<u8>>(),
+            usage: BufferUsages::INDIRECT | BufferUsages::STORAGE,
+        });
+
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("gpu_meshing_bind_group"),
+            layout: &pipeline.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: voxels.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: vertices.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: indirect.as_entire_binding(),
+                },
+            ],
+        });
+
+        jobs.pending.push(GpuMeshingJob {
+            entity: *entity,
+            revision: mesh.revision,
+            bind_group,
+        });
+        meshes.0.insert(
+            *entity,
+            GpuChunkMeshBuffers {
+                revision: mesh.revision,
+                vertices,
+                indirect,
+            },
+        );
+    }
+
+    // jobs of unloaded or since remeshed chunks are dropped.
+    let meshes = &meshes.0;
+    jobs.pending.retain(|job| {
+        meshes
+            .get(&job.entity)
+            .map_or(false, |buffers| buffers.revision == job.revision)
+    });
+}
+
+/// Dispatches the meshing compute shader over the chunks uploaded since the last dispatch, before the cameras render.
+struct GpuMeshingNode;
+
+impl Node for GpuMeshingNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let jobs = world.resource::<GpuMeshingJobs>();
+        if jobs.pending.is_empty() {
+            return Ok(());
+        }
+
+        let pipeline = match world
+            .resource::<PipelineCache>()
+            .get_compute_pipeline(world.resource::<GpuMeshingPipeline>().pipeline)
+        {
+            Some(pipeline) => pipeline,
+            None => return Ok(()),
+        };
+
+        let mut pass = render_context
+            .command_encoder
+            .begin_compute_pass(&ComputePassDescriptor {
+                label: Some("gpu_meshing_pass"),
+            });
+        pass.set_pipeline(pipeline);
+
+        for job in jobs.pending.iter() {
+            pass.set_bind_group(0, &job.bind_group, &[]);
+            pass.dispatch_workgroups(
+                CHUNK_LENGTH / WORKGROUP_SIZE,
+                CHUNK_HEIGHT / WORKGROUP_SIZE,
+                CHUNK_LENGTH / WORKGROUP_SIZE,
+            );
+        }
+
+        jobs.dispatched.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_gpu_chunk_meshes(
+    draw_functions: Res<DrawFunctions<AlphaMask3d>>,
+    voxel_pipeline: Res<VoxelTerrainRenderPipeline>,
+    meshing_pipeline: Res<GpuMeshingPipeline>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    mut specialized_pipelines: ResMut<SpecializedMeshPipelines<VoxelTerrainRenderPipeline>>,
+    msaa: Res<Msaa>,
+    meshes: Res<GpuChunkMeshes>,
+    mesh_uniforms: Query<&MeshUniform>,
+    mut views: Query<(
+        &ExtractedView,
+        &mut RenderPhase<AlphaMask3d>,
+        Option<&ViewRenderDistance>,
+    )>,
+) {
+    let draw_function = draw_functions
+        .read()
+        .get_id::<DrawGpuMeshedVoxel>()
+        .unwrap();
+    let key = MeshPipelineKey::from_msaa_samples(msaa.samples)
+        | MeshPipelineKey::from_primitive_topology(PrimitiveTopology::TriangleList);
+    let pipeline = specialized_pipelines
+        .specialize(
+            &mut pipeline_cache,
+            &voxel_pipeline,
+            key,
+            &meshing_pipeline.vertex_layout,
+        )
+        .unwrap();
+
+    for (view, mut phase, render_distance) in views.iter_mut() {
+        let view_row_2 = view.transform.compute_matrix().row(2);
+
+        // only the visible chunks got their mesh uniform extracted.
+        for entity in meshes.0.keys() {
+            if let Ok(mesh_uniform) = mesh_uniforms.get(*entity) {
+                let chunk_min = mesh_uniform.transform.col(3).truncate();
+                if !render_distance.map_or(true, |distance| {
+                    distance.contains_chunk(view.transform.translation(), chunk_min)
+                }) {
+                    continue;
+                }
+                phase.add(AlphaMask3d {
+                    entity: *entity,
+                    pipeline,
+                    draw_function,
+                    distance: view_row_2.dot(mesh_uniform.transform.col(3)),
+                });
+            }
+        }
+    }
+}
+
+/// Draws the vertices emitted by the meshing compute shader.
+struct DrawGpuChunkMesh;
+
+impl EntityRenderCommand for DrawGpuChunkMesh {
+    type Param = SRes<GpuChunkMeshes>;
+
+    fn render<'w>(
+        _view: Entity,
+        item: Entity,
+        meshes: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        match meshes.into_inner().0.get(&item) {
+            Some(buffers) => {
+                pass.set_vertex_buffer(0, buffers.vertices.slice(..));
+                pass.draw_indirect(&buffers.indirect, 0);
+                RenderCommandResult::Success
+            }
+            None => RenderCommandResult::Failure,
+        }
+    }
+}
+
+type DrawGpuMeshedVoxel = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    SetTerrainUniformsBindGroup<2>,
+    DrawGpuChunkMesh,
+);
+
+/// Label of the render graph node dispatching the meshing compute shader.
+const GPU_MESHING_NODE: &str = "gpu_meshing";
+
+/// Optional GPU meshing backend, see [`GpuMeshing`].
+/// The backend is only enabled on graphics adapters supporting compute shaders.
+pub struct GpuMeshingPlugin;
+
+impl Plugin for GpuMeshingPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let supported = app
+            .world
+            .get_resource::<RenderDevice>()
+            .map_or(false, |device| gpu_meshing_supported(&device.limits()));
+
+        app.insert_resource(GpuMeshing {
+            enabled: true,
+            supported,
+        });
+
+        if !supported {
+            info!("Compute shaders aren't supported, chunks are meshed on the CPU");
+            return;
+        }
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .init_resource::<GpuMeshingPipeline>()
+            .init_resource::<GpuChunkMeshes>()
+            .init_resource::<ExtractedGpuChunkMeshes>()
+            .init_resource::<GpuMeshingJobs>()
+            .add_render_command::<AlphaMask3d, DrawGpuMeshedVoxel>()
+            .add_system_to_stage(RenderStage::Extract, extract_gpu_chunk_meshes)
+            .add_system_to_stage(RenderStage::Prepare, prepare_gpu_chunk_meshes)
+            .add_system_to_stage(RenderStage::Queue, queue_gpu_chunk_meshes);
+
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(GPU_MESHING_NODE, GpuMeshingNode);
+        graph
+            .add_node_edge(
+                GPU_MESHING_NODE,
+                bevy::render::main_graph::node::CAMERA_DRIVER,
+            )
+            .unwrap();
+    }
+}
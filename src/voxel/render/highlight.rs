@@ -0,0 +1,106 @@
+use bevy::{
+    math::Vec3,
+    pbr::NotShadowCaster,
+    prelude::{
+        Assets, Color, Commands, Component, Mesh, ParallelSystemDescriptorCoercion, PbrBundle,
+        Plugin, Query, Res, ResMut, StandardMaterial, Transform, Visibility, With,
+    },
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+
+use crate::voxel::{
+    interaction::{TargetedVoxel, VoxelInteractionSystem},
+    WorldOrigin,
+};
+
+/// Tags the wireframe cube drawn around the voxel targeted by the player.
+#[derive(Component)]
+pub struct VoxelHighlight;
+
+/// Appends the corners and edges of the box spanning from `min` to `max` to a line list.
+pub fn push_box_lines(min: Vec3, max: Vec3, positions: &mut Vec<[f32; 3]>, indices: &mut Vec<u32>) {
+    let first = positions.len() as u32;
+    positions.extend((0..8).map(|corner| {
+        [
+            if corner & 1 != 0 { max.x } else { min.x },
+            if corner & 2 != 0 { max.y } else { min.y },
+            if corner & 4 != 0 { max.z } else { min.z },
+        ]
+    }));
+
+    // each edge joins two corners differing by a single axis.
+    for corner in 0..8u32 {
+        for axis in [1, 2, 4] {
+            if corner & axis == 0 {
+                indices.extend_from_slice(&[first + corner, first + (corner | axis)]);
+            }
+        }
+    }
+}
+
+/// Builds a line list mesh of the edges of a cube centered on the origin.
+fn wireframe_cube_mesh(size: f32) -> Mesh {
+    let (mut corners, mut indices) = (Vec::with_capacity(8), Vec::with_capacity(24));
+    push_box_lines(
+        Vec3::splat(-size / 2.0),
+        Vec3::splat(size / 2.0),
+        &mut corners,
+        &mut indices,
+    );
+
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 1.0, 0.0]; corners.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; corners.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, corners);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+fn spawn_voxel_highlight(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands,
+) {
+    commands
+        .spawn_bundle(PbrBundle {
+            // slightly larger than a voxel so that the lines aren't hidden by its faces.
+            mesh: meshes.add(wireframe_cube_mesh(1.005)),
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(0.05, 0.05, 0.05),
+                unlit: true,
+                ..Default::default()
+            }),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(VoxelHighlight)
+        .insert(NotShadowCaster);
+}
+
+/// Moves the highlight wireframe around the targeted voxel, hiding it when no voxel is targeted.
+fn update_voxel_highlight(
+    targeted: Res<TargetedVoxel>,
+    origin: Res<WorldOrigin>,
+    mut highlight: Query<(&mut Transform, &mut Visibility), With<VoxelHighlight>>,
+) {
+    if !targeted.is_changed() {
+        return;
+    }
+
+    if let Ok((mut transform, mut visibility)) = highlight.get_single_mut() {
+        visibility.is_visible = targeted.0.is_some();
+        if let Some(hit) = targeted.0 {
+            transform.translation = origin.to_translation(hit.position) + Vec3::splat(0.5);
+        }
+    }
+}
+
+/// Draws a wireframe cube around the voxel targeted by the player.
+pub struct VoxelHighlightPlugin;
+
+impl Plugin for VoxelHighlightPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_startup_system(spawn_voxel_highlight)
+            .add_system(update_voxel_highlight.after(VoxelInteractionSystem::UpdateTargetedVoxel));
+    }
+}
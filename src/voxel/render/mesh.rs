@@ -1,24 +1,60 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, mem::size_of};
 
-use crate::voxel::{storage::VoxelBuffer, MaterialVoxel};
+use crate::voxel::{storage::VoxelBuffer, Light, MaterialVoxel};
 use bevy::{
+    math::IVec3,
     prelude::Mesh,
     render::mesh::{Indices, VertexAttributeValues},
 };
-use block_mesh::{greedy_quads, GreedyQuadsBuffer, RIGHT_HANDED_Y_UP_CONFIG};
-use ndcopy::copy3;
-use ndshape::{RuntimeShape, Shape};
+use block_mesh::{
+    greedy_quads, GreedyQuadsBuffer, MergeVoxel, UnorientedQuad, Voxel as MeshableVoxel,
+    VoxelVisibility, RIGHT_HANDED_Y_UP_CONFIG,
+};
+use ndshape::Shape;
 
 use super::VoxelTerrainMesh;
 
+/// Normals of the faces of [`RIGHT_HANDED_Y_UP_CONFIG`], in the same order.
+const FACE_NORMALS: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::NEG_Y,
+    IVec3::NEG_Z,
+    IVec3::X,
+    IVec3::Y,
+    IVec3::Z,
+];
+
+/// A voxel along the light levels received by each of its faces, so that only the faces sharing the same light get merged.
+#[derive(Copy, Clone)]
+struct LitVoxel<T> {
+    voxel: T,
+    face_lights: [Light; 6],
+}
+
+impl<T: MeshableVoxel> MeshableVoxel for LitVoxel<T> {
+    #[inline]
+    fn get_visibility(&self) -> VoxelVisibility {
+        self.voxel.get_visibility()
+    }
+}
+
+impl<T: MergeVoxel> MergeVoxel for LitVoxel<T> {
+    type MergeValue = (T::MergeValue, [Light; 6]);
+
+    #[inline]
+    fn merge_value(&self) -> Self::MergeValue {
+        (self.voxel.merge_value(), self.face_lights)
+    }
+}
+
 /// Intermediate buffers for greedy meshing of voxel data which are reusable between frames to not allocate.
 pub struct MeshBuffers<T, S: Shape<3, Coord = u32>>
 where
     T: Copy + Default + MaterialVoxel,
 {
-    // A padded buffer to run greedy meshing algorithm on
-    scratch_buffer: VoxelBuffer<T, RuntimeShape<u32, 3>>,
     greedy_buffer: GreedyQuadsBuffer,
+    lit_buffer: Vec<LitVoxel<T>>,
+    quads: Vec<MeshQuad>,
     _phantom: PhantomData<S>,
 }
 
@@ -26,95 +62,406 @@ impl<T, S: Shape<3, Coord = u32>> MeshBuffers<T, S>
 where
     T: Copy + Default + MaterialVoxel,
 {
-    pub fn new(shape: S) -> Self {
-        let padded_shape = RuntimeShape::<u32, 3>::new(shape.as_array().map(|x| x + 2));
-
+    /// Creates the mesh buffers for meshing padded buffers of the specified shape.
+    pub fn new(padded_shape: S) -> Self {
         Self {
             greedy_buffer: GreedyQuadsBuffer::new(padded_shape.size() as usize),
-            scratch_buffer: VoxelBuffer::<T, RuntimeShape<u32, 3>>::new_empty(padded_shape),
+            lit_buffer: Vec::with_capacity(padded_shape.size() as usize),
+            quads: Vec::new(),
             _phantom: Default::default(),
         }
     }
+
+    /// Returns the approximate amount of memory allocated by the buffers, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        let quads: usize = self
+            .greedy_buffer
+            .quads
+            .groups
+            .iter()
+            .map(|group| group.capacity())
+            .sum();
+
+        // the visited mask of the greedy mesher is as large as the lit buffer.
+        quads * size_of::<UnorientedQuad>()
+            + self.quads.capacity() * size_of::<MeshQuad>()
+            + self.lit_buffer.capacity() * (size_of::<LitVoxel<T>>() + size_of::<bool>())
+    }
+
+    /// Releases the memory of the quad buffers, which grow to the size needed by the most complex chunk meshed so far.
+    pub fn trim(&mut self) {
+        self.greedy_buffer
+            .quads
+            .groups
+            .iter_mut()
+            .for_each(|group| {
+                group.clear();
+                group.shrink_to_fit();
+            });
+        self.quads = Vec::new();
+    }
 }
 
-// Processes the voxel data buffer specified as a parameter and generate.
-//todo: don't populate mesh directly, introduce a meshbuilding system.
-pub fn mesh_buffer<T, S>(
-    buffer: &VoxelBuffer<T, S>,
+/// Packs a vertex position of the padded buffer as chunk local 7 bit coordinates in the high bits of the vertex data.
+#[inline]
+fn pack_vertex_position(position: [f32; 3]) -> u32 {
+    // the vertices are offset by the 1 voxel wide padding of the meshed buffer.
+    let [x, y, z] = position.map(|axis| (axis as u32 - 1) & 127);
+    x << 11 | y << 18 | z << 25
+}
+
+/// A vertex of a terrain mesh, unpacked from its [`VoxelTerrainMesh::ATTRIBUTE_DATA`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TerrainVertex {
+    /// Position of the vertex relative to the chunk minimum.
+    pub position: IVec3,
+    pub normal: IVec3,
+    pub material: u8,
+}
+
+impl TerrainVertex {
+    pub fn unpack(data: u32) -> Self {
+        Self {
+            position: IVec3::new(
+                (data >> 11 & 127) as i32,
+                (data >> 18 & 127) as i32,
+                (data >> 25 & 127) as i32,
+            ),
+            normal: FACE_NORMALS[(data >> 8 & 7) as usize % FACE_NORMALS.len()],
+            material: data as u8,
+        }
+    }
+
+    /// Returns the packed vertex data and the indices of a terrain mesh, `None` if the mesh isn't a terrain mesh.
+    pub fn mesh_data(mesh: &Mesh) -> Option<(&[u32], &[u32])> {
+        match (
+            mesh.attribute(VoxelTerrainMesh::ATTRIBUTE_DATA),
+            mesh.indices(),
+        ) {
+            (Some(VertexAttributeValues::Uint32(data)), Some(Indices::U32(indices))) => {
+                Some((data, indices))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A quad of a greedy mesh, in the coordinates of the padded buffer it was meshed from.
+#[derive(Clone, Copy)]
+struct MeshQuad {
+    quad: UnorientedQuad,
+    /// Index of the face in [`RIGHT_HANDED_Y_UP_CONFIG`].
+    face: usize,
+    material: u8,
+    light: Light,
+}
+
+impl MeshQuad {
+    /// Returns the minimum and the (exclusive) maximum of the voxels whose faces the quad covers.
+    fn voxel_extent(&self) -> (IVec3, IVec3) {
+        let positions =
+            RIGHT_HANDED_Y_UP_CONFIG.faces[self.face].quad_mesh_positions(&self.quad, 1.0);
+        let (min, max) = positions.iter().fold(
+            (IVec3::splat(i32::MAX), IVec3::splat(i32::MIN)),
+            |(min, max), position| {
+                let position = IVec3::from(position.map(|axis| axis as i32));
+                (min.min(position), max.max(position))
+            },
+        );
+
+        // the quads of the faces pointing towards the positive axes lie on the far side of their voxels.
+        let normal = FACE_NORMALS[self.face];
+        (
+            min - normal.max(IVec3::ZERO),
+            max + (-normal).max(IVec3::ZERO),
+        )
+    }
+}
+
+/// The quads of a greedy mesh along with the voxels they were meshed from, so that the mesh can be patched with
+/// [`patch_mesh_buffer`] after a few voxels changed instead of being meshed again.
+pub struct PatchableMesh<T> {
+    voxels: Box<[T]>,
+    quads: Vec<MeshQuad>,
+}
+
+/// Fills the lit buffer with the voxels of the padded buffer, along the light of their faces for the voxels of the
+/// `[min; max]` extent only.
+fn fill_lit_buffer<T, S>(
+    padded_buffer: &VoxelBuffer<T, S>,
+    padded_light: &VoxelBuffer<Light, S>,
+    lit_buffer: &mut Vec<LitVoxel<T>>,
+    min: IVec3,
+    max: IVec3,
+) where
+    T: Copy + Default + PartialEq + MaterialVoxel,
+    S: Shape<3, Coord = u32>,
+{
+    let shape = padded_buffer.shape();
+    let extent = IVec3::from(shape.as_array().map(|axis| axis as i32));
+    let occupancy = padded_buffer.occupancy();
+
+    lit_buffer.clear();
+    lit_buffer.extend((0..shape.size()).map(|index| {
+        let pos = IVec3::from(shape.delinearize(index).map(|x| x as i32));
+        let mut face_lights = [Light::default(); 6];
+        if !occupancy.get(index as usize) || pos.cmplt(min).any() || pos.cmpgt(max).any() {
+            return LitVoxel {
+                voxel: padded_buffer.slice()[index as usize],
+                face_lights,
+            };
+        }
+
+        // only the faces next to empty voxels get meshed, the others don't need their light.
+        for (face_light, normal) in face_lights.iter_mut().zip(FACE_NORMALS) {
+            let neighbor = pos + normal;
+            if neighbor.cmpge(IVec3::ZERO).all()
+                && neighbor.cmplt(extent).all()
+                && !occupancy.get(shape.linearize(neighbor.as_uvec3().to_array()) as usize)
+            {
+                *face_light = padded_light.voxel_at(neighbor.as_uvec3());
+            }
+        }
+
+        LitVoxel {
+            voxel: padded_buffer.slice()[index as usize],
+            face_lights,
+        }
+    }));
+}
+
+/// Greedy meshes the voxels of the `[min; max]` extent of the lit buffer into quads, the 1 voxel wide border of the
+/// extent only hiding the faces of the voxels it touches.
+fn greedy_mesh_quads<T, S>(
+    padded_buffer: &VoxelBuffer<T, S>,
     mesh_buffers: &mut MeshBuffers<T, S>,
-    render_mesh: &mut Mesh,
-    scale: f32,
+    min: IVec3,
+    max: IVec3,
 ) where
-    T: Copy + Default + MaterialVoxel,
+    T: Copy + Default + PartialEq + MaterialVoxel,
     S: Shape<3, Coord = u32>,
 {
-    mesh_buffers
-        .greedy_buffer
-        .reset(buffer.shape().size() as usize);
-
-    let dst_shape = mesh_buffers.scratch_buffer.shape().clone();
-
-    copy3(
-        buffer.shape().as_array(),
-        buffer.slice(),
-        buffer.shape(),
-        [0; 3],
-        mesh_buffers.scratch_buffer.slice_mut(),
-        &dst_shape,
-        [1; 3],
-    );
+    let shape = padded_buffer.shape();
+    mesh_buffers.greedy_buffer.reset(shape.size() as usize);
 
     greedy_quads(
-        mesh_buffers.scratch_buffer.slice(),
-        mesh_buffers.scratch_buffer.shape(),
-        [0; 3],
-        mesh_buffers
-            .scratch_buffer
-            .shape()
-            .as_array()
-            .map(|axis| axis - 1),
+        &mesh_buffers.lit_buffer,
+        shape,
+        min.as_uvec3().to_array(),
+        max.as_uvec3().to_array(),
         &RIGHT_HANDED_Y_UP_CONFIG.faces,
         &mut mesh_buffers.greedy_buffer,
     );
 
-    let num_indices = mesh_buffers.greedy_buffer.quads.num_quads() * 6;
-    let num_vertices = mesh_buffers.greedy_buffer.quads.num_quads() * 4;
-    let mut indices = Vec::with_capacity(num_indices);
-    let mut positions = Vec::with_capacity(num_vertices);
-    let mut data = Vec::with_capacity(num_vertices);
-
     //normal face index depends on the quad orientation config
-    for (block_face_normal_index, (group, face)) in mesh_buffers
-        .greedy_buffer
-        .quads
-        .groups
-        .as_ref()
-        .into_iter()
-        .zip(RIGHT_HANDED_Y_UP_CONFIG.faces.into_iter())
-        .enumerate()
-    {
-        for quad in group.into_iter() {
-            indices.extend_from_slice(&face.quad_mesh_indices(positions.len() as u32));
-            positions.extend_from_slice(&face.quad_mesh_positions(&quad, scale));
-            data.extend_from_slice(
-                &[(block_face_normal_index as u32) << 8u32
-                    | buffer
-                        .voxel_at(quad.minimum.map(|x| x - 1).into())
-                        .as_mat_id() as u32; 4],
-            );
+    for (face, group) in mesh_buffers.greedy_buffer.quads.groups.iter().enumerate() {
+        for quad in group.iter() {
+            mesh_buffers.quads.push(MeshQuad {
+                quad: *quad,
+                face,
+                material: padded_buffer.voxel_at(quad.minimum.into()).as_mat_id(),
+                light: mesh_buffers.lit_buffer[shape.linearize(quad.minimum) as usize].face_lights
+                    [face],
+            });
         }
     }
+}
 
-    render_mesh.insert_attribute(
-        Mesh::ATTRIBUTE_POSITION,
-        VertexAttributeValues::Float32x3(positions),
-    );
+/// Writes the vertices and indices of the quads into the render mesh.
+fn write_quads(quads: &[MeshQuad], render_mesh: &mut Mesh) {
+    let mut indices = Vec::with_capacity(quads.len() * 6);
+    let mut data = Vec::with_capacity(quads.len() * 4);
+    let mut lights = Vec::with_capacity(quads.len() * 4);
+
+    for quad in quads {
+        let face = &RIGHT_HANDED_Y_UP_CONFIG.faces[quad.face];
+        indices.extend_from_slice(&face.quad_mesh_indices(data.len() as u32));
+
+        let face_data = (quad.face as u32) << 8u32 | quad.material as u32;
+        data.extend(
+            face.quad_mesh_positions(&quad.quad, 1.0)
+                .into_iter()
+                .map(|position| pack_vertex_position(position) | face_data),
+        );
+        lights.extend_from_slice(&[quad.light.0; 4]);
+    }
 
-    //todo: in the future we might want to encode all the information onto a single uint32
     render_mesh.insert_attribute(
         VoxelTerrainMesh::ATTRIBUTE_DATA,
         VertexAttributeValues::Uint32(data),
     );
 
-    render_mesh.set_indices(Some(Indices::U32(indices.clone())));
+    render_mesh.insert_attribute(
+        VoxelTerrainMesh::ATTRIBUTE_LIGHT,
+        VertexAttributeValues::Uint32(lights),
+    );
+
+    render_mesh.set_indices(Some(Indices::U32(indices)));
+}
+
+// Processes the voxel data buffer specified as a parameter and generate.
+// The buffer must be padded with a 1 voxel wide border holding the voxels of the neighboring chunks, no faces are generated for the border itself.
+// The faces are lit with the light levels of the voxels they face, read from the equally padded light buffer.
+//todo: don't populate mesh directly, introduce a meshbuilding system.
+pub fn mesh_buffer<T, S>(
+    padded_buffer: &VoxelBuffer<T, S>,
+    padded_light: &VoxelBuffer<Light, S>,
+    mesh_buffers: &mut MeshBuffers<T, S>,
+    render_mesh: &mut Mesh,
+) where
+    T: Copy + Default + PartialEq + MaterialVoxel,
+    S: Shape<3, Coord = u32>,
+{
+    let max = IVec3::from(padded_buffer.shape().as_array().map(|axis| axis as i32 - 1));
+
+    fill_lit_buffer(
+        padded_buffer,
+        padded_light,
+        &mut mesh_buffers.lit_buffer,
+        IVec3::ZERO,
+        max,
+    );
+    mesh_buffers.quads.clear();
+    greedy_mesh_quads(padded_buffer, mesh_buffers, IVec3::ZERO, max);
+    write_quads(&mesh_buffers.quads, render_mesh);
+}
+
+/// Meshes the buffer like [`mesh_buffer`], also returning the quads of the mesh so that it can be patched later by
+/// [`patch_mesh_buffer`].
+pub fn mesh_buffer_patchable<T, S>(
+    padded_buffer: &VoxelBuffer<T, S>,
+    padded_light: &VoxelBuffer<Light, S>,
+    mesh_buffers: &mut MeshBuffers<T, S>,
+    render_mesh: &mut Mesh,
+) -> PatchableMesh<T>
+where
+    T: Copy + Default + PartialEq + MaterialVoxel,
+    S: Shape<3, Coord = u32>,
+{
+    mesh_buffer(padded_buffer, padded_light, mesh_buffers, render_mesh);
+
+    PatchableMesh {
+        voxels: padded_buffer.slice().into(),
+        quads: mesh_buffers.quads.clone(),
+    }
+}
+
+/// Meshes a new version of the buffer of a [`PatchableMesh`] by only meshing again the voxels around the voxels which
+/// changed, along with the quads whose faces aren't lit the same anymore.
+/// Returns `None` without touching the render mesh if more than `max_volume` voxels would need to be meshed again,
+/// the buffer then needs to be meshed with [`mesh_buffer`].
+pub fn patch_mesh_buffer<T, S>(
+    previous: &PatchableMesh<T>,
+    padded_buffer: &VoxelBuffer<T, S>,
+    padded_light: &VoxelBuffer<Light, S>,
+    mesh_buffers: &mut MeshBuffers<T, S>,
+    max_volume: u32,
+    render_mesh: &mut Mesh,
+) -> Option<PatchableMesh<T>>
+where
+    T: Copy + Default + PartialEq + MaterialVoxel,
+    S: Shape<3, Coord = u32>,
+{
+    let shape = padded_buffer.shape();
+    let voxels = padded_buffer.slice();
+    if previous.voxels.len() != voxels.len() {
+        return None;
+    }
+
+    // bounding box of the voxels to mesh again, as a minimum and an exclusive maximum.
+    let mut region: Option<(IVec3, IVec3)> = None;
+    let grow = |region: &mut Option<(IVec3, IVec3)>, min: IVec3, max: IVec3| {
+        *region = Some(match *region {
+            Some((region_min, region_max)) => (region_min.min(min), region_max.max(max)),
+            None => (min, max),
+        });
+    };
+
+    // a changed voxel changes its own faces and the faces of its neighbors facing it.
+    for (index, _) in previous
+        .voxels
+        .iter()
+        .zip(voxels)
+        .enumerate()
+        .filter(|(_, (old, new))| old != new)
+    {
+        let pos = IVec3::from(shape.delinearize(index as u32).map(|axis| axis as i32));
+        grow(&mut region, pos - IVec3::ONE, pos + IVec3::splat(2));
+    }
+
+    let mut kept: Vec<bool> = previous
+        .quads
+        .iter()
+        .map(|quad| {
+            let (min, max) = quad.voxel_extent();
+            let normal = FACE_NORMALS[quad.face];
+            (min.x..max.x).all(|x| {
+                (min.y..max.y).all(|y| {
+                    (min.z..max.z).all(|z| {
+                        padded_light.voxel_at((IVec3::new(x, y, z) + normal).as_uvec3())
+                            == quad.light
+                    })
+                })
+            })
+        })
+        .collect();
+    for (quad, _) in previous.quads.iter().zip(&kept).filter(|(_, kept)| !**kept) {
+        let (min, max) = quad.voxel_extent();
+        grow(&mut region, min, max);
+    }
+
+    // the quads overlapping the region get meshed again along with it, which may in turn grow the region.
+    let mut grown = true;
+    while grown {
+        grown = false;
+        for (quad, kept) in previous.quads.iter().zip(kept.iter_mut()) {
+            let (region_min, region_max) = match region {
+                Some(region) => region,
+                None => break,
+            };
+            let (min, max) = quad.voxel_extent();
+            if *kept && min.cmplt(region_max).all() && max.cmpgt(region_min).all() {
+                *kept = false;
+                grow(&mut region, min, max);
+                grown = true;
+            }
+        }
+    }
+
+    mesh_buffers.quads.clear();
+    mesh_buffers.quads.extend(
+        previous
+            .quads
+            .iter()
+            .zip(&kept)
+            .filter(|(_, kept)| **kept)
+            .map(|(quad, _)| *quad),
+    );
+
+    // the padding of the buffer only hides faces.
+    let extent = IVec3::from(shape.as_array().map(|axis| axis as i32));
+    if let Some((min, max)) = region
+        .map(|(min, max)| (min.max(IVec3::ONE), max.min(extent - IVec3::ONE)))
+        .filter(|(min, max)| min.cmplt(*max).all())
+    {
+        if (max - min).as_uvec3().to_array().iter().product::<u32>() > max_volume {
+            return None;
+        }
+
+        fill_lit_buffer(
+            padded_buffer,
+            padded_light,
+            &mut mesh_buffers.lit_buffer,
+            min - IVec3::ONE,
+            max,
+        );
+        greedy_mesh_quads(padded_buffer, mesh_buffers, min - IVec3::ONE, max);
+    }
+
+    write_quads(&mesh_buffers.quads, render_mesh);
+
+    Some(PatchableMesh {
+        voxels: voxels.into(),
+        quads: mesh_buffers.quads.clone(),
+    })
 }
@@ -4,5 +4,51 @@ pub use mesh::*;
 mod pipeline;
 pub use pipeline::*;
 
+/// Optional compute shader based chunk meshing.
+#[cfg(feature = "gpu_meshing")]
+mod gpu_meshing;
+#[cfg(feature = "gpu_meshing")]
+pub use gpu_meshing::*;
+
+/// Reuse of the GPU buffers of the chunk meshes across remeshes.
+mod chunk_mesh_buffers;
+pub use chunk_mesh_buffers::{ChunkMeshBufferReuse, PooledChunkMesh};
+
+/// Frustum culling of the pooled chunk meshes on the GPU, drawing them indirectly.
+mod chunk_culling;
+pub use chunk_culling::GpuChunkCulling;
+
+/// Hot reloading of the terrain shader.
+mod shader_reload;
+
 mod terrain_uniforms;
 pub use terrain_uniforms::*;
+
+/// Render distance overrides of the secondary cameras.
+mod view_distance;
+pub use view_distance::ViewRenderDistance;
+
+/// Cascaded sun shadow maps of the terrain meshes.
+mod terrain_shadows;
+pub use terrain_shadows::{
+    ShadowCascade, TerrainShadowCascades, TerrainShadowSettings, MAX_SHADOW_CASCADES,
+};
+
+/// Crossed quad meshes of the foliage voxels, swaying with the wind.
+mod foliage;
+pub use foliage::*;
+
+/// Sky gradient drawn behind the terrain, its horizon color being the terrain fog color.
+mod sky;
+pub use sky::*;
+
+/// Meshing of the partially filled fluid voxels.
+mod fluid_mesh;
+pub use fluid_mesh::*;
+
+/// 3D texture of the coarse voxel volume around the camera, for the shader effects.
+mod voxel_volume;
+pub use voxel_volume::*;
+
+mod highlight;
+pub use highlight::*;
@@ -4,7 +4,9 @@ use bevy::prelude::{
     Bundle, ComputedVisibility, Entity, GlobalTransform, Mesh, Msaa, Query, Res, ResMut, Transform,
     Visibility, With,
 };
-use bevy::render::mesh::{MeshVertexAttribute, MeshVertexBufferLayout};
+use bevy::render::mesh::{
+    Indices, MeshVertexAttribute, MeshVertexBufferLayout, VertexAttributeValues,
+};
 
 use bevy::render::primitives::Aabb;
 use bevy::render::render_asset::RenderAssets;
@@ -12,9 +14,9 @@ use bevy::render::render_phase::{AddRenderCommand, DrawFunctions, RenderPhase, S
 use bevy::render::render_resource::{
     BindGroupLayout, BlendState, ColorTargetState, ColorWrites, CompareFunction, DepthBiasState,
     DepthStencilState, Face, FragmentState, FrontFace, MultisampleState, PipelineCache,
-    PolygonMode, PrimitiveState, RenderPipelineDescriptor, SpecializedMeshPipeline,
-    SpecializedMeshPipelineError, SpecializedMeshPipelines, StencilFaceState, StencilState,
-    TextureFormat, VertexFormat, VertexState,
+    PolygonMode, PrimitiveState, PrimitiveTopology, RenderPipelineDescriptor,
+    SpecializedMeshPipeline, SpecializedMeshPipelineError, SpecializedMeshPipelines,
+    StencilFaceState, StencilState, TextureFormat, VertexFormat, VertexState,
 };
 use bevy::render::texture::BevyDefault;
 use bevy::render::view::ExtractedView;
@@ -28,15 +30,40 @@ use bevy::{
     },
 };
 
-use super::terrain_uniforms::{self, SetTerrainUniformsBindGroup, TerrainUniforms};
+use super::{
+    shader_reload::{self, TERRAIN_SHADER_HANDLE, TERRAIN_SHADER_PATH},
+    terrain_uniforms::{self, SetTerrainUniformsBindGroup, TerrainUniforms},
+    ViewRenderDistance,
+};
 
 #[derive(Component, Clone, Default)]
 /// A marker component for voxel meshes.
 pub struct VoxelTerrainMesh;
 
 impl VoxelTerrainMesh {
+    /// Packed vertex data: chunk local position, face normal index and material id (see `voxel_data.wgsl` for the layout).
     pub const ATTRIBUTE_DATA: MeshVertexAttribute =
         MeshVertexAttribute::new("Vertex_Data", 1, VertexFormat::Uint32);
+
+    /// Light levels of the voxel faced by the vertex face, packed like [`crate::voxel::Light`].
+    pub const ATTRIBUTE_LIGHT: MeshVertexAttribute =
+        MeshVertexAttribute::new("Vertex_Light", 2, VertexFormat::Uint32);
+
+    /// Returns a mesh without any vertex, standing in for the CPU mesh of the chunks whose vertices are only known to
+    /// the render world.
+    pub fn placeholder_mesh() -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(
+            Self::ATTRIBUTE_DATA,
+            VertexAttributeValues::Uint32(Vec::new()),
+        );
+        mesh.insert_attribute(
+            Self::ATTRIBUTE_LIGHT,
+            VertexAttributeValues::Uint32(Vec::new()),
+        );
+        mesh.set_indices(Some(Indices::U32(Vec::new())));
+        mesh
+    }
 }
 
 impl ExtractComponent for VoxelTerrainMesh {
@@ -52,7 +79,8 @@ impl ExtractComponent for VoxelTerrainMesh {
 /// A render pipeline for rendering voxel terrain meshes.
 pub struct VoxelTerrainRenderPipeline {
     mesh_pipeline: MeshPipeline,
-    shader: Handle<Shader>,
+    /// The terrain shader as loaded from its file, which may not compile.
+    pub(super) source_shader: Handle<Shader>,
     material_array_layout: BindGroupLayout,
 }
 
@@ -60,10 +88,10 @@ impl FromWorld for VoxelTerrainRenderPipeline {
     fn from_world(world: &mut bevy::prelude::World) -> Self {
         VoxelTerrainRenderPipeline {
             mesh_pipeline: world.get_resource::<MeshPipeline>().unwrap().clone(),
-            shader: world
+            source_shader: world
                 .get_resource::<AssetServer>()
                 .unwrap()
-                .load("shaders/terrain_pipeline.wgsl") as Handle<Shader>,
+                .load(TERRAIN_SHADER_PATH) as Handle<Shader>,
             material_array_layout: world
                 .get_resource::<TerrainUniforms>()
                 .unwrap()
@@ -73,28 +101,42 @@ impl FromWorld for VoxelTerrainRenderPipeline {
     }
 }
 
-impl SpecializedMeshPipeline for VoxelTerrainRenderPipeline {
-    type Key = MeshPipelineKey;
+/// Returns the shader defs selecting the chunk lengths of the terrain shader, see `terrain_uniforms.wgsl`.
+fn chunk_size_shader_defs() -> Vec<String> {
+    let mut shader_defs = Vec::new();
+    if cfg!(feature = "chunk_16") {
+        shader_defs.push("CHUNK_16".to_string());
+    }
+    if cfg!(feature = "chunk_64") {
+        shader_defs.push("CHUNK_64".to_string());
+    }
+    if cfg!(feature = "chunk_tall") {
+        shader_defs.push("CHUNK_TALL".to_string());
+    }
+    shader_defs
+}
 
-    fn specialize(
+impl VoxelTerrainRenderPipeline {
+    /// Returns the descriptor of the pipeline using the specified shader.
+    pub(super) fn descriptor(
         &self,
-        key: Self::Key,
+        shader: Handle<Shader>,
+        key: MeshPipelineKey,
         layout: &MeshVertexBufferLayout,
-    ) -> Result<bevy::render::render_resource::RenderPipelineDescriptor, SpecializedMeshPipelineError>
-    {
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
         Ok(RenderPipelineDescriptor {
             vertex: VertexState {
-                shader: self.shader.clone(),
+                shader: shader.clone(),
                 entry_point: "vertex".into(),
-                shader_defs: Vec::new(),
+                shader_defs: chunk_size_shader_defs(),
                 buffers: vec![layout.get_layout(&[
-                    Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
-                    VoxelTerrainMesh::ATTRIBUTE_DATA.at_shader_location(1),
+                    VoxelTerrainMesh::ATTRIBUTE_DATA.at_shader_location(0),
+                    VoxelTerrainMesh::ATTRIBUTE_LIGHT.at_shader_location(1),
                 ])?],
             },
             fragment: Some(FragmentState {
-                shader: self.shader.clone(),
-                shader_defs: Vec::new(),
+                shader,
+                shader_defs: chunk_size_shader_defs(),
                 entry_point: "fragment".into(),
                 targets: vec![Some(ColorTargetState {
                     format: TextureFormat::bevy_default(),
@@ -142,6 +184,20 @@ impl SpecializedMeshPipeline for VoxelTerrainRenderPipeline {
     }
 }
 
+impl SpecializedMeshPipeline for VoxelTerrainRenderPipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<bevy::render::render_resource::RenderPipelineDescriptor, SpecializedMeshPipelineError>
+    {
+        // terrain meshes are drawn with the last version of the shader known to compile.
+        self.descriptor(TERRAIN_SHADER_HANDLE.typed(), key, layout)
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn queue_voxel_meshes(
     oq_draw_funcs: Res<DrawFunctions<AlphaMask3d>>,
@@ -151,14 +207,24 @@ fn queue_voxel_meshes(
     mut specialized_pipelines: ResMut<SpecializedMeshPipelines<VoxelTerrainRenderPipeline>>,
     msaa: Res<Msaa>,
     material_meshes: Query<(Entity, &Handle<Mesh>, &MeshUniform), With<VoxelTerrainMesh>>,
-    mut views: Query<(&ExtractedView, &mut RenderPhase<AlphaMask3d>)>,
+    mut views: Query<(
+        &ExtractedView,
+        &mut RenderPhase<AlphaMask3d>,
+        Option<&ViewRenderDistance>,
+    )>,
 ) {
     let draw_custom = oq_draw_funcs.read().get_id::<DrawVoxel>().unwrap();
     let key = MeshPipelineKey::from_msaa_samples(msaa.samples);
-    for (view, mut transparent_phase) in views.iter_mut() {
+    for (view, mut transparent_phase, render_distance) in views.iter_mut() {
         let view_matrix = view.transform.compute_matrix();
         let view_row_2 = view_matrix.row(2);
         material_meshes.for_each(|(entity, mesh_handle, mesh_uniform)| {
+            let chunk_min = mesh_uniform.transform.col(3).truncate();
+            if !render_distance.map_or(true, |distance| {
+                distance.contains_chunk(view.transform.translation(), chunk_min)
+            }) {
+                return;
+            }
             if let Some(mesh) = render_meshes.get(mesh_handle) {
                 transparent_phase.add(AlphaMask3d {
                     entity,
@@ -201,7 +267,16 @@ pub struct VoxelMeshRenderPipelinePlugin;
 impl Plugin for VoxelMeshRenderPipelinePlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_plugin(ExtractComponentPlugin::<VoxelTerrainMesh>::default())
-            .add_plugin(terrain_uniforms::VoxelTerrainUniformsPlugin);
+            .add_plugin(ExtractComponentPlugin::<ViewRenderDistance>::default())
+            .add_plugin(super::voxel_volume::VoxelVolumeTexturePlugin)
+            .add_plugin(terrain_uniforms::VoxelTerrainUniformsPlugin)
+            .add_plugin(super::chunk_mesh_buffers::ChunkMeshBuffersPlugin)
+            .add_plugin(super::chunk_culling::ChunkCullingPlugin)
+            .add_plugin(super::terrain_shadows::TerrainShadowsPlugin)
+            .add_plugin(shader_reload::TerrainShaderReloadPlugin)
+            .add_plugin(super::foliage::FoliageRenderPlugin);
+        #[cfg(feature = "gpu_meshing")]
+        app.add_plugin(super::gpu_meshing::GpuMeshingPlugin);
         app.sub_app_mut(RenderApp)
             .add_render_command::<AlphaMask3d, DrawVoxel>()
             .init_resource::<VoxelTerrainRenderPipeline>()
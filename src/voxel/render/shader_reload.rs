@@ -0,0 +1,156 @@
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Mutex,
+};
+
+use bevy::{
+    pbr::MeshPipelineKey,
+    prelude::{
+        error, info, AssetServer, Assets, Handle, HandleUntyped, Mesh, Msaa, Plugin, Res, ResMut,
+        Shader,
+    },
+    reflect::TypeUuid,
+    render::{
+        mesh::{MeshVertexBufferLayout, VertexAttributeValues},
+        render_resource::{
+            CachedPipelineState, CachedRenderPipelineId, PipelineCache, PipelineCacheError,
+            PrimitiveTopology,
+        },
+        RenderApp, RenderStage,
+    },
+};
+
+use super::{VoxelTerrainMesh, VoxelTerrainRenderPipeline};
+
+/// Path of the terrain shader file, relative to the assets folder.
+pub const TERRAIN_SHADER_PATH: &str = "shaders/terrain_pipeline.wgsl";
+
+/// Handle of the last version of the terrain shader known to compile, which is the one used for drawing the terrain.
+pub const TERRAIN_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x6f3c_1b2e_94d7_a580);
+
+/// Outcome of the compilation of a new version of the terrain shader file.
+type ShaderCompilation = Result<(), String>;
+
+struct ShaderCompilationSender(Mutex<Sender<ShaderCompilation>>);
+
+struct ShaderCompilationReceiver(Mutex<Receiver<ShaderCompilation>>);
+
+/// The terrain shader as loaded from its file.
+struct TerrainShaderSource(Handle<Shader>);
+
+/// A pipeline compiled from the terrain shader as loaded from its file, used for checking that each of its versions compiles before using it.
+/// The pipeline cache recompiles it by itself whenever the shader file changes.
+struct TerrainShaderProbe {
+    layout: MeshVertexBufferLayout,
+    pipeline: Option<CachedRenderPipelineId>,
+    reported: bool,
+}
+
+impl Default for TerrainShaderProbe {
+    fn default() -> Self {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(
+            VoxelTerrainMesh::ATTRIBUTE_DATA,
+            VertexAttributeValues::Uint32(Vec::new()),
+        );
+        mesh.insert_attribute(
+            VoxelTerrainMesh::ATTRIBUTE_LIGHT,
+            VertexAttributeValues::Uint32(Vec::new()),
+        );
+
+        Self {
+            layout: mesh.get_mesh_vertex_buffer_layout(),
+            pipeline: None,
+            reported: false,
+        }
+    }
+}
+
+/// Reports the outcome of each compilation of the terrain shader file to the main world.
+fn probe_terrain_shader(
+    voxel_pipeline: Res<VoxelTerrainRenderPipeline>,
+    msaa: Res<Msaa>,
+    sender: Res<ShaderCompilationSender>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    mut probe: ResMut<TerrainShaderProbe>,
+) {
+    let probe = &mut *probe;
+    let pipeline = match probe.pipeline {
+        Some(pipeline) => pipeline,
+        None => {
+            let key = MeshPipelineKey::from_msaa_samples(msaa.samples)
+                | MeshPipelineKey::from_primitive_topology(PrimitiveTopology::TriangleList);
+            let descriptor = voxel_pipeline
+                .descriptor(voxel_pipeline.source_shader.clone(), key, &probe.layout)
+                .unwrap();
+            *probe
+                .pipeline
+                .insert(pipeline_cache.queue_render_pipeline(descriptor))
+        }
+    };
+
+    let compilation = match pipeline_cache.get_render_pipeline_state(pipeline) {
+        CachedPipelineState::Ok(_) => Ok(()),
+        CachedPipelineState::Err(PipelineCacheError::ShaderNotLoaded(_))
+        | CachedPipelineState::Queued => {
+            // the shader file changed, report the outcome of its next compilation.
+            probe.reported = false;
+            return;
+        }
+        CachedPipelineState::Err(err) => Err(err.to_string()),
+    };
+
+    if !probe.reported {
+        probe.reported = true;
+        let _ = sender.0.lock().unwrap().send(compilation);
+    }
+}
+
+/// Replaces the terrain shader in use by the newest version of the shader file once it compiled, or keeps the previous one if it failed to.
+fn update_terrain_shader(
+    receiver: Res<ShaderCompilationReceiver>,
+    source: Res<TerrainShaderSource>,
+    mut shaders: ResMut<Assets<Shader>>,
+) {
+    for compilation in receiver.0.lock().unwrap().try_iter() {
+        match compilation {
+            Ok(()) => {
+                if let Some(shader) = shaders.get(&source.0).cloned() {
+                    let reloaded = shaders.get(&TERRAIN_SHADER_HANDLE.typed()).is_some();
+                    shaders.set_untracked(TERRAIN_SHADER_HANDLE, shader);
+                    if reloaded {
+                        info!("Reloaded the terrain shader");
+                    }
+                }
+            }
+            Err(err) => error!(
+                "Failed to compile the terrain shader, keeping its previous version: {}",
+                err
+            ),
+        }
+    }
+}
+
+/// Hot reloads the terrain shader when its file changes (if the asset server watches for changes), falling back to the previous version when the new one doesn't compile.
+/// Changes to the files imported by the terrain shader are applied as is.
+pub struct TerrainShaderReloadPlugin;
+
+impl Plugin for TerrainShaderReloadPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let (sender, receiver) = channel();
+        let source = app
+            .world
+            .resource::<AssetServer>()
+            .load(TERRAIN_SHADER_PATH);
+
+        app.insert_resource(ShaderCompilationReceiver(Mutex::new(receiver)))
+            .insert_resource(TerrainShaderSource(source))
+            .add_system(update_terrain_shader);
+
+        app.sub_app_mut(RenderApp)
+            .insert_resource(ShaderCompilationSender(Mutex::new(sender)))
+            .init_resource::<TerrainShaderProbe>()
+            .add_system_to_stage(RenderStage::Queue, probe_terrain_shader);
+    }
+}
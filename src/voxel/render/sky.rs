@@ -0,0 +1,215 @@
+use bevy::{
+    math::Vec3,
+    pbr::{MaterialMeshBundle, MaterialPipeline, MaterialPipelineKey},
+    prelude::{
+        shape, AlphaMode, Assets, Color, Commands, Handle, Material, MaterialPlugin, Mesh, Plugin,
+        Res, ResMut,
+    },
+    reflect::TypeUuid,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_resource::{
+            AsBindGroup, CompareFunction, RenderPipelineDescriptor, ShaderRef, ShaderType,
+            SpecializedMeshPipelineError,
+        },
+        view::NoFrustumCulling,
+    },
+};
+
+use crate::voxel::SkyShadowSettings;
+
+/// Path of the shader drawing the sky behind the terrain.
+const SKY_SHADER_PATH: &str = "shaders/sky.wgsl";
+
+/// Colors of the sky, its horizon color is also the color of the distance fog of the terrain so
+/// the terrain fades into the sky at the edge of the render distance.
+pub struct SkySettings {
+    /// Color of the sky straight up.
+    pub zenith_color: Color,
+    /// Color of the sky at the horizon, and of the terrain distance fog.
+    pub horizon_color: Color,
+    /// Color of the sky below the horizon.
+    pub ground_color: Color,
+    /// Color of the sun disk and of the glow scattered around it.
+    pub sun_color: Color,
+    /// How fast the horizon color fades into the zenith color, higher values keep a thinner horizon band.
+    pub gradient_exponent: f32,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        Self {
+            zenith_color: Color::rgb(0.18, 0.36, 0.72),
+            horizon_color: Color::rgb(0.62, 0.74, 0.86),
+            ground_color: Color::rgb(0.35, 0.4, 0.46),
+            sun_color: Color::rgb(1.0, 0.92, 0.75),
+            gradient_exponent: 0.45,
+        }
+    }
+}
+
+impl SkySettings {
+    /// Returns the color of the distance fog matching this sky.
+    pub fn fog_color(&self) -> Color {
+        self.horizon_color
+    }
+}
+
+/// Distance fog of the terrain, hiding the edge of the loaded area.
+/// The distances are fractions of the horizontal render distance so the fog follows its changes.
+pub struct DistanceFogSettings {
+    /// Distance at which the fog starts to fade the terrain.
+    pub start: f32,
+    /// Distance at which the terrain is entirely faded into the fog.
+    pub end: f32,
+    /// Color of the fog, the sky horizon color when `None`.
+    pub color: Option<Color>,
+}
+
+impl Default for DistanceFogSettings {
+    fn default() -> Self {
+        Self {
+            start: 0.7,
+            end: 1.0,
+            color: None,
+        }
+    }
+}
+
+impl DistanceFogSettings {
+    /// Returns the color of the fog drawn in front of the given sky.
+    pub fn color(&self, sky: &SkySettings) -> Color {
+        self.color.unwrap_or_else(|| sky.fog_color())
+    }
+}
+
+/// Fog seen while the camera is submerged in a liquid, whose color is the `submerged_fog` of the liquid material.
+/// The fog thickens and the colors get absorbed with the distance traveled through the liquid by the light, so that
+/// deep liquids look murky while shallow ones stay clear. Distances are in voxels.
+pub struct SubmergedFogSettings {
+    /// Fog density, the fraction of the liquid fog opacity reached after `d` voxels being `1 - exp(-density * d)`.
+    pub density: f32,
+    /// How much of each color channel (red, green and blue) is absorbed per voxel, red fading first in water.
+    pub absorption: Vec3,
+}
+
+impl Default for SubmergedFogSettings {
+    fn default() -> Self {
+        Self {
+            density: 0.25,
+            absorption: Vec3::new(0.06, 0.025, 0.015),
+        }
+    }
+}
+
+/// Sky colors and sun direction, as read by the sky shader.
+#[derive(Clone, Copy, Default, ShaderType)]
+pub struct SkyUniform {
+    pub zenith_color: Color,
+    pub horizon_color: Color,
+    pub ground_color: Color,
+    pub sun_color: Color,
+    /// Direction pointing toward the sun.
+    pub sun_direction: Vec3,
+    pub gradient_exponent: f32,
+}
+
+/// Material of the sky box, drawn at the far plane around whichever camera renders it.
+#[derive(AsBindGroup, TypeUuid, Clone, Default)]
+#[uuid = "3f6c2d91-7b4e-4a15-9e83-c02d5a6b1f47"]
+pub struct SkyMaterial {
+    #[uniform(0)]
+    pub sky: SkyUniform,
+}
+
+impl Material for SkyMaterial {
+    fn vertex_shader() -> ShaderRef {
+        SKY_SHADER_PATH.into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        SKY_SHADER_PATH.into()
+    }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Opaque
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.vertex.buffers =
+            vec![layout.get_layout(&[Mesh::ATTRIBUTE_POSITION.at_shader_location(0)])?];
+        // the box is seen from the inside.
+        descriptor.primitive.cull_mode = None;
+        // the sky is projected on the far plane, it is only drawn where nothing else was and hides nothing.
+        if let Some(depth_stencil) = descriptor.depth_stencil.as_mut() {
+            depth_stencil.depth_write_enabled = false;
+            depth_stencil.depth_compare = CompareFunction::GreaterEqual;
+        }
+        Ok(())
+    }
+}
+
+/// The material shared by the sky box.
+pub struct SkyMaterialHandle(pub Handle<SkyMaterial>);
+
+fn setup_sky(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    handle: Res<SkyMaterialHandle>,
+) {
+    // the vertex shader centers the box on the camera, it must never be culled.
+    commands
+        .spawn_bundle(MaterialMeshBundle {
+            mesh: meshes.add(Mesh::from(shape::Cube { size: 2.0 })),
+            material: handle.0.clone(),
+            ..Default::default()
+        })
+        .insert(NoFrustumCulling);
+}
+
+fn update_sky_material(
+    settings: Res<SkySettings>,
+    sky_shadows: Res<SkyShadowSettings>,
+    handle: Res<SkyMaterialHandle>,
+    mut materials: ResMut<Assets<SkyMaterial>>,
+) {
+    if !settings.is_changed() && !sky_shadows.is_changed() {
+        return;
+    }
+
+    if let Some(material) = materials.get_mut(&handle.0) {
+        material.sky = SkyUniform {
+            zenith_color: settings.zenith_color,
+            horizon_color: settings.horizon_color,
+            ground_color: settings.ground_color,
+            sun_color: settings.sun_color,
+            sun_direction: sky_shadows.sun_direction.normalize_or_zero(),
+            gradient_exponent: settings.gradient_exponent,
+        };
+    }
+}
+
+/// Renders a sky gradient with a scattered sun glow behind the terrain.
+pub struct SkyPlugin;
+
+impl Plugin for SkyPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_plugin(MaterialPlugin::<SkyMaterial>::default());
+
+        let handle = app
+            .world
+            .resource_mut::<Assets<SkyMaterial>>()
+            .add(SkyMaterial::default());
+        app.insert_resource(SkyMaterialHandle(handle))
+            .init_resource::<SkySettings>()
+            .init_resource::<DistanceFogSettings>()
+            .init_resource::<SubmergedFogSettings>()
+            .add_startup_system(setup_sky)
+            .add_system(update_sky_material);
+    }
+}
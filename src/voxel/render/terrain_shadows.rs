@@ -0,0 +1,682 @@
+use std::num::NonZeroU32;
+
+use bevy::{
+    math::{Mat4, Vec3, Vec4},
+    prelude::{
+        AssetServer, Commands, CoreStage, Entity, FromWorld, GlobalTransform, Handle, Mesh,
+        ParallelSystemDescriptorCoercion, Plugin, Query, Res, ResMut, With, World,
+    },
+    render::{
+        camera::Projection,
+        mesh::{GpuBufferInfo, MeshVertexBufferLayout, VertexAttributeValues},
+        render_asset::RenderAssets,
+        render_graph::{Node, NodeRunError, RenderGraph, RenderGraphContext},
+        render_phase::TrackedRenderPass,
+        render_resource::{
+            AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, BufferBindingType,
+            CachedRenderPipelineId, CompareFunction, DepthBiasState, DepthStencilState,
+            DynamicUniformBuffer, Extent3d, FilterMode, IndexFormat, LoadOp, MultisampleState,
+            Operations, PipelineCache, PolygonMode, PrimitiveState, PrimitiveTopology,
+            RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipelineDescriptor,
+            Sampler, SamplerDescriptor, ShaderStages, ShaderType, StencilState, StorageBuffer,
+            Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+            TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, VertexState,
+        },
+        renderer::{RenderContext, RenderDevice, RenderQueue},
+        view::Visibility,
+        Extract, RenderApp, RenderStage,
+    },
+    transform::TransformSystem,
+};
+
+use super::{chunk_mesh_buffers::ChunkMeshBuffers, VoxelTerrainMesh};
+use crate::voxel::{player::PlayerController, SkyShadowSettings, CHUNK_HEIGHT, CHUNK_LENGTH};
+
+/// Path of the shader rendering the terrain into the shadow maps, relative to the assets folder.
+const TERRAIN_SHADOW_PASS_SHADER_PATH: &str = "shaders/terrain_shadow_pass.wgsl";
+
+/// Maximum number of shadow cascades, matching the size of the cascade arrays of the terrain shader.
+pub const MAX_SHADOW_CASCADES: usize = 4;
+
+/// Distance (in voxels) the cascades extend toward the sun past the area they shadow, so that the terrain above that
+/// area (e.g. a mountain out of view) still casts its shadows.
+const CASTER_MARGIN: f32 = 128.0;
+
+const SHADOW_MAP_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// Settings of the sun shadows cast by the terrain meshes, rendered into shadow maps split into cascades of
+/// increasing size away from the camera. They darken the sunlight along the shadows approximated from the sky shadow
+/// heightfield, which keeps shading the terrain beyond [`Self::max_distance`].
+pub struct TerrainShadowSettings {
+    pub enabled: bool,
+    /// Number of cascades splitting the shadowed distance, from 1 to [`MAX_SHADOW_CASCADES`].
+    pub cascade_count: usize,
+    /// Width and height (in texels) of the shadow map of each cascade.
+    pub resolution: u32,
+    /// Distance from the camera (in voxels) up to which the terrain receives shadows.
+    pub max_distance: f32,
+    /// Blend between evenly spaced cascade splits (0) and logarithmic splits (1), which give more resolution to the
+    /// shadows close to the camera.
+    pub split_lambda: f32,
+    /// Offset (in voxels) of the shadowed positions along the face normals, preventing shadow acne.
+    pub normal_bias: f32,
+    /// How much the shadows darken the sunlight, from 0 to 1.
+    pub strength: f32,
+}
+
+impl Default for TerrainShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cascade_count: 3,
+            resolution: 2048,
+            max_distance: 256.0,
+            split_lambda: 0.75,
+            normal_bias: 0.1,
+            strength: 0.6,
+        }
+    }
+}
+
+/// A shadow cascade, an orthographic view along the sun light covering a slice of the camera frustum.
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowCascade {
+    /// Transform from the rendered positions to the view space of the cascade, looking along the sun light.
+    pub view: Mat4,
+    /// Half of the width and height of the area covered by the cascade, in voxels.
+    pub half_extent: f32,
+    /// Depth covered by the cascade along the sun light, in voxels.
+    pub depth: f32,
+    /// Distance from the camera (along its view direction) up to which the cascade shadows the terrain.
+    pub far: f32,
+}
+
+impl ShadowCascade {
+    pub fn view_projection(&self) -> Mat4 {
+        let extent = self.half_extent;
+        Mat4::orthographic_rh(-extent, extent, -extent, extent, 0.0, self.depth) * self.view
+    }
+
+    /// Returns whether a sphere (in rendered positions) overlaps the area covered by the cascade.
+    fn overlaps(&self, center: Vec3, radius: f32) -> bool {
+        let center = self.view.transform_point3(center);
+        center.x.abs() <= self.half_extent + radius
+            && center.y.abs() <= self.half_extent + radius
+            && center.z <= radius
+            && center.z >= -self.depth - radius
+    }
+}
+
+/// The shadow cascades of the current frame, fitted to the view of the player camera.
+#[derive(Default)]
+pub struct TerrainShadowCascades(pub Vec<ShadowCascade>);
+
+/// Fits the shadow cascades to the slices of the player camera frustum.
+/// The cascades are bounding spheres of the slices, with their positions snapped to the shadow map texels, so that
+/// their shadows don't shimmer as the camera turns or moves.
+fn update_terrain_shadow_cascades(
+    settings: Res<TerrainShadowSettings>,
+    sky_shadows: Res<SkyShadowSettings>,
+    camera: Query<(&GlobalTransform, &Projection), With<PlayerController>>,
+    mut cascades: ResMut<TerrainShadowCascades>,
+) {
+    cascades.0.clear();
+
+    let sun = sky_shadows.sun_direction.normalize_or_zero();
+    let (transform, perspective) = match camera.get_single() {
+        Ok((transform, Projection::Perspective(perspective)))
+            if settings.enabled && sun != Vec3::ZERO =>
+        {
+            (transform, perspective)
+        }
+        _ => return,
+    };
+
+    let count = settings.cascade_count.clamp(1, MAX_SHADOW_CASCADES);
+    let near = perspective.near;
+    let far = settings.max_distance.max(near + 1.0);
+    let split = |index: usize| {
+        let t = index as f32 / count as f32;
+        let even = near + (far - near) * t;
+        let logarithmic = near * (far / near).powf(t);
+        even + (logarithmic - even) * settings.split_lambda
+    };
+
+    let camera_position = transform.translation();
+    let (forward, right, up) = (transform.forward(), transform.right(), transform.up());
+    let tan_half_fov = (perspective.fov / 2.0).tan();
+    // any axis not parallel to the sun light does.
+    let light_up = if sun.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+    let light_rotation = Mat4::look_at_rh(Vec3::ZERO, -sun, light_up);
+
+    for index in 0..count {
+        let (slice_near, slice_far) = (split(index), split(index + 1));
+        let corners: Vec<Vec3> = [slice_near, slice_far]
+            .into_iter()
+            .flat_map(|depth| {
+                let half_height = depth * tan_half_fov;
+                let half_width = half_height * perspective.aspect_ratio;
+                let center = camera_position + forward * depth;
+                [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+                    .map(|(x, y)| center + right * x * half_width + up * y * half_height)
+            })
+            .collect();
+
+        let center = corners.iter().copied().sum::<Vec3>() / corners.len() as f32;
+        // the radius only depends on the slice, not on the camera orientation.
+        let radius = corners
+            .iter()
+            .map(|corner| corner.distance(center))
+            .fold(0.0, f32::max)
+            .ceil();
+
+        let texel_size = 2.0 * radius / settings.resolution.max(1) as f32;
+        let light_center = light_rotation.transform_point3(center);
+        let snapped = Vec3::new(
+            (light_center.x / texel_size).floor() * texel_size,
+            (light_center.y / texel_size).floor() * texel_size,
+            light_center.z,
+        );
+        let center = light_rotation.inverse().transform_point3(snapped);
+
+        cascades.0.push(ShadowCascade {
+            view: Mat4::look_at_rh(center + sun * (radius + CASTER_MARGIN), center, light_up),
+            half_extent: radius,
+            depth: 2.0 * radius + CASTER_MARGIN,
+            far: slice_far,
+        });
+    }
+}
+
+/// The shadow cascades and the terrain meshes casting shadows, extracted for the shadow pass.
+struct ExtractedTerrainShadows {
+    resolution: u32,
+    normal_bias: f32,
+    strength: f32,
+    cascades: Vec<ShadowCascade>,
+    /// Chunk entities and meshes along with their transform, the chunks hidden by the occlusion culling still casting
+    /// their shadows.
+    casters: Vec<(Entity, Handle<Mesh>, Mat4)>,
+}
+
+fn extract_terrain_shadows(
+    mut commands: Commands,
+    settings: Extract<Res<TerrainShadowSettings>>,
+    cascades: Extract<Res<TerrainShadowCascades>>,
+    chunks: Extract<
+        Query<(Entity, &Handle<Mesh>, &GlobalTransform, &Visibility), With<VoxelTerrainMesh>>,
+    >,
+) {
+    let casters = if cascades.0.is_empty() {
+        Vec::new()
+    } else {
+        chunks
+            .iter()
+            .filter(|(_, _, _, visibility)| visibility.is_visible)
+            .map(|(entity, mesh, transform, _)| (entity, mesh.clone(), transform.compute_matrix()))
+            .collect()
+    };
+
+    commands.insert_resource(ExtractedTerrainShadows {
+        resolution: settings.resolution.max(1),
+        normal_bias: settings.normal_bias,
+        strength: settings.strength,
+        cascades: cascades.0.clone(),
+        casters,
+    });
+}
+
+// shadow cascades as read by the terrain shader
+#[derive(ShaderType, Default, Clone)]
+pub(super) struct GpuTerrainShadows {
+    // transforms from the rendered positions to the clip space of each cascade
+    pub view_projections: [Mat4; MAX_SHADOW_CASCADES],
+    // distance from the camera along its view direction up to which each cascade is used
+    pub splits: Vec4,
+    // 0 when the shadows are disabled
+    pub cascade_count: u32,
+    pub normal_bias: f32,
+    pub strength: f32,
+}
+
+#[derive(ShaderType, Clone)]
+struct GpuShadowCascadeView {
+    view_projection: Mat4,
+}
+
+#[derive(ShaderType, Clone)]
+struct GpuShadowCaster {
+    model: Mat4,
+}
+
+/// The terrain meshes drawn into the shadow map of a cascade.
+struct ShadowCascadePass {
+    view_offset: u32,
+    /// Chunk entities and meshes along with the offset of their transform.
+    casters: Vec<(Entity, Handle<Mesh>, u32)>,
+}
+
+/// The shadow maps of the cascades, along with the buffers and bind groups of the shadow pass.
+pub(super) struct TerrainShadowMaps {
+    texture: Texture,
+    /// View of each cascade of the shadow maps, the shadow pass rendering into them one by one.
+    layer_views: Vec<TextureView>,
+    /// View of all the cascades, sampled by the terrain shader.
+    pub(super) cascades_view: TextureView,
+    pub(super) sampler: Sampler,
+    pub(super) params: StorageBuffer<GpuTerrainShadows>,
+    resolution: u32,
+    cascade_views: DynamicUniformBuffer<GpuShadowCascadeView>,
+    casters: DynamicUniformBuffer<GpuShadowCaster>,
+    cascade_views_bind_group: Option<BindGroup>,
+    casters_bind_group: Option<BindGroup>,
+    passes: Vec<ShadowCascadePass>,
+}
+
+impl TerrainShadowMaps {
+    fn create_texture(
+        render_device: &RenderDevice,
+        resolution: u32,
+        layers: u32,
+    ) -> (Texture, Vec<TextureView>, TextureView) {
+        let texture = render_device.create_texture(&TextureDescriptor {
+            label: Some("terrain_shadow_maps"),
+            size: Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: SHADOW_MAP_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+
+        let view = |dimension, base_array_layer, array_layer_count| {
+            texture.create_view(&TextureViewDescriptor {
+                label: Some("terrain_shadow_maps_view"),
+                format: None,
+                dimension: Some(dimension),
+                aspect: TextureAspect::All,
+                base_mip_level: 0,
+                mip_level_count: None,
+                base_array_layer,
+                array_layer_count,
+            })
+        };
+        let layer_views = (0..layers)
+            .map(|layer| view(TextureViewDimension::D2, layer, NonZeroU32::new(1)))
+            .collect();
+        let cascades_view = view(TextureViewDimension::D2Array, 0, NonZeroU32::new(layers));
+
+        (texture, layer_views, cascades_view)
+    }
+}
+
+impl FromWorld for TerrainShadowMaps {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        // a placeholder until the shadows get enabled, the terrain shader always sampling a shadow map.
+        let (texture, layer_views, cascades_view) = Self::create_texture(render_device, 1, 1);
+        let sampler = render_device.create_sampler(&SamplerDescriptor {
+            label: Some("terrain_shadow_maps_sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            // filtered comparisons soften the edges of the shadows.
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Nearest,
+            compare: Some(CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let mut params = StorageBuffer::default();
+        params.write_buffer(render_device, world.resource::<RenderQueue>());
+
+        Self {
+            texture,
+            layer_views,
+            cascades_view,
+            sampler,
+            params,
+            resolution: 1,
+            cascade_views: DynamicUniformBuffer::default(),
+            casters: DynamicUniformBuffer::default(),
+            cascade_views_bind_group: None,
+            casters_bind_group: None,
+            passes: Vec::new(),
+        }
+    }
+}
+
+/// Resizes the shadow maps to the cascade settings, and uploads the cascades and the transforms of the shadow casters
+/// overlapping them.
+fn prepare_terrain_shadows(
+    extracted: Res<ExtractedTerrainShadows>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut shadow_maps: ResMut<TerrainShadowMaps>,
+) {
+    let shadow_maps = &mut *shadow_maps;
+    let layers = extracted.cascades.len().max(1) as u32;
+    if !extracted.cascades.is_empty()
+        && (shadow_maps.resolution != extracted.resolution
+            || shadow_maps.layer_views.len() != layers as usize)
+    {
+        let (texture, layer_views, cascades_view) =
+            TerrainShadowMaps::create_texture(&render_device, extracted.resolution, layers);
+        // the shadow maps can take a lot of memory, so it's freed without waiting for the views to be dropped.
+        shadow_maps.texture.destroy();
+        shadow_maps.texture = texture;
+        shadow_maps.layer_views = layer_views;
+        shadow_maps.cascades_view = cascades_view;
+        shadow_maps.resolution = extracted.resolution;
+    }
+
+    let mut params = GpuTerrainShadows {
+        cascade_count: extracted.cascades.len() as u32,
+        normal_bias: extracted.normal_bias,
+        strength: extracted.strength,
+        ..Default::default()
+    };
+    for (index, cascade) in extracted.cascades.iter().enumerate() {
+        params.view_projections[index] = cascade.view_projection();
+        params.splits[index] = cascade.far;
+    }
+    shadow_maps.params.set(params);
+    shadow_maps
+        .params
+        .write_buffer(&render_device, &render_queue);
+
+    shadow_maps.cascade_views.clear();
+    shadow_maps.casters.clear();
+    shadow_maps.passes.clear();
+    if extracted.cascades.is_empty() {
+        return;
+    }
+
+    shadow_maps.passes = extracted
+        .cascades
+        .iter()
+        .map(|cascade| ShadowCascadePass {
+            view_offset: shadow_maps.cascade_views.push(GpuShadowCascadeView {
+                view_projection: cascade.view_projection(),
+            }),
+            casters: Vec::new(),
+        })
+        .collect();
+
+    let chunk_extent = Vec3::new(
+        CHUNK_LENGTH as f32,
+        CHUNK_HEIGHT as f32,
+        CHUNK_LENGTH as f32,
+    );
+    let chunk_radius = chunk_extent.length() / 2.0;
+    for (entity, mesh, model) in extracted.casters.iter() {
+        let center = model.transform_point3(chunk_extent / 2.0);
+        // the transform is only uploaded once for all the cascades the chunk overlaps.
+        let mut caster_offset = None;
+        for (cascade, pass) in extracted.cascades.iter().zip(shadow_maps.passes.iter_mut()) {
+            if cascade.overlaps(center, chunk_radius) {
+                let offset = *caster_offset.get_or_insert_with(|| {
+                    shadow_maps.casters.push(GpuShadowCaster { model: *model })
+                });
+                pass.casters.push((*entity, mesh.clone(), offset));
+            }
+        }
+    }
+
+    shadow_maps
+        .cascade_views
+        .write_buffer(&render_device, &render_queue);
+    shadow_maps
+        .casters
+        .write_buffer(&render_device, &render_queue);
+}
+
+/// The pipeline rendering the depth of the terrain meshes, as seen from the sun, into the shadow maps.
+struct TerrainShadowPipeline {
+    cascade_view_layout: BindGroupLayout,
+    caster_layout: BindGroupLayout,
+    pipeline: CachedRenderPipelineId,
+}
+
+impl FromWorld for TerrainShadowPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let uniform_layout = |label, min_binding_size| {
+            render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(min_binding_size),
+                    },
+                    count: None,
+                }],
+            })
+        };
+        let cascade_view_layout = uniform_layout(
+            "terrain_shadow_cascade_layout",
+            GpuShadowCascadeView::min_size(),
+        );
+        let caster_layout =
+            uniform_layout("terrain_shadow_caster_layout", GpuShadowCaster::min_size());
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(
+            VoxelTerrainMesh::ATTRIBUTE_DATA,
+            VertexAttributeValues::Uint32(Vec::new()),
+        );
+        mesh.insert_attribute(
+            VoxelTerrainMesh::ATTRIBUTE_LIGHT,
+            VertexAttributeValues::Uint32(Vec::new()),
+        );
+        let layout: MeshVertexBufferLayout = mesh.get_mesh_vertex_buffer_layout();
+
+        let shader = world
+            .resource::<AssetServer>()
+            .load(TERRAIN_SHADOW_PASS_SHADER_PATH);
+        let pipeline =
+            world
+                .resource_mut::<PipelineCache>()
+                .queue_render_pipeline(RenderPipelineDescriptor {
+                    label: Some("terrain_shadow_pipeline".into()),
+                    layout: Some(vec![cascade_view_layout.clone(), caster_layout.clone()]),
+                    vertex: VertexState {
+                        shader,
+                        shader_defs: Vec::new(),
+                        entry_point: "vertex".into(),
+                        buffers: vec![layout
+                            .get_layout(&[VoxelTerrainMesh::ATTRIBUTE_DATA.at_shader_location(0)])
+                            .unwrap()],
+                    },
+                    // only the depth gets rendered.
+                    fragment: None,
+                    primitive: PrimitiveState {
+                        // the back faces keep the shadows of the thin terrain features (e.g. a single voxel wall).
+                        cull_mode: None,
+                        polygon_mode: PolygonMode::Fill,
+                        topology: PrimitiveTopology::TriangleList,
+                        ..Default::default()
+                    },
+                    depth_stencil: Some(DepthStencilState {
+                        format: SHADOW_MAP_FORMAT,
+                        depth_write_enabled: true,
+                        depth_compare: CompareFunction::Less,
+                        stencil: StencilState::default(),
+                        bias: DepthBiasState {
+                            constant: 2,
+                            slope_scale: 2.0,
+                            clamp: 0.0,
+                        },
+                    }),
+                    multisample: MultisampleState::default(),
+                });
+
+        Self {
+            cascade_view_layout,
+            caster_layout,
+            pipeline,
+        }
+    }
+}
+
+fn queue_terrain_shadows(
+    render_device: Res<RenderDevice>,
+    pipeline: Res<TerrainShadowPipeline>,
+    mut shadow_maps: ResMut<TerrainShadowMaps>,
+) {
+    let shadow_maps = &mut *shadow_maps;
+    let bind_group = |layout, binding| {
+        render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("terrain_shadow_bind_group"),
+            layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: binding,
+            }],
+        })
+    };
+
+    shadow_maps.cascade_views_bind_group = shadow_maps
+        .cascade_views
+        .binding()
+        .map(|binding| bind_group(&pipeline.cascade_view_layout, binding));
+    shadow_maps.casters_bind_group = shadow_maps
+        .casters
+        .binding()
+        .map(|binding| bind_group(&pipeline.caster_layout, binding));
+}
+
+/// Renders the shadow maps of the cascades, before the cameras render the terrain sampling them.
+struct TerrainShadowPassNode;
+
+impl Node for TerrainShadowPassNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let shadow_maps = world.resource::<TerrainShadowMaps>();
+        let (cascade_views, casters) = match (
+            &shadow_maps.cascade_views_bind_group,
+            &shadow_maps.casters_bind_group,
+        ) {
+            (Some(cascade_views), Some(casters)) if !shadow_maps.passes.is_empty() => {
+                (cascade_views, casters)
+            }
+            _ => return Ok(()),
+        };
+        let pipeline = match world
+            .resource::<PipelineCache>()
+            .get_render_pipeline(world.resource::<TerrainShadowPipeline>().pipeline)
+        {
+            Some(pipeline) => pipeline,
+            None => return Ok(()),
+        };
+        let meshes = world.resource::<RenderAssets<Mesh>>();
+        let pooled_meshes = world.resource::<ChunkMeshBuffers>();
+
+        for (pass, layer_view) in shadow_maps.passes.iter().zip(&shadow_maps.layer_views) {
+            let render_pass =
+                render_context
+                    .command_encoder
+                    .begin_render_pass(&RenderPassDescriptor {
+                        label: Some("terrain_shadow_pass"),
+                        color_attachments: &[],
+                        depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                            view: layer_view,
+                            depth_ops: Some(Operations {
+                                load: LoadOp::Clear(1.0),
+                                store: true,
+                            }),
+                            stencil_ops: None,
+                        }),
+                    });
+            let mut render_pass = TrackedRenderPass::new(render_pass);
+            render_pass.set_render_pipeline(pipeline);
+            render_pass.set_bind_group(0, cascade_views, &[pass.view_offset]);
+
+            for (entity, mesh, offset) in pass.casters.iter() {
+                // the chunks whose vertices are written in place have a placeholder mesh.
+                if let Some(buffers) = pooled_meshes.0.get(entity) {
+                    render_pass.set_bind_group(1, casters, &[*offset]);
+                    render_pass.set_vertex_buffer(0, buffers.vertices.buffer.slice(..));
+                    render_pass.set_index_buffer(
+                        buffers.indices.buffer.slice(..),
+                        0,
+                        IndexFormat::Uint32,
+                    );
+                    render_pass.draw_indexed(0..buffers.index_count, 0, 0..1);
+                    continue;
+                }
+
+                let gpu_mesh = match meshes.get(mesh) {
+                    Some(gpu_mesh) => gpu_mesh,
+                    None => continue,
+                };
+
+                render_pass.set_bind_group(1, casters, &[*offset]);
+                render_pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+                match &gpu_mesh.buffer_info {
+                    GpuBufferInfo::Indexed {
+                        buffer,
+                        index_format,
+                        count,
+                    } => {
+                        render_pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                        render_pass.draw_indexed(0..*count, 0, 0..1);
+                    }
+                    GpuBufferInfo::NonIndexed { vertex_count } => {
+                        render_pass.draw(0..*vertex_count, 0..1);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Label of the render graph node rendering the terrain shadow maps.
+const TERRAIN_SHADOW_PASS_NODE: &str = "terrain_shadow_pass";
+
+/// Cascaded sun shadow maps of the terrain, see [`TerrainShadowSettings`].
+/// The chunks meshed on the GPU don't cast shadows, their vertices only being known to the render world.
+pub struct TerrainShadowsPlugin;
+
+impl Plugin for TerrainShadowsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<TerrainShadowSettings>()
+            .init_resource::<TerrainShadowCascades>()
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                update_terrain_shadow_cascades.after(TransformSystem::TransformPropagate),
+            );
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .init_resource::<TerrainShadowMaps>()
+            .init_resource::<TerrainShadowPipeline>()
+            .add_system_to_stage(RenderStage::Extract, extract_terrain_shadows)
+            .add_system_to_stage(RenderStage::Prepare, prepare_terrain_shadows)
+            .add_system_to_stage(RenderStage::Queue, queue_terrain_shadows);
+
+        let mut graph = render_app.world.resource_mut::<RenderGraph>();
+        graph.add_node(TERRAIN_SHADOW_PASS_NODE, TerrainShadowPassNode);
+        graph
+            .add_node_edge(
+                TERRAIN_SHADOW_PASS_NODE,
+                bevy::render::main_graph::node::CAMERA_DRIVER,
+            )
+            .unwrap();
+    }
+}
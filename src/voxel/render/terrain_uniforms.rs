@@ -1,27 +1,43 @@
 use std::default;
 
 use bevy::{
-    ecs::system::lifetimeless::SRes,
-    prelude::{info, Color, Commands, Entity, FromWorld, Plugin, Res, ResMut},
+    ecs::system::lifetimeless::{Read, SQuery, SRes},
+    math::{IVec2, Vec2, Vec3, Vec4},
+    prelude::{Color, Commands, Component, Entity, FromWorld, Plugin, Query, Res, ResMut, With},
     render::{
         render_phase::EntityRenderCommand,
         render_resource::{
             BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
-            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType, ShaderStages, ShaderType,
-            StorageBuffer,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+            DynamicUniformBuffer, SamplerBindingType, ShaderStages, ShaderType, StorageBuffer,
+            TextureSampleType, TextureViewDimension,
         },
         renderer::{RenderDevice, RenderQueue},
+        view::ExtractedView,
         Extract, RenderApp, RenderStage,
     },
+    time::Time,
 };
 
-use crate::voxel::{material::VoxelMaterialRegistry, ChunkLoadRadius};
+use crate::voxel::{
+    material::{LightAnimation, VoxelMaterialRegistry},
+    CameraSubmersion, ChunkLoadRadius, SkyShadowHeightfield, SkyShadowSettings, WorldOrigin,
+    CHUNK_LENGTH, SKY_SHADOW_NO_HEIGHT,
+};
+
+use super::{
+    terrain_shadows::{GpuTerrainShadows, TerrainShadowMaps},
+    DistanceFogSettings, GpuVoxelVolume, GpuVoxelVolumeParams, SkySettings, SubmergedFogSettings,
+    ViewRenderDistance,
+};
 
 /// A resource wrapping buffer references and bind groups for the different uniforms used for rendering terrains
 pub struct TerrainUniforms {
     pub bind_group_layout: BindGroupLayout,
     materials_buffer: StorageBuffer<GpuTerrainMaterials>,
     render_distance_params: StorageBuffer<GpuTerrainRenderSettings>,
+    sky_shadow_heightfield: StorageBuffer<GpuSkyShadowHeightfield>,
+    view_settings: DynamicUniformBuffer<GpuTerrainViewSettings>,
     pub bind_group: Option<BindGroup>,
 }
 
@@ -56,10 +72,84 @@ impl FromWorld for TerrainUniforms {
                         count: None,
                         visibility: ShaderStages::VERTEX_FRAGMENT,
                     },
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        ty: BindingType::Buffer {
+                            has_dynamic_offset: false,
+                            ty: bevy::render::render_resource::BufferBindingType::Storage {
+                                read_only: true,
+                            },
+                            min_binding_size: None,
+                        },
+                        count: None,
+                        visibility: ShaderStages::FRAGMENT,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 3,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Uint,
+                            view_dimension: TextureViewDimension::D3,
+                            multisampled: false,
+                        },
+                        count: None,
+                        visibility: ShaderStages::VERTEX_FRAGMENT,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 4,
+                        ty: BindingType::Buffer {
+                            has_dynamic_offset: false,
+                            ty: bevy::render::render_resource::BufferBindingType::Storage {
+                                read_only: true,
+                            },
+                            min_binding_size: Some(GpuVoxelVolumeParams::min_size()),
+                        },
+                        count: None,
+                        visibility: ShaderStages::VERTEX_FRAGMENT,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 5,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Depth,
+                            view_dimension: TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                        visibility: ShaderStages::FRAGMENT,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 6,
+                        ty: BindingType::Sampler(SamplerBindingType::Comparison),
+                        count: None,
+                        visibility: ShaderStages::FRAGMENT,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 7,
+                        ty: BindingType::Buffer {
+                            has_dynamic_offset: false,
+                            ty: bevy::render::render_resource::BufferBindingType::Storage {
+                                read_only: true,
+                            },
+                            min_binding_size: Some(GpuTerrainShadows::min_size()),
+                        },
+                        count: None,
+                        visibility: ShaderStages::FRAGMENT,
+                    },
+                    BindGroupLayoutEntry {
+                        binding: 8,
+                        ty: BindingType::Buffer {
+                            has_dynamic_offset: true,
+                            ty: bevy::render::render_resource::BufferBindingType::Uniform,
+                            min_binding_size: Some(GpuTerrainViewSettings::min_size()),
+                        },
+                        count: None,
+                        visibility: ShaderStages::FRAGMENT,
+                    },
                 ],
             }),
             materials_buffer: StorageBuffer::default(),
             render_distance_params: StorageBuffer::default(),
+            sky_shadow_heightfield: StorageBuffer::default(),
+            view_settings: DynamicUniformBuffer::default(),
             bind_group: None,
         }
     }
@@ -68,8 +158,19 @@ impl FromWorld for TerrainUniforms {
 /// Prepares the the bind group
 fn prepare_terrain_uniforms(
     mut terrain_uniforms: ResMut<TerrainUniforms>,
+    voxel_volume: Res<GpuVoxelVolume>,
+    shadow_maps: Res<TerrainShadowMaps>,
     render_device: Res<RenderDevice>,
 ) {
+    // no view draws the terrain.
+    let view_settings = match terrain_uniforms.view_settings.binding() {
+        Some(binding) => binding,
+        None => {
+            terrain_uniforms.bind_group = None;
+            return;
+        }
+    };
+
     let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
         entries: &[
             BindGroupEntry {
@@ -84,6 +185,34 @@ fn prepare_terrain_uniforms(
                 binding: 1,
                 resource: terrain_uniforms.render_distance_params.binding().unwrap(),
             },
+            BindGroupEntry {
+                binding: 2,
+                resource: terrain_uniforms.sky_shadow_heightfield.binding().unwrap(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: BindingResource::TextureView(&voxel_volume.view),
+            },
+            BindGroupEntry {
+                binding: 4,
+                resource: voxel_volume.params.binding().unwrap(),
+            },
+            BindGroupEntry {
+                binding: 5,
+                resource: BindingResource::TextureView(&shadow_maps.cascades_view),
+            },
+            BindGroupEntry {
+                binding: 6,
+                resource: BindingResource::Sampler(&shadow_maps.sampler),
+            },
+            BindGroupEntry {
+                binding: 7,
+                resource: shadow_maps.params.binding().unwrap(),
+            },
+            BindGroupEntry {
+                binding: 8,
+                resource: view_settings,
+            },
         ],
         label: None,
         layout: &terrain_uniforms.bind_group_layout,
@@ -102,45 +231,47 @@ pub struct GpuVoxelMaterial {
     pub perceptual_roughness: f32,
     pub metallic: f32,
     pub reflectance: f32,
+    pub triplanar_scale: f32,
+    pub triplanar_strength: f32,
 }
 
-#[derive(ShaderType, Clone)]
+// sized by the number of registered materials, so that only the materials in use get uploaded, up to `MAX_MATERIALS`.
+#[derive(ShaderType, Clone, Default)]
 struct GpuTerrainMaterials {
-    pub materials: [GpuVoxelMaterial; 256],
+    #[size(runtime)]
+    pub materials: Vec<GpuVoxelMaterial>,
 }
 
-impl Default for GpuTerrainMaterials {
-    fn default() -> Self {
-        Self {
-            materials: [Default::default(); 256],
-        }
-    }
+/// Returns the GPU materials of the registered materials, ending with the default material.
+fn gpu_terrain_materials(materials: &VoxelMaterialRegistry) -> GpuTerrainMaterials {
+    let mut gpu_mats = GpuTerrainMaterials {
+        materials: materials
+            .iter_mats()
+            .map(|material| GpuVoxelMaterial {
+                base_color: material.base_color,
+                flags: material.flags.bits(),
+                emissive: material.emissive,
+                perceptual_roughness: material.perceptual_roughness,
+                metallic: material.metallic,
+                reflectance: material.reflectance,
+                triplanar_scale: material.triplanar_scale,
+                triplanar_strength: material.triplanar_strength,
+            })
+            .collect(),
+    };
+
+    // the shader falls back to this last entry for the unregistered materials, keeping the buffer non-empty as well.
+    gpu_mats.materials.push(GpuVoxelMaterial {
+        base_color: Color::WHITE,
+        ..Default::default()
+    });
+
+    gpu_mats
 }
 
 fn extract_voxel_materials(mut commands: Commands, materials: Extract<Res<VoxelMaterialRegistry>>) {
     if materials.is_changed() {
-
-        let mut gpu_mats = GpuTerrainMaterials {
-            materials: [GpuVoxelMaterial {
-                base_color: Color::WHITE,
-                flags: 0,
-                ..Default::default()
-            }; 256],
-        };
-
-        materials
-            .iter_mats()
-            .enumerate()
-            .for_each(|(index, material)| {
-                gpu_mats.materials[index].base_color = material.base_color;
-                gpu_mats.materials[index].flags = material.flags.bits();
-                gpu_mats.materials[index].emissive = material.emissive;
-                gpu_mats.materials[index].perceptual_roughness = material.perceptual_roughness;
-                gpu_mats.materials[index].metallic = material.metallic;
-                gpu_mats.materials[index].reflectance = material.reflectance;
-            });
-
-        commands.insert_resource(gpu_mats);
+        commands.insert_resource(gpu_terrain_materials(&materials));
     }
 }
 
@@ -158,15 +289,63 @@ fn upload_voxel_materials(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn extract_terrain_render_settings_uniform(
     mut commands: Commands,
     render_distance: Extract<Res<ChunkLoadRadius>>,
+    submersion: Extract<Res<CameraSubmersion>>,
+    materials: Extract<Res<VoxelMaterialRegistry>>,
+    time: Extract<Res<Time>>,
+    sky: Extract<Res<SkySettings>>,
+    fog: Extract<Res<DistanceFogSettings>>,
+    origin: Extract<Res<WorldOrigin>>,
+    submerged_fog: Extract<Res<SubmergedFogSettings>>,
 ) {
-    if render_distance.is_changed() {
-        commands.insert_resource(GpuTerrainRenderSettings {
-            render_distance: render_distance.horizontal as u32,
+    // the settings are extracted every frame since the block light animations change every frame.
+    let seconds = LightAnimation::seconds(&time);
+    commands.insert_resource(ExtractedDefaultViewSettings {
+        render_distance: ViewRenderDistance {
+            horizontal: render_distance.horizontal,
+            vertical: render_distance.vertical,
+            lod_bias: 0.0,
+        },
+        fog_start: fog.start,
+        fog_end: fog.end,
+    });
+    commands.insert_resource(GpuTerrainRenderSettings {
+        submerged_fog: submersion
+            .voxel
+            .and_then(|voxel| materials.get_by_id(voxel.0))
+            .and_then(|material| material.submerged_fog)
+            .unwrap_or(Color::NONE),
+        fog_color: fog.color(&sky),
+        world_origin: IVec2::new(origin.get().x, origin.get().z),
+        submerged_absorption: submerged_fog.absorption.extend(submerged_fog.density),
+        submerged_surface: submersion.surface,
+        block_light_time: seconds,
+        block_lights: gpu_block_lights(&materials, seconds),
+    });
+}
+
+/// Returns the block light animation of each registered material after `seconds` of animation, ending with a steady
+/// light for the unregistered materials.
+fn gpu_block_lights(materials: &VoxelMaterialRegistry, seconds: f32) -> Vec<GpuBlockLight> {
+    let steady = GpuBlockLight {
+        color: Color::WHITE,
+        flicker: Vec2::ZERO,
+    };
+
+    materials
+        .iter_mats()
+        .map(|material| match material.light_animation {
+            Some(animation) => GpuBlockLight {
+                color: animation.color_at(seconds),
+                flicker: Vec2::new(animation.flicker_amplitude, animation.flicker_frequency),
+            },
+            None => steady,
         })
-    }
+        .chain(std::iter::once(steady))
+        .collect()
 }
 
 fn upload_render_distance_uniform(
@@ -186,22 +365,176 @@ fn upload_render_distance_uniform(
 // terrain render settings uniform
 #[derive(ShaderType, Default, Clone)]
 struct GpuTerrainRenderSettings {
-    // current render distance radius
+    // fog of the material the camera is submerged in, fully transparent when there's none
+    pub submerged_fog: Color,
+    // color of the distance fog, matching the sky horizon
+    pub fog_color: Color,
+    // world position (X and Z) of the origin of the rendered positions
+    pub world_origin: IVec2,
+    // per voxel absorption of the red, green and blue channels through the liquid the camera is submerged in, and fog density
+    pub submerged_absorption: Vec4,
+    // height of the surface of the liquid the camera is submerged in
+    pub submerged_surface: f32,
+    // time the block light animations are evaluated at, in seconds
+    pub block_light_time: f32,
+    // animation of the block light emitted by each material
+    #[size(runtime)]
+    pub block_lights: Vec<GpuBlockLight>,
+}
+
+// animation of the block light emitted by a material
+#[derive(ShaderType, Default, Clone, Copy)]
+struct GpuBlockLight {
+    // current drift of the light color, multiplying the color of the emitter
+    pub color: Color,
+    // flicker amplitude and frequency
+    pub flicker: Vec2,
+}
+
+// render settings of the views without a `ViewRenderDistance`, along the distance fog bounds as shares of the
+// render distance.
+struct ExtractedDefaultViewSettings {
+    render_distance: ViewRenderDistance,
+    fog_start: f32,
+    fog_end: f32,
+}
+
+// per view terrain render settings uniform
+#[derive(ShaderType, Default, Clone)]
+struct GpuTerrainViewSettings {
+    // horizontal render distance radius of the view, in chunks
     pub render_distance: u32,
+    // vertical render distance radius of the view, in chunks
+    pub vertical_render_distance: u32,
+    // horizontal distance at which the distance fog starts, in voxels
+    pub fog_start: f32,
+    // horizontal distance at which the terrain is entirely fogged, in voxels
+    pub fog_end: f32,
+    // horizontal distance past which the surface details aren't drawn, in voxels
+    pub detail_distance: f32,
+}
+
+/// Offset of the terrain settings of a view in the per view uniform buffer.
+#[derive(Component)]
+pub struct TerrainViewUniformOffset(u32);
+
+fn prepare_terrain_view_settings(
+    mut commands: Commands,
+    defaults: Res<ExtractedDefaultViewSettings>,
+    views: Query<(Entity, Option<&ViewRenderDistance>), With<ExtractedView>>,
+    mut terrain_uniforms: ResMut<TerrainUniforms>,
+    render_queue: Res<RenderQueue>,
+    render_device: Res<RenderDevice>,
+) {
+    terrain_uniforms.view_settings.clear();
+    for (entity, render_distance) in views.iter() {
+        // the views without an override fog the terrain up to the edge of the loaded area.
+        let (render_distance, detail_distance) = match render_distance {
+            Some(render_distance) => (render_distance, render_distance.detail_distance()),
+            None => (&defaults.render_distance, f32::MAX),
+        };
+
+        let horizontal_distance = (render_distance.horizontal.max(0) as u32 * CHUNK_LENGTH) as f32;
+        let offset = terrain_uniforms.view_settings.push(GpuTerrainViewSettings {
+            render_distance: render_distance.horizontal.max(0) as u32,
+            vertical_render_distance: render_distance.vertical.max(0) as u32,
+            fog_start: defaults.fog_start * horizontal_distance,
+            fog_end: defaults.fog_end * horizontal_distance,
+            detail_distance,
+        });
+        commands
+            .entity(entity)
+            .insert(TerrainViewUniformOffset(offset));
+    }
+
+    terrain_uniforms
+        .view_settings
+        .write_buffer(&render_device, &render_queue);
+}
+
+// sky shadow heightfield
+#[derive(ShaderType, Default, Clone)]
+struct GpuSkyShadowHeightfield {
+    // position (X and Z) of the first column, relative to the world origin of the rendered positions
+    pub origin: IVec2,
+    // number of columns along each side
+    pub size: u32,
+    // how much the shadows darken the sunlight, 0 when disabled
+    pub strength: f32,
+    // direction pointing toward the sun
+    pub sun_direction: Vec3,
+    pub softness: f32,
+    pub max_distance: f32,
+    #[size(runtime)]
+    pub heights: Vec<f32>,
+}
+
+fn extract_sky_shadow_heightfield(
+    mut commands: Commands,
+    settings: Extract<Res<SkyShadowSettings>>,
+    heightfield: Extract<Res<SkyShadowHeightfield>>,
+    world_origin: Extract<Res<WorldOrigin>>,
+) {
+    if !settings.is_changed() && !heightfield.is_changed() && !world_origin.is_changed() {
+        return;
+    }
+
+    let enabled = settings.enabled && !heightfield.heights.is_empty();
+    commands.insert_resource(GpuSkyShadowHeightfield {
+        origin: heightfield.origin - IVec2::new(world_origin.get().x, world_origin.get().z),
+        size: if enabled { heightfield.size } else { 0 },
+        strength: if enabled { settings.strength } else { 0.0 },
+        sun_direction: settings.sun_direction.normalize_or_zero(),
+        softness: settings.softness,
+        max_distance: settings.max_distance,
+        // storage buffers can't hold empty runtime arrays.
+        heights: if enabled {
+            heightfield.heights.clone()
+        } else {
+            vec![SKY_SHADOW_NO_HEIGHT]
+        },
+    });
+}
+
+fn upload_sky_shadow_heightfield(
+    heightfield: Res<GpuSkyShadowHeightfield>,
+    mut material_meta: ResMut<TerrainUniforms>,
+    render_queue: Res<RenderQueue>,
+    render_device: Res<RenderDevice>,
+) {
+    if heightfield.is_changed() {
+        material_meta
+            .sky_shadow_heightfield
+            .set(heightfield.clone());
+        material_meta
+            .sky_shadow_heightfield
+            .write_buffer(&render_device, &render_queue);
+    }
 }
 
-/// Binds the terrain uniforms for use in shaders.
+/// Binds the terrain uniforms for use in shaders, along the settings of the view.
 pub struct SetTerrainUniformsBindGroup<const I: usize>;
 impl<const I: usize> EntityRenderCommand for SetTerrainUniformsBindGroup<I> {
-    type Param = SRes<TerrainUniforms>;
+    type Param = (
+        SRes<TerrainUniforms>,
+        SQuery<Read<TerrainViewUniformOffset>>,
+    );
 
     fn render<'w>(
-        _view: Entity,
+        view: Entity,
         _item: Entity,
-        param: bevy::ecs::system::SystemParamItem<'w, '_, Self::Param>,
+        (terrain_uniforms, view_offsets): bevy::ecs::system::SystemParamItem<'w, '_, Self::Param>,
         pass: &mut bevy::render::render_phase::TrackedRenderPass<'w>,
     ) -> bevy::render::render_phase::RenderCommandResult {
-        pass.set_bind_group(I, param.into_inner().bind_group.as_ref().unwrap(), &[]);
+        let view_offset = match view_offsets.get_inner(view) {
+            Ok(view_offset) => view_offset,
+            Err(_) => return bevy::render::render_phase::RenderCommandResult::Failure,
+        };
+        pass.set_bind_group(
+            I,
+            terrain_uniforms.into_inner().bind_group.as_ref().unwrap(),
+            &[view_offset.0],
+        );
         bevy::render::render_phase::RenderCommandResult::Success
     }
 }
@@ -217,11 +550,96 @@ impl Plugin for VoxelTerrainUniformsPlugin {
             .add_system_to_stage(RenderStage::Queue, prepare_terrain_uniforms)
             .add_system_to_stage(RenderStage::Prepare, upload_voxel_materials)
             .add_system_to_stage(RenderStage::Prepare, upload_render_distance_uniform)
+            .add_system_to_stage(RenderStage::Prepare, upload_sky_shadow_heightfield)
+            .add_system_to_stage(RenderStage::Prepare, prepare_terrain_view_settings)
+            .add_system_to_stage(RenderStage::Extract, extract_sky_shadow_heightfield)
             .add_system_to_stage(
                 RenderStage::Extract,
                 extract_terrain_render_settings_uniform,
             );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::material::{MaterialRegistryInfo, MAX_MATERIALS};
+
+    #[test]
+    fn gpu_materials_follow_the_registry() {
+        let mut registry = VoxelMaterialRegistry::default();
+        assert_eq!(gpu_terrain_materials(&registry).materials.len(), 2);
+
+        // the buffer grows along the registry, up to the full registry.
+        for count in 2..=MAX_MATERIALS {
+            registry.register_at_runtime(MaterialRegistryInfo {
+                name: format!("Material {}", count).into(),
+                base_color: Color::RED,
+                ..Default::default()
+            });
+            let gpu_mats = gpu_terrain_materials(&registry);
+            assert_eq!(gpu_mats.materials.len(), count + 1);
+            assert_eq!(gpu_mats.materials[count - 1].base_color, Color::RED);
+            assert_eq!(gpu_mats.materials[count].base_color, Color::WHITE);
+        }
+    }
 
-        info!("type size: {}", GpuVoxelMaterial::min_size().get() * 256);
+    /// Uploads the materials through the render world system while the registry grows, so that the storage buffer gets
+    /// reallocated. Skipped on the machines without a graphics adapter. The extraction from the main world and the bind
+    /// group rebuilt every frame by `prepare_terrain_uniforms` aren't covered.
+    #[test]
+    fn materials_buffer_grows_along_the_registry() {
+        use bevy::{
+            ecs::schedule::{Stage, SystemStage},
+            prelude::World,
+        };
+        use futures_lite::future::block_on;
+        use std::sync::Arc;
+
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = match block_on(instance.request_adapter(&Default::default())) {
+            Some(adapter) => adapter,
+            None => {
+                eprintln!("no graphics adapter, skipping the materials buffer test");
+                return;
+            }
+        };
+        let (device, queue) = block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                limits: adapter.limits(),
+                ..Default::default()
+            },
+            None,
+        ))
+        .unwrap();
+
+        let mut world = World::new();
+        world.insert_resource(RenderDevice::from(Arc::new(device)));
+        let queue: RenderQueue = Arc::new(queue);
+        world.insert_resource(queue);
+        world.init_resource::<TerrainUniforms>();
+        let mut prepare = SystemStage::single_threaded().with_system(upload_voxel_materials);
+
+        let mut registry = VoxelMaterialRegistry::default();
+        for count in [1, 64, MAX_MATERIALS] {
+            while registry.iter_mats().count() < count {
+                let id = registry.iter_mats().count();
+                registry.register_at_runtime(MaterialRegistryInfo {
+                    name: format!("Material {}", id).into(),
+                    ..Default::default()
+                });
+            }
+            world.insert_resource(gpu_terrain_materials(&registry));
+            prepare.run(&mut world);
+
+            // the registered materials and the default material ending the buffer.
+            let size = world
+                .resource::<TerrainUniforms>()
+                .materials_buffer
+                .buffer()
+                .unwrap()
+                .size();
+            assert!(size >= (count as u64 + 1) * GpuVoxelMaterial::min_size().get());
+        }
     }
 }
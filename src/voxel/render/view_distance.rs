@@ -0,0 +1,61 @@
+use bevy::{
+    ecs::query::QueryItem,
+    math::Vec3,
+    prelude::{Camera, Component, With},
+    render::extract_component::ExtractComponent,
+};
+
+use crate::voxel::CHUNK_SIZE;
+
+/// Overrides the render distance of the terrain for a camera, so that the secondary views (minimaps, portals,
+/// reflection probes) only draw the chunks around them and skip the costly surface details.
+/// The cameras without it draw the whole loaded area, along the [`ChunkLoadRadius`](crate::voxel::ChunkLoadRadius)
+/// distance fog.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ViewRenderDistance {
+    /// Radius of the drawn area on the X and Z axes, in chunks.
+    pub horizontal: i32,
+    /// Half height of the drawn area on the Y axis, in chunks.
+    pub vertical: i32,
+    /// Share of the render distance past which the surface details (triplanar noise, cascade shadows) aren't drawn,
+    /// from 0 drawing them everywhere to 1 never drawing them.
+    pub lod_bias: f32,
+}
+
+impl Default for ViewRenderDistance {
+    fn default() -> Self {
+        Self {
+            horizontal: 4,
+            vertical: 2,
+            lod_bias: 0.5,
+        }
+    }
+}
+
+impl ViewRenderDistance {
+    /// Returns whether a chunk lies within the render distance of a view, from the rendered positions of the view and
+    /// of the chunk minimum.
+    pub fn contains_chunk(&self, view_position: Vec3, chunk_min: Vec3) -> bool {
+        let chunk_size = CHUNK_SIZE.as_vec3();
+        let offset = (chunk_min / chunk_size).round().as_ivec3()
+            - (view_position / chunk_size).floor().as_ivec3();
+
+        offset.y.abs() <= self.vertical
+            && offset.x.pow(2) + offset.z.pow(2) <= self.horizontal.pow(2)
+    }
+
+    /// Returns the distance past which the surface details aren't drawn, in voxels.
+    pub(super) fn detail_distance(&self) -> f32 {
+        (self.horizontal * CHUNK_SIZE.x) as f32 * (1.0 - self.lod_bias.clamp(0.0, 1.0))
+    }
+}
+
+impl ExtractComponent for ViewRenderDistance {
+    type Query = &'static ViewRenderDistance;
+
+    type Filter = With<Camera>;
+
+    fn extract_component(item: QueryItem<Self::Query>) -> Self {
+        *item
+    }
+}
@@ -0,0 +1,211 @@
+use std::num::NonZeroU32;
+
+use bevy::{
+    math::{IVec3, UVec3},
+    prelude::{Commands, FromWorld, Plugin, Res, ResMut},
+    render::{
+        render_resource::{
+            Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, ShaderType, StorageBuffer,
+            Texture, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+            TextureUsages, TextureView, TextureViewDescriptor,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        Extract, RenderApp, RenderStage,
+    },
+};
+
+use crate::voxel::{VoxelVolume, VoxelVolumeSettings, WorldOrigin, VOXEL_VOLUME_SCALE};
+
+/// The 3D texture holding the [`VoxelVolume`] in the render world, bound with the terrain uniforms.
+pub struct GpuVoxelVolume {
+    texture: Texture,
+    pub view: TextureView,
+    size: UVec3,
+    pub params: StorageBuffer<GpuVoxelVolumeParams>,
+}
+
+fn create_volume_texture(render_device: &RenderDevice, size: UVec3) -> (Texture, TextureView) {
+    let texture = render_device.create_texture(&TextureDescriptor {
+        label: Some("voxel_volume_texture"),
+        size: Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: size.z,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D3,
+        format: TextureFormat::R8Uint,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+    });
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    (texture, view)
+}
+
+impl FromWorld for GpuVoxelVolume {
+    fn from_world(world: &mut bevy::prelude::World) -> Self {
+        let render_device = world.get_resource::<RenderDevice>().unwrap();
+        let render_queue = world.get_resource::<RenderQueue>().unwrap();
+
+        // an empty texel stands in for the volume until it gets uploaded, the shaders see it as disabled.
+        let (texture, view) = create_volume_texture(render_device, UVec3::ONE);
+        let mut params = StorageBuffer::from(GpuVoxelVolumeParams::default());
+        params.write_buffer(render_device, render_queue);
+
+        Self {
+            texture,
+            view,
+            size: UVec3::ONE,
+            params,
+        }
+    }
+}
+
+#[derive(ShaderType, Default, Clone)]
+pub struct GpuVoxelVolumeParams {
+    // position of the first texel, relative to the world origin of the rendered positions
+    pub origin: IVec3,
+    // number of voxels along each side of a texel
+    pub scale: i32,
+    // number of texels along each axis, 0 when disabled
+    pub size: UVec3,
+}
+
+/// Texels of the volume to be written to the texture.
+struct VoxelVolumeUpload {
+    /// First texel of the written box.
+    offset: UVec3,
+    size: UVec3,
+    texels: Vec<u8>,
+}
+
+/// The volume updates of the frame, extracted from the main world.
+#[derive(Default)]
+struct ExtractedVoxelVolume {
+    size: UVec3,
+    params: Option<GpuVoxelVolumeParams>,
+    uploads: Vec<VoxelVolumeUpload>,
+}
+
+fn extract_voxel_volume(
+    mut commands: Commands,
+    settings: Extract<Res<VoxelVolumeSettings>>,
+    volume: Extract<Res<VoxelVolume>>,
+    world_origin: Extract<Res<WorldOrigin>>,
+) {
+    let mut extracted = ExtractedVoxelVolume {
+        size: volume.size,
+        ..Default::default()
+    };
+
+    if settings.is_changed() || volume.is_changed() || world_origin.is_changed() {
+        let enabled = settings.enabled && !volume.texels.is_empty();
+        extracted.params = Some(GpuVoxelVolumeParams {
+            origin: volume.origin - IVec3::new(world_origin.get().x, 0, world_origin.get().z),
+            scale: VOXEL_VOLUME_SCALE,
+            size: if enabled { volume.size } else { UVec3::ZERO },
+        });
+    }
+
+    if volume.is_changed() && !volume.texels.is_empty() {
+        if volume.full_update {
+            extracted.uploads.push(VoxelVolumeUpload {
+                offset: UVec3::ZERO,
+                size: volume.size,
+                texels: volume.texels.clone(),
+            });
+        } else {
+            // only the texels of the modified chunks get copied over.
+            let chunk_texels = VoxelVolume::CHUNK_TEXELS.as_uvec3();
+            for key in volume.updated_chunks.iter() {
+                let offset = match volume.chunk_texel(*key) {
+                    Some(offset) => offset,
+                    None => continue,
+                };
+
+                let mut texels =
+                    Vec::with_capacity((chunk_texels.x * chunk_texels.y * chunk_texels.z) as usize);
+                for z in 0..chunk_texels.z {
+                    for y in 0..chunk_texels.y {
+                        let row = volume.texel_index(offset + UVec3::new(0, y, z));
+                        texels
+                            .extend_from_slice(&volume.texels[row..row + chunk_texels.x as usize]);
+                    }
+                }
+
+                extracted.uploads.push(VoxelVolumeUpload {
+                    offset,
+                    size: chunk_texels,
+                    texels,
+                });
+            }
+        }
+    }
+
+    commands.insert_resource(extracted);
+}
+
+/// Writes the extracted texels to the volume texture, recreating the texture when the volume got resized.
+fn upload_voxel_volume(
+    extracted: Res<ExtractedVoxelVolume>,
+    mut gpu_volume: ResMut<GpuVoxelVolume>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if let Some(params) = &extracted.params {
+        gpu_volume.params.set(params.clone());
+        gpu_volume
+            .params
+            .write_buffer(&render_device, &render_queue);
+    }
+
+    if extracted.uploads.is_empty() {
+        return;
+    }
+
+    if gpu_volume.size != extracted.size {
+        let (texture, view) = create_volume_texture(&render_device, extracted.size);
+        gpu_volume.texture = texture;
+        gpu_volume.view = view;
+        gpu_volume.size = extracted.size;
+    }
+
+    for upload in extracted.uploads.iter() {
+        render_queue.write_texture(
+            ImageCopyTexture {
+                texture: &gpu_volume.texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: upload.offset.x,
+                    y: upload.offset.y,
+                    z: upload.offset.z,
+                },
+                aspect: TextureAspect::All,
+            },
+            &upload.texels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(upload.size.x),
+                rows_per_image: NonZeroU32::new(upload.size.y),
+            },
+            Extent3d {
+                width: upload.size.x,
+                height: upload.size.y,
+                depth_or_array_layers: upload.size.z,
+            },
+        );
+    }
+}
+
+/// Uploads the [`VoxelVolume`] to a 3D texture bound with the terrain uniforms, only writing the modified chunks.
+pub struct VoxelVolumeTexturePlugin;
+
+impl Plugin for VoxelVolumeTexturePlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.sub_app_mut(RenderApp)
+            .init_resource::<GpuVoxelVolume>()
+            .init_resource::<ExtractedVoxelVolume>()
+            .add_system_to_stage(RenderStage::Extract, extract_voxel_volume)
+            .add_system_to_stage(RenderStage::Prepare, upload_voxel_volume);
+    }
+}
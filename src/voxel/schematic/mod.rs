@@ -0,0 +1,319 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use bevy::{
+    math::IVec3,
+    prelude::{
+        error, info, Color, EventReader, EventWriter, ParallelSystemDescriptorCoercion, Plugin, Res,
+    },
+};
+
+use super::{
+    material::VoxelMaterialRegistry,
+    net::{NetworkVoxelEdit, ReplicationSystem},
+    storage::ChunkMap,
+    ChunkShape, Voxel,
+};
+
+/// Minimal reader and writer of the NBT format.
+mod nbt;
+
+/// MagicaVoxel `.vox` files.
+mod vox;
+
+/// Sponge `.schem` files.
+mod schem;
+
+/// Maximum number of voxels of the schematics read from files, larger schematics being rejected.
+const MAX_SCHEMATIC_VOLUME: i64 = 1 << 24;
+
+/// A block of a schematic palette, identified by name (e.g. `minecraft:stone`) and / or by color.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchematicBlock {
+    pub name: Option<String>,
+    pub color: Option<Color>,
+}
+
+/// A box of voxels in a format independent from the material registry, indexed in YZX order.
+#[derive(Clone, Debug)]
+pub struct Schematic {
+    pub size: IVec3,
+    /// The blocks of the schematic, `None` being the empty block.
+    pub palette: Vec<Option<SchematicBlock>>,
+    /// Palette indices of the voxels, `x + z * size.x + y * size.x * size.z`.
+    pub blocks: Vec<u16>,
+}
+
+/// Returns the name a material is written as in schematics, e.g. `vx_bevy:sand`.
+fn material_block_name(name: &str) -> String {
+    format!("vx_bevy:{}", name.to_ascii_lowercase().replace(' ', "_"))
+}
+
+/// Returns the material of a block name, matching the material names regardless of the namespace, case and spaces.
+fn material_by_block_name(registry: &VoxelMaterialRegistry, name: &str) -> Option<u8> {
+    // block states (e.g. `minecraft:oak_log[axis=y]`) are ignored.
+    let name = name.split('[').next().unwrap();
+    let name = name.rsplit(':').next().unwrap().to_ascii_lowercase();
+    registry
+        .iter_mats()
+        .position(|material| material.name.to_ascii_lowercase().replace(' ', "_") == name)
+        .map(|id| id as u8)
+}
+
+/// Returns the material whose base color is the closest to the specified color, ignoring the empty material.
+fn material_by_color(registry: &VoxelMaterialRegistry, color: Color) -> Option<u8> {
+    let [r, g, b, _] = color.as_rgba_f32();
+    registry
+        .iter_mats()
+        .enumerate()
+        .skip(1)
+        .map(|(id, material)| {
+            let [mr, mg, mb, _] = material.base_color.as_rgba_f32();
+            (id, (r - mr).powi(2) + (g - mg).powi(2) + (b - mb).powi(2))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(id, _)| id as u8)
+}
+
+#[allow(dead_code)]
+impl Schematic {
+    /// Returns the linear index of the voxel at the specified position in the schematic.
+    #[inline]
+    pub fn index(&self, pos: IVec3) -> usize {
+        (pos.x + pos.z * self.size.x + pos.y * self.size.x * self.size.z) as usize
+    }
+
+    /// Returns an iterator over the positions of the voxels, in the order of [`Schematic::blocks`].
+    pub fn positions(&self) -> impl Iterator<Item = IVec3> {
+        let size = self.size;
+        (0..size.y).flat_map(move |y| {
+            (0..size.z).flat_map(move |z| (0..size.x).map(move |x| IVec3::new(x, y, z)))
+        })
+    }
+
+    /// Maps the palette to the materials of the registry: blocks are matched by name first, then to the material with the
+    /// closest color. Blocks with neither a known name nor a color are mapped to the empty voxel.
+    pub fn material_palette(&self, registry: &VoxelMaterialRegistry) -> Vec<Voxel> {
+        self.palette
+            .iter()
+            .map(|block| {
+                let block = match block {
+                    Some(block) => block,
+                    None => return Voxel::EMPTY_VOXEL,
+                };
+
+                block
+                    .name
+                    .as_deref()
+                    .and_then(|name| material_by_block_name(registry, name))
+                    .or_else(|| {
+                        block
+                            .color
+                            .and_then(|color| material_by_color(registry, color))
+                    })
+                    .map_or(Voxel::EMPTY_VOXEL, Voxel)
+            })
+            .collect()
+    }
+
+    /// Copies the voxels of the world from `min` to `max` (inclusive), the materials being written by name and color.
+    /// Returns `None` if a voxel of the region isn't loaded.
+    pub fn from_world(
+        chunks: &ChunkMap<Voxel, ChunkShape>,
+        registry: &VoxelMaterialRegistry,
+        min: IVec3,
+        max: IVec3,
+    ) -> Option<Self> {
+        let (min, max) = (min.min(max), min.max(max));
+        let mut schematic = Self {
+            size: max - min + IVec3::ONE,
+            palette: Vec::new(),
+            blocks: Vec::new(),
+        };
+
+        // palette index of each material id.
+        let mut indices = [None; 256];
+        for pos in schematic.positions().collect::<Vec<_>>() {
+            let voxel = chunks.voxel_at(min + pos)?;
+            let index = *indices[voxel.0 as usize].get_or_insert_with(|| {
+                schematic.palette.push(
+                    registry
+                        .get_by_id(voxel.0)
+                        .filter(|_| voxel != Voxel::EMPTY_VOXEL)
+                        .map(|material| SchematicBlock {
+                            name: Some(material_block_name(&material.name)),
+                            color: Some(material.base_color),
+                        }),
+                );
+                (schematic.palette.len() - 1) as u16
+            });
+            schematic.blocks.push(index);
+        }
+
+        Some(schematic)
+    }
+
+    /// Returns the number of voxels of the schematic, `None` if its size is negative, zero or larger than
+    /// [`MAX_SCHEMATIC_VOLUME`].
+    pub fn volume(&self) -> Option<usize> {
+        if self.size.cmple(IVec3::ZERO).any() {
+            return None;
+        }
+        let volume = (self.size.x as i64)
+            .checked_mul(self.size.y as i64)?
+            .checked_mul(self.size.z as i64)?;
+        (volume <= MAX_SCHEMATIC_VOLUME).then(|| volume as usize)
+    }
+
+    /// Returns the voxels changed by pasting the schematic into the world with its minimum at `origin`, empty voxels
+    /// included. Voxels of unloaded chunks are skipped.
+    pub fn paste_edits(
+        &self,
+        chunks: &ChunkMap<Voxel, ChunkShape>,
+        registry: &VoxelMaterialRegistry,
+        origin: IVec3,
+    ) -> Vec<(IVec3, Voxel)> {
+        let materials = self.material_palette(registry);
+
+        self.positions()
+            .zip(self.blocks.iter())
+            .map(|(pos, index)| (origin + pos, materials[*index as usize]))
+            .filter(|(pos, voxel)| {
+                chunks
+                    .voxel_at(*pos)
+                    .map_or(false, |current| current != *voxel)
+            })
+            .collect()
+    }
+
+    /// Reads a schematic, its format being picked from the extension of the file (`.vox` or `.schem`).
+    pub fn read(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let schematic = match SchematicFormat::from_path(path)? {
+            SchematicFormat::Vox => vox::read(&std::fs::read(path)?)?,
+            SchematicFormat::Schem => schem::read(std::fs::File::open(path)?)?,
+        };
+
+        if schematic.volume() != Some(schematic.blocks.len())
+            || schematic
+                .blocks
+                .iter()
+                .any(|index| *index as usize >= schematic.palette.len())
+        {
+            return Err(anyhow!("malformed schematic"));
+        }
+        Ok(schematic)
+    }
+
+    /// Writes a schematic, its format being picked from the extension of the file (`.vox` or `.schem`).
+    pub fn write(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        match SchematicFormat::from_path(path)? {
+            SchematicFormat::Vox => std::fs::write(path, vox::write(self)?)?,
+            SchematicFormat::Schem => schem::write(self, std::fs::File::create(path)?)?,
+        }
+        Ok(())
+    }
+}
+
+/// The supported schematic file formats.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SchematicFormat {
+    /// MagicaVoxel models, whose palette colors are mapped to the closest materials.
+    Vox,
+    /// Sponge schematics, whose block names are mapped to the materials of the same name.
+    Schem,
+}
+
+impl SchematicFormat {
+    /// Returns the format of a file from its extension.
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        match path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("vox") => Ok(Self::Vox),
+            Some("schem") => Ok(Self::Schem),
+            _ => Err(anyhow!(
+                "unsupported schematic format, expected .vox or .schem"
+            )),
+        }
+    }
+}
+
+/// Event loading a schematic file into the world, with its minimum at `position`.
+pub struct ImportSchematic {
+    pub path: PathBuf,
+    pub position: IVec3,
+}
+
+/// Event saving the voxels of the world from `min` to `max` (inclusive) to a schematic file.
+pub struct ExportSchematic {
+    pub path: PathBuf,
+    pub min: IVec3,
+    pub max: IVec3,
+}
+
+/// Turns the imported schematics into voxel edits, each import forming a single undo batch with the edits of the frame.
+fn import_schematics(
+    mut events: EventReader<ImportSchematic>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    registry: Res<VoxelMaterialRegistry>,
+    mut edits: EventWriter<NetworkVoxelEdit>,
+) {
+    for event in events.iter() {
+        match Schematic::read(&event.path) {
+            Ok(schematic) => {
+                let changed = schematic.paste_edits(&chunks, &registry, event.position);
+                info!(
+                    "Imported schematic {:?} ({:?} voxels) at {:?}, {} voxels changed",
+                    event.path,
+                    schematic.size,
+                    event.position,
+                    changed.len()
+                );
+                edits.send_batch(changed.into_iter().map(|(pos, voxel)| NetworkVoxelEdit {
+                    pos,
+                    voxel,
+                    journaled: true,
+                    client: None,
+                }));
+            }
+            Err(err) => error!("Failed to import schematic {:?}: {}", event.path, err),
+        }
+    }
+}
+
+fn export_schematics(
+    mut events: EventReader<ExportSchematic>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    registry: Res<VoxelMaterialRegistry>,
+) {
+    for event in events.iter() {
+        let result = Schematic::from_world(&chunks, &registry, event.min, event.max)
+            .ok_or_else(|| anyhow!("the region isn't fully loaded"))
+            .and_then(|schematic| schematic.write(&event.path));
+
+        match result {
+            Ok(()) => info!(
+                "Exported the voxels from {:?} to {:?} to schematic {:?}",
+                event.min, event.max, event.path
+            ),
+            Err(err) => error!("Failed to export schematic {:?}: {}", event.path, err),
+        }
+    }
+}
+
+/// Imports and exports schematics through [`ImportSchematic`] and [`ExportSchematic`] events.
+pub struct SchematicPlugin;
+
+impl Plugin for SchematicPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_event::<ImportSchematic>()
+            .add_event::<ExportSchematic>()
+            .add_system(import_schematics.before(ReplicationSystem::ApplyVoxelEdits))
+            .add_system(export_schematics);
+    }
+}
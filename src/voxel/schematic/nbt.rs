@@ -0,0 +1,218 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+use anyhow::anyhow;
+
+/// A value of Minecraft's Named Binary Tag format, as used by the Sponge schematics.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+const TAG_END: u8 = 0;
+
+/// Nesting depth beyond which a file is considered malformed, so that it can't overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+#[allow(dead_code)]
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+
+    /// Returns the child of a compound tag with the specified name.
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(children) => children.get(name),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an integer tag of any width.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value as i64),
+            Tag::Short(value) => Some(value as i64),
+            Tag::Int(value) => Some(value as i64),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_len(reader: &mut impl Read) -> anyhow::Result<usize> {
+    let len = i32::from_be_bytes(read_bytes(reader)?);
+    usize::try_from(len).map_err(|_| anyhow!("negative length {}", len))
+}
+
+fn read_string(reader: &mut impl Read) -> anyhow::Result<String> {
+    let len = u16::from_be_bytes(read_bytes(reader)?) as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    // strings are "modified UTF-8", which only differs from UTF-8 for the null and supplementary characters.
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn read_payload(reader: &mut impl Read, id: u8, depth: usize) -> anyhow::Result<Tag> {
+    if depth > MAX_DEPTH {
+        return Err(anyhow!("tags nested too deep"));
+    }
+
+    Ok(match id {
+        1 => Tag::Byte(i8::from_be_bytes(read_bytes(reader)?)),
+        2 => Tag::Short(i16::from_be_bytes(read_bytes(reader)?)),
+        3 => Tag::Int(i32::from_be_bytes(read_bytes(reader)?)),
+        4 => Tag::Long(i64::from_be_bytes(read_bytes(reader)?)),
+        5 => Tag::Float(f32::from_be_bytes(read_bytes(reader)?)),
+        6 => Tag::Double(f64::from_be_bytes(read_bytes(reader)?)),
+        7 => {
+            // the length isn't trusted for allocating the array upfront.
+            let len = read_len(reader)?;
+            let mut bytes = Vec::new();
+            reader.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+            if bytes.len() != len {
+                return Err(anyhow!("truncated byte array"));
+            }
+            Tag::ByteArray(bytes.into_iter().map(|byte| byte as i8).collect())
+        }
+        8 => Tag::String(read_string(reader)?),
+        9 => {
+            let element_id = read_bytes::<1>(reader)?[0];
+            let len = read_len(reader)?;
+            let elements = (0..len)
+                .map(|_| read_payload(reader, element_id, depth + 1))
+                .collect::<anyhow::Result<_>>()?;
+            Tag::List(elements)
+        }
+        10 => {
+            let mut children = HashMap::new();
+            loop {
+                let child_id = read_bytes::<1>(reader)?[0];
+                if child_id == TAG_END {
+                    break;
+                }
+                let name = read_string(reader)?;
+                children.insert(name, read_payload(reader, child_id, depth + 1)?);
+            }
+            Tag::Compound(children)
+        }
+        11 => Tag::IntArray(
+            (0..read_len(reader)?)
+                .map(|_| Ok(i32::from_be_bytes(read_bytes(reader)?)))
+                .collect::<io::Result<_>>()?,
+        ),
+        12 => Tag::LongArray(
+            (0..read_len(reader)?)
+                .map(|_| Ok(i64::from_be_bytes(read_bytes(reader)?)))
+                .collect::<io::Result<_>>()?,
+        ),
+        _ => return Err(anyhow!("unknown tag type {}", id)),
+    })
+}
+
+/// Reads the root tag of an (uncompressed) NBT stream, along with its name.
+pub fn read_root(reader: &mut impl Read) -> anyhow::Result<(String, Tag)> {
+    let id = read_bytes::<1>(reader)?[0];
+    if id != 10 {
+        return Err(anyhow!("the root tag isn't a compound"));
+    }
+
+    let name = read_string(reader)?;
+    Ok((name, read_payload(reader, id, 0)?))
+}
+
+fn write_string(writer: &mut impl Write, string: &str) -> io::Result<()> {
+    let len = u16::try_from(string.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "string too long"))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(string.as_bytes())
+}
+
+fn write_len(writer: &mut impl Write, len: usize) -> io::Result<()> {
+    let len = i32::try_from(len)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "array too long"))?;
+    writer.write_all(&len.to_be_bytes())
+}
+
+fn write_payload(writer: &mut impl Write, tag: &Tag) -> io::Result<()> {
+    match tag {
+        Tag::Byte(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Short(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Int(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Long(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Float(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::Double(value) => writer.write_all(&value.to_be_bytes()),
+        Tag::ByteArray(values) => {
+            write_len(writer, values.len())?;
+            let bytes: Vec<u8> = values.iter().map(|value| *value as u8).collect();
+            writer.write_all(&bytes)
+        }
+        Tag::String(value) => write_string(writer, value),
+        Tag::List(elements) => {
+            writer.write_all(&[elements.first().map_or(TAG_END, Tag::id)])?;
+            write_len(writer, elements.len())?;
+            elements
+                .iter()
+                .try_for_each(|element| write_payload(writer, element))
+        }
+        Tag::Compound(children) => {
+            for (name, child) in children {
+                writer.write_all(&[child.id()])?;
+                write_string(writer, name)?;
+                write_payload(writer, child)?;
+            }
+            writer.write_all(&[TAG_END])
+        }
+        Tag::IntArray(values) => {
+            write_len(writer, values.len())?;
+            values
+                .iter()
+                .try_for_each(|value| writer.write_all(&value.to_be_bytes()))
+        }
+        Tag::LongArray(values) => {
+            write_len(writer, values.len())?;
+            values
+                .iter()
+                .try_for_each(|value| writer.write_all(&value.to_be_bytes()))
+        }
+    }
+}
+
+/// Writes a named root compound tag as an (uncompressed) NBT stream.
+pub fn write_root(writer: &mut impl Write, name: &str, root: &Tag) -> io::Result<()> {
+    writer.write_all(&[root.id()])?;
+    write_string(writer, name)?;
+    write_payload(writer, root)
+}
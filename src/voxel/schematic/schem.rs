@@ -0,0 +1,177 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use anyhow::anyhow;
+use bevy::math::IVec3;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use super::{nbt, nbt::Tag, Schematic, SchematicBlock};
+
+/// Version of the Sponge schematic format written.
+const SCHEM_VERSION: i32 = 2;
+
+/// Minecraft data version written in the schematics, the one of Minecraft 1.16.5.
+const DATA_VERSION: i32 = 2586;
+
+/// Names of the empty blocks.
+const AIR_BLOCKS: [&str; 3] = ["minecraft:air", "minecraft:cave_air", "minecraft:void_air"];
+
+/// Decodes the variable length integers of the block data.
+fn read_varints(bytes: &[i8]) -> anyhow::Result<Vec<u16>> {
+    let mut values = Vec::new();
+    let (mut value, mut shift) = (0u32, 0);
+    for byte in bytes.iter().map(|byte| *byte as u8) {
+        value |= ((byte & 0x7F) as u32) << shift;
+        if byte & 0x80 == 0 {
+            values.push(u16::try_from(value).map_err(|_| anyhow!("block palette too large"))?);
+            value = 0;
+            shift = 0;
+        } else {
+            shift += 7;
+            if shift > 21 {
+                return Err(anyhow!("malformed block data"));
+            }
+        }
+    }
+    Ok(values)
+}
+
+fn write_varint(bytes: &mut Vec<i8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte as i8);
+            return;
+        }
+        bytes.push((byte | 0x80) as i8);
+    }
+}
+
+/// Reads a Sponge schematic (version 2 or 3), blocks being identified by name.
+pub fn read(reader: impl Read) -> anyhow::Result<Schematic> {
+    let (_, root) = nbt::read_root(&mut GzDecoder::new(reader))?;
+    // version 3 wraps the schematic in an unnamed root compound.
+    let root = match root.get("Schematic") {
+        Some(schematic) => schematic,
+        None => &root,
+    };
+
+    let dimension = |name| {
+        root.get(name)
+            .and_then(Tag::as_i64)
+            // the dimensions are unsigned shorts.
+            .map(|len| (len & 0xFFFF) as i32)
+            .ok_or_else(|| anyhow!("missing {}", name))
+    };
+    let size = IVec3::new(
+        dimension("Width")?,
+        dimension("Height")?,
+        dimension("Length")?,
+    );
+
+    // version 3 moves the palette and block data to a `Blocks` compound.
+    let (palette, data) = match root.get("Blocks") {
+        Some(blocks) => (blocks.get("Palette"), blocks.get("Data")),
+        None => (root.get("Palette"), root.get("BlockData")),
+    };
+
+    let mut names: Vec<(usize, &str)> = match palette {
+        Some(Tag::Compound(palette)) => palette
+            .iter()
+            .map(|(name, index)| match index.as_i64() {
+                Some(index) if (0..=u16::MAX as i64).contains(&index) => {
+                    Ok((index as usize, name.as_str()))
+                }
+                _ => Err(anyhow!("invalid palette index for {}", name)),
+            })
+            .collect::<anyhow::Result<_>>()?,
+        _ => return Err(anyhow!("missing block palette")),
+    };
+    names.sort_unstable();
+
+    let mut schematic_palette = vec![None; names.last().map_or(0, |(index, _)| index + 1)];
+    for (index, name) in names {
+        if !AIR_BLOCKS.contains(&name) {
+            schematic_palette[index] = Some(SchematicBlock {
+                name: Some(name.to_string()),
+                color: None,
+            });
+        }
+    }
+
+    let blocks = match data {
+        Some(Tag::ByteArray(data)) => read_varints(data)?,
+        _ => return Err(anyhow!("missing block data")),
+    };
+
+    Ok(Schematic {
+        size,
+        palette: schematic_palette,
+        blocks,
+    })
+}
+
+/// Writes a schematic as a version 2 Sponge schematic, blocks without name being named after their color.
+pub fn write(schematic: &Schematic, writer: impl Write) -> anyhow::Result<()> {
+    let size = schematic.size;
+    if size.cmpgt(IVec3::splat(u16::MAX as i32)).any() {
+        return Err(anyhow!(
+            "Sponge schematics are at most {} blocks long",
+            u16::MAX
+        ));
+    }
+
+    // palette entries of the same name share the same index.
+    let mut palette: HashMap<String, Tag> = HashMap::new();
+    let indices: Vec<u32> = schematic
+        .palette
+        .iter()
+        .map(|block| {
+            let name = match block {
+                Some(SchematicBlock {
+                    name: Some(name), ..
+                }) => name.clone(),
+                Some(SchematicBlock {
+                    color: Some(color), ..
+                }) => {
+                    let [r, g, b, _] = color
+                        .as_rgba_f32()
+                        .map(|channel| (channel * 255.0).round() as u8);
+                    format!("vx_bevy:color_{:02x}{:02x}{:02x}", r, g, b)
+                }
+                Some(_) => "vx_bevy:unknown".to_string(),
+                None => AIR_BLOCKS[0].to_string(),
+            };
+
+            let next = palette.len() as i32;
+            match palette.entry(name).or_insert(Tag::Int(next)) {
+                Tag::Int(index) => *index as u32,
+                _ => unreachable!(),
+            }
+        })
+        .collect();
+
+    let mut block_data = Vec::with_capacity(schematic.blocks.len());
+    for index in schematic.blocks.iter() {
+        write_varint(&mut block_data, indices[*index as usize]);
+    }
+
+    let root = Tag::Compound(HashMap::from([
+        ("Version".to_string(), Tag::Int(SCHEM_VERSION)),
+        ("DataVersion".to_string(), Tag::Int(DATA_VERSION)),
+        ("Width".to_string(), Tag::Short(size.x as u16 as i16)),
+        ("Height".to_string(), Tag::Short(size.y as u16 as i16)),
+        ("Length".to_string(), Tag::Short(size.z as u16 as i16)),
+        ("PaletteMax".to_string(), Tag::Int(palette.len() as i32)),
+        ("Palette".to_string(), Tag::Compound(palette)),
+        ("BlockData".to_string(), Tag::ByteArray(block_data)),
+    ]));
+
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    nbt::write_root(&mut encoder, "Schematic", &root)?;
+    encoder.finish()?;
+    Ok(())
+}
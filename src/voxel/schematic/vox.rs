@@ -0,0 +1,189 @@
+use anyhow::anyhow;
+use bevy::{math::IVec3, prelude::Color};
+
+use super::{Schematic, SchematicBlock};
+
+const VOX_VERSION: i32 = 150;
+
+/// Maximum size of a model along each axis.
+const MAX_MODEL_SIZE: i32 = 256;
+
+/// A chunk of a `.vox` file: its id, content and children.
+struct VoxChunk<'a> {
+    id: &'a [u8],
+    content: &'a [u8],
+    children: &'a [u8],
+}
+
+fn read_i32(bytes: &[u8], offset: usize) -> anyhow::Result<i32> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|bytes| i32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| anyhow!("unexpected end of file"))
+}
+
+fn read_len(bytes: &[u8], offset: usize) -> anyhow::Result<usize> {
+    let len = read_i32(bytes, offset)?;
+    usize::try_from(len).map_err(|_| anyhow!("negative length {}", len))
+}
+
+/// Splits the consecutive chunks of a buffer.
+fn read_chunks(mut bytes: &[u8]) -> anyhow::Result<Vec<VoxChunk>> {
+    let mut chunks = Vec::new();
+    while !bytes.is_empty() {
+        let content_len = read_len(bytes, 4)?;
+        let children_len = read_len(bytes, 8)?;
+        let content_end = 12 + content_len;
+        let end = content_end + children_len;
+        if end > bytes.len() {
+            return Err(anyhow!("unexpected end of file"));
+        }
+
+        chunks.push(VoxChunk {
+            id: &bytes[..4],
+            content: &bytes[12..content_end],
+            children: &bytes[content_end..end],
+        });
+        bytes = &bytes[end..];
+    }
+    Ok(chunks)
+}
+
+/// Reads the first model of a MagicaVoxel file, the Z up axis of MagicaVoxel becoming the Y axis.
+pub fn read(bytes: &[u8]) -> anyhow::Result<Schematic> {
+    if bytes.len() < 8 || &bytes[..4] != b"VOX " {
+        return Err(anyhow!("not a MagicaVoxel file"));
+    }
+
+    let main = read_chunks(&bytes[8..])?
+        .into_iter()
+        .find(|chunk| chunk.id == b"MAIN")
+        .ok_or_else(|| anyhow!("missing MAIN chunk"))?;
+    let chunks = read_chunks(main.children)?;
+
+    // files with several models (and a scene graph placing them) only get their first model imported.
+    let size = chunks
+        .iter()
+        .find(|chunk| chunk.id == b"SIZE")
+        .ok_or_else(|| anyhow!("missing SIZE chunk"))?;
+    let (x, y, z) = (
+        read_i32(size.content, 0)?,
+        read_i32(size.content, 4)?,
+        read_i32(size.content, 8)?,
+    );
+    if [x, y, z]
+        .iter()
+        .any(|len| !(1..=MAX_MODEL_SIZE).contains(len))
+    {
+        return Err(anyhow!("invalid model size {}x{}x{}", x, y, z));
+    }
+
+    let voxels = chunks
+        .iter()
+        .find(|chunk| chunk.id == b"XYZI")
+        .ok_or_else(|| anyhow!("missing XYZI chunk"))?;
+    let colors = chunks
+        .iter()
+        .find(|chunk| chunk.id == b"RGBA")
+        .filter(|chunk| chunk.content.len() >= 256 * 4)
+        .ok_or_else(|| anyhow!("models using the default MagicaVoxel palette aren't supported"))?;
+
+    // palette index 0 is the empty voxel, index i the color i of the file.
+    let mut palette = vec![None];
+    palette.extend(colors.content.chunks(4).take(255).map(|rgba| {
+        Some(SchematicBlock {
+            name: None,
+            color: Some(Color::rgba_u8(rgba[0], rgba[1], rgba[2], rgba[3])),
+        })
+    }));
+
+    let mut schematic = Schematic {
+        size: IVec3::new(x, z, y),
+        palette,
+        blocks: vec![0; (x * y * z) as usize],
+    };
+
+    let count = read_len(voxels.content, 0)?;
+    let data = voxels
+        .content
+        .get(4..4 + count * 4)
+        .ok_or_else(|| anyhow!("unexpected end of file"))?;
+    for voxel in data.chunks(4) {
+        let [vx, vy, vz, color] = [voxel[0], voxel[1], voxel[2], voxel[3]].map(|v| v as i32);
+        if vx >= x || vy >= y || vz >= z {
+            return Err(anyhow!("voxel out of the model bounds"));
+        }
+
+        // the Y axis of MagicaVoxel points away from the viewer, flipped to keep the model from being mirrored.
+        let index = schematic.index(IVec3::new(vx, vz, y - 1 - vy));
+        schematic.blocks[index] = color as u16;
+    }
+
+    Ok(schematic)
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&(content.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(&(children.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(content);
+    bytes.extend_from_slice(children);
+}
+
+/// Writes a schematic as a MagicaVoxel model, the blocks without a color being written gray.
+pub fn write(schematic: &Schematic) -> anyhow::Result<Vec<u8>> {
+    let size = schematic.size;
+    if size.cmpgt(IVec3::splat(MAX_MODEL_SIZE)).any() {
+        return Err(anyhow!(
+            "MagicaVoxel models are at most {} voxels long",
+            MAX_MODEL_SIZE
+        ));
+    }
+
+    // color index of each palette entry, 0 being the empty voxel.
+    let mut colors: Vec<Color> = Vec::new();
+    let color_indices = schematic
+        .palette
+        .iter()
+        .map(|block| match block {
+            Some(block) => {
+                colors.push(block.color.unwrap_or(Color::GRAY));
+                u8::try_from(colors.len()).map_err(|_| anyhow!("more than 255 distinct materials"))
+            }
+            None => Ok(0),
+        })
+        .collect::<anyhow::Result<Vec<u8>>>()?;
+
+    let mut xyzi = Vec::new();
+    for (pos, index) in schematic.positions().zip(schematic.blocks.iter()) {
+        let color = color_indices[*index as usize];
+        if color != 0 {
+            xyzi.extend_from_slice(&[pos.x as u8, (size.z - 1 - pos.z) as u8, pos.y as u8, color]);
+        }
+    }
+    let mut xyzi_content = ((xyzi.len() / 4) as i32).to_le_bytes().to_vec();
+    xyzi_content.extend(xyzi);
+
+    let size_content: Vec<u8> = [size.x, size.z, size.y]
+        .iter()
+        .flat_map(|len| len.to_le_bytes())
+        .collect();
+
+    let mut rgba = vec![0; 256 * 4];
+    for (color, entry) in colors.iter().zip(rgba.chunks_mut(4)) {
+        let [r, g, b, a] = color
+            .as_rgba_f32()
+            .map(|channel| (channel * 255.0).round() as u8);
+        entry.copy_from_slice(&[r, g, b, a]);
+    }
+
+    let mut children = Vec::new();
+    write_chunk(&mut children, b"SIZE", &size_content, &[]);
+    write_chunk(&mut children, b"XYZI", &xyzi_content, &[]);
+    write_chunk(&mut children, b"RGBA", &rgba, &[]);
+
+    let mut bytes = b"VOX ".to_vec();
+    bytes.extend_from_slice(&VOX_VERSION.to_le_bytes());
+    write_chunk(&mut bytes, b"MAIN", &[], &children);
+    Ok(bytes)
+}
@@ -1,16 +1,29 @@
+use std::{hash::Hash, mem::size_of};
+
 use ilattice::extent::Extent;
 use ilattice::glam::UVec3;
 use ndshape::Shape;
+use once_cell::sync::OnceCell;
+
+use super::{CompressedVoxels, OccupancyBitset};
 
 /// A buffer of typed voxel data stored as a contiguous array in memory.
+/// The buffer can be compressed with [`VoxelBuffer::compress`], its voxels then get decompressed on the first access
+/// to the whole data and the compressed copy is dropped on the first write.
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct VoxelBuffer<V, S: Shape<3, Coord = u32>>
 where
     V: Copy + Clone + Default,
 {
-    data: Box<[V]>,
+    /// The uncompressed voxels, always set unless the buffer is compressed.
+    data: OnceCell<Box<[V]>>,
+    compressed: Option<CompressedVoxels<V>>,
+    /// Whether the last compression attempt didn't pay off, reset by the writes.
+    incompressible: bool,
     shape: S,
+    /// Occupancy of the voxels, computed on first use and dropped by the mutable accessors other than [`VoxelBuffer::set_voxel`].
+    occupancy: OnceCell<OccupancyBitset>,
 }
 
 #[allow(dead_code)]
@@ -21,39 +34,73 @@ where
     #[inline]
     pub fn new(shape: S, initial_val: V) -> Self {
         Self {
-            data: vec![initial_val.clone(); shape.size() as usize].into_boxed_slice(),
+            data: OnceCell::with_value(
+                vec![initial_val.clone(); shape.size() as usize].into_boxed_slice(),
+            ),
+            compressed: None,
+            incompressible: false,
             shape,
+            occupancy: OnceCell::new(),
         }
     }
 
     #[inline]
     pub fn new_empty(shape: S) -> Self {
         Self {
-            data: vec![Default::default(); shape.size() as usize].into_boxed_slice(),
+            data: OnceCell::with_value(
+                vec![Default::default(); shape.size() as usize].into_boxed_slice(),
+            ),
+            compressed: None,
+            incompressible: false,
             shape,
+            occupancy: OnceCell::new(),
         }
     }
 
+    /// Returns the uncompressed voxels, decompressing them if needed.
+    #[inline]
+    fn data(&self) -> &[V] {
+        self.data
+            .get_or_init(|| self.compressed.as_ref().unwrap().decompress())
+    }
+
+    /// Returns the uncompressed voxels for writing, dropping the compressed copy which gets outdated.
+    #[inline]
+    fn data_mut(&mut self) -> &mut [V] {
+        self.data();
+        self.compressed = None;
+        self.incompressible = false;
+        self.data.get_mut().unwrap()
+    }
+
     // Returns the voxel at the querried position in local space.
     #[inline]
     pub fn voxel_at(&self, pos: UVec3) -> V {
-        self.data[self.shape.linearize(pos.to_array()) as usize]
+        let index = self.shape.linearize(pos.to_array()) as usize;
+        match self.data.get() {
+            Some(data) => data[index],
+            // single voxel reads don't need the whole buffer decompressed.
+            None => self.compressed.as_ref().unwrap().get(index),
+        }
     }
 
     // Returns a mutable reference to the the voxel at the querried position in local space.
     #[inline]
     pub fn voxel_at_mut(&mut self, pos: UVec3) -> &mut V {
-        &mut self.data[self.shape.linearize(pos.to_array()) as usize]
+        self.occupancy = OnceCell::new();
+        let index = self.shape.linearize(pos.to_array()) as usize;
+        &mut self.data_mut()[index]
     }
 
     #[inline]
     pub fn slice(&self) -> &[V] {
-        &self.data
+        self.data()
     }
 
     #[inline]
     pub fn slice_mut(&mut self) -> &mut [V] {
-        &mut self.data
+        self.occupancy = OnceCell::new();
+        self.data_mut()
     }
 
     #[inline]
@@ -64,12 +111,97 @@ where
     /// Fills an extent of this buffer with the specified value.
     #[inline]
     pub fn fill_extent(&mut self, extent: Extent<UVec3>, val: V) {
+        self.occupancy = OnceCell::new();
+        self.data_mut();
         ndcopy::fill3(
             extent.shape.to_array(),
             val,
-            &mut self.data,
+            self.data.get_mut().unwrap(),
             &self.shape,
             extent.minimum.to_array(),
         );
     }
+
+    /// Returns whether the uncompressed voxels are dropped, see [`VoxelBuffer::compress`].
+    #[inline]
+    pub fn is_compressed(&self) -> bool {
+        self.data.get().is_none()
+    }
+
+    /// Returns whether [`VoxelBuffer::compress`] would drop the uncompressed voxels, or needs to be tried again after a write.
+    #[inline]
+    pub fn needs_compression(&self) -> bool {
+        !self.is_compressed() && !self.incompressible
+    }
+
+    /// Returns the approximate number of bytes used by the voxels, compressed or not.
+    pub fn memory_usage(&self) -> usize {
+        self.data
+            .get()
+            .map_or(0, |data| data.len() * size_of::<V>())
+            + self
+                .compressed
+                .as_ref()
+                .map_or(0, |compressed| compressed.size_bytes())
+    }
+}
+
+#[allow(dead_code)]
+impl<V, S: Shape<3, Coord = u32>> VoxelBuffer<V, S>
+where
+    V: Copy + Clone + Default + Eq + Hash,
+{
+    /// Compresses the voxels with a palette and run-length encoding, dropping the uncompressed voxels.
+    /// Buffers decompressed by a read since their compression only drop their uncompressed voxels again.
+    /// The buffer is left uncompressed if the compressed voxels wouldn't be smaller.
+    /// Returns whether the uncompressed voxels got dropped.
+    pub fn compress(&mut self) -> bool {
+        if self.compressed.is_none() {
+            let data = self.data.get().unwrap();
+            self.compressed = CompressedVoxels::compress(data)
+                .filter(|compressed| compressed.size_bytes() < data.len() * size_of::<V>());
+        }
+
+        if self.compressed.is_none() {
+            self.incompressible = true;
+            return false;
+        }
+        self.data = OnceCell::new();
+        true
+    }
+}
+
+#[allow(dead_code)]
+impl<V, S: Shape<3, Coord = u32>> VoxelBuffer<V, S>
+where
+    V: Copy + Clone + Default + PartialEq,
+{
+    /// Returns the occupancy of the voxels of this buffer, the default value of [`V`] being the empty voxel.
+    #[inline]
+    pub fn occupancy(&self) -> &OccupancyBitset {
+        self.occupancy
+            .get_or_init(|| OccupancyBitset::from_voxels(self.data(), &V::default()))
+    }
+
+    /// Replaces the voxel at the queried position in local space, keeping the occupancy up to date.
+    #[inline]
+    pub fn set_voxel(&mut self, pos: UVec3, voxel: V) {
+        let index = self.shape.linearize(pos.to_array()) as usize;
+        self.data_mut()[index] = voxel;
+        if let Some(occupancy) = self.occupancy.get_mut() {
+            occupancy.set(index, voxel != V::default());
+        }
+    }
+
+    /// Returns whether all the voxels of the extent (in local space) are empty.
+    pub fn is_extent_empty(&self, extent: Extent<UVec3>) -> bool {
+        let occupancy = self.occupancy();
+        if occupancy.is_empty() {
+            return true;
+        }
+
+        extent
+            .iter3()
+            .all(|pos| !occupancy.get(self.shape.linearize(pos.to_array()) as usize))
+    }
 }
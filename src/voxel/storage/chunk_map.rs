@@ -1,11 +1,13 @@
-use ilattice::{morton::Morton3i32, vector::Map as VecMap};
-use std::{collections::BTreeMap, hash::Hash};
+use ilattice::morton::Morton3i32;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    hash::Hash,
+};
 
 use bevy::math::IVec3;
+use ilattice::extent::Extent;
 use ndshape::Shape;
 
-use crate::voxel::CHUNK_LENGTH;
-
 use super::buffer::VoxelBuffer;
 
 /// Provides an interface to query or modify voxel data for worlds or scenes split into multiple voxel data buffers of a same shape with no level of detail.
@@ -17,6 +19,90 @@ where
     chunks: BTreeMap<Morton3i32, VoxelBuffer<V, S>>,
     shape_mask: IVec3,
     shape: S,
+    /// Recently unloaded buffers, from the least to the most recently unloaded.
+    cache: VecDeque<(IVec3, VoxelBuffer<V, S>)>,
+    cache_capacity: usize,
+    cache_hits: u64,
+    cache_misses: u64,
+    bounds: Option<ChunkMapBounds<V>>,
+}
+
+/// Bounds restricting the voxels of a [`ChunkMap`] to a fixed region, for worlds which aren't infinite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkMapBounds<V> {
+    /// Minimum voxel position of the region.
+    pub min: IVec3,
+    /// Maximum voxel position of the region (exclusive).
+    pub max: IVec3,
+    /// Voxel returned by the queries outside of the region.
+    pub barrier: V,
+}
+
+impl<V> ChunkMapBounds<V> {
+    /// Returns whether the specified voxel position is inside of the region.
+    #[inline]
+    pub fn contains(&self, pos: IVec3) -> bool {
+        pos.cmpge(self.min).all() && pos.cmplt(self.max).all()
+    }
+}
+
+/// Statistics about the cache of recently unloaded buffers of a [`ChunkMap`].
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ChunkCacheStats {
+    /// Number of buffers currently cached.
+    pub len: usize,
+    /// Maximum number of buffers kept in the cache.
+    pub capacity: usize,
+    /// Number of buffers restored from the cache.
+    pub hits: u64,
+    /// Number of restoration attempts which didn't find the buffer in the cache.
+    pub misses: u64,
+}
+
+/// Statistics about the memory used by the voxels of the buffers of a [`ChunkMap`], cached buffers included.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ChunkMemoryStats {
+    /// Number of buffers, cached buffers included.
+    pub buffers: usize,
+    /// Number of compressed buffers.
+    pub compressed: usize,
+    /// Approximate number of bytes used by the voxels.
+    pub bytes: usize,
+    /// Number of bytes the voxels would use without compression.
+    pub uncompressed_bytes: usize,
+}
+
+impl ChunkMemoryStats {
+    /// Returns the ratio of memory saved by the compression.
+    pub fn savings(&self) -> f32 {
+        match self.uncompressed_bytes {
+            0 => 0.0,
+            total => 1.0 - self.bytes as f32 / total as f32,
+        }
+    }
+}
+
+impl std::ops::Add for ChunkMemoryStats {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            buffers: self.buffers + rhs.buffers,
+            compressed: self.compressed + rhs.compressed,
+            bytes: self.bytes + rhs.bytes,
+            uncompressed_bytes: self.uncompressed_bytes + rhs.uncompressed_bytes,
+        }
+    }
+}
+
+impl ChunkCacheStats {
+    /// Returns the ratio of restoration attempts which found the buffer in the cache.
+    pub fn hit_rate(&self) -> f32 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f32 / total as f32,
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -30,25 +116,134 @@ where
             chunks: Default::default(),
             shape_mask: !(IVec3::from(chunk_shape.as_array().map(|x| x as i32)) - IVec3::ONE),
             shape: chunk_shape,
+            cache: Default::default(),
+            cache_capacity: 0,
+            cache_hits: 0,
+            cache_misses: 0,
+            bounds: None,
         }
     }
 
+    /// Sets the number of recently unloaded buffers kept in memory, see [`ChunkMap::unload`].
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    /// Returns the voxel at the specified position if its buffer is loaded, or the barrier voxel if the position is out of the map bounds.
     pub fn voxel_at(&self, pos: IVec3) -> Option<V> {
+        if let Some(bounds) = self.bounds.filter(|bounds| !bounds.contains(pos)) {
+            return Some(bounds.barrier);
+        }
+
         let chunk_minimum = pos & self.shape_mask;
-        let local_minimum = pos.map(|x| x.rem_euclid(CHUNK_LENGTH as i32)).as_uvec3();
+        let local_minimum = (pos - chunk_minimum).as_uvec3();
 
         self.buffer_at(chunk_minimum)
             .and_then(|buffer| Some(buffer.voxel_at(local_minimum)))
     }
 
+    /// Returns a mutable reference to the voxel at the specified position, out of bounds voxels can't be modified.
     pub fn voxel_at_mut(&mut self, pos: IVec3) -> Option<&mut V> {
+        if self.bounds.map_or(false, |bounds| !bounds.contains(pos)) {
+            return None;
+        }
+
         let chunk_minimum = pos & self.shape_mask;
-        let local_minimum = pos.map(|x| x.rem_euclid(CHUNK_LENGTH as i32)).as_uvec3();
+        let local_minimum = (pos - chunk_minimum).as_uvec3();
 
         self.buffer_at_mut(chunk_minimum)
             .and_then(|buffer| Some(buffer.voxel_at_mut(local_minimum)))
     }
 
+    /// Replaces the voxel at the specified position, keeping the occupancy of its buffer up to date.
+    /// Returns whether the voxel got replaced, voxels of unloaded buffers and out of bounds voxels can't be modified.
+    pub fn set_voxel(&mut self, pos: IVec3, voxel: V) -> bool {
+        if self.bounds.map_or(false, |bounds| !bounds.contains(pos)) {
+            return false;
+        }
+
+        let chunk_minimum = pos & self.shape_mask;
+        let local_minimum = (pos - chunk_minimum).as_uvec3();
+
+        match self.buffer_at_mut(chunk_minimum) {
+            Some(buffer) => {
+                buffer.set_voxel(local_minimum, voxel);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether the voxel at the specified position is occupied (non-empty) without reading the voxel data,
+    /// or `None` if its buffer isn't loaded. Out of bounds voxels are occupied by the barrier voxel.
+    pub fn is_occupied(&self, pos: IVec3) -> Option<bool> {
+        if let Some(bounds) = self.bounds.filter(|bounds| !bounds.contains(pos)) {
+            return Some(bounds.barrier != V::default());
+        }
+
+        let chunk_minimum = pos & self.shape_mask;
+        let local_minimum = (pos - chunk_minimum).as_uvec3();
+
+        self.buffer_at(chunk_minimum).map(|buffer| {
+            buffer
+                .occupancy()
+                .get(self.shape.linearize(local_minimum.to_array()) as usize)
+        })
+    }
+
+    /// Returns whether the buffer at the specified minimum is loaded and only holds empty voxels.
+    pub fn is_chunk_empty(&self, minimum: IVec3) -> bool {
+        self.buffer_at(minimum)
+            .map_or(false, |buffer| buffer.occupancy().is_empty())
+    }
+
+    /// Returns whether all the voxels from `min` to `max` (inclusive) are empty, unloaded voxels being considered empty.
+    pub fn is_region_empty(&self, min: IVec3, max: IVec3) -> bool {
+        if let Some(bounds) = self.bounds.as_ref() {
+            let inside = min.cmpge(bounds.min).all() && max.cmplt(bounds.max).all();
+            if !inside && bounds.barrier != V::default() {
+                return false;
+            }
+        }
+
+        let chunk_size = !self.shape_mask + IVec3::ONE;
+        let (first, last) = (min & self.shape_mask, max & self.shape_mask);
+
+        for x in (first.x..=last.x).step_by(chunk_size.x as usize) {
+            for y in (first.y..=last.y).step_by(chunk_size.y as usize) {
+                for z in (first.z..=last.z).step_by(chunk_size.z as usize) {
+                    let chunk_minimum = IVec3::new(x, y, z);
+                    let buffer = match self.buffer_at(chunk_minimum) {
+                        Some(buffer) => buffer,
+                        None => continue,
+                    };
+
+                    let local_min = (min.max(chunk_minimum) - chunk_minimum).as_uvec3();
+                    let local_max = (max.min(chunk_minimum + chunk_size - IVec3::ONE)
+                        - chunk_minimum)
+                        .as_uvec3();
+                    if !buffer.is_extent_empty(Extent::from_min_and_max(local_min, local_max)) {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Restricts the map to a fixed region, or lifts the restriction with `None`.
+    /// Voxel queries outside of the region return its barrier voxel, see [`ChunkMap::voxel_at`].
+    pub fn set_bounds(&mut self, bounds: Option<ChunkMapBounds<V>>) {
+        self.bounds = bounds;
+    }
+
+    /// Returns the region the map is restricted to, if any.
+    pub fn bounds(&self) -> Option<&ChunkMapBounds<V>> {
+        self.bounds.as_ref()
+    }
+
     /// Checks whether there's a buffer at the specified minimum.
     #[inline]
     pub fn exists(&self, minimum: IVec3) -> bool {
@@ -94,6 +289,114 @@ where
         self.chunks.remove(&pos.into())
     }
 
+    /// Removes the buffer at the specified minimum and keeps it in the cache of recently unloaded buffers,
+    /// evicting the least recently unloaded buffer if the cache is full.
+    pub fn unload(&mut self, pos: IVec3) {
+        let buffer = match self.chunks.remove(&pos.into()) {
+            Some(buffer) if self.cache_capacity > 0 => buffer,
+            _ => return,
+        };
+
+        if self.cache.len() >= self.cache_capacity {
+            self.cache.pop_front();
+        }
+        self.cache.push_back((pos, buffer));
+    }
+
+    /// Moves the buffer at the specified minimum back from the cache of recently unloaded buffers.
+    /// Returns whether the buffer was found in the cache.
+    pub fn restore_cached(&mut self, pos: IVec3) -> bool {
+        match self.cache.iter().position(|(key, _)| *key == pos) {
+            Some(index) => {
+                let (_, buffer) = self.cache.remove(index).unwrap();
+                self.chunks.insert(pos.into(), buffer);
+                self.cache_hits += 1;
+                true
+            }
+            None => {
+                self.cache_misses += 1;
+                false
+            }
+        }
+    }
+
+    /// Checks whether the buffer at the specified minimum is in the cache of recently unloaded buffers.
+    pub fn is_cached(&self, pos: IVec3) -> bool {
+        self.cache.iter().any(|(key, _)| *key == pos)
+    }
+
+    /// Drops all the cached buffers.
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Changes the maximum number of cached buffers, evicting the least recently unloaded ones if needed.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.cache_capacity = capacity;
+        while self.cache.len() > capacity {
+            self.cache.pop_front();
+        }
+    }
+
+    /// Returns statistics about the cache of recently unloaded buffers.
+    pub fn cache_stats(&self) -> ChunkCacheStats {
+        ChunkCacheStats {
+            len: self.cache.len(),
+            capacity: self.cache_capacity,
+            hits: self.cache_hits,
+            misses: self.cache_misses,
+        }
+    }
+
+    /// Compresses up to `max` of the buffers in need of compression whose minimum passes the filter, see [`VoxelBuffer::compress`].
+    /// The cached buffers are always compressed, being unused until restored.
+    /// Returns the number of compression attempts.
+    pub fn compress_buffers(&mut self, max: usize, filter: impl Fn(IVec3) -> bool) -> usize {
+        let cached = self
+            .cache
+            .iter_mut()
+            .map(|(key, buffer)| (*key, buffer, true));
+        let loaded = self
+            .chunks
+            .iter_mut()
+            .map(|(key, buffer)| (IVec3::from(*key), buffer, false));
+
+        let mut compressed = 0;
+        for (_, buffer, _) in cached
+            .chain(loaded)
+            .filter(|(key, buffer, cached)| buffer.needs_compression() && (*cached || filter(*key)))
+        {
+            if compressed >= max {
+                break;
+            }
+            buffer.compress();
+            compressed += 1;
+        }
+
+        compressed
+    }
+
+    /// Returns statistics about the memory used by the voxels of the buffers.
+    pub fn memory_stats(&self) -> ChunkMemoryStats {
+        let uncompressed = self.shape.size() as usize * std::mem::size_of::<V>();
+        self.chunks
+            .values()
+            .chain(self.cache.iter().map(|(_, buffer)| buffer))
+            .fold(ChunkMemoryStats::default(), |stats, buffer| {
+                ChunkMemoryStats {
+                    buffers: stats.buffers + 1,
+                    compressed: stats.compressed + buffer.is_compressed() as usize,
+                    bytes: stats.bytes + buffer.memory_usage(),
+                    uncompressed_bytes: stats.uncompressed_bytes + uncompressed,
+                }
+            })
+    }
+
+    /// Returns an iterator over the minimums of the stored buffers.
+    pub fn iter_keys(&self) -> impl Iterator<Item = IVec3> + '_ {
+        self.chunks.keys().map(|key| IVec3::from(*key))
+    }
+
     #[inline]
     pub fn shape_mask(&self) -> IVec3 {
         self.shape_mask
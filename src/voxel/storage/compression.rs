@@ -0,0 +1,99 @@
+use std::{collections::HashMap, hash::Hash, mem::size_of};
+
+/// Number of voxels of a section, small enough for the length of a run to fit in a `u16`.
+const SECTION_LEN: usize = 4096;
+
+/// A run of consecutive voxels sharing the same palette entry.
+#[derive(Clone, Copy, Debug)]
+struct VoxelRun {
+    index: u16,
+    len: u16,
+}
+
+/// Voxel data compressed with a palette of the distinct voxels, the palette indices being run-length encoded per section
+/// of [`SECTION_LEN`] voxels so that a single voxel can be read without decompressing the whole buffer.
+#[derive(Clone, Debug)]
+pub struct CompressedVoxels<V> {
+    palette: Box<[V]>,
+    sections: Box<[Box<[VoxelRun]>]>,
+    len: usize,
+}
+
+#[allow(dead_code)]
+impl<V: Copy> CompressedVoxels<V> {
+    /// Compresses the specified voxels, or returns `None` if they have too many distinct values for the palette.
+    pub fn compress(voxels: &[V]) -> Option<Self>
+    where
+        V: Eq + Hash,
+    {
+        let mut palette = Vec::new();
+        let mut indices = HashMap::new();
+
+        let mut sections = Vec::with_capacity((voxels.len() + SECTION_LEN - 1) / SECTION_LEN);
+        for section in voxels.chunks(SECTION_LEN) {
+            let mut runs: Vec<VoxelRun> = Vec::new();
+            for voxel in section {
+                let index = match indices.get(voxel) {
+                    Some(index) => *index,
+                    None => {
+                        let index = u16::try_from(palette.len()).ok()?;
+                        palette.push(*voxel);
+                        indices.insert(*voxel, index);
+                        index
+                    }
+                };
+
+                match runs.last_mut() {
+                    Some(run) if run.index == index => run.len += 1,
+                    _ => runs.push(VoxelRun { index, len: 1 }),
+                }
+            }
+            sections.push(runs.into_boxed_slice());
+        }
+
+        Some(Self {
+            palette: palette.into_boxed_slice(),
+            sections: sections.into_boxed_slice(),
+            len: voxels.len(),
+        })
+    }
+
+    /// Returns the voxel at the specified linear index.
+    pub fn get(&self, index: usize) -> V {
+        let mut offset = index % SECTION_LEN;
+        for run in self.sections[index / SECTION_LEN].iter() {
+            if offset < run.len as usize {
+                return self.palette[run.index as usize];
+            }
+            offset -= run.len as usize;
+        }
+
+        unreachable!("voxel index {} out of the compressed data", index)
+    }
+
+    /// Returns the decompressed voxels.
+    pub fn decompress(&self) -> Box<[V]> {
+        let mut voxels = Vec::with_capacity(self.len);
+        for run in self.sections.iter().flat_map(|runs| runs.iter()) {
+            voxels
+                .extend(std::iter::repeat(self.palette[run.index as usize]).take(run.len as usize));
+        }
+        voxels.into_boxed_slice()
+    }
+
+    /// Returns the number of distinct voxels.
+    pub fn palette_len(&self) -> usize {
+        self.palette.len()
+    }
+
+    /// Returns the approximate number of bytes used by the compressed data.
+    pub fn size_bytes(&self) -> usize {
+        self.palette.len() * size_of::<V>()
+            + self.sections.len() * size_of::<Box<[VoxelRun]>>()
+            + self
+                .sections
+                .iter()
+                .map(|runs| runs.len() * size_of::<VoxelRun>())
+                .sum::<usize>()
+    }
+}
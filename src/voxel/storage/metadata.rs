@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    math::{IVec3, UVec3},
+    prelude::World,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::voxel::chunk_key_at;
+
+/// The state of a stateful voxel, stored sparsely next to the voxel data.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoxelMetadata {
+    /// Orientation of the voxel, one of the 24 axis aligned rotations.
+    pub rotation: Option<u8>,
+    /// Growth stage of growing voxels (e.g. crops).
+    pub growth_stage: Option<u8>,
+    /// Stacks of voxels held by the voxel (e.g. a chest), as material ids and counts.
+    pub inventory: Vec<(u8, u16)>,
+    /// Named values for the states not covered by the other fields.
+    pub properties: BTreeMap<String, i64>,
+}
+
+/// Property of the [`VoxelMetadata`] holding the height of the partial voxels (e.g. slabs, snow layers), in steps of
+/// `1 / PARTIAL_HEIGHT_STEPS` voxel from the bottom of the voxel.
+pub const PARTIAL_HEIGHT_PROPERTY: &str = "height";
+
+/// Number of steps the height of the partial voxels is quantized to, a height of that many steps being a full voxel.
+pub const PARTIAL_HEIGHT_STEPS: i64 = 16;
+
+impl VoxelMetadata {
+    /// Returns whether the metadata holds no state, in which case it doesn't need to be stored.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the height (as a fraction of a voxel) of a partial voxel, `None` for the full voxels.
+    pub fn partial_height(&self) -> Option<f32> {
+        let steps = *self.properties.get(PARTIAL_HEIGHT_PROPERTY)?;
+        (steps < PARTIAL_HEIGHT_STEPS).then(|| steps.max(1) as f32 / PARTIAL_HEIGHT_STEPS as f32)
+    }
+
+    /// Sets the height (in steps of `1 / PARTIAL_HEIGHT_STEPS` voxel) of a partial voxel, `None` making it a full voxel.
+    /// The chunk of the voxel has to be marked dirty for its collider to match the new height.
+    pub fn set_partial_height(&mut self, steps: Option<u8>) {
+        match steps {
+            Some(steps) => self
+                .properties
+                .insert(PARTIAL_HEIGHT_PROPERTY.to_owned(), steps as i64),
+            None => self.properties.remove(PARTIAL_HEIGHT_PROPERTY),
+        };
+    }
+}
+
+/// The metadata of the voxels of a chunk, keyed by their position within the chunk.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChunkMetadata(pub HashMap<UVec3, VoxelMetadata>);
+
+impl ChunkMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Sparse per-voxel metadata of the loaded chunks (and of the unloaded chunks still cached in the chunk map), stored
+/// alongside the [`super::ChunkMap`] of the voxels. Only the voxels with a state get an entry.
+#[derive(Default)]
+pub struct VoxelMetadataMap {
+    chunks: HashMap<IVec3, ChunkMetadata>,
+    /// The chunks whose metadata changed since they were last saved.
+    modified: HashSet<IVec3>,
+}
+
+#[allow(dead_code)]
+impl VoxelMetadataMap {
+    /// Returns the metadata of the voxel at the specified world position.
+    pub fn get(&self, pos: IVec3) -> Option<&VoxelMetadata> {
+        let key = chunk_key_at(pos);
+        self.chunks
+            .get(&key)
+            .and_then(|chunk| chunk.0.get(&(pos - key).as_uvec3()))
+    }
+
+    /// Returns the metadata of the voxel at the specified world position for modification, inserting empty metadata
+    /// if it has none.
+    pub fn get_or_default(&mut self, pos: IVec3) -> &mut VoxelMetadata {
+        let key = chunk_key_at(pos);
+        self.modified.insert(key);
+        self.chunks
+            .entry(key)
+            .or_default()
+            .0
+            .entry((pos - key).as_uvec3())
+            .or_default()
+    }
+
+    /// Replaces the metadata of the voxel at the specified world position, empty metadata removing it.
+    pub fn insert(&mut self, pos: IVec3, metadata: VoxelMetadata) {
+        if metadata.is_empty() {
+            self.remove(pos);
+        } else {
+            *self.get_or_default(pos) = metadata;
+        }
+    }
+
+    /// Removes the metadata of the voxel at the specified world position, e.g. when the voxel gets replaced.
+    pub fn remove(&mut self, pos: IVec3) -> Option<VoxelMetadata> {
+        let key = chunk_key_at(pos);
+        let chunk = self.chunks.get_mut(&key)?;
+        let removed = chunk.0.remove(&(pos - key).as_uvec3());
+        if removed.is_some() {
+            self.modified.insert(key);
+        }
+        if chunk.is_empty() {
+            self.chunks.remove(&key);
+        }
+        removed
+    }
+
+    /// Returns the metadata of the voxels of a chunk.
+    pub fn chunk(&self, key: IVec3) -> Option<&ChunkMetadata> {
+        self.chunks.get(&key)
+    }
+
+    /// Sets the metadata of a chunk being loaded.
+    pub fn insert_chunk(&mut self, key: IVec3, metadata: ChunkMetadata) {
+        self.modified.remove(&key);
+        if metadata.is_empty() {
+            self.chunks.remove(&key);
+        } else {
+            self.chunks.insert(key, metadata);
+        }
+    }
+
+    /// Drops the metadata of the chunks not matching the filter, e.g. the chunks evicted from the chunk map.
+    pub fn retain_chunks(&mut self, mut filter: impl FnMut(IVec3) -> bool) {
+        self.chunks.retain(|key, _| filter(*key));
+        self.modified.retain(|key| filter(*key));
+    }
+
+    /// Returns whether the metadata of a chunk changed since it was last saved.
+    pub fn is_modified(&self, key: IVec3) -> bool {
+        self.modified.contains(&key)
+    }
+
+    /// Returns the chunks whose metadata changed since they were last saved.
+    pub fn modified_chunks(&self) -> Vec<IVec3> {
+        self.modified.iter().copied().collect()
+    }
+
+    /// Returns the chunks whose metadata changed since they were last saved, and considers them saved.
+    pub fn take_modified(&mut self) -> Vec<IVec3> {
+        self.modified.drain().collect()
+    }
+
+    /// Considers the metadata of a chunk saved.
+    pub fn mark_saved(&mut self, key: IVec3) {
+        self.modified.remove(&key);
+    }
+
+    /// Returns the number of voxels with metadata.
+    pub fn len(&self) -> usize {
+        self.chunks.values().map(|chunk| chunk.0.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+}
+
+/// Access to the voxel metadata from the ECS world, e.g. from exclusive systems and commands.
+pub trait VoxelMetadataWorldExt {
+    /// Returns a copy of the metadata of the voxel at the specified world position.
+    fn voxel_metadata(&self, pos: IVec3) -> Option<VoxelMetadata>;
+
+    /// Replaces the metadata of the voxel at the specified world position, empty metadata removing it.
+    fn set_voxel_metadata(&mut self, pos: IVec3, metadata: VoxelMetadata);
+
+    /// Modifies the metadata of the voxel at the specified world position, the metadata left empty being removed.
+    fn update_voxel_metadata(&mut self, pos: IVec3, update: impl FnOnce(&mut VoxelMetadata));
+}
+
+impl VoxelMetadataWorldExt for World {
+    fn voxel_metadata(&self, pos: IVec3) -> Option<VoxelMetadata> {
+        self.get_resource::<VoxelMetadataMap>()?.get(pos).cloned()
+    }
+
+    fn set_voxel_metadata(&mut self, pos: IVec3, metadata: VoxelMetadata) {
+        self.get_resource_or_insert_with(VoxelMetadataMap::default)
+            .insert(pos, metadata);
+    }
+
+    fn update_voxel_metadata(&mut self, pos: IVec3, update: impl FnOnce(&mut VoxelMetadata)) {
+        let mut map = self.get_resource_or_insert_with(VoxelMetadataMap::default);
+        let mut metadata = map.get(pos).cloned().unwrap_or_default();
+        update(&mut metadata);
+        map.insert(pos, metadata);
+    }
+}
@@ -1,5 +1,20 @@
 mod buffer;
 pub use buffer::*;
 
+/// Bitsets of the occupied voxels of the buffers.
+mod occupancy;
+pub use occupancy::*;
+
+/// Palette and run-length compression of the voxel data of the buffers.
+mod compression;
+pub use compression::*;
+
 mod chunk_map;
 pub use chunk_map::*;
+
+/// Sparse metadata of the stateful voxels.
+mod metadata;
+pub use metadata::*;
+
+mod save;
+pub use save::*;
@@ -0,0 +1,70 @@
+/// A bitset holding whether each voxel of a buffer is occupied (non-empty), 1 bit per voxel.
+/// Lets the queries only interested in the emptiness of voxels skip reading the voxel data, and whole empty buffers get skipped at once.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OccupancyBitset {
+    words: Box<[u64]>,
+    occupied: usize,
+}
+
+#[allow(dead_code)]
+impl OccupancyBitset {
+    /// Builds the occupancy of the specified voxels, any voxel different from `empty` being occupied.
+    pub fn from_voxels<V: PartialEq>(voxels: &[V], empty: &V) -> Self {
+        let mut words = vec![0u64; (voxels.len() + 63) / 64].into_boxed_slice();
+        let mut occupied = 0;
+
+        for (word, chunk) in words.iter_mut().zip(voxels.chunks(64)) {
+            for (bit, voxel) in chunk.iter().enumerate() {
+                if voxel != empty {
+                    *word |= 1 << bit;
+                }
+            }
+            occupied += word.count_ones() as usize;
+        }
+
+        Self { words, occupied }
+    }
+
+    /// Returns whether the voxel at the specified linear index is occupied.
+    #[inline]
+    pub fn get(&self, index: usize) -> bool {
+        self.words[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    /// Changes whether the voxel at the specified linear index is occupied.
+    #[inline]
+    pub fn set(&mut self, index: usize, occupied: bool) {
+        let word = &mut self.words[index / 64];
+        let mask = 1 << (index % 64);
+
+        match (*word & mask != 0, occupied) {
+            (false, true) => {
+                *word |= mask;
+                self.occupied += 1;
+            }
+            (true, false) => {
+                *word &= !mask;
+                self.occupied -= 1;
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the number of occupied voxels.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.occupied
+    }
+
+    /// Returns whether no voxel is occupied.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.occupied == 0
+    }
+
+    /// Returns the bits of the voxels, 64 voxels per word starting from the lowest bit.
+    #[inline]
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+}
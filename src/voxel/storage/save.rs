@@ -0,0 +1,220 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::math::IVec3;
+
+use crate::voxel::{ChunkShape, Voxel};
+
+use super::{ChunkMetadata, VoxelBuffer};
+
+const CHUNK_FILE_MAGIC: &[u8; 4] = b"VXCH";
+const CHUNK_FILE_VERSION: u8 = 1;
+const CHUNK_FILE_EXTENSION: &str = "chunk";
+const METADATA_FILE_EXTENSION: &str = "meta";
+
+/// Generation bookkeeping stored alongside the voxel data of a saved chunk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChunkSaveHeader {
+    /// Bitmask of the world generation stages which were applied to the chunk.
+    pub applied_stages: u32,
+    /// Whether the chunk wasn't modified since it was generated.
+    pub pristine: bool,
+}
+
+/// A chunk as it is stored in a world save.
+pub struct SavedChunk {
+    pub header: ChunkSaveHeader,
+    pub data: VoxelBuffer<Voxel, ChunkShape>,
+    /// Metadata of the stateful voxels of the chunk.
+    pub metadata: ChunkMetadata,
+}
+
+/// A world save on disk, storing each chunk in its own file.
+#[derive(Clone)]
+pub struct WorldSave {
+    root: PathBuf,
+}
+
+impl WorldSave {
+    /// Opens the world save at the specified directory, creating it if needed.
+    pub fn open(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(root.join("chunks"))?;
+        Ok(Self { root })
+    }
+
+    /// Returns the path of the settings overriding the user settings for this world.
+    pub fn settings_path(&self) -> PathBuf {
+        self.root.join("settings.ron")
+    }
+
+    fn material_names_path(&self) -> PathBuf {
+        self.root.join("material_ids.ron")
+    }
+
+    /// Loads the names of the materials by id the chunks were saved with, empty if they were never saved.
+    pub fn load_material_names(&self) -> io::Result<Vec<String>> {
+        match fs::read(self.material_names_path()) {
+            Ok(bytes) => ron::de::from_bytes(&bytes).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("material names are invalid: {}", err),
+                )
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the names of the materials by id, so that reloading the save restores the ids of the saved voxels.
+    pub fn save_material_names<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> io::Result<()> {
+        let names: Vec<&str> = names.into_iter().collect();
+        let serialized = ron::ser::to_string_pretty(&names, Default::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        let path = self.material_names_path();
+        let tmp_path = path.with_extension("ron.tmp");
+        fs::write(&tmp_path, serialized)?;
+        fs::rename(tmp_path, path)
+    }
+
+    fn chunk_path(&self, key: IVec3) -> PathBuf {
+        self.root.join("chunks").join(format!(
+            "{}_{}_{}.{}",
+            key.x, key.y, key.z, CHUNK_FILE_EXTENSION
+        ))
+    }
+
+    fn metadata_path(&self, key: IVec3) -> PathBuf {
+        self.chunk_path(key).with_extension(METADATA_FILE_EXTENSION)
+    }
+
+    /// Loads the chunk at the specified key along with its voxel metadata, returns `Ok(None)` if the chunk was never saved.
+    pub fn load_chunk(&self, key: IVec3) -> io::Result<Option<SavedChunk>> {
+        let mut file = match fs::File::open(self.chunk_path(key)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let mut header = [0u8; 10];
+        file.read_exact(&mut header)?;
+
+        if &header[0..4] != CHUNK_FILE_MAGIC || header[4] != CHUNK_FILE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("chunk file for {:?} has an invalid header", key),
+            ));
+        }
+
+        let mut data = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+        let mut raw = Vec::with_capacity(data.slice().len());
+        file.read_to_end(&mut raw)?;
+        if raw.len() != data.slice().len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "chunk file for {:?} holds {} voxels instead of {}, it may have been saved with another chunk size",
+                    key,
+                    raw.len(),
+                    data.slice().len()
+                ),
+            ));
+        }
+        data.slice_mut()
+            .iter_mut()
+            .zip(raw)
+            .for_each(|(voxel, raw)| *voxel = Voxel(raw));
+
+        Ok(Some(SavedChunk {
+            header: ChunkSaveHeader {
+                pristine: header[5] & 1 != 0,
+                applied_stages: u32::from_le_bytes(header[6..10].try_into().unwrap()),
+            },
+            data,
+            metadata: self.load_chunk_metadata(key)?,
+        }))
+    }
+
+    /// Loads the voxel metadata of a chunk, stored as RON next to the chunk file when the chunk has any.
+    pub fn load_chunk_metadata(&self, key: IVec3) -> io::Result<ChunkMetadata> {
+        match fs::read(self.metadata_path(key)) {
+            Ok(bytes) => ron::de::from_bytes(&bytes).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("metadata of chunk {:?} is invalid: {}", key, err),
+                )
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(ChunkMetadata::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the voxel metadata of a chunk, removing its file when the chunk has none left.
+    pub fn save_chunk_metadata(&self, key: IVec3, metadata: &ChunkMetadata) -> io::Result<()> {
+        let path = self.metadata_path(key);
+        if metadata.is_empty() {
+            return match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+
+        let serialized = ron::ser::to_string(metadata)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        let tmp_path = path.with_extension("meta.tmp");
+        fs::write(&tmp_path, serialized)?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Writes the chunk at the specified key to disk, overwriting any previous version.
+    pub fn save_chunk(
+        &self,
+        key: IVec3,
+        header: ChunkSaveHeader,
+        data: &VoxelBuffer<Voxel, ChunkShape>,
+    ) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(10 + data.slice().len());
+        bytes.extend_from_slice(CHUNK_FILE_MAGIC);
+        bytes.push(CHUNK_FILE_VERSION);
+        bytes.push(header.pristine as u8);
+        bytes.extend_from_slice(&header.applied_stages.to_le_bytes());
+        bytes.extend(data.slice().iter().map(|voxel| voxel.0));
+
+        // write to a temporary file first so that a crash mid-write doesn't corrupt the chunk.
+        let path = self.chunk_path(key);
+        let tmp_path = path.with_extension("tmp");
+        fs::File::create(&tmp_path)?.write_all(&bytes)?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Returns the keys of all the chunks stored in this save.
+    pub fn chunk_keys(&self) -> io::Result<Vec<IVec3>> {
+        Ok(fs::read_dir(self.root.join("chunks"))?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != CHUNK_FILE_EXTENSION {
+                    return None;
+                }
+
+                let coords = path
+                    .file_stem()?
+                    .to_str()?
+                    .split('_')
+                    .map(|x| x.parse::<i32>().ok())
+                    .collect::<Option<Vec<_>>>()?;
+
+                match coords[..] {
+                    [x, y, z] => Some(IVec3::new(x, y, z)),
+                    _ => None,
+                }
+            })
+            .collect())
+    }
+}
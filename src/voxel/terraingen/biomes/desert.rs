@@ -1,13 +1,11 @@
-use bevy::math::{IVec3, UVec3, Vec2, Vec3, Vec3Swizzles};
-use ilattice::prelude::Extent;
+use bevy::math::{IVec3, UVec3, Vec3};
 
 use crate::voxel::{
     material::VoxelMaterial,
-    materials::{Cactus, Sand, Sandstone},
+    materials::{Cactus, Sand},
     sdf,
-    storage::VoxelBuffer,
-    terraingen::noise,
-    ChunkShape, Voxel, CHUNK_LENGTH,
+    terraingen::{noise::Heightmap, scatter::ScatterDecorator, structures::StructureWriter},
+    Voxel, CHUNK_LENGTH_U,
 };
 
 use super::LayeredBiomeTerrainGenerator;
@@ -15,36 +13,24 @@ use super::LayeredBiomeTerrainGenerator;
 pub struct BasicDesertBiomeTerrainGenerator;
 
 impl LayeredBiomeTerrainGenerator for BasicDesertBiomeTerrainGenerator {
-    fn fill_strata(&self, layer: u32) -> Voxel {
-        match layer {
-            0..=5 => Sand::into_voxel(),
-            _ => Sandstone::into_voxel(),
-        }
-    }
-
-    fn place_decoration(
+    fn place_decorations(
         &self,
-        key: IVec3,
-        pos: UVec3,
-        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+        heightmap: &Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
+        writer: &mut StructureWriter,
     ) {
-        let cacti_spawn_chance = noise::rand2to1(
-            (pos.xz().as_vec2() + key.xz().as_vec2()) * 0.1,
-            Vec2::new(12.989, 78.233),
-        );
-
-        if cacti_spawn_chance > 0.992 {
-            let size = ((cacti_spawn_chance - 0.992) * 2000.0) as u32 + 2;
-            make_cacti(buffer, pos, size);
-        }
+        ScatterDecorator::new(0, 4.0, 8)
+            .with_anchor(|voxel: Voxel| voxel == Sand::into_voxel())
+            .scatter(heightmap, writer, |writer, pos, random| {
+                make_cacti(writer, pos, (random * 16.0) as u32 + 2);
+            });
     }
 }
 
-fn make_cacti(buffer: &mut VoxelBuffer<Voxel, ChunkShape>, pos: UVec3, size: u32) {
-    Extent::from_min_and_shape(UVec3::ZERO, UVec3::splat(CHUNK_LENGTH))
-        .iter3()
-        .filter(|x| {
-            sdf::sdf_v_capsule(x.as_vec3() - pos.as_vec3() - Vec3::Y, size as f32, 1.5) < 0.0
-        })
-        .for_each(|x| *buffer.voxel_at_mut(x) = Cactus::into_voxel());
+fn make_cacti(writer: &mut StructureWriter, pos: IVec3, size: u32) {
+    writer.fill_where(
+        pos - IVec3::new(2, 0, 2),
+        UVec3::new(5, size + 4, 5),
+        Cactus::into_voxel(),
+        |x| sdf::sdf_v_capsule((x - pos).as_vec3() - Vec3::Y, size as f32, 1.5) < 0.0,
+    );
 }
@@ -1,91 +1,36 @@
-use std::ops::Div;
-
-use bevy::math::{IVec3, UVec3};
-use ilattice::{glam::UVec2, prelude::Extent};
+use bevy::math::IVec3;
 
 use crate::voxel::{
-    material::VoxelMaterial,
-    materials::{Dirt, Grass},
-    storage::VoxelBuffer,
-    terraingen::noise::Heightmap,
-    ChunkShape, Voxel, CHUNK_LENGTH, CHUNK_LENGTH_U,
+    terraingen::{common::SEA_LEVEL, noise::Heightmap, structures::StructureWriter},
+    CHUNK_HEIGHT, CHUNK_LENGTH_U,
 };
 
 use super::BiomeTerrainGenerator;
 
-/// A biome terrain generator that applies a set of layers on top of the terrain.
+/// A biome terrain generator that places decorations on top of the terrain surface.
+/// The surface material layers themselves come from the biome palette.
 pub trait LayeredBiomeTerrainGenerator: BiomeTerrainGenerator {
-    /// The height function to use for applying the biome material layers on top of the terrain.
-    fn fill_strata(&self, layer: u32) -> Voxel {
-        match layer {
-            0..=1 => Grass::into_voxel(),
-            _ => Dirt::into_voxel(),
-        }
-    }
-
-    /// Numbers of material layers to apply on top of the terrain
-    fn num_layers(&self) -> u32 {
-        8
-    }
-
-    fn place_decoration(
+    /// Places the decorations of a chunk above sea level, usually through [`ScatterDecorator`](crate::voxel::terraingen::scatter::ScatterDecorator)s.
+    fn place_decorations(
         &self,
-        _key: IVec3,
-        _pos: UVec3,
-        _buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+        _heightmap: &Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
+        _writer: &mut StructureWriter,
     ) {
     }
 }
 
 impl<T: LayeredBiomeTerrainGenerator> BiomeTerrainGenerator for T {
-    fn carve_terrain(
-        &self,
-        chunk_key: IVec3,
-        heightmap: Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
-        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
-    ) {
-        Extent::from_min_and_shape(UVec2::ZERO, UVec2::splat(CHUNK_LENGTH))
-            .iter2()
-            .for_each(|pos| {
-                let height = heightmap.get(pos.into());
-                // we only want to apply surface layer decoration on top of the surface chunk
-                if height.div(CHUNK_LENGTH) == (chunk_key.y as u32).div(CHUNK_LENGTH) {
-                    let local_height = height.rem_euclid(CHUNK_LENGTH);
-
-                    for h in 0..=self.num_layers() {
-                        let remaining_height = local_height.checked_sub(h);
-
-                        match remaining_height {
-                            Some(uh) => {
-                                *buffer.voxel_at_mut([pos.x, uh, pos.y].into()) =
-                                    self.fill_strata(h)
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-            });
-    }
-
     fn decorate_terrain(
         &self,
         chunk_key: IVec3,
         heightmap: Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
-        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+        writer: &mut StructureWriter,
     ) {
-        if chunk_key.y <= 96 {
+        // the chunks below sea level are flooded.
+        if chunk_key.y + CHUNK_HEIGHT as i32 <= SEA_LEVEL as i32 {
             return;
         }
 
-        Extent::from_min_and_shape(UVec2::ZERO, UVec2::splat(CHUNK_LENGTH))
-            .iter2()
-            .for_each(|pos| {
-                let height = heightmap.get(pos.into());
-
-                if height.div(CHUNK_LENGTH) == (chunk_key.y as u32).div(CHUNK_LENGTH) {
-                    let local_height = height.rem_euclid(CHUNK_LENGTH);
-                    self.place_decoration(chunk_key, [pos.x, local_height, pos.y].into(), buffer);
-                }
-            });
+        self.place_decorations(&heightmap, writer);
     }
 }
@@ -1,6 +1,6 @@
 use crate::voxel::{storage::VoxelBuffer, ChunkShape, Voxel, CHUNK_LENGTH_U};
 
-use super::noise::Heightmap;
+use super::{noise::Heightmap, structures::StructureWriter};
 
 mod layered;
 use bevy::math::IVec3;
@@ -18,20 +18,22 @@ pub use snowy_plains::*;
 /// A trait representing a terrain generator for a biome.
 /// A biome can be defined as a collection of features that are applied on top of the terrain.
 pub trait BiomeTerrainGenerator: 'static + Sync + Send {
-    /// Carve the terrain using the materials for the biome.
+    /// Apply biome specific changes to the terrain shape after the biome palette was applied.
     fn carve_terrain(
         &self,
-        chunk_key: IVec3,
-        heightmap: Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
-        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
-    );
+        _chunk_key: IVec3,
+        _heightmap: Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
+        _buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+    ) {
+    }
 
     /// Decorate the terrain with this biome specific features (e.g. flowers, trees, ores etc).
+    /// Features may extend past the chunk borders, the [`StructureWriter`] takes care of deferring them to the neighboring chunks.
     fn decorate_terrain(
         &self,
         chunk_key: IVec3,
         heightmap: Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
-        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+        writer: &mut StructureWriter,
     );
 }
 
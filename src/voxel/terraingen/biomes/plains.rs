@@ -1,11 +1,12 @@
-use bevy::math::{IVec3, UVec3, Vec2, Vec3Swizzles};
+use bevy::math::IVec3;
 
 use crate::voxel::{
     material::VoxelMaterial,
-    materials::{Dirt, Grass, Leaves, Wood},
-    storage::VoxelBuffer,
-    terraingen::{common::make_tree, noise},
-    ChunkShape, Voxel,
+    materials::{Flower, Grass, Leaves, TallGrass, Wood},
+    terraingen::{
+        common::make_tree, noise::Heightmap, scatter::ScatterDecorator, structures::StructureWriter,
+    },
+    Voxel, CHUNK_LENGTH_U,
 };
 
 use super::LayeredBiomeTerrainGenerator;
@@ -13,29 +14,31 @@ use super::LayeredBiomeTerrainGenerator;
 pub struct BasicPlainsBiomeTerrainGenerator;
 
 impl LayeredBiomeTerrainGenerator for BasicPlainsBiomeTerrainGenerator {
-    fn fill_strata(&self, layer: u32) -> Voxel {
-        match layer {
-            0..=1 => Grass::into_voxel(),
-            _ => Dirt::into_voxel(),
-        }
-    }
-
-    fn place_decoration(
+    fn place_decorations(
         &self,
-        key: IVec3,
-        pos: UVec3,
-        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+        heightmap: &Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
+        writer: &mut StructureWriter,
     ) {
-        let spawn_chance = noise::rand2to1(
-            (pos.xz().as_vec2() + key.xz().as_vec2()) * 0.1,
-            Vec2::new(12.989, 78.233),
-        );
+        let is_grass = |voxel: Voxel| voxel == Grass::into_voxel();
+
+        ScatterDecorator::new(0, 280.0, 1)
+            .with_anchor(is_grass)
+            .scatter(heightmap, writer, |writer, pos, _| {
+                writer.set_voxel(pos + IVec3::Y, TallGrass::into_voxel());
+            });
+
+        ScatterDecorator::new(1, 6.0, 3)
+            .with_anchor(is_grass)
+            .scatter(heightmap, writer, |writer, pos, _| {
+                writer.set_voxel(pos + IVec3::Y, Flower::into_voxel());
+            });
 
-        if spawn_chance > 0.981 {
-            if pos.y <= 13 {
-                // this is a stupid hack but a real fix would be to allow terrain decoration to work vertically
-                make_tree::<Wood, Leaves>(buffer, pos);
-            }
-        }
+        // trees come last so that their trunk replaces the grass or flower they land on.
+        ScatterDecorator::new(2, 8.0, 7)
+            .with_jitter(0.8)
+            .with_anchor(is_grass)
+            .scatter(heightmap, writer, |writer, pos, _| {
+                make_tree::<Wood, Leaves>(writer, pos);
+            });
     }
 }
@@ -1,11 +1,11 @@
-use bevy::math::{IVec3, UVec3, Vec2, Vec3Swizzles};
-
 use crate::voxel::{
     material::VoxelMaterial,
-    materials::{Dirt, Grass, PineLeaves, Snow, PineWood},
-    storage::VoxelBuffer,
-    terraingen::{common::make_pine_tree, noise},
-    ChunkShape, Voxel,
+    materials::{PineLeaves, PineWood, Snow},
+    terraingen::{
+        common::make_pine_tree, noise::Heightmap, scatter::ScatterDecorator,
+        structures::StructureWriter,
+    },
+    Voxel, CHUNK_LENGTH_U,
 };
 
 use super::LayeredBiomeTerrainGenerator;
@@ -13,30 +13,16 @@ use super::LayeredBiomeTerrainGenerator;
 pub struct BasicSnowyPlainsBiomeTerrainGenerator;
 
 impl LayeredBiomeTerrainGenerator for BasicSnowyPlainsBiomeTerrainGenerator {
-    fn fill_strata(&self, layer: u32) -> Voxel {
-        match layer {
-            0 => Snow::into_voxel(),
-            1..=2 => Grass::into_voxel(),
-            _ => Dirt::into_voxel(),
-        }
-    }
-
-    fn place_decoration(
+    fn place_decorations(
         &self,
-        key: IVec3,
-        pos: UVec3,
-        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+        heightmap: &Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
+        writer: &mut StructureWriter,
     ) {
-        let spawn_chance = noise::rand2to1(
-            (pos.xz().as_vec2() + key.xz().as_vec2()) * 0.1,
-            Vec2::new(12.989, 78.233),
-        );
-
-        if spawn_chance > 0.981 {
-            if pos.y <= 13 {
-                // this is a stupid hack but a real fix would be to allow terrain decoration to work vertically
-                make_pine_tree::<PineWood, PineLeaves>(buffer, pos);
-            }
-        }
+        ScatterDecorator::new(0, 8.0, 8)
+            .with_jitter(0.8)
+            .with_anchor(|voxel: Voxel| voxel == Snow::into_voxel())
+            .scatter(heightmap, writer, |writer, pos, _| {
+                make_pine_tree::<PineWood, PineLeaves>(writer, pos);
+            });
     }
 }
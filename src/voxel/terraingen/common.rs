@@ -1,15 +1,25 @@
-use bevy::math::{IVec3, Vec3};
+use std::{ops::Div, sync::Arc};
+
+use bevy::math::{IVec2, IVec3, Vec3, Vec3Swizzles};
 use ilattice::{glam::UVec2, glam::UVec3, prelude::Extent};
 
 use crate::voxel::{
+    biomes::{BiomeMap, BiomeRegistry},
     material::VoxelMaterial,
-    materials::{Bedrock, Rock, Water},
+    materials::{Bedrock, Dirt, Flower, Grass, Gravel, Lava, Sand, TallGrass, Water},
     sdf,
     storage::VoxelBuffer,
-    ChunkShape, Voxel, CHUNK_LENGTH, CHUNK_LENGTH_U,
+    terraingen::TerrainGenConfig,
+    ChunkShape, Voxel, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_LENGTH_U, CHUNK_SIZE,
+};
+
+use super::{
+    noise::{generate_beach_noise, generate_cave_noise, ColumnHeightmapCache, Heightmap},
+    structures::StructureWriter,
 };
 
-use super::noise::Heightmap;
+/// World height of the water surface, the terrain below it is flooded.
+pub const SEA_LEVEL: u32 = 128;
 
 /// Generate the world bottom border for a chunk.
 pub fn terrain_generate_world_bottom_border(buffer: &mut VoxelBuffer<Voxel, ChunkShape>) {
@@ -19,16 +29,75 @@ pub fn terrain_generate_world_bottom_border(buffer: &mut VoxelBuffer<Voxel, Chun
     )
 }
 
-/// Carve the general terrain shape for a chunk.
+/// Fills a chunk with flat ground surfacing at sea level, used in place of the chunks whose generation failed.
+pub fn terrain_generate_fallback(buffer: &mut VoxelBuffer<Voxel, ChunkShape>, key: IVec3) {
+    const GROUND_HEIGHT: i32 = SEA_LEVEL as i32;
+
+    if key.y < GROUND_HEIGHT {
+        let top = (GROUND_HEIGHT - key.y).min(CHUNK_HEIGHT as i32) as u32;
+        buffer.fill_extent(
+            Extent::from_min_and_shape(UVec3::ZERO, UVec3::new(CHUNK_LENGTH, top, CHUNK_LENGTH)),
+            Dirt::into_voxel(),
+        );
+
+        if key.y + CHUNK_HEIGHT as i32 == GROUND_HEIGHT {
+            buffer.fill_extent(
+                Extent::from_min_and_shape(
+                    UVec3::new(0, top - 1, 0),
+                    UVec3::new(CHUNK_LENGTH, 1, CHUNK_LENGTH),
+                ),
+                Grass::into_voxel(),
+            );
+        }
+    }
+
+    if key.y == 0 {
+        terrain_generate_world_bottom_border(buffer);
+    }
+}
+
+/// Fills a chunk with the layers of a superflat terrain, stacked from the world bottom up.
+pub fn terrain_generate_superflat(
+    buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+    key: IVec3,
+    layers: &[(Voxel, u32)],
+) {
+    let mut layer_bottom = 0;
+    for (voxel, thickness) in layers.iter().copied() {
+        let layer_top = layer_bottom + thickness as i32;
+        let (bottom, top) = (
+            (layer_bottom - key.y).max(0),
+            (layer_top - key.y).min(CHUNK_HEIGHT as i32),
+        );
+        if bottom < top {
+            buffer.fill_extent(
+                Extent::from_min_and_shape(
+                    UVec3::new(0, bottom as u32, 0),
+                    UVec3::new(CHUNK_LENGTH, (top - bottom) as u32, CHUNK_LENGTH),
+                ),
+                voxel,
+            );
+        }
+        layer_bottom = layer_top;
+    }
+
+    if key.y == 0 {
+        terrain_generate_world_bottom_border(buffer);
+    }
+}
+
+/// Carve the general terrain shape for a chunk using the underground material of each column biome.
 pub fn terrain_carve_heightmap(
     buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
     key: IVec3,
     heighmap: &Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
+    biome_map: &BiomeMap,
+    biomes: &BiomeRegistry,
 ) {
     // drown the terrain under sea level.
-    if key.y <= 96 {
+    if key.y + CHUNK_HEIGHT as i32 <= SEA_LEVEL as i32 {
         buffer.fill_extent(
-            Extent::from_min_and_shape(UVec3::ZERO, UVec3::splat(CHUNK_LENGTH)),
+            Extent::from_min_and_shape(UVec3::ZERO, CHUNK_SIZE.as_uvec3()),
             Water::into_voxel(),
         );
     }
@@ -41,70 +110,216 @@ pub fn terrain_carve_heightmap(
                 .get(pos.into())
                 .checked_sub(key.y as u32)
                 .unwrap_or_default()
-                .min(CHUNK_LENGTH);
+                .min(CHUNK_HEIGHT);
+
+            let underground = biomes
+                .get_by_id(biome_map.get(pos.into()))
+                .unwrap()
+                .palette
+                .underground;
 
             for h in 0..local_height {
-                *buffer.voxel_at_mut([pos.x, h, pos.y].into()) = Rock::into_voxel();
+                *buffer.voxel_at_mut([pos.x, h, pos.y].into()) = underground;
             }
         });
 }
 
-pub fn make_pine_tree<T: VoxelMaterial, L: VoxelMaterial>(
+/// Apply the surface material layers of each column biome on top of the terrain.
+pub fn terrain_apply_biome_layers(
     buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
-    origin: UVec3,
+    key: IVec3,
+    heightmap: &Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
+    biome_map: &BiomeMap,
+    biomes: &BiomeRegistry,
 ) {
-    Extent::from_min_and_shape(UVec3::ZERO, UVec3::splat(CHUNK_LENGTH)) //may want to calculate an extent encompassing the tree instead of iterating over the complete 32^3 volume
-        .iter3()
-        .map(|position| {
-            let trunk_distance = sdf::sdf_capped_cylinder(
-                position.as_vec3() - (origin.as_vec3() + 2.0 * Vec3::Y),
-                1.5,
-                8.0,
-            ) < 0.;
-            let leaves_distance = sdf::sdf_vcone(
-                position.as_vec3() - (origin.as_vec3() + 6.0 * Vec3::Y),
-                7.0,
-                17.0,
-            ) < 0.;
-            (trunk_distance, leaves_distance, position)
-        })
-        .for_each(|(trunk_distance, leaves_distance, position)| {
-            if trunk_distance {
-                *buffer.voxel_at_mut(position) = T::into_voxel()
+    Extent::from_min_and_shape(UVec2::ZERO, UVec2::splat(CHUNK_LENGTH))
+        .iter2()
+        .for_each(|pos| {
+            let height = heightmap.get(pos.into());
+            // we only want to apply surface layer decoration on top of the surface chunk
+            if height.div(CHUNK_HEIGHT) != (key.y as u32).div(CHUNK_HEIGHT) {
+                return;
             }
 
-            if leaves_distance {
-                *buffer.voxel_at_mut(position) = L::into_voxel()
+            let palette = &biomes.get_by_id(biome_map.get(pos.into())).unwrap().palette;
+            let local_height = height.rem_euclid(CHUNK_HEIGHT);
+
+            for depth in 0..palette.depth() {
+                if let (Some(h), Some(material)) =
+                    (local_height.checked_sub(depth), palette.layer_at(depth))
+                {
+                    *buffer.voxel_at_mut([pos.x, h, pos.y].into()) = material;
+                }
             }
-        })
+        });
 }
 
-/// Make a tree using SDF functions
-pub fn make_tree<T: VoxelMaterial, L: VoxelMaterial>(
+/// Carve caves into the terrain of a chunk using 3D noise.
+/// Water is left untouched and the topmost voxels of each column are kept to avoid poking holes everywhere in the surface.
+/// Caves are flooded with lava below the configured lava level.
+pub fn terrain_carve_caves(
     buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
-    origin: UVec3,
+    key: IVec3,
+    heightmap: &Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
+    config: &TerrainGenConfig,
 ) {
-    Extent::from_min_and_shape(UVec3::ZERO, UVec3::splat(CHUNK_LENGTH)) //may want to calculate an extent encompassing the tree instead of iterating over the complete 32^3 volume
+    if !config.caves_enabled {
+        return;
+    }
+
+    let noise = generate_cave_noise(
+        key,
+        CHUNK_SIZE.as_uvec3(),
+        config.cave_frequency,
+        config.seed,
+    );
+
+    Extent::from_min_and_shape(UVec3::ZERO, CHUNK_SIZE.as_uvec3())
         .iter3()
-        .map(|position| {
-            let trunk_distance = sdf::sdf_capped_cylinder(
-                position.as_vec3() - (origin.as_vec3() + 2.0 * Vec3::Y),
-                1.5,
-                8.0,
-            ) < 0.;
-            let leaves_distance = sdf::sdf_sphere(
-                position.as_vec3() - (origin.as_vec3() + 14.0 * Vec3::Y),
-                6.0,
-            ) < 0.;
-            (trunk_distance, leaves_distance, position)
-        })
-        .for_each(|(trunk_distance, leaves_distance, position)| {
-            if trunk_distance {
-                *buffer.voxel_at_mut(position) = T::into_voxel()
+        .for_each(|pos| {
+            let surface = heightmap.get([pos.x, pos.z]);
+            let world_height = key.y as u32 + pos.y;
+
+            // keep the bottom of the world (and its bedrock) solid.
+            if world_height < 4 || world_height + config.cave_surface_margin > surface {
+                return;
             }
 
-            if leaves_distance {
-                *buffer.voxel_at_mut(position) = L::into_voxel()
+            let noise_value =
+                noise[(pos.x + CHUNK_LENGTH * (pos.y + CHUNK_HEIGHT * pos.z)) as usize];
+            if noise_value < config.cave_threshold {
+                return;
+            }
+
+            let voxel = buffer.voxel_at_mut(pos);
+            if *voxel != Water::into_voxel() {
+                *voxel = if world_height < config.cave_lava_level {
+                    Lava::into_voxel()
+                } else {
+                    Voxel::EMPTY_VOXEL
+                };
             }
         });
 }
+
+/// Turns the surface of the columns around sea level and close to the water into beaches.
+/// Columns above sea level become sand up to `beach_width` voxels away from the water (modulated by noise),
+/// the shallow sea bed becomes sand or gravel. The foliage growing on the new beaches is removed.
+pub fn terrain_generate_beaches(
+    buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+    key: IVec3,
+    heightmap: &Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
+    heightmaps: &ColumnHeightmapCache,
+    config: &TerrainGenConfig,
+) {
+    // thickness of the sand layer.
+    const BEACH_DEPTH: u32 = 3;
+
+    let min_height = SEA_LEVEL.saturating_sub(config.beach_height + BEACH_DEPTH);
+    let max_height = SEA_LEVEL + config.beach_height;
+    let chunk_min = key.y.max(0) as u32;
+    if !config.beaches_enabled
+        || chunk_min > max_height + 1
+        || chunk_min + CHUNK_HEIGHT < min_height
+    {
+        return;
+    }
+
+    // the water may be in the neighboring columns, whose heightmaps are needed too.
+    let chunk_length = CHUNK_LENGTH as i32;
+    let columns: Vec<Arc<[f32]>> = (-1..=1)
+        .flat_map(|z| (-1..=1).map(move |x| IVec2::new(x, z)))
+        .map(|offset| heightmaps.get(key.xz() + offset * chunk_length, config.seed))
+        .collect();
+    let is_sea = |x: i32, z: i32| {
+        let (column_x, column_z) = (x.div_euclid(chunk_length), z.div_euclid(chunk_length));
+        let (local_x, local_z) = (x.rem_euclid(chunk_length), z.rem_euclid(chunk_length));
+        columns[((column_z + 1) * 3 + column_x + 1) as usize]
+            [(local_z * chunk_length + local_x) as usize]
+            .round()
+            < SEA_LEVEL as f32
+    };
+    let width_noise = generate_beach_noise(key, CHUNK_LENGTH_U, config.seed);
+
+    Extent::from_min_and_shape(UVec2::ZERO, UVec2::splat(CHUNK_LENGTH))
+        .iter2()
+        .for_each(|pos| {
+            let height = heightmap.get(pos.into());
+            if height < min_height || height > max_height {
+                return;
+            }
+
+            let noise = width_noise[(pos.y * CHUNK_LENGTH + pos.x) as usize];
+            let underwater = height < SEA_LEVEL;
+            if !underwater {
+                let width = (config.beach_width as f32 * (0.4 + 0.6 * noise)).round() as i32;
+                // the heightmaps of the neighboring columns only cover a chunk length around the chunk.
+                let width = width.min(chunk_length);
+                let (x, z) = (pos.x as i32, pos.y as i32);
+                let near_water = (-width..=width).any(|dz| {
+                    (-width..=width)
+                        .any(|dx| dx * dx + dz * dz <= width * width && is_sea(x + dx, z + dz))
+                });
+                if !near_water {
+                    return;
+                }
+            }
+
+            // the deeper sea bed patches are gravel rather than sand.
+            let material = if underwater && noise < 0.35 {
+                Gravel::into_voxel()
+            } else {
+                Sand::into_voxel()
+            };
+
+            for world_height in height.saturating_sub(BEACH_DEPTH - 1)..=height + 1 {
+                let local_height = match world_height.checked_sub(chunk_min) {
+                    Some(local_height) if local_height < CHUNK_HEIGHT => local_height,
+                    _ => continue,
+                };
+
+                let voxel = buffer.voxel_at_mut([pos.x, local_height, pos.y].into());
+                if world_height > height {
+                    if *voxel == TallGrass::into_voxel() || *voxel == Flower::into_voxel() {
+                        *voxel = Voxel::EMPTY_VOXEL;
+                    }
+                } else if *voxel != Voxel::EMPTY_VOXEL
+                    && *voxel != Water::into_voxel()
+                    && *voxel != Lava::into_voxel()
+                {
+                    *voxel = material;
+                }
+            }
+        });
+}
+
+/// Make a pine tree using SDF functions, `origin` is relative to the chunk minimum.
+pub fn make_pine_tree<T: VoxelMaterial, L: VoxelMaterial>(
+    writer: &mut StructureWriter,
+    origin: IVec3,
+) {
+    let min = origin - IVec3::new(7, 6, 7);
+    let shape = UVec3::new(15, 30, 15);
+
+    writer.fill_where(min, shape, T::into_voxel(), |position| {
+        sdf::sdf_capped_cylinder((position - origin).as_vec3() - 2.0 * Vec3::Y, 1.5, 8.0) < 0.
+    });
+
+    writer.fill_where(min, shape, L::into_voxel(), |position| {
+        sdf::sdf_vcone((position - origin).as_vec3() - 6.0 * Vec3::Y, 7.0, 17.0) < 0.
+    });
+}
+
+/// Make a tree using SDF functions, `origin` is relative to the chunk minimum.
+pub fn make_tree<T: VoxelMaterial, L: VoxelMaterial>(writer: &mut StructureWriter, origin: IVec3) {
+    let min = origin - IVec3::new(6, 6, 6);
+    let shape = UVec3::new(13, 27, 13);
+
+    writer.fill_where(min, shape, T::into_voxel(), |position| {
+        sdf::sdf_capped_cylinder((position - origin).as_vec3() - 2.0 * Vec3::Y, 1.5, 8.0) < 0.
+    });
+
+    writer.fill_where(min, shape, L::into_voxel(), |position| {
+        sdf::sdf_sphere((position - origin).as_vec3() - 14.0 * Vec3::Y, 6.0) < 0.
+    });
+}
@@ -1,21 +1,37 @@
-use float_ord::FloatOrd;
-use std::{collections::BTreeMap, sync::RwLock};
+use std::{
+    fmt, fs,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::RwLock,
+};
 
+#[cfg(not(feature = "headless"))]
+use bevy::math::Vec3;
 use bevy::{
-    math::{IVec3, Vec3Swizzles},
-    prelude::Plugin,
+    math::{IVec2, IVec3},
+    prelude::{Color, EventWriter, Plugin, Res, ResMut},
 };
 use once_cell::sync::Lazy;
+use serde::Deserialize;
 
 use self::{
-    biomes::{BiomeTerrainGenerator, IntoBoxedTerrainGenerator},
-    common::terrain_generate_world_bottom_border,
-    noise::{generate_heightmap_data, Heightmap},
+    biomes::IntoBoxedTerrainGenerator,
+    common::{terrain_generate_superflat, terrain_generate_world_bottom_border, SEA_LEVEL},
+    noise::{generate_heightmap_data, ColumnHeightmapCache, Heightmap},
+    structures::{PendingVoxelEdits, StructureWriter},
 };
 
-use super::{storage::VoxelBuffer, ChunkShape, Voxel, CHUNK_LENGTH_U};
+#[cfg(not(feature = "headless"))]
+use super::biomes::AmbientParticleSettings;
+use super::{
+    biomes::{climate_at, seed_climate_offset, BiomeInfo, BiomePalette, BiomeRegistry, Climate},
+    material::VoxelMaterial,
+    materials::{Dirt, Grass, Rock, Sand, Sandstone, Snow},
+    storage::VoxelBuffer,
+    ChunkShape, Voxel, CHUNK_LENGTH_U,
+};
 
-mod biomes;
+pub mod biomes;
 
 /// noise functions ported over from C / GLSL code
 pub mod noise;
@@ -23,72 +39,577 @@ pub mod noise;
 /// common functions used by all terrain generators
 pub mod common;
 
+/// placement of structures which may span multiple chunks
+pub mod structures;
+
+/// deterministic random placement of decorations
+pub mod scatter;
+
 // Terrain generator singleton.
 pub static TERRAIN_GENERATOR: Lazy<RwLock<TerrainGenerator>> = Lazy::new(|| Default::default());
 
+/// A generation pass applied to chunks once the base terrain was generated (e.g. ores, structures).
+/// Each stage is identified by a stable bit index so that saves can record which stages were applied to a chunk,
+/// allowing stages added in later versions to be applied to previously generated chunks.
+pub trait WorldGenStage: 'static + Sync + Send {
+    /// Stable identifier of this stage, must lie in the `[0; 32)` range.
+    fn id(&self) -> u32;
+
+    /// Human readable name of this stage.
+    fn name(&self) -> &'static str;
+
+    /// Applies this stage to the specified chunk.
+    fn apply(&self, chunk_key: IVec3, buffer: &mut VoxelBuffer<Voxel, ChunkShape>);
+}
+
+/// A modification applied to the chunks right after their generation, before they get meshed for the first time.
+/// Plugins register post processors on the [`TERRAIN_GENERATOR`] to inject their own changes (ruins, ore rebalancing, terrain smoothing...),
+/// they're run inside the async generation tasks.
+pub trait ChunkPostProcessor: 'static + Sync + Send {
+    /// Modifies the freshly generated chunk.
+    fn process(&self, chunk_key: IVec3, buffer: &mut VoxelBuffer<Voxel, ChunkShape>);
+}
+
+impl<F> ChunkPostProcessor for F
+where
+    F: Fn(IVec3, &mut VoxelBuffer<Voxel, ChunkShape>) + 'static + Sync + Send,
+{
+    fn process(&self, chunk_key: IVec3, buffer: &mut VoxelBuffer<Voxel, ChunkShape>) {
+        self(chunk_key, buffer)
+    }
+}
+
+/// Seed of the world, all the noises and the random placements of the terrain generation derive from it
+/// so that the same seed always generates the same world.
+/// Changing it only affects the chunks generated afterwards, see the `RegenerateWorld` debug event for regenerating the loaded ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorldSeed(pub i32);
+
+/// Settings of a world read from a RON config file, e.g. `(seed: Some(42))`.
+#[derive(Default, Deserialize)]
+pub struct WorldConfigFile {
+    #[serde(default)]
+    pub seed: Option<i32>,
+}
+
+impl WorldConfigFile {
+    /// Reads the world settings from the specified RON file.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(ron::de::from_bytes(&fs::read(path)?)?)
+    }
+}
+
+/// Tweakable parameters of the terrain generation.
+/// Changes made to the resource are picked up by the terrain generator for the chunks generated afterwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainGenConfig {
+    /// Seed of the noise functions shaping the terrain and the biomes, kept in sync with the [`WorldSeed`] resource.
+    pub seed: i32,
+    pub caves_enabled: bool,
+    /// Frequency of the cave noise, higher values mean smaller and more frequent caves.
+    pub cave_frequency: f32,
+    /// Noise value above which voxels get carved out, higher values mean sparser caves.
+    pub cave_threshold: f32,
+    /// Minimum depth below the surface at which caves can be carved.
+    pub cave_surface_margin: u32,
+    /// World height below which carved caves get flooded with lava.
+    pub cave_lava_level: u32,
+    pub beaches_enabled: bool,
+    /// Maximum distance from the water at which the shore turns into a beach, in voxels.
+    pub beach_width: u32,
+    /// Maximum height above sea level of the beaches, in voxels.
+    pub beach_height: u32,
+}
+
+impl Default for TerrainGenConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            caves_enabled: true,
+            cave_frequency: 0.025,
+            cave_threshold: 0.45,
+            cave_surface_margin: 6,
+            cave_lava_level: 10,
+            beaches_enabled: true,
+            beach_width: 5,
+            beach_height: 2,
+        }
+    }
+}
+
+/// The reason the generation of a chunk failed.
+#[derive(Clone, Debug)]
+pub enum TerrainGenError {
+    /// A generation step panicked, with its panic message.
+    Panicked(String),
+    /// The heightmap of the chunk contains non finite heights.
+    InvalidHeights,
+    /// The chunk contains a voxel of a material which isn't registered.
+    InvalidMaterial(u8),
+}
+
+impl fmt::Display for TerrainGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TerrainGenError::Panicked(message) => write!(f, "generation panicked: {}", message),
+            TerrainGenError::InvalidHeights => write!(f, "the heightmap has non finite heights"),
+            TerrainGenError::InvalidMaterial(id) => {
+                write!(f, "the chunk contains unregistered material id {}", id)
+            }
+        }
+    }
+}
+
+/// The result of the generation of a chunk by [`TerrainGenerator::generate`].
+pub struct GeneratedChunk {
+    /// The voxels of the structures spilling over the chunk borders, which must be applied to the neighboring chunks.
+    pub overflow: PendingVoxelEdits,
+    /// The bitmask of the generation stages applied to the chunk, to be stored in its save header.
+    pub applied_stages: u32,
+}
+
+/// The base shape of the terrain generated by a [`TerrainGenerator`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BaseTerrain {
+    /// Hills shaped by noise, layered and decorated by the biomes, carved by caves.
+    Noise,
+    /// Flat terrain made of layers of voxels along their thickness, stacked from the world bottom up.
+    Superflat(Vec<(Voxel, u32)>),
+}
+
+impl Default for BaseTerrain {
+    fn default() -> Self {
+        Self::Noise
+    }
+}
+
+impl BaseTerrain {
+    /// Returns a superflat terrain of grass over dirt over rock, surfacing at sea level.
+    pub fn default_superflat() -> Self {
+        Self::Superflat(vec![
+            (Rock::into_voxel(), SEA_LEVEL - 8),
+            (Dirt::into_voxel(), 7),
+            (Grass::into_voxel(), 1),
+        ])
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BaseTerrain::Noise => "noise",
+            BaseTerrain::Superflat(_) => "superflat",
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct TerrainGenerator {
-    biomes_map: BTreeMap<FloatOrd<f32>, Box<dyn BiomeTerrainGenerator>>,
+    base: BaseTerrain,
+    biomes: BiomeRegistry,
+    stages: Vec<Box<dyn WorldGenStage>>,
+    /// Post processors along their priority, sorted by ascending priority.
+    post_processors: Vec<(i32, Box<dyn ChunkPostProcessor>)>,
+    config: TerrainGenConfig,
+    /// Heightmaps of the chunk columns, shared by the chunks of a column and the structures placed on them.
+    heightmaps: ColumnHeightmapCache,
 }
 
 impl TerrainGenerator {
-    pub fn register_biome_generator(
+    /// Replaces the tweakable parameters used for generating the next chunks.
+    pub fn set_config(&mut self, config: TerrainGenConfig) {
+        if config.seed != self.config.seed {
+            self.heightmaps.clear();
+        }
+        self.biomes.set_seed(config.seed);
+        self.config = config;
+    }
+
+    /// Returns the height of the generated terrain surface at the specified X and Z world coordinates, without
+    /// generating the chunks there. Cheap once a chunk of the column got generated.
+    pub fn surface_height(&self, column: IVec2) -> f32 {
+        match &self.base {
+            BaseTerrain::Noise => self.heightmaps.surface_height(column, self.config.seed),
+            BaseTerrain::Superflat(layers) => {
+                layers.iter().map(|(_, thickness)| *thickness).sum::<u32>() as f32
+            }
+        }
+    }
+
+    /// Returns the base shape of the generated terrain.
+    pub fn base_terrain(&self) -> &BaseTerrain {
+        &self.base
+    }
+
+    pub fn set_base_terrain(&mut self, base: BaseTerrain) -> &mut Self {
+        self.base = base;
+        self
+    }
+
+    /// Returns the registry of the biomes used by this generator.
+    pub fn biomes(&self) -> &BiomeRegistry {
+        &self.biomes
+    }
+
+    pub fn biomes_mut(&mut self) -> &mut BiomeRegistry {
+        &mut self.biomes
+    }
+
+    /// Registers a generation stage run after the base terrain generation.
+    #[allow(dead_code)]
+    pub fn register_stage(&mut self, stage: Box<dyn WorldGenStage>) -> &mut Self {
+        assert!(stage.id() < 32, "world gen stage ids must be lower than 32");
+        assert!(
+            self.stage_mask() & (1 << stage.id()) == 0,
+            "a world gen stage with id {} is already registered",
+            stage.id()
+        );
+        self.stages.push(stage);
+        self
+    }
+
+    /// Registers a post processor run on the newly generated chunks.
+    /// Post processors run by ascending priority, those with equal priorities run in registration order.
+    #[allow(dead_code)]
+    pub fn register_post_processor(
         &mut self,
-        chance: f32,
-        biome: Box<dyn BiomeTerrainGenerator>,
+        priority: i32,
+        processor: impl ChunkPostProcessor,
     ) -> &mut Self {
-        self.biomes_map.insert(FloatOrd(chance), biome);
+        let index = self
+            .post_processors
+            .partition_point(|(other, _)| *other <= priority);
+        self.post_processors
+            .insert(index, (priority, Box::new(processor)));
         self
     }
 
-    //returns the biome with the closest temp / humidity
-    fn biome_at(&self, chunk_key: IVec3) -> &Box<dyn BiomeTerrainGenerator> {
-        const BIOME_INVSCALE: f32 = 0.001;
+    /// Returns the bitmask of all the registered generation stages.
+    pub fn stage_mask(&self) -> u32 {
+        self.stages
+            .iter()
+            .fold(0, |mask, stage| mask | (1 << stage.id()))
+    }
+
+    /// Applies the registered stages missing from the `applied_stages` mask to the chunk.
+    /// Returns the names of the stages which were applied, none for superflat terrains which never get them.
+    pub fn apply_missing_stages(
+        &self,
+        chunk_key: IVec3,
+        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+        applied_stages: u32,
+    ) -> Vec<&'static str> {
+        if let BaseTerrain::Superflat(_) = self.base {
+            return Vec::new();
+        }
+
+        self.stages
+            .iter()
+            .filter(|stage| applied_stages & (1 << stage.id()) == 0)
+            .map(|stage| {
+                stage.apply(chunk_key, buffer);
+                stage.name()
+            })
+            .collect()
+    }
+
+    /// Renders an approximate top-down preview of the terrain generated with the specified seed, as `resolution` x `resolution` RGBA pixels.
+    /// The preview covers the `area` x `area` columns centered on the world origin, each pixel being colored by the biome and height of a column.
+    pub fn preview(&self, seed: i32, area: usize, resolution: usize) -> Vec<u8> {
+        let sea_level = SEA_LEVEL as f32;
+        let half_area = area as i32 / 2;
+        let heights = generate_heightmap_data(IVec3::new(-half_area, 0, -half_area), area, seed);
+        let climate_offset = seed_climate_offset(seed);
+        let step = area / resolution;
+
+        let mut pixels = Vec::with_capacity(resolution * resolution * 4);
+        for y in 0..resolution {
+            for x in 0..resolution {
+                let (column_x, column_z) = (x * step, y * step);
+                let column = IVec2::new(column_x as i32, column_z as i32) - IVec2::splat(half_area);
+                let height = heights[column_z * area + column_x];
+
+                let color = if height < sea_level {
+                    let depth = ((sea_level - height) / 8.0).min(1.0);
+                    Color::rgb(0.15, 0.35, 0.8 - 0.4 * depth)
+                } else {
+                    let biome = self
+                        .biomes
+                        .biome_for_climate(climate_at(column + climate_offset));
+                    let shade = 0.7 + 0.3 * ((height - sea_level) / 8.0).min(1.0);
+                    let color = self
+                        .biomes
+                        .get_by_id(biome)
+                        .map_or(Color::GRAY, |biome| biome.debug_color);
+                    Color::rgb(color.r() * shade, color.g() * shade, color.b() * shade)
+                };
+
+                pixels.extend(color.as_rgba_u32().to_le_bytes());
+            }
+        }
+
+        pixels
+    }
+
+    /// Returns the seed the chunks are currently generated with.
+    pub fn seed(&self) -> i32 {
+        self.config.seed
+    }
 
-        let coords = noise::voronoi(chunk_key.xzy().truncate().as_vec2() * BIOME_INVSCALE);
-        let p = FloatOrd(noise::rand2to1i(coords));
+    /// Generates the terrain of the specified chunk.
+    /// Returns the structure voxels spilling over the chunk borders along the generation stages which were applied,
+    /// or the reason the generation failed, in which case the buffer content must be discarded.
+    /// `material_count` is the number of registered materials, voxels of higher material ids are rejected.
+    pub fn generate(
+        &self,
+        chunk_key: IVec3,
+        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+        material_count: usize,
+    ) -> Result<GeneratedChunk, TerrainGenError> {
+        // a panicking biome generator or post processor must not take the whole generation task pool down.
+        let generated = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.generate_unchecked(chunk_key, buffer)
+        }))
+        .map_err(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            TerrainGenError::Panicked(message)
+        })??;
 
-        self.biomes_map
-            .range(..=p)
-            .last()
-            .map_or(self.biomes_map.first_key_value().unwrap().1, |x| x.1)
+        match buffer
+            .slice()
+            .iter()
+            .find(|voxel| voxel.0 as usize >= material_count)
+        {
+            Some(voxel) => Err(TerrainGenError::InvalidMaterial(voxel.0)),
+            None => Ok(generated),
+        }
     }
 
-    pub fn generate(&self, chunk_key: IVec3, buffer: &mut VoxelBuffer<Voxel, ChunkShape>) {
-        let biome = self.biome_at(chunk_key);
-        let noise = generate_heightmap_data(chunk_key, CHUNK_LENGTH_U);
+    fn generate_unchecked(
+        &self,
+        chunk_key: IVec3,
+        buffer: &mut VoxelBuffer<Voxel, ChunkShape>,
+    ) -> Result<GeneratedChunk, TerrainGenError> {
+        // the generation stages aren't applied to superflat worlds, the chunks are saved without any.
+        if let BaseTerrain::Superflat(layers) = &self.base {
+            terrain_generate_superflat(buffer, chunk_key, layers);
+            self.post_processors
+                .iter()
+                .for_each(|(_, processor)| processor.process(chunk_key, buffer));
+            return Ok(GeneratedChunk {
+                overflow: PendingVoxelEdits::default(),
+                applied_stages: 0,
+            });
+        }
+
+        let biome_map = self.biomes.biome_map(chunk_key);
+        let biome = self.biomes.get_by_id(biome_map.dominant()).unwrap();
+        let noise = self
+            .heightmaps
+            .get(IVec2::new(chunk_key.x, chunk_key.z), self.config.seed);
+        if noise.iter().any(|height| !height.is_finite()) {
+            return Err(TerrainGenError::InvalidHeights);
+        }
 
         let noise_map = Heightmap::<CHUNK_LENGTH_U, CHUNK_LENGTH_U>::from_slice(&noise);
 
-        common::terrain_carve_heightmap(buffer, chunk_key, &noise_map);
+        common::terrain_carve_heightmap(buffer, chunk_key, &noise_map, &biome_map, &self.biomes);
+        common::terrain_apply_biome_layers(buffer, chunk_key, &noise_map, &biome_map, &self.biomes);
 
-        biome.carve_terrain(chunk_key, noise_map, buffer);
-        biome.decorate_terrain(chunk_key, noise_map, buffer);
+        biome.generator.carve_terrain(chunk_key, noise_map, buffer);
+        common::terrain_carve_caves(buffer, chunk_key, &noise_map, &self.config);
+
+        let mut overflow = PendingVoxelEdits::default();
+        biome.generator.decorate_terrain(
+            chunk_key,
+            noise_map,
+            &mut StructureWriter::new(chunk_key, buffer, &mut overflow).with_seed(self.config.seed),
+        );
+
+        common::terrain_generate_beaches(
+            buffer,
+            chunk_key,
+            &noise_map,
+            &self.heightmaps,
+            &self.config,
+        );
 
         if chunk_key.y == 0 {
             terrain_generate_world_bottom_border(buffer);
         }
+
+        self.apply_missing_stages(chunk_key, buffer, 0);
+
+        self.post_processors
+            .iter()
+            .for_each(|(_, processor)| processor.process(chunk_key, buffer));
+
+        Ok(GeneratedChunk {
+            overflow,
+            applied_stages: self.stage_mask(),
+        })
+    }
+}
+
+/// Replaces the [`TERRAIN_GENERATOR`] singleton, returning the previous generator.
+/// The loaded chunks are left untouched, only the chunks generated afterwards use the new generator. The generation
+/// tasks in flight are waited on, so that no chunk gets generated half by each generator.
+/// The new generator keeps the current [`TerrainGenConfig`], and the stages and post processors registered on the
+/// previous generator (usually by plugins, at startup) are moved over to it, except for the stages it already registers.
+/// Prefer [`TerrainGeneratorSwap`] from systems, which announces the swap with a [`TerrainGeneratorSwapped`] event.
+pub fn swap_terrain_generator(mut generator: TerrainGenerator) -> TerrainGenerator {
+    let mut current = TERRAIN_GENERATOR.write().unwrap();
+    generator.set_config(current.config);
+
+    let stage_mask = generator.stage_mask();
+    let (duplicate_stages, stages): (Vec<_>, Vec<_>) = std::mem::take(&mut current.stages)
+        .into_iter()
+        .partition(|stage| stage_mask & (1 << stage.id()) != 0);
+    current.stages = duplicate_stages;
+    generator.stages.extend(stages);
+
+    for (priority, processor) in std::mem::take(&mut current.post_processors) {
+        let index = generator
+            .post_processors
+            .partition_point(|(other, _)| *other <= priority);
+        generator
+            .post_processors
+            .insert(index, (priority, processor));
     }
+
+    std::mem::replace(&mut *current, generator)
+}
+
+/// A terrain generator waiting to replace the [`TERRAIN_GENERATOR`] singleton, see [`swap_terrain_generator`].
+#[derive(Default)]
+pub struct TerrainGeneratorSwap(Option<TerrainGenerator>);
+
+#[allow(dead_code)]
+impl TerrainGeneratorSwap {
+    /// Requests the generator to be swapped in during the next update, replacing any generator already requested.
+    pub fn request(&mut self, generator: TerrainGenerator) {
+        self.0 = Some(generator);
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+/// Event sent once the [`TERRAIN_GENERATOR`] singleton got replaced, for the systems depending on the generated terrain.
+pub struct TerrainGeneratorSwapped {
+    /// Base terrain of the new generator.
+    pub base_terrain: BaseTerrain,
 }
 
 pub struct TerrainGeneratorPlugin;
 
 impl Plugin for TerrainGeneratorPlugin {
-    fn build(&self, _: &mut bevy::prelude::App) {
-        TERRAIN_GENERATOR
-            .write()
-            .unwrap()
-            .register_biome_generator(
-                0.0f32,
-                biomes::BasicPlainsBiomeTerrainGenerator.into_boxed_generator(),
-            )
-            .register_biome_generator(
-                0.8f32,
-                biomes::BasicDesertBiomeTerrainGenerator.into_boxed_generator(),
-            )
-            .register_biome_generator(
-                3.21,
-                biomes::BasicSnowyPlainsBiomeTerrainGenerator.into_boxed_generator(),
-            );
+    fn build(&self, app: &mut bevy::prelude::App) {
+        register_default_biomes(&mut TERRAIN_GENERATOR.write().unwrap());
+        app.init_resource::<WorldSeed>()
+            .init_resource::<TerrainGenConfig>()
+            .init_resource::<TerrainGeneratorSwap>()
+            .add_event::<TerrainGeneratorSwapped>()
+            .add_system(sync_terrain_gen_config)
+            .add_system(apply_terrain_generator_swap);
     }
 }
+
+/// Swaps in the terrain generator requested through the [`TerrainGeneratorSwap`] resource.
+fn apply_terrain_generator_swap(
+    mut swap: ResMut<TerrainGeneratorSwap>,
+    mut swapped_events: EventWriter<TerrainGeneratorSwapped>,
+) {
+    if let Some(generator) = swap.0.take() {
+        let base_terrain = generator.base_terrain().clone();
+        swap_terrain_generator(generator);
+        swapped_events.send(TerrainGeneratorSwapped { base_terrain });
+    }
+}
+
+/// Forwards the [`WorldSeed`] and the changes made to the [`TerrainGenConfig`] resource to the terrain generator singleton.
+fn sync_terrain_gen_config(seed: Res<WorldSeed>, mut config: ResMut<TerrainGenConfig>) {
+    if seed.is_changed() && config.seed != seed.0 {
+        config.seed = seed.0;
+    }
+
+    if config.is_changed() {
+        TERRAIN_GENERATOR.write().unwrap().set_config(*config);
+    }
+}
+
+/// Registers the built-in biomes into the specified terrain generator.
+pub fn register_default_biomes(generator: &mut TerrainGenerator) {
+    generator
+        .biomes_mut()
+        .register_biome(BiomeInfo {
+            name: "Plains",
+            climate: Climate::new(0.5, 0.5),
+            palette: BiomePalette {
+                layers: vec![(Grass::into_voxel(), 2), (Dirt::into_voxel(), 7)],
+                underground: Rock::into_voxel(),
+            },
+            debug_color: Color::LIME_GREEN,
+            // fireflies
+            #[cfg(not(feature = "headless"))]
+            ambient_particles: Some(AmbientParticleSettings {
+                color: Color::rgb(0.9, 1.0, 0.4),
+                emissive: true,
+                size: 0.15,
+                spawn_rate: 6.0,
+                velocity: Vec3::ZERO,
+                wander: 1.5,
+                lifetime: 8.0,
+            }),
+            generator: biomes::BasicPlainsBiomeTerrainGenerator.into_boxed_generator(),
+        })
+        .register_biome(BiomeInfo {
+            name: "Desert",
+            climate: Climate::new(0.9, 0.1),
+            palette: BiomePalette {
+                layers: vec![(Sand::into_voxel(), 6), (Sandstone::into_voxel(), 3)],
+                underground: Sandstone::into_voxel(),
+            },
+            debug_color: Color::rgb_u8(228, 219, 148),
+            // dust carried by the wind
+            #[cfg(not(feature = "headless"))]
+            ambient_particles: Some(AmbientParticleSettings {
+                color: Color::rgb_u8(214, 196, 140),
+                emissive: false,
+                size: 0.1,
+                spawn_rate: 20.0,
+                velocity: Vec3::new(4.0, -0.2, 1.0),
+                wander: 0.5,
+                lifetime: 5.0,
+            }),
+            generator: biomes::BasicDesertBiomeTerrainGenerator.into_boxed_generator(),
+        })
+        .register_biome(BiomeInfo {
+            name: "Snowy plains",
+            climate: Climate::new(0.1, 0.6),
+            palette: BiomePalette {
+                layers: vec![
+                    (Snow::into_voxel(), 1),
+                    (Grass::into_voxel(), 2),
+                    (Dirt::into_voxel(), 6),
+                ],
+                underground: Rock::into_voxel(),
+            },
+            debug_color: Color::WHITE,
+            // snowflakes
+            #[cfg(not(feature = "headless"))]
+            ambient_particles: Some(AmbientParticleSettings {
+                color: Color::WHITE,
+                emissive: false,
+                size: 0.12,
+                spawn_rate: 40.0,
+                velocity: Vec3::new(0.3, -2.0, 0.2),
+                wander: 0.4,
+                lifetime: 10.0,
+            }),
+            generator: biomes::BasicSnowyPlainsBiomeTerrainGenerator.into_boxed_generator(),
+        });
+}
@@ -1,4 +1,15 @@
-use bevy::math::{IVec3, Vec2, Vec2Swizzles, Vec3, Vec3Swizzles};
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    math::{IVec2, IVec3, UVec3, Vec2, Vec2Swizzles, Vec3, Vec3Swizzles},
+    utils::HashMap,
+};
+
+use crate::voxel::{CHUNK_LENGTH, CHUNK_LENGTH_U};
+
+/// Maximum number of chunk columns whose heightmap is kept by a [`ColumnHeightmapCache`], the whole cache being dropped
+/// once full.
+const COLUMN_HEIGHTMAP_CACHE_CAPACITY: usize = 4096;
 
 pub fn rand2to1(p: Vec2, dot: Vec2) -> f32 {
     let sp: Vec2 = p.to_array().map(|x| x.sin()).into();
@@ -71,8 +82,9 @@ pub fn voronoi(p: Vec2) -> Vec2 {
     return closest_point;
 }
 
-pub fn generate_heightmap_data(key: IVec3, chunk_len: usize) -> Vec<f32> {
+pub fn generate_heightmap_data(key: IVec3, chunk_len: usize, seed: i32) -> Vec<f32> {
     simdnoise::NoiseBuilder::fbm_2d_offset(key.x as f32, chunk_len, key.z as f32, chunk_len)
+        .with_seed(seed)
         .with_octaves(4)
         .generate()
         .0
@@ -81,6 +93,74 @@ pub fn generate_heightmap_data(key: IVec3, chunk_len: usize) -> Vec<f32> {
         .collect()
 }
 
+/// Cache of the heightmaps generated for the chunk columns, so that the chunks stacked in a column (and the structures
+/// placed on them) share the noise of their column instead of each generating it again.
+#[derive(Default)]
+pub struct ColumnHeightmapCache {
+    /// The heightmaps of the columns, keyed by the X and Z coordinates of their minimum.
+    heightmaps: Mutex<HashMap<IVec2, Arc<[f32]>>>,
+}
+
+#[allow(dead_code)]
+impl ColumnHeightmapCache {
+    /// Returns the heightmap of the chunk column at the specified minimum, generating it if not cached yet.
+    pub fn get(&self, column: IVec2, seed: i32) -> Arc<[f32]> {
+        if let Some(heights) = self.heightmaps.lock().unwrap().get(&column) {
+            return heights.clone();
+        }
+
+        // generated without holding the lock, the generation tasks of the other columns don't wait for it.
+        let heights: Arc<[f32]> =
+            generate_heightmap_data(IVec3::new(column.x, 0, column.y), CHUNK_LENGTH_U, seed).into();
+        let mut heightmaps = self.heightmaps.lock().unwrap();
+        if heightmaps.len() >= COLUMN_HEIGHTMAP_CACHE_CAPACITY {
+            heightmaps.clear();
+        }
+        heightmaps.insert(column, heights.clone());
+        heights
+    }
+
+    /// Returns the height of the generated surface at the specified X and Z world coordinates.
+    pub fn surface_height(&self, pos: IVec2, seed: i32) -> f32 {
+        let column = pos & !IVec2::splat(CHUNK_LENGTH as i32 - 1);
+        let local = pos - column;
+        self.get(column, seed)[(local.y * CHUNK_LENGTH as i32 + local.x) as usize]
+    }
+
+    /// Drops the cached heightmaps, e.g. once generated with an outdated seed.
+    pub fn clear(&self) {
+        self.heightmaps.lock().unwrap().clear();
+    }
+}
+
+/// Generates smooth 2D noise in the `[0; 1]` range, modulating the width of the beaches.
+pub fn generate_beach_noise(key: IVec3, chunk_len: usize, seed: i32) -> Vec<f32> {
+    simdnoise::NoiseBuilder::gradient_2d_offset(key.x as f32, chunk_len, key.z as f32, chunk_len)
+        .with_seed(seed.wrapping_add(1))
+        .with_freq(0.05)
+        .generate_scaled(0.0, 1.0)
+}
+
+/// Generates the 3D noise used for carving caves in a chunk.
+/// Values are laid out in the same order as the voxels of a chunk buffer.
+pub fn generate_cave_noise(key: IVec3, chunk_size: UVec3, frequency: f32, seed: i32) -> Vec<f32> {
+    simdnoise::NoiseBuilder::fbm_3d_offset(
+        key.x as f32,
+        chunk_size.x as usize,
+        key.y as f32,
+        chunk_size.y as usize,
+        key.z as f32,
+        chunk_size.z as usize,
+    )
+    .with_seed(seed)
+    .with_freq(frequency)
+    .with_lacunarity(2.0)
+    .with_gain(0.5)
+    .with_octaves(3)
+    .generate()
+    .0
+}
+
 /// A view into a slice of noise values with W x H dimensions.
 /// Provides methods for fetching a value at specified coordinates and to map values to a range.
 #[derive(Clone, Copy)]
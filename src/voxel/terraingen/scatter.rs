@@ -0,0 +1,183 @@
+use std::ops::Div;
+
+use bevy::math::{IVec2, IVec3, UVec2, Vec2, Vec3Swizzles};
+
+use super::{noise::Heightmap, structures::StructureWriter};
+use crate::voxel::{Voxel, CHUNK_HEIGHT, CHUNK_LENGTH, CHUNK_LENGTH_U};
+
+/// Where the decorations of a [`ScatterDecorator`] get anchored in the voxel columns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScatterPlacement {
+    /// The surface voxel given by the heightmap, only in the chunks the terrain surface lies in.
+    Surface,
+    /// A non-empty voxel below an empty one, cave floors included.
+    Floor,
+    /// A non-empty voxel above an empty one, e.g. cave ceilings.
+    Ceiling,
+}
+
+/// Deterministic random placement of decorations (flowers, rocks, trees...) for the terrain generators.
+///
+/// The columns are split in cells of `spacing` x `spacing` columns aligned on the world grid, each cell holding at
+/// most one candidate jittered around its center, which keeps the decorations spread out and seamless across the
+/// chunk borders. The candidates only depend on the world seed, the salt and the cell, so the same world always gets
+/// the same decorations.
+#[derive(Clone, Copy)]
+pub struct ScatterDecorator {
+    /// Distinguishes the decorators of a generator, so that they don't pick the same columns.
+    pub salt: u32,
+    /// Average number of candidates per chunk, before the placement and anchor filters. Capped to one per cell.
+    pub density: f32,
+    /// Size of the cells, roughly the minimum distance between two decorations.
+    pub spacing: u32,
+    /// How far the candidates may stray from the center of their cell, from 0 (on the center) to 1 (anywhere in the
+    /// cell).
+    pub jitter: f32,
+    pub placement: ScatterPlacement,
+    /// Returns whether a decoration may be anchored to a voxel, any non-empty voxel is accepted if `None`.
+    pub anchor: Option<fn(Voxel) -> bool>,
+}
+
+#[allow(dead_code)]
+impl ScatterDecorator {
+    /// Returns a decorator placing `density` decorations per chunk on the terrain surface, fully jittered.
+    pub fn new(salt: u32, density: f32, spacing: u32) -> Self {
+        Self {
+            salt,
+            density,
+            spacing: spacing.max(1),
+            jitter: 1.0,
+            placement: ScatterPlacement::Surface,
+            anchor: None,
+        }
+    }
+
+    pub fn with_jitter(mut self, jitter: f32) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_placement(mut self, placement: ScatterPlacement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    /// Only anchors the decorations to the voxels for which `anchor` returns true.
+    pub fn with_anchor(mut self, anchor: fn(Voxel) -> bool) -> Self {
+        self.anchor = Some(anchor);
+        self
+    }
+
+    /// Calls `place` for each decoration of the chunk being written, with the position of its anchor voxel (relative
+    /// to the chunk minimum) and a random value in `[0; 1)` for varying the decoration.
+    pub fn scatter(
+        &self,
+        heightmap: &Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
+        writer: &mut StructureWriter,
+        mut place: impl FnMut(&mut StructureWriter, IVec3, f32),
+    ) {
+        let chunk_key = writer.chunk_key();
+        let seed = writer.seed();
+        let spacing = self.spacing as i32;
+        let chance =
+            self.density * (spacing * spacing) as f32 / (CHUNK_LENGTH * CHUNK_LENGTH) as f32;
+
+        let chunk_min = chunk_key.xz();
+        let chunk_max = chunk_min + IVec2::splat(CHUNK_LENGTH as i32 - 1);
+        let min_cell = IVec2::new(
+            chunk_min.x.div_euclid(spacing),
+            chunk_min.y.div_euclid(spacing),
+        );
+        let max_cell = IVec2::new(
+            chunk_max.x.div_euclid(spacing),
+            chunk_max.y.div_euclid(spacing),
+        );
+
+        for cell_z in min_cell.y..=max_cell.y {
+            for cell_x in min_cell.x..=max_cell.x {
+                let cell = IVec2::new(cell_x, cell_z);
+                let random = |index: u32| cell_random(cell, seed, self.salt, index);
+                if random(0) >= chance {
+                    continue;
+                }
+
+                let offset = (Vec2::splat(0.5)
+                    + (Vec2::new(random(1), random(2)) - 0.5) * self.jitter)
+                    * spacing as f32;
+                let column = cell * spacing
+                    + offset
+                        .as_ivec2()
+                        .clamp(IVec2::ZERO, IVec2::splat(spacing - 1))
+                    - chunk_min;
+                if column.cmplt(IVec2::ZERO).any()
+                    || column.cmpge(IVec2::splat(CHUNK_LENGTH as i32)).any()
+                {
+                    continue;
+                }
+
+                if let Some(anchor) =
+                    self.find_anchor(column.as_uvec2(), heightmap, writer, random(3))
+                {
+                    place(writer, anchor, random(4));
+                }
+            }
+        }
+    }
+
+    /// Returns the anchor of a decoration in a column of the chunk, picking one at random among the cave floors or
+    /// ceilings.
+    fn find_anchor(
+        &self,
+        column: UVec2,
+        heightmap: &Heightmap<CHUNK_LENGTH_U, CHUNK_LENGTH_U>,
+        writer: &StructureWriter,
+        random: f32,
+    ) -> Option<IVec3> {
+        let at = |y: i32| writer.voxel_at(IVec3::new(column.x as i32, y, column.y as i32));
+        let is_anchor = |voxel: Voxel| {
+            voxel != Voxel::EMPTY_VOXEL && self.anchor.map_or(true, |anchor| anchor(voxel))
+        };
+
+        let candidates: Vec<i32> = match self.placement {
+            ScatterPlacement::Surface => {
+                let height = heightmap.get(column.into());
+                if height.div(CHUNK_HEIGHT) != (writer.chunk_key().y as u32).div(CHUNK_HEIGHT) {
+                    return None;
+                }
+                vec![height.rem_euclid(CHUNK_HEIGHT) as i32]
+            }
+            ScatterPlacement::Floor => (0..CHUNK_HEIGHT as i32 - 1)
+                .filter(|y| at(y + 1) == Some(Voxel::EMPTY_VOXEL))
+                .collect(),
+            ScatterPlacement::Ceiling => (1..CHUNK_HEIGHT as i32)
+                .filter(|y| at(y - 1) == Some(Voxel::EMPTY_VOXEL))
+                .collect(),
+        };
+
+        let candidates: Vec<i32> = candidates
+            .into_iter()
+            .filter(|y| at(*y).map_or(false, is_anchor))
+            .collect();
+        let y = *candidates.get((random * candidates.len() as f32) as usize)?;
+
+        Some(IVec3::new(column.x as i32, y, column.y as i32))
+    }
+}
+
+/// Returns a random value in `[0; 1)` derived from a cell, the world seed, the salt of a decorator and an index.
+fn cell_random(cell: IVec2, seed: i32, salt: u32, index: u32) -> f32 {
+    let mut hash = (cell.x as u32).wrapping_mul(0x8DA6_B343)
+        ^ (cell.y as u32).wrapping_mul(0xD816_3841)
+        ^ (seed as u32).wrapping_mul(0xCB1A_B31F)
+        ^ salt.wrapping_mul(0x1656_67B1)
+        ^ index.wrapping_mul(0x9E37_79B9);
+
+    // murmur3 finalizer, mixing the bits of all the inputs.
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85EB_CA6B);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xC2B2_AE35);
+    hash ^= hash >> 16;
+
+    (hash >> 8) as f32 / (1 << 24) as f32
+}
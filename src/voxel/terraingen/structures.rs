@@ -0,0 +1,148 @@
+use bevy::{
+    math::{IVec3, UVec3},
+    utils::HashMap,
+};
+
+use crate::voxel::{chunk_key_at, storage::VoxelBuffer, ChunkShape, Voxel, CHUNK_SIZE};
+
+/// Voxel edits emitted by structures spilling over the border of the chunk they were generated in,
+/// keyed by the chunk they belong to so they can be applied once that chunk gets loaded.
+#[derive(Default)]
+pub struct PendingVoxelEdits(HashMap<IVec3, Vec<(UVec3, Voxel)>>);
+
+#[allow(dead_code)]
+impl PendingVoxelEdits {
+    /// Queues the placement of a voxel at the specified world position.
+    pub fn push(&mut self, world_pos: IVec3, voxel: Voxel) {
+        let chunk_key = chunk_key_at(world_pos);
+        self.0
+            .entry(chunk_key)
+            .or_default()
+            .push(((world_pos - chunk_key).as_uvec3(), voxel));
+    }
+
+    /// Moves the edits of `other` into this set of pending edits.
+    pub fn merge(&mut self, other: PendingVoxelEdits) {
+        for (chunk_key, edits) in other.0 {
+            self.0.entry(chunk_key).or_default().extend(edits);
+        }
+    }
+
+    /// Removes and returns the pending edits of the specified chunk.
+    pub fn take(&mut self, chunk_key: IVec3) -> Option<Vec<(UVec3, Voxel)>> {
+        self.0.remove(&chunk_key)
+    }
+
+    /// Returns an iterator over the keys of the chunks with pending edits.
+    pub fn iter_keys(&self) -> impl Iterator<Item = &IVec3> {
+        self.0.keys()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the number of chunks with pending edits.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Drops the edits of the chunks farthest from `center` (a chunk key) until at most `max_chunks` chunks have
+    /// pending edits.
+    pub fn truncate(&mut self, center: IVec3, max_chunks: usize) {
+        if self.0.len() <= max_chunks {
+            return;
+        }
+
+        let mut keys: Vec<IVec3> = self.0.keys().copied().collect();
+        keys.sort_unstable_by_key(|key| (*key - center).abs().max_element());
+        for key in keys.into_iter().skip(max_chunks) {
+            self.0.remove(&key);
+        }
+    }
+
+    /// Applies pending edits to a chunk buffer.
+    /// Only empty voxels are replaced so that spilled structures don't carve into existing terrain.
+    pub fn apply(edits: &[(UVec3, Voxel)], buffer: &mut VoxelBuffer<Voxel, ChunkShape>) {
+        for (pos, voxel) in edits {
+            if buffer.voxel_at(*pos) == Voxel::EMPTY_VOXEL {
+                buffer.set_voxel(*pos, *voxel);
+            }
+        }
+    }
+}
+
+/// Writes the voxels of structures into the chunk being generated.
+/// Voxels falling outside of the chunk bounds are deferred to [`PendingVoxelEdits`].
+pub struct StructureWriter<'a> {
+    chunk_key: IVec3,
+    buffer: &'a mut VoxelBuffer<Voxel, ChunkShape>,
+    overflow: &'a mut PendingVoxelEdits,
+    seed: i32,
+}
+
+impl<'a> StructureWriter<'a> {
+    pub fn new(
+        chunk_key: IVec3,
+        buffer: &'a mut VoxelBuffer<Voxel, ChunkShape>,
+        overflow: &'a mut PendingVoxelEdits,
+    ) -> Self {
+        Self {
+            chunk_key,
+            buffer,
+            overflow,
+            seed: 0,
+        }
+    }
+
+    /// Sets the world seed the structures get placed with.
+    pub fn with_seed(mut self, seed: i32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Returns the world seed the structures get placed with.
+    pub fn seed(&self) -> i32 {
+        self.seed
+    }
+
+    /// Returns the key of the chunk being generated.
+    pub fn chunk_key(&self) -> IVec3 {
+        self.chunk_key
+    }
+
+    /// Returns the voxel at the specified position relative to the chunk minimum, `None` outside of the chunk.
+    pub fn voxel_at(&self, local_pos: IVec3) -> Option<Voxel> {
+        (local_pos.cmpge(IVec3::ZERO).all() && local_pos.cmplt(CHUNK_SIZE).all())
+            .then(|| self.buffer.voxel_at(local_pos.as_uvec3()))
+    }
+
+    /// Sets the voxel at the specified position relative to the chunk minimum, which may lie outside of the chunk.
+    pub fn set_voxel(&mut self, local_pos: IVec3, voxel: Voxel) {
+        if local_pos.cmpge(IVec3::ZERO).all() && local_pos.cmplt(CHUNK_SIZE).all() {
+            self.buffer.set_voxel(local_pos.as_uvec3(), voxel);
+        } else {
+            self.overflow.push(self.chunk_key + local_pos, voxel);
+        }
+    }
+
+    /// Sets all the voxels of the extent defined by `min` and `shape` (relative to the chunk minimum) for which `filter` returns true.
+    pub fn fill_where(
+        &mut self,
+        min: IVec3,
+        shape: UVec3,
+        voxel: Voxel,
+        filter: impl Fn(IVec3) -> bool,
+    ) {
+        for x in min.x..min.x + shape.x as i32 {
+            for y in min.y..min.y + shape.y as i32 {
+                for z in min.z..min.z + shape.z as i32 {
+                    let pos = IVec3::new(x, y, z);
+                    if filter(pos) {
+                        self.set_voxel(pos, voxel);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use bevy::{
+    math::{Vec3, Vec3Swizzles},
+    prelude::{
+        shape, Assets, Color, Commands, Component, Entity, FromWorld, GlobalTransform, Handle,
+        Local, Mesh, PbrBundle, Plugin, Query, Res, ResMut, StandardMaterial, Transform, With,
+        Without, World,
+    },
+    time::Time,
+    utils::HashMap,
+};
+
+use super::{origin::WorldOrigin, player::PlayerController, ChunkShape};
+use crate::voxel::{
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    storage::ChunkMap,
+    terraingen::TERRAIN_GENERATOR,
+    Voxel,
+};
+
+/// Maximum number of ambient particles alive at once.
+const MAX_AMBIENT_PARTICLES: usize = 512;
+/// Horizontal radius around the camera in which particles are spawned.
+const SPAWN_RADIUS: f32 = 24.0;
+/// Particles further than this distance from the camera get despawned.
+const DESPAWN_RADIUS: f32 = 40.0;
+
+/// An ambient particle drifting around the camera.
+#[derive(Component)]
+pub struct AmbientParticle {
+    velocity: Vec3,
+    wander: f32,
+    phase: f32,
+    expires_at: f32,
+    /// Whether the particle landed on the terrain.
+    resting: bool,
+}
+
+/// Shared mesh and per-biome materials of the ambient particles.
+struct AmbientParticleAssets {
+    mesh: Handle<Mesh>,
+    materials: HashMap<u8, Handle<StandardMaterial>>,
+}
+
+impl FromWorld for AmbientParticleAssets {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            mesh: world
+                .resource_mut::<Assets<Mesh>>()
+                .add(Mesh::from(shape::Cube { size: 1.0 })),
+            materials: Default::default(),
+        }
+    }
+}
+
+/// Spawn timing state and a tiny xorshift RNG, there's no need for a proper random crate for particle jitter.
+struct AmbientParticleSpawner {
+    accumulator: f32,
+    rng_state: u32,
+}
+
+impl Default for AmbientParticleSpawner {
+    fn default() -> Self {
+        Self {
+            accumulator: 0.0,
+            rng_state: 0x9E37_79B9,
+        }
+    }
+}
+
+impl AmbientParticleSpawner {
+    /// Returns a random number in the `[-1; 1]` range.
+    fn next_signed(&mut self) -> f32 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 17;
+        self.rng_state ^= self.rng_state << 5;
+        (self.rng_state as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+/// What a particle runs into at a given position.
+#[derive(PartialEq, Eq)]
+enum ParticleCollision {
+    Free,
+    Solid,
+    Liquid,
+}
+
+/// Checks the voxel at the specified translation against particles.
+fn particle_collision(
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    materials: &VoxelMaterialRegistry,
+    origin: &WorldOrigin,
+    pos: Vec3,
+) -> ParticleCollision {
+    match chunks.voxel_at(origin.voxel_at(pos)) {
+        Some(Voxel::EMPTY_VOXEL) | None => ParticleCollision::Free,
+        Some(voxel) => match materials.get_by_id(voxel.0) {
+            Some(material) if material.flags.contains(VoxelMaterialFlags::LIQUID) => {
+                ParticleCollision::Liquid
+            }
+            _ => ParticleCollision::Solid,
+        },
+    }
+}
+
+/// Spawns the ambient particles of the biome the camera is in.
+#[allow(clippy::too_many_arguments)]
+fn spawn_ambient_particles(
+    player: Query<&GlobalTransform, With<PlayerController>>,
+    particles: Query<(), With<AmbientParticle>>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    voxel_materials: Res<VoxelMaterialRegistry>,
+    origin: Res<WorldOrigin>,
+    time: Res<Time>,
+    mut assets: ResMut<AmbientParticleAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut spawner: Local<AmbientParticleSpawner>,
+    mut commands: Commands,
+) {
+    let camera_pos = match player.get_single() {
+        Ok(transform) => transform.translation(),
+        Err(_) => return,
+    };
+
+    let generator = TERRAIN_GENERATOR.read().unwrap();
+    let biome_id = generator
+        .biomes()
+        .biome_at(origin.voxel_at(camera_pos).xz());
+    let settings = match generator
+        .biomes()
+        .get_by_id(biome_id)
+        .and_then(|biome| biome.ambient_particles.as_ref())
+    {
+        Some(settings) => settings,
+        None => {
+            spawner.accumulator = 0.0;
+            return;
+        }
+    };
+
+    spawner.accumulator += settings.spawn_rate * time.delta_seconds();
+    let available = MAX_AMBIENT_PARTICLES.saturating_sub(particles.iter().count());
+    let count = (spawner.accumulator as usize).min(available);
+    spawner.accumulator = spawner.accumulator.fract();
+
+    if count == 0 {
+        return;
+    }
+
+    let AmbientParticleAssets {
+        mesh,
+        materials: biome_materials,
+    } = &mut *assets;
+
+    let material = biome_materials
+        .entry(biome_id)
+        .or_insert_with(|| {
+            materials.add(StandardMaterial {
+                base_color: settings.color,
+                emissive: if settings.emissive {
+                    settings.color
+                } else {
+                    Color::BLACK
+                },
+                unlit: settings.emissive,
+                ..Default::default()
+            })
+        })
+        .clone();
+
+    let now = time.time_since_startup().as_secs_f32();
+
+    for _ in 0..count {
+        let offset = Vec3::new(
+            spawner.next_signed() * SPAWN_RADIUS,
+            spawner.next_signed() * SPAWN_RADIUS * 0.5,
+            spawner.next_signed() * SPAWN_RADIUS,
+        );
+        let position = camera_pos + offset;
+
+        if particle_collision(&chunks, &voxel_materials, &origin, position)
+            != ParticleCollision::Free
+        {
+            continue;
+        }
+
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_translation(position)
+                    .with_scale(Vec3::splat(settings.size)),
+                ..Default::default()
+            })
+            .insert(AmbientParticle {
+                velocity: settings.velocity,
+                wander: settings.wander,
+                phase: spawner.next_signed() * std::f32::consts::PI,
+                expires_at: now + settings.lifetime * (0.75 + 0.25 * spawner.next_signed()),
+                resting: false,
+            });
+    }
+}
+
+/// Moves the ambient particles and despawns the expired ones.
+/// Particles come to rest when hitting the terrain and die when falling into liquids.
+fn step_ambient_particles(
+    player: Query<&GlobalTransform, With<PlayerController>>,
+    mut particles: Query<(Entity, &mut Transform, &mut AmbientParticle), Without<PlayerController>>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    voxel_materials: Res<VoxelMaterialRegistry>,
+    origin: Res<WorldOrigin>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let camera_pos = match player.get_single() {
+        Ok(transform) => transform.translation(),
+        Err(_) => return,
+    };
+
+    let now = time.time_since_startup().as_secs_f32();
+    let delta = time.delta_seconds();
+
+    particles.for_each_mut(|(entity, mut transform, mut particle)| {
+        if now >= particle.expires_at
+            || transform.translation.distance_squared(camera_pos) > DESPAWN_RADIUS.powi(2)
+        {
+            commands.entity(entity).despawn();
+            return;
+        }
+
+        if particle.resting {
+            return;
+        }
+
+        let t = now + particle.phase;
+        let wander = Vec3::new((t * 1.3).sin(), (t * 0.7).sin() * 0.5, (t * 1.1).cos());
+        let next = transform.translation + (particle.velocity + wander * particle.wander) * delta;
+
+        match particle_collision(&chunks, &voxel_materials, &origin, next) {
+            ParticleCollision::Free => transform.translation = next,
+            ParticleCollision::Solid => particle.resting = true,
+            ParticleCollision::Liquid => commands.entity(entity).despawn(),
+        }
+    });
+}
+
+/// Emits biome specific ambient particles (fireflies, dust, snowflakes) around the camera.
+pub struct AmbientParticlesPlugin;
+
+impl Plugin for AmbientParticlesPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<AmbientParticleAssets>()
+            .add_system(spawn_ambient_particles)
+            .add_system(step_ambient_particles);
+    }
+}
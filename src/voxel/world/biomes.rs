@@ -0,0 +1,211 @@
+#[cfg(not(feature = "headless"))]
+use bevy::math::Vec3;
+use bevy::{
+    math::{IVec2, IVec3, Vec2, Vec3Swizzles},
+    prelude::{info, Color},
+};
+use float_ord::FloatOrd;
+use ilattice::{glam::UVec2, prelude::Extent};
+
+use crate::voxel::{
+    terraingen::{biomes::BiomeTerrainGenerator, noise},
+    Voxel, CHUNK_LENGTH, CHUNK_LENGTH_U,
+};
+
+/// Scale applied to world coordinates before sampling the climate noise.
+/// A lower value means wider biomes.
+const CLIMATE_INVSCALE: f32 = 0.001;
+
+/// Climate parameters for a world column, both values lie in the `[0; 1]` range.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Climate {
+    pub temperature: f32,
+    pub humidity: f32,
+}
+
+impl Climate {
+    pub const fn new(temperature: f32, humidity: f32) -> Self {
+        Self {
+            temperature,
+            humidity,
+        }
+    }
+
+    #[inline]
+    fn distance_squared(&self, other: &Climate) -> f32 {
+        Vec2::new(self.temperature, self.humidity)
+            .distance_squared(Vec2::new(other.temperature, other.humidity))
+    }
+}
+
+/// Samples the climate at the specified world column.
+/// The climate is constant across a voronoi cell so that biomes form contiguous regions.
+pub fn climate_at(column: IVec2) -> Climate {
+    let cell = noise::voronoi(column.as_vec2() * CLIMATE_INVSCALE);
+
+    Climate {
+        temperature: noise::rand2to1(cell, Vec2::new(41.231, 97.174)).abs(),
+        humidity: noise::rand2to1(cell, Vec2::new(63.719, 21.443)).abs(),
+    }
+}
+
+/// Returns the offset applied to the world columns before sampling their climate, shifting the biomes around for each seed.
+pub fn seed_climate_offset(seed: i32) -> IVec2 {
+    // large but not too large, so that the scaled columns keep their float precision.
+    let hash = (seed as u32).wrapping_mul(0x9E37_79B9);
+    IVec2::new((hash & 0xFFFF) as i32, (hash >> 16) as i32) * 16
+}
+
+/// The set of materials used for building the terrain of a biome.
+pub struct BiomePalette {
+    /// Material layers applied on top of the terrain surface along with their thickness, ordered from the topmost layer.
+    pub layers: Vec<(Voxel, u32)>,
+    /// The material filling the terrain underneath the surface layers.
+    pub underground: Voxel,
+}
+
+impl BiomePalette {
+    /// Returns the surface layer material at the specified depth (0 being the topmost voxel), if any.
+    pub fn layer_at(&self, depth: u32) -> Option<Voxel> {
+        let mut remaining = depth;
+        for (material, thickness) in self.layers.iter() {
+            if remaining < *thickness {
+                return Some(*material);
+            }
+            remaining -= thickness;
+        }
+        None
+    }
+
+    /// Total thickness of the surface layers.
+    pub fn depth(&self) -> u32 {
+        self.layers.iter().map(|(_, thickness)| thickness).sum()
+    }
+}
+
+/// Settings of the ambient particles emitted around the camera while it is in a biome.
+#[cfg(not(feature = "headless"))]
+pub struct AmbientParticleSettings {
+    pub color: Color,
+    /// Whether the particles glow in the dark (e.g. fireflies).
+    pub emissive: bool,
+    /// Edge length of a particle, in voxels.
+    pub size: f32,
+    /// Number of particles spawned per second.
+    pub spawn_rate: f32,
+    /// Base velocity of the particles, in voxels per second.
+    pub velocity: Vec3,
+    /// Amplitude of the random wandering motion added on top of the base velocity.
+    pub wander: f32,
+    /// Lifetime of a particle, in seconds.
+    pub lifetime: f32,
+}
+
+/// Registry info about a biome.
+pub struct BiomeInfo {
+    pub name: &'static str,
+    /// The climate this biome thrives in, used for picking the biome of a column.
+    pub climate: Climate,
+    pub palette: BiomePalette,
+    /// Color used for displaying this biome in debug overlays.
+    pub debug_color: Color,
+    /// Ambient particles emitted around the camera in this biome, if any.
+    #[cfg(not(feature = "headless"))]
+    pub ambient_particles: Option<AmbientParticleSettings>,
+    pub generator: Box<dyn BiomeTerrainGenerator>,
+}
+
+/// A registry for biomes.
+/// Biomes are referred to by their index in the registry.
+#[derive(Default)]
+pub struct BiomeRegistry {
+    biomes: Vec<BiomeInfo>,
+    /// Offset of the climate sampling, derived from the world seed.
+    climate_offset: IVec2,
+}
+
+#[allow(dead_code)]
+impl BiomeRegistry {
+    pub fn register_biome(&mut self, biome: BiomeInfo) -> &mut Self {
+        info!(
+            "Registered biome {:?} (ID: {})",
+            biome.name,
+            self.biomes.len()
+        );
+        self.biomes.push(biome);
+        self
+    }
+
+    #[inline]
+    pub fn get_by_id(&self, id: u8) -> Option<&BiomeInfo> {
+        self.biomes.get(id as usize)
+    }
+
+    pub fn iter_biomes(&self) -> impl Iterator<Item = &BiomeInfo> {
+        self.biomes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.biomes.len()
+    }
+
+    /// Returns the id of the biome with the closest temperature / humidity.
+    pub fn biome_for_climate(&self, climate: Climate) -> u8 {
+        self.biomes
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, biome)| FloatOrd(biome.climate.distance_squared(&climate)))
+            .map_or(0, |(id, _)| id as u8)
+    }
+
+    /// Places the biomes according to the specified world seed.
+    pub fn set_seed(&mut self, seed: i32) {
+        self.climate_offset = seed_climate_offset(seed);
+    }
+
+    /// Returns the climate at the specified world column.
+    pub fn climate(&self, column: IVec2) -> Climate {
+        climate_at(column + self.climate_offset)
+    }
+
+    /// Returns the id of the biome at the specified world column.
+    pub fn biome_at(&self, column: IVec2) -> u8 {
+        self.biome_for_climate(self.climate(column))
+    }
+
+    /// Computes the biome of every column of the chunk at the specified key.
+    pub fn biome_map(&self, chunk_key: IVec3) -> BiomeMap {
+        let mut map = BiomeMap {
+            ids: [0; CHUNK_LENGTH_U * CHUNK_LENGTH_U],
+        };
+
+        Extent::from_min_and_shape(UVec2::ZERO, UVec2::splat(CHUNK_LENGTH))
+            .iter2()
+            .for_each(|pos| {
+                map.ids[pos.y as usize * CHUNK_LENGTH_U + pos.x as usize] =
+                    self.biome_at(chunk_key.xz() + pos.as_ivec2());
+            });
+
+        map
+    }
+}
+
+/// The biome ids of each column of a chunk.
+#[derive(Clone, Copy)]
+pub struct BiomeMap {
+    ids: [u8; CHUNK_LENGTH_U * CHUNK_LENGTH_U],
+}
+
+impl BiomeMap {
+    /// Gets the biome id at the specified local column coordinates.
+    #[inline]
+    pub fn get(&self, pos: [u32; 2]) -> u8 {
+        self.ids[pos[1] as usize * CHUNK_LENGTH_U + pos[0] as usize]
+    }
+
+    /// Returns the biome id at the center of the chunk, which drives chunk-wide decoration.
+    #[inline]
+    pub fn dominant(&self) -> u8 {
+        self.get([CHUNK_LENGTH / 2; 2])
+    }
+}
@@ -0,0 +1,472 @@
+use bevy::{
+    math::IVec3,
+    prelude::{
+        EventWriter, Local, ParallelSystemDescriptorCoercion, Plugin, Res, ResMut, SystemLabel,
+    },
+    time::Time,
+    utils::{Duration, HashMap, HashSet},
+};
+
+use super::{
+    chunk_key_at,
+    chunks::{CurrentLocalPlayerChunk, DirtyChunks},
+    fluids::FluidLevels,
+    materials::{Dirt, Grass, TallGrass, Water},
+    stages::{voxel_simulation_running, ChunkMeshingStage, VoxelSimulationPause},
+    ChunkShape, CHUNK_SIZE,
+};
+use crate::voxel::{
+    material::{VoxelMaterial, VoxelMaterialFlags, VoxelMaterialRegistry},
+    net::{ChunkClient, NetworkVoxelEdit, ReplicationSystem},
+    storage::{ChunkMap, VoxelMetadataMap},
+    Voxel,
+};
+
+/// Last growth stage of the growing voxels, after which they stop ticking.
+pub const MAX_GROWTH_STAGE: u8 = 7;
+
+/// Settings of the block ticks, updating the active voxels of the chunks around the player at a fixed rate.
+pub struct BlockTickSettings {
+    pub enabled: bool,
+    /// Interval between two ticks.
+    pub interval: Duration,
+    /// Number of active voxels ticked per chunk and per tick, picked at random.
+    pub ticks_per_chunk: usize,
+    /// Horizontal radius (in chunks) of the area around the player whose voxels get ticked.
+    pub radius: i32,
+}
+
+impl Default for BlockTickSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: Duration::from_millis(250),
+            ticks_per_chunk: 4,
+            radius: 8,
+        }
+    }
+}
+
+/// A tick of an active voxel, handed to the [`BlockTickHandler`] of its material.
+pub struct BlockTick<'a> {
+    pub pos: IVec3,
+    pub voxel: Voxel,
+    /// A random value, different for each tick.
+    pub random: u32,
+    pub chunks: &'a ChunkMap<Voxel, ChunkShape>,
+    pub materials: &'a VoxelMaterialRegistry,
+    pub fluid_levels: &'a FluidLevels,
+    pub metadata: &'a mut VoxelMetadataMap,
+    edits: &'a mut Vec<(IVec3, Voxel)>,
+    activated: &'a mut Vec<IVec3>,
+}
+
+#[allow(dead_code)]
+impl<'a> BlockTick<'a> {
+    /// Returns the voxel at an offset from the ticked voxel, `None` if not loaded.
+    pub fn neighbor(&self, offset: IVec3) -> Option<Voxel> {
+        self.chunks.voxel_at(self.pos + offset)
+    }
+
+    /// Returns whether the voxel at the specified position lets the voxels below it see the sky (e.g. grass grow).
+    pub fn is_clear(&self, pos: IVec3) -> bool {
+        match self.chunks.voxel_at(pos) {
+            Some(Voxel::EMPTY_VOXEL) => true,
+            Some(voxel) => self.materials.get_by_id(voxel.0).map_or(false, |material| {
+                material.flags.contains(VoxelMaterialFlags::FOLIAGE)
+            }),
+            None => false,
+        }
+    }
+
+    /// Replaces a voxel once the tick is over, the new voxel being activated if its material has a tick handler.
+    pub fn set_voxel(&mut self, pos: IVec3, voxel: Voxel) {
+        self.edits.push((pos, voxel));
+        self.activated.push(pos);
+    }
+
+    /// Activates a voxel, so that it gets ticked.
+    pub fn activate(&mut self, pos: IVec3) {
+        self.activated.push(pos);
+    }
+}
+
+/// Updates a ticked voxel, returns whether the voxel stays active.
+pub type BlockTickHandler = fn(&mut BlockTick) -> bool;
+
+/// The tick handlers of the voxel materials, the voxels of the materials without one never being active.
+#[derive(Default)]
+pub struct BlockTickHandlers {
+    handlers: HashMap<u8, BlockTickHandler>,
+}
+
+#[allow(dead_code)]
+impl BlockTickHandlers {
+    /// Registers the tick handler of a material, replacing any previous one.
+    pub fn register(&mut self, material: u8, handler: BlockTickHandler) -> &mut Self {
+        self.handlers.insert(material, handler);
+        self
+    }
+
+    pub fn unregister(&mut self, material: u8) {
+        self.handlers.remove(&material);
+    }
+
+    pub fn get(&self, material: u8) -> Option<BlockTickHandler> {
+        self.handlers.get(&material).copied()
+    }
+}
+
+/// The voxels with a tick handler of the loaded chunks, grouped by chunk.
+#[derive(Default)]
+pub struct ActiveVoxels(HashMap<IVec3, HashSet<IVec3>>);
+
+#[allow(dead_code)]
+impl ActiveVoxels {
+    pub fn activate(&mut self, pos: IVec3) {
+        self.0.entry(chunk_key_at(pos)).or_default().insert(pos);
+    }
+
+    pub fn deactivate(&mut self, pos: IVec3) {
+        let key = chunk_key_at(pos);
+        if let Some(voxels) = self.0.get_mut(&key) {
+            voxels.remove(&pos);
+            if voxels.is_empty() {
+                self.0.remove(&key);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "headless"))]
+    pub fn is_active(&self, pos: IVec3) -> bool {
+        self.0
+            .get(&chunk_key_at(pos))
+            .map_or(false, |voxels| voxels.contains(&pos))
+    }
+
+    /// Returns the number of active voxels.
+    pub fn len(&self) -> usize {
+        self.0.values().map(|voxels| voxels.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+const HORIZONTAL_DIRECTIONS: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+/// Spreads the grass to a random dirt voxel around it with clear sky above, and turns the grass buried under solid voxels
+/// back into dirt. The grass stays active while it can spread further.
+fn tick_grass(tick: &mut BlockTick) -> bool {
+    if !tick.is_clear(tick.pos + IVec3::Y) {
+        tick.set_voxel(tick.pos, Dirt::into_voxel());
+        return false;
+    }
+
+    let spreadable: Vec<IVec3> = (-1..=1)
+        .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z))))
+        .map(|offset| tick.pos + offset)
+        .filter(|pos| {
+            tick.chunks.voxel_at(*pos) == Some(Dirt::into_voxel()) && tick.is_clear(*pos + IVec3::Y)
+        })
+        .collect();
+
+    if spreadable.is_empty() {
+        return false;
+    }
+
+    // only spreading on some of the ticks makes the grass creep over the dirt.
+    if tick.random % 4 == 0 {
+        let target = spreadable[(tick.random / 4) as usize % spreadable.len()];
+        tick.set_voxel(target, Grass::into_voxel());
+    }
+    true
+}
+
+/// Grows a crop voxel one stage at a time, storing its stage in the voxel metadata. Stops ticking once fully grown.
+fn tick_crop(tick: &mut BlockTick) -> bool {
+    let stage = tick
+        .metadata
+        .get(tick.pos)
+        .and_then(|metadata| metadata.growth_stage)
+        .unwrap_or(0);
+    if stage >= MAX_GROWTH_STAGE {
+        return false;
+    }
+
+    if tick.random % 3 == 0 {
+        tick.metadata.get_or_default(tick.pos).growth_stage = Some(stage + 1);
+    }
+    stage + 1 < MAX_GROWTH_STAGE
+}
+
+/// Turns the empty or flowing fluid voxels resting on something and surrounded by at least two fluid sources into
+/// sources, so that dug out pools refill. The source stays active while refilling, the edits around it activating it
+/// again.
+fn tick_fluid_source(tick: &mut BlockTick) -> bool {
+    let water = Water::into_voxel();
+    let is_source = |tick: &BlockTick, pos: IVec3| {
+        tick.chunks.voxel_at(pos) == Some(water) && tick.fluid_levels.level_at(pos).is_none()
+    };
+    if !is_source(tick, tick.pos) {
+        return false;
+    }
+
+    let mut refilled = false;
+    for direction in HORIZONTAL_DIRECTIONS {
+        let pos = tick.pos + direction;
+        let fillable = match tick.chunks.voxel_at(pos) {
+            Some(Voxel::EMPTY_VOXEL) => true,
+            Some(voxel) => voxel == water && tick.fluid_levels.level_at(pos).is_some(),
+            None => false,
+        };
+        let supported = match tick.chunks.voxel_at(pos - IVec3::Y) {
+            Some(Voxel::EMPTY_VOXEL) | None => false,
+            Some(voxel) => voxel != water || tick.fluid_levels.level_at(pos - IVec3::Y).is_none(),
+        };
+        if !fillable || !supported {
+            continue;
+        }
+
+        let sources = HORIZONTAL_DIRECTIONS
+            .iter()
+            .filter(|neighbor| is_source(tick, pos + **neighbor))
+            .count();
+        if sources >= 2 {
+            tick.set_voxel(pos, water);
+            refilled = true;
+        }
+    }
+    refilled
+}
+
+/// Returns the offsets of the voxels activated around an edited voxel: its 3x3x3 neighborhood, and the row below it
+/// whose grass may spread to the dirt uncovered by the edit.
+fn activation_offsets() -> impl Iterator<Item = IVec3> {
+    (-1..=1).flat_map(|x| (-2..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z))))
+}
+
+/// Returns whether a voxel having a tick handler has anything to do once ticked. The water sources are only active when
+/// next to an empty or flowing water voxel they may refill, so that the oceans don't fill the active voxels.
+fn needs_ticks(
+    pos: IVec3,
+    voxel: Voxel,
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    fluid_levels: &FluidLevels,
+) -> bool {
+    if voxel != Water::into_voxel() {
+        return true;
+    }
+
+    fluid_levels.level_at(pos).is_none()
+        && HORIZONTAL_DIRECTIONS.iter().any(|direction| {
+            let neighbor = pos + *direction;
+            match chunks.voxel_at(neighbor) {
+                Some(Voxel::EMPTY_VOXEL) => true,
+                Some(other) => other == voxel && fluid_levels.level_at(neighbor).is_some(),
+                None => false,
+            }
+        })
+}
+
+/// Scans the freshly loaded chunks for the voxels having a tick handler, activates the voxels around the ones edited
+/// during the frame and forgets the unloaded chunks. The chunks only get scanned once, until unloaded.
+fn activate_chunk_voxels(
+    handlers: Res<BlockTickHandlers>,
+    dirty_chunks: Res<DirtyChunks>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    fluid_levels: Res<FluidLevels>,
+    mut active: ResMut<ActiveVoxels>,
+    mut scanned: Local<HashSet<IVec3>>,
+) {
+    active.0.retain(|key, _| chunks.exists(*key));
+    scanned.retain(|key| chunks.exists(*key));
+
+    let mut ticked = [false; 256];
+    handlers
+        .handlers
+        .keys()
+        .for_each(|material| ticked[*material as usize] = true);
+    let activable = |pos: IVec3, voxel: Voxel| {
+        ticked[voxel.0 as usize] && needs_ticks(pos, voxel, &chunks, &fluid_levels)
+    };
+
+    for key in dirty_chunks.iter_dirty() {
+        if scanned.contains(key) {
+            continue;
+        }
+        let buffer = match chunks.buffer_at(*key) {
+            Some(buffer) => buffer,
+            None => continue,
+        };
+        scanned.insert(*key);
+        if !buffer.slice().iter().any(|voxel| ticked[voxel.0 as usize]) {
+            active.0.remove(key);
+            continue;
+        }
+
+        let mut voxels = HashSet::default();
+        for x in 0..CHUNK_SIZE.x {
+            for y in 0..CHUNK_SIZE.y {
+                for z in 0..CHUNK_SIZE.z {
+                    let pos = *key + IVec3::new(x, y, z);
+                    if activable(pos, buffer.voxel_at((pos - *key).as_uvec3())) {
+                        voxels.insert(pos);
+                    }
+                }
+            }
+        }
+        if voxels.is_empty() {
+            active.0.remove(key);
+        } else {
+            active.0.insert(*key, voxels);
+        }
+    }
+
+    for pos in dirty_chunks
+        .iter_edited_voxels()
+        .flat_map(|pos| activation_offsets().map(move |offset| *pos + offset))
+    {
+        if let Some(voxel) = chunks.voxel_at(pos) {
+            if activable(pos, voxel) {
+                active.activate(pos);
+            }
+        }
+    }
+}
+
+/// Ticks random active voxels of the chunks around the player at a fixed rate, applying the voxels replaced by the tick
+/// handlers through [`NetworkVoxelEdit`]s so that a server replicates them. Clients leave the ticks to the server.
+#[allow(clippy::too_many_arguments)]
+fn tick_active_voxels(
+    settings: Res<BlockTickSettings>,
+    handlers: Res<BlockTickHandlers>,
+    time: Res<Time>,
+    pause: Res<VoxelSimulationPause>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    client: Option<Res<ChunkClient>>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    materials: Res<VoxelMaterialRegistry>,
+    mut fluid_levels: ResMut<FluidLevels>,
+    mut metadata: ResMut<VoxelMetadataMap>,
+    mut active: ResMut<ActiveVoxels>,
+    mut voxel_edits: EventWriter<NetworkVoxelEdit>,
+    mut elapsed: Local<Duration>,
+    mut seed: Local<u32>,
+) {
+    if !settings.enabled || client.is_some() {
+        return;
+    }
+
+    // a step of the paused simulation ticks exactly once.
+    *elapsed += time.delta();
+    if *elapsed < settings.interval && !pause.is_stepping() {
+        return;
+    }
+    *elapsed = Duration::ZERO;
+
+    let mut next_random = || {
+        // xorshift, seeded with a non zero state.
+        let mut state = if *seed == 0 { 0x2545_f491 } else { *seed };
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        *seed = state;
+        state
+    };
+
+    let radius = settings.radius * CHUNK_SIZE.x;
+    let keys: Vec<IVec3> = active
+        .0
+        .keys()
+        .filter(|key| {
+            let delta = **key - player_chunk.chunk_min;
+            delta.x.abs() <= radius && delta.z.abs() <= radius
+        })
+        .copied()
+        .collect();
+
+    let (mut edits, mut activated, mut deactivated) = (Vec::new(), Vec::new(), Vec::new());
+    for key in keys {
+        let voxels: Vec<IVec3> = active.0[&key].iter().copied().collect();
+        for _ in 0..settings.ticks_per_chunk.min(voxels.len()) {
+            let pos = voxels[next_random() as usize % voxels.len()];
+            let voxel = match chunks.voxel_at(pos) {
+                Some(voxel) => voxel,
+                None => continue,
+            };
+            let handler = match handlers.get(voxel.0) {
+                Some(handler) => handler,
+                None => {
+                    deactivated.push(pos);
+                    continue;
+                }
+            };
+
+            let mut tick = BlockTick {
+                pos,
+                voxel,
+                random: next_random(),
+                chunks: &chunks,
+                materials: &materials,
+                fluid_levels: &fluid_levels,
+                metadata: &mut metadata,
+                edits: &mut edits,
+                activated: &mut activated,
+            };
+            if !handler(&mut tick) {
+                deactivated.push(pos);
+            }
+        }
+    }
+
+    deactivated
+        .into_iter()
+        .for_each(|pos| active.deactivate(pos));
+    activated.into_iter().for_each(|pos| active.activate(pos));
+
+    for (pos, voxel) in edits {
+        // the voxels set by the ticks are fluid sources.
+        fluid_levels.set_level(pos, None);
+        voxel_edits.send(NetworkVoxelEdit {
+            pos,
+            voxel,
+            journaled: false,
+            client: None,
+        });
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`BlockTicksPlugin`]
+pub enum BlockTickSystem {
+    /// Ticks the active voxels, the voxels they replace being applied during the same frame.
+    TickActiveVoxels,
+}
+
+/// Ticks the active voxels (spreading grass, growing crops, refilling fluid sources) of the chunks around the player at
+/// a fixed rate. Other materials can be given a behavior by registering a [`BlockTickHandler`] in the
+/// [`BlockTickHandlers`].
+pub struct BlockTicksPlugin;
+
+impl Plugin for BlockTicksPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        let mut handlers = BlockTickHandlers::default();
+        handlers
+            .register(Grass::ID, tick_grass)
+            .register(TallGrass::ID, tick_crop)
+            .register(Water::ID, tick_fluid_source);
+
+        app.insert_resource(handlers)
+            .init_resource::<BlockTickSettings>()
+            .init_resource::<ActiveVoxels>()
+            .add_system(
+                tick_active_voxels
+                    .label(BlockTickSystem::TickActiveVoxels)
+                    .with_run_criteria(voxel_simulation_running)
+                    .before(ReplicationSystem::ApplyVoxelEdits),
+            )
+            .add_system_to_stage(ChunkMeshingStage, activate_chunk_voxels);
+    }
+}
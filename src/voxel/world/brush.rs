@@ -0,0 +1,252 @@
+use bevy::{
+    math::IVec3,
+    prelude::{
+        EventReader, EventWriter, Input, ParallelSystemDescriptorCoercion, Plugin, Query, Res,
+        SystemLabel,
+    },
+};
+
+use super::{
+    chunk_key_at,
+    input::InputAction,
+    interaction::{TargetedVoxel, VoxelInteractionSystem},
+    materials::Dirt,
+    player::PlayerController,
+    ChunkShape, CHUNK_SIZE, MAX_BRUSH_RADIUS,
+};
+use crate::voxel::{
+    material::{VoxelMaterial, VoxelMaterialFlags, VoxelMaterialRegistry},
+    net::{NetworkVoxelEdit, ReplicationSystem},
+    storage::ChunkMap,
+    Voxel,
+};
+
+/// Shape of the area edited by the [`TerraformBrush`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BrushShape {
+    /// Fills (or erases) the voxels within the radius of the center.
+    Sphere,
+    /// Fills (or erases) the voxels of the cube of half size the radius around the center.
+    Cube,
+    /// Erodes the solid voxels sticking out and fills the hollow ones within the radius of the center.
+    Smooth,
+}
+
+/// The brush terraforming the world at the voxel targeted by the player.
+pub struct TerraformBrush {
+    /// Whether the brush actions apply the brush.
+    pub enabled: bool,
+    pub shape: BrushShape,
+    /// Radius of the brush in voxels, up to [`MAX_BRUSH_RADIUS`].
+    pub radius: i32,
+    /// Material of the voxels filled by the sphere and cube brushes.
+    pub material: Voxel,
+}
+
+impl Default for TerraformBrush {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            shape: BrushShape::Sphere,
+            radius: 3,
+            material: Dirt::into_voxel(),
+        }
+    }
+}
+
+/// Event applying the [`TerraformBrush`] around a voxel, erasing the voxels instead of filling them if specified.
+pub struct ApplyTerraformBrush {
+    pub center: IVec3,
+    pub erase: bool,
+}
+
+/// Returns whether a voxel is solid, so that the fill brushes can replace the others and the smoothing can erode it.
+fn is_solid(voxel: Voxel, registry: &VoxelMaterialRegistry) -> bool {
+    voxel != Voxel::EMPTY_VOXEL
+        && registry.get_by_id(voxel.0).map_or(true, |material| {
+            !material.flags.contains(VoxelMaterialFlags::LIQUID)
+        })
+}
+
+/// Returns the voxel a smoothed voxel becomes from its 3x3x3 neighborhood: a solid voxel with few solid neighbors gets
+/// eroded, and a hollow voxel mostly surrounded by solid voxels gets filled with the most common neighboring material.
+fn smoothed_voxel(
+    pos: IVec3,
+    voxel: Voxel,
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    registry: &VoxelMaterialRegistry,
+) -> Voxel {
+    let mut counts = [0u8; 256];
+    let mut solid = 0;
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                if let Some(neighbor) = chunks.voxel_at(pos + IVec3::new(x, y, z)) {
+                    if is_solid(neighbor, registry) {
+                        counts[neighbor.0 as usize] += 1;
+                        solid += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    if is_solid(voxel, registry) {
+        if solid < 10 {
+            return Voxel::EMPTY_VOXEL;
+        }
+    } else if solid > 17 {
+        let (material, _) = counts
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, count)| **count)
+            .unwrap();
+        return Voxel(material as u8);
+    }
+    voxel
+}
+
+/// Returns the voxels changed by a brush applied around a voxel, chunk by chunk.
+/// The smoothing reads the voxels as they were before the brush, so the result doesn't depend on the iteration order.
+pub fn brush_edits(
+    brush: &TerraformBrush,
+    center: IVec3,
+    erase: bool,
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    registry: &VoxelMaterialRegistry,
+) -> Vec<(IVec3, Voxel)> {
+    let radius = brush.radius.clamp(0, MAX_BRUSH_RADIUS);
+    let min = center - IVec3::splat(radius);
+    let max = center + IVec3::splat(radius);
+    let radius_squared = (radius as f32 + 0.5).powi(2);
+
+    let mut edits = Vec::new();
+    let (min_key, max_key) = (chunk_key_at(min), chunk_key_at(max));
+    for key_x in (min_key.x..=max_key.x).step_by(CHUNK_SIZE.x as usize) {
+        for key_y in (min_key.y..=max_key.y).step_by(CHUNK_SIZE.y as usize) {
+            for key_z in (min_key.z..=max_key.z).step_by(CHUNK_SIZE.z as usize) {
+                let key = IVec3::new(key_x, key_y, key_z);
+                let buffer = match chunks.buffer_at(key) {
+                    Some(buffer) => buffer,
+                    None => continue,
+                };
+
+                // the part of the brush area within the chunk.
+                let chunk_min = min.max(key);
+                let chunk_max = max.min(key + CHUNK_SIZE - IVec3::ONE);
+                for x in chunk_min.x..=chunk_max.x {
+                    for y in chunk_min.y..=chunk_max.y {
+                        for z in chunk_min.z..=chunk_max.z {
+                            let pos = IVec3::new(x, y, z);
+                            let offset = pos - center;
+                            if brush.shape != BrushShape::Cube
+                                && offset.as_vec3().length_squared() > radius_squared
+                            {
+                                continue;
+                            }
+
+                            let voxel = buffer.voxel_at((pos - key).as_uvec3());
+                            let edited = match brush.shape {
+                                BrushShape::Smooth => smoothed_voxel(pos, voxel, chunks, registry),
+                                _ if erase => Voxel::EMPTY_VOXEL,
+                                _ if is_solid(voxel, registry) => voxel,
+                                _ => brush.material,
+                            };
+
+                            if edited != voxel {
+                                edits.push((pos, edited));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    edits
+}
+
+/// Applies the brush at the targeted voxel when the brush actions get pressed while looking around.
+fn use_terraform_brush(
+    brush: Res<TerraformBrush>,
+    actions: Res<Input<InputAction>>,
+    targeted: Res<TargetedVoxel>,
+    player: Query<&PlayerController>,
+    mut brush_events: EventWriter<ApplyTerraformBrush>,
+) {
+    if !brush.enabled
+        || !player
+            .get_single()
+            .map_or(false, |controller| controller.cursor_locked())
+    {
+        return;
+    }
+
+    let erase = match (
+        actions.just_pressed(InputAction::BrushApply),
+        actions.just_pressed(InputAction::BrushErase),
+    ) {
+        (true, _) => false,
+        (_, true) => true,
+        _ => return,
+    };
+
+    if let Some(hit) = targeted.0 {
+        brush_events.send(ApplyTerraformBrush {
+            center: hit.position,
+            erase,
+        });
+    }
+}
+
+/// Turns the applied brushes into voxel edits, all the edits of a frame forming a single undo batch.
+fn apply_terraform_brushes(
+    mut brush_events: EventReader<ApplyTerraformBrush>,
+    brush: Res<TerraformBrush>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    registry: Res<VoxelMaterialRegistry>,
+    mut edits: EventWriter<NetworkVoxelEdit>,
+) {
+    for event in brush_events.iter() {
+        edits.send_batch(
+            brush_edits(&brush, event.center, event.erase, &chunks, &registry)
+                .into_iter()
+                .map(|(pos, voxel)| NetworkVoxelEdit {
+                    pos,
+                    voxel,
+                    journaled: true,
+                    client: None,
+                }),
+        );
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`TerraformBrushPlugin`]
+pub enum TerraformBrushSystem {
+    /// Sends the brush events of the brush actions.
+    UseBrush,
+    /// Turns the brush events into voxel edits, applied during the same frame.
+    ApplyBrushes,
+}
+
+/// Terraforming of the world with a [`TerraformBrush`], bound to the brush actions.
+pub struct TerraformBrushPlugin;
+
+impl Plugin for TerraformBrushPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<TerraformBrush>()
+            .add_event::<ApplyTerraformBrush>()
+            .add_system(
+                use_terraform_brush
+                    .label(TerraformBrushSystem::UseBrush)
+                    .after(VoxelInteractionSystem::UpdateTargetedVoxel),
+            )
+            .add_system(
+                apply_terraform_brushes
+                    .label(TerraformBrushSystem::ApplyBrushes)
+                    .after(TerraformBrushSystem::UseBrush)
+                    .before(ReplicationSystem::ApplyVoxelEdits),
+            );
+    }
+}
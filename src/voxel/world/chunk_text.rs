@@ -0,0 +1,267 @@
+use std::{fmt::Write, path::PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+use bevy::{
+    math::{IVec3, UVec3},
+    prelude::{
+        error, info, EventReader, EventWriter, ParallelSystemDescriptorCoercion, Plugin, Res,
+    },
+};
+
+use super::{chunk_key_at, ChunkShape, CHUNK_SIZE};
+use crate::voxel::{
+    material::VoxelMaterialRegistry,
+    net::{NetworkVoxelEdit, ReplicationSystem},
+    storage::{ChunkMap, VoxelBuffer},
+    Voxel,
+};
+
+/// First line of the chunk text files, along the version of the format.
+const CHUNK_TEXT_HEADER: &str = "vx_bevy chunk 1";
+/// Character of the empty voxels.
+const EMPTY_CHAR: char = '.';
+/// Characters the materials of a chunk are written as, in order of first appearance. `#` starts the comments.
+const MATERIAL_CHARS: &str =
+    "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789!$%&*+-/:;<=>?@^_~|()[]{}";
+
+/// Writes the voxels of a chunk as text, layer by layer from the bottom up. Each layer is made of a row of characters
+/// per Z coordinate, a character per X coordinate. The characters are mapped to the material names by a palette, so
+/// that the file stays valid when the material ids change.
+pub fn write_chunk_text(
+    key: IVec3,
+    buffer: &VoxelBuffer<Voxel, ChunkShape>,
+    registry: &VoxelMaterialRegistry,
+) -> anyhow::Result<String> {
+    let mut chars = [None; 256];
+    chars[Voxel::EMPTY_VOXEL.0 as usize] = Some(EMPTY_CHAR);
+    let mut palette = vec![(EMPTY_CHAR, Voxel::EMPTY_VOXEL)];
+    let mut free_chars = MATERIAL_CHARS.chars();
+
+    let mut layers = String::new();
+    for y in 0..CHUNK_SIZE.y as u32 {
+        writeln!(layers, "layer {}", y)?;
+        for z in 0..CHUNK_SIZE.z as u32 {
+            for x in 0..CHUNK_SIZE.x as u32 {
+                let voxel = buffer.voxel_at(UVec3::new(x, y, z));
+                let char = match chars[voxel.0 as usize] {
+                    Some(char) => char,
+                    None => {
+                        let char = free_chars.next().ok_or_else(|| {
+                            anyhow!("the chunk holds too many materials to be written as text")
+                        })?;
+                        chars[voxel.0 as usize] = Some(char);
+                        palette.push((char, voxel));
+                        char
+                    }
+                };
+                layers.push(char);
+            }
+            layers.push('\n');
+        }
+    }
+
+    let mut text = String::new();
+    writeln!(text, "{}", CHUNK_TEXT_HEADER)?;
+    writeln!(text, "key {} {} {}", key.x, key.y, key.z)?;
+    writeln!(text, "palette")?;
+    for (char, voxel) in palette {
+        let name = &registry
+            .get_by_id(voxel.0)
+            .ok_or_else(|| anyhow!("the chunk holds unregistered material id {}", voxel.0))?
+            .name;
+        writeln!(text, "{} {}", char, name)?;
+    }
+    text.push_str(&layers);
+
+    Ok(text)
+}
+
+/// Reads a chunk written by [`write_chunk_text`], returning its key and voxels. Blank lines and lines starting with `#`
+/// are ignored, so that the files can be annotated by hand.
+pub fn read_chunk_text(
+    text: &str,
+    registry: &VoxelMaterialRegistry,
+) -> anyhow::Result<(IVec3, VoxelBuffer<Voxel, ChunkShape>)> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim_end()))
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'));
+    let mut next_line = |expected: &str| {
+        lines
+            .next()
+            .ok_or_else(|| anyhow!("unexpected end of file, expected {}", expected))
+    };
+
+    let (_, header) = next_line("the header")?;
+    if header != CHUNK_TEXT_HEADER {
+        bail!("not a chunk text file, expected {:?}", CHUNK_TEXT_HEADER);
+    }
+
+    let (number, line) = next_line("the chunk key")?;
+    let coordinates: Option<Vec<i32>> = line.strip_prefix("key ").and_then(|key| {
+        key.split_whitespace()
+            .map(|coordinate| coordinate.parse().ok())
+            .collect()
+    });
+    let key = match coordinates.as_deref() {
+        Some(&[x, y, z]) => IVec3::new(x, y, z),
+        _ => bail!("line {}: expected key <x> <y> <z>", number),
+    };
+    if chunk_key_at(key) != key {
+        bail!("line {}: {:?} isn't the minimum of a chunk", number, key);
+    }
+
+    let (number, line) = next_line("the palette")?;
+    if line != "palette" {
+        bail!("line {}: expected palette", number);
+    }
+
+    let mut voxels = [None; 128];
+    let mut line = next_line("the first layer")?;
+    while !line.1.starts_with("layer ") {
+        let (number, entry) = line;
+        let mut entry_chars = entry.chars();
+        let (char, name) = match (entry_chars.next(), entry_chars.as_str().strip_prefix(' ')) {
+            (Some(char), Some(name)) if char.is_ascii() => (char, name),
+            _ => bail!("line {}: expected <character> <material name>", number),
+        };
+        let id = registry
+            .iter_mats()
+            .position(|material| material.name == name)
+            .with_context(|| format!("line {}: unknown material {:?}", number, name))?;
+        voxels[char as usize] = Some(Voxel(id as u8));
+
+        line = next_line("the first layer")?;
+    }
+
+    let mut buffer = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+    for y in 0..CHUNK_SIZE.y as u32 {
+        if y > 0 {
+            line = next_line("a layer")?;
+        }
+        let (number, layer) = line;
+        if layer != format!("layer {}", y) {
+            bail!("line {}: expected layer {}", number, y);
+        }
+
+        for z in 0..CHUNK_SIZE.z as u32 {
+            let (number, row) = next_line("a row of voxels")?;
+            if row.chars().count() != CHUNK_SIZE.x as usize {
+                bail!("line {}: expected {} voxels", number, CHUNK_SIZE.x);
+            }
+
+            for (x, char) in row.chars().enumerate() {
+                let voxel = voxels
+                    .get(char as usize)
+                    .copied()
+                    .flatten()
+                    .ok_or_else(|| anyhow!("line {}: {:?} isn't in the palette", number, char))?;
+                buffer.set_voxel(UVec3::new(x as u32, y, z), voxel);
+            }
+        }
+    }
+
+    Ok((key, buffer))
+}
+
+/// Event writing the voxels of the chunk at `key` to a text file, see [`write_chunk_text`].
+pub struct ExportChunkText {
+    pub path: PathBuf,
+    pub key: IVec3,
+}
+
+/// Event replacing the voxels of a loaded chunk by the ones of a text file, see [`read_chunk_text`].
+pub struct ImportChunkText {
+    pub path: PathBuf,
+    /// Key of the chunk to replace, the key written in the file if `None`.
+    pub key: Option<IVec3>,
+}
+
+fn export_chunk_texts(
+    mut events: EventReader<ExportChunkText>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    registry: Res<VoxelMaterialRegistry>,
+) {
+    for event in events.iter() {
+        let result = chunks
+            .buffer_at(event.key)
+            .ok_or_else(|| anyhow!("the chunk isn't loaded"))
+            .and_then(|buffer| write_chunk_text(event.key, buffer, &registry))
+            .and_then(|text| std::fs::write(&event.path, text).map_err(anyhow::Error::from));
+
+        match result {
+            Ok(()) => info!("Exported chunk {:?} to {:?}", event.key, event.path),
+            Err(err) => error!(
+                "Failed to export chunk {:?} to {:?}: {}",
+                event.key, event.path, err
+            ),
+        }
+    }
+}
+
+/// Turns the imported chunks into voxel edits, so that they get journaled, replicated and lit like the player edits.
+fn import_chunk_texts(
+    mut events: EventReader<ImportChunkText>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    registry: Res<VoxelMaterialRegistry>,
+    mut edits: EventWriter<NetworkVoxelEdit>,
+) {
+    for event in events.iter() {
+        let (file_key, imported) = match std::fs::read_to_string(&event.path)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| read_chunk_text(&text, &registry))
+        {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                error!("Failed to import chunk {:?}: {}", event.path, err);
+                continue;
+            }
+        };
+
+        let key = event.key.unwrap_or(file_key);
+        if !chunks.exists(key) {
+            error!(
+                "Failed to import chunk {:?}: chunk {:?} isn't loaded",
+                event.path, key
+            );
+            continue;
+        }
+
+        let mut changed = 0;
+        for y in 0..CHUNK_SIZE.y {
+            for z in 0..CHUNK_SIZE.z {
+                for x in 0..CHUNK_SIZE.x {
+                    let local = IVec3::new(x, y, z);
+                    let voxel = imported.voxel_at(local.as_uvec3());
+                    if chunks.voxel_at(key + local) != Some(voxel) {
+                        edits.send(NetworkVoxelEdit {
+                            pos: key + local,
+                            voxel,
+                            journaled: true,
+                            client: None,
+                        });
+                        changed += 1;
+                    }
+                }
+            }
+        }
+        info!(
+            "Imported chunk {:?} into chunk {:?}, {} voxels changed",
+            event.path, key, changed
+        );
+    }
+}
+
+/// Exports and imports single chunks as human readable text through [`ExportChunkText`] and [`ImportChunkText`] events,
+/// so that suspicious chunks can be attached to bug reports, edited by hand and reloaded for reproducing issues.
+pub struct ChunkTextPlugin;
+
+impl Plugin for ChunkTextPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_event::<ExportChunkText>()
+            .add_event::<ImportChunkText>()
+            .add_system(export_chunk_texts)
+            .add_system(import_chunk_texts.before(ReplicationSystem::ApplyVoxelEdits));
+    }
+}
@@ -1,26 +1,42 @@
 use bevy::{
     ecs::schedule::ShouldRun,
-    math::IVec3,
+    math::{IVec3, Vec3},
     prelude::{
-        Changed, Commands, CoreStage, Entity, GlobalTransform, ParallelSystemDescriptorCoercion,
-        Plugin, Query, Res, ResMut, StageLabel, SystemLabel, SystemStage, With,
+        Commands, Component, CoreStage, DespawnRecursiveExt, Entity, EventWriter, GlobalTransform,
+        ParallelSystemDescriptorCoercion, Plugin, Query, Res, ResMut, SystemLabel, SystemSet,
     },
     utils::{HashMap, HashSet},
 };
+#[cfg(not(feature = "headless"))]
+use bevy::{
+    prelude::{Changed, With},
+    utils::Duration,
+};
 use float_ord::FloatOrd;
 
-use super::{player::PlayerController, Chunk, ChunkShape, CHUNK_LENGTH};
+#[cfg(not(feature = "headless"))]
+use super::player::PlayerController;
+use super::{
+    chunk_key_at, chunk_keys_around_voxel,
+    origin::WorldOrigin,
+    pipeline_log::{ChunkDecision, ChunkPipelineLog},
+    stages::{voxel_simulation_running, ChunkLoadingStage},
+    stats::ChunkLifecycleEvent,
+    Chunk, ChunkShape, CHUNK_SIZE,
+};
 use crate::voxel::storage::ChunkMap;
 use crate::voxel::Voxel;
 
 /// Updates the current chunk position for the current player.
+#[cfg(not(feature = "headless"))]
 fn update_player_pos(
     player: Query<&GlobalTransform, (With<PlayerController>, Changed<GlobalTransform>)>,
     mut chunk_pos: ResMut<CurrentLocalPlayerChunk>,
+    origin: Res<WorldOrigin>,
 ) {
     if let Ok(ply) = player.get_single() {
-        let player_coords = ply.translation().as_ivec3();
-        let nearest_chunk_origin = !IVec3::splat((CHUNK_LENGTH - 1) as i32) & player_coords;
+        let player_coords = origin.voxel_at(ply.translation());
+        let nearest_chunk_origin = chunk_key_at(player_coords);
 
         chunk_pos.world_pos = player_coords;
 
@@ -48,23 +64,18 @@ fn update_view_chunks(
     chunk_entities: Res<ChunkEntities>,
     view_radius: Res<ChunkLoadRadius>,
     mut chunk_command_queue: ResMut<ChunkCommandQueue>,
+    mut log: ResMut<ChunkPipelineLog>,
 ) {
-    // quick n dirty circular chunk loading.
     //perf: optimize this.
-    for x in -view_radius.horizontal..view_radius.horizontal {
-        for z in -view_radius.horizontal..view_radius.horizontal {
-            for y in -view_radius.vertical..view_radius.vertical {
-                if x.pow(2) + z.pow(2) >= view_radius.horizontal.pow(2) {
+    for x in -view_radius.horizontal..=view_radius.horizontal {
+        for z in -view_radius.horizontal..=view_radius.horizontal {
+            for y in -view_radius.vertical..=view_radius.vertical {
+                if !view_radius.contains(IVec3::new(x, y, z)) {
                     continue;
                 }
 
                 let chunk_key = {
-                    let mut pos: IVec3 = player_pos.chunk_min
-                        + IVec3::new(
-                            x * CHUNK_LENGTH as i32,
-                            y * CHUNK_LENGTH as i32,
-                            z * CHUNK_LENGTH as i32,
-                        );
+                    let mut pos: IVec3 = player_pos.chunk_min + IVec3::new(x, y, z) * CHUNK_SIZE;
 
                     pos.y = pos.y.max(0);
 
@@ -73,19 +84,33 @@ fn update_view_chunks(
 
                 if chunk_entities.entity(chunk_key).is_none() {
                     chunk_command_queue.create.push(chunk_key);
+                    log.record(chunk_key, ChunkDecision::Load, || {
+                        format!(
+                            "within the {} load radius {}x{} of the player chunk {}",
+                            view_radius.shape.name(),
+                            view_radius.horizontal,
+                            view_radius.vertical,
+                            player_pos.chunk_min
+                        )
+                    });
                 }
             }
         }
     }
 
-    // quick n dirty circular chunk !loading.
-    for loaded_chunk in chunk_entities.0.keys() {
-        let delta: IVec3 = *loaded_chunk - player_pos.chunk_min;
-        if delta.x.pow(2) + delta.z.pow(2)
-            > view_radius.horizontal.pow(2) * (CHUNK_LENGTH as i32).pow(2)
-            || delta.y.pow(2) > view_radius.vertical.pow(2) * (CHUNK_LENGTH as i32).pow(2)
-        {
+    for loaded_chunk in chunk_entities.iter_keys() {
+        let delta: IVec3 = (*loaded_chunk - player_pos.chunk_min) / CHUNK_SIZE;
+        if !view_radius.contains(delta) {
             chunk_command_queue.destroy.push(*loaded_chunk);
+            log.record(*loaded_chunk, ChunkDecision::Unload, || {
+                format!(
+                    "out of the {} load radius {}x{} of the player chunk {}",
+                    view_radius.shape.name(),
+                    view_radius.horizontal,
+                    view_radius.vertical,
+                    player_pos.chunk_min
+                )
+            });
         }
     }
 
@@ -95,102 +120,363 @@ fn update_view_chunks(
     });
 }
 
+/// Computes the chunks kept loaded by [`ChunkLoadAnchor`]s and schedules loading / unloading of their data.
+/// Those chunks aren't given an entity unless they're also in sight of the player.
+fn update_anchor_chunks(
+    anchors: Query<(&GlobalTransform, &ChunkLoadAnchor)>,
+    origin: Res<WorldOrigin>,
+    chunk_entities: Res<ChunkEntities>,
+    mut anchored_chunks: ResMut<AnchoredChunks>,
+    mut chunk_command_queue: ResMut<ChunkCommandQueue>,
+    mut log: ResMut<ChunkPipelineLog>,
+) {
+    let anchor_positions: Vec<(IVec3, i32)> = anchors
+        .iter()
+        .map(|(transform, anchor)| {
+            (
+                chunk_key_at(origin.voxel_at(transform.translation())),
+                anchor.radius,
+            )
+        })
+        .collect();
+
+    if anchor_positions == anchored_chunks.anchors {
+        return;
+    }
+
+    let mut wanted = HashSet::default();
+    for (anchor_chunk, radius) in anchor_positions.iter() {
+        for x in -radius..=*radius {
+            for z in -radius..=*radius {
+                for y in -radius..=*radius {
+                    if x.pow(2) + z.pow(2) > radius.pow(2) {
+                        continue;
+                    }
+
+                    let mut key = *anchor_chunk + IVec3::new(x, y, z) * CHUNK_SIZE;
+                    key.y = key.y.max(0);
+                    wanted.insert(key);
+                }
+            }
+        }
+    }
+
+    for key in wanted.difference(&anchored_chunks.chunks) {
+        chunk_command_queue.load_data.push(*key);
+        log.record(*key, ChunkDecision::LoadData, || {
+            "within the radius of a chunk load anchor".to_string()
+        });
+    }
+
+    // chunks with an entity get their data unloaded along with their entity.
+    for key in anchored_chunks
+        .chunks
+        .difference(&wanted)
+        .filter(|key| chunk_entities.entity(**key).is_none())
+    {
+        chunk_command_queue.unload_data.push(*key);
+        log.record(*key, ChunkDecision::UnloadData, || {
+            "out of the radius of all the chunk load anchors, without an entity".to_string()
+        });
+    }
+
+    anchored_chunks.anchors = anchor_positions;
+    anchored_chunks.chunks = wanted;
+}
+
 /// Creates the requested chunks and attach them an ECS entity.
 fn create_chunks(
     mut chunks_command_queue: ResMut<ChunkCommandQueue>,
     mut chunk_entities: ResMut<ChunkEntities>,
+    mut lifecycle_events: EventWriter<ChunkLifecycleEvent>,
     mut cmds: Commands,
 ) {
     chunks_command_queue.create.drain(..).for_each(|request| {
-        chunk_entities.attach_entity(request, cmds.spawn().insert(Chunk(request)).id())
+        chunk_entities.attach_entity(request, cmds.spawn().insert(Chunk(request)).id());
+        lifecycle_events.send(ChunkLifecycleEvent::Requested(request));
     });
 }
 
+/// Despawns the entities of the chunks queued for destruction.
+/// Their data gets unloaded as well unless they're kept loaded by a [`ChunkLoadAnchor`].
 fn destroy_chunks(
     mut chunks_command_queue: ResMut<ChunkCommandQueue>,
-    mut chunks: ResMut<ChunkMap<Voxel, ChunkShape>>,
     mut chunk_entities: ResMut<ChunkEntities>,
+    anchored_chunks: Res<AnchoredChunks>,
+    mut log: ResMut<ChunkPipelineLog>,
     mut cmds: Commands,
 ) {
-    for command in chunks_command_queue.destroy.drain(..) {
-        cmds.entity(chunk_entities.detach_entity(command).unwrap())
-            .despawn();
-        chunks.remove(command);
+    let ChunkCommandQueue {
+        destroy,
+        unload_data,
+        ..
+    } = &mut *chunks_command_queue;
+
+    for command in destroy.drain(..) {
+        if let Some(entity) = chunk_entities.detach_entity(command) {
+            // along the foliage mesh child of the chunk.
+            cmds.entity(entity).despawn_recursive();
+        }
+
+        if !anchored_chunks.chunks.contains(&command) {
+            unload_data.push(command);
+            log.record(command, ChunkDecision::UnloadData, || {
+                "its entity got despawned and no chunk load anchor keeps it loaded".to_string()
+            });
+        }
     }
 }
 
-fn clear_dirty_chunks(mut dirty_chunks: ResMut<DirtyChunks>) {
-    dirty_chunks.0.clear();
+/// Moves the data of the chunks queued for unloading from the chunk map to its cache of recently unloaded chunks.
+fn unload_chunk_data(
+    mut chunks_command_queue: ResMut<ChunkCommandQueue>,
+    mut chunks: ResMut<ChunkMap<Voxel, ChunkShape>>,
+) {
+    for command in chunks_command_queue.unload_data.drain(..) {
+        chunks.unload(command);
+    }
 }
 
-/// Label for the stage housing the chunk loading systems.
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, StageLabel)]
-pub struct ChunkLoadingStage;
+fn clear_dirty_chunks(mut dirty_chunks: ResMut<DirtyChunks>) {
+    dirty_chunks.dirty.clear();
+    dirty_chunks.edited_voxels.clear();
+}
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
 /// Labels for the systems added by [`VoxelWorldChunkingPlugin`]
 pub enum ChunkLoadingSystem {
     /// Updates the player current chunk.
     /// The computed position is used for loading / meshing priority systems.
+    #[cfg(not(feature = "headless"))]
     UpdatePlayerPos,
     /// Runs chunk view distance calculations and queue events for chunk creations and deletions.
     UpdateViewChunks,
+    /// Computes the chunks kept loaded as data only by the [`ChunkLoadAnchor`]s.
+    UpdateAnchorChunks,
     /// Creates the voxel buffers to hold chunk data and attach them a chunk entity in the ECS world.
     CreateChunks,
     /// Clears the dirty chunks list.
     ClearDirtyChunks,
+    /// Despawns the entities of the chunks queued for unloading.
+    DestroyChunks,
+    /// Removes the data of the unloaded chunks from the chunk map.
+    UnloadChunkData,
 }
 
 /// Handles dynamically loading / unloading regions (aka chunks) of the world according to camera position.
 pub struct VoxelWorldChunkingPlugin;
 
-/// Stores the Entity <-> Chunk voxel data buffer mapping
+/// Number of chunks along each axis of the regions of the [`ChunkEntities`] spatial index.
+const CHUNK_INDEX_REGION_SIZE: i32 = 8;
+
+/// Stores the Entity <-> Chunk voxel data buffer mapping, along with a spatial index of the loaded chunks for proximity queries.
 #[derive(Default)]
-pub struct ChunkEntities(HashMap<IVec3, Entity>);
+pub struct ChunkEntities {
+    entities: HashMap<IVec3, Entity>,
+    keys: HashMap<Entity, IVec3>,
+    /// The loaded chunk keys, grouped by regions of [`CHUNK_INDEX_REGION_SIZE`] chunks along each axis.
+    regions: HashMap<IVec3, HashSet<IVec3>>,
+}
 
+#[allow(dead_code)]
 impl ChunkEntities {
     /// Returns the entity attached to the chunk.
     pub fn entity(&self, pos: IVec3) -> Option<Entity> {
-        self.0.get(&pos).map(|x| x.clone())
+        self.entities.get(&pos).copied()
+    }
+
+    /// Returns the key of the chunk the specified entity is attached to.
+    pub fn chunk_key(&self, entity: Entity) -> Option<IVec3> {
+        self.keys.get(&entity).copied()
     }
 
     /// Attaches the specified entity to the chunk data.
     pub fn attach_entity(&mut self, pos: IVec3, entity: Entity) {
-        self.0.insert(pos, entity);
+        if let Some(previous) = self.entities.insert(pos, entity) {
+            self.keys.remove(&previous);
+        }
+        self.keys.insert(entity, pos);
+        self.regions
+            .entry(Self::region_of(pos))
+            .or_default()
+            .insert(pos);
     }
 
     /// Detaches the specified entity to the chunk data.
     pub fn detach_entity(&mut self, pos: IVec3) -> Option<Entity> {
-        self.0.remove(&pos)
+        let entity = self.entities.remove(&pos)?;
+        self.keys.remove(&entity);
+
+        let region = Self::region_of(pos);
+        if let Some(keys) = self.regions.get_mut(&region) {
+            keys.remove(&pos);
+            if keys.is_empty() {
+                self.regions.remove(&region);
+            }
+        }
+
+        Some(entity)
     }
 
     /// Returns an iterator iterating over the loaded chunk keys.
     pub fn iter_keys(&self) -> impl Iterator<Item = &IVec3> {
-        self.0.keys()
+        self.entities.keys()
     }
 
     /// Return the number of loaded chunks.
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.entities.len()
     }
+
+    /// Returns the keys of the loaded chunks overlapping the sphere of the specified center and radius (in world units).
+    pub fn chunks_in_sphere(&self, center: Vec3, radius: f32) -> Vec<IVec3> {
+        let region_extent = (CHUNK_SIZE * CHUNK_INDEX_REGION_SIZE).as_vec3();
+        let min_region = Self::region_of((center - radius).floor().as_ivec3());
+        let max_region = Self::region_of((center + radius).floor().as_ivec3());
+
+        let mut keys = Vec::new();
+        for x in min_region.x..=max_region.x {
+            for y in min_region.y..=max_region.y {
+                for z in min_region.z..=max_region.z {
+                    let region = IVec3::new(x, y, z);
+                    let region_min = region.as_vec3() * region_extent;
+                    if distance_to_box(center, region_min, region_min + region_extent) > radius {
+                        continue;
+                    }
+
+                    if let Some(region_keys) = self.regions.get(&region) {
+                        keys.extend(region_keys.iter().copied().filter(|key| {
+                            distance_to_box(center, key.as_vec3(), (*key + CHUNK_SIZE).as_vec3())
+                                <= radius
+                        }));
+                    }
+                }
+            }
+        }
+
+        keys
+    }
+
+    /// Returns the key of the loaded chunk whose center is the closest to the specified world position.
+    pub fn nearest_loaded_chunk(&self, pos: Vec3) -> Option<IVec3> {
+        let region_extent = (CHUNK_SIZE * CHUNK_INDEX_REGION_SIZE).as_vec3();
+        let origin = Self::region_of(pos.floor().as_ivec3());
+        let chunk_center = |key: IVec3| key.as_vec3() + CHUNK_SIZE.as_vec3() / 2.0;
+
+        let mut nearest: Option<(f32, IVec3)> = None;
+        let mut visited = 0;
+
+        // visit the regions by growing shells around the region containing the position,
+        // until the remaining regions are all further than the nearest chunk found.
+        for shell in 0.. {
+            if visited == self.regions.len() {
+                break;
+            }
+            let shell_distance = (shell - 1).max(0) as f32 * region_extent.min_element();
+            if nearest.map_or(false, |(distance, _)| distance < shell_distance) {
+                break;
+            }
+
+            for x in -shell..=shell {
+                for y in -shell..=shell {
+                    for z in -shell..=shell {
+                        if x.abs().max(y.abs()).max(z.abs()) != shell {
+                            continue;
+                        }
+
+                        let region_keys = match self.regions.get(&(origin + IVec3::new(x, y, z))) {
+                            Some(region_keys) => region_keys,
+                            None => continue,
+                        };
+                        visited += 1;
+
+                        for key in region_keys {
+                            let distance = chunk_center(*key).distance(pos);
+                            if nearest.map_or(true, |(nearest, _)| distance < nearest) {
+                                nearest = Some((distance, *key));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        nearest.map(|(_, key)| key)
+    }
+
+    /// Returns the spatial index region containing the specified chunk key (or world position).
+    fn region_of(pos: IVec3) -> IVec3 {
+        IVec3::new(
+            pos.x.div_euclid(CHUNK_SIZE.x * CHUNK_INDEX_REGION_SIZE),
+            pos.y.div_euclid(CHUNK_SIZE.y * CHUNK_INDEX_REGION_SIZE),
+            pos.z.div_euclid(CHUNK_SIZE.z * CHUNK_INDEX_REGION_SIZE),
+        )
+    }
+}
+
+/// Returns the distance between a point and an axis aligned box, 0 if the point is inside of the box.
+fn distance_to_box(point: Vec3, min: Vec3, max: Vec3) -> f32 {
+    point.clamp(min, max).distance(point)
 }
 
 /// Holds the dirty chunk for the current frame.
 #[derive(Default)]
-pub struct DirtyChunks(HashSet<IVec3>);
+pub struct DirtyChunks {
+    dirty: HashSet<IVec3>,
+    /// The chunks whose voxels got replaced through [`DirtyChunks::set_voxel`], until drained.
+    edited: HashSet<IVec3>,
+    /// The voxels replaced through [`DirtyChunks::set_voxel`] for the current frame.
+    edited_voxels: Vec<IVec3>,
+}
 
 #[allow(dead_code)]
 impl DirtyChunks {
     pub fn mark_dirty(&mut self, chunk: IVec3) {
-        self.0.insert(chunk);
+        self.dirty.insert(chunk);
+    }
+
+    pub fn is_dirty(&self, chunk: IVec3) -> bool {
+        self.dirty.contains(&chunk)
     }
 
     pub fn iter_dirty(&self) -> impl Iterator<Item = &IVec3> {
-        self.0.iter()
+        self.dirty.iter()
     }
 
     pub fn num_dirty(&self) -> usize {
-        self.0.len()
+        self.dirty.len()
+    }
+
+    /// Iterates over the voxels replaced through [`DirtyChunks::set_voxel`] for the current frame.
+    pub fn iter_edited_voxels(&self) -> impl Iterator<Item = &IVec3> {
+        self.edited_voxels.iter()
+    }
+
+    /// Drains the chunks whose voxels got replaced since the last call, unlike the dirty chunks which also include
+    /// the generated chunks and the neighbors of the edits.
+    pub fn drain_edited(&mut self) -> impl Iterator<Item = IVec3> + '_ {
+        self.edited.drain()
+    }
+
+    /// Replaces a voxel of the loaded chunks and marks its chunk dirty, along with the loaded neighbors sharing the
+    /// faces or ambient occlusion of the voxel when it lies on a chunk border, see [`chunk_keys_around_voxel`].
+    /// Returns whether the voxel got replaced, like [`ChunkMap::set_voxel`].
+    pub fn set_voxel(
+        &mut self,
+        chunks: &mut ChunkMap<Voxel, ChunkShape>,
+        pos: IVec3,
+        voxel: Voxel,
+    ) -> bool {
+        if !chunks.set_voxel(pos, voxel) {
+            return false;
+        }
+
+        self.edited.insert(chunk_key_at(pos));
+        self.edited_voxels.push(pos);
+        chunk_keys_around_voxel(pos)
+            .filter(|key| chunks.exists(*key))
+            .for_each(|key| self.mark_dirty(key));
+        true
     }
 }
 
@@ -200,59 +486,359 @@ pub struct CurrentLocalPlayerChunk {
     pub world_pos: IVec3,
 }
 
+/// Shape of the area of chunks loaded around the player.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChunkLoadShape {
+    /// A box spanning the horizontal radius on the X and Z axes.
+    Square,
+    /// A circle of the horizontal radius on the XZ plane, loading ~21% less chunks than a square for the same view
+    /// distance.
+    Cylinder,
+    /// An ellipsoid of the horizontal and vertical radii, also dropping the chunks far above and below the player.
+    Sphere,
+}
+
+impl ChunkLoadShape {
+    pub const ALL: [ChunkLoadShape; 3] = [
+        ChunkLoadShape::Square,
+        ChunkLoadShape::Cylinder,
+        ChunkLoadShape::Sphere,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChunkLoadShape::Square => "square",
+            ChunkLoadShape::Cylinder => "cylinder",
+            ChunkLoadShape::Sphere => "sphere",
+        }
+    }
+}
+
 // Resource holding the view distance.
 pub struct ChunkLoadRadius {
+    /// Radius of the loaded area on the X and Z axes, in chunks.
     pub horizontal: i32,
+    /// Half height of the loaded area on the Y axis, in chunks.
     pub vertical: i32,
+    pub shape: ChunkLoadShape,
+}
+
+impl Default for ChunkLoadRadius {
+    fn default() -> Self {
+        Self {
+            horizontal: 16,
+            vertical: 6,
+            shape: ChunkLoadShape::Cylinder,
+        }
+    }
+}
+
+impl ChunkLoadRadius {
+    /// Returns whether a chunk offset from the player chunk (in chunks) lies within the loaded area of a shape.
+    pub fn contains_in(&self, shape: ChunkLoadShape, offset: IVec3) -> bool {
+        if offset.y.abs() > self.vertical {
+            return false;
+        }
+
+        match shape {
+            ChunkLoadShape::Square => {
+                offset.x.abs() < self.horizontal && offset.z.abs() < self.horizontal
+            }
+            ChunkLoadShape::Cylinder => offset.x.pow(2) + offset.z.pow(2) < self.horizontal.pow(2),
+            ChunkLoadShape::Sphere => {
+                // the half chunk keeps the topmost and bottommost layers from shrinking to a single chunk.
+                let horizontal = self.horizontal as f32;
+                let vertical = self.vertical as f32 + 0.5;
+                (offset.x.pow(2) + offset.z.pow(2)) as f32 / horizontal.powi(2)
+                    + offset.y.pow(2) as f32 / vertical.powi(2)
+                    < 1.0
+            }
+        }
+    }
+
+    /// Returns whether a chunk offset from the player chunk (in chunks) lies within the loaded area.
+    pub fn contains(&self, offset: IVec3) -> bool {
+        self.contains_in(self.shape, offset)
+    }
+
+    /// Returns the number of chunks in the loaded area of a shape, ignoring the bottom of the world.
+    pub fn chunk_count(&self, shape: ChunkLoadShape) -> usize {
+        let (horizontal, vertical) = (self.horizontal, self.vertical);
+        (-horizontal..=horizontal)
+            .flat_map(|x| (-horizontal..=horizontal).map(move |z| (x, z)))
+            .flat_map(|(x, z)| (-vertical..=vertical).map(move |y| IVec3::new(x, y, z)))
+            .filter(|offset| self.contains_in(shape, *offset))
+            .count()
+    }
+}
+
+/// Maximum number of chunk tasks spawned per frame, the remaining work is deferred to the next frames starting with the chunks closest to the player.
+/// This prevents frame spikes when lots of chunks get loaded at once (e.g. when the load radius changes).
+pub struct ChunkTaskBudget {
+    /// Maximum number of terrain generation tasks spawned per frame.
+    pub generation: usize,
+    /// Maximum number of meshing tasks spawned per frame.
+    #[cfg(not(feature = "headless"))]
+    pub meshing: usize,
+    /// Time spent applying the completed chunk meshes per frame, the remaining ones are applied during the next frames.
+    #[cfg(not(feature = "headless"))]
+    pub mesh_apply_time: Duration,
+}
+
+impl Default for ChunkTaskBudget {
+    fn default() -> Self {
+        Self {
+            generation: 32,
+            #[cfg(not(feature = "headless"))]
+            meshing: 32,
+            #[cfg(not(feature = "headless"))]
+            mesh_apply_time: Duration::from_millis(2),
+        }
+    }
+}
+
+/// Sorts chunk keys from the closest to the furthest from the specified chunk.
+pub(super) fn sort_by_distance(keys: &mut [IVec3], origin: IVec3) {
+    keys.sort_unstable_by_key(|key| {
+        let delta = *key - origin;
+        delta.dot(delta)
+    });
+}
+
+/// A component making the entity keep the chunk data around it loaded, without spawning chunk entities or meshing them.
+/// Useful for simulation-only regions or headless anchors.
+#[derive(Component)]
+#[allow(dead_code)]
+pub struct ChunkLoadAnchor {
+    /// Radius of the region kept loaded, in chunks.
+    pub radius: i32,
+}
+
+/// The chunks kept loaded by [`ChunkLoadAnchor`]s.
+#[derive(Default)]
+pub struct AnchoredChunks {
+    anchors: Vec<(IVec3, i32)>,
+    chunks: HashSet<IVec3>,
+}
+
+impl AnchoredChunks {
+    /// Returns the number of chunks kept loaded by anchors.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns whether the specified chunk is kept loaded by an anchor.
+    pub fn contains(&self, chunk: IVec3) -> bool {
+        self.chunks.contains(&chunk)
+    }
 }
 
 /// A queue tracking the creation / destroy commands for chunks.
+/// Chunk entities are created / destroyed along with their data, while data only chunks only have their data loaded.
 #[derive(Default)]
 pub struct ChunkCommandQueue {
     create: Vec<IVec3>,
     destroy: Vec<IVec3>,
+    load_data: Vec<IVec3>,
+    unload_data: Vec<IVec3>,
 }
 
 impl ChunkCommandQueue {
+    pub fn queue_load<'a>(&mut self, region: impl Iterator<Item = &'a IVec3>) {
+        self.create.extend(region);
+    }
+
     pub fn queue_unload<'a>(&mut self, region: impl Iterator<Item = &'a IVec3>) {
         self.destroy.extend(region);
     }
+
+    /// Queues loading the data of the specified chunks without attaching them an entity.
+    pub fn queue_data_load<'a>(&mut self, region: impl Iterator<Item = &'a IVec3>) {
+        self.load_data.extend(region);
+    }
+
+    /// Queues unloading the data of the specified chunks, which must not have an entity attached.
+    pub fn queue_data_unload<'a>(&mut self, region: impl Iterator<Item = &'a IVec3>) {
+        self.unload_data.extend(region);
+    }
+
+    /// Returns an iterator over the chunks whose data is about to be unloaded this frame.
+    pub fn pending_data_unloads(&self) -> impl Iterator<Item = &IVec3> {
+        self.unload_data.iter()
+    }
+
+    /// Drains the chunks requested to be loaded as data only.
+    pub fn drain_data_loads(&mut self) -> impl Iterator<Item = IVec3> + '_ {
+        self.load_data.drain(..)
+    }
 }
 
 impl Plugin for VoxelWorldChunkingPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.insert_resource::<ChunkLoadRadius>(ChunkLoadRadius {
-            horizontal: 16,
-            vertical: 6,
-        })
-        .init_resource::<ChunkEntities>()
-        .insert_resource(CurrentLocalPlayerChunk {
+        app.insert_resource(CurrentLocalPlayerChunk {
             chunk_min: IVec3::ZERO.into(),
             world_pos: IVec3::ZERO,
         })
+        .init_resource::<ChunkLoadRadius>()
+        .init_resource::<ChunkEntities>()
         .init_resource::<ChunkCommandQueue>()
         .init_resource::<DirtyChunks>()
-        .add_stage_after(
-            CoreStage::Update,
+        .init_resource::<ChunkTaskBudget>()
+        .init_resource::<AnchoredChunks>();
+
+        let view_chunks_system = update_view_chunks
+            .label(ChunkLoadingSystem::UpdateViewChunks)
+            .with_run_criteria(update_view_chunks_criteria);
+        let loading_systems = SystemSet::new()
+            .with_system(update_anchor_chunks.label(ChunkLoadingSystem::UpdateAnchorChunks))
+            .with_system(
+                create_chunks
+                    .label(ChunkLoadingSystem::CreateChunks)
+                    .after(ChunkLoadingSystem::UpdateViewChunks),
+            );
+        // without a local player (e.g. on a headless server), the chunks around the world origin get loaded.
+        #[cfg(not(feature = "headless"))]
+        let (view_chunks_system, loading_systems) = (
+            view_chunks_system.after(ChunkLoadingSystem::UpdatePlayerPos),
+            loading_systems
+                .with_system(update_player_pos.label(ChunkLoadingSystem::UpdatePlayerPos)),
+        );
+
+        app.add_system_set_to_stage(
             ChunkLoadingStage,
-            SystemStage::parallel()
-                .with_system(update_player_pos.label(ChunkLoadingSystem::UpdatePlayerPos))
-                .with_system(
-                    update_view_chunks
-                        .label(ChunkLoadingSystem::UpdateViewChunks)
-                        .after(ChunkLoadingSystem::UpdatePlayerPos)
-                        .with_run_criteria(update_view_chunks_criteria),
-                )
-                .with_system(
-                    create_chunks
-                        .label(ChunkLoadingSystem::CreateChunks)
-                        .after(ChunkLoadingSystem::UpdateViewChunks),
-                ),
+            loading_systems.with_system(view_chunks_system),
+        )
+        .add_system_to_stage(
+            CoreStage::Last,
+            destroy_chunks.label(ChunkLoadingSystem::DestroyChunks),
+        )
+        .add_system_to_stage(
+            CoreStage::Last,
+            unload_chunk_data
+                .label(ChunkLoadingSystem::UnloadChunkData)
+                .after(ChunkLoadingSystem::DestroyChunks),
         )
-        .add_system_to_stage(CoreStage::Last, destroy_chunks)
         .add_system_to_stage(
             CoreStage::Last,
-            clear_dirty_chunks.label(ChunkLoadingSystem::ClearDirtyChunks),
+            // the chunks edited while the simulation is paused stay dirty until it runs again.
+            clear_dirty_chunks
+                .label(ChunkLoadingSystem::ClearDirtyChunks)
+                .with_run_criteria(voxel_simulation_running),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the chunks of a 3x3x3 area of loaded chunks around the chunk at the origin.
+    fn loaded_chunks() -> ChunkMap<Voxel, ChunkShape> {
+        let mut chunks = ChunkMap::new(ChunkShape {});
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    chunks.insert_empty(IVec3::new(x, y, z) * CHUNK_SIZE);
+                }
+            }
+        }
+        chunks
+    }
+
+    fn dirty_keys(dirty_chunks: &DirtyChunks) -> HashSet<IVec3> {
+        dirty_chunks.iter_dirty().copied().collect()
+    }
+
+    #[test]
+    fn inner_voxel_marks_its_chunk() {
+        let mut chunks = loaded_chunks();
+        let mut dirty_chunks = DirtyChunks::default();
+
+        assert!(dirty_chunks.set_voxel(&mut chunks, IVec3::new(5, 6, 7), Voxel(1)));
+        assert_eq!(dirty_keys(&dirty_chunks), HashSet::from_iter([IVec3::ZERO]));
+        assert_eq!(chunks.voxel_at(IVec3::new(5, 6, 7)), Some(Voxel(1)));
+    }
+
+    #[test]
+    fn face_voxel_marks_one_neighbor() {
+        let mut chunks = loaded_chunks();
+        let mut dirty_chunks = DirtyChunks::default();
+
+        dirty_chunks.set_voxel(&mut chunks, IVec3::new(CHUNK_SIZE.x - 1, 6, 7), Voxel(1));
+        assert_eq!(
+            dirty_keys(&dirty_chunks),
+            HashSet::from_iter([IVec3::ZERO, IVec3::new(CHUNK_SIZE.x, 0, 0)])
+        );
+    }
+
+    #[test]
+    fn edge_voxel_marks_three_neighbors() {
+        let mut chunks = loaded_chunks();
+        let mut dirty_chunks = DirtyChunks::default();
+
+        dirty_chunks.set_voxel(&mut chunks, IVec3::new(0, 6, CHUNK_SIZE.z - 1), Voxel(1));
+        assert_eq!(
+            dirty_keys(&dirty_chunks),
+            HashSet::from_iter([
+                IVec3::ZERO,
+                IVec3::new(-CHUNK_SIZE.x, 0, 0),
+                IVec3::new(0, 0, CHUNK_SIZE.z),
+                IVec3::new(-CHUNK_SIZE.x, 0, CHUNK_SIZE.z),
+            ])
+        );
+    }
+
+    #[test]
+    fn corner_voxel_marks_seven_neighbors() {
+        let mut chunks = loaded_chunks();
+        let mut dirty_chunks = DirtyChunks::default();
+
+        dirty_chunks.set_voxel(&mut chunks, IVec3::new(0, CHUNK_SIZE.y - 1, 0), Voxel(1));
+        let expected: HashSet<IVec3> = [0, -1]
+            .into_iter()
+            .flat_map(|x| [0, 1].into_iter().map(move |y| (x, y)))
+            .flat_map(|(x, y)| [0, -1].into_iter().map(move |z| IVec3::new(x, y, z)))
+            .map(|offset| offset * CHUNK_SIZE)
+            .collect();
+        assert_eq!(expected.len(), 8);
+        assert_eq!(dirty_keys(&dirty_chunks), expected);
+    }
+
+    #[test]
+    fn unloaded_neighbors_arent_marked() {
+        let mut chunks = ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {});
+        chunks.insert_empty(IVec3::ZERO);
+        chunks.insert_empty(IVec3::new(-CHUNK_SIZE.x, 0, 0));
+        let mut dirty_chunks = DirtyChunks::default();
+
+        dirty_chunks.set_voxel(&mut chunks, IVec3::ZERO, Voxel(1));
+        assert_eq!(
+            dirty_keys(&dirty_chunks),
+            HashSet::from_iter([IVec3::ZERO, IVec3::new(-CHUNK_SIZE.x, 0, 0)])
+        );
+    }
+
+    #[test]
+    fn unloaded_voxel_marks_nothing() {
+        let mut chunks = loaded_chunks();
+        let mut dirty_chunks = DirtyChunks::default();
+
+        assert!(!dirty_chunks.set_voxel(&mut chunks, CHUNK_SIZE * 4, Voxel(1)));
+        assert_eq!(dirty_chunks.num_dirty(), 0);
+        assert_eq!(dirty_chunks.drain_edited().count(), 0);
+    }
+
+    #[test]
+    fn border_voxel_only_edits_its_chunk() {
+        let mut chunks = loaded_chunks();
+        let mut dirty_chunks = DirtyChunks::default();
+        dirty_chunks.mark_dirty(CHUNK_SIZE);
+
+        dirty_chunks.set_voxel(&mut chunks, IVec3::ZERO, Voxel(1));
+        assert_eq!(
+            dirty_chunks.drain_edited().collect::<Vec<_>>(),
+            vec![IVec3::ZERO]
         );
+        assert_eq!(dirty_chunks.drain_edited().count(), 0);
     }
 }
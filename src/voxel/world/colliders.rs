@@ -0,0 +1,310 @@
+use bevy::{
+    math::{IVec3, Quat, UVec3, Vec3},
+    prelude::{
+        Commands, Component, Entity, Or, ParallelSystemDescriptorCoercion, Plugin, Query, Res,
+        SystemLabel, With,
+    },
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+use bevy_rapier3d::prelude::{Collider, RigidBody};
+use futures_lite::future;
+
+use super::{
+    chunks::{ChunkEntities, CurrentLocalPlayerChunk, DirtyChunks},
+    stages::ChunkMeshingStage,
+    Chunk, ChunkShape, CHUNK_SIZE,
+};
+use crate::voxel::{
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    storage::{ChunkMap, VoxelBuffer, VoxelMetadataMap},
+    Voxel,
+};
+
+/// Settings of the chunk collider generation.
+pub struct ChunkColliderSettings {
+    /// Radius (in chunks) around the player in which chunks are given a collider.
+    pub radius: i32,
+}
+
+impl Default for ChunkColliderSettings {
+    fn default() -> Self {
+        Self { radius: 3 }
+    }
+}
+
+/// Builds the collider of a chunk by greedily merging its solid voxels into boxes, the partial voxels being merged into
+/// boxes of their height with the neighboring partial voxels of the same height and layer.
+/// Returns `None` for chunks without any solid voxel.
+fn chunk_collider(
+    buffer: &VoxelBuffer<Voxel, ChunkShape>,
+    solid: &[bool; 256],
+    partial_heights: &HashMap<UVec3, f32>,
+) -> Option<Collider> {
+    let size = CHUNK_SIZE.as_uvec3();
+    let index = |pos: UVec3| (pos.x + size.x * (pos.y + size.y * pos.z)) as usize;
+    let is_solid =
+        |pos: UVec3| solid[buffer.voxel_at(pos).0 as usize] && !partial_heights.contains_key(&pos);
+
+    let mut merged = vec![false; (size.x * size.y * size.z) as usize];
+    let mut boxes = Vec::new();
+
+    for y in 0..size.y {
+        for z in 0..size.z {
+            for x in 0..size.x {
+                let min = UVec3::new(x, y, z);
+                if merged[index(min)] || !is_solid(min) {
+                    continue;
+                }
+
+                let free = |pos: UVec3| !merged[index(pos)] && is_solid(pos);
+
+                // grow the box along X, then Z, then Y as long as the added voxels are solid and not merged yet.
+                let mut max = min;
+                while max.x + 1 < size.x && free(UVec3::new(max.x + 1, y, z)) {
+                    max.x += 1;
+                }
+                while max.z + 1 < size.z
+                    && (min.x..=max.x).all(|x| free(UVec3::new(x, y, max.z + 1)))
+                {
+                    max.z += 1;
+                }
+                while max.y + 1 < size.y
+                    && (min.z..=max.z)
+                        .all(|z| (min.x..=max.x).all(|x| free(UVec3::new(x, max.y + 1, z))))
+                {
+                    max.y += 1;
+                }
+
+                for y in min.y..=max.y {
+                    for z in min.z..=max.z {
+                        for x in min.x..=max.x {
+                            merged[index(UVec3::new(x, y, z))] = true;
+                        }
+                    }
+                }
+
+                let half_extents = (max - min + UVec3::ONE).as_vec3() / 2.0;
+                boxes.push((
+                    min.as_vec3() + half_extents,
+                    Quat::IDENTITY,
+                    Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+                ));
+            }
+        }
+    }
+
+    let mut partial: Vec<(UVec3, f32)> = partial_heights
+        .iter()
+        .map(|(pos, height)| (*pos, *height))
+        .collect();
+    partial.sort_unstable_by_key(|(pos, _)| (pos.y, pos.z, pos.x));
+    for (min, height) in partial {
+        if merged[index(min)] {
+            continue;
+        }
+
+        let free = |pos: UVec3| !merged[index(pos)] && partial_heights.get(&pos) == Some(&height);
+
+        let mut max = min;
+        while max.x + 1 < size.x && free(UVec3::new(max.x + 1, min.y, min.z)) {
+            max.x += 1;
+        }
+        while max.z + 1 < size.z && (min.x..=max.x).all(|x| free(UVec3::new(x, min.y, max.z + 1))) {
+            max.z += 1;
+        }
+
+        for z in min.z..=max.z {
+            for x in min.x..=max.x {
+                merged[index(UVec3::new(x, min.y, z))] = true;
+            }
+        }
+
+        let half_extents = (max - min + UVec3::ONE).as_vec3() * Vec3::new(0.5, height / 2.0, 0.5);
+        boxes.push((
+            min.as_vec3() + half_extents,
+            Quat::IDENTITY,
+            Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+        ));
+    }
+
+    if boxes.is_empty() {
+        None
+    } else {
+        Some(Collider::compound(boxes))
+    }
+}
+
+#[derive(Component)]
+pub struct ChunkColliderTask(Task<Option<Collider>>);
+
+/// Marks the chunks whose collider was generated but came out empty, so that it isn't generated again until modified.
+#[derive(Component)]
+pub struct EmptyChunkCollider;
+
+/// Queues the collider generation of the chunks around the player which were modified or don't have a collider yet.
+#[allow(clippy::too_many_arguments)]
+fn queue_collider_tasks(
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    settings: Res<ChunkColliderSettings>,
+    dirty_chunks: Res<DirtyChunks>,
+    chunk_entities: Res<ChunkEntities>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    metadata: Res<VoxelMetadataMap>,
+    materials: Res<VoxelMaterialRegistry>,
+    colliders: Query<
+        (
+            Option<&Collider>,
+            Option<&ChunkColliderTask>,
+            Option<&EmptyChunkCollider>,
+        ),
+        With<Chunk>,
+    >,
+    mut commands: Commands,
+) {
+    let task_pool = AsyncComputeTaskPool::get();
+
+    let mut solid = [false; 256];
+    materials
+        .iter_mats()
+        .enumerate()
+        .skip(1)
+        .for_each(|(id, material)| {
+            solid[id] = !material
+                .flags
+                .intersects(VoxelMaterialFlags::LIQUID | VoxelMaterialFlags::FOLIAGE);
+        });
+
+    let radius = settings.radius;
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                let key = player_chunk.chunk_min + IVec3::new(x, y, z) * CHUNK_SIZE;
+
+                let entity = match chunk_entities.entity(key) {
+                    Some(entity) => entity,
+                    None => continue,
+                };
+                let has_collider = match colliders.get(entity) {
+                    Ok((collider, task, empty)) => {
+                        collider.is_some() || task.is_some() || empty.is_some()
+                    }
+                    Err(_) => continue,
+                };
+                if has_collider && !dirty_chunks.is_dirty(key) {
+                    continue;
+                }
+
+                if let Some(buffer) = chunks.buffer_at(key) {
+                    // empty chunks have no collider, no need to copy their voxels.
+                    if buffer.occupancy().is_empty() {
+                        commands
+                            .entity(entity)
+                            .remove::<ChunkColliderTask>()
+                            .remove::<Collider>()
+                            .remove::<RigidBody>()
+                            .insert(EmptyChunkCollider);
+                        continue;
+                    }
+
+                    // the partial voxels get a collider matching their height (e.g. slabs, snow layers).
+                    let partial_heights: HashMap<UVec3, f32> = metadata
+                        .chunk(key)
+                        .into_iter()
+                        .flat_map(|chunk| chunk.0.iter())
+                        .filter(|(local, _)| solid[buffer.voxel_at(**local).0 as usize])
+                        .filter_map(|(local, voxel)| Some((*local, voxel.partial_height()?)))
+                        .collect();
+                    let buffer = buffer.clone();
+                    let task = task_pool
+                        .spawn(async move { chunk_collider(&buffer, &solid, &partial_heights) });
+                    commands.entity(entity).insert(ChunkColliderTask(task));
+                }
+            }
+        }
+    }
+}
+
+/// Attaches the generated colliders to their chunk entities.
+fn process_collider_tasks(
+    mut tasks: Query<(Entity, &mut ChunkColliderTask), With<Chunk>>,
+    mut commands: Commands,
+) {
+    tasks.for_each_mut(|(entity, mut task)| {
+        if let Some(collider) = future::block_on(future::poll_once(&mut task.0)) {
+            let mut entity = commands.entity(entity);
+            entity.remove::<ChunkColliderTask>();
+            match collider {
+                Some(collider) => entity
+                    .insert(collider)
+                    .insert(RigidBody::Fixed)
+                    .remove::<EmptyChunkCollider>(),
+                None => entity
+                    .remove::<Collider>()
+                    .remove::<RigidBody>()
+                    .insert(EmptyChunkCollider),
+            };
+        }
+    });
+}
+
+/// Removes the colliders (and their rigid bodies) of the chunks which got out of the collider radius.
+fn remove_distant_colliders(
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    settings: Res<ChunkColliderSettings>,
+    colliders: Query<
+        (Entity, &Chunk),
+        Or<(
+            With<Collider>,
+            With<ChunkColliderTask>,
+            With<EmptyChunkCollider>,
+        )>,
+    >,
+    mut commands: Commands,
+) {
+    if !player_chunk.is_changed() && !settings.is_changed() {
+        return;
+    }
+
+    let max_distance = CHUNK_SIZE * settings.radius;
+    for (entity, chunk) in colliders.iter() {
+        let delta = (chunk.0 - player_chunk.chunk_min).abs();
+        if delta.cmpgt(max_distance).any() {
+            commands
+                .entity(entity)
+                .remove::<Collider>()
+                .remove::<RigidBody>()
+                .remove::<ChunkColliderTask>()
+                .remove::<EmptyChunkCollider>();
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`ChunkCollidersPlugin`]
+pub enum ChunkColliderSystem {
+    /// Queues the collider generation tasks of the chunks around the player.
+    QueueColliderTasks,
+    /// Attaches the generated colliders to the chunk entities.
+    ProcessColliderTasks,
+}
+
+/// Generates physics colliders for the chunks around the player.
+pub struct ChunkCollidersPlugin;
+
+impl Plugin for ChunkCollidersPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkColliderSettings>()
+            .add_system_to_stage(
+                ChunkMeshingStage,
+                queue_collider_tasks.label(ChunkColliderSystem::QueueColliderTasks),
+            )
+            .add_system_to_stage(
+                ChunkMeshingStage,
+                process_collider_tasks
+                    .label(ChunkColliderSystem::ProcessColliderTasks)
+                    .after(ChunkColliderSystem::QueueColliderTasks),
+            )
+            .add_system_to_stage(ChunkMeshingStage, remove_distant_colliders);
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    math::{IVec2, IVec3, Vec3Swizzles},
+    prelude::{CoreStage, Local, ParallelSystemDescriptorCoercion, Plugin, Res, ResMut},
+    utils::{HashMap, HashSet},
+};
+
+use super::{
+    chunks::{ChunkCommandQueue, ChunkLoadingSystem, DirtyChunks},
+    lighting::LightingSystem,
+    stages::LightingStage,
+    ChunkShape, CHUNK_HEIGHT, CHUNK_LENGTH,
+};
+use crate::voxel::{
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    storage::{ChunkMap, VoxelBuffer},
+    Voxel,
+};
+
+/// World height of the columns without any loaded opaque voxel in [`ColumnHeightmaps::column_heights`].
+pub const COLUMN_NO_HEIGHT: i32 = i32::MIN;
+
+/// Returns whether each material blocks the sunlight, liquids and foliage letting it through like the empty voxel.
+fn opaque_materials(registry: &VoxelMaterialRegistry) -> [bool; 256] {
+    let mut opaque = [false; 256];
+    registry
+        .iter_mats()
+        .enumerate()
+        .skip(1)
+        .for_each(|(id, material)| {
+            opaque[id] = !material
+                .flags
+                .intersects(VoxelMaterialFlags::LIQUID | VoxelMaterialFlags::FOLIAGE);
+        });
+    opaque
+}
+
+/// Returns the local height (plus one) of the highest opaque voxel of each column of a chunk, 0 for columns without any.
+fn compute_chunk_heights(
+    buffer: &VoxelBuffer<Voxel, ChunkShape>,
+    opaque: &[bool; 256],
+) -> Box<[u8]> {
+    let mut heights = vec![0u8; (CHUNK_LENGTH * CHUNK_LENGTH) as usize];
+
+    for z in 0..CHUNK_LENGTH {
+        for x in 0..CHUNK_LENGTH {
+            heights[(z * CHUNK_LENGTH + x) as usize] = (0..CHUNK_HEIGHT)
+                .rev()
+                .find(|y| opaque[buffer.voxel_at([x, *y, z].into()).0 as usize])
+                .map_or(0, |y| y as u8 + 1);
+        }
+    }
+
+    heights.into_boxed_slice()
+}
+
+/// The height of the highest opaque voxel of each loaded voxel column, kept up to date as the chunks get generated,
+/// edited and unloaded so that the surface queries ("what's the top solid block at x,z") don't scan the voxels.
+#[derive(Default)]
+pub struct ColumnHeightmaps {
+    /// The local heights of the columns of each loaded chunk by stack and Y, see [`ColumnHeightmaps::chunk_heights`].
+    chunks: HashMap<IVec2, BTreeMap<i32, Box<[u8]>>>,
+    /// The world heights of the columns of each stack of loaded chunks, keyed by the X and Z of the chunk minimums.
+    columns: HashMap<IVec2, Box<[i32]>>,
+    /// The chunk stacks whose heights changed during the last update.
+    changed: HashSet<IVec2>,
+    /// The chunk stacks which lost a chunk since the last update.
+    unloaded: HashSet<IVec2>,
+}
+
+#[allow(dead_code)]
+impl ColumnHeightmaps {
+    /// Returns the world height of the top of the highest loaded opaque voxel of a column, `None` if there's none.
+    pub fn surface_height(&self, column: IVec2) -> Option<i32> {
+        let chunk_length = CHUNK_LENGTH as i32;
+        let stack = column & !IVec2::splat(chunk_length - 1);
+        let local = column - stack;
+        let height = self.columns.get(&stack)?[(local.y * chunk_length + local.x) as usize];
+        (height != COLUMN_NO_HEIGHT).then(|| height)
+    }
+
+    /// Returns the local height (plus one) of the highest opaque voxel of each column of a loaded chunk, in rows along
+    /// the X axis, 0 for columns without any.
+    pub fn chunk_heights(&self, key: IVec3) -> Option<&[u8]> {
+        self.chunks.get(&key.xz())?.get(&key.y).map(AsRef::as_ref)
+    }
+
+    /// Returns the world height of the top of the highest opaque voxel of each column of a stack of chunks, in rows
+    /// along the X axis, [`COLUMN_NO_HEIGHT`] for columns without any.
+    pub fn column_heights(&self, stack: IVec2) -> Option<&[i32]> {
+        self.columns.get(&stack).map(AsRef::as_ref)
+    }
+
+    /// Iterates over the chunk stacks whose heights changed during the last update.
+    #[cfg(not(feature = "headless"))]
+    pub fn iter_changed(&self) -> impl Iterator<Item = &IVec2> {
+        self.changed.iter()
+    }
+
+    /// Aggregates the heights of the loaded chunks of a stack, from the highest down.
+    fn update_stack(&mut self, stack: IVec2) {
+        let chunks = match self.chunks.get(&stack) {
+            Some(chunks) if !chunks.is_empty() => chunks,
+            _ => {
+                self.chunks.remove(&stack);
+                self.columns.remove(&stack);
+                return;
+            }
+        };
+
+        let mut heights = vec![COLUMN_NO_HEIGHT; (CHUNK_LENGTH * CHUNK_LENGTH) as usize];
+        for (y, chunk_heights) in chunks.iter().rev() {
+            heights
+                .iter_mut()
+                .zip(chunk_heights.iter())
+                .filter(|(height, local)| **height == COLUMN_NO_HEIGHT && **local > 0)
+                .for_each(|(height, local)| *height = y + *local as i32);
+
+            if heights.iter().all(|height| *height != COLUMN_NO_HEIGHT) {
+                break;
+            }
+        }
+
+        self.columns.insert(stack, heights.into_boxed_slice());
+    }
+}
+
+/// Recomputes the heights of the generated or modified chunks before their light gets propagated, and of every loaded
+/// chunk when the opacity of the materials changes.
+fn update_column_heightmaps(
+    mut heightmaps: ResMut<ColumnHeightmaps>,
+    dirty_chunks: Res<DirtyChunks>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    materials: Res<VoxelMaterialRegistry>,
+    mut previous_opaque: Local<Vec<bool>>,
+) {
+    let opaque = opaque_materials(&materials);
+    let heightmaps = &mut *heightmaps;
+    heightmaps.changed.clear();
+    let mut stacks = std::mem::take(&mut heightmaps.unloaded);
+
+    // the material editor keeps the registry changed, only material flag changes matter here.
+    let keys: Vec<IVec3> = if previous_opaque.as_slice() != opaque.as_slice() {
+        *previous_opaque = opaque.to_vec();
+        heightmaps.chunks.clear();
+        heightmaps.columns.clear();
+        chunks.iter_keys().collect()
+    } else {
+        dirty_chunks.iter_dirty().copied().collect()
+    };
+
+    for key in keys {
+        match chunks.buffer_at(key) {
+            Some(buffer) => {
+                heightmaps
+                    .chunks
+                    .entry(key.xz())
+                    .or_default()
+                    .insert(key.y, compute_chunk_heights(buffer, &opaque));
+            }
+            None => {
+                if let Some(stack) = heightmaps.chunks.get_mut(&key.xz()) {
+                    stack.remove(&key.y);
+                }
+            }
+        }
+        stacks.insert(key.xz());
+    }
+
+    for stack in stacks {
+        heightmaps.update_stack(stack);
+        heightmaps.changed.insert(stack);
+    }
+}
+
+/// Drops the heights of the chunks being unloaded, their stacks getting updated along with the next modifications.
+fn unload_chunk_heights(
+    chunk_command_queue: Res<ChunkCommandQueue>,
+    mut heightmaps: ResMut<ColumnHeightmaps>,
+) {
+    chunk_command_queue.pending_data_unloads().for_each(|key| {
+        let removed = heightmaps
+            .chunks
+            .get_mut(&key.xz())
+            .and_then(|stack| stack.remove(&key.y));
+        if removed.is_some() {
+            heightmaps.unloaded.insert(key.xz());
+        }
+    });
+}
+
+/// Maintains the [`ColumnHeightmaps`] shared by the sunlight propagation, the sky shadows and the surface queries.
+pub struct ColumnHeightmapsPlugin;
+
+impl Plugin for ColumnHeightmapsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ColumnHeightmaps>()
+            .add_system_to_stage(
+                LightingStage,
+                update_column_heightmaps.before(LightingSystem::PropagateLight),
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                unload_chunk_heights
+                    .after(ChunkLoadingSystem::DestroyChunks)
+                    .before(ChunkLoadingSystem::UnloadChunkData),
+            );
+    }
+}
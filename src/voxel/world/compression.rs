@@ -0,0 +1,74 @@
+use bevy::prelude::{CoreStage, Plugin, Res, ResMut};
+
+use super::{chunks::CurrentLocalPlayerChunk, ChunkShape, CHUNK_SIZE};
+use crate::voxel::{
+    storage::{ChunkMap, ChunkMemoryStats},
+    Light, Voxel,
+};
+
+/// Settings of the in-memory compression of the voxel and light data of the chunks.
+pub struct ChunkCompressionSettings {
+    pub enabled: bool,
+    /// Distance (in chunks) from the player within which the chunks are kept uncompressed, being read and edited often.
+    pub min_distance: i32,
+    /// Maximum number of chunk buffers compressed per frame.
+    pub max_per_frame: usize,
+}
+
+impl Default for ChunkCompressionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_distance: 2,
+            max_per_frame: 64,
+        }
+    }
+}
+
+/// Memory used by the voxel and light data of the loaded and cached chunks.
+#[derive(Default)]
+pub struct ChunkMemoryUsage {
+    pub voxels: ChunkMemoryStats,
+    pub light: ChunkMemoryStats,
+}
+
+impl ChunkMemoryUsage {
+    /// Returns the statistics of the voxel and light data combined.
+    pub fn total(&self) -> ChunkMemoryStats {
+        self.voxels + self.light
+    }
+}
+
+/// Compresses the buffers of the chunks away from the player, and of the chunks decompressed by a read since.
+fn compress_chunks(
+    settings: Res<ChunkCompressionSettings>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    mut voxels: ResMut<ChunkMap<Voxel, ChunkShape>>,
+    mut lights: ResMut<ChunkMap<Light, ChunkShape>>,
+    mut usage: ResMut<ChunkMemoryUsage>,
+) {
+    if settings.enabled {
+        let player_chunk = player_chunk.chunk_min;
+        let far =
+            |key| ((key - player_chunk) / CHUNK_SIZE).abs().max_element() > settings.min_distance;
+
+        // the voxels get the budget first, being the most of the chunk data.
+        let budget = settings.max_per_frame;
+        let compressed = voxels.compress_buffers(budget, far);
+        lights.compress_buffers(budget - compressed, far);
+    }
+
+    usage.voxels = voxels.memory_stats();
+    usage.light = lights.memory_stats();
+}
+
+/// Compresses the chunk data kept in memory with a palette and run-length encoding, to reduce the memory used at large load radii.
+pub struct ChunkCompressionPlugin;
+
+impl Plugin for ChunkCompressionPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkCompressionSettings>()
+            .init_resource::<ChunkMemoryUsage>()
+            .add_system_to_stage(CoreStage::Last, compress_chunks);
+    }
+}
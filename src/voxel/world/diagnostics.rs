@@ -0,0 +1,104 @@
+#[cfg(not(feature = "headless"))]
+use bevy::prelude::Query;
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics},
+    prelude::{CoreStage, Plugin, Res, ResMut},
+};
+
+#[cfg(not(feature = "headless"))]
+use super::meshing::{ChunkMeshingQueue, ChunkMeshingTask, CompletedChunkMeshes};
+use super::terrain::TerrainGenTasks;
+
+/// Number of samples the averages of the chunk diagnostics are computed over.
+const HISTORY_LENGTH: usize = 120;
+
+/// Time taken by a chunk generation task (or loading from the world save), in milliseconds.
+pub const CHUNK_GENERATION_TIME: DiagnosticId =
+    DiagnosticId::from_u128(0x3c2a6e4f_91d0_4b7e_8a15_6f02c9d4e7a1);
+/// Time taken by a chunk meshing task, in milliseconds.
+pub const CHUNK_MESHING_TIME: DiagnosticId =
+    DiagnosticId::from_u128(0x3c2a6e4f_91d0_4b7e_8a15_6f02c9d4e7a2);
+/// Number of chunks waiting for their generation task to be spawned.
+pub const CHUNK_GENERATION_QUEUE: DiagnosticId =
+    DiagnosticId::from_u128(0x3c2a6e4f_91d0_4b7e_8a15_6f02c9d4e7a3);
+/// Number of chunk generation tasks in flight.
+pub const CHUNK_GENERATION_TASKS: DiagnosticId =
+    DiagnosticId::from_u128(0x3c2a6e4f_91d0_4b7e_8a15_6f02c9d4e7a4);
+/// Number of chunks waiting for their meshing task to be spawned.
+pub const CHUNK_MESHING_QUEUE: DiagnosticId =
+    DiagnosticId::from_u128(0x3c2a6e4f_91d0_4b7e_8a15_6f02c9d4e7a5);
+/// Number of chunk meshing tasks in flight.
+pub const CHUNK_MESHING_TASKS: DiagnosticId =
+    DiagnosticId::from_u128(0x3c2a6e4f_91d0_4b7e_8a15_6f02c9d4e7a6);
+/// Number of completed chunk meshes waiting to be applied.
+pub const CHUNK_MESHES_PENDING: DiagnosticId =
+    DiagnosticId::from_u128(0x3c2a6e4f_91d0_4b7e_8a15_6f02c9d4e7a7);
+
+fn setup_chunk_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(
+        Diagnostic::new(
+            CHUNK_GENERATION_TIME,
+            "chunk_generation_time",
+            HISTORY_LENGTH,
+        )
+        .with_suffix("ms"),
+    );
+    diagnostics.add(
+        Diagnostic::new(CHUNK_MESHING_TIME, "chunk_meshing_time", HISTORY_LENGTH).with_suffix("ms"),
+    );
+    diagnostics.add(Diagnostic::new(
+        CHUNK_GENERATION_QUEUE,
+        "chunk_generation_queue",
+        HISTORY_LENGTH,
+    ));
+    diagnostics.add(Diagnostic::new(
+        CHUNK_GENERATION_TASKS,
+        "chunk_generation_tasks",
+        HISTORY_LENGTH,
+    ));
+    diagnostics.add(Diagnostic::new(
+        CHUNK_MESHING_QUEUE,
+        "chunk_meshing_queue",
+        HISTORY_LENGTH,
+    ));
+    diagnostics.add(Diagnostic::new(
+        CHUNK_MESHING_TASKS,
+        "chunk_meshing_tasks",
+        HISTORY_LENGTH,
+    ));
+    diagnostics.add(Diagnostic::new(
+        CHUNK_MESHES_PENDING,
+        "chunk_meshes_pending",
+        HISTORY_LENGTH,
+    ));
+}
+
+/// Records the lengths of the chunk queues and the number of tasks in flight, the task timings are recorded when the tasks complete.
+/// The meshing diagnostics are only recorded when the world gets rendered.
+fn record_chunk_queue_diagnostics(
+    mut diagnostics: ResMut<Diagnostics>,
+    gen_tasks: Res<TerrainGenTasks>,
+    #[cfg(not(feature = "headless"))] meshing_queue: Option<Res<ChunkMeshingQueue>>,
+    #[cfg(not(feature = "headless"))] completed_meshes: Option<Res<CompletedChunkMeshes>>,
+    #[cfg(not(feature = "headless"))] meshing_tasks: Query<&ChunkMeshingTask>,
+) {
+    diagnostics.add_measurement(CHUNK_GENERATION_QUEUE, gen_tasks.queued_len() as f64);
+    diagnostics.add_measurement(CHUNK_GENERATION_TASKS, gen_tasks.len() as f64);
+
+    #[cfg(not(feature = "headless"))]
+    if let (Some(meshing_queue), Some(completed_meshes)) = (meshing_queue, completed_meshes) {
+        diagnostics.add_measurement(CHUNK_MESHING_QUEUE, meshing_queue.len() as f64);
+        diagnostics.add_measurement(CHUNK_MESHING_TASKS, meshing_tasks.iter().count() as f64);
+        diagnostics.add_measurement(CHUNK_MESHES_PENDING, completed_meshes.len() as f64);
+    }
+}
+
+/// Registers diagnostics measuring the chunk generation and meshing pipeline.
+pub struct ChunkDiagnosticsPlugin;
+
+impl Plugin for ChunkDiagnosticsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_startup_system(setup_chunk_diagnostics)
+            .add_system_to_stage(CoreStage::Last, record_chunk_queue_diagnostics);
+    }
+}
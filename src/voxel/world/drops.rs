@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    math::{Quat, Vec3},
+    pbr::NotShadowCaster,
+    prelude::{
+        shape, Assets, Color, Commands, Component, Entity, EventReader, EventWriter, FromWorld,
+        GlobalTransform, Handle, Mesh, PbrBundle, Plugin, Query, Res, ResMut, StandardMaterial,
+        Transform, With, Without, World,
+    },
+    time::Time,
+    utils::HashMap,
+};
+
+use super::{origin::WorldOrigin, player::PlayerController, ChunkShape};
+use crate::voxel::{
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    net::VoxelBroken,
+    storage::ChunkMap,
+    Voxel,
+};
+
+/// Maximum number of drops spawned per frame, so that breaking lots of voxels at once (e.g. with the brush) doesn't flood
+/// the world with entities.
+const MAX_DROPS_PER_FRAME: usize = 16;
+/// Distance from the player at which the drops get picked up.
+const PICKUP_RADIUS: f32 = 1.5;
+/// Time before a freshly spawned drop can be picked up, in seconds.
+const PICKUP_DELAY: f32 = 0.5;
+/// Lifetime of the drops not picked up, in seconds.
+const DROP_LIFETIME: f32 = 300.0;
+const DROP_GRAVITY: f32 = -20.0;
+/// Rotation speed of the drops around the vertical axis, in radians per second.
+const DROP_SPIN: f32 = 1.5;
+
+/// The voxels picked up by the player, as counts per material.
+#[derive(Default)]
+pub struct VoxelInventory {
+    counts: BTreeMap<u8, u32>,
+}
+
+#[allow(dead_code)]
+impl VoxelInventory {
+    pub fn add(&mut self, voxel: Voxel, count: u32) {
+        *self.counts.entry(voxel.0).or_default() += count;
+    }
+
+    /// Removes up to `count` voxels of a material, returns how many were removed.
+    pub fn remove(&mut self, voxel: Voxel, count: u32) -> u32 {
+        let held = match self.counts.get_mut(&voxel.0) {
+            Some(held) => held,
+            None => return 0,
+        };
+
+        let removed = count.min(*held);
+        *held -= removed;
+        if *held == 0 {
+            self.counts.remove(&voxel.0);
+        }
+        removed
+    }
+
+    pub fn count(&self, voxel: Voxel) -> u32 {
+        self.counts.get(&voxel.0).copied().unwrap_or(0)
+    }
+
+    /// Iterates over the held materials and their counts, by material id.
+    pub fn iter(&self) -> impl Iterator<Item = (Voxel, u32)> + '_ {
+        self.counts
+            .iter()
+            .map(|(material, count)| (Voxel(*material), *count))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+}
+
+/// Event sent when the player picks up a drop.
+pub struct VoxelPickedUp {
+    pub voxel: Voxel,
+}
+
+/// A small spinning cube of a broken voxel, falling until resting on the terrain and waiting to be picked up.
+#[derive(Component)]
+pub struct VoxelDrop {
+    pub voxel: Voxel,
+    velocity: Vec3,
+    spawned_at: f32,
+}
+
+/// Shared mesh and per-material materials of the drops.
+struct VoxelDropAssets {
+    mesh: Handle<Mesh>,
+    materials: HashMap<u8, Handle<StandardMaterial>>,
+}
+
+impl FromWorld for VoxelDropAssets {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            mesh: world
+                .resource_mut::<Assets<Mesh>>()
+                .add(Mesh::from(shape::Cube { size: 0.25 })),
+            materials: Default::default(),
+        }
+    }
+}
+
+/// Spawns the drops of the broken voxels, liquids don't drop anything.
+fn spawn_voxel_drops(
+    mut broken_events: EventReader<VoxelBroken>,
+    registry: Res<VoxelMaterialRegistry>,
+    origin: Res<WorldOrigin>,
+    time: Res<Time>,
+    mut assets: ResMut<VoxelDropAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands,
+) {
+    let now = time.time_since_startup().as_secs_f32();
+
+    for broken in broken_events.iter().take(MAX_DROPS_PER_FRAME) {
+        let material = match registry.get_by_id(broken.voxel.0) {
+            Some(material) if !material.flags.contains(VoxelMaterialFlags::LIQUID) => material,
+            _ => continue,
+        };
+
+        let VoxelDropAssets {
+            mesh,
+            materials: drop_materials,
+        } = &mut *assets;
+        let drop_material = drop_materials
+            .entry(broken.voxel.0)
+            .or_insert_with(|| {
+                materials.add(StandardMaterial {
+                    base_color: material.base_color,
+                    emissive: material.emissive,
+                    ..Default::default()
+                })
+            })
+            .clone();
+
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: mesh.clone(),
+                material: drop_material,
+                transform: Transform::from_translation(
+                    origin.to_translation(broken.pos) + Vec3::splat(0.5),
+                ),
+                ..Default::default()
+            })
+            .insert(VoxelDrop {
+                voxel: broken.voxel,
+                velocity: Vec3::Y * 4.0,
+                spawned_at: now,
+            })
+            .insert(NotShadowCaster);
+    }
+}
+
+/// Makes the drops spin and fall until resting on the terrain, and despawns the expired ones.
+fn step_voxel_drops(
+    mut drops: Query<(Entity, &mut Transform, &mut VoxelDrop)>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    origin: Res<WorldOrigin>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let now = time.time_since_startup().as_secs_f32();
+    let delta = time.delta_seconds();
+
+    drops.for_each_mut(|(entity, mut transform, mut drop)| {
+        if now - drop.spawned_at >= DROP_LIFETIME {
+            commands.entity(entity).despawn();
+            return;
+        }
+
+        transform.rotate(Quat::from_rotation_y(DROP_SPIN * delta));
+
+        // the drop falls again once the voxel it rests on gets broken.
+        drop.velocity.y += DROP_GRAVITY * delta;
+        let next = transform.translation + drop.velocity * delta;
+        let below = next - Vec3::Y * 0.125;
+        match chunks.voxel_at(origin.voxel_at(below)) {
+            Some(Voxel::EMPTY_VOXEL) => transform.translation = next,
+            // drops in unloaded chunks stay put until the chunk gets loaded.
+            None => drop.velocity = Vec3::ZERO,
+            Some(_) => {
+                drop.velocity = Vec3::ZERO;
+                transform.translation.y = below.floor().y + 1.125;
+            }
+        }
+    });
+}
+
+/// Picks up the drops close to the player into the [`VoxelInventory`].
+fn pick_up_voxel_drops(
+    player: Query<&GlobalTransform, With<PlayerController>>,
+    drops: Query<(Entity, &Transform, &VoxelDrop), Without<PlayerController>>,
+    time: Res<Time>,
+    mut inventory: ResMut<VoxelInventory>,
+    mut picked_up_events: EventWriter<VoxelPickedUp>,
+    mut commands: Commands,
+) {
+    let player = match player.get_single() {
+        Ok(player) => player.translation(),
+        Err(_) => return,
+    };
+    let now = time.time_since_startup().as_secs_f32();
+
+    for (entity, transform, drop) in drops.iter() {
+        if now - drop.spawned_at < PICKUP_DELAY
+            || transform.translation.distance(player) > PICKUP_RADIUS
+        {
+            continue;
+        }
+
+        inventory.add(drop.voxel, 1);
+        picked_up_events.send(VoxelPickedUp { voxel: drop.voxel });
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Spawns drops of the voxels broken by the player, falling with simple physics until picked up into the
+/// [`VoxelInventory`].
+pub struct VoxelDropsPlugin;
+
+impl Plugin for VoxelDropsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<VoxelDropAssets>()
+            .init_resource::<VoxelInventory>()
+            .add_event::<VoxelPickedUp>()
+            .add_system(spawn_voxel_drops)
+            .add_system(step_voxel_drops)
+            .add_system(pick_up_voxel_drops);
+    }
+}
@@ -0,0 +1,324 @@
+use bevy::{
+    math::{IVec3, UVec3, Vec3},
+    prelude::{
+        BuildChildren, Color, Commands, Component, DespawnRecursiveExt, Entity, Local,
+        ParallelSystemDescriptorCoercion, Plugin, PointLight, PointLightBundle, Query, Res,
+        Transform,
+    },
+    time::Time,
+    utils::{HashMap, HashSet},
+};
+
+use super::{
+    chunks::{ChunkEntities, CurrentLocalPlayerChunk, DirtyChunks},
+    meshing::ChunkRenderingSystem,
+    stages::ChunkMeshingStage,
+    ChunkShape, CHUNK_SIZE,
+};
+use crate::voxel::{
+    material::{LightAnimation, VoxelMaterialRegistry},
+    storage::ChunkMap,
+    Light, Voxel,
+};
+
+/// Settings of the point lights cast by the emissive voxels of the chunks around the player, lighting the entities
+/// (drops, debris, the player) and adding a smooth glow on top of the voxel block light.
+pub struct EmissivePointLightSettings {
+    pub enabled: bool,
+    /// Distance (in chunks) from the player beyond which the chunks get no point lights.
+    pub max_distance: u32,
+    /// Size (in voxels) of the cells whose emissive voxels are merged into a single light.
+    pub cluster_size: u32,
+    /// Maximum number of lights per chunk, the clusters with the most emissive voxels being kept.
+    pub max_lights_per_chunk: usize,
+    /// Intensity (in lumens) of a cluster holding a single emissive voxel.
+    pub intensity: f32,
+}
+
+impl Default for EmissivePointLightSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_distance: 3,
+            cluster_size: 8,
+            max_lights_per_chunk: 8,
+            intensity: 200.0,
+        }
+    }
+}
+
+/// A point light of the emissive voxels, child of a chunk entity, animated like the block light of its material.
+#[derive(Component)]
+pub struct EmissiveVoxelLight {
+    /// Color of the light before its animation.
+    color: Color,
+    /// Intensity of the light before its animation.
+    intensity: f32,
+    /// Animation of the material of most of the voxels of the light, `None` for a steady light.
+    animation: Option<LightAnimation>,
+    /// Offset (in radians) of the flicker, so that neighboring lights flicker out of phase.
+    phase: f32,
+}
+
+/// The emissive voxels of a cluster.
+#[derive(Default)]
+struct EmissiveCluster {
+    position_sum: Vec3,
+    color_sum: Vec3,
+    reach: u8,
+    count: u32,
+    /// Number of voxels of each material of the cluster.
+    materials: HashMap<u8, u32>,
+}
+
+/// The light emitted by a material.
+#[derive(Clone, Copy)]
+struct MaterialEmission {
+    color: Color,
+    reach: u8,
+    animation: Option<LightAnimation>,
+}
+
+/// Returns the emission of each material, `None` for the materials emitting no light.
+fn material_emissions(registry: &VoxelMaterialRegistry) -> [Option<MaterialEmission>; 256] {
+    let mut emissions = [None; 256];
+    registry
+        .iter_mats()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, material)| material.emissive != Color::BLACK)
+        .for_each(|(id, material)| {
+            // same reach as the block light of the material.
+            let intensity = material
+                .emissive
+                .r()
+                .max(material.emissive.g())
+                .max(material.emissive.b())
+                .clamp(0.0, 1.0);
+            let reach = material
+                .light_reach
+                .unwrap_or_else(|| (intensity * Light::MAX_LEVEL as f32).round() as u8)
+                .min(Light::MAX_LEVEL);
+            if reach > 0 {
+                emissions[id] = Some(MaterialEmission {
+                    color: material.emissive,
+                    reach,
+                    animation: material.light_animation,
+                });
+            }
+        });
+    emissions
+}
+
+/// Spawns the point lights of the emissive voxels of a chunk, as children of the chunk entity.
+fn spawn_chunk_lights(
+    commands: &mut Commands,
+    chunk: Entity,
+    key: IVec3,
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    emissions: &[Option<MaterialEmission>; 256],
+    settings: &EmissivePointLightSettings,
+) -> Vec<Entity> {
+    let buffer = match chunks.buffer_at(key) {
+        Some(buffer) => buffer,
+        None => return Vec::new(),
+    };
+    if !buffer
+        .slice()
+        .iter()
+        .any(|voxel| emissions[voxel.0 as usize].is_some())
+    {
+        return Vec::new();
+    }
+
+    let cluster_size = settings.cluster_size.max(1);
+    let mut clusters: HashMap<UVec3, EmissiveCluster> = HashMap::default();
+    for x in 0..CHUNK_SIZE.x as u32 {
+        for y in 0..CHUNK_SIZE.y as u32 {
+            for z in 0..CHUNK_SIZE.z as u32 {
+                let local = UVec3::new(x, y, z);
+                let voxel = buffer.voxel_at(local);
+                let emission = match emissions[voxel.0 as usize] {
+                    Some(emission) => emission,
+                    None => continue,
+                };
+
+                let cluster = clusters.entry(local / cluster_size).or_default();
+                cluster.position_sum += local.as_vec3() + Vec3::splat(0.5);
+                cluster.color_sum +=
+                    Vec3::new(emission.color.r(), emission.color.g(), emission.color.b());
+                cluster.reach = cluster.reach.max(emission.reach);
+                cluster.count += 1;
+                *cluster.materials.entry(voxel.0).or_default() += 1;
+            }
+        }
+    }
+
+    let mut clusters: Vec<EmissiveCluster> = clusters.into_values().collect();
+    clusters.sort_unstable_by_key(|cluster| std::cmp::Reverse(cluster.count));
+    clusters.truncate(settings.max_lights_per_chunk);
+
+    clusters
+        .into_iter()
+        .map(|cluster| {
+            let count = cluster.count as f32;
+            let color = cluster.color_sum / count;
+            let color = Color::rgb(color.x, color.y, color.z);
+            // many emissive voxels make a brighter light, but not linearly so that lava lakes don't blind.
+            let intensity = settings.intensity * count.sqrt();
+            let position = key.as_vec3() + cluster.position_sum / count;
+            let animation = cluster
+                .materials
+                .iter()
+                .max_by_key(|(_, count)| **count)
+                .and_then(|(id, _)| emissions[*id as usize])
+                .and_then(|emission| emission.animation);
+
+            let light = commands
+                .spawn_bundle(PointLightBundle {
+                    point_light: PointLight {
+                        color,
+                        intensity,
+                        range: cluster.reach as f32,
+                        shadows_enabled: false,
+                        ..Default::default()
+                    },
+                    transform: Transform::from_translation(cluster.position_sum / count),
+                    ..Default::default()
+                })
+                .insert(EmissiveVoxelLight {
+                    color,
+                    intensity,
+                    animation,
+                    phase: flicker_phase(position),
+                })
+                .id();
+            commands.entity(chunk).add_child(light);
+            light
+        })
+        .collect()
+}
+
+/// Returns a pseudo random flicker phase (in radians) for a light at the specified world position.
+fn flicker_phase(position: Vec3) -> f32 {
+    let hash = (position.dot(Vec3::new(12.9898, 78.233, 37.719)).sin() * 43_758.547).fract();
+    hash.abs() * std::f32::consts::PI
+}
+
+fn despawn_lights(commands: &mut Commands, lights: Vec<Entity>) {
+    lights
+        .into_iter()
+        .for_each(|light| commands.entity(light).despawn_recursive());
+}
+
+/// Keeps the point lights of the emissive voxels of the chunks close to the player up to date, respawning the lights of
+/// the modified chunks and of the chunks getting close to the player, and despawning the lights of the chunks moving
+/// away.
+#[allow(clippy::too_many_arguments)]
+fn update_emissive_point_lights(
+    settings: Res<EmissivePointLightSettings>,
+    registry: Res<VoxelMaterialRegistry>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    dirty_chunks: Res<DirtyChunks>,
+    chunk_entities: Res<ChunkEntities>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    mut lit_chunks: Local<HashMap<IVec3, Vec<Entity>>>,
+    mut commands: Commands,
+) {
+    // the despawned chunks already took their lights with them.
+    lit_chunks.retain(|key, _| chunk_entities.entity(*key).is_some());
+
+    if settings.is_changed() || registry.is_changed() {
+        for (_, lights) in lit_chunks.drain() {
+            despawn_lights(&mut commands, lights);
+        }
+    }
+    if !settings.enabled {
+        return;
+    }
+
+    let in_range = |key: IVec3| {
+        ((key - player_chunk.chunk_min) / CHUNK_SIZE)
+            .abs()
+            .max_element() as u32
+            <= settings.max_distance
+    };
+
+    let out_of_range: Vec<IVec3> = lit_chunks
+        .keys()
+        .copied()
+        .filter(|key| !in_range(*key))
+        .collect();
+    for key in out_of_range {
+        let lights = lit_chunks.remove(&key).unwrap();
+        despawn_lights(&mut commands, lights);
+    }
+
+    let radius = settings.max_distance as i32;
+    let mut updated: HashSet<IVec3> = dirty_chunks
+        .iter_dirty()
+        .copied()
+        .filter(|key| in_range(*key))
+        .collect();
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                let key = player_chunk.chunk_min + IVec3::new(x, y, z) * CHUNK_SIZE;
+                if !lit_chunks.contains_key(&key) {
+                    updated.insert(key);
+                }
+            }
+        }
+    }
+
+    let emissions = material_emissions(&registry);
+    for key in updated {
+        // chunks without data yet get their lights once generated, which marks them dirty.
+        let chunk = match chunk_entities.entity(key) {
+            Some(chunk) if chunks.buffer_at(key).is_some() => chunk,
+            _ => continue,
+        };
+
+        if let Some(lights) = lit_chunks.remove(&key) {
+            despawn_lights(&mut commands, lights);
+        }
+        let lights = spawn_chunk_lights(&mut commands, chunk, key, &chunks, &emissions, &settings);
+        lit_chunks.insert(key, lights);
+    }
+}
+
+/// Animates the point lights of the emissive voxels like the block light of their material.
+fn animate_emissive_point_lights(
+    time: Res<Time>,
+    mut lights: Query<(&EmissiveVoxelLight, &mut PointLight)>,
+) {
+    let seconds = LightAnimation::seconds(&time);
+    for (light, mut point_light) in lights.iter_mut() {
+        let animation = match light.animation {
+            Some(animation) => animation,
+            None => continue,
+        };
+
+        let drift = animation.color_at(seconds);
+        point_light.color = Color::rgb(
+            light.color.r() * drift.r(),
+            light.color.g() * drift.g(),
+            light.color.b() * drift.b(),
+        );
+        point_light.intensity = light.intensity * animation.flicker_at(seconds, light.phase);
+    }
+}
+
+/// Casts point lights from the emissive voxels of the chunks around the player.
+pub struct EmissivePointLightsPlugin;
+
+impl Plugin for EmissivePointLightsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<EmissivePointLightSettings>()
+            .add_system_to_stage(
+                ChunkMeshingStage,
+                update_emissive_point_lights.after(ChunkRenderingSystem::ProcessMeshTasks),
+            )
+            .add_system(animate_emissive_point_lights);
+    }
+}
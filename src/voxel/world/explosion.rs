@@ -0,0 +1,325 @@
+use bevy::{
+    math::{IVec3, Vec3},
+    prelude::{
+        info, shape, Assets, Color, Commands, Component, Entity, EventReader, EventWriter,
+        FromWorld, Handle, Local, Mesh, ParallelSystemDescriptorCoercion, PbrBundle, Plugin, Query,
+        Res, ResMut, StandardMaterial, SystemLabel, Transform, With, World,
+    },
+    time::Time,
+    utils::{Duration, HashMap, HashSet, Instant},
+};
+
+use super::{
+    chunk_key_at,
+    chunks::{ChunkEntities, DirtyChunks},
+    meshing::{ChunkMeshingQueue, ChunkMeshingTask, CompletedChunkMeshes},
+    origin::WorldOrigin,
+    ChunkShape, CHUNK_SIZE,
+};
+use crate::voxel::{
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    net::{NetworkVoxelEdit, ReplicationSystem},
+    storage::ChunkMap,
+    Voxel,
+};
+
+/// Largest radius of an explosion, in voxels.
+pub const MAX_EXPLOSION_RADIUS: f32 = 24.0;
+/// Fraction of the explosion radius always carved out, the voxels of the shell beyond it being removed with a chance
+/// falling off toward the edge, so that the crater edges are ragged.
+const EXPLOSION_CORE: f32 = 0.6;
+/// Maximum number of debris particles spawned by an explosion.
+const MAX_DEBRIS_PER_EXPLOSION: usize = 48;
+/// Lifetime of the debris particles, in seconds.
+const DEBRIS_LIFETIME: f32 = 4.0;
+const DEBRIS_GRAVITY: f32 = -24.0;
+
+/// Event blowing up the voxels in a noisy sphere around a voxel, spawning debris of the removed voxels.
+#[derive(Clone, Copy, Debug)]
+pub struct Explosion {
+    pub center: IVec3,
+    /// Radius of the crater in voxels, up to [`MAX_EXPLOSION_RADIUS`].
+    pub radius: f32,
+}
+
+/// Returns a pseudo random value in the `[0; 1]` range for a voxel of an explosion.
+fn voxel_noise(pos: IVec3, seed: u32) -> f32 {
+    let mut hash = (pos.x as u32).wrapping_mul(0x8da6_b343)
+        ^ (pos.y as u32).wrapping_mul(0xd816_3841)
+        ^ (pos.z as u32).wrapping_mul(0xcb1a_b31f)
+        ^ seed;
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0x5bd1_e995);
+    hash ^= hash >> 15;
+    hash as f32 / u32::MAX as f32
+}
+
+/// Returns the voxels removed by an explosion, chunk by chunk. Liquids aren't blown away.
+pub fn explosion_voxels(
+    explosion: &Explosion,
+    seed: u32,
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    registry: &VoxelMaterialRegistry,
+) -> Vec<(IVec3, Voxel)> {
+    let radius = explosion.radius.min(MAX_EXPLOSION_RADIUS);
+    if radius <= 0.0 {
+        return Vec::new();
+    }
+
+    let extent = IVec3::splat(radius.ceil() as i32);
+    let (min, max) = (explosion.center - extent, explosion.center + extent);
+
+    let mut removed = Vec::new();
+    let (min_key, max_key) = (chunk_key_at(min), chunk_key_at(max));
+    for key_x in (min_key.x..=max_key.x).step_by(CHUNK_SIZE.x as usize) {
+        for key_y in (min_key.y..=max_key.y).step_by(CHUNK_SIZE.y as usize) {
+            for key_z in (min_key.z..=max_key.z).step_by(CHUNK_SIZE.z as usize) {
+                let key = IVec3::new(key_x, key_y, key_z);
+                let buffer = match chunks.buffer_at(key) {
+                    Some(buffer) => buffer,
+                    None => continue,
+                };
+
+                let chunk_min = min.max(key);
+                let chunk_max = max.min(key + CHUNK_SIZE - IVec3::ONE);
+                for x in chunk_min.x..=chunk_max.x {
+                    for y in chunk_min.y..=chunk_max.y {
+                        for z in chunk_min.z..=chunk_max.z {
+                            let pos = IVec3::new(x, y, z);
+                            let distance = (pos - explosion.center).as_vec3().length() / radius;
+                            let falloff = (distance - EXPLOSION_CORE) / (1.0 - EXPLOSION_CORE);
+                            if distance > 1.0 || (falloff > 0.0 && voxel_noise(pos, seed) < falloff)
+                            {
+                                continue;
+                            }
+
+                            let voxel = buffer.voxel_at((pos - key).as_uvec3());
+                            let liquid = registry.get_by_id(voxel.0).map_or(false, |material| {
+                                material.flags.contains(VoxelMaterialFlags::LIQUID)
+                            });
+                            if voxel != Voxel::EMPTY_VOXEL && !liquid {
+                                removed.push((pos, voxel));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    removed
+}
+
+/// A piece of debris flying out of an explosion.
+#[derive(Component)]
+pub struct ExplosionDebris {
+    velocity: Vec3,
+    expires_at: f32,
+}
+
+/// Shared mesh and per-material materials of the debris.
+struct ExplosionDebrisAssets {
+    mesh: Handle<Mesh>,
+    materials: HashMap<u8, Handle<StandardMaterial>>,
+}
+
+impl FromWorld for ExplosionDebrisAssets {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            mesh: world
+                .resource_mut::<Assets<Mesh>>()
+                .add(Mesh::from(shape::Cube { size: 0.35 })),
+            materials: Default::default(),
+        }
+    }
+}
+
+/// Time taken for the chunks affected by explosions to get remeshed, a stress test of the multi-chunk remeshing.
+#[derive(Default)]
+pub struct ExplosionRemeshLatency {
+    /// The chunks of the explosions not fully remeshed yet, with the time of the explosion.
+    pending: Vec<(Instant, HashSet<IVec3>)>,
+    /// Latency of the last explosion whose chunks all got remeshed.
+    pub last: Option<Duration>,
+}
+
+/// Carves the craters of the explosions through voxel edits, and spawns their debris.
+#[allow(clippy::too_many_arguments)]
+fn explode(
+    mut explosions: EventReader<Explosion>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    registry: Res<VoxelMaterialRegistry>,
+    origin: Res<WorldOrigin>,
+    time: Res<Time>,
+    mut assets: ResMut<ExplosionDebrisAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut latency: ResMut<ExplosionRemeshLatency>,
+    mut edits: EventWriter<NetworkVoxelEdit>,
+    mut seed: Local<u32>,
+    mut commands: Commands,
+) {
+    let now = time.time_since_startup().as_secs_f32();
+
+    for explosion in explosions.iter() {
+        *seed = seed.wrapping_add(0x9e37_79b9);
+        let removed = explosion_voxels(explosion, *seed, &chunks, &registry);
+        if removed.is_empty() {
+            continue;
+        }
+
+        latency.pending.push((
+            Instant::now(),
+            removed.iter().map(|(pos, _)| chunk_key_at(*pos)).collect(),
+        ));
+
+        // the debris are picked evenly among the removed voxels.
+        let step = (removed.len() / MAX_DEBRIS_PER_EXPLOSION).max(1);
+        for (index, (pos, voxel)) in removed
+            .iter()
+            .step_by(step)
+            .take(MAX_DEBRIS_PER_EXPLOSION)
+            .enumerate()
+        {
+            let ExplosionDebrisAssets {
+                mesh,
+                materials: debris_materials,
+            } = &mut *assets;
+            let material = debris_materials
+                .entry(voxel.0)
+                .or_insert_with(|| {
+                    materials.add(StandardMaterial {
+                        base_color: registry
+                            .get_by_id(voxel.0)
+                            .map_or(Color::GRAY, |material| material.base_color),
+                        ..Default::default()
+                    })
+                })
+                .clone();
+
+            let outward = (*pos - explosion.center).as_vec3().normalize_or_zero();
+            let jitter = voxel_noise(*pos, *seed ^ index as u32);
+            commands
+                .spawn_bundle(PbrBundle {
+                    mesh: mesh.clone(),
+                    material,
+                    transform: Transform::from_translation(
+                        origin.to_translation(*pos) + Vec3::splat(0.5),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ExplosionDebris {
+                    velocity: (outward + Vec3::Y) * (6.0 + 10.0 * jitter),
+                    expires_at: now + DEBRIS_LIFETIME * (0.5 + 0.5 * jitter),
+                });
+        }
+
+        info!(
+            "Explosion at {} blew up {} voxels",
+            explosion.center,
+            removed.len()
+        );
+        edits.send_batch(removed.into_iter().map(|(pos, _)| NetworkVoxelEdit {
+            pos,
+            voxel: Voxel::EMPTY_VOXEL,
+            journaled: true,
+            client: None,
+        }));
+    }
+}
+
+/// Moves the debris under gravity, stopping them on the terrain, and despawns the expired ones.
+fn step_explosion_debris(
+    mut debris: Query<(Entity, &mut Transform, &mut ExplosionDebris)>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    origin: Res<WorldOrigin>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    let now = time.time_since_startup().as_secs_f32();
+    let delta = time.delta_seconds();
+
+    debris.for_each_mut(|(entity, mut transform, mut debris)| {
+        if now >= debris.expires_at {
+            commands.entity(entity).despawn();
+            return;
+        }
+
+        if debris.velocity == Vec3::ZERO {
+            return;
+        }
+
+        debris.velocity.y += DEBRIS_GRAVITY * delta;
+        let next = transform.translation + debris.velocity * delta;
+        match chunks.voxel_at(origin.voxel_at(next)) {
+            Some(Voxel::EMPTY_VOXEL) | None => transform.translation = next,
+            Some(_) => debris.velocity = Vec3::ZERO,
+        }
+    });
+}
+
+/// Logs the time taken to remesh the chunks of the explosions, once none of them waits for a remesh anymore.
+fn track_explosion_remesh_latency(
+    mut latency: ResMut<ExplosionRemeshLatency>,
+    dirty_chunks: Res<DirtyChunks>,
+    queue: Res<ChunkMeshingQueue>,
+    completed: Res<CompletedChunkMeshes>,
+    chunk_entities: Res<ChunkEntities>,
+    tasks: Query<(), With<ChunkMeshingTask>>,
+) {
+    if latency.pending.is_empty() {
+        return;
+    }
+
+    let remeshing = |key: IVec3| {
+        dirty_chunks.is_dirty(key)
+            || queue.contains(key)
+            || chunk_entities.entity(key).map_or(false, |entity| {
+                tasks.get(entity).is_ok() || completed.contains(entity)
+            })
+    };
+
+    let mut finished = None;
+    latency.pending.retain_mut(|(started, keys)| {
+        keys.retain(|key| remeshing(*key));
+        if keys.is_empty() {
+            finished = Some(started.elapsed());
+        }
+        !keys.is_empty()
+    });
+
+    if let Some(elapsed) = finished {
+        info!(
+            "Explosion chunks remeshed in {:.1}ms",
+            elapsed.as_secs_f64() * 1000.0
+        );
+        latency.last = Some(elapsed);
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`ExplosionPlugin`]
+pub enum ExplosionSystem {
+    /// Turns the explosions into voxel edits, applied during the same frame, and spawns their debris.
+    Explode,
+}
+
+/// Explosions carving ragged craters into the terrain, and the tracking of how long their chunks take to get remeshed.
+pub struct ExplosionPlugin;
+
+impl Plugin for ExplosionPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ExplosionDebrisAssets>()
+            .init_resource::<ExplosionRemeshLatency>()
+            .add_event::<Explosion>()
+            .add_system(
+                explode
+                    .label(ExplosionSystem::Explode)
+                    .before(ReplicationSystem::ApplyVoxelEdits),
+            )
+            .add_system(step_explosion_debris)
+            .add_system_to_stage(
+                bevy::prelude::CoreStage::Last,
+                track_explosion_remesh_latency,
+            );
+    }
+}
@@ -0,0 +1,440 @@
+#[cfg(not(feature = "headless"))]
+use bevy::prelude::{
+    default, AlphaMode, Assets, Commands, DespawnRecursiveExt, Entity, Handle, Mesh, PbrBundle,
+    StandardMaterial, Transform,
+};
+use bevy::{
+    math::IVec3,
+    prelude::{Local, ParallelSystemDescriptorCoercion, Plugin, Res, ResMut, Time},
+    utils::{HashMap, HashSet},
+};
+
+use super::{
+    chunk_key_at,
+    chunks::{sort_by_distance, CurrentLocalPlayerChunk, DirtyChunks},
+    lighting::LightUpdates,
+    stages::{ChunkMeshingStage, TerrainGenStage, VoxelSimulationPause},
+    terrain::TerrainGenSystem,
+    ChunkShape, CHUNK_SIZE,
+};
+#[cfg(not(feature = "headless"))]
+use super::{chunks::ChunkEntities, origin::WorldOrigin, PaddedChunkShape};
+use crate::voxel::{material::VoxelMaterialRegistry, storage::ChunkMap, Voxel};
+#[cfg(not(feature = "headless"))]
+use crate::voxel::{render::mesh_fluid_voxels, storage::VoxelBuffer};
+
+/// Fluid level of the fluid sources (e.g. generated water), flowing fluid voxels have lower levels.
+pub const MAX_FLUID_LEVEL: u8 = 8;
+
+const HORIZONTAL_DIRECTIONS: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+/// Settings of the fluid simulation, a cellular automaton making fluid voxels fall and spread around their sources.
+/// The materials with a [`crate::voxel::material::MaterialRegistryInfo::flow_interval`] are simulated, each stepping at
+/// its own interval.
+pub struct FluidSettings {
+    pub enabled: bool,
+    /// Maximum number of chunks simulated per step of a fluid, the chunks closest to the player first.
+    pub max_chunks_per_step: usize,
+}
+
+impl Default for FluidSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_chunks_per_step: 8,
+        }
+    }
+}
+
+/// The levels of the flowing fluid voxels, grouped by chunk.
+/// Fluid voxels without a level are sources, flowing levels aren't saved so flowing fluid comes back as sources once reloaded from a world save.
+#[derive(Default)]
+pub struct FluidLevels(HashMap<IVec3, HashMap<IVec3, u8>>);
+
+#[allow(dead_code)]
+impl FluidLevels {
+    /// Returns the level of the flowing fluid voxel at the specified position, `None` for sources and non fluid voxels.
+    pub fn level_at(&self, pos: IVec3) -> Option<u8> {
+        self.0
+            .get(&chunk_key_at(pos))
+            .and_then(|levels| levels.get(&pos))
+            .copied()
+    }
+
+    /// Sets the level of the flowing fluid voxel at the specified position, `None` making it a source (or a non fluid voxel).
+    pub fn set_level(&mut self, pos: IVec3, level: Option<u8>) {
+        let key = chunk_key_at(pos);
+        match level {
+            Some(level) => {
+                self.0.entry(key).or_default().insert(pos, level);
+            }
+            None => {
+                if let Some(levels) = self.0.get_mut(&key) {
+                    levels.remove(&pos);
+                    if levels.is_empty() {
+                        self.0.remove(&key);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns an iterator over the positions and levels of the flowing fluid voxels of a chunk.
+    pub fn iter_chunk(&self, key: IVec3) -> impl Iterator<Item = (IVec3, u8)> + '_ {
+        self.0
+            .get(&key)
+            .into_iter()
+            .flat_map(|levels| levels.iter().map(|(pos, level)| (*pos, *level)))
+    }
+
+    /// Empties the flowing fluid voxels of a padded chunk buffer, which are meshed separately with their partial height.
+    #[cfg(not(feature = "headless"))]
+    pub fn clear_flowing_voxels(
+        &self,
+        key: IVec3,
+        padded: &mut VoxelBuffer<Voxel, PaddedChunkShape>,
+    ) {
+        let padded_max = CHUNK_SIZE + IVec3::ONE;
+
+        for neighbor in (-1..=1)
+            .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z))))
+        {
+            for (pos, _) in self.iter_chunk(key + neighbor * CHUNK_SIZE) {
+                let local = pos - key + IVec3::ONE;
+                if local.cmpge(IVec3::ZERO).all() && local.cmple(padded_max).all() {
+                    *padded.voxel_at_mut(local.as_uvec3()) = Voxel::EMPTY_VOXEL;
+                }
+            }
+        }
+    }
+}
+
+/// The chunks whose fluid voxels may flow, by fluid material.
+#[derive(Default)]
+pub struct ActiveFluidChunks(HashMap<u8, HashSet<IVec3>>);
+
+/// Returns the voxels of the materials which flow, along with their flow interval.
+fn flowing_materials(registry: &VoxelMaterialRegistry) -> impl Iterator<Item = (Voxel, f32)> + '_ {
+    registry
+        .iter_mats()
+        .enumerate()
+        .filter_map(|(id, material)| Some((Voxel(id as u8), material.flow_interval?)))
+}
+
+/// Activates the fluid simulation of the modified chunks.
+fn activate_fluid_chunks(
+    dirty_chunks: Res<DirtyChunks>,
+    registry: Res<VoxelMaterialRegistry>,
+    mut active_chunks: ResMut<ActiveFluidChunks>,
+) {
+    for (fluid, _) in flowing_materials(&registry) {
+        active_chunks
+            .0
+            .entry(fluid.0)
+            .or_default()
+            .extend(dirty_chunks.iter_dirty().copied());
+    }
+}
+
+/// Returns the fluid level of a voxel: the level of the flowing fluid voxels, [`MAX_FLUID_LEVEL`] for sources and 0 for empty voxels.
+/// Returns `None` for the other voxels and the unloaded ones, which block the fluid.
+fn fluid_level(
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    levels: &FluidLevels,
+    fluid: Voxel,
+    pos: IVec3,
+) -> Option<u8> {
+    match chunks.voxel_at(pos)? {
+        Voxel::EMPTY_VOXEL => Some(0),
+        voxel if voxel == fluid => Some(levels.level_at(pos).unwrap_or(MAX_FLUID_LEVEL)),
+        _ => None,
+    }
+}
+
+/// Returns the level a voxel receives from its neighbors: fluid falls down at its almost full level,
+/// and spreads sideways losing a level per voxel when resting on something.
+fn inflow_level(
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    levels: &FluidLevels,
+    fluid: Voxel,
+    pos: IVec3,
+) -> u8 {
+    if fluid_level(chunks, levels, fluid, pos + IVec3::Y).unwrap_or(0) > 0 {
+        return MAX_FLUID_LEVEL - 1;
+    }
+
+    HORIZONTAL_DIRECTIONS
+        .iter()
+        .map(|direction| pos + *direction)
+        .filter(|neighbor| fluid_level(chunks, levels, fluid, *neighbor - IVec3::Y) != Some(0))
+        .filter_map(|neighbor| fluid_level(chunks, levels, fluid, neighbor))
+        .max()
+        .map_or(0, |level| level.saturating_sub(1))
+}
+
+/// Steps the simulation of a fluid in its active chunks closest to the player.
+/// Chunks whose fluid didn't change are deactivated until modified again.
+#[allow(clippy::too_many_arguments)]
+fn step_fluid(
+    fluid: Voxel,
+    max_chunks: usize,
+    player_chunk: IVec3,
+    chunks: &mut ChunkMap<Voxel, ChunkShape>,
+    levels: &mut FluidLevels,
+    active_chunks: &mut HashSet<IVec3>,
+    dirty_chunks: &mut DirtyChunks,
+    light_updates: &mut LightUpdates,
+) {
+    active_chunks.retain(|key| chunks.exists(*key));
+    let mut keys: Vec<IVec3> = active_chunks.iter().copied().collect();
+    sort_by_distance(&mut keys, player_chunk);
+    keys.truncate(max_chunks);
+
+    let mut changes = Vec::new();
+
+    for key in keys {
+        active_chunks.remove(&key);
+
+        // fluid can only flow in chunks holding some, or bordering a chunk holding some.
+        let has_fluid = [IVec3::ZERO, IVec3::Y]
+            .into_iter()
+            .chain(HORIZONTAL_DIRECTIONS)
+            .filter_map(|direction| chunks.buffer_at(key + direction * CHUNK_SIZE))
+            .any(|buffer| buffer.slice().contains(&fluid));
+        if !has_fluid {
+            continue;
+        }
+
+        for x in 0..CHUNK_SIZE.x {
+            for y in 0..CHUNK_SIZE.y {
+                for z in 0..CHUNK_SIZE.z {
+                    let pos = key + IVec3::new(x, y, z);
+                    let level = match fluid_level(chunks, levels, fluid, pos) {
+                        Some(level) if level < MAX_FLUID_LEVEL => level,
+                        _ => continue,
+                    };
+
+                    let inflow = inflow_level(chunks, levels, fluid, pos);
+                    if inflow != level {
+                        changes.push((pos, inflow));
+                    }
+                }
+            }
+        }
+    }
+
+    for (pos, level) in changes {
+        match level {
+            0 => {
+                dirty_chunks.set_voxel(chunks, pos, Voxel::EMPTY_VOXEL);
+                levels.set_level(pos, None);
+            }
+            level => {
+                dirty_chunks.set_voxel(chunks, pos, fluid);
+                levels.set_level(pos, Some(level));
+            }
+        }
+
+        light_updates.queue(pos);
+        // the change may let the fluid flow further, possibly in a neighboring chunk.
+        for direction in [IVec3::Y, IVec3::NEG_Y]
+            .into_iter()
+            .chain(HORIZONTAL_DIRECTIONS)
+        {
+            active_chunks.insert(chunk_key_at(pos + direction));
+        }
+    }
+}
+
+/// Steps the simulation of each fluid at the flow interval of its material, or once per step of the paused simulation.
+#[allow(clippy::too_many_arguments)]
+fn simulate_fluids(
+    settings: Res<FluidSettings>,
+    time: Res<Time>,
+    pause: Res<VoxelSimulationPause>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    registry: Res<VoxelMaterialRegistry>,
+    mut chunks: ResMut<ChunkMap<Voxel, ChunkShape>>,
+    mut levels: ResMut<FluidLevels>,
+    mut active_chunks: ResMut<ActiveFluidChunks>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    mut light_updates: ResMut<LightUpdates>,
+    mut elapsed: Local<HashMap<u8, f32>>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    let fluids: Vec<(Voxel, f32)> = flowing_materials(&registry).collect();
+    elapsed.retain(|id, _| fluids.iter().any(|(fluid, _)| fluid.0 == *id));
+
+    for (fluid, interval) in fluids {
+        let elapsed = elapsed.entry(fluid.0).or_default();
+        *elapsed += time.delta_seconds();
+        if *elapsed < interval && !pause.is_stepping() {
+            continue;
+        }
+        *elapsed = 0.0;
+
+        step_fluid(
+            fluid,
+            settings.max_chunks_per_step,
+            player_chunk.chunk_min,
+            &mut chunks,
+            &mut levels,
+            active_chunks.0.entry(fluid.0).or_default(),
+            &mut dirty_chunks,
+            &mut light_updates,
+        );
+    }
+}
+
+/// Drops the levels of the fluid voxels of the unloaded chunks, keeping the ones of the chunks cached in memory.
+fn prune_fluid_levels(chunks: Res<ChunkMap<Voxel, ChunkShape>>, mut levels: ResMut<FluidLevels>) {
+    levels
+        .0
+        .retain(|key, _| chunks.exists(*key) || chunks.is_cached(*key));
+}
+
+/// The entities holding the meshes of the flowing fluid voxels of the chunks, by chunk and fluid material.
+#[cfg(not(feature = "headless"))]
+#[derive(Default)]
+struct ChunkFluidMeshes(HashMap<(IVec3, u8), Entity>);
+
+/// Remeshes the flowing fluid voxels of the dirty chunks with the dedicated fluid mesher, a mesh per fluid.
+#[cfg(not(feature = "headless"))]
+#[allow(clippy::too_many_arguments)]
+fn update_fluid_meshes(
+    dirty_chunks: Res<DirtyChunks>,
+    chunk_entities: Res<ChunkEntities>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    levels: Res<FluidLevels>,
+    registry: Res<VoxelMaterialRegistry>,
+    origin: Res<WorldOrigin>,
+    mut fluid_meshes: ResMut<ChunkFluidMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut fluid_materials: Local<HashMap<u8, Handle<StandardMaterial>>>,
+    mut commands: Commands,
+) {
+    fluid_meshes.0.retain(|(key, _), entity| {
+        let keep = chunk_entities.entity(*key).is_some();
+        if !keep {
+            commands.entity(*entity).despawn_recursive();
+        }
+        keep
+    });
+
+    for (fluid, _) in flowing_materials(&registry) {
+        let material = registry.get_by_id(fluid.0).unwrap();
+        let mut color = material.base_color;
+        color.set_a(0.8);
+
+        match fluid_materials.get(&fluid.0) {
+            Some(handle) => {
+                if registry.is_changed() {
+                    if let Some(fluid_material) = materials.get_mut(handle) {
+                        fluid_material.base_color = color;
+                        fluid_material.emissive = material.emissive;
+                    }
+                }
+            }
+            None => {
+                fluid_materials.insert(
+                    fluid.0,
+                    materials.add(StandardMaterial {
+                        base_color: color,
+                        emissive: material.emissive,
+                        alpha_mode: AlphaMode::Blend,
+                        ..default()
+                    }),
+                );
+            }
+        }
+    }
+
+    for key in dirty_chunks.iter_dirty().copied() {
+        let mut voxels: HashMap<u8, Vec<(IVec3, f32)>> = HashMap::default();
+        for (pos, level) in levels.iter_chunk(key) {
+            let fluid = match chunks.voxel_at(pos) {
+                Some(fluid) => fluid,
+                None => continue,
+            };
+            let height = match fluid_level(&chunks, &levels, fluid, pos + IVec3::Y) {
+                Some(above) if above > 0 => 1.0,
+                _ => level as f32 / MAX_FLUID_LEVEL as f32,
+            };
+            voxels.entry(fluid.0).or_default().push((pos - key, height));
+        }
+
+        for (id, material) in fluid_materials.iter() {
+            let fluid_voxels = match voxels.get(id) {
+                Some(fluid_voxels) if chunk_entities.entity(key).is_some() => fluid_voxels,
+                _ => {
+                    if let Some(entity) = fluid_meshes.0.remove(&(key, *id)) {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                    continue;
+                }
+            };
+
+            // sources are part of the terrain mesh, which hides the fluid faces touching them like solid voxels.
+            let fluid = Voxel(*id);
+            let mesh = meshes.add(mesh_fluid_voxels(fluid_voxels, |local| match fluid_level(
+                &chunks,
+                &levels,
+                fluid,
+                key + local,
+            )? {
+                MAX_FLUID_LEVEL => None,
+                level => Some(level as f32 / MAX_FLUID_LEVEL as f32),
+            }));
+
+            match fluid_meshes.0.get(&(key, *id)) {
+                Some(entity) => {
+                    commands.entity(*entity).insert(mesh);
+                }
+                None => {
+                    let entity = commands
+                        .spawn_bundle(PbrBundle {
+                            mesh,
+                            material: material.clone(),
+                            transform: Transform::from_translation(origin.to_translation(key)),
+                            ..default()
+                        })
+                        .id();
+                    fluid_meshes.0.insert((key, *id), entity);
+                }
+            }
+        }
+    }
+}
+
+/// Simulates flowing fluid voxels.
+pub struct VoxelWorldFluidsPlugin;
+
+impl Plugin for VoxelWorldFluidsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<FluidSettings>()
+            .init_resource::<FluidLevels>()
+            .init_resource::<ActiveFluidChunks>()
+            .add_system_to_stage(
+                TerrainGenStage,
+                simulate_fluids.after(TerrainGenSystem::ProcessTerrainGen),
+            )
+            .add_system_to_stage(ChunkMeshingStage, activate_fluid_chunks)
+            .add_system_to_stage(bevy::prelude::CoreStage::Last, prune_fluid_levels);
+    }
+}
+
+/// Renders the partial height surfaces of the flowing fluid voxels simulated by the [`VoxelWorldFluidsPlugin`].
+#[cfg(not(feature = "headless"))]
+pub struct FluidMeshesPlugin;
+
+#[cfg(not(feature = "headless"))]
+impl Plugin for FluidMeshesPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkFluidMeshes>()
+            .add_system_to_stage(ChunkMeshingStage, update_fluid_meshes);
+    }
+}
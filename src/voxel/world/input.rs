@@ -0,0 +1,274 @@
+use bevy::{
+    input::InputSystem,
+    math::Vec2,
+    prelude::{
+        Axis, CoreStage, Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType,
+        Gamepads, Input, KeyCode, MouseButton, ParallelSystemDescriptorCoercion, Plugin, Query,
+        Res, ResMut, SystemLabel,
+    },
+    utils::HashMap,
+};
+use serde::{Deserialize, Serialize};
+
+use super::player::PlayerController;
+
+/// An action of the player, triggered by any of the inputs bound to it in the [`InputMap`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum InputAction {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    /// Jumps when walking, flies up when flying.
+    Ascend,
+    /// Flies down when flying, crouches when walking.
+    Descend,
+    Sprint,
+    ToggleMovementMode,
+    /// Locks / unlocks the cursor, the unlocked cursor being emulated with the gamepad sticks.
+    ToggleCursor,
+    /// Picks the material of the targeted voxel as the placement material.
+    Eyedropper,
+    HotbarNext,
+    HotbarPrevious,
+    /// Clicks at the position of the emulated cursor.
+    CursorClick,
+    /// Applies the terraforming brush, when enabled.
+    BrushApply,
+    /// Applies the terraforming brush in erasing mode, when enabled.
+    BrushErase,
+    ToggleMinimap,
+}
+
+/// A key, mouse button or gamepad button an action can be bound to.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum InputSource {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType),
+}
+
+/// The inputs bound to each [`InputAction`], and the gamepad sticks settings.
+pub struct InputMap {
+    bindings: HashMap<InputAction, Vec<InputSource>>,
+    /// The stick moving the player, and the emulated cursor when unlocked.
+    pub move_stick: (GamepadAxisType, GamepadAxisType),
+    /// The stick rotating the camera, and scrolling when the cursor is unlocked.
+    pub look_stick: (GamepadAxisType, GamepadAxisType),
+    /// Stick deflections shorter than this are ignored.
+    pub dead_zone: f32,
+    /// Rotation speed of the camera at full stick deflection, in radians per second.
+    pub look_speed: f32,
+    /// Speed of the emulated cursor at full stick deflection, in logical pixels per second.
+    pub cursor_speed: f32,
+}
+
+impl InputAction {
+    /// All the actions, in the order they're listed in the settings.
+    pub const ALL: [InputAction; 16] = [
+        InputAction::MoveForward,
+        InputAction::MoveBackward,
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::Ascend,
+        InputAction::Descend,
+        InputAction::Sprint,
+        InputAction::ToggleMovementMode,
+        InputAction::ToggleCursor,
+        InputAction::Eyedropper,
+        InputAction::HotbarNext,
+        InputAction::HotbarPrevious,
+        InputAction::CursorClick,
+        InputAction::BrushApply,
+        InputAction::BrushErase,
+        InputAction::ToggleMinimap,
+    ];
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use InputAction::*;
+        use InputSource::*;
+
+        let mut map = Self {
+            bindings: Default::default(),
+            move_stick: (GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY),
+            look_stick: (GamepadAxisType::RightStickX, GamepadAxisType::RightStickY),
+            dead_zone: 0.15,
+            look_speed: 3.0,
+            cursor_speed: 800.0,
+        };
+
+        for (action, source) in [
+            (MoveForward, Key(KeyCode::W)),
+            (MoveBackward, Key(KeyCode::S)),
+            (MoveLeft, Key(KeyCode::A)),
+            (MoveRight, Key(KeyCode::D)),
+            (Ascend, Key(KeyCode::Space)),
+            (Ascend, Gamepad(GamepadButtonType::South)),
+            (Descend, Key(KeyCode::LShift)),
+            (Descend, Gamepad(GamepadButtonType::East)),
+            (Sprint, Key(KeyCode::LControl)),
+            (Sprint, Gamepad(GamepadButtonType::LeftThumb)),
+            (ToggleMovementMode, Key(KeyCode::F)),
+            (ToggleMovementMode, Gamepad(GamepadButtonType::North)),
+            (ToggleCursor, Key(KeyCode::Escape)),
+            (ToggleCursor, Gamepad(GamepadButtonType::Select)),
+            (Eyedropper, Mouse(MouseButton::Middle)),
+            (Eyedropper, Gamepad(GamepadButtonType::West)),
+            (HotbarNext, Gamepad(GamepadButtonType::RightTrigger)),
+            (HotbarPrevious, Gamepad(GamepadButtonType::LeftTrigger)),
+            (CursorClick, Gamepad(GamepadButtonType::South)),
+            (BrushApply, Mouse(MouseButton::Left)),
+            (BrushApply, Gamepad(GamepadButtonType::RightTrigger2)),
+            (BrushErase, Mouse(MouseButton::Right)),
+            (BrushErase, Gamepad(GamepadButtonType::LeftTrigger2)),
+            (ToggleMinimap, Key(KeyCode::M)),
+        ] {
+            map.bind(action, source);
+        }
+
+        map
+    }
+}
+
+#[allow(dead_code)]
+impl InputMap {
+    /// Binds an input to the specified action, in addition to its current bindings.
+    pub fn bind(&mut self, action: InputAction, source: InputSource) {
+        let sources = self.bindings.entry(action).or_default();
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+
+    /// Removes an input from the bindings of the specified action.
+    pub fn unbind(&mut self, action: InputAction, source: InputSource) {
+        if let Some(sources) = self.bindings.get_mut(&action) {
+            sources.retain(|bound| *bound != source);
+        }
+    }
+
+    /// Removes all the bindings of the specified action.
+    pub fn clear(&mut self, action: InputAction) {
+        self.bindings.remove(&action);
+    }
+
+    /// Returns the inputs bound to the specified action.
+    pub fn bindings(&self, action: InputAction) -> &[InputSource] {
+        self.bindings.get(&action).map_or(&[], |sources| sources)
+    }
+
+    /// Iterates over all the actions and their bindings.
+    pub fn iter(&self) -> impl Iterator<Item = (&InputAction, &Vec<InputSource>)> {
+        self.bindings.iter()
+    }
+}
+
+/// Deflections of the sticks of the first connected gamepad, with the dead zone of the [`InputMap`] applied.
+/// While the cursor is unlocked the sticks move and scroll the emulated cursor instead of driving the player.
+#[derive(Default)]
+pub struct GamepadSticks {
+    pub movement: Vec2,
+    pub look: Vec2,
+    pub cursor: Vec2,
+    pub scroll: Vec2,
+}
+
+/// Returns the deflection of a stick, rescaled so that it starts from zero at the edge of the dead zone.
+fn read_stick(
+    axes: &Axis<GamepadAxis>,
+    gamepad: Gamepad,
+    (x, y): (GamepadAxisType, GamepadAxisType),
+    dead_zone: f32,
+) -> Vec2 {
+    let value = Vec2::new(
+        axes.get(GamepadAxis::new(gamepad, x)).unwrap_or(0.0),
+        axes.get(GamepadAxis::new(gamepad, y)).unwrap_or(0.0),
+    );
+    let length = value.length();
+    if length <= dead_zone {
+        return Vec2::ZERO;
+    }
+
+    value / length * ((length - dead_zone) / (1.0 - dead_zone)).min(1.0)
+}
+
+/// Updates the state of the actions from the state of the inputs bound to them.
+fn update_input_actions(
+    map: Res<InputMap>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    player: Query<&PlayerController>,
+    mut actions: ResMut<Input<InputAction>>,
+    mut sticks: ResMut<GamepadSticks>,
+) {
+    let gamepad = gamepads.iter().next().copied();
+    let cursor_unlocked = player
+        .get_single()
+        .map_or(false, |controller| !controller.cursor_locked());
+
+    actions.clear();
+    for (action, sources) in map.iter() {
+        // the gamepad only drives the emulated cursor while it is unlocked, and only the player otherwise.
+        let gamepad = gamepad.filter(|_| match action {
+            InputAction::ToggleCursor => true,
+            InputAction::CursorClick => cursor_unlocked,
+            _ => !cursor_unlocked,
+        });
+        let pressed = sources.iter().any(|source| match *source {
+            InputSource::Key(key) => keys.pressed(key),
+            InputSource::Mouse(button) => mouse_buttons.pressed(button),
+            InputSource::Gamepad(button) => gamepad.map_or(false, |gamepad| {
+                gamepad_buttons.pressed(GamepadButton::new(gamepad, button))
+            }),
+        });
+
+        if pressed {
+            actions.press(*action);
+        } else {
+            actions.release(*action);
+        }
+    }
+
+    *sticks = match gamepad {
+        Some(gamepad) if cursor_unlocked => GamepadSticks {
+            cursor: read_stick(&gamepad_axes, gamepad, map.move_stick, map.dead_zone),
+            scroll: read_stick(&gamepad_axes, gamepad, map.look_stick, map.dead_zone),
+            ..Default::default()
+        },
+        Some(gamepad) => GamepadSticks {
+            movement: read_stick(&gamepad_axes, gamepad, map.move_stick, map.dead_zone),
+            look: read_stick(&gamepad_axes, gamepad, map.look_stick, map.dead_zone),
+            ..Default::default()
+        },
+        None => GamepadSticks::default(),
+    };
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`InputMapPlugin`]
+pub enum InputMapSystem {
+    /// Updates the actions state from the raw inputs.
+    UpdateActions,
+}
+
+/// Maps the keyboard, mouse and gamepad inputs to the player actions, read from the `Input<InputAction>` resource.
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<InputMap>()
+            .init_resource::<Input<InputAction>>()
+            .init_resource::<GamepadSticks>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_input_actions
+                    .label(InputMapSystem::UpdateActions)
+                    .after(InputSystem),
+            );
+    }
+}
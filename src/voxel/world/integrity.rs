@@ -0,0 +1,200 @@
+#[cfg(not(feature = "headless"))]
+use bevy::prelude::Visibility;
+use bevy::{
+    math::IVec3,
+    prelude::{
+        info, warn, Commands, CoreStage, DespawnRecursiveExt, Entity, EventReader, Plugin, Query,
+        Res, ResMut, With,
+    },
+};
+
+#[cfg(not(feature = "headless"))]
+use super::meshing::{ChunkMeshingQueue, ChunkMeshingTask, CompletedChunkMeshes};
+use super::{
+    chunks::{AnchoredChunks, ChunkCommandQueue, ChunkEntities, DirtyChunks},
+    terrain::{TerrainGenTasks, MAX_GENERATED_HEIGHT},
+    Chunk, ChunkShape,
+};
+use crate::voxel::{storage::ChunkMap, Voxel};
+
+/// Event requesting a cross-check of the chunk bookkeeping.
+pub struct ValidateChunks {
+    /// Whether the inconsistencies found should be repaired.
+    pub repair: bool,
+}
+
+/// Inconsistencies found between the chunk map, the chunk entities and the pending chunk tasks.
+#[derive(Default, Clone, Debug)]
+pub struct ChunkIntegrityReport {
+    /// Chunk entities which aren't registered in [`ChunkEntities`].
+    pub orphan_entities: Vec<Entity>,
+    /// Entries of [`ChunkEntities`] pointing to despawned entities.
+    pub dangling_entities: Vec<IVec3>,
+    /// Chunk entities with neither data nor a pending generation task.
+    pub missing_data: Vec<IVec3>,
+    /// Chunk buffers neither attached to an entity nor kept loaded by an anchor.
+    pub leaked_buffers: Vec<IVec3>,
+    /// Generation tasks of chunks neither attached to an entity nor kept loaded by an anchor.
+    pub stray_tasks: Vec<IVec3>,
+    /// Chunk entities with data which were never meshed and aren't queued for meshing, when the world gets rendered.
+    pub unmeshed_chunks: Vec<IVec3>,
+    /// Whether the inconsistencies were repaired.
+    pub repaired: bool,
+}
+
+impl ChunkIntegrityReport {
+    /// Returns the total number of inconsistencies found.
+    pub fn issue_count(&self) -> usize {
+        self.orphan_entities.len()
+            + self.dangling_entities.len()
+            + self.missing_data.len()
+            + self.leaked_buffers.len()
+            + self.stray_tasks.len()
+            + self.unmeshed_chunks.len()
+    }
+
+    /// Returns a human readable summary of the report, one line per kind of inconsistency.
+    pub fn summary(&self) -> Vec<String> {
+        vec![
+            format!("Orphan chunk entities: {}", self.orphan_entities.len()),
+            format!("Dangling chunk entities: {}", self.dangling_entities.len()),
+            format!("Chunks missing data: {}", self.missing_data.len()),
+            format!("Leaked chunk buffers: {}", self.leaked_buffers.len()),
+            format!("Stray generation tasks: {}", self.stray_tasks.len()),
+            format!("Unmeshed chunks: {}", self.unmeshed_chunks.len()),
+        ]
+    }
+}
+
+/// Holds the report of the last chunk validation.
+#[derive(Default)]
+pub struct ChunkIntegrity {
+    pub last_report: Option<ChunkIntegrityReport>,
+}
+
+/// Cross-checks the chunk bookkeeping when requested, and repairs the inconsistencies found if asked to.
+/// Runs at the start of the frame, when the deferred chunk spawns / despawns of the previous frame have all been applied.
+fn validate_chunks(
+    mut requests: EventReader<ValidateChunks>,
+    chunk_query: Query<(Entity, &Chunk)>,
+    #[cfg(not(feature = "headless"))] meshed_chunk_query: Query<
+        (&Visibility, Option<&ChunkMeshingTask>),
+        With<Chunk>,
+    >,
+    chunk_entities_query: Query<Entity, With<Chunk>>,
+    mut chunk_entities: ResMut<ChunkEntities>,
+    mut chunk_command_queue: ResMut<ChunkCommandQueue>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    mut gen_tasks: ResMut<TerrainGenTasks>,
+    mut integrity: ResMut<ChunkIntegrity>,
+    anchored_chunks: Res<AnchoredChunks>,
+    #[cfg(not(feature = "headless"))] meshing_queue: Option<Res<ChunkMeshingQueue>>,
+    #[cfg(not(feature = "headless"))] completed_meshes: Option<Res<CompletedChunkMeshes>>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    mut commands: Commands,
+) {
+    let repair = match requests.iter().last() {
+        Some(request) => request.repair,
+        None => return,
+    };
+
+    let mut report = ChunkIntegrityReport::default();
+
+    for (entity, chunk) in chunk_query.iter() {
+        if chunk_entities.entity(chunk.0) != Some(entity) {
+            report.orphan_entities.push(entity);
+            continue;
+        }
+
+        if !chunks.exists(chunk.0) {
+            if !gen_tasks.contains(chunk.0) && chunk.0.y < MAX_GENERATED_HEIGHT {
+                report.missing_data.push(chunk.0);
+            }
+            continue;
+        }
+
+        // the chunks are only meshed when the world gets rendered.
+        #[cfg(not(feature = "headless"))]
+        if let (Ok((visibility, meshing_task)), Some(meshing_queue), Some(completed_meshes)) = (
+            meshed_chunk_query.get(entity),
+            meshing_queue.as_deref(),
+            completed_meshes.as_deref(),
+        ) {
+            if !visibility.is_visible
+                && meshing_task.is_none()
+                && !meshing_queue.contains(chunk.0)
+                && !completed_meshes.contains(entity)
+            {
+                report.unmeshed_chunks.push(chunk.0);
+            }
+        }
+    }
+
+    report.dangling_entities = chunk_entities
+        .iter_keys()
+        .filter(|key| {
+            chunk_entities
+                .entity(**key)
+                .map_or(true, |entity| chunk_entities_query.get(entity).is_err())
+        })
+        .copied()
+        .collect();
+
+    let is_wanted =
+        |key: IVec3| chunk_entities.entity(key).is_some() || anchored_chunks.contains(key);
+
+    report.leaked_buffers = chunks.iter_keys().filter(|key| !is_wanted(*key)).collect();
+    report.stray_tasks = gen_tasks
+        .iter_keys()
+        .filter(|key| !is_wanted(**key))
+        .copied()
+        .collect();
+
+    if report.issue_count() == 0 {
+        info!("Chunk validation found no inconsistencies");
+    } else {
+        warn!(
+            "Chunk validation found {} inconsistencies: {}",
+            report.issue_count(),
+            report.summary().join(", ")
+        );
+    }
+
+    if repair {
+        for entity in report.orphan_entities.iter() {
+            commands.entity(*entity).despawn_recursive();
+        }
+
+        // respawn the entities of the dangling chunks.
+        for key in report.dangling_entities.iter() {
+            chunk_entities.detach_entity(*key);
+        }
+        chunk_command_queue.queue_load(report.dangling_entities.iter());
+
+        chunk_command_queue.queue_data_load(report.missing_data.iter());
+        chunk_command_queue.queue_data_unload(report.leaked_buffers.iter());
+
+        for key in report.stray_tasks.iter() {
+            gen_tasks.cancel(*key);
+        }
+
+        for key in report.unmeshed_chunks.iter() {
+            dirty_chunks.mark_dirty(*key);
+        }
+
+        report.repaired = true;
+    }
+
+    integrity.last_report = Some(report);
+}
+
+/// Adds the [`ValidateChunks`] event for diagnosing and repairing inconsistencies in the chunk bookkeeping.
+pub struct ChunkIntegrityPlugin;
+
+impl Plugin for ChunkIntegrityPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkIntegrity>()
+            .add_event::<ValidateChunks>()
+            .add_system_to_stage(CoreStage::PreUpdate, validate_chunks);
+    }
+}
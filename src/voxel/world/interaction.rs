@@ -0,0 +1,224 @@
+use bevy::{
+    math::{IVec3, Vec3},
+    pbr::{AlphaMode, NotShadowCaster},
+    prelude::{
+        shape, Assets, Color, Commands, Component, EventWriter, GlobalTransform, Handle, Input,
+        Mesh, ParallelSystemDescriptorCoercion, PbrBundle, Plugin, Query, Res, ResMut,
+        StandardMaterial, SystemLabel, Transform, Visibility, With,
+    },
+};
+
+use super::{
+    input::InputAction,
+    materials::Dirt,
+    origin::WorldOrigin,
+    player::PlayerController,
+    raycast::{raycast_voxels, VoxelRaycastHit},
+    ChunkShape, PLAYER_REACH,
+};
+use crate::voxel::{
+    material::{VoxelMaterial, VoxelMaterialFlags, VoxelMaterialRegistry},
+    storage::ChunkMap,
+    Voxel,
+};
+
+/// The voxel the player is currently looking at, if any within reach.
+#[derive(Default)]
+pub struct TargetedVoxel(pub Option<VoxelRaycastHit>);
+
+/// The material of the voxels placed by the player.
+pub struct PlacementMaterial(pub Voxel);
+
+impl Default for PlacementMaterial {
+    fn default() -> Self {
+        Self(Dirt::into_voxel())
+    }
+}
+
+/// World position at which a voxel would be placed: the replaceable voxel adjacent to the face of the targeted voxel.
+#[derive(Default)]
+pub struct PlacementTarget(pub Option<IVec3>);
+
+/// Tags the translucent preview of the voxel about to be placed.
+#[derive(Component)]
+pub struct PlacementGhost;
+
+/// Event sent whenever the [`PlacementMaterial`] changes, so that UIs can reflect the new selection.
+#[allow(dead_code)]
+pub struct PlacementMaterialChanged {
+    pub previous: Voxel,
+    pub current: Voxel,
+}
+
+/// Updates the voxel targeted by the player, liquids are ignored.
+fn update_targeted_voxel(
+    player: Query<&GlobalTransform, With<PlayerController>>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    materials: Res<VoxelMaterialRegistry>,
+    origin: Res<WorldOrigin>,
+    mut targeted: ResMut<TargetedVoxel>,
+) {
+    let hit = player.get_single().ok().and_then(|transform| {
+        raycast_voxels(
+            &chunks,
+            transform.translation(),
+            origin.get(),
+            transform.forward(),
+            PLAYER_REACH,
+            |voxel| {
+                materials.get_by_id(voxel.0).map_or(true, |material| {
+                    !material.flags.contains(VoxelMaterialFlags::LIQUID)
+                })
+            },
+        )
+    });
+
+    if targeted.0 != hit {
+        targeted.0 = hit;
+    }
+}
+
+/// Updates the placement target from the targeted voxel, only empty and liquid voxels can be replaced.
+fn update_placement_target(
+    targeted: Res<TargetedVoxel>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    materials: Res<VoxelMaterialRegistry>,
+    mut placement_target: ResMut<PlacementTarget>,
+) {
+    let target = targeted
+        .0
+        .filter(|hit| hit.normal != IVec3::ZERO)
+        .map(|hit| hit.position + hit.normal)
+        .filter(|pos| {
+            chunks.voxel_at(*pos).map_or(false, |voxel| {
+                voxel == Voxel::EMPTY_VOXEL
+                    || materials.get_by_id(voxel.0).map_or(false, |material| {
+                        material.flags.contains(VoxelMaterialFlags::LIQUID)
+                    })
+            })
+        });
+
+    if placement_target.0 != target {
+        placement_target.0 = target;
+    }
+}
+
+fn spawn_placement_ghost(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut commands: Commands,
+) {
+    commands
+        .spawn_bundle(PbrBundle {
+            // slightly larger than a voxel to avoid z-fighting with the neighboring faces.
+            mesh: meshes.add(Mesh::from(shape::Cube { size: 1.02 })),
+            material: materials.add(StandardMaterial {
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..Default::default()
+            }),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(PlacementGhost)
+        .insert(NotShadowCaster);
+}
+
+/// Moves the placement ghost to the placement target and tints it with the color of the placement material.
+fn update_placement_ghost(
+    placement_target: Res<PlacementTarget>,
+    placement_material: Res<PlacementMaterial>,
+    voxel_materials: Res<VoxelMaterialRegistry>,
+    origin: Res<WorldOrigin>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut ghost: Query<
+        (&mut Transform, &mut Visibility, &Handle<StandardMaterial>),
+        With<PlacementGhost>,
+    >,
+) {
+    let (mut transform, mut visibility, material) = match ghost.get_single_mut() {
+        Ok(ghost) => ghost,
+        Err(_) => return,
+    };
+
+    if placement_target.is_changed() {
+        visibility.is_visible = placement_target.0.is_some();
+        if let Some(pos) = placement_target.0 {
+            transform.translation = origin.to_translation(pos) + Vec3::splat(0.5);
+        }
+    }
+
+    if placement_material.is_changed() || voxel_materials.is_changed() {
+        if let Some(material) = materials.get_mut(material) {
+            material.base_color = voxel_materials
+                .get_by_id(placement_material.0 .0)
+                .map_or(Color::WHITE, |info| info.base_color);
+            material.base_color.set_a(0.35);
+        }
+    }
+}
+
+/// Sets the placement material to the material of the targeted voxel when the eyedropper action is triggered.
+fn eyedropper(
+    actions: Res<Input<InputAction>>,
+    targeted: Res<TargetedVoxel>,
+    mut placement_material: ResMut<PlacementMaterial>,
+    mut changed_events: EventWriter<PlacementMaterialChanged>,
+) {
+    if !actions.just_pressed(InputAction::Eyedropper) {
+        return;
+    }
+
+    if let Some(hit) = targeted.0 {
+        if hit.voxel != placement_material.0 {
+            changed_events.send(PlacementMaterialChanged {
+                previous: placement_material.0,
+                current: hit.voxel,
+            });
+            placement_material.0 = hit.voxel;
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`VoxelInteractionPlugin`]
+pub enum VoxelInteractionSystem {
+    /// Casts a ray from the player camera to find the targeted voxel.
+    UpdateTargetedVoxel,
+    /// Picks the targeted voxel material as the placement material.
+    Eyedropper,
+    /// Computes where a voxel would be placed.
+    UpdatePlacementTarget,
+    /// Moves the translucent preview of the voxel about to be placed.
+    UpdatePlacementGhost,
+}
+
+/// Handles the player interactions with the voxels of the world.
+pub struct VoxelInteractionPlugin;
+
+impl Plugin for VoxelInteractionPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<TargetedVoxel>()
+            .init_resource::<PlacementMaterial>()
+            .init_resource::<PlacementTarget>()
+            .add_event::<PlacementMaterialChanged>()
+            .add_system(update_targeted_voxel.label(VoxelInteractionSystem::UpdateTargetedVoxel))
+            .add_system(
+                eyedropper
+                    .label(VoxelInteractionSystem::Eyedropper)
+                    .after(VoxelInteractionSystem::UpdateTargetedVoxel),
+            )
+            .add_startup_system(spawn_placement_ghost)
+            .add_system(
+                update_placement_target
+                    .label(VoxelInteractionSystem::UpdatePlacementTarget)
+                    .after(VoxelInteractionSystem::UpdateTargetedVoxel),
+            )
+            .add_system(
+                update_placement_ghost
+                    .label(VoxelInteractionSystem::UpdatePlacementGhost)
+                    .after(VoxelInteractionSystem::UpdatePlacementTarget)
+                    .after(VoxelInteractionSystem::Eyedropper),
+            );
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    input::Input,
+    math::IVec3,
+    prelude::{
+        info, EventReader, EventWriter, KeyCode, ParallelSystemDescriptorCoercion, Plugin, Res,
+        ResMut, SystemLabel,
+    },
+};
+
+use crate::voxel::{
+    net::{NetworkVoxelEdit, ReplicationSystem},
+    Voxel,
+};
+
+/// A voxel edit recorded in the [`VoxelEditJournal`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct JournalEntry {
+    pub pos: IVec3,
+    /// The voxel replaced by the edit, restored when undone.
+    pub previous: Voxel,
+    pub voxel: Voxel,
+}
+
+/// History of the voxels placed and broken through [`NetworkVoxelEdit`] events, grouped in batches of the edits applied
+/// during the same frame, which get undone and redone together.
+pub struct VoxelEditJournal {
+    undo: VecDeque<Vec<JournalEntry>>,
+    redo: Vec<Vec<JournalEntry>>,
+    /// Edits of the batch being recorded.
+    batch: Vec<JournalEntry>,
+    /// Maximum number of batches which can be undone, the oldest ones being forgotten past it.
+    pub max_batches: usize,
+}
+
+impl Default for VoxelEditJournal {
+    fn default() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            batch: Vec::new(),
+            max_batches: 256,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl VoxelEditJournal {
+    /// Records an edit in the current batch, which gets committed by [`VoxelEditJournal::end_batch`].
+    pub fn record(&mut self, pos: IVec3, previous: Voxel, voxel: Voxel) {
+        if previous != voxel {
+            self.batch.push(JournalEntry {
+                pos,
+                previous,
+                voxel,
+            });
+        }
+    }
+
+    /// Commits the current batch, forgetting the undone batches which can't be redone anymore.
+    pub fn end_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+
+        self.undo.push_back(std::mem::take(&mut self.batch));
+        self.redo.clear();
+        while self.undo.len() > self.max_batches {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Returns the edits reverting the last batch, in reverse order, and makes it redoable.
+    pub fn undo(&mut self) -> Vec<NetworkVoxelEdit> {
+        let batch = match self.undo.pop_back() {
+            Some(batch) => batch,
+            None => return Vec::new(),
+        };
+
+        let edits = batch
+            .iter()
+            .rev()
+            .map(|entry| NetworkVoxelEdit {
+                pos: entry.pos,
+                voxel: entry.previous,
+                journaled: false,
+                client: None,
+            })
+            .collect();
+        self.redo.push(batch);
+        edits
+    }
+
+    /// Returns the edits of the last undone batch, and makes it undoable again.
+    pub fn redo(&mut self) -> Vec<NetworkVoxelEdit> {
+        let batch = match self.redo.pop() {
+            Some(batch) => batch,
+            None => return Vec::new(),
+        };
+
+        let edits = batch
+            .iter()
+            .map(|entry| NetworkVoxelEdit {
+                pos: entry.pos,
+                voxel: entry.voxel,
+                journaled: false,
+                client: None,
+            })
+            .collect();
+        self.undo.push_back(batch);
+        edits
+    }
+
+    /// Returns the number of batches which can be undone.
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// Returns the number of batches which can be redone.
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Forgets the whole history, e.g. when the edited world is replaced.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.batch.clear();
+    }
+}
+
+/// Event undoing the last batch of edits of the [`VoxelEditJournal`].
+pub struct UndoVoxelEdits;
+
+/// Event redoing the last undone batch of edits of the [`VoxelEditJournal`].
+pub struct RedoVoxelEdits;
+
+/// Sends the edits undoing and redoing the journaled batches.
+fn undo_redo_voxel_edits(
+    mut undo_events: EventReader<UndoVoxelEdits>,
+    mut redo_events: EventReader<RedoVoxelEdits>,
+    mut journal: ResMut<VoxelEditJournal>,
+    mut edits: EventWriter<NetworkVoxelEdit>,
+) {
+    for _ in undo_events.iter() {
+        let undone = journal.undo();
+        if !undone.is_empty() {
+            info!("Undoing {} voxel edits", undone.len());
+        }
+        edits.send_batch(undone.into_iter());
+    }
+
+    for _ in redo_events.iter() {
+        let redone = journal.redo();
+        if !redone.is_empty() {
+            info!("Redoing {} voxel edits", redone.len());
+        }
+        edits.send_batch(redone.into_iter());
+    }
+}
+
+/// Undoes the edits with Ctrl+Z and redoes them with Ctrl+Y, when a keyboard is available.
+fn undo_redo_shortcuts(
+    keys: Option<Res<Input<KeyCode>>>,
+    mut undo_events: EventWriter<UndoVoxelEdits>,
+    mut redo_events: EventWriter<RedoVoxelEdits>,
+) {
+    let keys = match keys {
+        Some(keys) if keys.any_pressed([KeyCode::LControl, KeyCode::RControl]) => keys,
+        _ => return,
+    };
+
+    if keys.just_pressed(KeyCode::Z) {
+        undo_events.send(UndoVoxelEdits);
+    }
+    if keys.just_pressed(KeyCode::Y) {
+        redo_events.send(RedoVoxelEdits);
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`VoxelEditJournalPlugin`]
+pub enum VoxelEditJournalSystem {
+    /// Sends the undo / redo requests of the keyboard shortcuts.
+    Shortcuts,
+    /// Sends the edits undoing / redoing the requested batches, applied during the same frame.
+    UndoRedo,
+}
+
+/// Keeps the [`VoxelEditJournal`] of the applied voxel edits, and undoes / redoes them on request (Ctrl+Z / Ctrl+Y).
+pub struct VoxelEditJournalPlugin;
+
+impl Plugin for VoxelEditJournalPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<VoxelEditJournal>()
+            .add_event::<UndoVoxelEdits>()
+            .add_event::<RedoVoxelEdits>()
+            .add_system(undo_redo_shortcuts.label(VoxelEditJournalSystem::Shortcuts))
+            .add_system(
+                undo_redo_voxel_edits
+                    .label(VoxelEditJournalSystem::UndoRedo)
+                    .after(VoxelEditJournalSystem::Shortcuts)
+                    .before(ReplicationSystem::ApplyVoxelEdits),
+            );
+    }
+}
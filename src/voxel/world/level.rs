@@ -0,0 +1,59 @@
+use std::io;
+
+use bevy::{
+    math::IVec3,
+    prelude::{Plugin, Res, ResMut},
+};
+
+use super::{ChunkShape, CHUNK_SIZE};
+use crate::voxel::{
+    storage::{ChunkMap, ChunkMapBounds, WorldSave},
+    Voxel,
+};
+
+/// An authored (not infinite) world, whose chunks are only ever streamed from the [`WorldSave`] resource and never generated.
+/// Inserting this resource before adding the [`super::VoxelWorldPlugin`] enables the mode, chunks missing from the save are left empty.
+#[derive(Clone, Copy, Debug)]
+pub struct AuthoredLevel {
+    /// Region of the level (in voxels), voxel queries outside of it return the barrier voxel.
+    pub bounds: ChunkMapBounds<Voxel>,
+}
+
+impl AuthoredLevel {
+    /// Creates a level spanning all the chunks stored in the specified save, surrounded by the barrier voxel.
+    pub fn from_save(save: &WorldSave, barrier: Voxel) -> io::Result<Self> {
+        let keys = save.chunk_keys()?;
+        let min = keys.iter().copied().reduce(IVec3::min).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the level doesn't have any chunk",
+            )
+        })?;
+        let max = keys.iter().copied().reduce(IVec3::max).unwrap() + CHUNK_SIZE;
+
+        Ok(Self {
+            bounds: ChunkMapBounds { min, max, barrier },
+        })
+    }
+
+    /// Returns whether the chunk at the specified key overlaps the level.
+    pub fn contains_chunk(&self, key: IVec3) -> bool {
+        (key + CHUNK_SIZE).cmpgt(self.bounds.min).all() && key.cmplt(self.bounds.max).all()
+    }
+}
+
+fn apply_level_bounds(
+    level: Option<Res<AuthoredLevel>>,
+    mut chunks: ResMut<ChunkMap<Voxel, ChunkShape>>,
+) {
+    chunks.set_bounds(level.map(|level| level.bounds));
+}
+
+/// Restricts the world to the bounds of the [`AuthoredLevel`], if any.
+pub struct AuthoredLevelPlugin;
+
+impl Plugin for AuthoredLevelPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_startup_system(apply_level_bounds);
+    }
+}
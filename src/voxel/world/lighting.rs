@@ -0,0 +1,458 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    math::IVec3,
+    prelude::{CoreStage, ParallelSystemDescriptorCoercion, Plugin, Res, ResMut, SystemLabel},
+    utils::HashSet,
+};
+
+use super::{
+    chunk_key_at,
+    chunks::{ChunkCommandQueue, ChunkLoadingSystem, DirtyChunks},
+    column_heights::ColumnHeightmaps,
+    stages::LightingStage,
+    ChunkShape, CHUNK_SIZE,
+};
+use crate::voxel::{
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    storage::ChunkMap,
+    Light, Voxel,
+};
+
+const NEIGHBOR_DIRECTIONS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+/// Positions of the voxels which were modified and need their light to be recomputed.
+#[derive(Default)]
+pub struct LightUpdates(Vec<IVec3>);
+
+impl LightUpdates {
+    /// Queues the update of the light around the voxel at the specified world position.
+    pub fn queue(&mut self, pos: IVec3) {
+        self.0.push(pos);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LightChannel {
+    Sun,
+    Block,
+}
+
+impl LightChannel {
+    #[inline]
+    fn get(self, light: Light) -> u8 {
+        match self {
+            LightChannel::Sun => light.sun(),
+            LightChannel::Block => light.block(),
+        }
+    }
+
+    #[inline]
+    fn set(self, light: Light, level: u8) -> Light {
+        match self {
+            LightChannel::Sun => light.with_sun(level),
+            LightChannel::Block => light.with_block(level),
+        }
+    }
+
+    /// Returns the level of light received by a voxel from a neighbor with the specified light level.
+    /// Full sunlight travels down without fading.
+    #[inline]
+    fn falloff(self, level: u8, direction: IVec3) -> u8 {
+        if self == LightChannel::Sun && level == Light::MAX_LEVEL && direction == IVec3::NEG_Y {
+            level
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+/// Light related properties of every voxel material, looked up while propagating light.
+struct MaterialLightProperties {
+    transparent: [bool; 256],
+    emission: [u8; 256],
+    /// Color and emitter of the emitted light, packed by [`Light::pack_source`].
+    source: [u32; 256],
+}
+
+impl MaterialLightProperties {
+    fn from_registry(registry: &VoxelMaterialRegistry) -> Self {
+        let mut properties = Self {
+            transparent: [false; 256],
+            emission: [0; 256],
+            source: [0; 256],
+        };
+
+        properties.transparent[Voxel::EMPTY_VOXEL.0 as usize] = true;
+        registry
+            .iter_mats()
+            .enumerate()
+            .skip(1)
+            .for_each(|(id, material)| {
+                properties.transparent[id] = material
+                    .flags
+                    .intersects(VoxelMaterialFlags::LIQUID | VoxelMaterialFlags::FOLIAGE);
+                // unless the material has an explicit reach, the brightest component of the emissive color drives the light level of torch-like voxels.
+                let intensity = material
+                    .emissive
+                    .r()
+                    .max(material.emissive.g())
+                    .max(material.emissive.b())
+                    .clamp(0.0, 1.0);
+                properties.emission[id] = material
+                    .light_reach
+                    .unwrap_or_else(|| (intensity * Light::MAX_LEVEL as f32).round() as u8)
+                    .min(Light::MAX_LEVEL);
+                properties.source[id] = Light::pack_source(id as u8, material.emissive);
+            });
+
+        properties
+    }
+}
+
+/// Flood fill light propagation over the loaded chunks, working in world coordinates so that light crosses chunk borders.
+struct LightPropagator<'a> {
+    voxels: &'a ChunkMap<Voxel, ChunkShape>,
+    heightmaps: &'a ColumnHeightmaps,
+    lights: &'a mut ChunkMap<Light, ChunkShape>,
+    materials: MaterialLightProperties,
+    /// The chunks whose light was modified.
+    touched: HashSet<IVec3>,
+}
+
+impl<'a> LightPropagator<'a> {
+    #[inline]
+    fn is_transparent(&self, pos: IVec3) -> Option<bool> {
+        self.voxels
+            .voxel_at(pos)
+            .map(|voxel| self.materials.transparent[voxel.0 as usize])
+    }
+
+    #[inline]
+    fn emission(&self, pos: IVec3) -> u8 {
+        self.voxels
+            .voxel_at(pos)
+            .map_or(0, |voxel| self.materials.emission[voxel.0 as usize])
+    }
+
+    /// Makes the voxel at the specified position emit its block light, with the color of its material.
+    fn emit(&mut self, pos: IVec3, emission: u8) {
+        self.set_level(LightChannel::Block, pos, emission);
+        if let Some(voxel) = self.voxels.voxel_at(pos) {
+            self.set_block_source(pos, self.materials.source[voxel.0 as usize]);
+        }
+    }
+
+    fn set_block_source(&mut self, pos: IVec3, source: u32) {
+        if let Some(light) = self.lights.voxel_at_mut(pos) {
+            *light = light.with_block_source(source);
+        }
+    }
+
+    fn set_level(&mut self, channel: LightChannel, pos: IVec3, level: u8) {
+        if let Some(light) = self.lights.voxel_at_mut(pos) {
+            *light = channel.set(*light, level);
+            self.touched.insert(chunk_key_at(pos));
+
+            // the faces of the neighboring chunks bordering the voxel are lit by it too.
+            let local = pos - chunk_key_at(pos);
+            for axis in 0..3 {
+                let mut offset = IVec3::ZERO;
+                if local[axis] == 0 {
+                    offset[axis] = -1;
+                } else if local[axis] == CHUNK_SIZE[axis] - 1 {
+                    offset[axis] = 1;
+                } else {
+                    continue;
+                }
+                self.touched.insert(chunk_key_at(pos + offset));
+            }
+        }
+    }
+
+    /// Spreads the light from the queued positions to their transparent neighbors.
+    /// The block light keeps the color and animation of its emitter, the brightest light winning where several lights meet.
+    fn propagate(&mut self, channel: LightChannel, mut queue: VecDeque<IVec3>) {
+        while let Some(pos) = queue.pop_front() {
+            let (level, source) = match self.lights.voxel_at(pos) {
+                Some(light) => (channel.get(light), light.block_source()),
+                None => continue,
+            };
+
+            for direction in NEIGHBOR_DIRECTIONS {
+                let neighbor = pos + direction;
+                let expected = channel.falloff(level, direction);
+
+                if expected == 0 || self.is_transparent(neighbor) != Some(true) {
+                    continue;
+                }
+
+                match self.lights.voxel_at(neighbor) {
+                    Some(light) if channel.get(light) < expected => {
+                        self.set_level(channel, neighbor, expected);
+                        if channel == LightChannel::Block {
+                            self.set_block_source(neighbor, source);
+                        }
+                        queue.push_back(neighbor);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Removes the light which was spread from the queued positions (along with their former light level).
+    /// Returns the positions from which light needs to be spread again to fill the darkened area.
+    fn remove(
+        &mut self,
+        channel: LightChannel,
+        mut queue: VecDeque<(IVec3, u8)>,
+    ) -> VecDeque<IVec3> {
+        let mut refill = VecDeque::new();
+
+        while let Some((pos, level)) = queue.pop_front() {
+            for direction in NEIGHBOR_DIRECTIONS {
+                let neighbor = pos + direction;
+                let neighbor_level = match self.lights.voxel_at(neighbor) {
+                    Some(light) => channel.get(light),
+                    None => continue,
+                };
+
+                if neighbor_level == 0 {
+                    continue;
+                }
+
+                if neighbor_level < level || channel.falloff(level, direction) == neighbor_level {
+                    self.set_level(channel, neighbor, 0);
+                    queue.push_back((neighbor, neighbor_level));
+
+                    // light sources keep emitting.
+                    let emission = self.emission(neighbor);
+                    if channel == LightChannel::Block && emission > 0 {
+                        self.emit(neighbor, emission);
+                        refill.push_back(neighbor);
+                    }
+                } else {
+                    refill.push_back(neighbor);
+                }
+            }
+        }
+
+        refill
+    }
+
+    /// Recomputes the light around a modified voxel.
+    fn update_voxel(&mut self, pos: IVec3) {
+        let light = match self.lights.voxel_at(pos) {
+            Some(light) => light,
+            None => return,
+        };
+
+        for channel in [LightChannel::Sun, LightChannel::Block] {
+            let mut removal = VecDeque::new();
+            let level = channel.get(light);
+            if level > 0 {
+                self.set_level(channel, pos, 0);
+                removal.push_back((pos, level));
+            }
+
+            let mut refill = self.remove(channel, removal);
+
+            let emission = self.emission(pos);
+            if channel == LightChannel::Block && emission > 0 {
+                self.emit(pos, emission);
+                refill.push_back(pos);
+            }
+
+            // let the light from the neighbors flow into the voxel if it became transparent.
+            if self.is_transparent(pos) == Some(true) {
+                refill.extend(NEIGHBOR_DIRECTIONS.iter().map(|direction| pos + *direction));
+            }
+
+            self.propagate(channel, refill);
+        }
+    }
+
+    /// Computes the light of a newly loaded chunk, and lets the light flow between it and its loaded neighbors.
+    fn light_chunk(&mut self, key: IVec3) {
+        self.lights.insert_empty(key);
+
+        let mut sun_queue = VecDeque::new();
+        let mut block_queue = VecDeque::new();
+        for x in 0..CHUNK_SIZE.x {
+            for z in 0..CHUNK_SIZE.z {
+                // chunks without anything loaded above them are assumed to be open to the sky.
+                let sky_lit = self
+                    .lights
+                    .voxel_at(key + IVec3::new(x, CHUNK_SIZE.y, z))
+                    .map_or(true, |light| light.sun() == Light::MAX_LEVEL);
+
+                if !sky_lit {
+                    continue;
+                }
+
+                // the sunlight goes straight down to the highest opaque voxel of the column.
+                let top = match self.heightmaps.chunk_heights(key) {
+                    Some(heights) => heights[(z * CHUNK_SIZE.x + x) as usize] as i32,
+                    None => (0..CHUNK_SIZE.y)
+                        .rev()
+                        .find(|y| self.is_transparent(key + IVec3::new(x, *y, z)) != Some(true))
+                        .map_or(0, |y| y + 1),
+                };
+                for y in top..CHUNK_SIZE.y {
+                    let pos = key + IVec3::new(x, y, z);
+                    self.set_level(LightChannel::Sun, pos, Light::MAX_LEVEL);
+                    sun_queue.push_back(pos);
+                }
+            }
+        }
+
+        for x in 0..CHUNK_SIZE.x {
+            for y in 0..CHUNK_SIZE.y {
+                for z in 0..CHUNK_SIZE.z {
+                    let pos = key + IVec3::new(x, y, z);
+                    let emission = self.emission(pos);
+                    if emission > 0 {
+                        self.emit(pos, emission);
+                        block_queue.push_back(pos);
+                    }
+                }
+            }
+        }
+
+        // light coming from the neighboring chunks.
+        for direction in NEIGHBOR_DIRECTIONS {
+            let neighbor_key = key + direction * CHUNK_SIZE;
+            if self.lights.buffer_at(neighbor_key).is_none() {
+                continue;
+            }
+
+            // the face of the chunk touching the neighbor, on which the neighbor light is seeded.
+            let face_min = direction.max(IVec3::ZERO) * (CHUNK_SIZE - IVec3::ONE);
+            let face_max = face_min + (IVec3::ONE - direction.abs()) * (CHUNK_SIZE - IVec3::ONE);
+
+            for x in face_min.x..=face_max.x {
+                for y in face_min.y..=face_max.y {
+                    for z in face_min.z..=face_max.z {
+                        let pos = key + IVec3::new(x, y, z) + direction;
+                        sun_queue.push_back(pos);
+                        block_queue.push_back(pos);
+                    }
+                }
+            }
+        }
+
+        self.propagate(LightChannel::Sun, sun_queue);
+        self.propagate(LightChannel::Block, block_queue);
+
+        // the chunk below may have assumed to be open to the sky while this chunk blocks the sunlight.
+        let mut removal = VecDeque::new();
+        for x in 0..CHUNK_SIZE.x {
+            for z in 0..CHUNK_SIZE.z {
+                let below = key + IVec3::new(x, -1, z);
+                let below_sunlit = self
+                    .lights
+                    .voxel_at(below)
+                    .map_or(false, |light| light.sun() == Light::MAX_LEVEL);
+                let bottom_sunlit = self
+                    .lights
+                    .voxel_at(below + IVec3::Y)
+                    .map_or(false, |light| light.sun() == Light::MAX_LEVEL);
+
+                if below_sunlit && !bottom_sunlit {
+                    self.set_level(LightChannel::Sun, below, 0);
+                    removal.push_back((below, Light::MAX_LEVEL));
+                }
+            }
+        }
+
+        let refill = self.remove(LightChannel::Sun, removal);
+        self.propagate(LightChannel::Sun, refill);
+    }
+}
+
+/// Lights the newly loaded chunks and updates the light around the modified voxels, then remeshes the chunks whose light changed.
+fn propagate_light(
+    voxels: Res<ChunkMap<Voxel, ChunkShape>>,
+    mut lights: ResMut<ChunkMap<Light, ChunkShape>>,
+    mut light_updates: ResMut<LightUpdates>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    materials: Res<VoxelMaterialRegistry>,
+    heightmaps: Res<ColumnHeightmaps>,
+) {
+    let new_chunks: Vec<IVec3> = dirty_chunks
+        .iter_dirty()
+        .filter(|key| voxels.exists(**key) && !lights.exists(**key))
+        .copied()
+        .collect();
+
+    if new_chunks.is_empty() && light_updates.0.is_empty() {
+        return;
+    }
+
+    let mut propagator = LightPropagator {
+        voxels: &voxels,
+        heightmaps: &heightmaps,
+        lights: &mut lights,
+        materials: MaterialLightProperties::from_registry(&materials),
+        touched: Default::default(),
+    };
+
+    new_chunks
+        .into_iter()
+        .for_each(|key| propagator.light_chunk(key));
+
+    light_updates
+        .0
+        .drain(..)
+        .for_each(|pos| propagator.update_voxel(pos));
+
+    propagator
+        .touched
+        .into_iter()
+        .for_each(|key| dirty_chunks.mark_dirty(key));
+}
+
+/// Drops the light data of the chunks being unloaded.
+fn unload_chunk_light(
+    chunk_command_queue: Res<ChunkCommandQueue>,
+    mut lights: ResMut<ChunkMap<Light, ChunkShape>>,
+) {
+    chunk_command_queue.pending_data_unloads().for_each(|key| {
+        lights.remove(*key);
+    });
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`VoxelWorldLightingPlugin`]
+pub enum LightingSystem {
+    /// Propagates the light updates queued by the voxel edits and the loaded chunks.
+    PropagateLight,
+}
+
+/// Computes the sunlight and block light levels of the voxels, stored in a [`ChunkMap<Light, ChunkShape>`] resource.
+pub struct VoxelWorldLightingPlugin;
+
+impl Plugin for VoxelWorldLightingPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.insert_resource(ChunkMap::<Light, ChunkShape>::new(ChunkShape {}))
+            .init_resource::<LightUpdates>()
+            .add_system_to_stage(
+                LightingStage,
+                propagate_light.label(LightingSystem::PropagateLight),
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                unload_chunk_light
+                    .after(ChunkLoadingSystem::DestroyChunks)
+                    .before(ChunkLoadingSystem::UnloadChunkData),
+            );
+    }
+}
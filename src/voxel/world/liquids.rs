@@ -0,0 +1,112 @@
+use bevy::{
+    math::{IVec3, Vec3},
+    prelude::{EventWriter, GlobalTransform, Plugin, Query, Res, ResMut, With},
+    time::Time,
+};
+
+use super::{origin::WorldOrigin, player::PlayerController, ChunkShape};
+use crate::voxel::{material::VoxelMaterialRegistry, storage::ChunkMap, Voxel};
+
+/// Number of voxels scanned above the camera when looking for the surface of the liquid it is submerged in.
+const MAX_SURFACE_SCAN: i32 = 64;
+
+/// The voxel the camera is submerged in, if any, and how deep.
+#[derive(Default, PartialEq)]
+pub struct CameraSubmersion {
+    pub voxel: Option<Voxel>,
+    /// Height (in rendered coordinates) of the surface of the liquid above the camera, approximated by the top of the
+    /// column of voxels of the same liquid above the camera voxel (looked up to 64 voxels above the camera).
+    pub surface: f32,
+}
+
+/// Event sent every frame the player is in contact with a voxel material dealing damage (lava for instance).
+pub struct VoxelContactDamage {
+    pub voxel: Voxel,
+    /// Damage dealt during the frame.
+    pub damage: f32,
+}
+
+/// Updates the [`CameraSubmersion`] resource from the voxel at the camera position.
+fn update_camera_submersion(
+    player: Query<&GlobalTransform, With<PlayerController>>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    origin: Res<WorldOrigin>,
+    mut submersion: ResMut<CameraSubmersion>,
+) {
+    let camera_voxel = player
+        .get_single()
+        .ok()
+        .map(|transform| origin.voxel_at(transform.translation()));
+    let submerged_in = camera_voxel
+        .and_then(|pos| chunks.voxel_at(pos))
+        .filter(|voxel| *voxel != Voxel::EMPTY_VOXEL);
+
+    // the surface is the bottom of the first voxel of another material above the camera.
+    let surface = match (camera_voxel, submerged_in) {
+        (Some(camera_voxel), Some(voxel)) => {
+            let surface_voxel = (1..=MAX_SURFACE_SCAN)
+                .map(|dy| camera_voxel + IVec3::Y * dy)
+                .find(|pos| chunks.voxel_at(*pos) != Some(voxel))
+                .unwrap_or(camera_voxel + IVec3::Y * MAX_SURFACE_SCAN);
+            origin.to_translation(surface_voxel).y
+        }
+        _ => 0.0,
+    };
+
+    let updated = CameraSubmersion {
+        voxel: submerged_in,
+        surface,
+    };
+    if *submersion != updated {
+        *submersion = updated;
+    }
+}
+
+/// Sends [`VoxelContactDamage`] events while the body of the player intersects damaging voxels.
+fn detect_contact_damage(
+    player: Query<(&GlobalTransform, &PlayerController)>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    materials: Res<VoxelMaterialRegistry>,
+    origin: Res<WorldOrigin>,
+    time: Res<Time>,
+    mut damage_events: EventWriter<VoxelContactDamage>,
+) {
+    let (camera_pos, feet_pos) = match player.get_single() {
+        Ok((transform, controller)) => (
+            transform.translation(),
+            transform.translation() - Vec3::Y * controller.eye_height(),
+        ),
+        Err(_) => return,
+    };
+
+    // the most damaging material touched by the body wins.
+    let contact = [camera_pos, feet_pos]
+        .into_iter()
+        .filter_map(|pos| chunks.voxel_at(origin.voxel_at(pos)))
+        .filter_map(|voxel| {
+            materials
+                .get_by_id(voxel.0)
+                .map(|material| (voxel, material.contact_damage))
+        })
+        .filter(|(_, damage)| *damage > 0.0)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    if let Some((voxel, damage)) = contact {
+        damage_events.send(VoxelContactDamage {
+            voxel,
+            damage: damage * time.delta_seconds(),
+        });
+    }
+}
+
+/// Handles the interactions of the player with liquid and damaging voxels.
+pub struct VoxelWorldLiquidsPlugin;
+
+impl Plugin for VoxelWorldLiquidsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<CameraSubmersion>()
+            .add_event::<VoxelContactDamage>()
+            .add_system(update_camera_submersion)
+            .add_system(detect_contact_damage);
+    }
+}
@@ -0,0 +1,131 @@
+use bevy::{
+    math::{UVec2, UVec3, Vec3},
+    prelude::{Color, Component},
+};
+
+use super::{PaddedChunkShape, CHUNK_HEIGHT, CHUNK_LENGTH};
+use crate::voxel::{
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    storage::VoxelBuffer,
+    Voxel,
+};
+
+/// Share of the color of a column taken by each translucent voxel (liquids, foliage), the voxels below showing through
+/// for the rest.
+const TRANSLUCENT_WEIGHT: f32 = 0.5;
+
+/// Color of a material as seen on the maps.
+#[derive(Clone, Copy)]
+pub(super) struct MapColor {
+    rgb: Vec3,
+    translucent: bool,
+}
+
+/// Returns the map color of each material, `None` for the empty voxel.
+pub(super) fn material_map_colors(registry: &VoxelMaterialRegistry) -> [Option<MapColor>; 256] {
+    let mut colors = [None; 256];
+    registry
+        .iter_mats()
+        .enumerate()
+        .skip(1)
+        .for_each(|(id, material)| {
+            let [r, g, b, _] = material.base_color.as_rgba_f32();
+            colors[id] = Some(MapColor {
+                rgb: Vec3::new(r, g, b),
+                translucent: material
+                    .flags
+                    .intersects(VoxelMaterialFlags::LIQUID | VoxelMaterialFlags::FOLIAGE),
+            });
+        });
+    colors
+}
+
+/// The colors of the voxel columns of a chunk as seen from above, baked while meshing the chunk so that the maps don't
+/// scan the voxels and stay up to date with the remeshes.
+#[derive(Component, Clone)]
+pub struct ChunkMapColors {
+    /// Color of each column in rows along the X axis, as sRGB bytes with a zero alpha for the empty columns.
+    colors: Box<[[u8; 4]]>,
+    /// Height (from the bottom of the chunk) of the top of the highest non-empty voxel of each column.
+    heights: Box<[u8]>,
+}
+
+#[allow(dead_code)]
+impl ChunkMapColors {
+    /// Averages the colors of the voxels seen from above in each column of a padded chunk buffer, from the highest
+    /// non-empty voxel down to the first opaque voxel.
+    pub(super) fn compute(
+        buffer: &VoxelBuffer<Voxel, PaddedChunkShape>,
+        material_colors: &[Option<MapColor>; 256],
+    ) -> Self {
+        let columns = (CHUNK_LENGTH * CHUNK_LENGTH) as usize;
+        let mut colors = vec![[0; 4]; columns];
+        let mut heights = vec![0; columns];
+
+        for z in 0..CHUNK_LENGTH {
+            for x in 0..CHUNK_LENGTH {
+                let index = (z * CHUNK_LENGTH + x) as usize;
+                let mut rgb = Vec3::ZERO;
+                let mut remaining = 1.0;
+
+                for y in (0..CHUNK_HEIGHT).rev() {
+                    let voxel = buffer.voxel_at(UVec3::new(x + 1, y + 1, z + 1));
+                    let color = match material_colors[voxel.0 as usize] {
+                        Some(color) => color,
+                        None => continue,
+                    };
+                    if remaining == 1.0 {
+                        heights[index] = (y + 1) as u8;
+                    }
+
+                    let weight = if color.translucent {
+                        remaining * TRANSLUCENT_WEIGHT
+                    } else {
+                        remaining
+                    };
+                    rgb += color.rgb * weight;
+                    remaining -= weight;
+                    if !color.translucent {
+                        break;
+                    }
+                }
+
+                if remaining < 1.0 {
+                    let rgb = rgb / (1.0 - remaining);
+                    colors[index] = Color::rgb(rgb.x, rgb.y, rgb.z).as_rgba_u32().to_le_bytes();
+                }
+            }
+        }
+
+        Self {
+            colors: colors.into_boxed_slice(),
+            heights: heights.into_boxed_slice(),
+        }
+    }
+
+    /// Returns the color of a column from its chunk local position, `None` for the empty columns.
+    pub fn color(&self, local: UVec2) -> Option<Color> {
+        let [r, g, b, a] = *self.colors.get(Self::index(local)?)?;
+        (a != 0).then(|| Color::rgba_u8(r, g, b, a))
+    }
+
+    /// Returns the height (from the bottom of the chunk) of the top of a column from its chunk local position, `None`
+    /// for the empty columns.
+    pub fn height(&self, local: UVec2) -> Option<u32> {
+        let index = Self::index(local)?;
+        (self.colors[index][3] != 0).then(|| self.heights[index] as u32)
+    }
+
+    /// Iterates over the chunk local position, color and height of the non-empty columns.
+    pub fn iter(&self) -> impl Iterator<Item = (UVec2, Color, u32)> + '_ {
+        (0..CHUNK_LENGTH * CHUNK_LENGTH).filter_map(|index| {
+            let local = UVec2::new(index % CHUNK_LENGTH, index / CHUNK_LENGTH);
+            Some((local, self.color(local)?, self.height(local)?))
+        })
+    }
+
+    fn index(local: UVec2) -> Option<usize> {
+        (local.cmplt(UVec2::splat(CHUNK_LENGTH)).all())
+            .then(|| (local.y * CHUNK_LENGTH + local.x) as usize)
+    }
+}
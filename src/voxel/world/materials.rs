@@ -1,7 +1,9 @@
 use bevy::prelude::{Color, Plugin};
 
 use crate::{
-    voxel::material::{MaterialRegistryInfo, VoxelMaterialFlags, VoxelMaterialRegistry},
+    voxel::material::{
+        LightAnimation, MaterialRegistryInfo, VoxelMaterialFlags, VoxelMaterialRegistry,
+    },
     voxel_material,
 };
 
@@ -18,6 +20,10 @@ voxel_material!(Wood, 10);
 voxel_material!(Leaves, 11);
 voxel_material!(PineLeaves, 12);
 voxel_material!(PineWood, 13);
+voxel_material!(Lava, 14);
+voxel_material!(TallGrass, 15);
+voxel_material!(Flower, 16);
+voxel_material!(Gravel, 17);
 
 pub struct VoxelWorldBaseMaterialsPlugin;
 
@@ -30,16 +36,16 @@ impl Plugin for VoxelWorldBaseMaterialsPlugin {
 
         registry.register_material::<Dirt>(MaterialRegistryInfo {
             base_color: Color::rgb_u8(112, 97, 92),
-            name: Dirt::NAME,
-            flags: VoxelMaterialFlags::SOLID,
+            name: Dirt::NAME.into(),
+            flags: VoxelMaterialFlags::SOLID | VoxelMaterialFlags::TRIPLANAR,
             emissive: Color::BLACK,
             ..Default::default()
         });
 
         registry.register_material::<Sand>(MaterialRegistryInfo {
             base_color: Color::rgb_u8(228, 219, 148),
-            name: Sand::NAME,
-            flags: VoxelMaterialFlags::SOLID,
+            name: Sand::NAME.into(),
+            flags: VoxelMaterialFlags::SOLID | VoxelMaterialFlags::TRIPLANAR,
             emissive: Color::BLACK,
             
             ..Default::default()
@@ -47,39 +53,40 @@ impl Plugin for VoxelWorldBaseMaterialsPlugin {
 
         registry.register_material::<Grass>(MaterialRegistryInfo {
             base_color: Color::LIME_GREEN,
-            name: Grass::NAME,
-            flags: VoxelMaterialFlags::SOLID,
+            name: Grass::NAME.into(),
+            flags: VoxelMaterialFlags::SOLID | VoxelMaterialFlags::TRIPLANAR,
             emissive: Color::BLACK,
             ..Default::default()
         });
 
         registry.register_material::<Rock>(MaterialRegistryInfo {
             base_color: Color::GRAY,
-            name: Rock::NAME,
-            flags: VoxelMaterialFlags::SOLID,
+            name: Rock::NAME.into(),
+            flags: VoxelMaterialFlags::SOLID | VoxelMaterialFlags::TRIPLANAR,
             emissive: Color::BLACK,
             ..Default::default()
         });
 
         registry.register_material::<Snow>(MaterialRegistryInfo {
             base_color: Color::WHITE,
-            name: Snow::NAME,
-            flags: VoxelMaterialFlags::SOLID,
+            name: Snow::NAME.into(),
+            flags: VoxelMaterialFlags::SOLID | VoxelMaterialFlags::TRIPLANAR,
             emissive: Color::BLACK,
             ..Default::default()
         });
 
         registry.register_material::<Water>(MaterialRegistryInfo {
             base_color: *Color::rgb_u8(78, 167, 215).set_a(0.4),
-            name: Water::NAME,
+            name: Water::NAME.into(),
             flags: VoxelMaterialFlags::LIQUID,
             emissive: Color::BLACK,
+            flow_interval: Some(0.25),
             ..Default::default()
         });
 
         registry.register_material::<Sandstone>(MaterialRegistryInfo {
             base_color: Color::rgb_u8(198, 192, 144),
-            name: Sandstone::NAME,
+            name: Sandstone::NAME.into(),
             flags: VoxelMaterialFlags::SOLID,
             emissive: Color::BLACK,
             ..Default::default()
@@ -87,7 +94,7 @@ impl Plugin for VoxelWorldBaseMaterialsPlugin {
 
         registry.register_material::<Bedrock>(MaterialRegistryInfo {
             base_color: Color::DARK_GRAY,
-            name: Bedrock::NAME,
+            name: Bedrock::NAME.into(),
             flags: VoxelMaterialFlags::UNBREAKABLE,
             emissive: Color::BLACK,
             ..Default::default()
@@ -95,7 +102,7 @@ impl Plugin for VoxelWorldBaseMaterialsPlugin {
 
         registry.register_material::<Cactus>(MaterialRegistryInfo {
             base_color: Color::rgb_u8(0, 96, 0),
-            name: Cactus::NAME,
+            name: Cactus::NAME.into(),
             flags: VoxelMaterialFlags::SOLID,
             emissive: Color::BLACK,
             ..Default::default()
@@ -103,7 +110,7 @@ impl Plugin for VoxelWorldBaseMaterialsPlugin {
 
         registry.register_material::<Wood>(MaterialRegistryInfo {
             base_color: Color::rgb_u8(188, 147, 97),
-            name: Wood::NAME,
+            name: Wood::NAME.into(),
             flags: VoxelMaterialFlags::SOLID,
             emissive: Color::BLACK,
             ..Default::default()
@@ -111,7 +118,7 @@ impl Plugin for VoxelWorldBaseMaterialsPlugin {
 
         registry.register_material::<Leaves>(MaterialRegistryInfo {
             base_color: Color::rgb_u8(90, 186, 69),
-            name: Leaves::NAME,
+            name: Leaves::NAME.into(),
             flags: VoxelMaterialFlags::SOLID,
             emissive: Color::BLACK,
             ..Default::default()
@@ -119,7 +126,7 @@ impl Plugin for VoxelWorldBaseMaterialsPlugin {
 
         registry.register_material::<PineLeaves>(MaterialRegistryInfo {
             base_color: Color::rgb_u8(135, 201, 167),
-            name: PineLeaves::NAME,
+            name: PineLeaves::NAME.into(),
             flags: VoxelMaterialFlags::SOLID,
             emissive: Color::BLACK,
             ..Default::default()
@@ -127,7 +134,53 @@ impl Plugin for VoxelWorldBaseMaterialsPlugin {
 
         registry.register_material::<PineWood>(MaterialRegistryInfo {
             base_color: Color::rgb_u8(174, 155, 126),
-            name: PineWood::NAME,
+            name: PineWood::NAME.into(),
+            flags: VoxelMaterialFlags::SOLID,
+            emissive: Color::BLACK,
+            ..Default::default()
+        });
+
+        registry.register_material::<Lava>(MaterialRegistryInfo {
+            base_color: Color::rgb_u8(207, 75, 16),
+            name: Lava::NAME.into(),
+            flags: VoxelMaterialFlags::LIQUID,
+            emissive: Color::rgb(1.0, 0.4, 0.05),
+            contact_damage: 4.0,
+            submerged_fog: Some(*Color::rgb_u8(235, 96, 20).set_a(0.95)),
+            // lava creeps much slower than water.
+            flow_interval: Some(1.5),
+            // a slow and deep glow rather than the quick flicker of a flame.
+            light_animation: Some(LightAnimation {
+                temperature: 1800.0,
+                temperature_drift: 250.0,
+                drift_frequency: 0.1,
+                flicker_amplitude: 0.06,
+                flicker_frequency: 0.8,
+            }),
+            ..Default::default()
+        });
+
+        registry.register_material::<TallGrass>(MaterialRegistryInfo {
+            base_color: Color::rgb_u8(104, 176, 58),
+            name: TallGrass::NAME.into(),
+            flags: VoxelMaterialFlags::FOLIAGE,
+            emissive: Color::BLACK,
+            max_render_distance: Some(4),
+            ..Default::default()
+        });
+
+        registry.register_material::<Flower>(MaterialRegistryInfo {
+            base_color: Color::rgb_u8(232, 208, 64),
+            name: Flower::NAME.into(),
+            flags: VoxelMaterialFlags::FOLIAGE,
+            emissive: Color::BLACK,
+            max_render_distance: Some(4),
+            ..Default::default()
+        });
+
+        registry.register_material::<Gravel>(MaterialRegistryInfo {
+            base_color: Color::rgb_u8(138, 132, 124),
+            name: Gravel::NAME.into(),
             flags: VoxelMaterialFlags::SOLID,
             emissive: Color::BLACK,
             ..Default::default()
@@ -1,79 +1,848 @@
-use std::cell::RefCell;
+use std::{
+    collections::VecDeque,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
 
+#[cfg(feature = "gpu_meshing")]
+use super::persistence::ChunkSaveHeaders;
 use super::{
-    chunks::{ChunkEntities, ChunkLoadingStage, DirtyChunks},
-    Chunk, ChunkShape, Voxel, CHUNK_LENGTH,
+    chunk_key_at, chunk_keys_around_voxel,
+    chunks::{
+        sort_by_distance, ChunkEntities, ChunkTaskBudget, CurrentLocalPlayerChunk, DirtyChunks,
+    },
+    diagnostics::CHUNK_MESHING_TIME,
+    fluids::FluidLevels,
+    map_colors::{material_map_colors, ChunkMapColors, MapColor},
+    occlusion::{ChunkConnectivity, ChunkSolidFaces},
+    origin::WorldOrigin,
+    partial_voxels::clear_partial_voxels,
+    pipeline_log::{ChunkDecision, ChunkPipelineLog},
+    stages::{ChunkMeshingPrepareStage, ChunkMeshingStage},
+    stats::ChunkLifecycleEvent,
+    Chunk, ChunkShape, PaddedChunkShape, Voxel, CHUNK_SIZE,
 };
+#[cfg(feature = "gpu_meshing")]
+use crate::voxel::render::{GpuChunkMesh, GpuMeshing};
 use crate::voxel::{
-    render::{mesh_buffer, MeshBuffers, VoxelTerrainMeshBundle},
-    storage::ChunkMap,
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    render::{
+        mesh_buffer, mesh_buffer_patchable, mesh_foliage, patch_mesh_buffer, ChunkMeshBufferReuse,
+        FoliageMaterial, FoliageMaterialHandle, FoliageMesh, MeshBuffers, PatchableMesh,
+        PooledChunkMesh, VoxelTerrainMesh, VoxelTerrainMeshBundle,
+    },
+    storage::{ChunkMap, VoxelBuffer, VoxelMetadataMap},
+    Light,
 };
 use bevy::{
+    diagnostic::Diagnostics,
     prelude::*,
     render::{primitives::Aabb, render_resource::PrimitiveTopology},
     tasks::{AsyncComputeTaskPool, Task},
+    utils::{Duration, HashMap, Instant},
 };
 use futures_lite::future;
+use ndcopy::copy3;
+use ndshape::Shape;
 use once_cell::sync::Lazy;
 use thread_local::ThreadLocal;
 
 /// Attaches to the newly inserted chunk entities components required for rendering.
 pub fn prepare_chunks(
     chunks: Query<(Entity, &Chunk), Added<Chunk>>,
+    origin: Res<WorldOrigin>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut cmds: Commands,
 ) {
     for (chunk, chunk_key) in chunks.iter() {
         cmds.entity(chunk).insert_bundle(VoxelTerrainMeshBundle {
             mesh: meshes.add(Mesh::new(PrimitiveTopology::TriangleList)),
-            transform: Transform::from_translation(chunk_key.0.as_vec3()),
+            transform: Transform::from_translation(origin.to_translation(chunk_key.0)),
             visibility: Visibility { is_visible: false },
-            aabb: Aabb::from_min_max(Vec3::ZERO, Vec3::splat(CHUNK_LENGTH as f32)),
+            aabb: Aabb::from_min_max(Vec3::ZERO, CHUNK_SIZE.as_vec3()),
             ..Default::default()
         });
     }
 }
 
+/// The mesh buffers of a thread, along with the number of frames since they were last used.
+struct PooledMeshBuffers {
+    buffers: MeshBuffers<Voxel, PaddedChunkShape>,
+    idle_frames: u32,
+}
+
 // a pool of mesh buffers shared between meshing tasks.
-static SHARED_MESH_BUFFERS: Lazy<ThreadLocal<RefCell<MeshBuffers<Voxel, ChunkShape>>>> =
+static SHARED_MESH_BUFFERS: Lazy<ThreadLocal<Mutex<PooledMeshBuffers>>> =
     Lazy::new(|| ThreadLocal::default());
 
+/// Policy for releasing the memory of the pooled mesh buffers, which otherwise stay as large as required by the most complex chunk meshed so far.
+pub struct MeshBufferTrimming {
+    /// Number of frames a buffer must stay unused before getting trimmed.
+    pub idle_frames: u32,
+    /// Buffers using less memory than this (in bytes) aren't trimmed.
+    pub max_bytes: usize,
+}
+
+impl Default for MeshBufferTrimming {
+    fn default() -> Self {
+        Self {
+            idle_frames: 120,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// Statistics about the pooled mesh buffers.
+#[derive(Default)]
+pub struct MeshBufferPoolStats {
+    /// Number of pooled mesh buffers, one per thread which ran a meshing task.
+    pub buffers: usize,
+    /// Memory used by the pooled mesh buffers, in bytes.
+    pub memory_bytes: usize,
+    /// Total number of buffer trims.
+    pub trimmed: usize,
+}
+
+/// Trims the pooled mesh buffers which stayed idle for long enough and updates the pool statistics.
+fn trim_mesh_buffers(trimming: Res<MeshBufferTrimming>, mut stats: ResMut<MeshBufferPoolStats>) {
+    stats.buffers = 0;
+    stats.memory_bytes = 0;
+
+    for pooled in SHARED_MESH_BUFFERS.iter() {
+        stats.buffers += 1;
+
+        // buffers locked by a running meshing task aren't idle.
+        if let Ok(mut pooled) = pooled.try_lock() {
+            pooled.idle_frames = pooled.idle_frames.saturating_add(1);
+
+            if pooled.idle_frames >= trimming.idle_frames
+                && pooled.buffers.memory_usage() > trimming.max_bytes
+            {
+                pooled.buffers.trim();
+                stats.trimmed += 1;
+            }
+
+            stats.memory_bytes += pooled.buffers.memory_usage();
+        }
+    }
+}
+
+/// Copies the data of a chunk into a buffer padded with the bordering voxels of its face neighbors.
+/// This lets the mesher cull the faces between solid chunks, unloaded neighbors are treated as empty.
+fn padded_chunk_buffer<V>(
+    chunks: &ChunkMap<V, ChunkShape>,
+    key: IVec3,
+) -> Option<VoxelBuffer<V, PaddedChunkShape>>
+where
+    V: Copy + Default + PartialEq + Eq + Hash,
+{
+    let buffer = chunks.buffer_at(key)?;
+    let mut padded = VoxelBuffer::<V, PaddedChunkShape>::new_empty(PaddedChunkShape {});
+
+    copy3(
+        CHUNK_SIZE.as_uvec3().to_array(),
+        buffer.slice(),
+        buffer.shape(),
+        [0; 3],
+        padded.slice_mut(),
+        &PaddedChunkShape {},
+        [1; 3],
+    );
+
+    for axis in 0..3 {
+        for side in [-1, 1] {
+            let mut offset = IVec3::ZERO;
+            offset[axis] = side * CHUNK_SIZE[axis];
+
+            let neighbor = match chunks.buffer_at(key + offset) {
+                Some(neighbor) => neighbor,
+                None => continue,
+            };
+
+            // copy the 1 voxel thick slice of the neighbor touching the chunk.
+            let chunk_size = CHUNK_SIZE.as_uvec3().to_array();
+            let mut slice_shape = chunk_size;
+            let mut src_min = [0; 3];
+            let mut dst_min = [1; 3];
+            slice_shape[axis] = 1;
+            if side < 0 {
+                src_min[axis] = chunk_size[axis] - 1;
+                dst_min[axis] = 0;
+            } else {
+                dst_min[axis] = chunk_size[axis] + 1;
+            }
+
+            copy3(
+                slice_shape,
+                neighbor.slice(),
+                neighbor.shape(),
+                src_min,
+                padded.slice_mut(),
+                &PaddedChunkShape {},
+                dst_min,
+            );
+        }
+    }
+
+    Some(padded)
+}
+
+/// Returns the distance between two chunks, in chunks along the axis separating them the most.
+fn chunk_distance(key: IVec3, origin: IVec3) -> u32 {
+    ((key - origin) / CHUNK_SIZE).abs().max_element() as u32
+}
+
+/// Returns which materials are too far away to be meshed at the specified chunk distance, or `None` if all of them are meshed.
+fn distance_culled_materials(
+    registry: &VoxelMaterialRegistry,
+    distance: u32,
+) -> Option<[bool; 256]> {
+    let mut culled = [false; 256];
+    let mut any_culled = false;
+
+    registry.iter_mats().enumerate().for_each(|(id, material)| {
+        if material
+            .max_render_distance
+            .map_or(false, |max_distance| distance > max_distance)
+        {
+            culled[id] = true;
+            any_culled = true;
+        }
+    });
+
+    any_culled.then(|| culled)
+}
+
+/// Settings of the material LOD of the far chunks, whose visually similar materials get merged so that the greedy
+/// mesher merges more faces where the difference is invisible anyway.
+pub struct MaterialLodSettings {
+    pub enabled: bool,
+    /// Distance (in chunks) beyond which the materials of the chunks get merged.
+    pub distance: u32,
+    /// Largest distance between the linear RGB base colors of two merged materials.
+    pub max_color_distance: f32,
+}
+
+impl Default for MaterialLodSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            distance: 8,
+            max_color_distance: 0.08,
+        }
+    }
+}
+
+/// The materials merged in the far chunks, computed from the [`MaterialLodSettings`] when queuing the meshing tasks.
+struct MaterialLod {
+    distance: u32,
+    /// The material replacing each material in the far chunks.
+    merged: [u8; 256],
+}
+
+impl MaterialLod {
+    /// Groups the materials with the same flags and similar colors, each group being replaced by its first material.
+    /// Foliage, liquid and emissive materials are never merged, their look not only depending on their base color.
+    /// Returns `None` when disabled or when no material gets merged.
+    fn new(registry: &VoxelMaterialRegistry, settings: &MaterialLodSettings) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+
+        let mut merged = [0; 256];
+        merged
+            .iter_mut()
+            .enumerate()
+            .for_each(|(id, merged)| *merged = id as u8);
+
+        let mut representatives: Vec<(u8, VoxelMaterialFlags, Vec3)> = Vec::new();
+        let mut any_merged = false;
+        for (id, material) in registry.iter_mats().enumerate().skip(1) {
+            if material
+                .flags
+                .intersects(VoxelMaterialFlags::FOLIAGE | VoxelMaterialFlags::LIQUID)
+                || material.emissive != Color::BLACK
+            {
+                continue;
+            }
+
+            let [r, g, b, _] = material.base_color.as_linear_rgba_f32();
+            let color = Vec3::new(r, g, b);
+            match representatives.iter().find(|(_, flags, representative)| {
+                *flags == material.flags
+                    && representative.distance(color) <= settings.max_color_distance
+            }) {
+                Some((representative, _, _)) => {
+                    merged[id] = *representative;
+                    any_merged = true;
+                }
+                None => representatives.push((id as u8, material.flags, color)),
+            }
+        }
+
+        any_merged.then(|| Self {
+            distance: settings.distance,
+            merged,
+        })
+    }
+}
+
+/// Returns the color of each foliage material, `None` for the other materials.
+fn foliage_colors(registry: &VoxelMaterialRegistry) -> [Option<Color>; 256] {
+    let mut colors = [None; 256];
+    registry
+        .iter_mats()
+        .enumerate()
+        .filter(|(_, material)| material.flags.contains(VoxelMaterialFlags::FOLIAGE))
+        .for_each(|(id, material)| colors[id] = Some(material.base_color));
+    colors
+}
+
+/// Empties the foliage voxels of a padded chunk buffer, so that they're ignored by the terrain mesher and the occlusion data.
+/// Returns the chunk local position, material and light of the foliage voxels inside the chunk.
+fn take_foliage_voxels(
+    buffer: &mut VoxelBuffer<Voxel, PaddedChunkShape>,
+    light: &VoxelBuffer<Light, PaddedChunkShape>,
+    foliage_colors: &[Option<Color>; 256],
+) -> Vec<(IVec3, Voxel, Light)> {
+    let mut foliage = Vec::new();
+
+    for (index, voxel) in buffer.slice_mut().iter_mut().enumerate() {
+        if foliage_colors[voxel.0 as usize].is_none() {
+            continue;
+        }
+
+        let padded_pos = UVec3::from(PaddedChunkShape {}.delinearize(index as u32));
+        let pos = padded_pos.as_ivec3() - IVec3::ONE;
+        if pos.cmpge(IVec3::ZERO).all() && pos.cmplt(CHUNK_SIZE).all() {
+            foliage.push((pos, *voxel, light.voxel_at(padded_pos)));
+        }
+        *voxel = Voxel::EMPTY_VOXEL;
+    }
+
+    foliage
+}
+
+/// Queues a remesh of the chunks which crossed the render distance of a material or the material LOD distance since the
+/// player last moved to another chunk.
+fn queue_distance_culled_remesh(
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    registry: Res<VoxelMaterialRegistry>,
+    lod_settings: Res<MaterialLodSettings>,
+    chunk_entities: Res<ChunkEntities>,
+    mut queue: ResMut<ChunkMeshingQueue>,
+    mut log: ResMut<ChunkPipelineLog>,
+    mut previous_chunk: Local<Option<IVec3>>,
+) {
+    if !player_chunk.is_changed() && !registry.is_changed() && !lod_settings.is_changed() {
+        return;
+    }
+
+    let previous = previous_chunk.replace(player_chunk.chunk_min);
+    let mut max_distances: Vec<u32> = registry
+        .iter_mats()
+        .filter_map(|material| material.max_render_distance)
+        .collect();
+    if lod_settings.enabled {
+        max_distances.push(lod_settings.distance);
+    }
+
+    // disabling the material LOD leaves no cutoff but the far chunks still need a remesh.
+    if max_distances.is_empty() && !lod_settings.is_changed() {
+        return;
+    }
+
+    // registry and LOD settings edits may have changed any cutoff, remesh everything in that case.
+    let previous = match previous {
+        Some(previous) if !registry.is_changed() && !lod_settings.is_changed() => previous,
+        _ => {
+            for key in chunk_entities.iter_keys() {
+                queue.queue(*key, ChunkRemeshPriority::Normal);
+                log.record(*key, ChunkDecision::Remesh, || {
+                    "queued, the material render distances or LOD may have changed".to_string()
+                });
+            }
+            return;
+        }
+    };
+
+    for key in chunk_entities.iter_keys() {
+        let old_distance = chunk_distance(*key, previous);
+        let new_distance = chunk_distance(*key, player_chunk.chunk_min);
+
+        if max_distances
+            .iter()
+            .any(|max_distance| (old_distance > *max_distance) != (new_distance > *max_distance))
+        {
+            queue.queue(*key, ChunkRemeshPriority::Normal);
+            log.record(*key, ChunkDecision::Remesh, || {
+                format!(
+                    "queued, crossed the render distance of a material ({} -> {} chunks away)",
+                    old_distance, new_distance
+                )
+            });
+        }
+    }
+}
+
+/// Order in which the queued chunks get meshed, before their distance to the player.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChunkRemeshPriority {
+    /// The chunk holds a voxel edited since it was last meshed.
+    Edited,
+    /// The chunk borders a voxel edited since it was last meshed.
+    EditNeighbor,
+    Normal,
+}
+
+/// The chunks waiting for their meshing task to be spawned, along with their priority. The chunks queued several times
+/// (e.g. by repeated edits) are meshed once, with their highest priority.
+#[derive(Default)]
+pub struct ChunkMeshingQueue {
+    queued: HashMap<IVec3, ChunkRemeshPriority>,
+    /// Generation of the data of each chunk entity, incremented when the chunk gets dirty. The meshes of an older
+    /// generation are outdated, and dropped instead of being applied.
+    generations: HashMap<Entity, u64>,
+}
+
+#[allow(dead_code)]
+impl ChunkMeshingQueue {
+    /// Returns whether the specified chunk is waiting to be meshed.
+    pub fn contains(&self, key: IVec3) -> bool {
+        self.queued.contains_key(&key)
+    }
+
+    /// Returns the priority of a chunk waiting to be meshed.
+    pub fn priority(&self, key: IVec3) -> Option<ChunkRemeshPriority> {
+        self.queued.get(&key).copied()
+    }
+
+    /// Queues a chunk, keeping its priority if it was already queued with a higher one.
+    fn queue(&mut self, key: IVec3, priority: ChunkRemeshPriority) {
+        let queued = self.queued.entry(key).or_insert(priority);
+        *queued = (*queued).min(priority);
+    }
+
+    /// Returns the generation of the data of a chunk entity, the meshes of the older generations being outdated.
+    pub fn generation(&self, entity: Entity) -> u64 {
+        self.generations.get(&entity).copied().unwrap_or_default()
+    }
+
+    /// Returns the number of chunks waiting to be meshed.
+    pub fn len(&self) -> usize {
+        self.queued.len()
+    }
+}
+
+/// The chunks touched by small edits near the player (e.g. breaking a voxel), meshed synchronously on the frame they get
+/// edited instead of waiting behind the meshing tasks, so that edits feel instant. The edited chunks too far away or
+/// over the budget are meshed by tasks, before the other queued chunks.
+pub struct ImmediateChunkRemesh {
+    keys: HashMap<IVec3, ChunkRemeshPriority>,
+    /// The quads of the CPU meshes of the chunks within `max_distance`, patched by their next remesh.
+    patch_bases: HashMap<Entity, Arc<PatchableMesh<Voxel>>>,
+    /// Maximum number of chunks meshed synchronously per frame, the others are meshed by tasks as usual.
+    pub max_chunks: usize,
+    /// Maximum distance (in chunks) between the player and the chunks meshed synchronously.
+    pub max_distance: u32,
+    /// Whether the chunks within `max_distance` get their mesh patched around the changed voxels instead of being
+    /// meshed in full.
+    pub incremental: bool,
+    /// Maximum share of the chunk volume meshed again by a patch, the chunks changing more being meshed in full.
+    pub max_patch_share: f32,
+}
+
+impl Default for ImmediateChunkRemesh {
+    fn default() -> Self {
+        Self {
+            keys: Default::default(),
+            patch_bases: Default::default(),
+            max_chunks: 4,
+            max_distance: 1,
+            incremental: true,
+            max_patch_share: 0.25,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl ImmediateChunkRemesh {
+    /// Requests the immediate remesh of the chunk of the edited voxel at the specified world position,
+    /// along with the neighboring chunks sharing the faces or ambient occlusion of the voxel.
+    /// The chunks still need to be marked dirty for their light to be updated before they get meshed.
+    pub fn queue_edit(&mut self, pos: IVec3) {
+        let key = chunk_key_at(pos);
+        self.keys.insert(key, ChunkRemeshPriority::Edited);
+
+        for neighbor in chunk_keys_around_voxel(pos).skip(1) {
+            self.keys
+                .entry(neighbor)
+                .or_insert(ChunkRemeshPriority::EditNeighbor);
+        }
+    }
+
+    /// Returns how the CPU mesh of a chunk gets patched, `None` for the chunks meshed in full.
+    fn patching(&self, entity: Entity, key: IVec3, player_chunk: IVec3) -> Option<MeshPatching> {
+        (self.incremental && chunk_distance(key, player_chunk) <= self.max_distance).then(|| {
+            MeshPatching {
+                base: self.patch_bases.get(&entity).cloned(),
+                max_volume: ((CHUNK_SIZE.x * CHUNK_SIZE.y * CHUNK_SIZE.z) as f32
+                    * self.max_patch_share) as u32,
+            }
+        })
+    }
+}
+
+/// The previous mesh of a chunk near the player, see [`ImmediateChunkRemesh::incremental`].
+struct MeshPatching {
+    /// The mesh to patch, `None` for the chunks getting near the player.
+    base: Option<Arc<PatchableMesh<Voxel>>>,
+    /// Maximum number of voxels meshed again by the patch.
+    max_volume: u32,
+}
+
+/// Where the vertices of a chunk get generated.
+#[derive(Clone, Copy)]
+enum MeshingBackend {
+    Cpu,
+    #[cfg(feature = "gpu_meshing")]
+    Gpu,
+}
+
+/// The output of a meshing task.
+enum ChunkMesh {
+    /// A mesh along its quads, kept for patching the mesh of the chunks near the player.
+    Cpu(Mesh, Option<Arc<PatchableMesh<Voxel>>>),
+    #[cfg(feature = "gpu_meshing")]
+    Gpu(GpuChunkMesh),
+}
+
+/// The voxels and light of a chunk ready to be meshed, along with how to mesh it.
+struct ChunkMeshingInput {
+    buffer: VoxelBuffer<Voxel, PaddedChunkShape>,
+    light: VoxelBuffer<Light, PaddedChunkShape>,
+    culled: Option<[bool; 256]>,
+    /// The material replacing each material, for the chunks beyond the material LOD distance.
+    merged: Option<[u8; 256]>,
+    backend: MeshingBackend,
+    patching: Option<MeshPatching>,
+}
+
+/// Copies the data required for meshing a chunk, or `None` if the chunk data isn't loaded.
+#[allow(clippy::too_many_arguments)]
+fn chunk_meshing_input(
+    key: IVec3,
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    lights: &ChunkMap<Light, ChunkShape>,
+    fluid_levels: &FluidLevels,
+    metadata: &VoxelMetadataMap,
+    registry: &VoxelMaterialRegistry,
+    material_lod: Option<&MaterialLod>,
+    player_chunk: IVec3,
+    backend: MeshingBackend,
+    patching: Option<MeshPatching>,
+) -> Option<ChunkMeshingInput> {
+    let mut buffer = padded_chunk_buffer(chunks, key)?;
+    // flowing fluid voxels get their own partial height meshes.
+    fluid_levels.clear_flowing_voxels(key, &mut buffer);
+    // so do the partial voxels (e.g. snow layers).
+    clear_partial_voxels(metadata, key, &mut buffer);
+    // chunks not lit yet are meshed in the dark.
+    let light = padded_chunk_buffer(lights, key)
+        .unwrap_or_else(|| VoxelBuffer::<Light, PaddedChunkShape>::new_empty(PaddedChunkShape {}));
+    let distance = chunk_distance(key, player_chunk);
+    let culled = distance_culled_materials(registry, distance);
+    let merged = material_lod
+        .filter(|material_lod| distance > material_lod.distance)
+        .map(|material_lod| material_lod.merged);
+
+    Some(ChunkMeshingInput {
+        buffer,
+        light,
+        culled,
+        merged,
+        backend,
+        patching,
+    })
+}
+
+/// Meshes a chunk, returning the meshing output along the time it took.
+fn mesh_chunk(
+    ChunkMeshingInput {
+        mut buffer,
+        light,
+        culled,
+        merged,
+        backend,
+        patching,
+    }: ChunkMeshingInput,
+    foliage_colors: &[Option<Color>; 256],
+    map_colors: &[Option<MapColor>; 256],
+) -> (ChunkMeshingOutput, Duration) {
+    let start = Instant::now();
+    // the maps show the voxels as they are, regardless of the distance culling and the material LOD.
+    let chunk_map_colors = ChunkMapColors::compute(&buffer, map_colors);
+    let mut foliage = take_foliage_voxels(&mut buffer, &light, foliage_colors);
+
+    // occlusion data ignores the distance culling, which only hides small details.
+    let connectivity = ChunkConnectivity::compute(&buffer);
+    let solid_faces = ChunkSolidFaces::compute(&buffer);
+
+    if let Some(culled) = culled {
+        buffer
+            .slice_mut()
+            .iter_mut()
+            .filter(|voxel| culled[voxel.0 as usize])
+            .for_each(|voxel| *voxel = Voxel::EMPTY_VOXEL);
+        foliage.retain(|(_, voxel, _)| !culled[voxel.0 as usize]);
+    }
+
+    if let Some(merged) = merged {
+        buffer
+            .slice_mut()
+            .iter_mut()
+            .for_each(|voxel| voxel.0 = merged[voxel.0 as usize]);
+    }
+
+    let foliage_mesh = (!foliage.is_empty()).then(|| {
+        let voxels: Vec<(IVec3, Color, Light)> = foliage
+            .into_iter()
+            .map(|(pos, voxel, light)| (pos, foliage_colors[voxel.0 as usize].unwrap(), light))
+            .collect();
+        mesh_foliage(&voxels)
+    });
+
+    let mesh = match backend {
+        MeshingBackend::Cpu => {
+            let mut pooled = SHARED_MESH_BUFFERS
+                .get_or(|| {
+                    Mutex::new(PooledMeshBuffers {
+                        buffers: MeshBuffers::new(PaddedChunkShape {}),
+                        idle_frames: 0,
+                    })
+                })
+                .lock()
+                .unwrap();
+            pooled.idle_frames = 0;
+
+            let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+            let patchable = match patching {
+                Some(MeshPatching { base, max_volume }) => {
+                    let patched = base.and_then(|base| {
+                        patch_mesh_buffer(
+                            &base,
+                            &buffer,
+                            &light,
+                            &mut pooled.buffers,
+                            max_volume,
+                            &mut mesh,
+                        )
+                    });
+                    // large changes get the chunk meshed in full.
+                    Some(Arc::new(patched.unwrap_or_else(|| {
+                        mesh_buffer_patchable(&buffer, &light, &mut pooled.buffers, &mut mesh)
+                    })))
+                }
+                None => {
+                    mesh_buffer(&buffer, &light, &mut pooled.buffers, &mut mesh);
+                    None
+                }
+            };
+            ChunkMesh::Cpu(mesh, patchable)
+        }
+        #[cfg(feature = "gpu_meshing")]
+        MeshingBackend::Gpu => ChunkMesh::Gpu(GpuChunkMesh::new(&buffer, &light)),
+    };
+
+    (
+        (
+            mesh,
+            foliage_mesh,
+            connectivity,
+            solid_faces,
+            chunk_map_colors,
+        ),
+        start.elapsed(),
+    )
+}
+
 /// Queues meshing tasks for the chunks in need of a remesh.
+/// Only [`ChunkTaskBudget::meshing`] tasks are spawned per frame, starting with the chunks closest to the player.
+/// The chunks requested through [`ImmediateChunkRemesh`] are meshed right away instead, their mesh being applied on the same frame.
+#[allow(clippy::too_many_arguments)]
 fn queue_mesh_tasks(
     mut commands: Commands,
     dirty_chunks: Res<DirtyChunks>,
     chunk_entities: Res<ChunkEntities>,
     chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    lights: Res<ChunkMap<Light, ChunkShape>>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    budget: Res<ChunkTaskBudget>,
+    registry: Res<VoxelMaterialRegistry>,
+    lod_settings: Res<MaterialLodSettings>,
+    fluid_levels: Res<FluidLevels>,
+    metadata: Res<VoxelMetadataMap>,
+    #[cfg(feature = "gpu_meshing")] gpu_meshing: Res<GpuMeshing>,
+    #[cfg(feature = "gpu_meshing")] save_headers: Res<ChunkSaveHeaders>,
+    mut queue: ResMut<ChunkMeshingQueue>,
+    mut immediate: ResMut<ImmediateChunkRemesh>,
+    mut completed: ResMut<CompletedChunkMeshes>,
+    mut log: ResMut<ChunkPipelineLog>,
 ) {
     let task_pool = AsyncComputeTaskPool::get();
-    
-    dirty_chunks
-        .iter_dirty()
+    let foliage_colors = Arc::new(foliage_colors(&registry));
+    let map_colors = Arc::new(material_map_colors(&registry));
+    let material_lod = MaterialLod::new(&registry, &lod_settings);
+
+    // freshly generated chunks are meshed on the GPU, edited ones get the fewer triangles of the CPU greedy mesher.
+    #[cfg(feature = "gpu_meshing")]
+    let backend = |key: IVec3| match save_headers.get(key) {
+        Some(header) if header.pristine && gpu_meshing.is_active() => MeshingBackend::Gpu,
+        _ => MeshingBackend::Cpu,
+    };
+    #[cfg(not(feature = "gpu_meshing"))]
+    let backend = |_: IVec3| MeshingBackend::Cpu;
+
+    if chunk_entities.is_changed() {
+        let ChunkMeshingQueue { generations, .. } = &mut *queue;
+        generations.retain(|entity, _| chunk_entities.chunk_key(*entity).is_some());
+    }
+    // the chunks moving away from the player drop the quads of their mesh.
+    let ImmediateChunkRemesh {
+        patch_bases,
+        max_distance,
+        incremental,
+        ..
+    } = &mut *immediate;
+    patch_bases.retain(|entity, _| {
+        *incremental
+            && chunk_entities.chunk_key(*entity).map_or(false, |key| {
+                chunk_distance(key, player_chunk.chunk_min) <= *max_distance
+            })
+    });
+
+    for key in dirty_chunks.iter_dirty() {
+        queue.queue(*key, ChunkRemeshPriority::Normal);
+        log.record(*key, ChunkDecision::Remesh, || {
+            "queued, marked dirty by a change of its voxels, light or neighbors".to_string()
+        });
+
+        // the meshes in flight or waiting to be applied are outdated, the tasks getting cancelled.
+        if let Some(entity) = chunk_entities.entity(*key) {
+            *queue.generations.entry(entity).or_default() += 1;
+            commands.entity(entity).remove::<ChunkMeshingTask>();
+        }
+    }
+    // chunks without an entity or data can't be meshed, they get queued again once dirtied.
+    queue.queued.retain(|key, _| {
+        let meshable = chunk_entities.entity(*key).is_some() && chunks.buffer_at(*key).is_some();
+        if !meshable {
+            log.record(*key, ChunkDecision::Remesh, || {
+                "dropped from the queue, no entity or data".to_string()
+            });
+        }
+        meshable
+    });
+
+    // the chunks too far away or over the budget are left to the meshing tasks.
+    let ImmediateChunkRemesh {
+        keys: immediate_keys,
+        max_chunks,
+        max_distance,
+        ..
+    } = &mut *immediate;
+    // the edited chunks are meshed before their neighbors, repeated edits of a chunk only remeshing it once.
+    let mut edited_keys: Vec<IVec3> = Vec::new();
+    for (key, priority) in immediate_keys.drain() {
+        if queue.contains(key) {
+            queue.queue(key, priority);
+            edited_keys.push(key);
+        }
+    }
+    let mut immediate_keys: Vec<IVec3> = edited_keys
+        .into_iter()
+        .filter(|key| chunk_distance(*key, player_chunk.chunk_min) <= *max_distance)
+        .collect();
+    sort_by_distance(&mut immediate_keys, player_chunk.chunk_min);
+    immediate_keys.sort_by_key(|key| queue.queued[key]);
+    immediate_keys.truncate(*max_chunks);
+
+    for key in immediate_keys {
+        let entity = chunk_entities.entity(key).unwrap();
+        let input = match chunk_meshing_input(
+            key,
+            &chunks,
+            &lights,
+            &fluid_levels,
+            &metadata,
+            &registry,
+            material_lod.as_ref(),
+            player_chunk.chunk_min,
+            MeshingBackend::Cpu,
+            immediate.patching(entity, key, player_chunk.chunk_min),
+        ) {
+            Some(input) => input,
+            None => continue,
+        };
+        queue.queued.remove(&key);
+
+        // the older meshes of the chunk, still in flight or waiting to be applied, would overwrite the new one.
+        commands.entity(entity).remove::<ChunkMeshingTask>();
+        completed
+            .queue
+            .retain(|(completed, _, _)| *completed != entity);
+
+        let (output, _) = mesh_chunk(input, &foliage_colors, &map_colors);
+        completed
+            .immediate
+            .push((entity, queue.generation(entity), output));
+        log.record(key, ChunkDecision::Remesh, || {
+            "meshed immediately after an edit near the player".to_string()
+        });
+    }
+
+    let mut keys: Vec<IVec3> = queue.queued.keys().copied().collect();
+    sort_by_distance(&mut keys, player_chunk.chunk_min);
+    keys.sort_by_key(|key| queue.queued[key]);
+    keys.truncate(budget.meshing);
+
+    keys.into_iter()
         .filter_map(|key| {
-            chunk_entities
-                .entity(*key)
-                .and_then(|entity| Some((key, entity)))
-        })
-        .filter_map(|(key, entity)| {
-            chunks
-                .buffer_at(*key)
-                .and_then(|buffer| Some((buffer.clone(), entity)))
+            queue.queued.remove(&key);
+            let entity = chunk_entities.entity(key)?;
+            log.record(key, ChunkDecision::Remesh, || {
+                format!(
+                    "meshing task spawned, {} chunks away from the player",
+                    chunk_distance(key, player_chunk.chunk_min)
+                )
+            });
+            let backend = backend(key);
+            let patching = matches!(backend, MeshingBackend::Cpu)
+                .then(|| immediate.patching(entity, key, player_chunk.chunk_min))
+                .flatten();
+            chunk_meshing_input(
+                key,
+                &chunks,
+                &lights,
+                &fluid_levels,
+                &metadata,
+                &registry,
+                material_lod.as_ref(),
+                player_chunk.chunk_min,
+                backend,
+                patching,
+            )
+            .map(|input| (entity, queue.generation(entity), input))
         })
-        .map(|(buffer, entity)| {
+        .map(|(entity, generation, input)| {
+            let (foliage_colors, map_colors) = (foliage_colors.clone(), map_colors.clone());
             (
                 entity,
-                ChunkMeshingTask(task_pool.spawn(async move {
-                    let mut mesh_buffers = SHARED_MESH_BUFFERS
-                        .get_or(|| {
-                            RefCell::new(MeshBuffers::<Voxel, ChunkShape>::new(ChunkShape {}))
-                        })
-                        .borrow_mut();
-
-                    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-                    mesh_buffer(&buffer, &mut mesh_buffers, &mut mesh, 1.0);
-
-                    mesh
-                })),
+                ChunkMeshingTask {
+                    task: task_pool
+                        .spawn(async move { mesh_chunk(input, &foliage_colors, &map_colors) }),
+                    generation,
+                },
             )
         })
         .for_each(|(entity, task)| {
@@ -81,43 +850,264 @@ fn queue_mesh_tasks(
         });
 }
 
-/// Polls and process the generated chunk meshes
+/// The entity holding the foliage mesh of a chunk, a child of the chunk entity.
+#[derive(Component)]
+pub struct ChunkFoliage(Entity);
+
+/// The output of a meshing task.
+type ChunkMeshingOutput = (
+    ChunkMesh,
+    Option<Mesh>,
+    ChunkConnectivity,
+    ChunkSolidFaces,
+    ChunkMapColors,
+);
+
+/// The completed chunk meshes waiting to be applied in completion order, along with the generation of the chunk data
+/// they were meshed from.
+#[derive(Default)]
+pub struct CompletedChunkMeshes {
+    queue: VecDeque<(Entity, u64, ChunkMeshingOutput)>,
+    /// The meshes of the chunks remeshed through [`ImmediateChunkRemesh`], applied regardless of the time budget.
+    immediate: Vec<(Entity, u64, ChunkMeshingOutput)>,
+}
+
+impl CompletedChunkMeshes {
+    /// Returns whether the mesh of the specified chunk entity is waiting to be applied.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.queue
+            .iter()
+            .chain(self.immediate.iter())
+            .any(|(completed, _, _)| *completed == entity)
+    }
+
+    /// Returns the number of completed meshes waiting to be applied.
+    pub fn len(&self) -> usize {
+        self.queue.len() + self.immediate.len()
+    }
+}
+
+/// Statistics about the application of the completed chunk meshes.
+#[derive(Default)]
+pub struct ChunkMeshApplyStats {
+    /// Number of meshes applied during the last frame.
+    pub applied: usize,
+    /// Number of completed meshes deferred to the next frames at the end of the last frame.
+    pub deferred: usize,
+    /// Total number of completed meshes which had to wait for a later frame to be applied.
+    pub total_deferred: usize,
+    /// Total number of completed meshes dropped because their chunk got dirty again while they were meshed.
+    pub dropped: usize,
+    /// Time spent applying meshes during the last frame.
+    pub apply_time: Duration,
+}
+
+/// Applies a generated mesh to its chunk entity, along its foliage mesh and occlusion data.
+#[allow(clippy::too_many_arguments)]
+fn apply_chunk_mesh(
+    entity: Entity,
+    (mesh, foliage_mesh, connectivity, solid_faces, map_colors): ChunkMeshingOutput,
+    (handle, visibility, chunk_foliage): (&Handle<Mesh>, &mut Visibility, Option<&ChunkFoliage>),
+    meshes: &mut Assets<Mesh>,
+    foliage_material: &Handle<FoliageMaterial>,
+    buffer_reuse: &ChunkMeshBufferReuse,
+    patch_bases: &mut HashMap<Entity, Arc<PatchableMesh<Voxel>>>,
+    commands: &mut Commands,
+) {
+    match (foliage_mesh, chunk_foliage) {
+        (Some(foliage_mesh), Some(foliage)) => {
+            commands.entity(foliage.0).insert(meshes.add(foliage_mesh));
+        }
+        (Some(foliage_mesh), None) => {
+            let foliage = commands
+                .spawn_bundle(MaterialMeshBundle::<FoliageMaterial> {
+                    mesh: meshes.add(foliage_mesh),
+                    material: foliage_material.clone(),
+                    ..default()
+                })
+                .insert(Aabb::from_min_max(Vec3::ZERO, CHUNK_SIZE.as_vec3()))
+                .insert(FoliageMesh)
+                .id();
+            commands
+                .entity(entity)
+                .add_child(foliage)
+                .insert(ChunkFoliage(foliage));
+        }
+        (None, Some(foliage)) => {
+            commands.entity(foliage.0).despawn();
+            commands.entity(entity).remove::<ChunkFoliage>();
+        }
+        (None, None) => {}
+    }
+
+    let mut chunk = commands.entity(entity);
+    match mesh {
+        ChunkMesh::Cpu(mesh, patchable) => {
+            match patchable {
+                Some(patchable) => patch_bases.insert(entity, patchable),
+                None => patch_bases.remove(&entity),
+            };
+            match buffer_reuse
+                .enabled
+                .then(|| PooledChunkMesh::new(&mesh))
+                .flatten()
+            {
+                // the vertices are written in place into the GPU buffers of the chunk, the mesh asset only gets
+                // replaced by a placeholder once.
+                Some(pooled_mesh) => {
+                    let is_placeholder = meshes
+                        .get(handle)
+                        .and_then(|mesh| mesh.indices())
+                        .map_or(false, |indices| indices.is_empty());
+                    if !is_placeholder {
+                        *meshes.get_mut(handle).unwrap() = VoxelTerrainMesh::placeholder_mesh();
+                    }
+                    chunk.insert(pooled_mesh);
+                }
+                None => {
+                    *meshes.get_mut(handle).unwrap() = mesh;
+                    chunk.remove::<PooledChunkMesh>();
+                }
+            }
+            #[cfg(feature = "gpu_meshing")]
+            chunk.remove::<GpuChunkMesh>();
+        }
+        // the vertices are emitted by a compute shader in the render world.
+        #[cfg(feature = "gpu_meshing")]
+        ChunkMesh::Gpu(gpu_mesh) => {
+            *meshes.get_mut(handle).unwrap() = VoxelTerrainMesh::placeholder_mesh();
+            chunk.insert(gpu_mesh).remove::<PooledChunkMesh>();
+            patch_bases.remove(&entity);
+        }
+    }
+
+    visibility.is_visible = true;
+    chunk
+        .insert(connectivity)
+        .insert(solid_faces)
+        .insert(map_colors);
+}
+
+/// Polls the meshing tasks, then applies the completed meshes in completion order until [`ChunkTaskBudget::mesh_apply_time`] is spent.
+/// The meshes completed during the same frame are queued in a rotating order, so that a burst of completions doesn't starve the same chunks every time.
+#[allow(clippy::too_many_arguments)]
 fn process_mesh_tasks(
     mut meshes: ResMut<Assets<Mesh>>,
-    mut chunk_query: Query<
-        (
-            Entity,
-            &Handle<Mesh>,
-            &mut ChunkMeshingTask,
-            &mut Visibility,
-        ),
-        With<Chunk>,
-    >,
+    foliage_material: Res<FoliageMaterialHandle>,
+    buffer_reuse: Res<ChunkMeshBufferReuse>,
+    budget: Res<ChunkTaskBudget>,
+    queue: Res<ChunkMeshingQueue>,
+    mut immediate: ResMut<ImmediateChunkRemesh>,
+    mut completed: ResMut<CompletedChunkMeshes>,
+    mut stats: ResMut<ChunkMeshApplyStats>,
+    mut tasks: Query<(Entity, &mut ChunkMeshingTask), With<Chunk>>,
+    mut chunk_query: Query<(
+        &Chunk,
+        &Handle<Mesh>,
+        &mut Visibility,
+        Option<&ChunkFoliage>,
+    )>,
+    mut rotation: Local<usize>,
+    mut diagnostics: ResMut<Diagnostics>,
+    mut lifecycle_events: EventWriter<ChunkLifecycleEvent>,
     mut commands: Commands,
 ) {
-    chunk_query.for_each_mut(|(entity, handle, mut mesh_task, mut visibility)| {
-        if let Some(mesh) = future::block_on(future::poll_once(&mut mesh_task.0)) {
-            *meshes.get_mut(handle).unwrap() = mesh;
-            visibility.is_visible = true;
+    let mut newly_completed = Vec::new();
+    tasks.for_each_mut(|(entity, mut mesh_task)| {
+        // the tasks of the chunks remeshed immediately this frame are outdated, and getting removed.
+        if completed
+            .immediate
+            .iter()
+            .any(|(immediate, _, _)| *immediate == entity)
+        {
+            return;
+        }
+        if let Some((output, meshing_time)) =
+            future::block_on(future::poll_once(&mut mesh_task.task))
+        {
+            diagnostics.add_measurement(CHUNK_MESHING_TIME, meshing_time.as_secs_f64() * 1000.0);
             commands.entity(entity).remove::<ChunkMeshingTask>();
+            newly_completed.push((entity, mesh_task.generation, output));
         }
     });
-}
 
-/// A stage existing solely for enabling the use of change detection.
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, StageLabel)]
-pub struct ChunkMeshingPrepareStage;
+    // the query iterates the chunks in a stable order, which would always favor the same chunks.
+    if !newly_completed.is_empty() {
+        let len = newly_completed.len();
+        newly_completed.rotate_left(*rotation % len);
+        *rotation = rotation.wrapping_add(1);
+    }
+    let newly_completed_count = newly_completed.len();
+    completed.queue.extend(newly_completed);
 
-/// Label for the stage housing the chunk meshing systems.
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, StageLabel)]
-pub struct ChunkMeshingStage;
+    let start = Instant::now();
+    stats.applied = 0;
+    let mut immediate_meshes = std::mem::take(&mut completed.immediate);
+    // at least one mesh gets applied per frame, so that the meshing always makes progress.
+    while !immediate_meshes.is_empty()
+        || stats.applied == 0
+        || start.elapsed() < budget.mesh_apply_time
+    {
+        let (entity, generation, output) = match immediate_meshes
+            .pop()
+            .or_else(|| completed.queue.pop_front())
+        {
+            Some(completed) => completed,
+            None => break,
+        };
+        // the chunk got dirty again since it was meshed, its newer mesh is on the way.
+        if generation < queue.generation(entity) {
+            stats.dropped += 1;
+            continue;
+        }
+
+        // the chunk may have been unloaded while its mesh was waiting.
+        if let Ok((chunk, handle, mut visibility, chunk_foliage)) = chunk_query.get_mut(entity) {
+            apply_chunk_mesh(
+                entity,
+                output,
+                (handle, &mut *visibility, chunk_foliage),
+                &mut meshes,
+                &foliage_material.0,
+                &buffer_reuse,
+                &mut immediate.patch_bases,
+                &mut commands,
+            );
+            lifecycle_events.send(ChunkLifecycleEvent::Meshed(chunk.0));
+            stats.applied += 1;
+        }
+    }
+
+    stats.deferred = completed.queue.len();
+    stats.total_deferred += completed.queue.len().min(newly_completed_count);
+    stats.apply_time = start.elapsed();
+}
+
+/// Hides the foliage of the hidden chunks, which doesn't inherit the visibility of its chunk.
+fn sync_foliage_visibility(
+    chunks: Query<(&Visibility, &ChunkFoliage), (With<Chunk>, Changed<Visibility>)>,
+    mut foliage: Query<&mut Visibility, (With<FoliageMesh>, Without<Chunk>)>,
+) {
+    for (chunk_visibility, chunk_foliage) in chunks.iter() {
+        if let Ok(mut visibility) = foliage.get_mut(chunk_foliage.0) {
+            visibility.is_visible = chunk_visibility.is_visible;
+        }
+    }
+}
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`VoxelWorldMeshingPlugin`]
 pub enum ChunkRenderingSystem {
-    /// Queues meshing tasks for the chunks in need of a remesh.
+    /// Attaches the components required for rendering to the newly inserted chunk entities.
+    PrepareChunks,
+
+    /// Queues a remesh of the chunks crossing the render distance of a material or the material LOD distance.
+    QueueDistanceCulledRemesh,
+
+    /// Queues meshing tasks for the chunks in need of a remesh, and meshes the chunks requiring an immediate remesh.
     QueueMeshTasks,
 
-    /// Polls and process the generated chunk meshes.
+    /// Polls the meshing tasks and applies the completed meshes within the frame time budget.
     ProcessMeshTasks,
 }
 
@@ -126,24 +1116,45 @@ pub struct VoxelWorldMeshingPlugin;
 
 impl Plugin for VoxelWorldMeshingPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_stage_after(
-            ChunkLoadingStage,
-            ChunkMeshingPrepareStage,
-            SystemStage::single(prepare_chunks),
-        )
-        .add_stage_after(
-            ChunkMeshingPrepareStage,
-            ChunkMeshingStage,
-            SystemStage::parallel()
-                .with_system(queue_mesh_tasks.label(ChunkRenderingSystem::QueueMeshTasks))
-                .with_system(
-                    process_mesh_tasks
-                        .label(ChunkRenderingSystem::ProcessMeshTasks)
-                        .after(ChunkRenderingSystem::QueueMeshTasks),
-                ),
-        );
+        app.init_resource::<ChunkMeshingQueue>()
+            .init_resource::<ImmediateChunkRemesh>()
+            .init_resource::<CompletedChunkMeshes>()
+            .init_resource::<ChunkMeshApplyStats>()
+            .init_resource::<MeshBufferTrimming>()
+            .init_resource::<MeshBufferPoolStats>()
+            .init_resource::<MaterialLodSettings>()
+            .add_system_to_stage(CoreStage::Last, trim_mesh_buffers)
+            .add_system_to_stage(
+                ChunkMeshingPrepareStage,
+                prepare_chunks.label(ChunkRenderingSystem::PrepareChunks),
+            )
+            .add_system_set_to_stage(
+                ChunkMeshingStage,
+                SystemSet::new()
+                    .with_system(
+                        queue_distance_culled_remesh
+                            .label(ChunkRenderingSystem::QueueDistanceCulledRemesh),
+                    )
+                    .with_system(
+                        queue_mesh_tasks
+                            .label(ChunkRenderingSystem::QueueMeshTasks)
+                            .after(ChunkRenderingSystem::QueueDistanceCulledRemesh),
+                    )
+                    .with_system(
+                        process_mesh_tasks
+                            .label(ChunkRenderingSystem::ProcessMeshTasks)
+                            .after(ChunkRenderingSystem::QueueMeshTasks),
+                    )
+                    .with_system(
+                        sync_foliage_visibility.after(ChunkRenderingSystem::ProcessMeshTasks),
+                    ),
+            );
     }
 }
 
+/// A meshing task in flight, along with the generation of the chunk data it meshes.
 #[derive(Component)]
-pub struct ChunkMeshingTask(Task<Mesh>);
+pub struct ChunkMeshingTask {
+    task: Task<(ChunkMeshingOutput, Duration)>,
+    generation: u64,
+}
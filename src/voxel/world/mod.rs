@@ -1,4 +1,6 @@
 use bevy::{prelude::{Component, Plugin}, math::IVec3};
+#[cfg(not(feature = "headless"))]
+use bevy_rapier3d::plugin::{NoUserData, RapierPhysicsPlugin};
 use ndshape::ConstShape3u32;
 
 use super::{storage::ChunkMap, terraingen, Voxel};
@@ -6,38 +8,386 @@ use super::{storage::ChunkMap, terraingen, Voxel};
 /// Systems for dynamically loading / unloading regions (aka chunks) of the world according to camera position.
 mod chunks;
 pub use chunks::{
-    ChunkCommandQueue, ChunkEntities, ChunkLoadRadius, CurrentLocalPlayerChunk, DirtyChunks,
+    AnchoredChunks, ChunkCommandQueue, ChunkEntities, ChunkLoadAnchor, ChunkLoadRadius,
+    ChunkLoadShape, ChunkLoadingSystem, ChunkTaskBudget, CurrentLocalPlayerChunk, DirtyChunks,
 };
 
+/// Biome specific ambient particles emitted around the camera.
+#[cfg(not(feature = "headless"))]
+mod ambient_particles;
+
+/// Biome definitions and climate sampling used to pick per-column terrain materials.
+pub mod biomes;
+
+#[cfg(not(feature = "headless"))]
 mod chunks_anim;
+
+/// Brushes terraforming the world in bulk.
+#[cfg(not(feature = "headless"))]
+mod brush;
+#[cfg(not(feature = "headless"))]
+pub use brush::{
+    brush_edits, ApplyTerraformBrush, BrushShape, TerraformBrush, TerraformBrushSystem,
+};
+
+/// Explosions carving craters into the terrain.
+#[cfg(not(feature = "headless"))]
+mod explosion;
+#[cfg(not(feature = "headless"))]
+pub use explosion::{
+    explosion_voxels, Explosion, ExplosionDebris, ExplosionRemeshLatency, ExplosionSystem,
+    MAX_EXPLOSION_RADIUS,
+};
+
+/// Point lights cast by the emissive voxels around the player.
+#[cfg(not(feature = "headless"))]
+mod emissive_lights;
+#[cfg(not(feature = "headless"))]
+pub use emissive_lights::{EmissivePointLightSettings, EmissiveVoxelLight};
+
+/// Drops of the broken voxels, picked up by the player.
+#[cfg(not(feature = "headless"))]
+mod drops;
+#[cfg(not(feature = "headless"))]
+pub use drops::{VoxelDrop, VoxelInventory, VoxelPickedUp};
+
+/// Physics colliders of the chunks around the player.
+#[cfg(not(feature = "headless"))]
+mod colliders;
+#[cfg(not(feature = "headless"))]
+pub use colliders::ChunkColliderSettings;
+
+/// In-memory compression of the chunk data.
+mod compression;
+pub use compression::{ChunkCompressionSettings, ChunkMemoryUsage};
+
+/// Diagnostics measuring the chunk generation and meshing pipeline.
+mod diagnostics;
+pub use diagnostics::{
+    CHUNK_GENERATION_QUEUE, CHUNK_GENERATION_TASKS, CHUNK_GENERATION_TIME, CHUNK_MESHES_PENDING,
+    CHUNK_MESHING_QUEUE, CHUNK_MESHING_TASKS, CHUNK_MESHING_TIME,
+};
+
+/// Diagnosis and repair of inconsistencies in the chunk bookkeeping.
+mod integrity;
+pub use integrity::{ChunkIntegrity, ChunkIntegrityReport, ValidateChunks};
+
+/// Undo / redo history of the voxel edits.
+mod journal;
+pub use journal::{
+    JournalEntry, RedoVoxelEdits, UndoVoxelEdits, VoxelEditJournal, VoxelEditJournalSystem,
+};
+
+/// Player interactions with the voxels of the world (targeting, material picking).
+#[cfg(not(feature = "headless"))]
+pub mod interaction;
+
+/// Cellular automaton simulation of flowing fluid voxels.
+mod fluids;
+pub use fluids::{FluidLevels, FluidSettings, MAX_FLUID_LEVEL};
+
+/// Fixed rate ticks of the active voxels (spreading grass, growing crops, refilling fluid sources).
+mod block_ticks;
+pub use block_ticks::{
+    ActiveVoxels, BlockTick, BlockTickHandler, BlockTickHandlers, BlockTickSettings,
+    BlockTickSystem, MAX_GROWTH_STAGE,
+};
+
+/// Submersion in and contact with liquid or damaging voxels.
+#[cfg(not(feature = "headless"))]
+mod liquids;
+#[cfg(not(feature = "headless"))]
+pub use liquids::{CameraSubmersion, VoxelContactDamage};
+
+/// Authored levels, streamed from a fixed world save instead of being generated.
+mod level;
+pub use level::AuthoredLevel;
+
+/// Heights of the highest opaque voxel of the loaded voxel columns.
+mod column_heights;
+pub use column_heights::{ColumnHeightmaps, COLUMN_NO_HEIGHT};
+
+/// Meshes of the partial voxels (e.g. snow layers, slabs), as high as their metadata.
+#[cfg(not(feature = "headless"))]
+mod partial_voxels;
+
+/// Sunlight and block light propagation.
+mod lighting;
+pub use lighting::{LightUpdates, LightingSystem};
+
 pub mod materials;
+#[cfg(not(feature = "headless"))]
 mod meshing;
+#[cfg(not(feature = "headless"))]
+pub use meshing::{
+    ChunkMeshApplyStats, ChunkRemeshPriority, ChunkRenderingSystem, ImmediateChunkRemesh,
+    MaterialLodSettings, MeshBufferPoolStats, MeshBufferTrimming,
+};
+
+/// Export and import of single chunks as human readable text, for debugging.
+mod chunk_text;
+pub use chunk_text::{read_chunk_text, write_chunk_text, ExportChunkText, ImportChunkText};
+
+/// Top-down colors of the chunk columns, baked while meshing the chunks for the maps.
+#[cfg(not(feature = "headless"))]
+mod map_colors;
+#[cfg(not(feature = "headless"))]
+pub use map_colors::ChunkMapColors;
+
+/// Culling of the chunks hidden behind the terrain.
+#[cfg(not(feature = "headless"))]
+mod occlusion;
+#[cfg(not(feature = "headless"))]
+pub use occlusion::ChunkOcclusionCulling;
+
+/// Opt-in log of why the chunks got loaded, unloaded or remeshed.
+mod pipeline_log;
+pub use pipeline_log::{ChunkDecision, ChunkLogEntry, ChunkPipelineLog};
+
+/// Throughput and latency statistics of the chunk pipeline, computed from the chunk lifecycle events.
+mod stats;
+pub use stats::{ChunkLifecycleEvent, ChunkPipelineStats};
+
+/// Public read-only API streaming summaries of the chunk columns to other crates (e.g. maps and minimaps).
+mod observers;
+pub use observers::{
+    ChunkColumnSummaries, ChunkColumnSummary, ColumnSummary, WorldObserver, WorldObserverId,
+    WorldObservers,
+};
+
+/// Floating origin keeping the rendered positions small far away from the world origin.
+mod origin;
+pub use origin::WorldOrigin;
+#[cfg(not(feature = "headless"))]
+pub use origin::{FloatingOriginSettings, WorldOriginShifted};
+
+/// Mapping of the keyboard, mouse and gamepad inputs to the player actions.
+#[cfg(not(feature = "headless"))]
+pub mod input;
+
+/// Saving and loading of chunks to / from a world save.
+pub mod persistence;
+#[cfg(not(feature = "headless"))]
 pub mod player;
+
+/// Background pregeneration of the chunks of an area to the world save.
+mod pregen;
+pub use pregen::{
+    CancelChunkPregen, ChunkPregen, ChunkPregenFinished, ChunkPregenProgress, ChunkPregenSystem,
+    StartChunkPregen,
+};
+
+/// Ray casting against the voxels of the world.
+#[cfg(not(feature = "headless"))]
+pub mod raycast;
+
+/// Recording of the inputs and world events of a session to a replay file, and its playback.
+#[cfg(not(feature = "headless"))]
+mod replay;
+#[cfg(not(feature = "headless"))]
+pub use replay::{
+    Replay, ReplayEvent, ReplayFrame, ReplayPlayer, ReplayRecorder, ReplaySystem,
+    StopReplayRecording, REPLAY_VERSION,
+};
+
+/// Loading of the chunks around the spawn before the player gains control.
+#[cfg(not(feature = "headless"))]
+mod spawn_loading;
+#[cfg(not(feature = "headless"))]
+pub use spawn_loading::{
+    spawn_area_loaded, SpawnLoading, SpawnLoadingProgress, SpawnLoadingSettings,
+};
+
+/// Heightfield of the terrain around the player, used for approximating the shadows cast by the sky light.
+#[cfg(not(feature = "headless"))]
+mod sky_shadows;
+#[cfg(not(feature = "headless"))]
+pub use sky_shadows::{SkyShadowHeightfield, SkyShadowSettings, SKY_SHADOW_NO_HEIGHT};
+
+/// Coarse occupancy and material volume of the voxels around the camera, uploaded for the shader effects.
+#[cfg(not(feature = "headless"))]
+mod voxel_volume;
+#[cfg(not(feature = "headless"))]
+pub use voxel_volume::{VoxelVolume, VoxelVolumeSettings, VOXEL_VOLUME_SCALE};
+
+/// Labels of the stages of the voxel world, their ordering and their pause.
+mod stages;
+pub use stages::{
+    voxel_simulation_running, ChunkLoadingStage, ChunkMeshingPrepareStage, ChunkMeshingStage,
+    LightingStage, TerrainGenStage, VoxelSimulationPause, VoxelWorldStagesPlugin,
+};
+
 mod terrain;
+pub use terrain::{ChunkGenErrors, RetryChunkGen, TerrainGenSystem, MAX_GENERATED_HEIGHT};
 
 /// Registers all resources and systems for simulating and rendering an editable and interactive voxel world.
+#[cfg(not(feature = "headless"))]
 pub struct VoxelWorldPlugin;
 
+#[cfg(not(feature = "headless"))]
 impl Plugin for VoxelWorldPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.insert_resource(ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {}))
-            .add_plugin(chunks::VoxelWorldChunkingPlugin)
-            .add_plugin(meshing::VoxelWorldMeshingPlugin)
-            // ordering of plugin insertion matters here.
-            .add_plugin(terraingen::TerrainGeneratorPlugin)
-            .add_plugin(terrain::VoxelWorldTerrainGenPlugin)
+        app.add_plugin(VoxelWorldSimulationPlugin)
+            .add_plugin(VoxelWorldRenderPlugin);
+    }
+}
+
+/// Registers the resources and systems loading, generating, lighting, simulating, saving and replicating the chunks of the
+/// world, which don't depend on the player nor on the rendering of the world (e.g. for a server streaming the world to its
+/// clients). None of them needs a GPU nor the render app, so that the world runs in a headless app as well.
+pub struct VoxelWorldSimulationPlugin;
+
+impl Plugin for VoxelWorldSimulationPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.insert_resource(
+            ChunkMap::<Voxel, ChunkShape>::new(ChunkShape {})
+                .with_cache_capacity(UNLOADED_CHUNK_CACHE_CAPACITY),
+        )
+        // the stages are added first, so that the plugins can add their systems to any of them.
+        .add_plugin(VoxelWorldStagesPlugin)
+        .add_plugin(origin::FloatingOriginPlugin)
+        .add_plugin(pipeline_log::ChunkPipelineLogPlugin)
+        .add_plugin(stats::ChunkPipelineStatsPlugin)
+        .add_plugin(chunks::VoxelWorldChunkingPlugin)
+        .add_plugin(terraingen::TerrainGeneratorPlugin)
+        .add_plugin(terrain::VoxelWorldTerrainGenPlugin)
+        .add_plugin(pregen::ChunkPregenPlugin)
+        .add_plugin(column_heights::ColumnHeightmapsPlugin)
+        .add_plugin(lighting::VoxelWorldLightingPlugin)
+        .add_plugin(compression::ChunkCompressionPlugin)
+        .add_plugin(super::material::VoxelMaterialPlugin)
+        .add_plugin(materials::VoxelWorldBaseMaterialsPlugin)
+        .add_plugin(super::palette::PaletteImportPlugin)
+        .add_plugin(super::schematic::SchematicPlugin)
+        .add_plugin(persistence::VoxelWorldPersistencePlugin)
+        .add_plugin(level::AuthoredLevelPlugin)
+        .add_plugin(integrity::ChunkIntegrityPlugin)
+        .add_plugin(chunk_text::ChunkTextPlugin)
+        .add_plugin(diagnostics::ChunkDiagnosticsPlugin)
+        .add_plugin(fluids::VoxelWorldFluidsPlugin)
+        .add_plugin(block_ticks::BlockTicksPlugin)
+        .add_plugin(journal::VoxelEditJournalPlugin)
+        .add_plugin(super::net::VoxelReplicationPlugin)
+        .add_plugin(observers::WorldObserversPlugin);
+    }
+}
+
+/// Registers the resources and systems meshing and rendering the chunks around the player, along with the player
+/// controller, its physics and its interactions with the world. Requires the [`VoxelWorldSimulationPlugin`].
+#[cfg(not(feature = "headless"))]
+pub struct VoxelWorldRenderPlugin;
+
+#[cfg(not(feature = "headless"))]
+impl Plugin for VoxelWorldRenderPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_plugin(meshing::VoxelWorldMeshingPlugin)
+            .add_plugin(occlusion::ChunkOcclusionCullingPlugin)
+            .add_plugin(sky_shadows::SkyShadowsPlugin)
+            .add_plugin(emissive_lights::EmissivePointLightsPlugin)
+            .add_plugin(voxel_volume::VoxelVolumePlugin)
             .add_plugin(super::render::VoxelMeshRenderPipelinePlugin)
-            .add_plugin(super::material::VoxelMaterialPlugin)
-            .add_plugin(materials::VoxelWorldBaseMaterialsPlugin)
             .add_plugin(chunks_anim::ChunkAppearanceAnimatorPlugin)
-            .add_plugin(bevy_atmosphere::plugin::AtmospherePlugin)
-            .add_plugin(player::VoxelWorldPlayerControllerPlugin);
+            .add_plugin(ambient_particles::AmbientParticlesPlugin)
+            .add_plugin(super::render::SkyPlugin)
+            .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+            .add_plugin(colliders::ChunkCollidersPlugin)
+            .add_plugin(input::InputMapPlugin)
+            .add_plugin(spawn_loading::SpawnLoadingPlugin)
+            .add_plugin(player::VoxelWorldPlayerControllerPlugin)
+            .add_plugin(interaction::VoxelInteractionPlugin)
+            .add_plugin(brush::TerraformBrushPlugin)
+            .add_plugin(explosion::ExplosionPlugin)
+            .add_plugin(drops::VoxelDropsPlugin)
+            .add_plugin(replay::VoxelWorldReplayPlugin)
+            .add_plugin(super::render::VoxelHighlightPlugin)
+            .add_plugin(liquids::VoxelWorldLiquidsPlugin)
+            // the flowing fluids and the partial voxels build their own meshes.
+            .add_plugin(fluids::FluidMeshesPlugin)
+            .add_plugin(partial_voxels::PartialVoxelMeshesPlugin);
     }
 }
 
-pub const CHUNK_LENGTH: u32 = 32;
+#[cfg(all(feature = "chunk_16", feature = "chunk_64"))]
+compile_error!("the chunk_16 and chunk_64 features are mutually exclusive");
+
+/// Length of the chunk edges selected by the `chunk_16` and `chunk_64` features, 32 voxels by default.
+#[cfg(feature = "chunk_16")]
+const CHUNK_EDGE: u32 = 16;
+#[cfg(feature = "chunk_64")]
+const CHUNK_EDGE: u32 = 64;
+#[cfg(not(any(feature = "chunk_16", feature = "chunk_64")))]
+const CHUNK_EDGE: u32 = 32;
+
+/// Horizontal (X and Z axes) length of the chunks.
+pub const CHUNK_LENGTH: u32 = CHUNK_EDGE;
 pub const CHUNK_LENGTH_U: usize = CHUNK_LENGTH as usize;
-pub type ChunkShape = ConstShape3u32<CHUNK_LENGTH, CHUNK_LENGTH, CHUNK_LENGTH>;
+/// Vertical length of the chunks, taller chunks (e.g. 64) mean fewer chunks to load and mesh along the vertical axis.
+/// Twice the horizontal length with the `chunk_tall` feature (e.g. 32x64x32 chunks).
+#[cfg(feature = "chunk_tall")]
+pub const CHUNK_HEIGHT: u32 = CHUNK_EDGE * 2;
+#[cfg(not(feature = "chunk_tall"))]
+pub const CHUNK_HEIGHT: u32 = CHUNK_EDGE;
+/// Dimensions of the chunks.
+pub const CHUNK_SIZE: IVec3 = IVec3::new(
+    CHUNK_LENGTH as i32,
+    CHUNK_HEIGHT as i32,
+    CHUNK_LENGTH as i32,
+);
+
+// chunk keys are computed by masking world positions, and the mesher packs vertex positions on 7 bits.
+const _: () = assert!(
+    CHUNK_LENGTH.is_power_of_two() && CHUNK_LENGTH < 128,
+    "the chunk length must be a power of two below 128"
+);
+const _: () = assert!(
+    CHUNK_HEIGHT.is_power_of_two() && CHUNK_HEIGHT < 128,
+    "the chunk height must be a power of two below 128, chunk_tall excludes chunk_64"
+);
+
+/// Maximum distance at which the player can interact with voxels, the servers rejecting the edits out of reach.
+pub const PLAYER_REACH: f32 = 12.0;
+
+/// Largest radius of the terraforming brush, in voxels.
+pub const MAX_BRUSH_RADIUS: i32 = 16;
+
+/// Number of recently unloaded chunks kept in memory, so that walking back and forth across a chunk border doesn't regenerate them.
+pub const UNLOADED_CHUNK_CACHE_CAPACITY: usize = 512;
+
+pub type ChunkShape = ConstShape3u32<CHUNK_LENGTH, CHUNK_HEIGHT, CHUNK_LENGTH>;
+
+/// Shape of a chunk padded with a 1 voxel wide border of its neighbors, used for meshing.
+pub type PaddedChunkShape =
+    ConstShape3u32<{ CHUNK_LENGTH + 2 }, { CHUNK_HEIGHT + 2 }, { CHUNK_LENGTH + 2 }>;
+
+/// Returns the key (minimum) of the chunk containing the specified world position.
+#[inline]
+pub fn chunk_key_at(pos: IVec3) -> IVec3 {
+    pos & !(CHUNK_SIZE - IVec3::ONE)
+}
+
+/// Returns the keys of the chunks whose meshes depend on the voxel at the specified world position: its own chunk, then
+/// the up to 7 neighbors sharing its faces, edges or corner (for the face culling and ambient occlusion) when the voxel
+/// lies on the border of its chunk.
+pub fn chunk_keys_around_voxel(pos: IVec3) -> impl Iterator<Item = IVec3> {
+    let key = chunk_key_at(pos);
+    let local = pos - key;
+    let side = |axis: usize| {
+        if local[axis] == 0 {
+            -1
+        } else if local[axis] == CHUNK_SIZE[axis] - 1 {
+            1
+        } else {
+            0
+        }
+    };
+    let sides = IVec3::new(side(0), side(1), side(2));
+
+    // each axis where the voxel lies on the border either keeps the chunk or steps to the neighbor on that side.
+    (0..8)
+        .map(|mask: i32| IVec3::new(mask & 1, (mask >> 1) & 1, (mask >> 2) & 1))
+        .filter(move |steps| (*steps * sides.abs()) == *steps)
+        .map(move |steps| key + steps * sides * CHUNK_SIZE)
+}
 
 // A component tagging an entity as a chunk.
 #[derive(Component)]
@@ -0,0 +1,314 @@
+use bevy::{
+    math::{IVec2, IVec3, UVec2, Vec3Swizzles},
+    prelude::{Plugin, Res, ResMut},
+    utils::{HashMap, HashSet},
+};
+
+use super::{
+    chunks::DirtyChunks, stages::ChunkMeshingStage, ChunkShape, CHUNK_HEIGHT, CHUNK_LENGTH,
+};
+use crate::voxel::{storage::ChunkMap, terraingen::TERRAIN_GENERATOR, Voxel};
+
+/// Maximum number of chunk columns summarized per frame, the others being summarized over the next frames.
+const MAX_SUMMARIES_PER_FRAME: usize = 8;
+
+/// Summary of a column of voxels, as seen from above.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColumnSummary {
+    /// Height of the top of the highest non-empty voxel, `None` for the columns without any loaded non-empty voxel.
+    pub height: Option<i32>,
+    /// Material of the highest non-empty voxel, the empty voxel for the columns without any.
+    pub surface: Voxel,
+    /// Id of the biome of the column.
+    pub biome: u8,
+}
+
+/// Summary of the voxel columns of a chunk column, i.e. the stack of the loaded chunks sharing the same X and Z
+/// coordinates.
+#[derive(Clone, Debug)]
+pub struct ChunkColumnSummary {
+    /// World position (on the X and Z axes) of the first voxel column, the minimum of the chunks of the column.
+    pub origin: IVec2,
+    /// Summaries of the voxel columns, in rows along the X axis.
+    pub columns: Box<[ColumnSummary]>,
+}
+
+#[allow(dead_code)]
+impl ChunkColumnSummary {
+    /// Returns the summary of a voxel column, from its local position in the chunk column.
+    pub fn column(&self, local: UVec2) -> Option<&ColumnSummary> {
+        if local.cmpge(UVec2::splat(CHUNK_LENGTH)).any() {
+            return None;
+        }
+        self.columns
+            .get((local.y * CHUNK_LENGTH + local.x) as usize)
+    }
+
+    /// Iterates over the world positions (on the X and Z axes) and summaries of the voxel columns.
+    pub fn iter(&self) -> impl Iterator<Item = (IVec2, &ColumnSummary)> + '_ {
+        self.columns.iter().enumerate().map(|(index, summary)| {
+            let local = IVec2::new(
+                index as i32 % CHUNK_LENGTH as i32,
+                index as i32 / CHUNK_LENGTH as i32,
+            );
+            (self.origin + local, summary)
+        })
+    }
+}
+
+/// Read-only observer of the world, notified of the chunk columns getting loaded, modified and unloaded, so that the
+/// maps, minimaps and overlays of other crates don't depend on the internals of the chunk pipeline.
+///
+/// The observers are notified from the [`ChunkMeshingStage`], on the main thread.
+pub trait WorldObserver: Send + Sync + 'static {
+    /// Called when a chunk column got loaded or modified, with its up to date summary.
+    fn column_changed(&mut self, summary: &ChunkColumnSummary);
+
+    /// Called when the last loaded chunk of a chunk column got unloaded.
+    fn column_unloaded(&mut self, _origin: IVec2) {}
+}
+
+/// Identifies a [`WorldObserver`] registered to the [`WorldObservers`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WorldObserverId(u32);
+
+/// The registered [`WorldObserver`]s. No chunk column gets summarized while none is registered.
+#[derive(Default)]
+pub struct WorldObservers {
+    observers: Vec<(WorldObserverId, Box<dyn WorldObserver>)>,
+    /// Observers registered since the last update, which first get the summaries of the already loaded chunk columns.
+    registered: Vec<(WorldObserverId, Box<dyn WorldObserver>)>,
+    next_id: u32,
+}
+
+#[allow(dead_code)]
+impl WorldObservers {
+    /// Registers an observer, notified of the chunk columns already loaded and then of the changes to the chunk
+    /// columns.
+    pub fn register(&mut self, observer: Box<dyn WorldObserver>) -> WorldObserverId {
+        let id = WorldObserverId(self.next_id);
+        self.next_id += 1;
+        self.registered.push((id, observer));
+        id
+    }
+
+    /// Unregisters an observer, returning it if it was registered.
+    pub fn unregister(&mut self, id: WorldObserverId) -> Option<Box<dyn WorldObserver>> {
+        [&mut self.observers, &mut self.registered]
+            .into_iter()
+            .find_map(|observers| {
+                let index = observers
+                    .iter()
+                    .position(|(observer_id, _)| *observer_id == id)?;
+                Some(observers.remove(index).1)
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.len() + self.registered.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The summaries of the loaded chunk columns, kept up to date while [`WorldObserver`]s are registered.
+#[derive(Default)]
+pub struct ChunkColumnSummaries {
+    summaries: HashMap<IVec2, ChunkColumnSummary>,
+    /// Chunk keys (on the Y axis) of the loaded chunks of each chunk column.
+    loaded: HashMap<IVec2, HashSet<i32>>,
+    /// Chunk columns waiting to be summarized.
+    queued: HashSet<IVec2>,
+    tracking: bool,
+}
+
+#[allow(dead_code)]
+impl ChunkColumnSummaries {
+    /// Returns the summary of the chunk column at the specified origin, if loaded and summarized.
+    pub fn get(&self, origin: IVec2) -> Option<&ChunkColumnSummary> {
+        self.summaries.get(&origin)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ChunkColumnSummary> {
+        self.summaries.values()
+    }
+
+    /// Returns the number of chunk columns waiting to be summarized.
+    pub fn num_queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    fn track_chunk(&mut self, key: IVec3) {
+        self.loaded.entry(key.xz()).or_default().insert(key.y);
+        self.queued.insert(key.xz());
+    }
+}
+
+/// Summarizes the voxel columns of a chunk column from the highest loaded chunk down.
+fn summarize_chunk_column(
+    origin: IVec2,
+    chunk_keys_y: &HashSet<i32>,
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    biomes: &[u8],
+) -> ChunkColumnSummary {
+    let mut chunk_keys_y: Vec<i32> = chunk_keys_y.iter().copied().collect();
+    chunk_keys_y.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut columns: Vec<ColumnSummary> = biomes
+        .iter()
+        .map(|biome| ColumnSummary {
+            height: None,
+            surface: Voxel::EMPTY_VOXEL,
+            biome: *biome,
+        })
+        .collect();
+
+    for y in chunk_keys_y {
+        let buffer = match chunks.buffer_at(IVec3::new(origin.x, y, origin.y)) {
+            Some(buffer) => buffer,
+            None => continue,
+        };
+
+        for z in 0..CHUNK_LENGTH {
+            for x in 0..CHUNK_LENGTH {
+                let column = &mut columns[(z * CHUNK_LENGTH + x) as usize];
+                if column.height.is_some() {
+                    continue;
+                }
+
+                if let Some((local_y, voxel)) = (0..CHUNK_HEIGHT)
+                    .rev()
+                    .map(|local_y| (local_y, buffer.voxel_at([x, local_y, z].into())))
+                    .find(|(_, voxel)| *voxel != Voxel::EMPTY_VOXEL)
+                {
+                    column.height = Some(y + local_y as i32 + 1);
+                    column.surface = voxel;
+                }
+            }
+        }
+
+        if columns.iter().all(|column| column.height.is_some()) {
+            break;
+        }
+    }
+
+    ChunkColumnSummary {
+        origin,
+        columns: columns.into_boxed_slice(),
+    }
+}
+
+/// Keeps the summaries of the loaded chunk columns up to date and notifies the registered observers of their changes.
+fn update_chunk_column_summaries(
+    dirty_chunks: Res<DirtyChunks>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    mut summaries: ResMut<ChunkColumnSummaries>,
+    mut observers: ResMut<WorldObservers>,
+) {
+    if observers.is_empty() {
+        if summaries.tracking {
+            *summaries = Default::default();
+        }
+        return;
+    }
+
+    // the chunks loaded before the first observer got registered aren't dirty anymore.
+    if !summaries.tracking {
+        summaries.tracking = true;
+        chunks
+            .iter_keys()
+            .for_each(|key| summaries.track_chunk(key));
+    }
+
+    dirty_chunks
+        .iter_dirty()
+        .filter(|key| chunks.exists(**key))
+        .for_each(|key| summaries.track_chunk(*key));
+
+    let WorldObservers {
+        observers: registered_observers,
+        registered,
+        ..
+    } = &mut *observers;
+
+    if chunks.is_changed() {
+        let ChunkColumnSummaries {
+            summaries: column_summaries,
+            loaded,
+            queued,
+            ..
+        } = &mut *summaries;
+
+        loaded.retain(|origin, chunk_keys_y| {
+            let count = chunk_keys_y.len();
+            chunk_keys_y.retain(|y| chunks.exists(IVec3::new(origin.x, *y, origin.y)));
+            if chunk_keys_y.is_empty() {
+                queued.remove(origin);
+                if column_summaries.remove(origin).is_some() {
+                    registered_observers
+                        .iter_mut()
+                        .for_each(|(_, observer)| observer.column_unloaded(*origin));
+                }
+                return false;
+            }
+
+            if chunk_keys_y.len() != count {
+                queued.insert(*origin);
+            }
+            true
+        });
+    }
+
+    for (id, mut observer) in registered.drain(..) {
+        summaries
+            .summaries
+            .values()
+            .for_each(|summary| observer.column_changed(summary));
+        registered_observers.push((id, observer));
+    }
+
+    let batch: Vec<IVec2> = summaries
+        .queued
+        .iter()
+        .take(MAX_SUMMARIES_PER_FRAME)
+        .copied()
+        .collect();
+    if batch.is_empty() {
+        return;
+    }
+
+    let generator = TERRAIN_GENERATOR.read().unwrap();
+    for origin in batch {
+        summaries.queued.remove(&origin);
+
+        let biome_map = generator
+            .biomes()
+            .biome_map(IVec3::new(origin.x, 0, origin.y));
+        let mut biomes = Vec::with_capacity((CHUNK_LENGTH * CHUNK_LENGTH) as usize);
+        for z in 0..CHUNK_LENGTH {
+            for x in 0..CHUNK_LENGTH {
+                biomes.push(biome_map.get([x, z]));
+            }
+        }
+
+        let summary = summarize_chunk_column(origin, &summaries.loaded[&origin], &chunks, &biomes);
+        registered_observers
+            .iter_mut()
+            .for_each(|(_, observer)| observer.column_changed(&summary));
+        summaries.summaries.insert(origin, summary);
+    }
+}
+
+/// Streams the summaries of the chunk columns (surface material, height and biome of each voxel column) to the
+/// registered [`WorldObserver`]s as the chunks get loaded, modified and unloaded.
+pub struct WorldObserversPlugin;
+
+impl Plugin for WorldObserversPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<WorldObservers>()
+            .init_resource::<ChunkColumnSummaries>()
+            .add_system_to_stage(ChunkMeshingStage, update_chunk_column_summaries);
+    }
+}
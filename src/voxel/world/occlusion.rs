@@ -0,0 +1,260 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    math::IVec3,
+    prelude::{
+        Component, ComputedVisibility, CoreStage, ParallelSystemDescriptorCoercion, Plugin, Query,
+        Res, ResMut, With,
+    },
+    render::view::VisibilitySystems,
+    utils::HashSet,
+};
+
+use super::{
+    chunks::{ChunkEntities, CurrentLocalPlayerChunk},
+    Chunk, PaddedChunkShape, CHUNK_SIZE,
+};
+use crate::voxel::{storage::VoxelBuffer, Voxel};
+
+/// Normals of the chunk faces, in the same order as the faces of the meshing config.
+const FACE_NORMALS: [IVec3; 6] = [
+    IVec3::NEG_X,
+    IVec3::NEG_Y,
+    IVec3::NEG_Z,
+    IVec3::X,
+    IVec3::Y,
+    IVec3::Z,
+];
+
+#[inline]
+const fn opposite_face(face: usize) -> usize {
+    (face + 3) % 6
+}
+
+/// Which faces of a chunk can see each other through the empty voxels of the chunk.
+/// Bit `b` of the mask at index `a` is set when face `a` connects to face `b`.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkConnectivity([u8; 6]);
+
+impl Default for ChunkConnectivity {
+    /// All the faces are connected to each other, this is how chunks without known connectivity are handled.
+    fn default() -> Self {
+        Self([0b111111; 6])
+    }
+}
+
+impl ChunkConnectivity {
+    #[inline]
+    pub fn connects(&self, from: usize, to: usize) -> bool {
+        self.0[from] & (1 << to) != 0
+    }
+
+    /// Computes the connectivity of a chunk by flood filling its empty voxels.
+    /// The buffer is padded like the meshing buffers, the padding is ignored.
+    pub fn compute(padded_buffer: &VoxelBuffer<Voxel, PaddedChunkShape>) -> Self {
+        let index = |pos: IVec3| (pos.x + CHUNK_SIZE.x * (pos.y + CHUNK_SIZE.y * pos.z)) as usize;
+        let is_empty = |pos: IVec3| {
+            padded_buffer.voxel_at((pos + IVec3::ONE).as_uvec3()) == Voxel::EMPTY_VOXEL
+        };
+
+        let mut connectivity = Self([0; 6]);
+        let mut visited = vec![false; (CHUNK_SIZE.x * CHUNK_SIZE.y * CHUNK_SIZE.z) as usize];
+        let mut queue = VecDeque::new();
+
+        for x in 0..CHUNK_SIZE.x {
+            for y in 0..CHUNK_SIZE.y {
+                for z in 0..CHUNK_SIZE.z {
+                    let start = IVec3::new(x, y, z);
+                    if visited[index(start)] || !is_empty(start) {
+                        continue;
+                    }
+
+                    visited[index(start)] = true;
+                    queue.push_back(start);
+                    let mut faces = 0u8;
+
+                    while let Some(pos) = queue.pop_front() {
+                        for (face, normal) in FACE_NORMALS.iter().enumerate() {
+                            let neighbor = pos + *normal;
+                            if neighbor.cmplt(IVec3::ZERO).any() || neighbor.cmpge(CHUNK_SIZE).any()
+                            {
+                                faces |= 1 << face;
+                                continue;
+                            }
+
+                            if !visited[index(neighbor)] && is_empty(neighbor) {
+                                visited[index(neighbor)] = true;
+                                queue.push_back(neighbor);
+                            }
+                        }
+                    }
+
+                    for face in 0..6 {
+                        if faces & (1 << face) != 0 {
+                            connectivity.0[face] |= faces;
+                        }
+                    }
+
+                    // no need to go further once every face sees every other.
+                    if connectivity == Self::default() {
+                        return connectivity;
+                    }
+                }
+            }
+        }
+
+        connectivity
+    }
+}
+
+/// Which faces of a chunk are fully solid (i.e. without any empty voxel), bit `f` being set when face `f` is.
+/// Nothing can be seen through a fully solid face, whatever the connectivity of the chunks on both sides.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChunkSolidFaces(u8);
+
+impl ChunkSolidFaces {
+    #[inline]
+    pub fn is_solid(&self, face: usize) -> bool {
+        self.0 & (1 << face) != 0
+    }
+
+    /// Computes the solid faces of a chunk by scanning the voxel layers along its borders.
+    /// The buffer is padded like the meshing buffers, the padding is ignored.
+    pub fn compute(padded_buffer: &VoxelBuffer<Voxel, PaddedChunkShape>) -> Self {
+        let mut solid_faces = 0;
+
+        for (face, normal) in FACE_NORMALS.iter().enumerate() {
+            let axis = normal
+                .abs()
+                .to_array()
+                .iter()
+                .position(|c| *c != 0)
+                .unwrap();
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let layer = if normal[axis] < 0 {
+                0
+            } else {
+                CHUNK_SIZE[axis] - 1
+            };
+
+            let solid = (0..CHUNK_SIZE[u]).all(|a| {
+                (0..CHUNK_SIZE[v]).all(|b| {
+                    let mut pos = IVec3::ZERO;
+                    pos[axis] = layer;
+                    pos[u] = a;
+                    pos[v] = b;
+                    padded_buffer.voxel_at((pos + IVec3::ONE).as_uvec3()) != Voxel::EMPTY_VOXEL
+                })
+            });
+
+            if solid {
+                solid_faces |= 1 << face;
+            }
+        }
+
+        Self(solid_faces)
+    }
+}
+
+/// Settings and statistics of the chunk occlusion culling.
+pub struct ChunkOcclusionCulling {
+    pub enabled: bool,
+    /// Number of chunks hidden behind the terrain during the last frame.
+    pub culled: usize,
+}
+
+impl Default for ChunkOcclusionCulling {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            culled: 0,
+        }
+    }
+}
+
+/// Hides the chunks which can't be seen from the chunk of the player, using a flood fill through the chunk face connectivity graph.
+/// The flood fill never walks back in a direction opposite to one it already took, so it doesn't leak around corners.
+/// It doesn't cross fully solid faces either: the chunk behind a solid face is only seen if reached another way,
+/// and the flood fill stops at chunks entered through one of their solid faces.
+fn cull_occluded_chunks(
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    chunk_entities: Res<ChunkEntities>,
+    connectivities: Query<&ChunkConnectivity>,
+    solid_faces: Query<&ChunkSolidFaces>,
+    mut chunks: Query<(&Chunk, &mut ComputedVisibility), With<ChunkConnectivity>>,
+    mut culling: ResMut<ChunkOcclusionCulling>,
+) {
+    if !culling.enabled {
+        culling.culled = 0;
+        return;
+    }
+
+    let connectivity_at = |key: IVec3| {
+        chunk_entities
+            .entity(key)
+            .and_then(|entity| connectivities.get(entity).ok().copied())
+            .unwrap_or_default()
+    };
+    let solid_faces_at = |key: IVec3| {
+        chunk_entities
+            .entity(key)
+            .and_then(|entity| solid_faces.get(entity).ok().copied())
+            .unwrap_or_default()
+    };
+
+    let mut reachable = HashSet::default();
+    let mut queue = VecDeque::new();
+
+    reachable.insert(player_chunk.chunk_min);
+    queue.push_back((player_chunk.chunk_min, None, 0u8));
+
+    while let Some((key, entered_face, directions)) = queue.pop_front() {
+        let connectivity = connectivity_at(key);
+        let solid = solid_faces_at(key);
+
+        for (face, normal) in FACE_NORMALS.iter().enumerate() {
+            if directions & (1 << opposite_face(face)) != 0 || solid.is_solid(face) {
+                continue;
+            }
+
+            if let Some(entered_face) = entered_face {
+                if !connectivity.connects(entered_face, face) {
+                    continue;
+                }
+            }
+
+            let neighbor = key + *normal * CHUNK_SIZE;
+            if chunk_entities.entity(neighbor).is_none() || !reachable.insert(neighbor) {
+                continue;
+            }
+
+            // the solid face of the neighbor is visible, but nothing behind it.
+            if solid_faces_at(neighbor).is_solid(opposite_face(face)) {
+                continue;
+            }
+
+            queue.push_back((neighbor, Some(opposite_face(face)), directions | 1 << face));
+        }
+    }
+
+    culling.culled = 0;
+    for (chunk, mut visibility) in chunks.iter_mut() {
+        if visibility.is_visible && !reachable.contains(&chunk.0) {
+            visibility.is_visible = false;
+            culling.culled += 1;
+        }
+    }
+}
+
+/// Culls the chunks hidden behind the terrain, on top of the frustum culling done by bevy.
+pub struct ChunkOcclusionCullingPlugin;
+
+impl Plugin for ChunkOcclusionCullingPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkOcclusionCulling>()
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                cull_occluded_chunks.after(VisibilitySystems::CheckVisibility),
+            );
+    }
+}
@@ -0,0 +1,133 @@
+#[cfg(not(feature = "headless"))]
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::{
+        CoreStage, EventWriter, Node, ParallelSystemDescriptorCoercion, Parent, Query, Res, ResMut,
+        Transform, Without,
+    },
+    transform::TransformSystem,
+};
+use bevy::{
+    math::{IVec3, Vec3},
+    prelude::Plugin,
+};
+
+#[cfg(not(feature = "headless"))]
+use super::{chunk_key_at, player::PlayerController};
+
+/// World position (in voxels) of the origin of the rendered scene: the translation of an entity is relative to it,
+/// so that the positions around the camera stay small and keep their float precision far away from the world origin.
+/// The origin is aligned on the chunk grid and only moves horizontally, the height of the world being bounded.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WorldOrigin(IVec3);
+
+#[allow(dead_code)]
+impl WorldOrigin {
+    /// Returns the world position of the origin.
+    #[inline]
+    pub fn get(&self) -> IVec3 {
+        self.0
+    }
+
+    /// Returns the world position of the voxel containing the specified translation.
+    #[inline]
+    pub fn voxel_at(&self, translation: Vec3) -> IVec3 {
+        translation.floor().as_ivec3() + self.0
+    }
+
+    /// Returns the world position of the specified translation, losing precision far away from the world origin.
+    #[inline]
+    pub fn to_world(&self, translation: Vec3) -> Vec3 {
+        translation + self.0.as_vec3()
+    }
+
+    /// Returns the translation of the specified world position.
+    #[inline]
+    pub fn to_translation(&self, position: IVec3) -> Vec3 {
+        (position - self.0).as_vec3()
+    }
+}
+
+/// Settings of the re-basing of the [`WorldOrigin`] around the player.
+#[cfg(not(feature = "headless"))]
+pub struct FloatingOriginSettings {
+    pub enabled: bool,
+    /// Horizontal distance (in voxels) between the player and the origin beyond which the origin is moved to the player.
+    pub rebase_distance: f32,
+}
+
+#[cfg(not(feature = "headless"))]
+impl Default for FloatingOriginSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rebase_distance: 2048.0,
+        }
+    }
+}
+
+/// Event sent when the [`WorldOrigin`] moves, the translations of the entities having been offset by `previous - current`.
+#[cfg(not(feature = "headless"))]
+pub struct WorldOriginShifted {
+    pub previous: IVec3,
+    pub current: IVec3,
+}
+
+/// Moves the origin to the chunk of the player once it wanders too far away from it, and offsets all the root entities
+/// (chunks, player, particles...) accordingly. The children follow their parent, and the UI nodes are laid out on the screen.
+#[cfg(not(feature = "headless"))]
+fn rebase_world_origin(
+    settings: Res<FloatingOriginSettings>,
+    mut origin: ResMut<WorldOrigin>,
+    mut entities: Query<
+        (&mut Transform, Option<&PlayerController>),
+        (Without<Parent>, Without<Node>),
+    >,
+    mut shifted_events: EventWriter<WorldOriginShifted>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    let player = match entities
+        .iter()
+        .find_map(|(transform, controller)| controller.map(|_| transform.translation))
+    {
+        Some(player) => player,
+        None => return,
+    };
+
+    if player.xz().length() <= settings.rebase_distance {
+        return;
+    }
+
+    let mut shift = chunk_key_at(player.floor().as_ivec3());
+    shift.y = 0;
+    let offset = shift.as_vec3();
+    entities.for_each_mut(|(mut transform, _)| transform.translation -= offset);
+
+    let previous = origin.0;
+    origin.0 += shift;
+    shifted_events.send(WorldOriginShifted {
+        previous,
+        current: origin.0,
+    });
+}
+
+/// Keeps the rendered scene around the player through a floating [`WorldOrigin`].
+pub struct FloatingOriginPlugin;
+
+impl Plugin for FloatingOriginPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<WorldOrigin>();
+
+        // the origin follows the local player, which only exists when the world gets rendered.
+        #[cfg(not(feature = "headless"))]
+        app.init_resource::<FloatingOriginSettings>()
+            .add_event::<WorldOriginShifted>()
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                rebase_world_origin.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
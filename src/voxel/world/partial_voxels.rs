@@ -0,0 +1,182 @@
+use bevy::{
+    math::IVec3,
+    prelude::{
+        default, Assets, Color, Commands, DespawnRecursiveExt, Entity, Handle, Local, Mesh,
+        PbrBundle, Plugin, Res, ResMut, StandardMaterial, Transform,
+    },
+    utils::HashMap,
+};
+
+use super::{
+    chunks::{ChunkEntities, DirtyChunks},
+    origin::WorldOrigin,
+    stages::ChunkMeshingStage,
+    ChunkShape, PaddedChunkShape, CHUNK_SIZE,
+};
+use crate::voxel::{
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    render::mesh_fluid_voxels,
+    storage::{ChunkMap, VoxelBuffer, VoxelMetadataMap},
+    Voxel,
+};
+
+/// Empties the partial voxels (e.g. snow layers, slabs) of a padded chunk buffer, so that they're left out of the terrain
+/// mesh and get their own partial height meshes.
+pub fn clear_partial_voxels(
+    metadata: &VoxelMetadataMap,
+    key: IVec3,
+    padded: &mut VoxelBuffer<Voxel, PaddedChunkShape>,
+) {
+    let padded_max = CHUNK_SIZE + IVec3::ONE;
+
+    for neighbor in (-1..=1)
+        .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z))))
+    {
+        let neighbor_key = key + neighbor * CHUNK_SIZE;
+        let chunk = match metadata.chunk(neighbor_key) {
+            Some(chunk) => chunk,
+            None => continue,
+        };
+
+        for (pos, voxel_metadata) in chunk.0.iter() {
+            if voxel_metadata.partial_height().is_none() {
+                continue;
+            }
+            let local = neighbor_key + pos.as_ivec3() - key + IVec3::ONE;
+            if local.cmpge(IVec3::ZERO).all() && local.cmple(padded_max).all() {
+                *padded.voxel_at_mut(local.as_uvec3()) = Voxel::EMPTY_VOXEL;
+            }
+        }
+    }
+}
+
+/// The entities holding the meshes of the partial voxels of the chunks, by chunk and material.
+#[derive(Default)]
+struct ChunkPartialVoxelMeshes(HashMap<(IVec3, u8), Entity>);
+
+/// Remeshes the partial voxels of the dirty chunks with the partial height mesher of the fluids, a mesh per material.
+#[allow(clippy::too_many_arguments)]
+fn update_partial_voxel_meshes(
+    dirty_chunks: Res<DirtyChunks>,
+    chunk_entities: Res<ChunkEntities>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    metadata: Res<VoxelMetadataMap>,
+    registry: Res<VoxelMaterialRegistry>,
+    origin: Res<WorldOrigin>,
+    mut partial_meshes: ResMut<ChunkPartialVoxelMeshes>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut partial_materials: Local<HashMap<u8, Handle<StandardMaterial>>>,
+    mut commands: Commands,
+) {
+    partial_meshes.0.retain(|(key, _), entity| {
+        let keep = chunk_entities.entity(*key).is_some();
+        if !keep {
+            commands.entity(*entity).despawn_recursive();
+        }
+        keep
+    });
+
+    if registry.is_changed() {
+        for (id, handle) in partial_materials.iter() {
+            if let (Some(info), Some(material)) =
+                (registry.get_by_id(*id), materials.get_mut(handle))
+            {
+                material.base_color = info.base_color;
+                material.emissive = info.emissive;
+            }
+        }
+    }
+
+    // height of the solid part of the voxels next to the partial voxels, `None` for the full voxels hiding their faces.
+    let neighbor_height = |pos: IVec3| match chunks.voxel_at(pos)? {
+        Voxel::EMPTY_VOXEL => Some(0.0),
+        voxel
+            if registry.get_by_id(voxel.0).map_or(false, |material| {
+                material
+                    .flags
+                    .intersects(VoxelMaterialFlags::LIQUID | VoxelMaterialFlags::FOLIAGE)
+            }) =>
+        {
+            Some(0.0)
+        }
+        _ => metadata.get(pos)?.partial_height(),
+    };
+
+    for key in dirty_chunks.iter_dirty().copied() {
+        let mut voxels: HashMap<u8, Vec<(IVec3, f32)>> = HashMap::default();
+        for (pos, voxel_metadata) in metadata
+            .chunk(key)
+            .into_iter()
+            .flat_map(|chunk| chunk.0.iter())
+        {
+            let height = match voxel_metadata.partial_height() {
+                Some(height) => height,
+                None => continue,
+            };
+            match chunks.voxel_at(key + pos.as_ivec3()) {
+                Some(Voxel::EMPTY_VOXEL) | None => continue,
+                Some(voxel) => voxels
+                    .entry(voxel.0)
+                    .or_default()
+                    .push((pos.as_ivec3(), height)),
+            }
+        }
+
+        let loaded = chunk_entities.entity(key).is_some();
+        partial_meshes.0.retain(|(mesh_key, id), entity| {
+            let keep = *mesh_key != key || (loaded && voxels.contains_key(id));
+            if !keep {
+                commands.entity(*entity).despawn_recursive();
+            }
+            keep
+        });
+        if !loaded {
+            continue;
+        }
+
+        for (id, partial_voxels) in voxels {
+            let material = partial_materials
+                .entry(id)
+                .or_insert_with(|| {
+                    let info = registry.get_by_id(id);
+                    materials.add(StandardMaterial {
+                        base_color: info.map_or(Color::WHITE, |info| info.base_color),
+                        emissive: info.map_or(Color::BLACK, |info| info.emissive),
+                        ..default()
+                    })
+                })
+                .clone();
+            let mesh = meshes.add(mesh_fluid_voxels(&partial_voxels, |local| {
+                neighbor_height(key + local)
+            }));
+
+            match partial_meshes.0.get(&(key, id)) {
+                Some(entity) => {
+                    commands.entity(*entity).insert(mesh);
+                }
+                None => {
+                    let entity = commands
+                        .spawn_bundle(PbrBundle {
+                            mesh,
+                            material,
+                            transform: Transform::from_translation(origin.to_translation(key)),
+                            ..default()
+                        })
+                        .id();
+                    partial_meshes.0.insert((key, id), entity);
+                }
+            }
+        }
+    }
+}
+
+/// Renders the partial voxels (e.g. snow layers, slabs) at the height held by their metadata, matching their colliders.
+pub struct PartialVoxelMeshesPlugin;
+
+impl Plugin for PartialVoxelMeshesPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkPartialVoxelMeshes>()
+            .add_system_to_stage(ChunkMeshingStage, update_partial_voxel_meshes);
+    }
+}
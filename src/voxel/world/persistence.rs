@@ -0,0 +1,348 @@
+use std::{future::Future, io};
+
+use bevy::{
+    app::AppExit,
+    math::IVec3,
+    prelude::{
+        error, CoreStage, EventReader, ParallelSystemDescriptorCoercion, Plugin, Res, ResMut,
+    },
+    tasks::{IoTaskPool, Task},
+    time::Time,
+    utils::{Duration, HashMap, HashSet},
+};
+use futures_lite::future;
+
+use super::{
+    chunks::{ChunkCommandQueue, ChunkLoadingSystem, DirtyChunks},
+    ChunkShape,
+};
+use crate::voxel::{
+    storage::{ChunkMap, ChunkSaveHeader, VoxelMetadataMap, WorldSave},
+    terraingen::TerrainGenerator,
+    Voxel,
+};
+
+/// The save headers of the loaded chunks, and of the unloaded chunks still cached in the chunk map.
+#[derive(Default)]
+pub struct ChunkSaveHeaders(HashMap<IVec3, ChunkSaveHeader>);
+
+#[allow(dead_code)]
+impl ChunkSaveHeaders {
+    pub fn get(&self, key: IVec3) -> Option<&ChunkSaveHeader> {
+        self.0.get(&key)
+    }
+
+    pub fn get_mut(&mut self, key: IVec3) -> Option<&mut ChunkSaveHeader> {
+        self.0.get_mut(&key)
+    }
+
+    pub fn insert(&mut self, key: IVec3, header: ChunkSaveHeader) {
+        self.0.insert(key, header);
+    }
+
+    pub fn remove(&mut self, key: IVec3) -> Option<ChunkSaveHeader> {
+        self.0.remove(&key)
+    }
+}
+
+/// The writes of each chunk to the world save running in the background, chained so that the writes of a chunk never
+/// share its temporary file and land in the order they were made.
+#[derive(Default)]
+struct ChunkSaveTasks(HashMap<IVec3, Task<()>>);
+
+impl ChunkSaveTasks {
+    /// Spawns a write of a chunk (or of its voxel metadata), starting once the previous write of the chunk is over.
+    fn spawn(&mut self, key: IVec3, write: impl Future<Output = ()> + Send + 'static) {
+        let previous = self.0.remove(&key);
+        let task = IoTaskPool::get().spawn(async move {
+            if let Some(previous) = previous {
+                previous.await;
+            }
+            write.await;
+        });
+        self.0.insert(key, task);
+    }
+
+    /// Drops the writes which are over.
+    fn prune(&mut self) {
+        self.0
+            .retain(|_, task| future::block_on(future::poll_once(task)).is_none());
+    }
+
+    /// Blocks until all the writes are over.
+    fn wait_all(&mut self) {
+        for (_, task) in self.0.drain() {
+            future::block_on(task);
+        }
+    }
+}
+
+/// Marks the chunks whose voxels got replaced as modified, so that world upgrades leave them untouched.
+fn mark_edited_chunks(
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    mut headers: ResMut<ChunkSaveHeaders>,
+) {
+    for key in dirty_chunks.drain_edited() {
+        if let Some(header) = headers.get_mut(key) {
+            header.pristine = false;
+        }
+    }
+}
+
+/// Writes the voxel metadata of a chunk to the world save in the background, if it changed since it was last saved.
+fn save_modified_metadata(
+    world_save: &WorldSave,
+    metadata: &mut VoxelMetadataMap,
+    save_tasks: &mut ChunkSaveTasks,
+    key: IVec3,
+) {
+    if !metadata.is_modified(key) {
+        return;
+    }
+    metadata.mark_saved(key);
+
+    let (world_save, chunk_metadata) = (
+        world_save.clone(),
+        metadata.chunk(key).cloned().unwrap_or_default(),
+    );
+    save_tasks.spawn(key, async move {
+        if let Err(err) = world_save.save_chunk_metadata(key, &chunk_metadata) {
+            error!(
+                "Failed to save the voxel metadata of chunk {:?}: {}",
+                key, err
+            );
+        }
+    });
+}
+
+/// Writes the chunks about to be unloaded to the world save in the background, along with their voxel metadata.
+fn save_unloaded_chunks(
+    world_save: Option<Res<WorldSave>>,
+    chunk_command_queue: Res<ChunkCommandQueue>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    mut headers: ResMut<ChunkSaveHeaders>,
+    mut metadata: ResMut<VoxelMetadataMap>,
+    mut save_tasks: ResMut<ChunkSaveTasks>,
+) {
+    for key in chunk_command_queue.pending_data_unloads() {
+        let header = headers.get(*key).copied();
+
+        let world_save = match world_save.as_deref() {
+            Some(world_save) => world_save,
+            None => continue,
+        };
+        save_modified_metadata(world_save, &mut metadata, &mut save_tasks, *key);
+
+        if let (Some(header), Some(buffer)) = (header, chunks.buffer_at(*key)) {
+            let (world_save, buffer, key) = (world_save.clone(), buffer.clone(), *key);
+
+            save_tasks.spawn(key, async move {
+                if let Err(err) = world_save.save_chunk(key, header, &buffer) {
+                    error!("Failed to save chunk {:?}: {}", key, err);
+                }
+            });
+        }
+    }
+}
+
+/// Periodic saving of the loaded chunks, so that a crash doesn't lose the changes made since the chunks got loaded.
+pub struct AutosaveSettings {
+    /// Time between two autosaves, `None` disabling the autosave.
+    pub interval: Option<Duration>,
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        Self {
+            interval: Some(Duration::from_secs(300)),
+        }
+    }
+}
+
+/// The chunks changed (or loaded) since the last autosave, and the time elapsed since.
+#[derive(Default)]
+struct AutosaveState {
+    changed: HashSet<IVec3>,
+    elapsed: Duration,
+}
+
+/// Writes the chunks and the voxel metadata changed since the last autosave to the world save in the background, once
+/// per autosave interval.
+#[allow(clippy::too_many_arguments)]
+fn autosave_chunks(
+    settings: Res<AutosaveSettings>,
+    world_save: Option<Res<WorldSave>>,
+    dirty_chunks: Res<DirtyChunks>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    headers: Res<ChunkSaveHeaders>,
+    time: Res<Time>,
+    mut state: ResMut<AutosaveState>,
+    mut metadata: ResMut<VoxelMetadataMap>,
+    mut save_tasks: ResMut<ChunkSaveTasks>,
+) {
+    save_tasks.prune();
+
+    let (interval, world_save) = match (settings.interval, world_save) {
+        (Some(interval), Some(world_save)) => (interval, world_save),
+        _ => return,
+    };
+
+    // the chunks being edited or freshly generated are dirty for the frame.
+    state.changed.extend(dirty_chunks.iter_dirty());
+    state.elapsed += time.delta();
+    if state.elapsed < interval {
+        return;
+    }
+    state.elapsed = Duration::ZERO;
+
+    for key in metadata.modified_chunks() {
+        save_modified_metadata(&world_save, &mut metadata, &mut save_tasks, key);
+    }
+
+    for key in state.changed.drain() {
+        if let (Some(header), Some(buffer)) = (headers.get(key).copied(), chunks.buffer_at(key)) {
+            let (world_save, buffer) = (world_save.clone(), buffer.clone());
+
+            save_tasks.spawn(key, async move {
+                if let Err(err) = world_save.save_chunk(key, header, &buffer) {
+                    error!("Failed to autosave chunk {:?}: {}", key, err);
+                }
+            });
+        }
+    }
+}
+
+/// Drops the save headers and the voxel metadata of the chunks neither loaded nor cached anymore.
+fn prune_save_headers(
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    mut headers: ResMut<ChunkSaveHeaders>,
+    mut metadata: ResMut<VoxelMetadataMap>,
+) {
+    // headers only outnumber the loaded and cached chunks once cached chunks got evicted.
+    if headers.0.len() <= chunks.iter_keys().count() + chunks.cache_stats().len {
+        return;
+    }
+
+    headers
+        .0
+        .retain(|key, _| chunks.exists(*key) || chunks.is_cached(*key));
+    metadata.retain_chunks(|key| chunks.exists(key) || chunks.is_cached(key));
+}
+
+/// Writes all the loaded chunks and the modified voxel metadata to the world save when the app is about to exit.
+fn save_chunks_on_exit(
+    exit_events: EventReader<AppExit>,
+    world_save: Option<Res<WorldSave>>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    headers: Res<ChunkSaveHeaders>,
+    mut metadata: ResMut<VoxelMetadataMap>,
+    mut save_tasks: ResMut<ChunkSaveTasks>,
+) {
+    let world_save = match world_save {
+        Some(world_save) if !exit_events.is_empty() => world_save,
+        _ => return,
+    };
+    // the background writes would race with (or land after) the final ones.
+    save_tasks.wait_all();
+
+    for (key, header) in headers.0.iter() {
+        if let Some(buffer) = chunks.buffer_at(*key) {
+            if let Err(err) = world_save.save_chunk(*key, *header, buffer) {
+                error!("Failed to save chunk {:?}: {}", key, err);
+            }
+        }
+    }
+
+    for key in metadata.take_modified() {
+        let chunk_metadata = metadata.chunk(key).cloned().unwrap_or_default();
+        if let Err(err) = world_save.save_chunk_metadata(key, &chunk_metadata) {
+            error!(
+                "Failed to save the voxel metadata of chunk {:?}: {}",
+                key, err
+            );
+        }
+    }
+}
+
+/// Summary of the changes made to a world save by [`upgrade_world_save`].
+#[derive(Default, Debug)]
+pub struct WorldUpgradeReport {
+    /// Number of chunks to which at least a generation stage was applied.
+    pub upgraded: usize,
+    /// Number of chunks which already had all the generation stages applied.
+    pub up_to_date: usize,
+    /// Number of chunks left untouched because they were modified since they were generated.
+    pub skipped_modified: usize,
+}
+
+/// Applies the generation stages of `generator` missing from the chunks of a world save.
+/// Only pristine chunks get upgraded, chunks modified since their generation are left untouched to preserve player builds.
+pub fn upgrade_world_save(
+    world_save: &WorldSave,
+    generator: &TerrainGenerator,
+) -> io::Result<WorldUpgradeReport> {
+    let mut report = WorldUpgradeReport::default();
+
+    for key in world_save.chunk_keys()? {
+        let mut chunk = match world_save.load_chunk(key)? {
+            Some(chunk) => chunk,
+            None => continue,
+        };
+
+        if !chunk.header.pristine {
+            report.skipped_modified += 1;
+            continue;
+        }
+
+        let applied =
+            generator.apply_missing_stages(key, &mut chunk.data, chunk.header.applied_stages);
+
+        if applied.is_empty() {
+            report.up_to_date += 1;
+            continue;
+        }
+
+        world_save.save_chunk(
+            key,
+            ChunkSaveHeader {
+                applied_stages: chunk.header.applied_stages | generator.stage_mask(),
+                pristine: true,
+            },
+            &chunk.data,
+        )?;
+        report.upgraded += 1;
+    }
+
+    Ok(report)
+}
+
+/// Tracks the save headers of the loaded chunks and handles saving chunks to the world save, if a [`WorldSave`] resource was inserted.
+/// The changed chunks are also saved periodically, see [`AutosaveSettings`].
+pub struct VoxelWorldPersistencePlugin;
+
+impl Plugin for VoxelWorldPersistencePlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkSaveHeaders>()
+            .init_resource::<VoxelMetadataMap>()
+            .init_resource::<AutosaveSettings>()
+            .init_resource::<AutosaveState>()
+            .init_resource::<ChunkSaveTasks>()
+            // the edits get applied during the update and simulation stages, before any chunk gets saved.
+            .add_system_to_stage(CoreStage::PostUpdate, mark_edited_chunks)
+            .add_system_to_stage(
+                CoreStage::Last,
+                autosave_chunks.before(ChunkLoadingSystem::ClearDirtyChunks),
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                save_unloaded_chunks
+                    .after(ChunkLoadingSystem::DestroyChunks)
+                    .before(ChunkLoadingSystem::UnloadChunkData),
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                prune_save_headers.after(ChunkLoadingSystem::UnloadChunkData),
+            )
+            .add_system_to_stage(CoreStage::Last, save_chunks_on_exit);
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    math::IVec3,
+    prelude::{CoreStage, Plugin, ResMut},
+    utils::{Duration, HashMap, HashSet, Instant},
+};
+
+/// The decisions of the chunk pipeline recorded by the [`ChunkPipelineLog`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ChunkDecision {
+    /// The chunk got an entity.
+    Load,
+    /// The entity of the chunk got despawned.
+    Unload,
+    /// The chunk data got requested, loaded from the cache, the save or the server or generated.
+    LoadData,
+    /// The chunk data got unloaded.
+    UnloadData,
+    /// The chunk got queued for a remesh, meshed or dropped from the meshing queue.
+    Remesh,
+}
+
+impl ChunkDecision {
+    pub const ALL: [ChunkDecision; 5] = [
+        ChunkDecision::Load,
+        ChunkDecision::Unload,
+        ChunkDecision::LoadData,
+        ChunkDecision::UnloadData,
+        ChunkDecision::Remesh,
+    ];
+
+    /// Returns the name of the decision in the console commands.
+    pub fn name(self) -> &'static str {
+        match self {
+            ChunkDecision::Load => "load",
+            ChunkDecision::Unload => "unload",
+            ChunkDecision::LoadData => "load_data",
+            ChunkDecision::UnloadData => "unload_data",
+            ChunkDecision::Remesh => "remesh",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|decision| decision.name() == name)
+    }
+}
+
+/// A decision taken for a chunk, along with why it was taken.
+#[derive(Clone, Debug)]
+pub struct ChunkLogEntry {
+    /// Time since the log got created.
+    pub time: Duration,
+    /// Frame during which the decision was taken, for ordering the decisions taken by different systems.
+    pub frame: u64,
+    pub decision: ChunkDecision,
+    pub reason: String,
+}
+
+/// An opt-in log of why each chunk got loaded, unloaded or remeshed, kept per chunk key for debugging the churn and
+/// ordering issues of the chunk pipeline. Nothing gets recorded (nor formatted) while disabled.
+pub struct ChunkPipelineLog {
+    pub enabled: bool,
+    /// The decisions being recorded, the others being filtered out.
+    pub recorded: HashSet<ChunkDecision>,
+    /// Maximum number of entries kept per chunk, the oldest ones being dropped past it.
+    pub max_entries_per_chunk: usize,
+    entries: HashMap<IVec3, VecDeque<ChunkLogEntry>>,
+    started: Instant,
+    frame: u64,
+}
+
+impl Default for ChunkPipelineLog {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            recorded: ChunkDecision::ALL.into_iter().collect(),
+            max_entries_per_chunk: 64,
+            entries: Default::default(),
+            started: Instant::now(),
+            frame: 0,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl ChunkPipelineLog {
+    /// Returns whether the decisions of the specified kind get recorded.
+    pub fn records(&self, decision: ChunkDecision) -> bool {
+        self.enabled && self.recorded.contains(&decision)
+    }
+
+    /// Records a decision taken for a chunk, the reason only being formatted when the decision gets recorded.
+    pub fn record(&mut self, key: IVec3, decision: ChunkDecision, reason: impl FnOnce() -> String) {
+        if !self.records(decision) {
+            return;
+        }
+
+        let entry = ChunkLogEntry {
+            time: self.started.elapsed(),
+            frame: self.frame,
+            decision,
+            reason: reason(),
+        };
+        let entries = self.entries.entry(key).or_default();
+        entries.push_back(entry);
+        while entries.len() > self.max_entries_per_chunk {
+            entries.pop_front();
+        }
+    }
+
+    /// Returns the recorded decisions of a chunk, oldest first.
+    pub fn entries(&self, key: IVec3) -> impl Iterator<Item = &ChunkLogEntry> {
+        self.entries.get(&key).into_iter().flatten()
+    }
+
+    /// Returns the number of chunks with recorded decisions.
+    pub fn chunk_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+fn advance_chunk_log_frame(mut log: ResMut<ChunkPipelineLog>) {
+    // only counting frames while enabled would keep the log resource changed for nothing.
+    if log.enabled {
+        log.frame += 1;
+    }
+}
+
+/// Keeps the [`ChunkPipelineLog`] of the decisions taken by the chunk loading, generation and meshing systems.
+pub struct ChunkPipelineLogPlugin;
+
+impl Plugin for ChunkPipelineLogPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkPipelineLog>()
+            .add_system_to_stage(CoreStage::First, advance_chunk_log_frame);
+    }
+}
@@ -1,21 +1,116 @@
 use bevy::{input::mouse::MouseMotion, prelude::*};
 use std::f32::consts::FRAC_PI_2;
 
-// Reusing the player controller impl for now.
+use super::{
+    chunk_key_at,
+    column_heights::ColumnHeightmaps,
+    input::{GamepadSticks, InputAction, InputMap},
+    origin::WorldOrigin,
+    spawn_loading::spawn_area_loaded,
+    terrain::MAX_GENERATED_HEIGHT,
+    ChunkShape,
+};
+use crate::voxel::{
+    material::{VoxelMaterialFlags, VoxelMaterialRegistry},
+    storage::{ChunkMap, VoxelMetadataMap},
+    terraingen::TERRAIN_GENERATOR,
+    Voxel,
+};
 
 pub const DEFAULT_CAMERA_SENS: f32 = 0.005;
 
+/// Height of the camera above the feet of the player.
+pub const PLAYER_EYE_HEIGHT: f32 = 1.5;
+const PLAYER_HEIGHT: f32 = 1.8;
+/// Height of the camera above the feet of the crouching player.
+const CROUCH_EYE_HEIGHT: f32 = 1.1;
+/// Height of the body of the crouching player, which fits under the partial voxels hanging above a 1.5 voxels gap.
+const CROUCH_HEIGHT: f32 = 1.4;
+const CROUCH_FACTOR: f32 = 0.4;
+const PLAYER_HALF_WIDTH: f32 = 0.3;
+
+const WALK_SPEED: f32 = 5.0;
+const SPRINT_FACTOR: f32 = 1.8;
+const JUMP_VELOCITY: f32 = 8.0;
+const GRAVITY: f32 = 25.0;
+const MAX_FALL_SPEED: f32 = 60.0;
+/// Height of the partial voxels (e.g. slabs, snow layers) the walking player steps onto instead of being blocked by.
+const MAX_STEP_HEIGHT: f32 = 0.5;
+
+/// How the player moves around the world.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlayerMovementMode {
+    /// Free flying camera, going through the terrain.
+    Fly,
+    /// Walking with gravity and collisions against the voxels of the world.
+    Walk,
+}
+
+impl Default for PlayerMovementMode {
+    fn default() -> Self {
+        Self::Walk
+    }
+}
+
 #[derive(Default, Component)]
 pub struct PlayerController {
     yaw: f32,
     pitch: f32,
     cursor_locked: bool,
+    pub mode: PlayerMovementMode,
+    velocity: Vec3,
+    grounded: bool,
+    /// Whether the crouch action is held, the player only standing up once there is room for it.
+    crouch_held: bool,
+    crouching: bool,
+}
+
+/// Event moving the player to the specified X and Z world coordinates, above the generated terrain surface.
+/// The chunks around the previous position get unloaded and the ones around the new position loaded as usual.
+pub struct TeleportPlayer {
+    pub position: Vec2,
+}
+
+impl PlayerController {
+    /// Returns whether the cursor is locked for looking around, the unlocked cursor interacting with the UI.
+    pub fn cursor_locked(&self) -> bool {
+        self.cursor_locked
+    }
+
+    /// Returns the height of the camera above the feet of the player, lower when crouching.
+    pub fn eye_height(&self) -> f32 {
+        if self.crouching {
+            CROUCH_EYE_HEIGHT
+        } else {
+            PLAYER_EYE_HEIGHT
+        }
+    }
+
+    /// Returns the height of the body of the player, lower when crouching.
+    fn body_height(&self) -> f32 {
+        if self.crouching {
+            CROUCH_HEIGHT
+        } else {
+            PLAYER_HEIGHT
+        }
+    }
+
+    /// Updates the yaw and pitch of the controller from a rotation of the camera moved by something else (e.g. a replay),
+    /// so that looking around continues from it.
+    pub fn sync_rotation(&mut self, rotation: Quat) {
+        let (yaw, pitch, _) = rotation.to_euler(EulerRot::YXZ);
+        self.yaw = yaw;
+        self.pitch = -pitch;
+    }
 }
 
 pub fn handle_player_mouse_move(
     mut query: Query<(&mut PlayerController, &mut Transform)>,
     mut mouse_motion_event_reader: EventReader<MouseMotion>,
     mut window: ResMut<Windows>,
+    sticks: Res<GamepadSticks>,
+    input_map: Res<InputMap>,
+    time: Res<Time>,
 ) {
     let (mut controller, mut transform) = query.single_mut();
     let mut delta = Vec2::ZERO;
@@ -24,6 +119,11 @@ pub fn handle_player_mouse_move(
         for mouse_move in mouse_motion_event_reader.iter() {
             delta += mouse_move.delta;
         }
+
+        // converted to the equivalent mouse motion, pushing the stick up looks up.
+        delta +=
+            Vec2::new(sticks.look.x, -sticks.look.y) * input_map.look_speed * time.delta_seconds()
+                / DEFAULT_CAMERA_SENS;
     }
 
     let first_win = window.get_primary_mut().unwrap();
@@ -51,46 +151,63 @@ pub fn handle_player_mouse_move(
 
 pub fn handle_player_input(
     mut query: Query<(&mut PlayerController, &mut Transform)>,
-    input: Res<Input<KeyCode>>,
+    input: Res<Input<InputAction>>,
+    sticks: Res<GamepadSticks>,
 ) {
     let (mut controller, mut transform) = query.single_mut();
 
-    if input.just_pressed(KeyCode::Escape) {
+    if input.just_pressed(InputAction::ToggleCursor) {
         controller.cursor_locked = !controller.cursor_locked;
     }
 
-    let mut direction = Vec3::ZERO;
+    if input.just_pressed(InputAction::ToggleMovementMode) {
+        controller.mode = match controller.mode {
+            PlayerMovementMode::Fly => PlayerMovementMode::Walk,
+            PlayerMovementMode::Walk => PlayerMovementMode::Fly,
+        };
+        controller.velocity = Vec3::ZERO;
+        // the flying camera keeps its height, the walking player starts standing from it.
+        controller.crouching = false;
+        controller.crouch_held = false;
+    }
+
+    if controller.mode == PlayerMovementMode::Walk {
+        handle_walk_input(&mut controller, &transform, &input, sticks.movement);
+        return;
+    }
+
+    let mut direction = Vec3::new(sticks.movement.x, 0.0, -sticks.movement.y);
 
     let forward = transform.rotation.mul_vec3(Vec3::Z).normalize() * Vec3::new(1.0, 0., 1.0);
     let right = transform.rotation.mul_vec3(Vec3::X).normalize();
 
     let mut acceleration = 1.0f32;
 
-    if input.pressed(KeyCode::W) {
+    if input.pressed(InputAction::MoveForward) {
         direction.z -= 1.0;
     }
 
-    if input.pressed(KeyCode::S) {
+    if input.pressed(InputAction::MoveBackward) {
         direction.z += 1.0;
     }
 
-    if input.pressed(KeyCode::D) {
+    if input.pressed(InputAction::MoveRight) {
         direction.x += 1.0;
     }
 
-    if input.pressed(KeyCode::A) {
+    if input.pressed(InputAction::MoveLeft) {
         direction.x -= 1.0;
     }
 
-    if input.pressed(KeyCode::Space) {
+    if input.pressed(InputAction::Ascend) {
         direction.y += 1.0;
     }
 
-    if input.pressed(KeyCode::LShift) {
+    if input.pressed(InputAction::Descend) {
         direction.y -= 1.0;
     }
 
-    if input.pressed(KeyCode::LControl) {
+    if input.pressed(InputAction::Sprint) {
         acceleration *= 8.0;
     }
 
@@ -104,11 +221,292 @@ pub fn handle_player_input(
         + direction.y * Vec3::Y * acceleration;
 }
 
+/// Sets the horizontal velocity of a walking player from the movement actions and stick, and makes it jump when grounded.
+fn handle_walk_input(
+    controller: &mut PlayerController,
+    transform: &Transform,
+    input: &Input<InputAction>,
+    stick: Vec2,
+) {
+    let forward =
+        (transform.rotation.mul_vec3(Vec3::Z) * Vec3::new(1.0, 0., 1.0)).normalize_or_zero();
+    let right = transform.rotation.mul_vec3(Vec3::X).normalize();
+
+    let mut direction = right * stick.x - forward * stick.y;
+    if input.pressed(InputAction::MoveForward) {
+        direction -= forward;
+    }
+    if input.pressed(InputAction::MoveBackward) {
+        direction += forward;
+    }
+    if input.pressed(InputAction::MoveRight) {
+        direction += right;
+    }
+    if input.pressed(InputAction::MoveLeft) {
+        direction -= right;
+    }
+
+    // the descend action crouches, the stance being updated along the physics.
+    controller.crouch_held = input.pressed(InputAction::Descend);
+
+    let mut speed = WALK_SPEED;
+    if controller.crouching {
+        speed *= CROUCH_FACTOR;
+    } else if input.pressed(InputAction::Sprint) {
+        speed *= SPRINT_FACTOR;
+    }
+
+    // a partially deflected stick walks slower.
+    let horizontal = direction.clamp_length_max(1.0) * speed;
+    controller.velocity.x = horizontal.x;
+    controller.velocity.z = horizontal.z;
+
+    if controller.grounded && input.pressed(InputAction::Ascend) {
+        controller.velocity.y = JUMP_VELOCITY;
+        controller.grounded = false;
+    }
+}
+
+/// Moves the player to the positions requested through [`TeleportPlayer`] events.
+pub fn teleport_player(
+    mut events: EventReader<TeleportPlayer>,
+    mut query: Query<(&mut PlayerController, &mut Transform)>,
+    origin: Res<WorldOrigin>,
+    heightmaps: Res<ColumnHeightmaps>,
+) {
+    let position = match events.iter().last() {
+        Some(event) => event.position,
+        None => return,
+    };
+    let (mut controller, mut transform) = query.single_mut();
+
+    // unless loaded already, the height of the terrain surface is sampled from the generator heightmap.
+    let column = position.floor().as_ivec2();
+    let surface = heightmaps
+        .surface_height(column)
+        .map(|height| height as f32)
+        .unwrap_or_else(|| TERRAIN_GENERATOR.read().unwrap().surface_height(column));
+
+    // the floating origin catches up with far away teleports before the transforms get propagated.
+    transform.translation = Vec3::new(position.x, surface + 2.0 + PLAYER_EYE_HEIGHT, position.y)
+        - origin.get().as_vec3();
+    controller.velocity = Vec3::ZERO;
+    controller.grounded = false;
+}
+
+/// Returns the bounds of the player body of the specified height standing at the specified feet position.
+#[inline]
+fn player_bounds(feet: Vec3, height: f32) -> (Vec3, Vec3) {
+    (
+        feet - Vec3::new(PLAYER_HALF_WIDTH, 0.0, PLAYER_HALF_WIDTH),
+        feet + Vec3::new(PLAYER_HALF_WIDTH, height, PLAYER_HALF_WIDTH),
+    )
+}
+
+/// Returns the bottom and top of the solid parts of the voxels overlapped by the box with the specified bounds, `None`
+/// if it overlaps none. `solid_height` returns the height of the solid part of a voxel, from its bottom.
+fn overlapped_solid(
+    min: Vec3,
+    max: Vec3,
+    solid_height: &impl Fn(IVec3) -> f32,
+) -> Option<(f32, f32)> {
+    let (min_voxel, max_voxel) = (min.floor().as_ivec3(), max.ceil().as_ivec3() - IVec3::ONE);
+    let mut extent: Option<(f32, f32)> = None;
+    for x in min_voxel.x..=max_voxel.x {
+        for y in min_voxel.y..=max_voxel.y {
+            for z in min_voxel.z..=max_voxel.z {
+                let (bottom, top) = (y as f32, y as f32 + solid_height(IVec3::new(x, y, z)));
+                if top > bottom && top > min.y && bottom < max.y {
+                    extent = Some(extent.map_or((bottom, top), |(lowest, highest)| {
+                        (lowest.min(bottom), highest.max(top))
+                    }));
+                }
+            }
+        }
+    }
+    extent
+}
+
+/// Moves the player body one axis at a time, stopping it against the solid voxels it runs into and stepping onto the
+/// low partial voxels. Returns whether the body landed on the ground.
+fn move_and_collide(
+    feet: &mut Vec3,
+    velocity: &mut Vec3,
+    body_height: f32,
+    delta_seconds: f32,
+    solid_height: &impl Fn(IVec3) -> f32,
+) -> bool {
+    let motion = *velocity * delta_seconds;
+    // moves are split in steps shorter than a voxel so that the body doesn't tunnel through thin walls.
+    let steps = (motion.abs().max_element() / 0.5).ceil().max(1.0) as u32;
+    let (min_offset, max_offset) = player_bounds(Vec3::ZERO, body_height);
+
+    let mut blocked = [false; 3];
+    let mut grounded = false;
+
+    for _ in 0..steps {
+        // vertical first so that walking on the ground isn't blocked by the ground itself.
+        for axis in [1, 0, 2] {
+            let delta = motion[axis] / steps as f32;
+            if blocked[axis] || delta == 0.0 {
+                continue;
+            }
+
+            let mut moved = *feet;
+            moved[axis] += delta;
+            let (min, max) = player_bounds(moved, body_height);
+
+            let (bottom, top) = match overlapped_solid(min, max, solid_height) {
+                Some(extent) => extent,
+                None => {
+                    *feet = moved;
+                    continue;
+                }
+            };
+
+            // the grounded body walks up the partial voxels low enough.
+            if axis != 1 && grounded && top - feet.y <= MAX_STEP_HEIGHT {
+                let stepped = Vec3::new(moved.x, top + 0.001, moved.z);
+                let (min, max) = player_bounds(stepped, body_height);
+                if overlapped_solid(min, max, solid_height).is_none() {
+                    *feet = stepped;
+                    continue;
+                }
+            }
+
+            // snap the body against the face of the voxel it ran into, the top of the partial voxels being lower.
+            if axis == 1 {
+                if delta > 0.0 {
+                    feet.y = bottom - max_offset.y - 0.001;
+                } else {
+                    feet.y = top - min_offset.y + 0.001;
+                    grounded = true;
+                }
+            } else if delta > 0.0 {
+                feet[axis] = max[axis].floor() - max_offset[axis] - 0.001;
+            } else {
+                feet[axis] = min[axis].floor() + 1.0 - min_offset[axis] + 0.001;
+            }
+            velocity[axis] = 0.0;
+            blocked[axis] = true;
+        }
+    }
+
+    grounded
+}
+
+/// Applies gravity to a walking player and moves it according to its velocity, colliding with the voxels of the world.
+/// Liquids aren't solid, and unloaded voxels are solid so that the player doesn't fall through the world while it loads.
+/// The partial voxels (e.g. slabs, snow layers) are only solid up to their height, and the crouching player fits under
+/// lower ceilings.
+pub fn apply_player_physics(
+    mut query: Query<(&mut PlayerController, &mut Transform)>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    metadata: Res<VoxelMetadataMap>,
+    materials: Res<VoxelMaterialRegistry>,
+    origin: Res<WorldOrigin>,
+    time: Res<Time>,
+) {
+    let (mut controller, mut transform) = query.single_mut();
+    if controller.mode != PlayerMovementMode::Walk {
+        return;
+    }
+
+    // the body moves in the translation space, the voxels are looked up in world space.
+    let offset = origin.get();
+    let solid_height = |pos: IVec3| {
+        let is_solid =
+            chunks
+                .voxel_at(pos + offset)
+                .map_or(pos.y < MAX_GENERATED_HEIGHT, |voxel| {
+                    voxel != Voxel::EMPTY_VOXEL
+                        && materials.get_by_id(voxel.0).map_or(true, |material| {
+                            !material.flags.intersects(
+                                VoxelMaterialFlags::LIQUID | VoxelMaterialFlags::FOLIAGE,
+                            )
+                        })
+                });
+        if !is_solid {
+            return 0.0;
+        }
+        metadata
+            .get(pos + offset)
+            .and_then(|metadata| metadata.partial_height())
+            .unwrap_or(1.0)
+    };
+
+    let mut feet = transform.translation - Vec3::Y * controller.eye_height();
+
+    // the player waits for the chunk it is in to load (e.g. after a teleport) instead of getting pushed out of the unloaded voxels.
+    if !chunks.exists(chunk_key_at(origin.voxel_at(feet))) {
+        return;
+    }
+
+    // the crouching player stands back up once the standing body fits.
+    controller.crouching = controller.crouch_held || {
+        let (min, max) = player_bounds(feet, PLAYER_HEIGHT);
+        controller.crouching && overlapped_solid(min, max, &solid_height).is_some()
+    };
+    let body_height = controller.body_height();
+
+    // push the player out of the terrain it is stuck in (e.g. when switching from the fly mode).
+    let (min, max) = player_bounds(feet, body_height);
+    if let Some((_, top)) = overlapped_solid(min, max, &solid_height) {
+        feet.y = (feet.y.floor() + 1.0).min(top + 0.001);
+        controller.velocity = Vec3::ZERO;
+        transform.translation = feet + Vec3::Y * controller.eye_height();
+        return;
+    }
+
+    let delta_seconds = time.delta_seconds();
+    controller.velocity.y = (controller.velocity.y - GRAVITY * delta_seconds).max(-MAX_FALL_SPEED);
+
+    let mut velocity = controller.velocity;
+    controller.grounded = move_and_collide(
+        &mut feet,
+        &mut velocity,
+        body_height,
+        delta_seconds,
+        &solid_height,
+    );
+    controller.velocity = velocity;
+
+    transform.translation = feet + Vec3::Y * controller.eye_height();
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`VoxelWorldPlayerControllerPlugin`]
+pub enum PlayerControllerSystem {
+    /// Handles the movement actions.
+    HandleInput,
+    /// Moves the walking player according to its velocity.
+    ApplyPhysics,
+    /// Moves the player to the requested teleport positions.
+    Teleport,
+}
+
 pub struct VoxelWorldPlayerControllerPlugin;
 
 impl Plugin for VoxelWorldPlayerControllerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(handle_player_mouse_move)
-            .add_system(handle_player_input);
+        app.add_event::<TeleportPlayer>()
+            .add_system(teleport_player.label(PlayerControllerSystem::Teleport))
+            .add_system(
+                handle_player_mouse_move
+                    .label(PlayerControllerSystem::HandleInput)
+                    .with_run_criteria(spawn_area_loaded),
+            )
+            .add_system(
+                handle_player_input
+                    .label(PlayerControllerSystem::HandleInput)
+                    .with_run_criteria(spawn_area_loaded),
+            )
+            .add_system(
+                apply_player_physics
+                    .label(PlayerControllerSystem::ApplyPhysics)
+                    .with_run_criteria(spawn_area_loaded)
+                    .after(PlayerControllerSystem::HandleInput)
+                    .after(PlayerControllerSystem::Teleport),
+            );
     }
 }
@@ -0,0 +1,402 @@
+use std::io;
+
+use bevy::{
+    math::{IVec3, UVec3},
+    prelude::{
+        error, EventReader, EventWriter, ParallelSystemDescriptorCoercion, Plugin, Res, ResMut,
+        SystemLabel,
+    },
+    tasks::{AsyncComputeTaskPool, IoTaskPool, Task},
+    utils::{Duration, HashSet, Instant},
+};
+use futures_lite::future;
+
+use super::{
+    chunk_key_at,
+    chunks::{sort_by_distance, ChunkLoadRadius, DirtyChunks},
+    lighting::LightUpdates,
+    stages::TerrainGenStage,
+    terrain::{TerrainGenSystem, TerrainGenTasks, MAX_GENERATED_HEIGHT},
+    ChunkShape, CHUNK_SIZE,
+};
+use crate::voxel::{
+    material::VoxelMaterialRegistry,
+    storage::{ChunkMap, ChunkSaveHeader, VoxelBuffer, WorldSave},
+    terraingen::{structures::PendingVoxelEdits, TERRAIN_GENERATOR},
+    Voxel,
+};
+
+/// Event starting the pregeneration of the chunks within `radius` chunks of the `center` world position,
+/// replacing the pregeneration in progress if any.
+pub struct StartChunkPregen {
+    pub center: IVec3,
+    pub radius: i32,
+}
+
+/// Event cancelling the pregeneration in progress, the chunks already saved are kept.
+pub struct CancelChunkPregen;
+
+/// Event sent when a pregeneration completes or gets cancelled.
+pub struct ChunkPregenFinished {
+    pub progress: ChunkPregenProgress,
+    pub cancelled: bool,
+}
+
+/// Counts of the chunks processed by a pregeneration.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChunkPregenProgress {
+    /// Number of chunks in the pregenerated area.
+    pub total: usize,
+    /// Number of chunks generated and written to the world save.
+    pub generated: usize,
+    /// Number of chunks skipped because they were already saved or loaded.
+    pub skipped: usize,
+    /// Number of chunks whose generation or saving failed.
+    pub failed: usize,
+    /// Time elapsed since the pregeneration started.
+    pub elapsed: Duration,
+}
+
+impl ChunkPregenProgress {
+    /// Returns the number of chunks processed so far.
+    pub fn done(&self) -> usize {
+        self.generated + self.skipped + self.failed
+    }
+
+    /// Returns the fraction of the chunks processed so far, between 0 and 1.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.done() as f32 / self.total as f32
+    }
+}
+
+/// Outcome of a pregeneration task: the structure voxels spilling over the chunk borders, or why the chunk failed.
+type PregenOutput = Result<PendingVoxelEdits, String>;
+
+/// Background generation of the chunks of an area straight to the world save, so that servers can pre-warm their world
+/// before players join. The pregenerated chunks are neither loaded nor meshed, they get loaded from the save when players come close.
+pub struct ChunkPregen {
+    /// The chunks left to generate, the closest to the center last.
+    pending: Vec<IVec3>,
+    tasks: Vec<(IVec3, Task<PregenOutput>)>,
+    progress: ChunkPregenProgress,
+    started: Option<Instant>,
+    /// The chunks of the world save, including the pregenerated ones.
+    saved: HashSet<IVec3>,
+    /// The structure voxels spilling over into chunks with a task in flight, written once the task is over.
+    overflow: PendingVoxelEdits,
+    /// The tasks writing the structure voxels spilling over into the chunks already saved.
+    patches: Vec<(IVec3, Task<io::Result<()>>)>,
+    /// Maximum number of pregeneration tasks in flight, so that the chunks around the players still get generated quickly.
+    pub max_tasks: usize,
+}
+
+impl Default for ChunkPregen {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+            tasks: Vec::new(),
+            progress: ChunkPregenProgress::default(),
+            started: None,
+            saved: HashSet::default(),
+            overflow: PendingVoxelEdits::default(),
+            patches: Vec::new(),
+            max_tasks: 4,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl ChunkPregen {
+    /// Returns whether a pregeneration is in progress.
+    pub fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Returns the progress of the pregeneration in progress, or of the last one.
+    pub fn progress(&self) -> ChunkPregenProgress {
+        self.progress
+    }
+
+    /// Returns whether a pregeneration or a patch task of a chunk is in flight.
+    fn is_busy(&self, key: IVec3) -> bool {
+        self.tasks.iter().any(|(task_key, _)| *task_key == key)
+            || self.patches.iter().any(|(patch_key, _)| *patch_key == key)
+    }
+}
+
+/// Generates a chunk, along with the structure voxels spilling over from its neighbors, and writes it to the world save.
+fn pregenerate_chunk(
+    key: IVec3,
+    world_save: WorldSave,
+    material_count: usize,
+    edits: Vec<(UVec3, Voxel)>,
+) -> PregenOutput {
+    let generator = TERRAIN_GENERATOR.read().unwrap();
+    let mut chunk_data = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+    let generated = generator
+        .generate(key, &mut chunk_data, material_count)
+        .map_err(|err| format!("generation failed with seed {}: {}", generator.seed(), err))?;
+    PendingVoxelEdits::apply(&edits, &mut chunk_data);
+
+    let header = ChunkSaveHeader {
+        applied_stages: generated.applied_stages,
+        pristine: true,
+    };
+    world_save
+        .save_chunk(key, header, &chunk_data)
+        .map_err(|err| format!("saving failed: {}", err))?;
+
+    Ok(generated.overflow)
+}
+
+/// Writes the structure voxels spilling over into a chunk already in the world save.
+fn patch_saved_chunk(
+    key: IVec3,
+    world_save: WorldSave,
+    edits: Vec<(UVec3, Voxel)>,
+) -> io::Result<()> {
+    let mut chunk = match world_save.load_chunk(key)? {
+        Some(chunk) => chunk,
+        None => return Ok(()),
+    };
+    PendingVoxelEdits::apply(&edits, &mut chunk.data);
+    world_save.save_chunk(key, chunk.header, &chunk.data)
+}
+
+/// Starts and cancels the pregenerations.
+fn handle_pregen_events(
+    mut start_events: EventReader<StartChunkPregen>,
+    mut cancel_events: EventReader<CancelChunkPregen>,
+    mut finished_events: EventWriter<ChunkPregenFinished>,
+    mut pregen: ResMut<ChunkPregen>,
+    mut pending_edits: ResMut<PendingVoxelEdits>,
+    world_save: Option<Res<WorldSave>>,
+    load_radius: Res<ChunkLoadRadius>,
+) {
+    let cancelled = cancel_events.iter().count() > 0;
+    let start = start_events.iter().last();
+
+    if (cancelled || start.is_some()) && pregen.is_running() {
+        pregen.pending.clear();
+        pregen.tasks.clear();
+        pregen.started = None;
+        // the chunks left unsaved get the spilling voxels if they get generated once loaded.
+        let overflow = std::mem::take(&mut pregen.overflow);
+        pending_edits.merge(overflow);
+        finished_events.send(ChunkPregenFinished {
+            progress: pregen.progress,
+            cancelled: true,
+        });
+    }
+
+    let (start, world_save) = match (start, world_save) {
+        (Some(start), Some(world_save)) => (start, world_save),
+        (Some(_), None) => {
+            error!("Chunk pregeneration requires a world save to write the chunks to");
+            return;
+        }
+        _ => return,
+    };
+
+    // the already saved chunks are skipped, listing them once is cheaper than checking each chunk.
+    let saved: HashSet<IVec3> = match world_save.chunk_keys() {
+        Ok(keys) => keys.into_iter().collect(),
+        Err(err) => {
+            error!("Failed to list the chunks of the world save: {}", err);
+            return;
+        }
+    };
+
+    // the vertical extent is the one loaded around a player standing at the center.
+    let center = chunk_key_at(start.center);
+    let mut keys = Vec::new();
+    for x in -start.radius..=start.radius {
+        for z in -start.radius..=start.radius {
+            if x.pow(2) + z.pow(2) > start.radius.pow(2) {
+                continue;
+            }
+            for y in -load_radius.vertical..=load_radius.vertical {
+                let key = center + IVec3::new(x, y, z) * CHUNK_SIZE;
+                if key.y < MAX_GENERATED_HEIGHT {
+                    keys.push(key);
+                }
+            }
+        }
+    }
+
+    sort_by_distance(&mut keys, center);
+    keys.reverse();
+
+    let total = keys.len();
+    keys.retain(|key| !saved.contains(key));
+    pregen.saved = saved;
+
+    pregen.progress = ChunkPregenProgress {
+        total,
+        skipped: total - keys.len(),
+        ..Default::default()
+    };
+    pregen.pending = keys;
+    pregen.started = Some(Instant::now());
+}
+
+/// Spawns the pregeneration tasks, and writes the structure voxels spilling over the finished chunks to their neighbors.
+#[allow(clippy::too_many_arguments)]
+fn process_pregen_tasks(
+    mut pregen: ResMut<ChunkPregen>,
+    mut finished_events: EventWriter<ChunkPregenFinished>,
+    mut chunk_data: ResMut<ChunkMap<Voxel, ChunkShape>>,
+    mut pending_edits: ResMut<PendingVoxelEdits>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    mut light_updates: ResMut<LightUpdates>,
+    gen_tasks: Res<TerrainGenTasks>,
+    world_save: Option<Res<WorldSave>>,
+    materials: Res<VoxelMaterialRegistry>,
+) {
+    let pregen = &mut *pregen;
+
+    pregen.patches.retain_mut(
+        |(key, task)| match future::block_on(future::poll_once(task)) {
+            Some(Err(err)) => {
+                error!(
+                    "Failed to write the structure voxels spilling over into chunk {:?}: {}",
+                    key, err
+                );
+                false
+            }
+            Some(Ok(())) => false,
+            None => true,
+        },
+    );
+
+    let mut overflow = std::mem::take(&mut pregen.overflow);
+    pregen.tasks.retain_mut(
+        |(key, task)| match future::block_on(future::poll_once(task)) {
+            Some(Ok(chunk_overflow)) => {
+                pregen.progress.generated += 1;
+                pregen.saved.insert(*key);
+                overflow.merge(chunk_overflow);
+                false
+            }
+            Some(Err(err)) => {
+                error!("Failed to pregenerate chunk {:?}: {}", key, err);
+                pregen.progress.failed += 1;
+                false
+            }
+            None => true,
+        },
+    );
+
+    // the loaded neighbors get the spilling voxels right away and the saved ones through a patch of their save. The
+    // ones with a task in flight or left to pregenerate get them once the task is over, the others once generated.
+    for key in overflow.iter_keys().copied().collect::<Vec<_>>() {
+        let edits = overflow.take(key).unwrap();
+        if let Some(buffer) = chunk_data.buffer_at_mut(key) {
+            PendingVoxelEdits::apply(&edits, buffer);
+            dirty_chunks.mark_dirty(key);
+            edits
+                .iter()
+                .for_each(|(pos, _)| light_updates.queue(key + pos.as_ivec3()));
+        } else if pregen.is_busy(key) || pregen.pending.contains(&key) {
+            edits
+                .into_iter()
+                .for_each(|(pos, voxel)| pregen.overflow.push(key + pos.as_ivec3(), voxel));
+        } else if let Some(world_save) = world_save
+            .as_deref()
+            .filter(|_| pregen.saved.contains(&key))
+        {
+            let world_save = world_save.clone();
+            pregen.patches.push((
+                key,
+                IoTaskPool::get().spawn(async move { patch_saved_chunk(key, world_save, edits) }),
+            ));
+        } else {
+            edits
+                .into_iter()
+                .for_each(|(pos, voxel)| pending_edits.push(key + pos.as_ivec3(), voxel));
+        }
+    }
+
+    let started = match pregen.started {
+        Some(started) => started,
+        None => return,
+    };
+
+    if let Some(world_save) = world_save {
+        let task_pool = AsyncComputeTaskPool::get();
+        let material_count = materials.iter_mats().count();
+
+        while pregen.tasks.len() < pregen.max_tasks {
+            let key = match pregen.pending.pop() {
+                Some(key) => key,
+                None => break,
+            };
+
+            // the chunks in use by the world get saved when they unload.
+            if chunk_data.exists(key) || chunk_data.is_cached(key) || gen_tasks.contains(key) {
+                pregen.progress.skipped += 1;
+                continue;
+            }
+
+            // the chunks loaded from the save don't get the pending edits anymore, they're written along the chunk.
+            let edits = pregen
+                .overflow
+                .take(key)
+                .into_iter()
+                .chain(pending_edits.take(key))
+                .flatten()
+                .collect();
+            let world_save = world_save.clone();
+            pregen.tasks.push((
+                key,
+                task_pool.spawn(async move {
+                    pregenerate_chunk(key, world_save, material_count, edits)
+                }),
+            ));
+        }
+    }
+
+    pregen.progress.elapsed = started.elapsed();
+    if pregen.pending.is_empty() && pregen.tasks.is_empty() {
+        pregen.started = None;
+        finished_events.send(ChunkPregenFinished {
+            progress: pregen.progress,
+            cancelled: false,
+        });
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`ChunkPregenPlugin`]
+pub enum ChunkPregenSystem {
+    /// Starts and cancels the pregenerations.
+    HandleEvents,
+    /// Spawns and polls the pregeneration tasks.
+    ProcessTasks,
+}
+
+/// Pregenerates the chunks of an area to the world save when requested through [`StartChunkPregen`] events.
+pub struct ChunkPregenPlugin;
+
+impl Plugin for ChunkPregenPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkPregen>()
+            .add_event::<StartChunkPregen>()
+            .add_event::<CancelChunkPregen>()
+            .add_event::<ChunkPregenFinished>()
+            .add_system_to_stage(
+                TerrainGenStage,
+                handle_pregen_events.label(ChunkPregenSystem::HandleEvents),
+            )
+            .add_system_to_stage(
+                TerrainGenStage,
+                process_pregen_tasks
+                    .label(ChunkPregenSystem::ProcessTasks)
+                    .after(ChunkPregenSystem::HandleEvents)
+                    .after(TerrainGenSystem::ProcessTerrainGen),
+            );
+    }
+}
@@ -0,0 +1,109 @@
+use bevy::math::{IVec3, Vec3};
+
+use super::ChunkShape;
+use crate::voxel::{storage::ChunkMap, Voxel};
+
+/// The result of a ray cast against the voxels of the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VoxelRaycastHit {
+    /// World position of the voxel which was hit.
+    pub position: IVec3,
+    /// Normal of the face through which the ray entered the voxel, zero if the ray started inside of it.
+    pub normal: IVec3,
+    pub voxel: Voxel,
+    /// Distance traveled by the ray before hitting the voxel.
+    pub distance: f32,
+}
+
+/// Casts a ray through the loaded voxels of the world, returning the first non-empty voxel for which `filter` returns true.
+/// The ray is traversed voxel by voxel (Amanatides & Woo) so no voxel along the ray gets skipped,
+/// the voxels of empty chunks aren't read.
+/// `origin` is relative to the world position `offset` so that rays cast far away from the world origin keep their precision,
+/// rendered positions being relative to the [`super::WorldOrigin`].
+pub fn raycast_voxels(
+    chunks: &ChunkMap<Voxel, ChunkShape>,
+    origin: Vec3,
+    offset: IVec3,
+    direction: Vec3,
+    max_distance: f32,
+    filter: impl Fn(Voxel) -> bool,
+) -> Option<VoxelRaycastHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+
+    let mut position = origin.floor().as_ivec3();
+    let mut normal = IVec3::ZERO;
+    let mut distance = 0.0;
+
+    let mut step = IVec3::ZERO;
+    let mut t_delta = Vec3::splat(f32::INFINITY);
+    let mut t_max = Vec3::splat(f32::INFINITY);
+
+    for axis in 0..3 {
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            t_max[axis] = (position[axis] as f32 + 1.0 - origin[axis]) / direction[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            t_max[axis] = (origin[axis] - position[axis] as f32) / -direction[axis];
+        } else {
+            continue;
+        }
+        t_delta[axis] = 1.0 / direction[axis].abs();
+    }
+
+    // emptiness of the chunk the ray is currently in, to avoid looking it up for each voxel.
+    let mut current_chunk: Option<(IVec3, bool)> = None;
+
+    while distance <= max_distance {
+        let world_position = position + offset;
+        let chunk_key = world_position & chunks.shape_mask();
+        let chunk_empty = match current_chunk {
+            Some((key, empty)) if key == chunk_key => empty,
+            _ => {
+                let empty = chunks.is_chunk_empty(chunk_key);
+                current_chunk = Some((chunk_key, empty));
+                empty
+            }
+        };
+        // the voxels of bounded maps may be barrier voxels even inside of empty chunks.
+        let skip = chunk_empty
+            && chunks
+                .bounds()
+                .map_or(true, |bounds| bounds.contains(world_position));
+
+        match (!skip).then(|| chunks.voxel_at(world_position)).flatten() {
+            Some(voxel) if voxel != Voxel::EMPTY_VOXEL && filter(voxel) => {
+                return Some(VoxelRaycastHit {
+                    position: world_position,
+                    normal,
+                    voxel,
+                    distance,
+                })
+            }
+            _ => {}
+        }
+
+        let axis = if t_max.x < t_max.y {
+            if t_max.x < t_max.z {
+                0
+            } else {
+                2
+            }
+        } else if t_max.y < t_max.z {
+            1
+        } else {
+            2
+        };
+
+        position[axis] += step[axis];
+        distance = t_max[axis];
+        t_max[axis] += t_delta[axis];
+        normal = IVec3::ZERO;
+        normal[axis] = -step[axis];
+    }
+
+    None
+}
@@ -0,0 +1,456 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    app::AppExit,
+    input::Input,
+    math::{IVec3, Quat, Vec2, Vec3},
+    prelude::{
+        error, info, Commands, CoreStage, EventReader, EventWriter,
+        ParallelSystemDescriptorCoercion, Plugin, Query, Res, ResMut, SystemLabel, Time, Transform,
+        With,
+    },
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    input::{GamepadSticks, InputAction, InputMapSystem},
+    origin::WorldOrigin,
+    player::{PlayerController, PlayerControllerSystem},
+    ChunkShape,
+};
+use crate::voxel::{
+    net::NetworkVoxelEdit, schematic::ImportSchematic, storage::ChunkMap, terraingen::WorldSeed,
+    Voxel,
+};
+
+/// Version of the replay file format, replays of other versions can't be played back.
+pub const REPLAY_VERSION: u32 = 1;
+
+/// An event of the world recorded in a replay.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ReplayEvent {
+    VoxelEdit { pos: [i32; 3], voxel: u8 },
+    ImportSchematic { path: PathBuf, position: [i32; 3] },
+}
+
+/// The inputs, player transform and world events of a recorded frame.
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    /// Time since the start of the recording, in seconds.
+    pub time: f32,
+    /// The pressed actions.
+    pub actions: Vec<InputAction>,
+    pub movement_stick: [f32; 2],
+    pub look_stick: [f32; 2],
+    pub cursor_stick: [f32; 2],
+    pub scroll_stick: [f32; 2],
+    /// World origin the player translation is relative to.
+    pub origin: [i32; 3],
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub events: Vec<ReplayEvent>,
+}
+
+/// A recording of the frames of a session, played back on a world generated with the same seed.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: i32,
+    pub frames: Vec<ReplayFrame>,
+}
+
+#[allow(dead_code)]
+impl Replay {
+    /// Reads a replay from a gzipped RON file.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let replay: Self = ron::de::from_reader(GzDecoder::new(BufReader::new(File::open(path)?)))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(anyhow::anyhow!(
+                "replay version {} isn't supported (expected {})",
+                replay.version,
+                REPLAY_VERSION
+            ));
+        }
+        Ok(replay)
+    }
+
+    /// Writes the replay to a gzipped RON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut encoder =
+            GzEncoder::new(BufWriter::new(File::create(path)?), Compression::default());
+        ron::ser::to_writer(&mut encoder, self)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    /// Returns the duration of the recording, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.frames.last().map_or(0.0, |frame| frame.time)
+    }
+}
+
+/// Records the session to a replay file, from the frame the resource gets inserted.
+/// The recording is saved when the app exits or a [`StopReplayRecording`] event is sent.
+///
+/// For the replay to be deterministic, the recording should start along with the app (e.g. with `--record`), so that
+/// the world gets generated the same way during the playback.
+pub struct ReplayRecorder {
+    path: PathBuf,
+    replay: Replay,
+    /// Time the recording started at, set on its first frame.
+    start: Option<f64>,
+}
+
+#[allow(dead_code)]
+impl ReplayRecorder {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            replay: Replay {
+                version: REPLAY_VERSION,
+                ..Default::default()
+            },
+            start: None,
+        }
+    }
+
+    /// Returns the number of recorded frames.
+    pub fn frame_count(&self) -> usize {
+        self.replay.frames.len()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Plays a replay back at its recorded pace: the frames are played back once the time elapsed since the start of the
+/// playback reaches their recorded time, the recorded actions replace the player inputs, the player follows its
+/// recorded transform and the recorded world events are sent again. The resource is removed once done.
+pub struct ReplayPlayer {
+    replay: Replay,
+    /// Number of frames played back so far.
+    played: usize,
+    /// Time the playback started at, set on its first frame.
+    start: Option<f64>,
+}
+
+#[allow(dead_code)]
+impl ReplayPlayer {
+    /// Loads the replay to play back, see [`ReplayPlayer::seed`] for generating the same world.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(Self {
+            replay: Replay::load(path)?,
+            played: 0,
+            start: None,
+        })
+    }
+
+    /// Returns the seed of the recorded world, which the world played back on must be generated with.
+    pub fn seed(&self) -> WorldSeed {
+        WorldSeed(self.replay.seed)
+    }
+
+    /// Returns the number of frames played back so far and the number of frames of the replay.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.played, self.replay.frames.len())
+    }
+
+    /// Returns the last frame played back once `played` frames were, `None` before the first one.
+    fn last_played_frame(&self, played: usize) -> Option<&ReplayFrame> {
+        self.replay.frames.get(played.checked_sub(1)?)
+    }
+}
+
+/// The voxel edits played back while their chunk wasn't loaded yet, sent again once it is.
+#[derive(Default)]
+struct QueuedReplayEdits(Vec<(IVec3, Voxel)>);
+
+/// Event stopping the recording of the session, and saving it.
+pub struct StopReplayRecording;
+
+/// Records the world events sent during the frame, the inputs and the transform of the player.
+#[allow(clippy::too_many_arguments)]
+fn record_frame(
+    recorder: Option<ResMut<ReplayRecorder>>,
+    mut edits: EventReader<NetworkVoxelEdit>,
+    mut imports: EventReader<ImportSchematic>,
+    actions: Res<Input<InputAction>>,
+    sticks: Res<GamepadSticks>,
+    player: Query<&Transform, With<PlayerController>>,
+    origin: Res<WorldOrigin>,
+    seed: Res<WorldSeed>,
+    time: Res<Time>,
+) {
+    let mut recorder = match recorder {
+        Some(recorder) => recorder,
+        None => return,
+    };
+
+    let now = time.seconds_since_startup();
+    let start = *recorder.start.get_or_insert_with(|| {
+        info!("Started recording the replay");
+        now
+    });
+    recorder.replay.seed = seed.0;
+
+    let mut events: Vec<ReplayEvent> = edits
+        .iter()
+        .map(|edit| ReplayEvent::VoxelEdit {
+            pos: edit.pos.to_array(),
+            voxel: edit.voxel.0,
+        })
+        .collect();
+    events.extend(imports.iter().map(|import| ReplayEvent::ImportSchematic {
+        path: import.path.clone(),
+        position: import.position.to_array(),
+    }));
+
+    let transform = player.get_single().copied().unwrap_or_default();
+    recorder.replay.frames.push(ReplayFrame {
+        time: (now - start) as f32,
+        actions: actions.get_pressed().copied().collect(),
+        movement_stick: sticks.movement.to_array(),
+        look_stick: sticks.look.to_array(),
+        cursor_stick: sticks.cursor.to_array(),
+        scroll_stick: sticks.scroll.to_array(),
+        origin: origin.get().to_array(),
+        translation: transform.translation.to_array(),
+        rotation: transform.rotation.to_array(),
+        events,
+    });
+}
+
+/// Saves the recording when stopped or when the app exits.
+fn save_recording(
+    recorder: Option<Res<ReplayRecorder>>,
+    mut stop_events: EventReader<StopReplayRecording>,
+    exit_events: EventReader<AppExit>,
+    mut commands: Commands,
+) {
+    let recorder = match recorder {
+        Some(recorder) if stop_events.iter().count() > 0 || !exit_events.is_empty() => recorder,
+        _ => return,
+    };
+
+    match recorder.replay.save(&recorder.path) {
+        Ok(()) => info!(
+            "Saved the replay of {} frames ({:.1}s) to {}",
+            recorder.frame_count(),
+            recorder.replay.duration(),
+            recorder.path.display()
+        ),
+        Err(err) => error!(
+            "Failed to save the replay to {}: {}",
+            recorder.path.display(),
+            err
+        ),
+    }
+    commands.remove_resource::<ReplayRecorder>();
+}
+
+/// Replaces the state of the actions with the recorded one, the actions pressed on the previous frame being kept
+/// pressed without being just pressed again.
+fn replay_actions(
+    actions: &mut Input<InputAction>,
+    previous: &[InputAction],
+    current: &[InputAction],
+) {
+    for action in InputAction::ALL {
+        actions.reset(action);
+        let was_pressed = previous.contains(&action);
+        if was_pressed {
+            actions.press(action);
+            actions.clear_just_pressed(action);
+        }
+
+        if current.contains(&action) {
+            actions.press(action);
+        } else if was_pressed {
+            actions.release(action);
+        }
+    }
+}
+
+/// Sends the queued voxel edits of the chunks loaded since, in the recorded order.
+fn send_queued_replay_edits(
+    mut queued: ResMut<QueuedReplayEdits>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    mut edits: EventWriter<NetworkVoxelEdit>,
+) {
+    if queued.0.is_empty() {
+        return;
+    }
+
+    queued.0.retain(|(pos, voxel)| {
+        let loaded = chunks.voxel_at(*pos).is_some();
+        if loaded {
+            edits.send(NetworkVoxelEdit {
+                pos: *pos,
+                voxel: *voxel,
+                journaled: true,
+                client: None,
+            });
+        }
+        !loaded
+    });
+}
+
+/// Plays back the frames whose recorded time was reached: replaces the inputs of the player with the ones of the last
+/// of them, and sends their world events again. The voxel edits of the chunks not loaded yet are queued, the playback
+/// may run ahead of the terrain generation.
+#[allow(clippy::too_many_arguments)]
+fn play_back_inputs(
+    player: Option<ResMut<ReplayPlayer>>,
+    mut actions: ResMut<Input<InputAction>>,
+    mut sticks: ResMut<GamepadSticks>,
+    mut edits: EventWriter<NetworkVoxelEdit>,
+    mut imports: EventWriter<ImportSchematic>,
+    mut queued: ResMut<QueuedReplayEdits>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    time: Res<Time>,
+) {
+    let mut player = match player {
+        Some(player) => player,
+        None => return,
+    };
+    let player = &mut *player;
+
+    let now = time.seconds_since_startup();
+    let elapsed = (now - *player.start.get_or_insert(now)) as f32;
+    let first = player.played;
+    player.played += player.replay.frames[first..]
+        .iter()
+        .take_while(|frame| frame.time <= elapsed)
+        .count();
+
+    for event in player.replay.frames[first..player.played]
+        .iter()
+        .flat_map(|frame| frame.events.iter())
+    {
+        match event {
+            ReplayEvent::VoxelEdit { pos, voxel } => {
+                let (pos, voxel) = (IVec3::from(*pos), Voxel(*voxel));
+                // the earlier edits queued for the chunk were sent again first, if it got loaded.
+                if chunks.voxel_at(pos).is_some() {
+                    edits.send(NetworkVoxelEdit {
+                        pos,
+                        voxel,
+                        journaled: true,
+                        client: None,
+                    });
+                } else {
+                    queued.0.push((pos, voxel));
+                }
+            }
+            ReplayEvent::ImportSchematic { path, position } => imports.send(ImportSchematic {
+                path: path.clone(),
+                position: IVec3::from(*position),
+            }),
+        }
+    }
+
+    // the actions of the last frame played back stay pressed until the next one is due.
+    let frame = match player.last_played_frame(player.played) {
+        Some(frame) => frame,
+        None => return,
+    };
+    let previous = if player.played > first {
+        player.last_played_frame(first)
+    } else {
+        Some(frame)
+    }
+    .map_or(&[][..], |previous| &previous.actions);
+    replay_actions(&mut actions, previous, &frame.actions);
+
+    *sticks = GamepadSticks {
+        movement: Vec2::from(frame.movement_stick),
+        look: Vec2::from(frame.look_stick),
+        cursor: Vec2::from(frame.cursor_stick),
+        scroll: Vec2::from(frame.scroll_stick),
+    };
+}
+
+/// Moves the player to its transform of the last frame played back, the resource being removed after the last frame.
+fn play_back_player(
+    player: Option<Res<ReplayPlayer>>,
+    mut controllers: Query<(&mut PlayerController, &mut Transform)>,
+    origin: Res<WorldOrigin>,
+    mut commands: Commands,
+) {
+    let player = match player {
+        Some(player) => player,
+        None => return,
+    };
+
+    let frame = match player.last_played_frame(player.played) {
+        Some(frame) => frame,
+        None => return,
+    };
+
+    if let Ok((mut controller, mut transform)) = controllers.get_single_mut() {
+        // the recorded translation is relative to the origin of the recording.
+        let origin_offset = IVec3::from(frame.origin) - origin.get();
+        transform.translation = Vec3::from(frame.translation) + origin_offset.as_vec3();
+        transform.rotation = Quat::from_array(frame.rotation);
+        controller.sync_rotation(transform.rotation);
+    }
+
+    if player.played == player.replay.frames.len() {
+        info!("Finished playing back the replay");
+        commands.remove_resource::<ReplayPlayer>();
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, SystemLabel)]
+/// Labels for the systems added by [`VoxelWorldReplayPlugin`]
+pub enum ReplaySystem {
+    /// Sends the played back voxel edits of the chunks loaded since.
+    SendQueuedEdits,
+    /// Replaces the player inputs with the played back ones.
+    PlayBackInputs,
+    /// Moves the player to its played back transform.
+    PlayBackPlayer,
+    /// Records the frame.
+    RecordFrame,
+}
+
+/// Records sessions to replay files when a [`ReplayRecorder`] resource is inserted, and plays them back when a
+/// [`ReplayPlayer`] resource is inserted.
+pub struct VoxelWorldReplayPlugin;
+
+impl Plugin for VoxelWorldReplayPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_event::<StopReplayRecording>()
+            .init_resource::<QueuedReplayEdits>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                send_queued_replay_edits.label(ReplaySystem::SendQueuedEdits),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                play_back_inputs
+                    .label(ReplaySystem::PlayBackInputs)
+                    .after(InputMapSystem::UpdateActions)
+                    .after(ReplaySystem::SendQueuedEdits),
+            )
+            .add_system(
+                play_back_player
+                    .label(ReplaySystem::PlayBackPlayer)
+                    .after(PlayerControllerSystem::ApplyPhysics),
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                record_frame.label(ReplaySystem::RecordFrame),
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                save_recording.after(ReplaySystem::RecordFrame),
+            );
+    }
+}
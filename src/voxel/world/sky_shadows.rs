@@ -0,0 +1,153 @@
+use bevy::{
+    math::{IVec2, IVec3, Vec3, Vec3Swizzles},
+    prelude::{Local, Plugin, Res, ResMut},
+    utils::HashSet,
+};
+
+use super::{
+    chunks::CurrentLocalPlayerChunk,
+    column_heights::{ColumnHeightmaps, COLUMN_NO_HEIGHT},
+    stages::ChunkMeshingStage,
+    CHUNK_LENGTH,
+};
+
+/// Radius (in chunks) of the area around the player covered by the sky shadow heightfield.
+pub const SKY_SHADOW_RADIUS: i32 = 8;
+
+/// Height of the columns without any loaded opaque voxel.
+pub const SKY_SHADOW_NO_HEIGHT: f32 = -1.0e9;
+
+/// Settings of the soft terrain shadows cast by the sky light, approximated by ray marching a heightfield in the terrain shader.
+pub struct SkyShadowSettings {
+    pub enabled: bool,
+    /// Direction pointing toward the sun.
+    pub sun_direction: Vec3,
+    /// How much the shadows darken the sunlight, from 0 to 1.
+    pub strength: f32,
+    /// Sharpness of the penumbra, lower values give softer shadows.
+    pub softness: f32,
+    /// Maximum distance (in voxels) marched toward the sun.
+    pub max_distance: f32,
+}
+
+impl Default for SkyShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sun_direction: Vec3::new(0.4, 1.0, 0.3).normalize(),
+            strength: 0.5,
+            softness: 8.0,
+            max_distance: 96.0,
+        }
+    }
+}
+
+/// The height of the highest solid voxel of each column around the player.
+#[derive(Default)]
+pub struct SkyShadowHeightfield {
+    /// World position (on the X and Z axes) of the first column.
+    pub origin: IVec2,
+    /// Number of columns along each side of the heightfield.
+    pub size: u32,
+    /// Height of the top of the highest solid voxel of each column, in rows along the X axis.
+    /// Columns without any loaded solid voxel are at [`SKY_SHADOW_NO_HEIGHT`].
+    pub heights: Vec<f32>,
+}
+
+impl SkyShadowHeightfield {
+    /// Returns the height of the specified column, if covered by the heightfield.
+    #[allow(dead_code)]
+    pub fn height_at(&self, column: IVec2) -> Option<f32> {
+        let local = column - self.origin;
+        if local.cmplt(IVec2::ZERO).any() || local.cmpge(IVec2::splat(self.size as i32)).any() {
+            return None;
+        }
+
+        self.heights
+            .get((local.y * self.size as i32 + local.x) as usize)
+            .copied()
+    }
+}
+
+/// Copies the heights of a stack of chunks to the heightfield.
+fn update_chunk_stack(
+    heightfield: &mut SkyShadowHeightfield,
+    heightmaps: &ColumnHeightmaps,
+    stack: IVec2,
+) {
+    let local = stack - heightfield.origin;
+    let size = heightfield.size as i32;
+    let stack_heights = heightmaps.column_heights(stack);
+    for z in 0..CHUNK_LENGTH as i32 {
+        let row = (local.y + z) * size + local.x;
+        let heights = &mut heightfield.heights[row as usize..(row + CHUNK_LENGTH as i32) as usize];
+        match stack_heights {
+            Some(stack_heights) => heights
+                .iter_mut()
+                .zip(&stack_heights[(z * CHUNK_LENGTH as i32) as usize..])
+                .for_each(|(height, stack_height)| {
+                    *height = if *stack_height == COLUMN_NO_HEIGHT {
+                        SKY_SHADOW_NO_HEIGHT
+                    } else {
+                        *stack_height as f32
+                    }
+                }),
+            None => heights.fill(SKY_SHADOW_NO_HEIGHT),
+        }
+    }
+}
+
+/// Keeps the sky shadow heightfield centered on the player and up to date with the chunk modifications.
+fn update_sky_shadow_heightfield(
+    settings: Res<SkyShadowSettings>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    heightmaps: Res<ColumnHeightmaps>,
+    mut heightfield: ResMut<SkyShadowHeightfield>,
+    mut center: Local<Option<IVec3>>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    let chunk_length = CHUNK_LENGTH as i32;
+    let origin_chunk = player_chunk.chunk_min.xz() - IVec2::splat(SKY_SHADOW_RADIUS * chunk_length);
+    let mut stacks: HashSet<IVec2> = HashSet::default();
+
+    if *center != Some(player_chunk.chunk_min) {
+        *center = Some(player_chunk.chunk_min);
+
+        let size = ((2 * SKY_SHADOW_RADIUS + 1) * chunk_length) as u32;
+        heightfield.origin = origin_chunk;
+        heightfield.size = size;
+        heightfield.heights = vec![SKY_SHADOW_NO_HEIGHT; (size * size) as usize];
+
+        for z in -SKY_SHADOW_RADIUS..=SKY_SHADOW_RADIUS {
+            for x in -SKY_SHADOW_RADIUS..=SKY_SHADOW_RADIUS {
+                stacks.insert(player_chunk.chunk_min.xz() + IVec2::new(x, z) * chunk_length);
+            }
+        }
+    } else {
+        stacks.extend(heightmaps.iter_changed().copied().filter(|stack| {
+            let local = *stack - origin_chunk;
+            local.cmpge(IVec2::ZERO).all()
+                && local
+                    .cmplt(IVec2::splat((2 * SKY_SHADOW_RADIUS + 1) * chunk_length))
+                    .all()
+        }));
+    }
+
+    for stack in stacks {
+        update_chunk_stack(&mut heightfield, &heightmaps, stack);
+    }
+}
+
+/// Maintains the heightfield used for approximating the terrain shadows cast by the sky light.
+pub struct SkyShadowsPlugin;
+
+impl Plugin for SkyShadowsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<SkyShadowSettings>()
+            .init_resource::<SkyShadowHeightfield>()
+            .add_system_to_stage(ChunkMeshingStage, update_sky_shadow_heightfield);
+    }
+}
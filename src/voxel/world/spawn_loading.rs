@@ -0,0 +1,177 @@
+use bevy::{
+    ecs::schedule::ShouldRun,
+    math::IVec3,
+    prelude::{info, warn, CoreStage, EventReader, Plugin, Res, ResMut},
+    utils::{Duration, HashSet, Instant},
+};
+
+use super::{
+    chunks::{ChunkEntities, ChunkLoadRadius, CurrentLocalPlayerChunk},
+    level::AuthoredLevel,
+    stats::ChunkLifecycleEvent,
+    terrain::MAX_GENERATED_HEIGHT,
+    ChunkShape, CHUNK_SIZE,
+};
+use crate::voxel::{storage::ChunkMap, Voxel};
+
+/// Settings of the loading of the chunks around the spawn, during which the player has no control.
+pub struct SpawnLoadingSettings {
+    pub enabled: bool,
+    /// Horizontal radius (in chunks) of the area around the spawn generated and meshed before the player gains control,
+    /// capped to the [`ChunkLoadRadius`].
+    pub radius: i32,
+    /// Time after which the player gains control even if the area isn't fully loaded (e.g. with a slow server).
+    pub timeout: Duration,
+}
+
+impl Default for SpawnLoadingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            radius: 6,
+            timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Counts of the chunks of the spawn area loaded so far.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpawnLoadingProgress {
+    /// Number of chunks of the spawn area, the ones left empty by the terrain generation aside.
+    pub total: usize,
+    /// Number of chunks whose data got generated or loaded from the world save.
+    pub generated: usize,
+    /// Number of chunks whose mesh got applied.
+    pub meshed: usize,
+    /// Time elapsed since the loading started.
+    pub elapsed: Duration,
+}
+
+#[allow(dead_code)]
+impl SpawnLoadingProgress {
+    /// Returns the fraction of the work done so far, between 0 and 1, generating and meshing counting as much.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        (self.generated + self.meshed) as f32 / (2 * self.total) as f32
+    }
+}
+
+/// Loading of the chunks around the spawn at startup, the player controller being disabled until the area is generated
+/// and meshed so that the player doesn't see the terrain pop in nor fall through it.
+#[derive(Default)]
+pub struct SpawnLoading {
+    loaded: bool,
+    progress: SpawnLoadingProgress,
+    /// The chunks of the spawn area meshed so far.
+    meshed: HashSet<IVec3>,
+    started: Option<Instant>,
+}
+
+#[allow(dead_code)]
+impl SpawnLoading {
+    /// Returns whether the spawn area is loaded, the player having control.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    pub fn progress(&self) -> SpawnLoadingProgress {
+        self.progress
+    }
+
+    /// Gives the control to the player right away, the remaining chunks popping in as usual.
+    pub fn skip(&mut self) {
+        self.loaded = true;
+        self.meshed.clear();
+    }
+}
+
+/// Counts the generated and meshed chunks of the spawn area, and ends the loading once they're all meshed.
+#[allow(clippy::too_many_arguments)]
+fn update_spawn_loading(
+    settings: Res<SpawnLoadingSettings>,
+    mut loading: ResMut<SpawnLoading>,
+    mut lifecycle_events: EventReader<ChunkLifecycleEvent>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    load_radius: Res<ChunkLoadRadius>,
+    chunk_entities: Res<ChunkEntities>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    level: Option<Res<AuthoredLevel>>,
+) {
+    if loading.loaded {
+        return;
+    }
+    if !settings.enabled {
+        loading.skip();
+        return;
+    }
+
+    let started = *loading.started.get_or_insert_with(Instant::now);
+    for event in lifecycle_events.iter() {
+        if let ChunkLifecycleEvent::Meshed(key) = event {
+            loading.meshed.insert(*key);
+        }
+    }
+
+    // the chunks never getting any data (above the generated height or outside of the level) aren't waited for.
+    let radius = settings.radius.min(load_radius.horizontal);
+    let keys: Vec<IVec3> = chunk_entities
+        .iter_keys()
+        .copied()
+        .filter(|key| {
+            let offset = (*key - player_chunk.chunk_min) / CHUNK_SIZE;
+            offset.x.pow(2) + offset.z.pow(2) <= radius.pow(2)
+                && key.y < MAX_GENERATED_HEIGHT
+                && level
+                    .as_ref()
+                    .map_or(true, |level| level.contains_chunk(*key))
+        })
+        .collect();
+
+    let progress = SpawnLoadingProgress {
+        total: keys.len(),
+        generated: keys.iter().filter(|key| chunks.exists(**key)).count(),
+        meshed: keys
+            .iter()
+            .filter(|key| loading.meshed.contains(*key))
+            .count(),
+        elapsed: started.elapsed(),
+    };
+    loading.progress = progress;
+
+    // the chunk entities only get created once the player chunk is known.
+    if progress.total > 0 && progress.meshed == progress.total {
+        info!(
+            "Loaded the {} chunks around the spawn in {:.2?}",
+            progress.total, progress.elapsed
+        );
+        loading.skip();
+    } else if progress.elapsed >= settings.timeout {
+        warn!(
+            "Gave the control to the player after {:.2?}, with {} of the {} chunks around the spawn meshed",
+            progress.elapsed, progress.meshed, progress.total
+        );
+        loading.skip();
+    }
+}
+
+/// Run criteria of the player controller systems, which only run once the spawn area is loaded.
+pub fn spawn_area_loaded(loading: Res<SpawnLoading>) -> ShouldRun {
+    if loading.loaded {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Generates and meshes the chunks around the spawn before the player gains control, see [`SpawnLoading`].
+pub struct SpawnLoadingPlugin;
+
+impl Plugin for SpawnLoadingPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<SpawnLoadingSettings>()
+            .init_resource::<SpawnLoading>()
+            .add_system_to_stage(CoreStage::PostUpdate, update_spawn_loading);
+    }
+}
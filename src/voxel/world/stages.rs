@@ -0,0 +1,141 @@
+use bevy::{
+    ecs::schedule::ShouldRun,
+    prelude::{CoreStage, Plugin, Res, ResMut, StageLabel, SystemStage},
+};
+
+/// Label for the stage housing the chunk loading systems.
+/// Runs after [`CoreStage::Update`], so that the voxels edited by the gameplay systems get lit and meshed on the same frame.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, StageLabel)]
+pub struct ChunkLoadingStage;
+
+/// Label for the stage housing the terrain generation systems, runs after [`ChunkLoadingStage`].
+/// A whole stage is needed for the terrain generation to see the chunks added by the chunk loading through `Added` queries.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, StageLabel)]
+pub struct TerrainGenStage;
+
+/// Label for the stage housing the light propagation systems, runs after [`TerrainGenStage`].
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, StageLabel)]
+pub struct LightingStage;
+
+/// A stage existing solely for enabling the use of change detection, runs after [`LightingStage`].
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, StageLabel)]
+pub struct ChunkMeshingPrepareStage;
+
+/// Label for the stage housing the chunk meshing systems, runs after [`ChunkMeshingPrepareStage`] and before [`CoreStage::PostUpdate`].
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, StageLabel)]
+pub struct ChunkMeshingStage;
+
+/// Pause of the voxel world stages (chunk loading, terrain generation, fluids, lighting and meshing), the camera and the
+/// gameplay systems still running. While paused, the stages can be advanced one update at a time for debugging the
+/// simulation and the state transitions of the chunk pipeline.
+#[derive(Default)]
+pub struct VoxelSimulationPause {
+    pub paused: bool,
+    /// Number of updates of the voxel stages left to run while paused.
+    pending_steps: u32,
+    /// Whether the voxel stages run during the current frame.
+    running: bool,
+    /// Whether the voxel stages run during the current frame because of a step.
+    stepping: bool,
+}
+
+#[allow(dead_code)]
+impl VoxelSimulationPause {
+    /// Queues a single update of the voxel stages, run during the next frame. Does nothing unless paused.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    /// Returns whether the voxel stages run during the current frame.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Returns whether the voxel stages run during the current frame because of a [`Self::step`], the fixed rate
+    /// simulations (fluids, block ticks) then ticking exactly once regardless of the time elapsed.
+    pub fn is_stepping(&self) -> bool {
+        self.stepping
+    }
+
+    /// Returns the number of updates queued with [`Self::step`] not run yet.
+    pub fn pending_steps(&self) -> u32 {
+        self.pending_steps
+    }
+}
+
+/// Decides whether the voxel stages run during the frame, consuming a step when paused.
+fn update_simulation_pause(mut pause: ResMut<VoxelSimulationPause>) {
+    let (running, stepping) = if !pause.paused {
+        pause.pending_steps = 0;
+        (true, false)
+    } else if pause.pending_steps > 0 {
+        pause.pending_steps -= 1;
+        (true, true)
+    } else {
+        (false, false)
+    };
+
+    // avoids flagging the resource as changed every frame.
+    if pause.running != running || pause.stepping != stepping {
+        pause.running = running;
+        pause.stepping = stepping;
+    }
+}
+
+/// Run criteria of the voxel stages and of the systems only making sense when they run, e.g. the clearing of the dirty
+/// chunks not meshed yet.
+pub fn voxel_simulation_running(pause: Res<VoxelSimulationPause>) -> ShouldRun {
+    if pause.running {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Adds the stages of the voxel world between [`CoreStage::Update`] and [`CoreStage::PostUpdate`], in this order:
+/// [`ChunkLoadingStage`], [`TerrainGenStage`], [`LightingStage`], [`ChunkMeshingPrepareStage`] and [`ChunkMeshingStage`].
+///
+/// The stages all exist once the plugin is added, so the systems of other plugins can be added to them regardless of the
+/// order in which the plugins are added, and ordered relative to the engine systems through their public labels
+/// (e.g. [`super::ChunkLoadingSystem`], [`super::TerrainGenSystem`], [`super::LightingSystem`], [`super::ChunkRenderingSystem`]).
+/// New stages can be inserted between them with `add_stage_after` / `add_stage_before`.
+///
+/// The stages don't run while the [`VoxelSimulationPause`] is paused.
+pub struct VoxelWorldStagesPlugin;
+
+impl Plugin for VoxelWorldStagesPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.insert_resource(VoxelSimulationPause {
+            running: true,
+            ..Default::default()
+        })
+        .add_system_to_stage(CoreStage::First, update_simulation_pause)
+        .add_stage_after(
+            CoreStage::Update,
+            ChunkLoadingStage,
+            SystemStage::parallel().with_run_criteria(voxel_simulation_running),
+        )
+        .add_stage_after(
+            ChunkLoadingStage,
+            TerrainGenStage,
+            SystemStage::parallel().with_run_criteria(voxel_simulation_running),
+        )
+        .add_stage_after(
+            TerrainGenStage,
+            LightingStage,
+            SystemStage::single_threaded().with_run_criteria(voxel_simulation_running),
+        )
+        .add_stage_after(
+            LightingStage,
+            ChunkMeshingPrepareStage,
+            SystemStage::single_threaded().with_run_criteria(voxel_simulation_running),
+        )
+        .add_stage_after(
+            ChunkMeshingPrepareStage,
+            ChunkMeshingStage,
+            SystemStage::parallel().with_run_criteria(voxel_simulation_running),
+        );
+    }
+}
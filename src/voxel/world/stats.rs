@@ -0,0 +1,161 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    math::IVec3,
+    prelude::{CoreStage, EventReader, Plugin, Res, ResMut},
+    utils::{Duration, HashMap, Instant},
+};
+
+use super::chunks::ChunkEntities;
+
+/// Length of the window the throughputs of the [`ChunkPipelineStats`] are computed over.
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(1);
+/// Number of latency samples the percentiles of the [`ChunkPipelineStats`] are computed over.
+const LATENCY_SAMPLES: usize = 512;
+
+/// Events sent along the lifecycle of a chunk, from the request of its entity to its mesh becoming visible.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkLifecycleEvent {
+    /// The chunk got an entity, as it came in sight of the player.
+    Requested(IVec3),
+    /// The chunk data got loaded from the world save or generated.
+    Generated(IVec3),
+    /// A mesh of the chunk got applied, making it visible.
+    Meshed(IVec3),
+}
+
+/// Statistics of the chunk pipeline computed from the [`ChunkLifecycleEvent`]s, for tuning the chunk task budgets and
+/// priorities.
+pub struct ChunkPipelineStats {
+    /// The chunks requested and not visible yet, along with when they got requested.
+    requested: HashMap<IVec3, Instant>,
+    generated: VecDeque<Instant>,
+    meshed: VecDeque<Instant>,
+    /// Most recent latencies from the request of a chunk to its first mesh being applied.
+    latencies: VecDeque<Duration>,
+    /// Latencies of [`Self::latencies`] sorted, updated when new latencies get recorded.
+    sorted_latencies: Vec<Duration>,
+}
+
+impl Default for ChunkPipelineStats {
+    fn default() -> Self {
+        Self {
+            requested: Default::default(),
+            generated: Default::default(),
+            meshed: Default::default(),
+            latencies: VecDeque::with_capacity(LATENCY_SAMPLES),
+            sorted_latencies: Vec::with_capacity(LATENCY_SAMPLES),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl ChunkPipelineStats {
+    /// Returns the number of chunks generated (or loaded from the save) during the last second.
+    pub fn generated_per_second(&self) -> usize {
+        self.generated.len()
+    }
+
+    /// Returns the number of chunk meshes applied during the last second.
+    pub fn meshed_per_second(&self) -> usize {
+        self.meshed.len()
+    }
+
+    /// Returns the number of requested chunks not visible yet.
+    pub fn pending_chunks(&self) -> usize {
+        self.requested.len()
+    }
+
+    /// Returns the number of latency samples the percentiles are computed over.
+    pub fn latency_samples(&self) -> usize {
+        self.sorted_latencies.len()
+    }
+
+    /// Returns the latency from the request of a chunk to it being visible under which fall `percentile` percents of
+    /// the recent chunks, `None` until a chunk became visible.
+    pub fn latency_percentile(&self, percentile: f32) -> Option<Duration> {
+        let last = self.sorted_latencies.len().checked_sub(1)?;
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * last as f32).round() as usize;
+        Some(self.sorted_latencies[rank])
+    }
+
+    /// Forgets the recorded latencies and throughputs, keeping track of the chunks in flight.
+    pub fn reset(&mut self) {
+        self.generated.clear();
+        self.meshed.clear();
+        self.latencies.clear();
+        self.sorted_latencies.clear();
+    }
+
+    fn record_latency(&mut self, latency: Duration) {
+        if self.latencies.len() == LATENCY_SAMPLES {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency);
+    }
+}
+
+fn trim_window(events: &mut VecDeque<Instant>, now: Instant) {
+    while matches!(events.front(), Some(time) if now.duration_since(*time) > THROUGHPUT_WINDOW) {
+        events.pop_front();
+    }
+}
+
+/// Updates the [`ChunkPipelineStats`] from the lifecycle events of the frame.
+fn record_chunk_pipeline_stats(
+    mut events: EventReader<ChunkLifecycleEvent>,
+    chunk_entities: Res<ChunkEntities>,
+    mut stats: ResMut<ChunkPipelineStats>,
+) {
+    let now = Instant::now();
+    let mut latencies_changed = false;
+
+    for event in events.iter() {
+        match *event {
+            ChunkLifecycleEvent::Requested(key) => {
+                stats.requested.insert(key, now);
+            }
+            ChunkLifecycleEvent::Generated(_) => stats.generated.push_back(now),
+            ChunkLifecycleEvent::Meshed(key) => {
+                stats.meshed.push_back(now);
+                // only the first mesh of a chunk counts, the remeshes aren't waited on by the player.
+                if let Some(requested) = stats.requested.remove(&key) {
+                    stats.record_latency(now.duration_since(requested));
+                    latencies_changed = true;
+                }
+            }
+        }
+    }
+
+    // the chunks unloaded before becoming visible never will.
+    if chunk_entities.is_changed() {
+        stats
+            .requested
+            .retain(|key, _| chunk_entities.entity(*key).is_some());
+    }
+
+    trim_window(&mut stats.generated, now);
+    trim_window(&mut stats.meshed, now);
+
+    if latencies_changed {
+        let ChunkPipelineStats {
+            latencies,
+            sorted_latencies,
+            ..
+        } = &mut *stats;
+        sorted_latencies.clear();
+        sorted_latencies.extend(latencies.iter());
+        sorted_latencies.sort_unstable();
+    }
+}
+
+/// Computes the [`ChunkPipelineStats`] of the chunk generation throughput and latency.
+pub struct ChunkPipelineStatsPlugin;
+
+impl Plugin for ChunkPipelineStatsPlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<ChunkPipelineStats>()
+            .add_event::<ChunkLifecycleEvent>()
+            .add_system_to_stage(CoreStage::Last, record_chunk_pipeline_stats);
+    }
+}
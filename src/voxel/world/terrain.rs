@@ -1,65 +1,403 @@
 use super::{
-    chunks::{ChunkLoadingStage, DirtyChunks},
-    Chunk, ChunkShape,
+    chunks::{
+        sort_by_distance, ChunkCommandQueue, ChunkLoadingSystem, ChunkTaskBudget,
+        CurrentLocalPlayerChunk, DirtyChunks,
+    },
+    diagnostics::CHUNK_GENERATION_TIME,
+    level::AuthoredLevel,
+    lighting::LightUpdates,
+    materials::Snow,
+    persistence::ChunkSaveHeaders,
+    pipeline_log::{ChunkDecision, ChunkPipelineLog},
+    stages::TerrainGenStage,
+    stats::ChunkLifecycleEvent,
+    Chunk, ChunkShape, CHUNK_SIZE,
 };
 use crate::voxel::{
-    storage::{VoxelBuffer, ChunkMap},
-    terraingen::TERRAIN_GENERATOR,
+    material::{VoxelMaterial, VoxelMaterialRegistry},
+    net::ChunkClient,
+    storage::{
+        ChunkMap, ChunkMetadata, ChunkSaveHeader, SavedChunk, VoxelBuffer, VoxelMetadata,
+        VoxelMetadataMap, WorldSave,
+    },
+    terraingen::{
+        common::terrain_generate_fallback, structures::PendingVoxelEdits, TerrainGenError,
+        TERRAIN_GENERATOR,
+    },
     Voxel,
 };
 use bevy::{
+    diagnostic::Diagnostics,
+    math::{IVec3, UVec3},
     prelude::{
-        Added, Commands, Component, Entity, ParallelSystemDescriptorCoercion, Plugin, Query,
-        ResMut, StageLabel, SystemLabel, SystemStage,
+        error, warn, Added, CoreStage, EventReader, EventWriter, ParallelSystemDescriptorCoercion,
+        Plugin, Query, Res, ResMut, SystemLabel, SystemSet,
     },
     tasks::{AsyncComputeTaskPool, Task},
+    utils::{Duration, HashMap, HashSet, Instant},
 };
 use futures_lite::future;
 
-/// Queues the terrain gen async tasks for the newly created chunks.
+/// Chunks at or above this height are left empty and never get any data.
+pub const MAX_GENERATED_HEIGHT: i32 = 288;
+
+/// Maximum number of unloaded chunks with [`PendingVoxelEdits`], the edits of the chunks farthest from the player being
+/// dropped past this count.
+const MAX_PENDING_EDIT_CHUNKS: usize = 4096;
+
+/// Queues the terrain gen async tasks for the newly created chunks and the chunks requested as data only.
+/// Chunks which were previously saved are loaded from the world save instead of being generated.
+/// Authored levels are only loaded from their save, the chunks outside of the level never get any data.
+/// When connected to a server, the chunks are requested to the server instead.
+#[allow(clippy::too_many_arguments)]
 fn queue_terrain_gen(
-    mut commands: Commands,
-    new_chunks: Query<(Entity, &Chunk), Added<Chunk>>,
+    new_chunks: Query<&Chunk, Added<Chunk>>,
+    mut chunk_command_queue: ResMut<ChunkCommandQueue>,
+    mut gen_tasks: ResMut<TerrainGenTasks>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    mut chunk_data: ResMut<ChunkMap<Voxel, ChunkShape>>,
+    mut pending_edits: ResMut<PendingVoxelEdits>,
+    world_save: Option<Res<WorldSave>>,
+    level: Option<Res<AuthoredLevel>>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    budget: Res<ChunkTaskBudget>,
+    materials: Res<VoxelMaterialRegistry>,
+    mut client: Option<ResMut<ChunkClient>>,
+    mut log: ResMut<ChunkPipelineLog>,
 ) {
-
     let task_pool = AsyncComputeTaskPool::get();
+    let material_count = materials.iter_mats().count();
 
-    new_chunks
+    let requests: Vec<IVec3> = new_chunks
         .iter()
-        .filter(|(_, key)| key.0.y < 288)
-        .map(|(entity, key)| (entity, key.0.clone()))
-        .map(|(entity, key)| {
-            (
-                entity,
-                (TerrainGenTask(task_pool.spawn(async move {
-                    let mut chunk_data = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
-                    TERRAIN_GENERATOR
-                        .read()
-                        .unwrap()
-                        .generate(key, &mut chunk_data);
-                    chunk_data
-                }))),
+        .map(|chunk| chunk.0)
+        .chain(chunk_command_queue.drain_data_loads())
+        .collect();
+
+    for key in requests {
+        // the chunk data may already be loaded as data only, in which case it only needs meshing.
+        if chunk_data.exists(key) {
+            dirty_chunks.mark_dirty(key);
+            log.record(key, ChunkDecision::LoadData, || {
+                "data already loaded, marked dirty for meshing".to_string()
+            });
+            continue;
+        }
+
+        if gen_tasks.contains(key) {
+            continue;
+        }
+
+        if key.y >= MAX_GENERATED_HEIGHT {
+            log.record(key, ChunkDecision::LoadData, || {
+                format!(
+                    "left empty above the maximum generated height {}",
+                    MAX_GENERATED_HEIGHT
+                )
+            });
+            continue;
+        }
+
+        if level
+            .as_ref()
+            .map_or(false, |level| !level.contains_chunk(key))
+        {
+            log.record(key, ChunkDecision::LoadData, || {
+                "left empty outside of the authored level".to_string()
+            });
+            continue;
+        }
+
+        // the cached chunks of a client may have missed edits since they were unloaded, they're requested again.
+        if let Some(client) = client.as_mut() {
+            client.request_chunk(key);
+            log.record(key, ChunkDecision::LoadData, || {
+                "requested from the server".to_string()
+            });
+            continue;
+        }
+
+        // recently unloaded chunks are still in memory, no need to load or generate them again.
+        if chunk_data.restore_cached(key) {
+            // the cached chunks may have been modified, the structures spilling into them are dropped rather than
+            // growing back into the player builds.
+            pending_edits.take(key);
+            dirty_chunks.mark_dirty(key);
+            mark_neighbors_dirty(key, &chunk_data, &mut dirty_chunks);
+            log.record(key, ChunkDecision::LoadData, || {
+                "restored from the cache of recently unloaded chunks".to_string()
+            });
+            continue;
+        }
+
+        gen_tasks.queued.insert(key);
+        log.record(key, ChunkDecision::LoadData, || {
+            "queued for loading from the save or generation".to_string()
+        });
+    }
+
+    // spawn the tasks of the chunks closest to the player first, the others wait for the next frames.
+    let mut queued: Vec<IVec3> = gen_tasks.queued.iter().copied().collect();
+    sort_by_distance(&mut queued, player_chunk.chunk_min);
+
+    for key in queued.into_iter().take(budget.generation) {
+        gen_tasks.queued.remove(&key);
+        log.record(key, ChunkDecision::LoadData, || {
+            format!(
+                "task spawned, closest chunks first within {} tasks per frame",
+                budget.generation
             )
-        })
-        .for_each(|(entity, gen_task)| {
-            commands.entity(entity).insert(gen_task);
         });
+
+        let world_save = world_save.as_deref().cloned();
+        let authored = level.is_some();
+        gen_tasks.tasks.insert(
+            key,
+            task_pool.spawn(async move {
+                let start = Instant::now();
+                let output = load_or_generate_chunk(key, world_save, authored, material_count);
+                (output, start.elapsed())
+            }),
+        );
+    }
+}
+
+/// The output of a terrain generation task: the chunk data, the structure voxels spilling over its borders (`None` for
+/// the chunks which weren't generated, e.g. loaded from the world save) and the reason its generation failed if it got
+/// replaced by a fallback chunk.
+type ChunkGenOutput = (
+    SavedChunk,
+    Option<PendingVoxelEdits>,
+    Option<TerrainGenError>,
+);
+
+/// Loads a chunk from the world save, or generates it when it isn't saved.
+fn load_or_generate_chunk(
+    key: IVec3,
+    world_save: Option<WorldSave>,
+    authored: bool,
+    material_count: usize,
+) -> ChunkGenOutput {
+    match world_save.as_ref().map(|save| save.load_chunk(key)) {
+        Some(Ok(Some(saved))) => return (saved, None, None),
+        Some(Err(err)) => warn!("Failed to load chunk {:?} from save: {}", key, err),
+        _ => {}
+    }
+
+    // authored levels never invoke the generator, the chunks missing from their save are empty.
+    if authored {
+        let saved = SavedChunk {
+            header: ChunkSaveHeader::default(),
+            data: VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {}),
+            metadata: ChunkMetadata::default(),
+        };
+        return (saved, None, None);
+    }
+
+    let generator = TERRAIN_GENERATOR.read().unwrap();
+    let mut chunk_data = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+    match generator.generate(key, &mut chunk_data, material_count) {
+        Ok(generated) => {
+            let metadata = cover_snow_layers(key, &mut chunk_data);
+            let saved = SavedChunk {
+                header: ChunkSaveHeader {
+                    applied_stages: generated.applied_stages,
+                    pristine: true,
+                },
+                data: chunk_data,
+                metadata,
+            };
+            (saved, Some(generated.overflow), None)
+        }
+        Err(err) => {
+            error!(
+                "Failed to generate chunk {:?} with seed {}: {}, using a flat fallback chunk",
+                key,
+                generator.seed(),
+                err
+            );
+            let mut fallback = VoxelBuffer::<Voxel, ChunkShape>::new_empty(ChunkShape {});
+            terrain_generate_fallback(&mut fallback, key);
+            let saved = SavedChunk {
+                header: ChunkSaveHeader::default(),
+                data: fallback,
+                metadata: ChunkMetadata::default(),
+            };
+            (saved, Some(PendingVoxelEdits::default()), Some(err))
+        }
+    }
+}
+
+/// Covers the snow surfaces of a generated chunk with snow layers, partial snow voxels whose height varies from column to
+/// column. Returns the metadata holding the heights of the layers.
+fn cover_snow_layers(key: IVec3, chunk_data: &mut VoxelBuffer<Voxel, ChunkShape>) -> ChunkMetadata {
+    let snow = Snow::into_voxel();
+    let mut metadata = ChunkMetadata::default();
+
+    for x in 0..CHUNK_SIZE.x as u32 {
+        for z in 0..CHUNK_SIZE.z as u32 {
+            // the layers resting on the top voxels of the chunk would belong to the chunk above, they're left out.
+            let top = (0..CHUNK_SIZE.y as u32 - 1)
+                .rev()
+                .map(|y| UVec3::new(x, y, z))
+                .find(|pos| chunk_data.voxel_at(*pos) != Voxel::EMPTY_VOXEL);
+            let layer = match top {
+                Some(top)
+                    if chunk_data.voxel_at(top) == snow
+                        && chunk_data.voxel_at(top + UVec3::Y) == Voxel::EMPTY_VOXEL =>
+                {
+                    top + UVec3::Y
+                }
+                _ => continue,
+            };
+
+            // hashed from the world column, so that the chunks generated again get the same layers.
+            let column = key + layer.as_ivec3();
+            let hash = (column.x as u32).wrapping_mul(0x9e37_79b1)
+                ^ (column.z as u32).wrapping_mul(0x85eb_ca6b);
+            let mut voxel_metadata = VoxelMetadata::default();
+            voxel_metadata.set_partial_height(Some(2 + (hash >> 16) as u8 % 4));
+
+            chunk_data.set_voxel(layer, snow);
+            metadata.0.insert(layer, voxel_metadata);
+        }
+    }
+
+    metadata
 }
 
-/// Polls for finished gen tasks and put back the generated terrain into the voxel map
+/// Polls for finished gen tasks and put back the generated terrain into the voxel map.
+/// Structure voxels spilling over the generated chunks are applied to the loaded neighbors, or kept pending until those get loaded.
+#[allow(clippy::too_many_arguments)]
 fn process_terrain_gen(
     mut chunk_data: ResMut<ChunkMap<Voxel, ChunkShape>>,
-    mut commands: Commands,
     mut dirty_chunks: ResMut<DirtyChunks>,
-    mut gen_chunks: Query<(Entity, &Chunk, &mut TerrainGenTask)>,
+    mut gen_tasks: ResMut<TerrainGenTasks>,
+    mut save_headers: ResMut<ChunkSaveHeaders>,
+    mut pending_edits: ResMut<PendingVoxelEdits>,
+    mut light_updates: ResMut<LightUpdates>,
+    mut gen_errors: ResMut<ChunkGenErrors>,
+    mut diagnostics: ResMut<Diagnostics>,
+    mut log: ResMut<ChunkPipelineLog>,
+    mut metadata: ResMut<VoxelMetadataMap>,
+    mut lifecycle_events: EventWriter<ChunkLifecycleEvent>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
 ) {
-    gen_chunks.for_each_mut(|(entity, chunk, mut gen_task)| {
-        if let Some(data) = future::block_on(future::poll_once(&mut gen_task.0)) {
-            chunk_data.insert(chunk.0, data);
-            dirty_chunks.mark_dirty(chunk.0);
-            commands.entity(entity).remove::<TerrainGenTask>();
+    let mut overflow = PendingVoxelEdits::default();
+
+    gen_tasks.tasks.retain(|key, gen_task| {
+        match future::block_on(future::poll_once(gen_task)) {
+            Some(((chunk_save, chunk_overflow, gen_error), gen_time)) => {
+                diagnostics.add_measurement(CHUNK_GENERATION_TIME, gen_time.as_secs_f64() * 1000.0);
+                chunk_data.insert(*key, chunk_save.data);
+                metadata.insert_chunk(*key, chunk_save.metadata);
+                // fallback chunks have no save header so they never get written to the world save.
+                log.record(*key, ChunkDecision::LoadData, || match &gen_error {
+                    Some(err) => format!(
+                        "generation failed after {:?}, using a fallback chunk: {}",
+                        gen_time, err
+                    ),
+                    None => format!("loaded or generated in {:?}", gen_time),
+                });
+                match gen_error {
+                    Some(err) => {
+                        gen_errors.0.insert(*key, err);
+                    }
+                    None => {
+                        gen_errors.0.remove(key);
+                        save_headers.insert(*key, chunk_save.header);
+                    }
+                }
+                dirty_chunks.mark_dirty(*key);
+                mark_neighbors_dirty(*key, &chunk_data, &mut dirty_chunks);
+                lifecycle_events.send(ChunkLifecycleEvent::Generated(*key));
+
+                let edits = pending_edits.take(*key);
+                // the saved chunks may have been modified, the structures spilling into them are dropped rather than
+                // growing back into the player builds.
+                if let (Some(_), Some(edits)) = (&chunk_overflow, edits) {
+                    PendingVoxelEdits::apply(&edits, chunk_data.buffer_at_mut(*key).unwrap());
+                }
+                overflow.merge(chunk_overflow.unwrap_or_default());
+                false
+            }
+            None => true,
         }
     });
+
+    for key in overflow.iter_keys().copied().collect::<Vec<_>>() {
+        if let Some(buffer) = chunk_data.buffer_at_mut(key) {
+            let edits = overflow.take(key).unwrap();
+            PendingVoxelEdits::apply(&edits, buffer);
+            dirty_chunks.mark_dirty(key);
+            edits
+                .iter()
+                .for_each(|(pos, _)| light_updates.queue(key + pos.as_ivec3()));
+        }
+    }
+
+    pending_edits.merge(overflow);
+    pending_edits.truncate(player_chunk.chunk_min, MAX_PENDING_EDIT_CHUNKS);
+}
+
+/// Marks the loaded face neighbors of a chunk as dirty, so that their faces bordering the chunk get culled on remesh.
+fn mark_neighbors_dirty(
+    key: IVec3,
+    chunk_data: &ChunkMap<Voxel, ChunkShape>,
+    dirty_chunks: &mut DirtyChunks,
+) {
+    [
+        IVec3::X,
+        IVec3::NEG_X,
+        IVec3::Y,
+        IVec3::NEG_Y,
+        IVec3::Z,
+        IVec3::NEG_Z,
+    ]
+    .into_iter()
+    .map(|direction| key + direction * CHUNK_SIZE)
+    .filter(|neighbor| chunk_data.exists(*neighbor))
+    .for_each(|neighbor| dirty_chunks.mark_dirty(neighbor));
+}
+
+/// Regenerates the chunks requested through [`RetryChunkGen`] events, discarding their current data.
+fn retry_chunk_gen(
+    mut events: EventReader<RetryChunkGen>,
+    mut chunk_data: ResMut<ChunkMap<Voxel, ChunkShape>>,
+    mut save_headers: ResMut<ChunkSaveHeaders>,
+    mut gen_tasks: ResMut<TerrainGenTasks>,
+) {
+    for RetryChunkGen(key) in events.iter() {
+        if !chunk_data.exists(*key) || gen_tasks.contains(*key) {
+            continue;
+        }
+
+        chunk_data.remove(*key);
+        save_headers.remove(*key);
+        gen_tasks.queued.insert(*key);
+    }
+}
+
+/// Drops the errors of the chunks which got unloaded.
+fn prune_chunk_gen_errors(
+    chunk_data: Res<ChunkMap<Voxel, ChunkShape>>,
+    mut gen_errors: ResMut<ChunkGenErrors>,
+) {
+    if !gen_errors.0.is_empty() {
+        gen_errors
+            .0
+            .retain(|key, _| chunk_data.exists(*key) || chunk_data.is_cached(*key));
+    }
+}
+
+/// Drops the pending gen tasks of the chunks being unloaded.
+fn cancel_terrain_gen(
+    chunk_command_queue: Res<ChunkCommandQueue>,
+    mut gen_tasks: ResMut<TerrainGenTasks>,
+) {
+    chunk_command_queue.pending_data_unloads().for_each(|key| {
+        gen_tasks.cancel(*key);
+    });
 }
 
 /// Handles terrain generation.
@@ -74,25 +412,91 @@ pub enum TerrainGenSystem {
     ProcessTerrainGen,
 }
 
-// we need to use a whole system stage for this in order to enable the usage of added component querries.
-#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Hash, StageLabel)]
-struct TerrainGenStage;
-
 impl Plugin for VoxelWorldTerrainGenPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_stage_after(
-            ChunkLoadingStage,
-            TerrainGenStage,
-            SystemStage::parallel()
-                .with_system(queue_terrain_gen.label(TerrainGenSystem::QueueTerrainGen))
-                .with_system(
-                    process_terrain_gen
-                        .label(TerrainGenSystem::ProcessTerrainGen)
-                        .after(TerrainGenSystem::QueueTerrainGen),
-                ),
-        );
+        app.init_resource::<TerrainGenTasks>()
+            .init_resource::<PendingVoxelEdits>()
+            .init_resource::<ChunkGenErrors>()
+            .add_event::<RetryChunkGen>()
+            .add_system_set_to_stage(
+                TerrainGenStage,
+                SystemSet::new()
+                    .with_system(retry_chunk_gen.before(TerrainGenSystem::QueueTerrainGen))
+                    .with_system(queue_terrain_gen.label(TerrainGenSystem::QueueTerrainGen))
+                    .with_system(
+                        process_terrain_gen
+                            .label(TerrainGenSystem::ProcessTerrainGen)
+                            .after(TerrainGenSystem::QueueTerrainGen),
+                    ),
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                cancel_terrain_gen
+                    .after(ChunkLoadingSystem::DestroyChunks)
+                    .before(ChunkLoadingSystem::UnloadChunkData),
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                prune_chunk_gen_errors.after(ChunkLoadingSystem::UnloadChunkData),
+            );
+    }
+}
+
+/// The in-flight terrain generation tasks indexed by chunk key, along with the chunks waiting for their generation task to be spawned.
+#[derive(Default)]
+pub struct TerrainGenTasks {
+    tasks: HashMap<IVec3, Task<(ChunkGenOutput, Duration)>>,
+    queued: HashSet<IVec3>,
+}
+
+#[allow(dead_code)]
+impl TerrainGenTasks {
+    /// Returns the number of chunks currently being generated.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Returns the number of chunks waiting for their generation task to be spawned.
+    pub fn queued_len(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Returns whether the specified chunk is currently being generated or waiting to be.
+    pub fn contains(&self, key: IVec3) -> bool {
+        self.tasks.contains_key(&key) || self.queued.contains(&key)
+    }
+
+    /// Returns an iterator over the keys of the chunks being generated or waiting to be.
+    pub fn iter_keys(&self) -> impl Iterator<Item = &IVec3> {
+        self.tasks.keys().chain(self.queued.iter())
+    }
+
+    /// Drops the generation task of the specified chunk.
+    pub fn cancel(&mut self, key: IVec3) {
+        self.tasks.remove(&key);
+        self.queued.remove(&key);
+    }
+}
+
+/// The chunks whose generation failed and were replaced by a flat fallback chunk, along the reason of the failure.
+#[derive(Default)]
+pub struct ChunkGenErrors(HashMap<IVec3, TerrainGenError>);
+
+impl ChunkGenErrors {
+    /// Returns the reason the generation of the specified chunk failed, if it did.
+    pub fn get(&self, key: IVec3) -> Option<&TerrainGenError> {
+        self.0.get(&key)
+    }
+
+    /// Returns an iterator over the chunks whose generation failed, along the reason of the failure.
+    pub fn iter(&self) -> impl Iterator<Item = (&IVec3, &TerrainGenError)> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
     }
 }
 
-#[derive(Component)]
-struct TerrainGenTask(Task<VoxelBuffer<Voxel, ChunkShape>>);
+/// Event requesting a chunk to be generated again, e.g. after its generation failed.
+pub struct RetryChunkGen(pub IVec3);
@@ -0,0 +1,227 @@
+use bevy::{
+    math::{IVec3, UVec3},
+    prelude::{Local, Plugin, Res, ResMut},
+    time::Time,
+    utils::{Duration, HashSet},
+};
+
+use super::{
+    chunks::{CurrentLocalPlayerChunk, DirtyChunks},
+    stages::ChunkMeshingStage,
+    ChunkShape, CHUNK_SIZE,
+};
+use crate::voxel::{
+    storage::{ChunkMap, VoxelBuffer},
+    Voxel,
+};
+
+/// Number of voxels along each side of a texel of the [`VoxelVolume`].
+pub const VOXEL_VOLUME_SCALE: i32 = 2;
+
+/// Settings of the coarse [`VoxelVolume`] uploaded to the GPU for the shader effects.
+pub struct VoxelVolumeSettings {
+    pub enabled: bool,
+    /// Horizontal radius (in chunks) of the area around the camera covered by the volume.
+    pub horizontal_radius: i32,
+    /// Vertical radius (in chunks) of the area around the camera covered by the volume.
+    pub vertical_radius: i32,
+    /// Time between two updates of the volume, the chunks modified in between being updated together.
+    pub update_interval: Duration,
+}
+
+impl Default for VoxelVolumeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            horizontal_radius: 4,
+            vertical_radius: 2,
+            update_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A coarse occupancy and material volume of the voxels around the camera, for the shader effects needing to know
+/// about the surrounding terrain (contact shadows, volumetric fog occlusion, screen-space voxel reflections...).
+/// Each texel covers [`VOXEL_VOLUME_SCALE`]³ voxels and holds the id of their most common material, 0 when all empty.
+#[derive(Default)]
+pub struct VoxelVolume {
+    /// World position of the first voxel of the first texel.
+    pub origin: IVec3,
+    /// Number of texels along each axis.
+    pub size: UVec3,
+    /// Texel materials in rows along the X axis, then layers along the Y axis.
+    pub texels: Vec<u8>,
+    /// Chunks whose texels changed during the last update, to be uploaded incrementally.
+    pub updated_chunks: Vec<IVec3>,
+    /// Whether the whole volume changed during the last update (e.g. when the camera moved to another chunk).
+    pub full_update: bool,
+}
+
+impl VoxelVolume {
+    /// Number of texels along each axis of a chunk.
+    pub const CHUNK_TEXELS: IVec3 = IVec3::new(
+        CHUNK_SIZE.x / VOXEL_VOLUME_SCALE,
+        CHUNK_SIZE.y / VOXEL_VOLUME_SCALE,
+        CHUNK_SIZE.z / VOXEL_VOLUME_SCALE,
+    );
+
+    /// Returns the index of a texel of the volume.
+    pub fn texel_index(&self, texel: UVec3) -> usize {
+        ((texel.z * self.size.y + texel.y) * self.size.x + texel.x) as usize
+    }
+
+    /// Returns the texel containing a chunk's first voxel, if the chunk is covered by the volume.
+    pub fn chunk_texel(&self, chunk_key: IVec3) -> Option<UVec3> {
+        let texel = (chunk_key - self.origin) / VOXEL_VOLUME_SCALE;
+        (chunk_key.cmpge(self.origin).all() && texel.cmplt(self.size.as_ivec3()).all())
+            .then(|| texel.as_uvec3())
+    }
+
+    /// Returns the material of the texel containing a voxel, if covered by the volume.
+    #[allow(dead_code)]
+    pub fn material_at(&self, pos: IVec3) -> Option<Voxel> {
+        let local = pos - self.origin;
+        if local.cmplt(IVec3::ZERO).any() {
+            return None;
+        }
+
+        let texel = local / VOXEL_VOLUME_SCALE;
+        texel
+            .cmplt(self.size.as_ivec3())
+            .all()
+            .then(|| Voxel(self.texels[self.texel_index(texel.as_uvec3())]))
+    }
+
+    /// Writes the texels of a chunk, or clears them if the chunk isn't loaded.
+    fn write_chunk(&mut self, chunk_key: IVec3, buffer: Option<&VoxelBuffer<Voxel, ChunkShape>>) {
+        let first = match self.chunk_texel(chunk_key) {
+            Some(first) => first,
+            None => return,
+        };
+
+        let chunk_texels = Self::CHUNK_TEXELS.as_uvec3();
+        for z in 0..chunk_texels.z {
+            for y in 0..chunk_texels.y {
+                for x in 0..chunk_texels.x {
+                    let texel = UVec3::new(x, y, z);
+                    let material = buffer.map_or(0, |buffer| coarse_material(buffer, texel));
+                    let index = self.texel_index(first + texel);
+                    self.texels[index] = material;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the most common material of the voxels of a chunk texel, 0 when they're all empty.
+fn coarse_material(buffer: &VoxelBuffer<Voxel, ChunkShape>, texel: UVec3) -> u8 {
+    let scale = VOXEL_VOLUME_SCALE as u32;
+    let mut counts: [(u8, u8); 8] = Default::default();
+    let mut materials = 0;
+
+    for z in 0..scale {
+        for y in 0..scale {
+            for x in 0..scale {
+                let voxel = buffer.voxel_at(texel * scale + UVec3::new(x, y, z));
+                if voxel == Voxel::EMPTY_VOXEL {
+                    continue;
+                }
+
+                match counts[..materials]
+                    .iter_mut()
+                    .find(|(material, _)| *material == voxel.0)
+                {
+                    Some((_, count)) => *count += 1,
+                    None if materials < counts.len() => {
+                        counts[materials] = (voxel.0, 1);
+                        materials += 1;
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    counts[..materials]
+        .iter()
+        .max_by_key(|(_, count)| *count)
+        .map_or(0, |(material, _)| *material)
+}
+
+/// Keeps the [`VoxelVolume`] centered on the camera, rewriting the texels of the chunks modified since the last update.
+/// The volume is only modified when updated, so that the render world only uploads it then.
+fn update_voxel_volume(
+    settings: Res<VoxelVolumeSettings>,
+    time: Res<Time>,
+    player_chunk: Res<CurrentLocalPlayerChunk>,
+    chunks: Res<ChunkMap<Voxel, ChunkShape>>,
+    dirty_chunks: Res<DirtyChunks>,
+    mut volume: ResMut<VoxelVolume>,
+    mut pending_chunks: Local<HashSet<IVec3>>,
+    mut since_update: Local<Duration>,
+) {
+    if !settings.enabled {
+        return;
+    }
+
+    // the dirty chunks only stay marked for a frame.
+    pending_chunks.extend(dirty_chunks.iter_dirty().copied());
+
+    *since_update += time.delta();
+    if *since_update < settings.update_interval {
+        return;
+    }
+    *since_update = Duration::ZERO;
+
+    let radius = IVec3::new(
+        settings.horizontal_radius,
+        settings.vertical_radius,
+        settings.horizontal_radius,
+    );
+    let origin = player_chunk.chunk_min - radius * CHUNK_SIZE;
+    let size = ((2 * radius + IVec3::ONE) * VoxelVolume::CHUNK_TEXELS).as_uvec3();
+
+    if volume.origin != origin || volume.size != size {
+        volume.origin = origin;
+        volume.size = size;
+        volume.texels = vec![0; (size.x * size.y * size.z) as usize];
+        volume.updated_chunks.clear();
+        volume.full_update = true;
+
+        for z in -radius.z..=radius.z {
+            for y in -radius.y..=radius.y {
+                for x in -radius.x..=radius.x {
+                    let key = player_chunk.chunk_min + IVec3::new(x, y, z) * CHUNK_SIZE;
+                    volume.write_chunk(key, chunks.buffer_at(key));
+                }
+            }
+        }
+
+        pending_chunks.clear();
+        return;
+    }
+
+    if pending_chunks.is_empty() {
+        return;
+    }
+
+    volume.updated_chunks.clear();
+    volume.full_update = false;
+    for key in pending_chunks.drain() {
+        if volume.chunk_texel(key).is_some() {
+            volume.write_chunk(key, chunks.buffer_at(key));
+            volume.updated_chunks.push(key);
+        }
+    }
+}
+
+/// Maintains the coarse [`VoxelVolume`] around the camera, uploaded to a 3D texture by the terrain render pipeline.
+pub struct VoxelVolumePlugin;
+
+impl Plugin for VoxelVolumePlugin {
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.init_resource::<VoxelVolumeSettings>()
+            .init_resource::<VoxelVolume>()
+            .add_system_to_stage(ChunkMeshingStage, update_voxel_volume);
+    }
+}